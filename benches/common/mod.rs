@@ -0,0 +1,65 @@
+//! Synthetic orders shared by the `sync` bench and the `upsert_benchmark`
+//! example, so both measure the same payloads
+
+use serde_json::{json, Value};
+use toptop_order::order::Order;
+
+/// An order as TikTok returns it in a list page, with two line items, a
+/// payment breakdown and a recipient address with district levels
+pub fn order_json(i: usize, update_time: i64) -> Value {
+    json!({
+        "id": format!("5770000000{:08}", i),
+        "status": "AWAITING_SHIPMENT",
+        "create_time": 1_700_000_000 + i as i64,
+        "update_time": update_time,
+        "paid_time": 1_700_000_060 + i as i64,
+        "user_id": format!("7490000000{:08}", i % 997),
+        "buyer_email": format!("buyer{}@scs.tiktokw.us", i % 997),
+        "buyer_message": "",
+        "delivery_option_name": "Standard shipping",
+        "fulfillment_type": "FULFILLMENT_BY_SELLER",
+        "shipping_type": "TIKTOK",
+        "payment_method_name": "CCDC",
+        "rts_sla_time": 1_700_172_800 + i as i64,
+        "payment": {
+            "currency": "VND",
+            "total_amount": "215000",
+            "sub_total": "200000",
+            "shipping_fee": "15000",
+            "seller_discount": "0",
+            "platform_discount": "0",
+            "tax": "0"
+        },
+        "recipient_address": {
+            "full_address": "12 Nguyen Hue, Ben Nghe, Quan 1, Ho Chi Minh",
+            "name": "Nguyen Van A",
+            "phone_number": "(+84)90*****12",
+            "region_code": "VN",
+            "address_detail": "12 Nguyen Hue",
+            "district_info": [
+                {"address_level": "L0", "address_level_name": "Country", "address_name": "Viet Nam"},
+                {"address_level": "L1", "address_level_name": "Province", "address_name": "Ho Chi Minh"},
+                {"address_level": "L2", "address_level_name": "District", "address_name": "Quan 1"},
+                {"address_level": "L3", "address_level_name": "Ward", "address_name": "Ben Nghe"}
+            ]
+        },
+        "line_items": (0..2).map(|n| json!({
+            "id": format!("li-{}-{}", i, n),
+            "product_id": "1729000000000000001",
+            "product_name": "Benchmark product",
+            "sku_id": format!("172900000000000000{}", n + 2),
+            "sku_name": "Default",
+            "seller_sku": format!("BENCH-{}", n),
+            "quantity": 1,
+            "sale_price": "100000",
+            "original_price": "120000"
+        })).collect::<Vec<_>>()
+    })
+}
+
+/// `count` orders from [`order_json`]
+pub fn synthetic_orders(count: usize, update_time: i64) -> Vec<Order> {
+    (0..count)
+        .map(|i| serde_json::from_value(order_json(i, update_time)).expect("valid synthetic order"))
+        .collect()
+}
@@ -0,0 +1,109 @@
+//! Sync throughput benchmarks: `upsert_orders` across batch sizes, with and
+//! without the normalized side tables, and deserialization of order pages.
+//!
+//! ```bash
+//! cargo bench --bench sync
+//! cargo bench --bench sync -- upsert_orders/normalized
+//! ```
+
+mod common;
+
+use common::{order_json, synthetic_orders};
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use serde_json::json;
+use std::sync::atomic::{AtomicI64, Ordering};
+use toptop_order::address::AddressStore;
+use toptop_order::database::Database;
+use toptop_order::order::GetOrderListResponse;
+use toptop_order::requests::ApiResponse;
+use toptop_order::sku_stats::SkuStatsStore;
+
+/// Orders written per measured `upsert_orders` call
+const UPSERT_COUNT: usize = 500;
+
+/// One list page of `count` orders in the API envelope
+fn order_page(count: usize) -> String {
+    json!({
+        "code": 0,
+        "message": "Success",
+        "request_id": "2024010100000000000000000000",
+        "data": {
+            "orders": (0..count).map(|i| order_json(i, 1_700_000_000)).collect::<Vec<_>>(),
+            "total_count": count,
+            "next_page_token": "bmV4dA=="
+        }
+    })
+    .to_string()
+}
+
+fn upsert_orders(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().expect("tokio runtime");
+    let dir = std::env::temp_dir().join(format!("toptop-sync-bench-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).expect("bench directory");
+
+    let mut group = c.benchmark_group("upsert_orders");
+    group.throughput(Throughput::Elements(UPSERT_COUNT as u64));
+    group.sample_size(20);
+
+    for schema in ["json", "normalized"] {
+        for batch_size in [1, 50, 500] {
+            let path = dir.join(format!("{}_{}.db", schema, batch_size));
+            let db = runtime.block_on(async {
+                let db = Database::new(path.to_str().unwrap_or_default())
+                    .await
+                    .expect("open database")
+                    .with_upsert_batch_size(batch_size);
+                // Also write line items and addresses into their own tables
+                let db = match schema {
+                    "normalized" => db.with_sku_stats().with_address_normalization(),
+                    _ => db,
+                };
+                db.init().await.expect("init database");
+                SkuStatsStore::new(db.pool().clone())
+                    .init()
+                    .await
+                    .expect("init SKU stats");
+                AddressStore::new(db.pool().clone())
+                    .init()
+                    .await
+                    .expect("init addresses");
+                db
+            });
+
+            // Every iteration stores a newer version, so no order is skipped
+            // as unchanged
+            let update_time = AtomicI64::new(1_700_000_000);
+            group.bench_with_input(BenchmarkId::new(schema, batch_size), &db, |b, db| {
+                b.to_async(&runtime).iter_batched(
+                    || synthetic_orders(UPSERT_COUNT, update_time.fetch_add(1, Ordering::Relaxed)),
+                    |orders| async move {
+                        db.upsert_orders(&orders).await.expect("upsert orders");
+                    },
+                    BatchSize::LargeInput,
+                )
+            });
+        }
+    }
+    group.finish();
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+fn deserialize_order_page(c: &mut Criterion) {
+    let mut group = c.benchmark_group("deserialize_order_page");
+    // 100 is the largest page TikTok returns; 1000 approximates a backfill batch
+    for count in [10, 100, 1000] {
+        let page = order_page(count);
+        group.throughput(Throughput::Bytes(page.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(count), &page, |b, page| {
+            b.iter(|| {
+                serde_json::from_str::<ApiResponse<GetOrderListResponse>>(page)
+                    .expect("valid order page")
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, upsert_orders, deserialize_order_page);
+criterion_main!(benches);
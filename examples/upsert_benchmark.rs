@@ -0,0 +1,52 @@
+//! Compare `upsert_orders` throughput across batch sizes.
+//!
+//! ```bash
+//! cargo run --release --example upsert_benchmark -- 5000
+//! ```
+
+#[path = "../benches/common/mod.rs"]
+mod common;
+
+use common::synthetic_orders;
+use std::time::Instant;
+use toptop_order::database::Database;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let count: usize = std::env::args()
+        .nth(1)
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(2000);
+
+    let dir = std::env::temp_dir().join(format!("toptop-upsert-bench-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+
+    println!("Upserting {} orders", count);
+    println!(
+        "{:>10} {:>12} {:>14}",
+        "batch", "insert (ms)", "update (ms)"
+    );
+
+    for batch_size in [1, 50, 500, 5000] {
+        let path = dir.join(format!("bench_{}.db", batch_size));
+        let db = Database::new(path.to_str().unwrap_or_default())
+            .await?
+            .with_upsert_batch_size(batch_size);
+        db.init().await?;
+
+        let start = Instant::now();
+        db.upsert_orders(&synthetic_orders(count, 1_700_000_000))
+            .await?;
+        let insert_ms = start.elapsed().as_millis();
+
+        let start = Instant::now();
+        db.upsert_orders(&synthetic_orders(count, 1_700_000_100))
+            .await?;
+        let update_ms = start.elapsed().as_millis();
+
+        println!("{:>10} {:>12} {:>14}", batch_size, insert_ms, update_ms);
+    }
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
@@ -0,0 +1,514 @@
+use crate::finance::Statement;
+use crate::order::Order;
+use crate::reporting;
+use chrono::Duration;
+use chrono_tz::Tz;
+use std::str::FromStr;
+use tracing::warn;
+
+/// Most days one accounting export may span
+pub const MAX_RANGE_DAYS: i64 = 366;
+
+/// Statement payment status of a payout that reached the seller's bank
+const PAID: &str = "PAID";
+
+/// Accounting package an export is imported into
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// QuickBooks Online invoice and bank transaction import
+    Quickbooks,
+    /// Xero sales invoice and bank statement import
+    Xero,
+}
+
+impl ExportFormat {
+    fn default_date_format(&self) -> &'static str {
+        match self {
+            ExportFormat::Quickbooks => "%m/%d/%Y",
+            ExportFormat::Xero => "%d/%m/%Y",
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ExportFormat::Quickbooks => "quickbooks",
+            ExportFormat::Xero => "xero",
+        }
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "quickbooks" => Ok(ExportFormat::Quickbooks),
+            "xero" => Ok(ExportFormat::Xero),
+            _ => Err(format!(
+                "invalid format {:?}, expected quickbooks or xero",
+                value
+            )),
+        }
+    }
+}
+
+/// What an export contains
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportKind {
+    /// One sales invoice per completed order
+    Invoices,
+    /// One bank line per paid TikTok payout statement
+    Payouts,
+}
+
+impl ExportKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ExportKind::Invoices => "invoices",
+            ExportKind::Payouts => "payouts",
+        }
+    }
+}
+
+impl FromStr for ExportKind {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "invoices" => Ok(ExportKind::Invoices),
+            "payouts" => Ok(ExportKind::Payouts),
+            _ => Err(format!(
+                "invalid kind {:?}, expected invoices or payouts",
+                value
+            )),
+        }
+    }
+}
+
+/// How orders map onto the books: the contact invoices are raised against,
+/// and the Xero account code and QuickBooks item of each kind of line
+#[derive(Debug, Clone, PartialEq)]
+pub struct AccountingMapping {
+    pub contact: String,
+    /// Prefixed to the order id to form the invoice number of orders without
+    /// an external reference
+    pub invoice_prefix: String,
+    /// Days from the invoice date to its due date
+    pub due_days: i64,
+    /// chrono format of dates; unset uses the format's usual one
+    pub date_format: Option<String>,
+    /// Xero tax type of every line
+    pub tax_type: String,
+    /// QuickBooks tax code of every line
+    pub tax_code: String,
+    pub sales_account: String,
+    pub sales_item: String,
+    pub shipping_account: String,
+    pub shipping_item: String,
+    pub tax_account: String,
+    pub tax_item: String,
+    /// Commission and fees TikTok withheld, booked as a negative line
+    pub fee_account: String,
+    pub fee_item: String,
+}
+
+impl Default for AccountingMapping {
+    fn default() -> Self {
+        Self {
+            contact: "TikTok Shop".to_string(),
+            invoice_prefix: "TT-".to_string(),
+            due_days: 0,
+            date_format: None,
+            tax_type: "Tax Exempt".to_string(),
+            tax_code: "NON".to_string(),
+            sales_account: "200".to_string(),
+            sales_item: "Sales".to_string(),
+            shipping_account: "200".to_string(),
+            shipping_item: "Shipping".to_string(),
+            tax_account: "820".to_string(),
+            tax_item: "Sales Tax".to_string(),
+            fee_account: "404".to_string(),
+            fee_item: "TikTok Shop Fees".to_string(),
+        }
+    }
+}
+
+/// Parse `key=value` pairs separated by commas over the defaults, e.g.
+/// `contact=TikTok Shop VN,sales_account=4000,fee_account=6100`
+pub fn parse_mapping(value: &str) -> Result<AccountingMapping, String> {
+    let mut mapping = AccountingMapping::default();
+    for entry in value.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        let (key, value) = entry
+            .split_once('=')
+            .ok_or_else(|| format!("{:?} is not key=value", entry))?;
+        let value = value.trim().to_string();
+        let field = match key.trim() {
+            "contact" => &mut mapping.contact,
+            "invoice_prefix" => &mut mapping.invoice_prefix,
+            "tax_type" => &mut mapping.tax_type,
+            "tax_code" => &mut mapping.tax_code,
+            "sales_account" => &mut mapping.sales_account,
+            "sales_item" => &mut mapping.sales_item,
+            "shipping_account" => &mut mapping.shipping_account,
+            "shipping_item" => &mut mapping.shipping_item,
+            "tax_account" => &mut mapping.tax_account,
+            "tax_item" => &mut mapping.tax_item,
+            "fee_account" => &mut mapping.fee_account,
+            "fee_item" => &mut mapping.fee_item,
+            "due_days" => {
+                mapping.due_days = value
+                    .parse()
+                    .ok()
+                    .filter(|days| *days >= 0)
+                    .ok_or_else(|| format!("due_days {:?} is not a number of days", value))?;
+                continue;
+            }
+            "date_format" => {
+                mapping.date_format = Some(value).filter(|f| !f.is_empty());
+                continue;
+            }
+            other => return Err(format!("unknown key {:?}", other)),
+        };
+        *field = value;
+    }
+    Ok(mapping)
+}
+
+/// An order to invoice, with what is known about it beyond the order itself
+#[derive(Debug, Clone)]
+pub struct InvoiceOrder {
+    pub order: Order,
+    /// ERP/invoice reference set with `POST /orders/{id}/external-ref`
+    pub external_ref: Option<String>,
+    /// Tax from the order's price detail, else its payment info
+    pub tax: Option<f64>,
+    /// Fees TikTok withheld, once the order is settled
+    pub fees: Option<f64>,
+}
+
+/// One line of an invoice
+struct InvoiceLine<'a> {
+    description: String,
+    quantity: f64,
+    unit_amount: f64,
+    account: &'a str,
+    item: &'a str,
+}
+
+/// An amount as TikTok sends it, e.g. `"12.50"`
+fn amount(value: &str) -> Result<f64, String> {
+    value
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|a| a.is_finite())
+        .ok_or_else(|| format!("{:?} is not an amount", value))
+}
+
+fn format_amount(value: f64) -> String {
+    format!("{:.2}", value)
+}
+
+/// Quote a CSV field when it holds a separator, quote or line break. Text
+/// starting with `=`, `+`, `-`, `@`, a tab or a carriage return gets a
+/// leading `'`, so spreadsheets
+/// don't run it as a formula; plain numbers such as negative amounts stay
+/// as they are.
+fn escape(field: &str) -> String {
+    let formula = field.starts_with(['=', '+', '-', '@', '\t', '\r']) && field.parse::<f64>().is_err();
+    let field = if formula {
+        format!("'{}", field)
+    } else {
+        field.to_string()
+    };
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field
+    }
+}
+
+fn push_row(csv: &mut String, fields: &[String]) {
+    let row: Vec<String> = fields.iter().map(|f| escape(f)).collect();
+    csv.push_str(&row.join(","));
+    csv.push_str("\r\n");
+}
+
+/// Lines of an invoice; fails on an item or shipping amount that can't be read
+fn invoice_lines<'a>(
+    invoice: &InvoiceOrder,
+    mapping: &'a AccountingMapping,
+) -> Result<Vec<InvoiceLine<'a>>, String> {
+    let order = &invoice.order;
+    let mut lines = order
+        .item_list
+        .iter()
+        .map(|item| {
+            Ok(InvoiceLine {
+                description: match item.sku_name.as_deref().filter(|s| !s.is_empty()) {
+                    Some(sku) => format!("{} ({})", item.product_name, sku),
+                    None => item.product_name.clone(),
+                },
+                quantity: item.quantity.unwrap_or(1).max(1) as f64,
+                unit_amount: amount(&item.sale_price)
+                    .map_err(|e| format!("sale price of item {}: {}", item.id, e))?,
+                account: &mapping.sales_account,
+                item: &mapping.sales_item,
+            })
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    // No shipping fee is sent for orders without shipping
+    let shipping = match order.payment.as_ref().map(|p| p.shipping_fee.trim()) {
+        Some(fee) if !fee.is_empty() => {
+            amount(fee).map_err(|e| format!("shipping fee: {}", e))?
+        }
+        _ => 0.0,
+    };
+    if shipping != 0.0 {
+        lines.push(InvoiceLine {
+            description: "Shipping".to_string(),
+            quantity: 1.0,
+            unit_amount: shipping,
+            account: &mapping.shipping_account,
+            item: &mapping.shipping_item,
+        });
+    }
+    if let Some(tax) = invoice.tax.filter(|t| *t != 0.0) {
+        lines.push(InvoiceLine {
+            description: "Tax".to_string(),
+            quantity: 1.0,
+            unit_amount: tax,
+            account: &mapping.tax_account,
+            item: &mapping.tax_item,
+        });
+    }
+    if let Some(fees) = invoice.fees.filter(|f| *f != 0.0) {
+        lines.push(InvoiceLine {
+            description: "TikTok Shop commission and fees".to_string(),
+            quantity: 1.0,
+            unit_amount: -fees,
+            account: &mapping.fee_account,
+            item: &mapping.fee_item,
+        });
+    }
+    Ok(lines)
+}
+
+/// Sales invoices of `orders` as a QuickBooks or Xero import CSV, one row per
+/// line: items, shipping, tax and (once settled) TikTok's fees as a negative
+/// line. Invoices are dated on the business day the order was created in
+/// `timezone`. Orders with an amount that can't be read are logged and left
+/// out rather than invoiced wrongly.
+pub fn invoices_csv(
+    format: ExportFormat,
+    mapping: &AccountingMapping,
+    timezone: Tz,
+    orders: &[InvoiceOrder],
+) -> String {
+    let date_format = mapping
+        .date_format
+        .as_deref()
+        .unwrap_or(format.default_date_format());
+    let mut csv = String::new();
+    push_row(
+        &mut csv,
+        &match format {
+            ExportFormat::Quickbooks => [
+                "InvoiceNo",
+                "Customer",
+                "InvoiceDate",
+                "DueDate",
+                "Memo",
+                "Item(Product/Service)",
+                "ItemDescription",
+                "ItemQuantity",
+                "ItemRate",
+                "ItemAmount",
+                "ItemTaxCode",
+                "Currency",
+            ],
+            ExportFormat::Xero => [
+                "ContactName",
+                "InvoiceNumber",
+                "Reference",
+                "InvoiceDate",
+                "DueDate",
+                "Description",
+                "Quantity",
+                "UnitAmount",
+                "AccountCode",
+                "TaxType",
+                "TaxAmount",
+                "Currency",
+            ],
+        }
+        .map(str::to_string),
+    );
+
+    for invoice in orders {
+        let order = &invoice.order;
+        let lines = match invoice_lines(invoice, mapping) {
+            Ok(lines) => lines,
+            Err(e) => {
+                warn!("Leaving order {} out of the invoice export: {}", order.id, e);
+                continue;
+            }
+        };
+        let number = invoice
+            .external_ref
+            .clone()
+            .unwrap_or_else(|| format!("{}{}", mapping.invoice_prefix, order.id));
+        let date = reporting::date_of(order.create_time, timezone);
+        let due = date + Duration::days(mapping.due_days);
+        let (date, due) = (
+            date.format(date_format).to_string(),
+            due.format(date_format).to_string(),
+        );
+        let currency = order
+            .payment
+            .as_ref()
+            .map(|p| p.currency.clone())
+            .unwrap_or_default();
+
+        for line in lines {
+            let row = match format {
+                ExportFormat::Quickbooks => [
+                    number.clone(),
+                    mapping.contact.clone(),
+                    date.clone(),
+                    due.clone(),
+                    format!("TikTok order {}", order.id),
+                    line.item.to_string(),
+                    line.description,
+                    line.quantity.to_string(),
+                    format_amount(line.unit_amount),
+                    format_amount(line.quantity * line.unit_amount),
+                    mapping.tax_code.clone(),
+                    currency.clone(),
+                ],
+                ExportFormat::Xero => [
+                    mapping.contact.clone(),
+                    number.clone(),
+                    order.id.clone(),
+                    date.clone(),
+                    due.clone(),
+                    line.description,
+                    line.quantity.to_string(),
+                    format_amount(line.unit_amount),
+                    line.account.to_string(),
+                    mapping.tax_type.clone(),
+                    format_amount(0.0),
+                    currency.clone(),
+                ],
+            };
+            push_row(&mut csv, &row);
+        }
+    }
+    csv
+}
+
+/// Paid payout statements as a QuickBooks or Xero bank statement CSV, one
+/// row per payout, dated on the business day of the statement in `timezone`.
+/// Payouts without a readable amount are logged and left out.
+pub fn payouts_csv(
+    format: ExportFormat,
+    mapping: &AccountingMapping,
+    timezone: Tz,
+    statements: &[Statement],
+) -> String {
+    let date_format = mapping
+        .date_format
+        .as_deref()
+        .unwrap_or(format.default_date_format());
+    let mut csv = String::new();
+    match format {
+        ExportFormat::Quickbooks => push_row(
+            &mut csv,
+            &["Date", "Description", "Amount"].map(str::to_string),
+        ),
+        ExportFormat::Xero => push_row(
+            &mut csv,
+            &["Date", "Amount", "Payee", "Description", "Reference"].map(str::to_string),
+        ),
+    }
+
+    for statement in statements
+        .iter()
+        .filter(|s| s.payment_status.as_deref() == Some(PAID))
+    {
+        let date = reporting::date_of(statement.statement_time, timezone)
+            .format(date_format)
+            .to_string();
+        let amount = match statement.settlement_amount.as_deref().map(amount) {
+            Some(Ok(amount)) => format_amount(amount),
+            Some(Err(e)) => {
+                warn!("Leaving payout {} out of the export: {}", statement.id, e);
+                continue;
+            }
+            None => {
+                warn!("Leaving payout {} out of the export: no settlement amount", statement.id);
+                continue;
+            }
+        };
+        let description = format!(
+            "TikTok Shop payout {} ({})",
+            statement.id, statement.currency
+        );
+        let reference = statement
+            .payment_id
+            .clone()
+            .unwrap_or_else(|| statement.id.clone());
+        match format {
+            ExportFormat::Quickbooks => push_row(&mut csv, &[date, description, amount]),
+            ExportFormat::Xero => push_row(
+                &mut csv,
+                &[
+                    date,
+                    amount,
+                    mapping.contact.clone(),
+                    description,
+                    reference,
+                ],
+            ),
+        }
+    }
+    csv
+}
+
+/// File name an export is downloaded as
+pub fn file_name(format: ExportFormat, kind: ExportKind, from: &str, to: &str) -> String {
+    format!(
+        "tiktok-{}-{}-{}-{}.csv",
+        kind.as_str(),
+        format.as_str(),
+        from,
+        to
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formulas_are_defused() {
+        assert_eq!(escape("=HYPERLINK(\"http://x\")"), "\"'=HYPERLINK(\"\"http://x\"\")\"");
+        assert_eq!(escape("+cmd"), "'+cmd");
+        assert_eq!(escape("-2+3"), "'-2+3");
+        assert_eq!(escape("@SUM(A1)"), "'@SUM(A1)");
+        assert_eq!(escape("\t=1+1"), "'\t=1+1");
+        assert_eq!(escape("\r=1+1"), "\"'\r=1+1\"");
+        assert_eq!(escape("-12.50"), "-12.50");
+        assert_eq!(escape("Mug, blue"), "\"Mug, blue\"");
+        assert_eq!(escape("Mug"), "Mug");
+    }
+
+    #[test]
+    fn amounts_must_parse() {
+        assert_eq!(amount(" 12.50 "), Ok(12.5));
+        assert!(amount("").is_err());
+        assert!(amount("12,50").is_err());
+        assert!(amount("NaN").is_err());
+    }
+}
@@ -0,0 +1,324 @@
+use crate::order::RecipientAddress;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+#[cfg(feature = "server")]
+use crate::database::{self, Database};
+#[cfg(feature = "server")]
+use crate::jobs::{Job, JobHandler};
+#[cfg(feature = "server")]
+use crate::order::Order;
+#[cfg(feature = "server")]
+use crate::pii_crypto::PiiCipher;
+#[cfg(feature = "server")]
+use async_trait::async_trait;
+#[cfg(feature = "server")]
+use sqlx::sqlite::{Sqlite, SqlitePool};
+#[cfg(feature = "server")]
+use sqlx::{Row, Transaction};
+#[cfg(feature = "server")]
+use std::sync::Arc;
+#[cfg(feature = "server")]
+use tracing::info;
+
+/// Bumped when normalization rules change, so stored addresses are redone
+pub const NORMALIZATION_VERSION: i64 = 1;
+
+/// Job that normalizes addresses of orders stored before (or under older rules than) this module
+pub const BACKFILL_JOB_KIND: &str = "address_backfill";
+
+/// Prefixes naming the kind of province-level unit rather than the unit itself
+const PROVINCE_PREFIXES: &[&str] = &[
+    "thành phố ",
+    "thanh pho ",
+    "tp. ",
+    "tp.",
+    "tp ",
+    "tỉnh ",
+    "tinh ",
+    "province of ",
+    "changwat ",
+    "provinsi ",
+];
+
+/// Recipient address split into administrative levels
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, ToSchema)]
+pub struct NormalizedAddress {
+    /// Country (district level L0)
+    pub country: Option<String>,
+    /// Province, state or centrally-run city (L1), without a "Tỉnh"/"Thành phố" prefix
+    pub province: Option<String>,
+    /// District or county (L2)
+    pub district: Option<String>,
+    /// Ward, commune or sub-district (L3)
+    pub ward: Option<String>,
+    /// House number and street
+    pub street: Option<String>,
+    pub postal_code: Option<String>,
+    pub region_code: Option<String>,
+    /// Problems a courier may trip over, e.g. `invalid_postal_code` or `missing_district`
+    pub issues: Vec<String>,
+}
+
+/// Trim and collapse runs of whitespace; `None` if nothing is left
+pub fn clean(value: &str) -> Option<String> {
+    let cleaned = value.split_whitespace().collect::<Vec<_>>().join(" ");
+    Some(cleaned).filter(|v| !v.is_empty())
+}
+
+/// Province name as stored: cleaned, with administrative-type prefixes dropped
+/// so "Thành phố Hồ Chí Minh" and "Hồ Chí Minh" compare equal
+pub fn province_name(value: &str) -> Option<String> {
+    let cleaned = clean(value)?;
+    let lower = cleaned.to_lowercase();
+    let stripped = PROVINCE_PREFIXES
+        .iter()
+        .find(|prefix| lower.starts_with(*prefix))
+        .map(|prefix| cleaned.chars().skip(prefix.chars().count()).collect())
+        .unwrap_or(cleaned);
+    clean(&stripped)
+}
+
+/// Check `postal_code` against the format used in `region_code`.
+///
+/// Returns the issue to report, if any. Regions without a known format accept
+/// any code; Vietnam doesn't require one.
+pub fn validate_postal_code(
+    region_code: Option<&str>,
+    postal_code: Option<&str>,
+) -> Option<&'static str> {
+    let region = region_code.map(str::to_ascii_uppercase).unwrap_or_default();
+    let (digits, required): (&[usize], bool) = match region.as_str() {
+        "VN" => (&[6], false),
+        "SG" => (&[6], true),
+        "TH" | "MY" | "ID" => (&[5], true),
+        "PH" => (&[4], true),
+        "US" => (&[5, 9], true),
+        _ => return None,
+    };
+
+    let Some(code) = postal_code.map(str::trim).filter(|c| !c.is_empty()) else {
+        return required.then_some("missing_postal_code");
+    };
+    let code: String = code.chars().filter(|c| *c != '-' && *c != ' ').collect();
+    let valid = code.chars().all(|c| c.is_ascii_digit()) && digits.contains(&code.len());
+    (!valid).then_some("invalid_postal_code")
+}
+
+/// Split a TikTok recipient address into country/province/district/ward
+/// using its `district_info` levels, and flag what a courier would miss
+pub fn normalize(address: &RecipientAddress) -> NormalizedAddress {
+    let mut normalized = NormalizedAddress {
+        region_code: address
+            .region_code
+            .as_deref()
+            .and_then(clean)
+            .map(|r| r.to_ascii_uppercase()),
+        postal_code: address.postal_code.as_deref().and_then(clean),
+        ..Default::default()
+    };
+
+    for level in &address.district_info {
+        let name = &level.address_name;
+        match level.address_level.to_ascii_uppercase().as_str() {
+            "L0" => normalized.country = clean(name),
+            "L1" => normalized.province = province_name(name),
+            "L2" => normalized.district = clean(name),
+            "L3" => normalized.ward = clean(name),
+            _ => {}
+        }
+    }
+
+    let lines: Vec<String> = [
+        &address.address_line1,
+        &address.address_line2,
+        &address.address_line3,
+        &address.address_line4,
+    ]
+    .into_iter()
+    .filter_map(|line| line.as_deref().and_then(clean))
+    .collect();
+    normalized.street = if lines.is_empty() {
+        address.address_detail.as_deref().and_then(clean)
+    } else {
+        Some(lines.join(", "))
+    };
+
+    if address.district_info.is_empty() {
+        normalized.issues.push("missing_district_info".to_string());
+    } else {
+        for (field, value) in [
+            ("province", &normalized.province),
+            ("district", &normalized.district),
+        ] {
+            if value.is_none() {
+                normalized.issues.push(format!("missing_{}", field));
+            }
+        }
+    }
+    if normalized.street.is_none() {
+        normalized.issues.push("missing_street".to_string());
+    }
+    if let Some(issue) = validate_postal_code(
+        normalized.region_code.as_deref(),
+        normalized.postal_code.as_deref(),
+    ) {
+        normalized.issues.push(issue.to_string());
+    }
+
+    normalized
+}
+
+/// Normalized recipient addresses in the `order_addresses` table, one row per order
+#[cfg(feature = "server")]
+#[derive(Clone)]
+pub struct AddressStore {
+    pool: SqlitePool,
+    cipher: Option<PiiCipher>,
+}
+
+#[cfg(feature = "server")]
+impl AddressStore {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool, cipher: None }
+    }
+
+    /// Decrypt streets stored encrypted (see [`PiiCipher`])
+    pub fn with_pii_cipher(mut self, cipher: Option<PiiCipher>) -> Self {
+        self.cipher = cipher;
+        self
+    }
+
+    /// Initialize the order_addresses table
+    pub async fn init(&self) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS order_addresses (
+                order_id TEXT PRIMARY KEY,
+                country TEXT,
+                province TEXT,
+                district TEXT,
+                ward TEXT,
+                street TEXT,
+                postal_code TEXT,
+                region_code TEXT,
+                issues TEXT NOT NULL,
+                version INTEGER NOT NULL
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            "CREATE INDEX IF NOT EXISTS idx_order_addresses_area
+            ON order_addresses (province COLLATE NOCASE, district COLLATE NOCASE)",
+        )
+        .execute(&self.pool)
+        .await?;
+        // Listings filter on it, so changes invalidate their ETag
+        database::track_orders_version(&self.pool, "order_addresses").await?;
+
+        Ok(())
+    }
+
+    /// Store the normalized recipient address of `order` within an upsert transaction.
+    /// Orders without an address get an empty row, so the backfill skips them.
+    /// The street is encrypted with `cipher` when set.
+    pub async fn record(
+        tx: &mut Transaction<'_, Sqlite>,
+        order: &Order,
+        cipher: Option<&PiiCipher>,
+    ) -> Result<(), sqlx::Error> {
+        let mut normalized = match &order.recipient_address {
+            Some(address) => normalize(address),
+            None => NormalizedAddress {
+                issues: vec!["missing_address".to_string()],
+                ..Default::default()
+            },
+        };
+        if let Some(cipher) = cipher {
+            cipher.encrypt_field(&mut normalized.street);
+        }
+
+        sqlx::query(
+            "INSERT OR REPLACE INTO order_addresses (
+                order_id, country, province, district, ward, street, postal_code, region_code,
+                issues, version
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+        )
+        .bind(&order.id)
+        .bind(&normalized.country)
+        .bind(&normalized.province)
+        .bind(&normalized.district)
+        .bind(&normalized.ward)
+        .bind(&normalized.street)
+        .bind(&normalized.postal_code)
+        .bind(&normalized.region_code)
+        .bind(normalized.issues.join(","))
+        .bind(NORMALIZATION_VERSION)
+        .execute(&mut **tx)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get(&self, order_id: &str) -> Result<Option<NormalizedAddress>, sqlx::Error> {
+        let row = sqlx::query(
+            "SELECT country, province, district, ward, street, postal_code, region_code, issues
+            FROM order_addresses WHERE order_id = ?1",
+        )
+        .bind(order_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.map(|row| {
+            let issues: String = row.try_get("issues")?;
+            let mut street = row.try_get("street")?;
+            if let Some(cipher) = &self.cipher {
+                cipher.decrypt_field(&mut street);
+            }
+            Ok(NormalizedAddress {
+                country: row.try_get("country")?,
+                province: row.try_get("province")?,
+                district: row.try_get("district")?,
+                ward: row.try_get("ward")?,
+                street,
+                postal_code: row.try_get("postal_code")?,
+                region_code: row.try_get("region_code")?,
+                issues: issues
+                    .split(',')
+                    .filter(|i| !i.is_empty())
+                    .map(str::to_string)
+                    .collect(),
+            })
+        })
+        .transpose()
+    }
+}
+
+/// Normalizes addresses of orders stored before this module or under older rules
+#[cfg(feature = "server")]
+pub struct AddressBackfillHandler {
+    db: Arc<Database>,
+}
+
+#[cfg(feature = "server")]
+impl AddressBackfillHandler {
+    pub fn new(db: Arc<Database>) -> Self {
+        Self { db }
+    }
+}
+
+#[cfg(feature = "server")]
+#[async_trait]
+impl JobHandler for AddressBackfillHandler {
+    async fn handle(&self, _job: &Job) -> Result<(), String> {
+        let normalized = self
+            .db
+            .normalize_stored_addresses()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        info!("Address backfill normalized {} orders", normalized);
+        Ok(())
+    }
+}
@@ -0,0 +1,146 @@
+use crate::error::AppError;
+use crate::requests::TikTokShopApiClient;
+use chrono::NaiveDate;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use utoipa::ToSchema;
+
+/// TikTok API code for an app or shop lacking the scope an endpoint needs
+const SCOPE_NOT_GRANTED_CODE: i32 = 105005;
+
+/// Client for the Data Analytics API (shop performance metrics)
+pub struct AnalyticsClient {
+    api_client: TikTokShopApiClient,
+    shop_cipher: Option<String>,
+}
+
+/// An amount in one currency
+#[derive(Debug, Clone, Default, Deserialize, Serialize, ToSchema)]
+#[serde(default)]
+pub struct MetricAmount {
+    pub amount: String,
+    pub currency: String,
+}
+
+impl MetricAmount {
+    pub fn value(&self) -> Option<f64> {
+        self.amount.trim().parse().ok()
+    }
+}
+
+/// Shop metrics over one interval, `[start_date, end_date)`. Metrics TikTok
+/// adds later are kept in `other`.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, ToSchema)]
+#[serde(default)]
+pub struct ShopPerformanceInterval {
+    pub start_date: String,
+    pub end_date: String,
+    /// Gross merchandise value, per currency
+    pub gmv: Vec<MetricAmount>,
+    pub orders: Option<i64>,
+    pub sku_orders: Option<i64>,
+    pub units_sold: Option<i64>,
+    pub buyers: Option<i64>,
+    pub avg_order_value: Vec<MetricAmount>,
+    pub product_impressions: Option<i64>,
+    pub product_page_views: Option<i64>,
+    pub avg_product_page_visitors: Option<i64>,
+    /// Share of visitors who ordered, as a decimal string
+    pub conversion_rate: Option<String>,
+    #[serde(flatten)]
+    #[schema(value_type = Object)]
+    pub other: BTreeMap<String, serde_json::Value>,
+}
+
+/// Shop performance between `start_date_ge` and `end_date_lt`, one interval
+/// per `granularity` (`1D` is a day)
+#[derive(Debug, Clone)]
+pub struct GetShopPerformanceRequest {
+    pub start_date_ge: NaiveDate,
+    pub end_date_lt: NaiveDate,
+    pub granularity: String,
+    /// `LOCAL` for the shop's currency or `USD`
+    pub currency: String,
+}
+
+impl GetShopPerformanceRequest {
+    /// Daily intervals in the shop's currency
+    pub fn daily(start_date_ge: NaiveDate, end_date_lt: NaiveDate) -> Self {
+        Self {
+            start_date_ge,
+            end_date_lt,
+            granularity: "1D".to_string(),
+            currency: "LOCAL".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ShopPerformance {
+    pub intervals: Vec<ShopPerformanceInterval>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GetShopPerformanceResponse {
+    #[serde(default)]
+    pub performance: ShopPerformance,
+}
+
+/// Whether the error means the app or shop isn't authorized for analytics,
+/// as opposed to a failure worth retrying
+pub fn is_not_authorized(error: &AppError) -> bool {
+    match error {
+        AppError::ApiError { code, .. } => *code == SCOPE_NOT_GRANTED_CODE,
+        AppError::HttpStatus { status, .. } => *status == StatusCode::FORBIDDEN,
+        _ => false,
+    }
+}
+
+impl AnalyticsClient {
+    pub fn new(app_key: String, app_secret: String) -> Self {
+        Self::with_api_client(TikTokShopApiClient::new(app_key, app_secret))
+    }
+
+    pub fn with_api_client(api_client: TikTokShopApiClient) -> Self {
+        Self {
+            api_client,
+            shop_cipher: None,
+        }
+    }
+
+    /// Cipher used when a call doesn't pass one explicitly
+    pub fn with_shop_cipher(mut self, shop_cipher: Option<String>) -> Self {
+        self.shop_cipher = shop_cipher;
+        self
+    }
+
+    /// GMV, orders, buyers and traffic of the shop per interval
+    pub async fn get_shop_performance(
+        &self,
+        access_token: &str,
+        shop_cipher: Option<&str>,
+        request: &GetShopPerformanceRequest,
+    ) -> Result<Vec<ShopPerformanceInterval>, AppError> {
+        let shop_cipher = shop_cipher.or(self.shop_cipher.as_deref());
+        let mut params = BTreeMap::new();
+        params.insert(
+            "start_date_ge".to_string(),
+            request.start_date_ge.to_string(),
+        );
+        params.insert("end_date_lt".to_string(), request.end_date_lt.to_string());
+        params.insert("granularity".to_string(), request.granularity.clone());
+        params.insert("currency".to_string(), request.currency.clone());
+        let response: GetShopPerformanceResponse = self
+            .api_client
+            .get(
+                "/analytics/202405/shop/performance",
+                Some(access_token),
+                shop_cipher,
+                params,
+            )
+            .await?;
+        Ok(response.performance.intervals)
+    }
+}
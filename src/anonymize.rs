@@ -0,0 +1,191 @@
+use crate::order::{Order, RecipientAddress};
+use sha2::{Digest, Sha256};
+
+/// Copy of `order` with buyer names, phones, emails, street addresses, the
+/// buyer message and the TikTok user ID removed. Amounts, items, tracking and
+/// the address's region, postal code and administrative divisions are kept.
+pub fn scrub_order(order: &Order) -> Order {
+    let mut order = order.clone();
+    order.buyer_email = None;
+    order.buyer_message = None;
+    order.user_id = None;
+
+    if let Some(address) = order.recipient_address.as_mut() {
+        for field in [
+            &mut address.full_address,
+            &mut address.name,
+            &mut address.phone,
+            &mut address.address_detail,
+            &mut address.address_line1,
+            &mut address.address_line2,
+            &mut address.address_line3,
+            &mut address.address_line4,
+            &mut address.first_name,
+            &mut address.last_name,
+            &mut address.first_name_local_script,
+            &mut address.last_name_local_script,
+        ] {
+            *field = None;
+        }
+    }
+
+    order
+}
+
+/// Replaces buyer PII in orders with deterministic fakes.
+///
+/// The same input value always maps to the same fake within one `Anonymizer`,
+/// so relationships between orders (e.g. repeat buyers) survive anonymization.
+pub struct Anonymizer {
+    salt: String,
+}
+
+impl Anonymizer {
+    pub fn new(salt: impl Into<String>) -> Self {
+        Self { salt: salt.into() }
+    }
+
+    /// Return a copy of `order` with names, phones, addresses and emails replaced
+    pub fn anonymize_order(&self, order: &Order) -> Order {
+        let mut order = order.clone();
+
+        order.buyer_email = order.buyer_email.as_deref().map(|v| self.fake_email(v));
+        order.buyer_message = order
+            .buyer_message
+            .as_deref()
+            .filter(|v| !v.is_empty())
+            .map(|_| "[redacted buyer message]".to_string());
+        order.user_id = order.user_id.as_deref().map(|v| self.fake_digits(v));
+        order.tracking_number = order.tracking_number.as_deref().map(|v| self.fake_alnum(v));
+
+        if let Some(address) = order.recipient_address.as_mut() {
+            self.anonymize_address(address);
+        }
+
+        for item in order.item_list.iter_mut() {
+            item.tracking_number = item.tracking_number.as_deref().map(|v| self.fake_alnum(v));
+        }
+
+        order
+    }
+
+    fn anonymize_address(&self, address: &mut RecipientAddress) {
+        let name_token = address
+            .name
+            .as_deref()
+            .map(|v| self.token(v))
+            .unwrap_or_else(|| "unknown".to_string());
+
+        address.name = address
+            .name
+            .as_ref()
+            .map(|_| format!("Buyer {}", name_token));
+        address.first_name = address.first_name.as_ref().map(|_| "Buyer".to_string());
+        address.last_name = address.last_name.as_ref().map(|_| name_token.clone());
+        address.first_name_local_script = address
+            .first_name_local_script
+            .as_ref()
+            .map(|_| "Buyer".to_string());
+        address.last_name_local_script = address
+            .last_name_local_script
+            .as_ref()
+            .map(|_| name_token.clone());
+        address.phone = address.phone.as_deref().map(|v| self.fake_phone(v));
+
+        address.address_detail = address
+            .address_detail
+            .as_deref()
+            .map(|v| format!("{} Sample Street", self.number(v)));
+        for line in [
+            &mut address.address_line1,
+            &mut address.address_line2,
+            &mut address.address_line3,
+            &mut address.address_line4,
+        ] {
+            *line = line
+                .as_deref()
+                .filter(|v| !v.is_empty())
+                .map(|v| format!("{} Sample Street", self.number(v)));
+        }
+
+        // Keep the administrative divisions (useful for reproducing routing
+        // issues) but drop the street-level full address.
+        address.full_address = address.full_address.as_ref().map(|_| {
+            let mut parts: Vec<String> = address
+                .district_info
+                .iter()
+                .rev()
+                .map(|d| d.address_name.clone())
+                .collect();
+            parts.insert(0, "Sample Street".to_string());
+            parts.join(", ")
+        });
+    }
+
+    fn digest(&self, value: &str) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update(self.salt.as_bytes());
+        hasher.update(value.as_bytes());
+        hasher.finalize().into()
+    }
+
+    /// Short stable hex token for a value
+    fn token(&self, value: &str) -> String {
+        hex::encode(&self.digest(value)[..3])
+    }
+
+    fn number(&self, value: &str) -> u32 {
+        let d = self.digest(value);
+        u32::from_be_bytes([0, 0, d[0], d[1]]) % 999 + 1
+    }
+
+    fn fake_email(&self, value: &str) -> String {
+        format!("buyer-{}@example.com", self.token(value))
+    }
+
+    /// Replace every digit while keeping length, prefix symbols and masking
+    fn fake_phone(&self, value: &str) -> String {
+        let digest = self.digest(value);
+        let mut i = 0;
+        value
+            .chars()
+            .map(|c| {
+                if c.is_ascii_digit() {
+                    let digit = (digest[i % digest.len()] % 10) as u32;
+                    i += 1;
+                    char::from_digit(digit, 10).unwrap_or('0')
+                } else {
+                    c
+                }
+            })
+            .collect()
+    }
+
+    fn fake_digits(&self, value: &str) -> String {
+        let digest = self.digest(value);
+        (0..value.len().max(1))
+            .map(|i| char::from_digit((digest[i % digest.len()] % 10) as u32, 10).unwrap_or('0'))
+            .collect()
+    }
+
+    /// Same length and character classes as `value`
+    fn fake_alnum(&self, value: &str) -> String {
+        let digest = self.digest(value);
+        value
+            .chars()
+            .enumerate()
+            .map(|(i, c)| {
+                let b = digest[i % digest.len()];
+                if c.is_ascii_digit() {
+                    (b'0' + b % 10) as char
+                } else if c.is_ascii_uppercase() {
+                    (b'A' + b % 26) as char
+                } else if c.is_ascii_lowercase() {
+                    (b'a' + b % 26) as char
+                } else {
+                    c
+                }
+            })
+            .collect()
+    }
+}
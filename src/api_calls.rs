@@ -0,0 +1,169 @@
+use crate::requests::{ApiRecorder, RecordedCall};
+use async_trait::async_trait;
+use serde::Serialize;
+use sqlx::sqlite::{SqlitePool, SqliteRow};
+use sqlx::Row;
+use std::collections::BTreeMap;
+use tracing::warn;
+use utoipa::ToSchema;
+
+/// A recorded TikTok API call
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ApiCall {
+    pub id: i64,
+    pub method: String,
+    pub path: String,
+    /// Query parameters, secrets masked
+    pub params: BTreeMap<String, String>,
+    pub request_body: Option<String>,
+    /// HTTP status, unset when no response came back
+    pub status: Option<i64>,
+    /// `code` from TikTok's response envelope
+    pub api_code: Option<i64>,
+    pub response_body: Option<String>,
+    pub error: Option<String>,
+    pub duration_ms: i64,
+    /// Request ID of the server request or task that made the call
+    pub request_id: Option<String>,
+    pub created_at: i64,
+}
+
+/// Filter for [`ApiCallStore::list`]
+#[derive(Debug, Clone, Default)]
+pub struct ApiCallFilter<'a> {
+    /// Only calls to paths containing this
+    pub path: Option<&'a str>,
+    /// Only calls that errored, got a non-2xx status or a non-zero `code`
+    pub failed: bool,
+    /// Only calls older than this id, for paging
+    pub before_id: Option<i64>,
+}
+
+/// Request/response pairs recorded with `RECORD_API_CALLS`, in the
+/// `api_call_log` table
+#[derive(Debug, Clone)]
+pub struct ApiCallStore {
+    pool: SqlitePool,
+}
+
+impl ApiCallStore {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Initialize the api_call_log table
+    pub async fn init(&self) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS api_call_log (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                method TEXT NOT NULL,
+                path TEXT NOT NULL,
+                params TEXT NOT NULL,
+                request_body TEXT,
+                status INTEGER,
+                api_code INTEGER,
+                response_body TEXT,
+                error TEXT,
+                duration_ms INTEGER NOT NULL,
+                request_id TEXT,
+                created_at INTEGER NOT NULL
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            "CREATE INDEX IF NOT EXISTS idx_api_call_log_created_at
+            ON api_call_log (created_at)",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn insert(&self, call: &RecordedCall) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "INSERT INTO api_call_log (method, path, params, request_body, status, api_code,
+                response_body, error, duration_ms, request_id, created_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+        )
+        .bind(&call.method)
+        .bind(&call.path)
+        .bind(serde_json::to_string(&call.params).unwrap_or_default())
+        .bind(&call.request_body)
+        .bind(call.status.map(i64::from))
+        .bind(call.api_code)
+        .bind(&call.response_body)
+        .bind(&call.error)
+        .bind(call.duration_ms as i64)
+        .bind(&call.request_id)
+        .bind(chrono::Utc::now().timestamp())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Newest calls first
+    pub async fn list(
+        &self,
+        filter: &ApiCallFilter<'_>,
+        limit: i64,
+    ) -> Result<Vec<ApiCall>, sqlx::Error> {
+        sqlx::query(
+            "SELECT id, method, path, params, request_body, status, api_code, response_body,
+                error, duration_ms, request_id, created_at
+            FROM api_call_log
+            WHERE (?1 IS NULL OR instr(path, ?1) > 0)
+            AND (?2 = 0 OR error IS NOT NULL OR status NOT BETWEEN 200 AND 299
+                OR api_code != 0)
+            AND (?3 IS NULL OR id < ?3)
+            ORDER BY id DESC LIMIT ?4",
+        )
+        .bind(filter.path)
+        .bind(filter.failed)
+        .bind(filter.before_id)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(call_from_row)
+        .collect()
+    }
+
+    /// Delete calls recorded more than `retention_secs` ago
+    pub async fn prune(&self, retention_secs: i64) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query("DELETE FROM api_call_log WHERE created_at < ?1")
+            .bind(chrono::Utc::now().timestamp() - retention_secs)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+}
+
+#[async_trait]
+impl ApiRecorder for ApiCallStore {
+    async fn record(&self, call: RecordedCall) {
+        if let Err(e) = self.insert(&call).await {
+            warn!("Failed to record API call to {}: {}", call.path, e);
+        }
+    }
+}
+
+fn call_from_row(row: &SqliteRow) -> Result<ApiCall, sqlx::Error> {
+    let params: String = row.try_get("params")?;
+    Ok(ApiCall {
+        id: row.try_get("id")?,
+        method: row.try_get("method")?,
+        path: row.try_get("path")?,
+        params: serde_json::from_str(&params).unwrap_or_default(),
+        request_body: row.try_get("request_body")?,
+        status: row.try_get("status")?,
+        api_code: row.try_get("api_code")?,
+        response_body: row.try_get("response_body")?,
+        error: row.try_get("error")?,
+        duration_ms: row.try_get("duration_ms")?,
+        request_id: row.try_get("request_id")?,
+        created_at: row.try_get("created_at")?,
+    })
+}
@@ -0,0 +1,393 @@
+use crate::config::LiveConfig;
+use crate::database::Database;
+use crate::health::ApiHealth;
+use crate::jobs::{Job, JobHandler, JobQueue};
+use crate::order::GetOrderListRequest;
+use crate::shops::ShopRegistry;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::SqlitePool;
+use sqlx::Row;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+use utoipa::ToSchema;
+
+/// Fetch the next pages of a historical order import
+pub const BACKFILL_JOB_KIND: &str = "order_backfill";
+
+/// Widest `create_time` range requested from TikTok at once
+pub const MAX_WINDOW_DAYS: i64 = 30;
+pub const DEFAULT_WINDOW_DAYS: i64 = 7;
+
+/// Pages fetched per job, so a long import doesn't hold up other jobs
+const PAGES_PER_JOB: u32 = 20;
+
+/// A historical order import over `[from_time, to_time)`, walked in
+/// `create_time` windows. `cursor_time` and `page_token` mark where to resume.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct BackfillRun {
+    pub id: i64,
+    pub from_time: i64,
+    pub to_time: i64,
+    pub window_secs: i64,
+    /// Start of the window being fetched
+    pub cursor_time: i64,
+    /// Next page within the current window
+    pub page_token: Option<String>,
+    pub pages_fetched: i64,
+    pub orders_fetched: i64,
+    /// `running`, `completed` or `failed`
+    pub status: String,
+    pub last_error: Option<String>,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+impl BackfillRun {
+    /// End of the window being fetched
+    pub fn window_end(&self) -> i64 {
+        (self.cursor_time + self.window_secs).min(self.to_time)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackfillJobPayload {
+    pub run_id: i64,
+}
+
+/// Backfill runs and their progress, in the `backfill_runs` table
+#[derive(Clone)]
+pub struct BackfillStore {
+    pool: SqlitePool,
+}
+
+impl BackfillStore {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Initialize the backfill_runs table
+    pub async fn init(&self) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS backfill_runs (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                from_time INTEGER NOT NULL,
+                to_time INTEGER NOT NULL,
+                window_secs INTEGER NOT NULL,
+                cursor_time INTEGER NOT NULL,
+                page_token TEXT,
+                pages_fetched INTEGER NOT NULL DEFAULT 0,
+                orders_fetched INTEGER NOT NULL DEFAULT 0,
+                status TEXT NOT NULL,
+                last_error TEXT,
+                created_at INTEGER NOT NULL,
+                updated_at INTEGER NOT NULL
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Record a new run and queue its first job
+    pub async fn start(
+        &self,
+        job_queue: &JobQueue,
+        from_time: i64,
+        to_time: i64,
+        window_days: i64,
+        max_attempts: u32,
+    ) -> Result<BackfillRun, sqlx::Error> {
+        let now = chrono::Utc::now().timestamp();
+        let window_secs = window_days.clamp(1, MAX_WINDOW_DAYS) * 86_400;
+
+        let id = sqlx::query(
+            "INSERT INTO backfill_runs (
+                from_time, to_time, window_secs, cursor_time, status, created_at, updated_at
+            ) VALUES (?1, ?2, ?3, ?1, 'running', ?4, ?4)",
+        )
+        .bind(from_time)
+        .bind(to_time)
+        .bind(window_secs)
+        .bind(now)
+        .execute(&self.pool)
+        .await?
+        .last_insert_rowid();
+
+        enqueue_step(job_queue, id, 0, max_attempts).await?;
+
+        self.get(id).await?.ok_or(sqlx::Error::RowNotFound)
+    }
+
+    pub async fn get(&self, id: i64) -> Result<Option<BackfillRun>, sqlx::Error> {
+        let row = sqlx::query(
+            "SELECT id, from_time, to_time, window_secs, cursor_time, page_token, pages_fetched,
+                orders_fetched, status, last_error, created_at, updated_at
+            FROM backfill_runs WHERE id = ?1",
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.map(|row| run_from_row(&row)).transpose()
+    }
+
+    /// Runs, newest first
+    pub async fn list(&self, limit: i64) -> Result<Vec<BackfillRun>, sqlx::Error> {
+        let rows = sqlx::query(
+            "SELECT id, from_time, to_time, window_secs, cursor_time, page_token, pages_fetched,
+                orders_fetched, status, last_error, created_at, updated_at
+            FROM backfill_runs ORDER BY id DESC LIMIT ?1",
+        )
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter().map(run_from_row).collect()
+    }
+
+    /// Save progress after a page has been stored
+    async fn advance(
+        &self,
+        run: &BackfillRun,
+        cursor_time: i64,
+        page_token: Option<&str>,
+        orders: usize,
+    ) -> Result<(), sqlx::Error> {
+        let status = if cursor_time >= run.to_time { "completed" } else { "running" };
+
+        sqlx::query(
+            "UPDATE backfill_runs SET cursor_time = ?2, page_token = ?3,
+                pages_fetched = pages_fetched + 1, orders_fetched = orders_fetched + ?4,
+                status = ?5, last_error = NULL, updated_at = ?6
+            WHERE id = ?1",
+        )
+        .bind(run.id)
+        .bind(cursor_time)
+        .bind(page_token)
+        .bind(orders as i64)
+        .bind(status)
+        .bind(chrono::Utc::now().timestamp())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn set_status(&self, id: i64, status: &str, error: Option<&str>) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "UPDATE backfill_runs SET status = ?2, last_error = COALESCE(?3, last_error), updated_at = ?4
+            WHERE id = ?1",
+        )
+        .bind(id)
+        .bind(status)
+        .bind(error)
+        .bind(chrono::Utc::now().timestamp())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+fn run_from_row(row: &sqlx::sqlite::SqliteRow) -> Result<BackfillRun, sqlx::Error> {
+    Ok(BackfillRun {
+        id: row.try_get("id")?,
+        from_time: row.try_get("from_time")?,
+        to_time: row.try_get("to_time")?,
+        window_secs: row.try_get("window_secs")?,
+        cursor_time: row.try_get("cursor_time")?,
+        page_token: row.try_get("page_token")?,
+        pages_fetched: row.try_get("pages_fetched")?,
+        orders_fetched: row.try_get("orders_fetched")?,
+        status: row.try_get("status")?,
+        last_error: row.try_get("last_error")?,
+        created_at: row.try_get("created_at")?,
+        updated_at: row.try_get("updated_at")?,
+    })
+}
+
+/// Parse a backfill bound: RFC 3339, `YYYY-MM-DD` (midnight UTC) or unix seconds
+pub fn parse_time(value: &str) -> Option<i64> {
+    let value = value.trim();
+    if let Ok(time) = chrono::DateTime::parse_from_rfc3339(value) {
+        return Some(time.timestamp());
+    }
+    if let Ok(date) = chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return Some(date.and_hms_opt(0, 0, 0)?.and_utc().timestamp());
+    }
+    value.parse().ok()
+}
+
+/// Each step gets its own dedup key, so a crashed step is resumed rather than duplicated
+async fn enqueue_step(
+    job_queue: &JobQueue,
+    run_id: i64,
+    pages_fetched: i64,
+    max_attempts: u32,
+) -> Result<bool, sqlx::Error> {
+    job_queue
+        .enqueue(
+            BACKFILL_JOB_KIND,
+            Some(&format!("{}:{}:{}", BACKFILL_JOB_KIND, run_id, pages_fetched)),
+            &serde_json::to_value(BackfillJobPayload { run_id }).unwrap_or_default(),
+            max_attempts,
+        )
+        .await
+}
+
+/// Fetches up to [`PAGES_PER_JOB`] pages of a run, then queues the next step.
+///
+/// Orders are stored without triggering fulfillment: historical orders must
+/// not provision eSIMs.
+pub struct BackfillHandler {
+    db: Arc<Database>,
+    store: BackfillStore,
+    job_queue: JobQueue,
+    shops: ShopRegistry,
+    /// Live, so a reloaded `BACKFILL_PAGE_DELAY_MS` paces the next step
+    config: LiveConfig,
+    api_health: Arc<ApiHealth>,
+}
+
+impl BackfillHandler {
+    pub fn new(
+        db: Arc<Database>,
+        store: BackfillStore,
+        job_queue: JobQueue,
+        shops: ShopRegistry,
+        config: LiveConfig,
+        api_health: Arc<ApiHealth>,
+    ) -> Self {
+        Self {
+            db,
+            store,
+            job_queue,
+            shops,
+            config,
+            api_health,
+        }
+    }
+
+    async fn run_step(&self, run_id: i64) -> Result<(), String> {
+        let Some(mut run) = self.store.get(run_id).await.map_err(|e| e.to_string())? else {
+            warn!("Backfill run {} no longer exists", run_id);
+            return Ok(());
+        };
+        if run.status == "completed" {
+            return Ok(());
+        }
+        if run.status != "running" {
+            // A retried dead letter resumes a failed run
+            self.store
+                .set_status(run.id, "running", None)
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+
+        if !self.api_health.allows_requests() {
+            return Err("TikTok API circuit is open".to_string());
+        }
+
+        let config = self.config.current();
+        let token = config
+            .token_manager()
+            .valid_token()
+            .await
+            .map_err(|e| e.to_string())?;
+        let order_client = self
+            .shops
+            .order_client(&config)
+            .await
+            .map_err(|e| e.to_string())?
+            .with_health(self.api_health.clone());
+        let page_delay = Duration::from_millis(config.backfill_page_delay_ms);
+
+        for page in 0..PAGES_PER_JOB {
+            if page > 0 {
+                tokio::time::sleep(page_delay).await;
+            }
+
+            let window_end = run.window_end();
+            let mut request = GetOrderListRequest::new()
+                .with_page_size(50)
+                .with_create_time_range(run.cursor_time, window_end);
+            if let Some(token) = run.page_token.clone() {
+                request = request.with_page_token(token);
+            }
+
+            let response = order_client
+                .get_order_list(&token.access_token, None, config.shop_id.as_deref(), request)
+                .await
+                .map_err(|e| e.to_string())?;
+
+            self.db
+                .upsert_backfilled_orders(&response.orders)
+                .await
+                .map_err(|e| e.to_string())?;
+
+            // An empty token means the window is exhausted
+            let next_page = response.next_page_token.filter(|t| !t.is_empty());
+            let cursor_time = if next_page.is_some() { run.cursor_time } else { window_end };
+            self.store
+                .advance(&run, cursor_time, next_page.as_deref(), response.orders.len())
+                .await
+                .map_err(|e| e.to_string())?;
+
+            run = self
+                .store
+                .get(run.id)
+                .await
+                .map_err(|e| e.to_string())?
+                .ok_or_else(|| format!("Backfill run {} disappeared", run_id))?;
+
+            if run.status == "completed" {
+                info!(
+                    "Backfill run {} completed: {} orders in {} pages",
+                    run.id, run.orders_fetched, run.pages_fetched
+                );
+                return Ok(());
+            }
+        }
+
+        info!(
+            "Backfill run {} reached {} ({} orders so far)",
+            run.id,
+            chrono::DateTime::from_timestamp(run.cursor_time, 0).unwrap_or_default(),
+            run.orders_fetched
+        );
+        enqueue_step(&self.job_queue, run.id, run.pages_fetched, config.job_max_attempts)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl JobHandler for BackfillHandler {
+    async fn handle(&self, job: &Job) -> Result<(), String> {
+        let payload: BackfillJobPayload = serde_json::from_value(job.payload.clone())
+            .map_err(|e| format!("Invalid backfill payload: {}", e))?;
+
+        let result = self.run_step(payload.run_id).await;
+        if let Err(error) = &result {
+            // Progress is kept; the retry resumes from the last stored page
+            if let Err(e) = self.store.set_status(payload.run_id, "running", Some(error)).await {
+                warn!("Failed to record backfill run {} error: {}", payload.run_id, e);
+            }
+        }
+        result
+    }
+
+    async fn on_permanent_failure(&self, job: &Job, error: &str) {
+        let Ok(payload) = serde_json::from_value::<BackfillJobPayload>(job.payload.clone()) else {
+            return;
+        };
+        if let Err(e) = self.store.set_status(payload.run_id, "failed", Some(error)).await {
+            warn!("Failed to mark backfill run {} failed: {}", payload.run_id, e);
+        }
+    }
+}
@@ -0,0 +1,760 @@
+//! OpenAPI description of the HTTP API, served as Swagger UI at `/docs`.
+//!
+//! Handlers build their JSON bodies with `serde_json::json!`; the envelope
+//! structs below only describe those bodies for the generated spec.
+
+use serde::Serialize;
+use toptop_order::address::NormalizedAddress;
+use toptop_order::analytics::{MetricAmount, ShopPerformanceInterval};
+use toptop_order::api_calls::ApiCall;
+use toptop_order::backfill::BackfillRun;
+use toptop_order::cancellations::{CancellationBreakdown, CancellationCount, StoredCancellation};
+use toptop_order::carriers::{Carrier, CarrierUpdate, TrackedOrder};
+use toptop_order::communications::{Direction, TimelineEntry};
+use toptop_order::customers::Customer;
+use toptop_order::daily_stats::{Granularity, Metric, TimeseriesPoint};
+use toptop_order::database::BuyerMessage;
+use toptop_order::erasure::ErasedDocument;
+use toptop_order::esim::{EsimUsageSnapshot, ProvisionedEsim};
+use toptop_order::esim_delivery::EsimDelivery;
+use toptop_order::events::{OrderEvent, OrderEventKind};
+use toptop_order::external_refs::ExternalRef;
+use toptop_order::fulfillment::{CombinablePackage, SplitPackage};
+use toptop_order::fx::{FxRate, OrderFx, RateSource};
+use toptop_order::health::{CircuitState, HealthSnapshot};
+use toptop_order::item_fulfillment::{FulfillmentProgress, ItemFulfillment, ItemState};
+use toptop_order::jobs::DeadLetter;
+use toptop_order::logistics::{
+    CachedDeliveryOption, CachedWarehouse, DeliveryOption, ShippingProvider, Warehouse,
+    WarehouseAddress,
+};
+use toptop_order::maintenance::{DatabaseStats, MaintenanceRun, TableStats};
+use toptop_order::mirror::OrderMirror;
+use toptop_order::notes::OrderNote;
+use toptop_order::order::{
+    CancelLineItem, Cancellation, DistrictInfo, LineItemPriceDetail, Order, OrderItem, Package,
+    PaymentInfo, PriceDetail, RecipientAddress,
+};
+use toptop_order::order_diff::{FieldChange, OrderDiff};
+use toptop_order::order_timeline::{EventSource, OrderTimelineEvent};
+use toptop_order::outbound_webhooks::WebhookDelivery;
+use toptop_order::packages::{PackageOrigin, TrackedPackage};
+use toptop_order::price_details::StoredPriceDetail;
+use toptop_order::processing::{OrderProcessing, ProcessingState, ProcessingTransition};
+use toptop_order::promotions::{Campaign, CampaignDiscounts};
+use toptop_order::reconciliation::{Discrepancy, DiscrepancyKind, ReconciliationRun};
+use toptop_order::replacements::ReplacementLinks;
+use toptop_order::risk::{RiskAssessment, RiskSignal};
+use toptop_order::scheduler::TaskStatus;
+use toptop_order::shop_performance::ShopPerformanceDay;
+use toptop_order::shop_settings::{ShopSettings, ShopSettingsUpdate};
+use toptop_order::shops::{ShopDisconnection, ShopSummary};
+use toptop_order::sku_mappings::{
+    FulfillmentAction, SkuMapping, SkuMappingUpdate, SkuMappingsUpdate,
+};
+use toptop_order::sku_stats::SkuSales;
+use toptop_order::sla::{LateOrder, SlaDeadline, SlaKind};
+use toptop_order::startup::{StartupPhase, StartupStatus, StartupStep};
+use toptop_order::supervisor::{SupervisedState, SupervisedStatus};
+use toptop_order::tags::OrderTag;
+use toptop_order::threepl::{ThreePlCallback, ThreePlShipment};
+use toptop_order::validation::OrderAnomaly;
+use toptop_order::warehouse_routing::WarehouseAssignment;
+use utoipa::{OpenApi, ToSchema};
+
+#[derive(Serialize, ToSchema)]
+pub struct ErrorResponse {
+    pub error: String,
+    /// Stable identifier such as `not_found` or `upstream_api_error`
+    pub code: String,
+    /// Whether repeating the request may succeed
+    pub retryable: bool,
+    /// TikTok error code, for `upstream_api_error`
+    pub upstream_code: Option<i32>,
+    /// TikTok request ID, for `upstream_api_error`
+    pub upstream_request_id: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct OrdersResponse {
+    pub success: bool,
+    pub count: usize,
+    pub orders: Vec<TrackedOrder>,
+    /// Pass as `cursor` for the next page; absent on the last page and on full listings
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct OrderResponse {
+    pub success: bool,
+    /// `local` or `api`
+    pub source: String,
+    pub replacement: ReplacementLinks,
+    /// Risk score given when the order was first synced
+    pub risk: Option<RiskAssessment>,
+    /// ERP/invoice reference, if one was attached
+    pub external_ref: Option<ExternalRef>,
+    /// Preferred warehouse chosen by `WAREHOUSE_ROUTES`
+    pub warehouse: Option<WarehouseAssignment>,
+    /// Total converted into `BASE_CURRENCY`, when conversion is enabled
+    pub fx: Option<OrderFx>,
+    /// Fulfillment status of each line item, as of the last sync
+    pub items: Vec<ItemFulfillment>,
+    pub order: TrackedOrder,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct OrderDiffResponse {
+    pub success: bool,
+    /// Whether the stored copy matches the live one field for field
+    pub in_sync: bool,
+    pub diff: OrderDiff,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct TimelineResponse {
+    pub success: bool,
+    pub order_id: String,
+    pub count: usize,
+    pub timeline: Vec<TimelineEntry>,
+    /// Every event known about the order, oldest first
+    pub events: Vec<OrderTimelineEvent>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct HealthResponse {
+    pub status: String,
+    /// `production` or `sandbox`
+    pub mode: String,
+    pub timestamp: String,
+    pub tiktok_api: HealthSnapshot,
+    /// Set while the seller has de-authorized the app
+    pub shop_disconnected: Option<ShopDisconnection>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ReadyResponse {
+    /// `ready`, `degraded` (a scheduled task's last run failed or a background
+    /// task is restarting after a panic), `starting` or `unavailable`
+    pub status: String,
+    /// Startup phases done so far
+    pub startup: StartupStatus,
+    /// `ok`, or the error from querying the database
+    pub database: String,
+    pub tasks: Vec<TaskStatus>,
+    pub supervised: Vec<SupervisedStatus>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct WebhookAckResponse {
+    pub success: bool,
+    /// The event was already received and was not processed again
+    pub duplicate: bool,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct CustomerOrdersResponse {
+    pub success: bool,
+    pub customer: Customer,
+    pub count: usize,
+    pub orders: Vec<TrackedOrder>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct CustomerErasureResponse {
+    pub success: bool,
+    pub customer_id: String,
+    /// Orders the customer's PII was scrubbed from
+    pub orders_scrubbed: u64,
+    /// Cached shipping labels deleted with their files
+    pub documents_deleted: Vec<ErasedDocument>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct LookupResponse {
+    pub success: bool,
+    /// `partial` when only the last 4 phone digits were given or the tracking
+    /// number matched as a substring, otherwise `exact`
+    #[serde(rename = "match")]
+    pub matched: String,
+    pub count: usize,
+    pub orders: Vec<TrackedOrder>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct CarriersResponse {
+    pub success: bool,
+    pub count: usize,
+    pub carriers: Vec<Carrier>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct CarrierResponse {
+    pub success: bool,
+    pub carrier: Carrier,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct DeleteCarrierResponse {
+    pub success: bool,
+    pub key: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct SkuStatsResponse {
+    pub success: bool,
+    pub window_days: i64,
+    pub count: usize,
+    pub skus: Vec<SkuSales>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct FxRatesResponse {
+    pub success: bool,
+    pub base_currency: Option<String>,
+    pub count: usize,
+    pub rates: Vec<FxRate>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct TimeseriesResponse {
+    pub success: bool,
+    pub metric: Metric,
+    pub granularity: Granularity,
+    pub from: String,
+    pub to: String,
+    pub points: Vec<TimeseriesPoint>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct CancellationStatsResponse {
+    pub success: bool,
+    pub from: String,
+    pub to: String,
+    pub breakdown: CancellationBreakdown,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct DiscountStatsResponse {
+    pub success: bool,
+    pub from: String,
+    pub to: String,
+    pub campaigns: Vec<CampaignDiscounts>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ShopsResponse {
+    pub success: bool,
+    pub count: usize,
+    pub shops: Vec<ShopSummary>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ShopSettingsResponse {
+    pub success: bool,
+    pub settings: ShopSettings,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ShopPerformanceResponse {
+    pub success: bool,
+    pub from: String,
+    pub to: String,
+    pub days: Vec<ShopPerformanceDay>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ShopPerformanceSyncResponse {
+    pub success: bool,
+    pub from: String,
+    pub to: String,
+    /// Days stored
+    pub days: usize,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct AnomaliesResponse {
+    pub success: bool,
+    pub count: usize,
+    pub anomalies: Vec<OrderAnomaly>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct SlaResponse {
+    pub success: bool,
+    pub due_within_secs: i64,
+    pub count: usize,
+    pub deadlines: Vec<SlaDeadline>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct LateOrdersResponse {
+    pub success: bool,
+    pub count: usize,
+    pub orders: Vec<LateOrder>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct BuyerMessagesResponse {
+    pub success: bool,
+    pub count: usize,
+    pub messages: Vec<BuyerMessage>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct BuyerMessageResponse {
+    pub success: bool,
+    pub message: BuyerMessage,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct OrderTagsResponse {
+    pub success: bool,
+    pub order_id: String,
+    pub tags: Vec<OrderTag>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct OrderNotesResponse {
+    pub success: bool,
+    pub order_id: String,
+    pub count: usize,
+    pub notes: Vec<OrderNote>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct OrderProcessingResponse {
+    pub success: bool,
+    pub processing: OrderProcessing,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct OrderNoteResponse {
+    pub success: bool,
+    pub note: OrderNote,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct WebhookDeliveriesResponse {
+    pub success: bool,
+    pub count: usize,
+    pub deliveries: Vec<WebhookDelivery>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ApiCallsResponse {
+    pub success: bool,
+    /// Whether `RECORD_API_CALLS` is on; older calls stay listed until pruned
+    pub recording: bool,
+    pub count: usize,
+    pub calls: Vec<ApiCall>,
+    /// Pass as `before` for the next page; unset on the last page
+    pub next_before: Option<i64>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ReconciliationRunsResponse {
+    pub success: bool,
+    pub count: usize,
+    pub runs: Vec<ReconciliationRun>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct DeadLettersResponse {
+    pub success: bool,
+    pub count: usize,
+    pub dead_letters: Vec<DeadLetter>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct BackfillResponse {
+    pub success: bool,
+    pub run: BackfillRun,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct BackfillsResponse {
+    pub success: bool,
+    pub count: usize,
+    pub runs: Vec<BackfillRun>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct OrderAddressResponse {
+    pub success: bool,
+    pub order_id: String,
+    pub address: NormalizedAddress,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct PriceDetailResponse {
+    pub success: bool,
+    pub price_detail: StoredPriceDetail,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ExternalRefResponse {
+    pub success: bool,
+    pub external_ref: ExternalRef,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct OrderMirrorsResponse {
+    pub success: bool,
+    pub count: usize,
+    pub mirrors: Vec<OrderMirror>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ThreePlShipmentResponse {
+    pub success: bool,
+    pub shipment: ThreePlShipment,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct SkuMappingsResponse {
+    pub success: bool,
+    pub count: usize,
+    pub mappings: Vec<SkuMapping>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct EsimDeliveriesResponse {
+    pub success: bool,
+    pub order_id: String,
+    pub deliveries: Vec<EsimDelivery>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct EsimResendResponse {
+    pub success: bool,
+    pub order_id: String,
+    /// Items whose eSIM delivery was queued again
+    pub line_item_ids: Vec<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct EsimUsageResponse {
+    pub success: bool,
+    pub esim: ProvisionedEsim,
+    /// Unset until the usage is first polled
+    pub latest: Option<EsimUsageSnapshot>,
+    pub history: Vec<EsimUsageSnapshot>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct SetExternalRefResponse {
+    pub success: bool,
+    pub external_ref: ExternalRef,
+    /// Whether a push to TikTok was queued (`PUSH_EXTERNAL_REFS`)
+    pub push_queued: bool,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct DeleteExternalRefResponse {
+    pub success: bool,
+    pub order_id: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct OrderPackagesResponse {
+    pub success: bool,
+    pub order_id: String,
+    /// Packages TikTok reported at the last sync
+    pub packages: Vec<Package>,
+    /// Packages created here by splitting or combining
+    pub tracked: Vec<TrackedPackage>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct CancellationsResponse {
+    pub success: bool,
+    pub count: usize,
+    pub cancellations: Vec<StoredCancellation>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct CancellationResponse {
+    pub success: bool,
+    pub cancellation: StoredCancellation,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct SplitOrderResponse {
+    pub success: bool,
+    pub order_id: String,
+    pub packages: Vec<TrackedPackage>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct CombinablePackagesResponse {
+    pub success: bool,
+    pub combinable_packages: Vec<CombinablePackage>,
+    pub total_count: i64,
+    pub next_page_token: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct CombinePackagesResponse {
+    pub success: bool,
+    pub packages: Vec<CombinablePackage>,
+    /// Combinations TikTok rejected
+    pub errors: Vec<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct LogisticsResponse {
+    pub success: bool,
+    pub warehouses: Vec<CachedWarehouse>,
+    pub fetched_at: i64,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ValidateShippingResponse {
+    pub success: bool,
+    pub warehouse_id: String,
+    pub valid: bool,
+    /// Why the combination can't be used
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct DbStatsResponse {
+    pub success: bool,
+    pub stats: DatabaseStats,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct RetryDeadLetterResponse {
+    pub success: bool,
+    pub dead_letter_id: i64,
+    pub job_id: i64,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ConfigReloadResponse {
+    pub success: bool,
+    /// Settings now in effect, e.g. `sync_schedule`
+    pub applied: Vec<String>,
+    /// Whether settings that need a restart changed too
+    pub restart_required: bool,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct TasksResponse {
+    pub success: bool,
+    pub count: usize,
+    pub tasks: Vec<crate::BackgroundTask>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct TaskResponse {
+    pub success: bool,
+    pub task: crate::BackgroundTask,
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "toptop-order", description = "Synced TikTok Shop orders and fulfillment state"),
+    paths(
+        crate::get_orders_handler,
+        crate::order_stream_handler,
+        crate::list_anomalies_handler,
+        crate::sla_handler,
+        crate::late_orders_handler,
+        crate::buyer_messages_handler,
+        crate::ack_buyer_message_handler,
+        crate::list_order_tags_handler,
+        crate::add_order_tags_handler,
+        crate::remove_order_tag_handler,
+        crate::list_order_notes_handler,
+        crate::add_order_note_handler,
+        crate::get_order_handler,
+        crate::get_order_diff_handler,
+        crate::get_order_timeline_handler,
+        crate::get_order_processing_handler,
+        crate::get_order_address_handler,
+        crate::get_order_price_detail_handler,
+        crate::get_external_ref_handler,
+        crate::get_threepl_shipment_handler,
+        crate::list_order_mirrors_handler,
+        crate::get_esim_deliveries_handler,
+        crate::resend_esim_handler,
+        crate::get_esim_usage_handler,
+        crate::set_external_ref_handler,
+        crate::delete_external_ref_handler,
+        crate::list_order_packages_handler,
+        crate::split_order_handler,
+        crate::list_combinable_packages_handler,
+        crate::combine_packages_handler,
+        crate::get_logistics_handler,
+        crate::validate_shipping_handler,
+        crate::packing_slip_handler,
+        crate::shipping_label_handler,
+        crate::list_cancellations_handler,
+        crate::approve_cancellation_handler,
+        crate::reject_cancellation_handler,
+        crate::get_customer_orders_handler,
+        crate::erase_customer_data_handler,
+        crate::lookup_handler,
+        crate::list_carriers_handler,
+        crate::put_carrier_handler,
+        crate::delete_carrier_handler,
+        crate::sku_stats_handler,
+        crate::stats_timeseries_handler,
+        crate::cancellation_stats_handler,
+        crate::discount_stats_handler,
+        crate::shop_performance_handler,
+        crate::sync_shop_performance_handler,
+        crate::list_shops_handler,
+        crate::get_shop_settings_handler,
+        crate::update_shop_settings_handler,
+        crate::fx_rates_handler,
+        crate::accounting_export_handler,
+        crate::start_backfill_handler,
+        crate::list_backfills_handler,
+        crate::get_backfill_handler,
+        crate::health_handler,
+        crate::readiness_handler,
+        crate::tiktok_webhook_handler,
+        crate::threepl_callback_handler,
+        crate::dashboard_handler,
+        crate::authorize_handler,
+        crate::oauth_callback_handler,
+        crate::list_dead_letters_handler,
+        crate::retry_dead_letter_handler,
+        crate::list_sku_mappings_handler,
+        crate::put_sku_mappings_handler,
+        crate::reload_config_handler,
+        crate::list_tasks_handler,
+        crate::stop_task_handler,
+        crate::start_task_handler,
+        crate::db_stats_handler,
+        crate::list_webhook_deliveries_handler,
+        crate::list_api_calls_handler,
+        crate::list_reconciliation_runs_handler,
+    ),
+    components(schemas(
+        Order,
+        TrackedOrder,
+        FulfillmentProgress,
+        ItemFulfillment,
+        ItemState,
+        Carrier,
+        CarrierUpdate,
+        OrderItem,
+        Package,
+        PaymentInfo,
+        PriceDetail,
+        LineItemPriceDetail,
+        StoredPriceDetail,
+        ExternalRef,
+        ThreePlShipment,
+        OrderMirror,
+        ProvisionedEsim,
+        EsimUsageSnapshot,
+        ThreePlCallback,
+        crate::ExternalRefRequest,
+        TrackedPackage,
+        PackageOrigin,
+        SplitPackage,
+        CombinablePackage,
+        crate::SplitRequest,
+        crate::SplitGroup,
+        crate::CombineRequest,
+        CachedWarehouse,
+        WarehouseAssignment,
+        CachedDeliveryOption,
+        Warehouse,
+        WarehouseAddress,
+        DeliveryOption,
+        ShippingProvider,
+        RecipientAddress,
+        NormalizedAddress,
+        DistrictInfo,
+        TimelineEntry,
+        OrderTimelineEvent,
+        EventSource,
+        Direction,
+        HealthSnapshot,
+        CircuitState,
+        TaskStatus,
+        StartupStatus,
+        StartupStep,
+        StartupPhase,
+        SupervisedStatus,
+        SupervisedState,
+        BackfillRun,
+        crate::BackfillRequest,
+        DeadLetter,
+        WebhookDelivery,
+        ApiCall,
+        ReconciliationRun,
+        Discrepancy,
+        DiscrepancyKind,
+        DatabaseStats,
+        TableStats,
+        MaintenanceRun,
+        OrderAnomaly,
+        SlaDeadline,
+        SlaKind,
+        LateOrder,
+        BuyerMessage,
+        OrderTag,
+        ReplacementLinks,
+        OrderDiff,
+        FieldChange,
+        RiskAssessment,
+        RiskSignal,
+        OrderProcessing,
+        ProcessingState,
+        ProcessingTransition,
+        OrderNote,
+        StoredCancellation,
+        Cancellation,
+        CancelLineItem,
+        CancellationBreakdown,
+        CancellationCount,
+        Campaign,
+        CampaignDiscounts,
+        ShopPerformanceDay,
+        ShopDisconnection,
+        ShopSummary,
+        ShopSettings,
+        ShopSettingsUpdate,
+        SkuMapping,
+        SkuMappingUpdate,
+        SkuMappingsUpdate,
+        FulfillmentAction,
+        ShopPerformanceInterval,
+        MetricAmount,
+        crate::RejectCancellationBody,
+        crate::TagsRequest,
+        crate::NoteRequest,
+        Customer,
+        ErasedDocument,
+        SkuSales,
+        TimeseriesResponse,
+        TimeseriesPoint,
+        Metric,
+        Granularity,
+        FxRatesResponse,
+        FxRate,
+        RateSource,
+        OrderFx,
+        OrderEvent,
+        OrderEventKind,
+        ErrorResponse,
+    )),
+    tags(
+        (name = "orders", description = "Stored orders"),
+        (name = "customers", description = "Buyers derived from stored orders"),
+        (name = "carriers", description = "Shipping provider tracking links"),
+        (name = "fulfillment", description = "Packages, warehouses and shipping providers"),
+        (name = "stats", description = "Sales aggregates"),
+        (name = "exports", description = "Accounting exports"),
+        (name = "webhooks", description = "TikTok push events"),
+        (name = "admin", description = "Operations"),
+    )
+)]
+pub struct ApiDoc;
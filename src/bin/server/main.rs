@@ -0,0 +1,6342 @@
+use axum::{
+    body::{Body, Bytes},
+    extract::{rejection::JsonRejection, FromRequestParts, Path, Query, Request, State},
+    http::{header, request::Parts, HeaderMap, HeaderName, HeaderValue, StatusCode},
+    middleware::{self, Next},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+    routing::{delete, get, post, put},
+    Json, Router,
+};
+use chrono::DateTime;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio_stream::wrappers::{errors::BroadcastStreamRecvError, BroadcastStream, ReceiverStream};
+use tokio_stream::{Stream, StreamExt};
+use tower_http::compression::CompressionLayer;
+use tracing::{error, info, info_span, warn, Instrument};
+
+use base64::prelude::*;
+use sha2::{Digest, Sha256};
+
+use async_trait::async_trait;
+
+use utoipa::{IntoParams, OpenApi, ToSchema};
+use utoipa_swagger_ui::SwaggerUi;
+
+use toptop_order::accounting::{self, ExportFormat, ExportKind, InvoiceOrder};
+use toptop_order::address::{self, AddressBackfillHandler, AddressStore};
+use toptop_order::anonymize::Anonymizer;
+use toptop_order::api_calls::{ApiCallFilter, ApiCallStore};
+use toptop_order::backfill::{self, BackfillHandler, BackfillStore};
+use toptop_order::cache::OrderCache;
+use toptop_order::cancellations::{self, CancellationMonitor, CancellationStore};
+use toptop_order::carriers::{CarrierStore, CarrierUpdate};
+use toptop_order::communications::{self, CommunicationStore};
+use toptop_order::config::{self, Config, ConfigReload, LiveConfig};
+use toptop_order::customers::{self, CustomerBackfillHandler, CustomerStore};
+use toptop_order::daily_stats::{self, DailyStatsRollupHandler, DailyStatsStore};
+use toptop_order::dashboard::{self, DashboardView};
+use toptop_order::deauthorization::Deauthorizer;
+use toptop_order::database::{Database, OrderCursor, OrderFilter};
+use toptop_order::erasure::PiiEraser;
+use toptop_order::error::AppError;
+use toptop_order::documents::DocumentStore;
+use toptop_order::esim::{
+    self, EsimAutoCompleteHandler, EsimProvisioningHandler, EsimStore, EsimUsagePoller,
+};
+use toptop_order::esim_delivery::{
+    self, EsimDeliveryHandler, EsimDeliveryStore, SmtpMailer, WebhookSmsProvider,
+};
+use toptop_order::events::{EventBus, OrderEvent};
+use toptop_order::external_refs::{self, ExternalRefPushHandler, ExternalRefStore};
+use toptop_order::fx::{self, FxBackfillHandler, FxConverter, FxStore, RateSource};
+use toptop_order::fulfillment::{
+    CombinePackagesRequest, DocumentType, FulfillmentClient, SplitOrderRequest,
+};
+use toptop_order::health::{AdaptiveInterval, ApiHealth, CircuitState};
+use toptop_order::item_fulfillment::ItemFulfillmentStore;
+use toptop_order::jobs::{JobQueue, JobWorker};
+use toptop_order::logistics::{LogisticsSnapshot, LogisticsStore};
+use toptop_order::lookup::{self, LookupBackfillHandler};
+use toptop_order::maintenance::MaintenanceStore;
+use toptop_order::mirror::{self, MirrorClient, MirrorHandler, MirrorPublisher, MirrorStore};
+use toptop_order::notes::{self, NoteStore};
+use toptop_order::oauth::TikTokShopOAuth;
+use toptop_order::oauth_state::{
+    self, OAuthStateStore, RedisOAuthStateStore, SqliteOAuthStateStore,
+};
+use toptop_order::order::{
+    GetOrderListRequest, Order, RejectCancellationRequest, CANCELLATION_PENDING,
+};
+use toptop_order::order_diff;
+use toptop_order::order_jobs::{self, OrderRefreshHandler, OrderUpsertHandler};
+use toptop_order::order_schema::{self, OrderSchemaUpgradeHandler, ORDER_SCHEMA_VERSION};
+use toptop_order::order_timeline;
+use toptop_order::outbound_webhooks::{
+    self, DeliveryFilter, WebhookDeliveryHandler, WebhookDeliveryStore, WebhookPublisher,
+};
+use toptop_order::outbox::{self, OutboxRelay, OutboxStore};
+use toptop_order::packages::PackageStore;
+use toptop_order::packing_slip;
+use toptop_order::payload_archive::{self, ArchiveHandler, PayloadArchive, PayloadSource, S3Client};
+use toptop_order::pii_crypto::{self, PiiEncryptHandler};
+use toptop_order::price_details::{
+    self, PriceDetailFetchHandler, PriceDetailFetcher, PriceDetailStore,
+};
+use toptop_order::processing::{ProcessingPipeline, ProcessingStore};
+use toptop_order::promotions;
+use toptop_order::reconciliation::{Reconciler, ReconciliationStore};
+use toptop_order::replacements::{self, ReplacementResolveHandler, ReplacementStore};
+use toptop_order::reporting;
+use toptop_order::request_id::{self, REQUEST_ID_HEADER};
+use toptop_order::requests::ApiRecorder;
+use toptop_order::risk::RiskStore;
+use toptop_order::scheduler::{
+    self, ScheduledTask, Scheduler, SchedulerStatus, TaskRun, TaskStatus,
+};
+use toptop_order::secrets::{self, SecretTokenBackend};
+use toptop_order::settlements::{self, SettlementFetchHandler, SettlementFetcher, SettlementStore};
+use toptop_order::shop_performance::{
+    self, ShopPerformanceStore, ShopPerformanceSync, SnapshotOutcome,
+};
+use toptop_order::shop_settings::{ShopSettingsStore, ShopSettingsUpdate};
+use toptop_order::shops::{ShopClient, ShopRegistry, ShopSummary, ShopTarget};
+use toptop_order::sku_stats::{self, LowStockCheckHandler, SkuStatsRebuildHandler, SkuStatsStore};
+use toptop_order::sku_mappings::{SkuMappingStore, SkuMappingsUpdate};
+use toptop_order::sla::{self, SlaMonitor, SlaStore};
+use toptop_order::startup::{Startup, StartupPhase};
+use toptop_order::storage::{TokenInfo, TokenManager, TokenStorage};
+use toptop_order::supervisor::{SupervisedStatus, Supervisor};
+use toptop_order::tags::{self, RetagHandler, TagStore};
+#[cfg(feature = "otel")]
+use toptop_order::telemetry;
+use toptop_order::tenants::{self, Tenant, TenantStore, API_KEY_HEADER};
+use toptop_order::threepl::{
+    self, ThreePlCallback, ThreePlClient, ThreePlPushHandler, ThreePlShipHandler, ThreePlStore,
+};
+use toptop_order::transport::ReqwestTransport;
+use toptop_order::validation::{AnomalyStore, OrderValidator};
+use toptop_order::warehouse_routing::WarehouseAssignmentStore;
+use toptop_order::webhooks::{self, ReconcilePlan, WebhookClient, WebhookEvent, WebhookStore};
+
+mod api_docs;
+
+use api_docs::ApiDoc;
+
+#[derive(Clone)]
+struct AppState {
+    startup: Startup,
+    /// Registry holding the API keys of `config.tenant_id`, in multi-tenant mode
+    tenants: Option<TenantStore>,
+    db: Arc<Database>,
+    communications: CommunicationStore,
+    webhook_store: WebhookStore,
+    job_queue: JobQueue,
+    shops: ShopRegistry,
+    backfills: BackfillStore,
+    anomalies: AnomalyStore,
+    customers: CustomerStore,
+    eraser: PiiEraser,
+    addresses: AddressStore,
+    item_fulfillment: ItemFulfillmentStore,
+    carriers: CarrierStore,
+    order_tags: TagStore,
+    notes: NoteStore,
+    replacements: ReplacementStore,
+    risk: RiskStore,
+    processing: ProcessingStore,
+    webhook_deliveries: WebhookDeliveryStore,
+    sku_stats: SkuStatsStore,
+    daily_stats: DailyStatsStore,
+    fx: FxStore,
+    documents: DocumentStore,
+    maintenance: MaintenanceStore,
+    api_calls: ApiCallStore,
+    price_details: PriceDetailStore,
+    settlements: SettlementStore,
+    reconciliation: ReconciliationStore,
+    cancellations: CancellationStore,
+    cancellation_monitor: CancellationMonitor,
+    shop_performance: ShopPerformanceStore,
+    shop_performance_sync: ShopPerformanceSync,
+    deauthorizer: Deauthorizer,
+    shop_settings: ShopSettingsStore,
+    external_refs: ExternalRefStore,
+    threepl: ThreePlStore,
+    mirrors: MirrorStore,
+    payload_archive: Option<PayloadArchive>,
+    packages: PackageStore,
+    logistics: LogisticsStore,
+    warehouses: WarehouseAssignmentStore,
+    esims: EsimStore,
+    esim_deliveries: EsimDeliveryStore,
+    sku_mappings: SkuMappingStore,
+    events: EventBus,
+    config: Config,
+    oauth_client: TikTokShopOAuth,
+    /// `state` values of authorizations started at `/auth/tiktok`
+    oauth_states: Arc<dyn OAuthStateStore>,
+    api_health: Arc<ApiHealth>,
+    scheduler: SchedulerStatus,
+    supervisor: Supervisor,
+    live_config: LiveConfig,
+}
+
+/// Load the stored token, refreshing and persisting it if the access token expired
+async fn load_valid_token(oauth_client: &TikTokShopOAuth) -> Result<TokenInfo, AppError> {
+    TokenManager::new(oauth_client.clone()).valid_token().await
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    dotenvy::dotenv().ok();
+
+    // Initialize tracing; the provider exports spans while it is alive
+    #[cfg(feature = "otel")]
+    let _tracer_provider = init_tracing();
+    #[cfg(not(feature = "otel"))]
+    tracing_subscriber::fmt()
+        .with_target(false)
+        .compact()
+        .init();
+
+    // Setup and the tenant registry run without app credentials
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("init") => return init_command(&args[1..]).await,
+        Some("tenant") => return tenant_command(&args[1..]).await,
+        _ => {}
+    }
+
+    // Readiness checks fail until every startup phase is done
+    let startup = Startup::new();
+
+    // Load configuration
+    let mut config = match Config::load() {
+        Ok(config) => config,
+        Err(e) => {
+            error!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    // Persisted files resolve under DATA_DIR; fail now if they can't be
+    // written, before the tenant registry is opened there
+    match config.prepare_directories() {
+        Ok(dirs) => {
+            let dirs: Vec<String> = dirs.iter().map(|d| d.display().to_string()).collect();
+            info!("Data directories are writable: {}", dirs.join(", "));
+        }
+        Err(e) => {
+            error!("{}", e);
+            std::process::exit(1);
+        }
+    }
+
+    // In multi-tenant mode, take the tenant's credentials from the registry
+    // and keep its data in its own directory
+    let tenant_store = match config.tenant_id.clone() {
+        Some(tenant_id) => {
+            let registry = std::path::absolute(&config.tenant_registry)?;
+            let store = TenantStore::open(&registry, config.pii_cipher.clone()).await?;
+            let Some(tenant) = store.get(&tenant_id).await? else {
+                error!("Tenant {:?} is not in the registry {}", tenant_id, registry.display());
+                std::process::exit(1);
+            };
+            config.apply_tenant(&tenant);
+            let dir = config.tenant_dir().unwrap_or_default();
+            info!("Serving tenant {:?} ({}) from {}", tenant_id, tenant.name, dir.display());
+            Some(store)
+        }
+        None => None,
+    };
+
+    // Secrets the environment and config file leave unset come from the
+    // secret provider, which then keeps the OAuth token too
+    if config.secret_source.is_external() {
+        let provider = config.secret_source.provider();
+        if let Err(e) = config.resolve_secrets(provider.as_ref()).await {
+            error!("{}", e);
+            std::process::exit(1);
+        }
+        let name = secrets::token_secret(config.tenant_id.as_deref());
+        let backend = SecretTokenBackend::load(provider.clone(), name).await?;
+        TokenStorage::use_backend(Arc::new(backend));
+        info!("Reading secrets and the OAuth token from {}", provider.describe());
+    }
+
+    // Without a secret provider, the token lives in TIKTOK_TOKEN_FILE
+    TokenStorage::use_file(&config.token_file);
+    if config.is_sandbox() {
+        warn!("Running in SANDBOX mode: TikTok sandbox API, stubbed Wow client, dry-run notifications");
+    }
+    #[cfg(feature = "chaos")]
+    if let Some(faults) = &config.api_faults {
+        warn!("Injecting faults into TikTok API calls (CHAOS_FAULTS): {:?}", faults);
+    }
+
+    // Subcommands run to completion instead of starting the server
+    match args.first().map(String::as_str) {
+        Some("export-sample") => return export_sample_command(&config, &args[1..]).await,
+        Some("erase-customer") => return erase_customer_command(&config, &args[1..]).await,
+        Some(other) => return Err(format!("Unknown command: {}", other).into()),
+        None => {}
+    }
+    startup.advance(
+        StartupPhase::Database,
+        format!("{} mode", config.mode.as_str()),
+    );
+
+    // Initialize database
+    info!("Initializing database at {}", config.database_path);
+    let events = EventBus::default();
+    let event_sink = match &config.event_sink {
+        Some(target) => Some(outbox::connect(target).await?),
+        None => None,
+    };
+    let mut db = Database::open(&config.database_path, config.sqlite_settings())
+        .await?
+        .with_upsert_batch_size(config.upsert_batch_size)
+        .with_event_bus(events.clone())
+        .with_validator(OrderValidator::new())
+        .with_customer_linking()
+        .with_sku_stats()
+        .with_report_timezone(config.report_timezone)
+        .with_address_normalization()
+        .with_item_fulfillment()
+        .with_tagger(config.order_tagger())
+        .with_replacement_linking()
+        .with_risk_scoring(config.risk_scorer());
+    if !config.warehouse_routes.is_empty() {
+        db = db.with_warehouse_routing(config.warehouse_router());
+    }
+    if let Some(cipher) = &config.pii_cipher {
+        info!("Encrypting buyer PII in the database (PII_ENCRYPTION_KEY)");
+        db = db.with_pii_cipher(cipher.clone());
+    }
+    let fx_converter = config.fx_converter();
+    if let Some(converter) = &fx_converter {
+        db = db.with_fx(converter.clone());
+    }
+    if event_sink.is_some() {
+        db = db.with_event_outbox();
+    }
+    if let Some(url) = &config.redis_url {
+        let cache = OrderCache::connect(url, config.cache_ttl_secs)
+            .await
+            .map_err(|e| format!("Redis cache is unreachable: {}", e))?;
+        info!("Caching order reads in Redis for {}s", config.cache_ttl_secs);
+        db = db.with_cache(cache);
+    }
+    db.init().await?;
+    info!("Database initialized");
+
+    let job_queue = JobQueue::new(db.pool().clone());
+    job_queue.init().await?;
+    let esim_store = EsimStore::new(db.pool().clone());
+    esim_store.init().await?;
+    let esim_delivery_store = EsimDeliveryStore::new(db.pool().clone());
+    esim_delivery_store.init().await?;
+    let sku_mappings = SkuMappingStore::new(db.pool().clone());
+    sku_mappings.init().await?;
+    let webhook_store = WebhookStore::new(db.pool().clone());
+    webhook_store.init().await?;
+    let communication_store = CommunicationStore::new(db.pool().clone());
+    communication_store.init().await?;
+    let shops = ShopRegistry::new(db.pool().clone());
+    shops.init().await?;
+    let anomalies = AnomalyStore::new(db.pool().clone());
+    anomalies.init().await?;
+    let customers =
+        CustomerStore::new(db.pool().clone()).with_pii_cipher(config.pii_cipher.clone());
+    customers.init().await?;
+    let addresses =
+        AddressStore::new(db.pool().clone()).with_pii_cipher(config.pii_cipher.clone());
+    addresses.init().await?;
+    let item_fulfillment = ItemFulfillmentStore::new(db.pool().clone());
+    item_fulfillment.init().await?;
+    let order_tags = TagStore::new(db.pool().clone());
+    order_tags.init().await?;
+    let notes = NoteStore::new(db.pool().clone());
+    notes.init().await?;
+    let replacements = ReplacementStore::new(db.pool().clone());
+    replacements.init().await?;
+    let risk = RiskStore::new(db.pool().clone());
+    risk.init().await?;
+    let processing = ProcessingStore::new(db.pool().clone());
+    processing.init().await?;
+    let webhook_deliveries = WebhookDeliveryStore::new(db.pool().clone());
+    webhook_deliveries.init().await?;
+    let event_outbox = OutboxStore::new(db.pool().clone());
+    event_outbox.init().await?;
+    let carriers = CarrierStore::new(db.pool().clone());
+    carriers.init().await?;
+    let sku_stats = SkuStatsStore::new(db.pool().clone()).with_timezone(config.report_timezone);
+    sku_stats.init().await?;
+    let daily_stats = DailyStatsStore::new(db.pool().clone());
+    daily_stats.init().await?;
+    let fx = FxStore::new(db.pool().clone());
+    fx.init().await?;
+    if let Some(converter) = &fx_converter {
+        fx.save_rates(RateSource::Static, &config.fx_rates).await?;
+        let known = fx::load_rates(&fx, converter).await?;
+        let converted = fx.convert_pending(converter).await?;
+        info!(
+            "Converting order totals into {} with {} rates ({} pending orders converted)",
+            converter.base_currency(),
+            known,
+            converted
+        );
+    }
+    let backfills = BackfillStore::new(db.pool().clone());
+    backfills.init().await?;
+    let documents = DocumentStore::new(db.pool().clone(), &config.documents_dir);
+    documents.init().await?;
+    let maintenance = MaintenanceStore::new(db.pool().clone());
+    maintenance.init().await?;
+    let sla_store = SlaStore::new(db.pool().clone());
+    sla_store.init().await?;
+    let api_calls = ApiCallStore::new(db.pool().clone());
+    api_calls.init().await?;
+    // States go where every instance behind a load balancer sees them
+    let oauth_states: Arc<dyn OAuthStateStore> = match &config.redis_url {
+        Some(url) => Arc::new(
+            RedisOAuthStateStore::connect(url)
+                .await
+                .map_err(|e| format!("Redis is unreachable for OAuth states: {}", e))?,
+        ),
+        None => {
+            let store = SqliteOAuthStateStore::new(db.pool().clone());
+            store.init().await?;
+            Arc::new(store)
+        }
+    };
+    info!("Keeping OAuth states in {}", oauth_states.describe());
+    let price_details = PriceDetailStore::new(db.pool().clone());
+    price_details.init().await?;
+    let settlements = SettlementStore::new(db.pool().clone());
+    settlements.init().await?;
+    let reconciliation = ReconciliationStore::new(db.pool().clone());
+    reconciliation.init().await?;
+    let cancellations = CancellationStore::new(db.pool().clone());
+    cancellations.init().await?;
+    let shop_performance = ShopPerformanceStore::new(db.pool().clone());
+    shop_performance.init().await?;
+    let shop_settings = ShopSettingsStore::new(db.pool().clone());
+    shop_settings.init().await?;
+    let external_refs = ExternalRefStore::new(db.pool().clone());
+    external_refs.init().await?;
+    let threepl = ThreePlStore::new(db.pool().clone());
+    threepl.init().await?;
+    let mirrors = MirrorStore::new(db.pool().clone());
+    mirrors.init().await?;
+    let packages = PackageStore::new(db.pool().clone());
+    packages.init().await?;
+    let logistics = LogisticsStore::new(db.pool().clone());
+    logistics.init().await?;
+    let warehouses = WarehouseAssignmentStore::new(db.pool().clone());
+    warehouses.init().await?;
+    startup.advance(
+        StartupPhase::Token,
+        format!("tables ready in {}", config.database_path),
+    );
+
+    // Initialize OAuth client
+    let oauth_client = config.oauth_client();
+
+    // Check and refresh token if needed
+    let token_storage = TokenStorage::new();
+    let (has_token, token_detail) = if let Some(token_info) = token_storage.get() {
+        info!(
+            "Loaded saved token from {}",
+            token_storage.storage_path().display()
+        );
+        info!("Token expires at: {}", token_info.expires_at);
+
+        match config.token_manager().valid_token().await {
+            Ok(valid_token) if valid_token.access_token != token_info.access_token => (
+                true,
+                format!("refreshed, expires at {}", valid_token.expires_at),
+            ),
+            Ok(_) => (true, format!("expires at {}", token_info.expires_at)),
+            Err(e) => {
+                error!("Token refresh failed: {}", e);
+                info!("Please re-authorize the app if needed");
+                (true, format!("refresh failed: {}", e))
+            }
+        }
+    } else {
+        info!("No saved token found. Please authorize via /auth/tiktok");
+        (false, "no token stored; authorize via /auth/tiktok".to_string())
+    };
+    startup.advance(StartupPhase::Services, token_detail);
+
+    // Every API client below is built from config, so they all record
+    let recorder: Option<Arc<dyn ApiRecorder>> = if config.record_api_calls {
+        info!("Recording TikTok API calls; see GET /admin/api-calls");
+        Some(Arc::new(api_calls.clone()))
+    } else {
+        None
+    };
+    // The archive sees order pages first, then passes each call on to the call log
+    let payload_archive = config.payload_archive.as_ref().map(|settings| {
+        info!("Archiving raw order pages and webhook payloads to {}", settings.url);
+        PayloadArchive::new(job_queue.clone(), shops.clone(), config.clone())
+            .with_inner(recorder.clone())
+    });
+    let recorder = match &payload_archive {
+        Some(archive) => Some(Arc::new(archive.clone()) as Arc<dyn ApiRecorder>),
+        None => recorder,
+    };
+    let config = match recorder {
+        Some(recorder) => config.with_api_recorder(recorder),
+        None => config,
+    };
+    // What SIGHUP and POST /admin/reload can change without a restart
+    let live_config = LiveConfig::new(config.clone());
+
+    let db = Arc::new(db);
+    let cancellation_monitor = CancellationMonitor::new(
+        db.clone(),
+        cancellations.clone(),
+        shops.clone(),
+        config.clone(),
+        events.clone(),
+    );
+    let shop_performance_sync =
+        ShopPerformanceSync::new(shop_performance.clone(), shops.clone(), config.clone());
+    let deauthorizer = Deauthorizer::new(shops.clone(), config.clone(), events.clone());
+
+    // Shared TikTok API health, fed by the API client and read by the scheduler
+    let api_health = Arc::new(ApiHealth::default());
+
+    // Start job worker
+    let mut worker = JobWorker::new(job_queue.clone())
+        .register(
+            order_schema::UPGRADE_JOB_KIND,
+            Arc::new(OrderSchemaUpgradeHandler::new(db.clone())),
+        )
+        .register(
+            order_jobs::REFRESH_JOB_KIND,
+            Arc::new(OrderRefreshHandler::new(
+                db.clone(),
+                shops.clone(),
+                config.clone(),
+            )),
+        )
+        .register(
+            order_jobs::UPSERT_JOB_KIND,
+            Arc::new(OrderUpsertHandler::new(db.clone())),
+        )
+        .register(
+            customers::BACKFILL_JOB_KIND,
+            Arc::new(CustomerBackfillHandler::new(db.clone())),
+        )
+        .register(
+            sku_stats::REBUILD_JOB_KIND,
+            Arc::new(SkuStatsRebuildHandler::new(db.clone())),
+        )
+        .register(
+            daily_stats::ROLLUP_JOB_KIND,
+            Arc::new(DailyStatsRollupHandler::new(db.clone(), shops.clone(), config.clone())),
+        )
+        .register(lookup::BACKFILL_JOB_KIND, Arc::new(LookupBackfillHandler::new(db.clone())))
+        .register(fx::BACKFILL_JOB_KIND, Arc::new(FxBackfillHandler::new(db.clone())))
+        .register(
+            address::BACKFILL_JOB_KIND,
+            Arc::new(AddressBackfillHandler::new(db.clone())),
+        )
+        .register(tags::RETAG_JOB_KIND, Arc::new(RetagHandler::new(db.clone())))
+        .register(
+            replacements::RESOLVE_JOB_KIND,
+            Arc::new(ReplacementResolveHandler::new(
+                replacements.clone(),
+                shops.clone(),
+                config.clone(),
+            )),
+        )
+        .register(
+            backfill::BACKFILL_JOB_KIND,
+            Arc::new(BackfillHandler::new(
+                db.clone(),
+                backfills.clone(),
+                job_queue.clone(),
+                shops.clone(),
+                live_config.clone(),
+                api_health.clone(),
+            )),
+        )
+        .register(
+            sku_stats::LOW_STOCK_JOB_KIND,
+            Arc::new(LowStockCheckHandler::new(
+                sku_stats.clone(),
+                shops.clone(),
+                config.clone(),
+            )),
+        )
+        .register(
+            price_details::FETCH_JOB_KIND,
+            Arc::new(PriceDetailFetchHandler::new(
+                PriceDetailFetcher::new(price_details.clone(), shops.clone(), config.clone()),
+                db.clone(),
+            )),
+        )
+        .register(
+            settlements::FETCH_JOB_KIND,
+            Arc::new(SettlementFetchHandler::new(
+                SettlementFetcher::new(settlements.clone(), shops.clone(), config.clone()),
+                db.clone(),
+            )),
+        )
+        .register(
+            external_refs::PUSH_JOB_KIND,
+            Arc::new(ExternalRefPushHandler::new(
+                external_refs.clone(),
+                shops.clone(),
+                config.clone(),
+            )),
+        );
+    // eSIM provisioning and usage polling need WowEsim credentials (or sandbox mode)
+    let wow_client = match config.provisions_esims() {
+        true => Some(config.wow_client()?),
+        false => None,
+    };
+    if let Some(wow_client) = &wow_client {
+        let mut handler = EsimProvisioningHandler::new(
+            wow_client.clone(),
+            esim_store.clone(),
+            job_queue.clone(),
+            shops.clone(),
+            config.clone(),
+        );
+        if config.esim_smtp.is_some() || config.esim_sms.is_some() {
+            let mut delivery = EsimDeliveryHandler::new(
+                db.clone(),
+                esim_store.clone(),
+                esim_delivery_store.clone(),
+                communication_store.clone(),
+                config.is_sandbox(),
+            );
+            if let Some(settings) = &config.esim_smtp {
+                let mailer = SmtpMailer::new(settings).map_err(AppError::ConfigError)?;
+                delivery = delivery.with_mailer(mailer);
+            }
+            if let Some(settings) = &config.esim_sms {
+                let client = reqwest::Client::builder()
+                    .timeout(Duration::from_secs(10))
+                    .build()?;
+                delivery = delivery.with_sms(Arc::new(WebhookSmsProvider::new(
+                    settings.clone(),
+                    Arc::new(ReqwestTransport::with_client(client)),
+                )));
+            }
+            worker = worker.register(esim_delivery::DELIVERY_JOB_KIND, Arc::new(delivery));
+            handler = handler.with_delivery();
+            info!("eSIM delivery to buyers enabled");
+        }
+        worker = worker
+            .register(esim::PROVISION_JOB_KIND, Arc::new(handler))
+            .register(
+                esim::AUTO_COMPLETE_JOB_KIND,
+                Arc::new(EsimAutoCompleteHandler::new(
+                    db.clone(),
+                    esim_store.clone(),
+                    sku_mappings.clone(),
+                    shops.clone(),
+                    config.clone(),
+                )),
+            );
+        info!("eSIM provisioning enabled for SKUs mapped in /admin/sku-mappings");
+    }
+    // Registered without subscribers too, since reloading config can add some
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()?;
+    let handler = WebhookDeliveryHandler::new(
+        live_config.clone(),
+        Arc::new(ReqwestTransport::with_client(client)),
+        webhook_deliveries.clone(),
+    );
+    worker = worker.register(outbound_webhooks::DELIVERY_JOB_KIND, Arc::new(handler));
+    if let Some(settings) = &config.threepl {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(30))
+            .build()?;
+        let client = ThreePlClient::new(
+            settings.clone(),
+            Arc::new(ReqwestTransport::with_client(client)),
+        );
+        worker = worker
+            .register(
+                threepl::PUSH_JOB_KIND,
+                Arc::new(ThreePlPushHandler::new(client, threepl.clone(), db.clone())),
+            )
+            .register(
+                threepl::SHIP_JOB_KIND,
+                Arc::new(ThreePlShipHandler::new(
+                    threepl.clone(),
+                    db.clone(),
+                    shops.clone(),
+                    config.clone(),
+                )),
+            );
+        info!("3PL push enabled ({})", settings.url);
+    }
+    if config.pii_cipher.is_some() {
+        worker = worker.register(
+            pii_crypto::ENCRYPT_JOB_KIND,
+            Arc::new(PiiEncryptHandler::new(db.clone())),
+        );
+    }
+    if let Some(settings) = &config.payload_archive {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(60))
+            .build()?;
+        let client = S3Client::new(
+            settings.clone(),
+            Arc::new(ReqwestTransport::with_client(client)),
+        );
+        worker = worker.register(
+            payload_archive::ARCHIVE_JOB_KIND,
+            Arc::new(ArchiveHandler::new(client)),
+        );
+    }
+    if !config.mirror_targets.is_empty() {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(30))
+            .build()?;
+        let transport = Arc::new(ReqwestTransport::with_client(client));
+        let clients = config
+            .mirror_targets
+            .iter()
+            .map(|target| MirrorClient::new(target.clone(), transport.clone()))
+            .collect();
+        worker = worker.register(
+            mirror::MIRROR_JOB_KIND,
+            Arc::new(MirrorHandler::new(clients, mirrors.clone(), db.clone())),
+        );
+    }
+    // Long-running loops are restarted if they panic
+    let supervisor = Supervisor::new();
+    supervisor.spawn("job_worker", move || worker.clone().run());
+
+    // Upgrade stored order blobs once per schema version
+    job_queue
+        .enqueue(
+            order_schema::UPGRADE_JOB_KIND,
+            Some(&format!("{}:v{}", order_schema::UPGRADE_JOB_KIND, ORDER_SCHEMA_VERSION)),
+            &serde_json::json!({ "version": ORDER_SCHEMA_VERSION }),
+            config.job_max_attempts,
+        )
+        .await?;
+
+    // Encrypt what was stored before PII_ENCRYPTION_KEY was set, on every start
+    // in case the key was unset for a while
+    if config.pii_cipher.is_some() {
+        job_queue
+            .enqueue(
+                pii_crypto::ENCRYPT_JOB_KIND,
+                None,
+                &serde_json::json!({}),
+                config.job_max_attempts,
+            )
+            .await?;
+    }
+
+    // Link orders stored before customer tracking; later orders are linked on upsert
+    job_queue
+        .enqueue(
+            customers::BACKFILL_JOB_KIND,
+            Some(customers::BACKFILL_JOB_KIND),
+            &serde_json::json!({}),
+            config.job_max_attempts,
+        )
+        .await?;
+    // Index orders stored before support lookups; later orders are indexed on upsert
+    job_queue
+        .enqueue(
+            lookup::BACKFILL_JOB_KIND,
+            Some(lookup::BACKFILL_JOB_KIND),
+            &serde_json::json!({}),
+            config.job_max_attempts,
+        )
+        .await?;
+    // Once per report timezone, which decides the day each order counts on
+    job_queue
+        .enqueue(
+            sku_stats::REBUILD_JOB_KIND,
+            Some(&format!("{}:{}", sku_stats::REBUILD_JOB_KIND, config.report_timezone)),
+            &serde_json::json!({}),
+            config.job_max_attempts,
+        )
+        .await?;
+    // Once per base currency
+    if let Some(converter) = &fx_converter {
+        job_queue
+            .enqueue(
+                fx::BACKFILL_JOB_KIND,
+                Some(&format!("{}:{}", fx::BACKFILL_JOB_KIND, converter.base_currency())),
+                &serde_json::json!({}),
+                config.job_max_attempts,
+            )
+            .await?;
+    }
+    // Fill daily_stats before the first nightly rollup, and again when the
+    // report timezone changes
+    job_queue
+        .enqueue(
+            daily_stats::ROLLUP_JOB_KIND,
+            Some(&format!("{}:{}", daily_stats::ROLLUP_JOB_KIND, config.report_timezone)),
+            &serde_json::json!({}),
+            config.job_max_attempts,
+        )
+        .await?;
+    // Once per set of normalization rules
+    job_queue
+        .enqueue(
+            address::BACKFILL_JOB_KIND,
+            Some(&format!("{}:v{}", address::BACKFILL_JOB_KIND, address::NORMALIZATION_VERSION)),
+            &serde_json::json!({ "version": address::NORMALIZATION_VERSION }),
+            config.job_max_attempts,
+        )
+        .await?;
+    // Replacement orders stored without knowing what they replace
+    let unresolved = replacements.unresolved().await?;
+    replacements::enqueue_resolution(
+        &job_queue,
+        unresolved.iter().map(String::as_str),
+        config.job_max_attempts,
+    )
+    .await?;
+    // Once per set of tag rules
+    job_queue
+        .enqueue(
+            tags::RETAG_JOB_KIND,
+            Some(&format!("{}:{}", tags::RETAG_JOB_KIND, config.order_tagger().fingerprint())),
+            &serde_json::json!({}),
+            config.job_max_attempts,
+        )
+        .await?;
+
+    // Advance stored orders through processing; fulfillment starts from here
+    supervisor.spawn("processing_pipeline", {
+        let (db, processing, job_queue, sku_mappings, config, events) = (
+            db.clone(),
+            processing.clone(),
+            job_queue.clone(),
+            sku_mappings.clone(),
+            config.clone(),
+            events.clone(),
+        );
+        move || {
+            ProcessingPipeline::new(
+                db.clone(),
+                processing.clone(),
+                job_queue.clone(),
+                sku_mappings.clone(),
+                config.clone(),
+            )
+            .run(events.subscribe())
+        }
+    });
+
+    // Push order events to our own systems
+    if !config.outbound_webhooks.is_empty() {
+        info!(
+            "Publishing order events to {} webhook subscribers",
+            config.outbound_webhooks.len()
+        );
+    }
+    supervisor.spawn("webhook_publisher", {
+        let (live_config, job_queue, events) =
+            (live_config.clone(), job_queue.clone(), events.clone());
+        let max_attempts = config.job_max_attempts;
+        move || {
+            WebhookPublisher::new(live_config.clone(), job_queue.clone(), max_attempts)
+                .run(events.subscribe())
+        }
+    });
+
+    // Copy paid orders into WooCommerce/Shopify
+    if !config.mirror_targets.is_empty() {
+        supervisor.spawn("order_mirror_publisher", {
+            let (config, job_queue, events) = (config.clone(), job_queue.clone(), events.clone());
+            move || {
+                MirrorPublisher::new(
+                    config.mirror_targets.clone(),
+                    job_queue.clone(),
+                    config.job_max_attempts,
+                )
+                .run(events.subscribe())
+            }
+        });
+        let platforms: Vec<_> = config.mirror_targets.iter().map(|t| t.platform()).collect();
+        info!("Mirroring orders to {}", platforms.join(", "));
+    }
+
+    // Relay recorded order events to the message broker
+    if let (Some(sink), Some(target)) = (event_sink, &config.event_sink) {
+        info!(
+            "Relaying order events to {} under {}.*",
+            target.broker(),
+            config.event_sink_prefix
+        );
+        supervisor.spawn("outbox_relay", {
+            let (event_outbox, prefix) = (event_outbox.clone(), config.event_sink_prefix.clone());
+            move || OutboxRelay::new(event_outbox.clone(), sink.clone(), prefix.clone()).run()
+        });
+    }
+
+    // Make TikTok's webhook subscriptions match config
+    if config.webhook_address.is_some() {
+        supervisor.spawn("webhook_reconcile", {
+            let (db, webhook_store, shops, config, oauth_client) = (
+                db.clone(),
+                webhook_store.clone(),
+                shops.clone(),
+                config.clone(),
+                oauth_client.clone(),
+            );
+            move || {
+                reconcile_webhooks(
+                    db.clone(),
+                    webhook_store.clone(),
+                    shops.clone(),
+                    config.clone(),
+                    oauth_client.clone(),
+                )
+            }
+        });
+    }
+
+    // Periodic work, on the cron schedules from config. With STARTUP_SYNC the
+    // first order sync runs before the server reports ready instead.
+    let order_sync = Arc::new(OrderSyncTask::new(
+        db.clone(),
+        live_config.clone(),
+        job_queue.clone(),
+        shops.clone(),
+        api_health.clone(),
+        deauthorizer.clone(),
+        shop_settings.clone(),
+    ));
+    let scheduler = if config.startup_sync {
+        Scheduler::new().register("order_sync", config.sync_schedule.clone(), order_sync.clone())
+    } else {
+        Scheduler::new().register_at_startup(
+            "order_sync",
+            config.sync_schedule.clone(),
+            order_sync.clone(),
+        )
+    };
+    let scheduler = scheduler
+        .register(
+            "token_refresh",
+            config.token_refresh_schedule.clone(),
+            Arc::new(WhileConnected {
+                deauthorizer: deauthorizer.clone(),
+                task: TokenRefreshTask {
+                    oauth_client: oauth_client.clone(),
+                    deauthorizer: deauthorizer.clone(),
+                },
+            }),
+        )
+        .register(
+            "stats_rollup",
+            config.stats_rollup_schedule.clone(),
+            Arc::new(StatsRollupTask {
+                db: db.clone(),
+                job_queue: job_queue.clone(),
+                shops: shops.clone(),
+                config: config.clone(),
+            }),
+        );
+    let scheduler = match (&fx_converter, &config.fx_rates_url) {
+        (Some(converter), Some(url)) => scheduler.register(
+            "fx_refresh",
+            config.fx_refresh_schedule.clone(),
+            Arc::new(FxRefreshTask {
+                store: fx.clone(),
+                converter: converter.clone(),
+                url: url.clone(),
+            }),
+        ),
+        _ => scheduler,
+    };
+    let scheduler = match config.archive_after_days {
+        Some(days) => scheduler.register(
+            "order_archive",
+            config.archive_schedule.clone(),
+            Arc::new(ArchiveTask {
+                db: db.clone(),
+                retention_days: days,
+            }),
+        ),
+        None => scheduler,
+    };
+    let scheduler = match config.pii_retention_days {
+        Some(days) => scheduler.register(
+            "pii_retention",
+            config.pii_retention_schedule.clone(),
+            Arc::new(PiiRetentionTask {
+                eraser: PiiEraser::new(db.clone(), documents.clone()),
+                retention_days: days,
+            }),
+        ),
+        None => scheduler,
+    };
+    let scheduler = scheduler.register(
+        "db_maintenance",
+        config.maintenance_schedule.clone(),
+        Arc::new(MaintenanceTask {
+            maintenance: maintenance.clone(),
+            api_calls: api_calls.clone(),
+            oauth_states: oauth_states.clone(),
+            api_call_retention_secs: config.api_call_retention_days * 86_400,
+        }),
+    )
+    .register(
+        "sla_check",
+        config.sla_check_schedule.clone(),
+        Arc::new(SlaCheckTask {
+            monitor: SlaMonitor::new(
+                db.clone(),
+                sla_store,
+                events.clone(),
+                config.sla_alert_within_secs,
+            ),
+        }),
+    )
+    .register(
+        "late_digest",
+        config.late_digest_schedule.clone(),
+        Arc::new(LateDigestTask {
+            db: db.clone(),
+            events: events.clone(),
+            shops: shops.clone(),
+            config: config.clone(),
+        }),
+    )
+    .register(
+        "reconcile",
+        config.reconcile_schedule.clone(),
+        Arc::new(WhileConnected {
+            deauthorizer: deauthorizer.clone(),
+            task: ReconcileTask {
+                reconciler: Reconciler::new(
+                    db.clone(),
+                    reconciliation.clone(),
+                    shops.clone(),
+                    config.clone(),
+                ),
+                window_secs: config.reconcile_window_secs,
+            },
+        }),
+    )
+    .register(
+        "cancellation_sync",
+        config.cancellation_sync_schedule.clone(),
+        Arc::new(WhileConnected {
+            deauthorizer: deauthorizer.clone(),
+            task: CancellationSyncTask {
+                monitor: cancellation_monitor.clone(),
+                deauthorizer: deauthorizer.clone(),
+            },
+        }),
+    )
+    .register(
+        "shop_refresh",
+        config.shop_refresh_schedule.clone(),
+        Arc::new(WhileConnected {
+            deauthorizer: deauthorizer.clone(),
+            task: ShopRefreshTask {
+                shops: shops.clone(),
+                config: config.clone(),
+                oauth_client: oauth_client.clone(),
+                deauthorizer: deauthorizer.clone(),
+            },
+        }),
+    )
+    .register(
+        "shop_performance",
+        config.shop_performance_schedule.clone(),
+        Arc::new(WhileConnected {
+            deauthorizer: deauthorizer.clone(),
+            task: ShopPerformanceTask {
+                sync: shop_performance_sync.clone(),
+                deauthorizer: deauthorizer.clone(),
+            },
+        }),
+    );
+    let scheduler = match &wow_client {
+        Some(wow_client) => scheduler.register(
+            "esim_usage",
+            config.esim_usage_schedule.clone(),
+            Arc::new(EsimUsageTask {
+                poller: EsimUsagePoller::new(wow_client.clone(), esim_store.clone()),
+            }),
+        ),
+        None => scheduler,
+    };
+    let scheduler_status = scheduler.status();
+    scheduler.start(&supervisor);
+
+    // Apply config changes on SIGHUP, as POST /admin/reload does
+    #[cfg(unix)]
+    supervisor.spawn("config_reload", {
+        let (live_config, scheduler_status) = (live_config.clone(), scheduler_status.clone());
+        move || reload_on_sighup(live_config.clone(), scheduler_status.clone())
+    });
+
+    // Create app state
+    let state = AppState {
+        startup: startup.clone(),
+        db: db.clone(),
+        communications: communication_store,
+        webhook_store: webhook_store.clone(),
+        job_queue: job_queue.clone(),
+        shops,
+        backfills,
+        anomalies,
+        customers,
+        eraser: PiiEraser::new(db.clone(), documents.clone()),
+        addresses,
+        item_fulfillment,
+        carriers,
+        order_tags,
+        notes,
+        replacements: replacements.clone(),
+        risk,
+        processing: processing.clone(),
+        webhook_deliveries: webhook_deliveries.clone(),
+        sku_stats,
+        daily_stats,
+        fx,
+        documents,
+        maintenance,
+        api_calls,
+        price_details,
+        settlements,
+        reconciliation: reconciliation.clone(),
+        cancellations: cancellations.clone(),
+        cancellation_monitor: cancellation_monitor.clone(),
+        shop_performance,
+        shop_performance_sync,
+        deauthorizer,
+        shop_settings,
+        external_refs,
+        threepl,
+        mirrors,
+        payload_archive,
+        packages,
+        logistics,
+        warehouses,
+        esims: esim_store,
+        esim_deliveries: esim_delivery_store,
+        sku_mappings,
+        events,
+        tenants: tenant_store,
+        config: config.clone(),
+        oauth_client: oauth_client.clone(),
+        oauth_states: oauth_states.clone(),
+        api_health,
+        scheduler: scheduler_status,
+        supervisor,
+        live_config,
+    };
+
+    // Build router; /admin routes, starting an authorization and customer data erasure require ADMIN_TOKEN when it is set
+    if config.admin_token.is_none() {
+        let open = if config.tenant_id.is_some() {
+            "/admin routes are"
+        } else {
+            "/admin routes, cancellation decisions, eSIM resends and buyer PII are"
+        };
+        warn!("ADMIN_TOKEN is not set; {} open to anyone who can reach the server", open);
+    }
+    let admin = Router::new()
+        .route("/admin", get(dashboard_handler))
+        .route("/auth/tiktok", get(authorize_handler))
+        .route("/admin/dead-letters", get(list_dead_letters_handler))
+        .route("/admin/dead-letters/{id}/retry", post(retry_dead_letter_handler))
+        .route("/admin/db/stats", get(db_stats_handler))
+        .route("/admin/webhook-deliveries", get(list_webhook_deliveries_handler))
+        .route("/admin/api-calls", get(list_api_calls_handler))
+        .route("/admin/reconciliation", get(list_reconciliation_runs_handler))
+        .route("/admin/shop-performance/sync", post(sync_shop_performance_handler))
+        .route("/admin/reload", post(reload_config_handler))
+        .route(
+            "/admin/sku-mappings",
+            get(list_sku_mappings_handler).put(put_sku_mappings_handler),
+        )
+        .route("/admin/tasks", get(list_tasks_handler))
+        .route("/admin/tasks/{name}/stop", post(stop_task_handler))
+        .route("/admin/tasks/{name}/start", post(start_task_handler))
+        .route("/customers/{id}/data", delete(erase_customer_data_handler))
+        .route_layer(middleware::from_fn_with_state(state.clone(), require_admin));
+
+    // Deciding on cancellations and resending eSIMs is refused to anyone
+    // holding neither the tenant's API key nor ADMIN_TOKEN
+    let operator = Router::new()
+        .route("/cancellations/{id}/approve", post(approve_cancellation_handler))
+        .route("/cancellations/{id}/reject", post(reject_cancellation_handler))
+        .route("/orders/{id}/esim/resend", post(resend_esim_handler))
+        .route_layer(middleware::from_fn_with_state(state.clone(), require_operator));
+
+    let mut app = Router::new()
+        .route("/orders", get(get_orders_handler))
+        .route("/orders/stream", get(order_stream_handler))
+        .route("/orders/anomalies", get(list_anomalies_handler))
+        .route("/orders/sla", get(sla_handler))
+        .route("/orders/late", get(late_orders_handler))
+        .route("/orders/with-messages", get(buyer_messages_handler))
+        .route("/orders/{id}", get(get_order_handler))
+        .route("/orders/{id}/diff", get(get_order_diff_handler))
+        .route("/orders/{id}/timeline", get(get_order_timeline_handler))
+        .route("/orders/{id}/processing", get(get_order_processing_handler))
+        .route("/orders/{id}/packing-slip.pdf", get(packing_slip_handler))
+        .route("/orders/{id}/label", get(shipping_label_handler))
+        .route("/orders/{id}/packages", get(list_order_packages_handler))
+        .route("/orders/{id}/packages/split", post(split_order_handler))
+        .route("/packages/combinable", get(list_combinable_packages_handler))
+        .route("/packages/combine", post(combine_packages_handler))
+        .route("/logistics", get(get_logistics_handler))
+        .route("/logistics/validate", get(validate_shipping_handler))
+        .route("/orders/{id}/address", get(get_order_address_handler))
+        .route("/orders/{id}/price-detail", get(get_order_price_detail_handler))
+        .route(
+            "/orders/{id}/external-ref",
+            get(get_external_ref_handler)
+                .post(set_external_ref_handler)
+                .delete(delete_external_ref_handler),
+        )
+        .route("/orders/{id}/3pl", get(get_threepl_shipment_handler))
+        .route("/orders/{id}/mirrors", get(list_order_mirrors_handler))
+        .route("/orders/{id}/esim", get(get_esim_deliveries_handler))
+        .route("/esims/{iccid}/usage", get(get_esim_usage_handler))
+        .route("/orders/{id}/message/ack", post(ack_buyer_message_handler))
+        .route("/orders/{id}/tags", get(list_order_tags_handler).post(add_order_tags_handler))
+        .route("/orders/{id}/tags/{tag}", delete(remove_order_tag_handler))
+        .route("/orders/{id}/notes", get(list_order_notes_handler).post(add_order_note_handler))
+        .route("/cancellations", get(list_cancellations_handler))
+        .route("/customers/{id}/orders", get(get_customer_orders_handler))
+        .route("/lookup", get(lookup_handler))
+        .route("/stats/skus", get(sku_stats_handler))
+        .route("/stats/timeseries", get(stats_timeseries_handler))
+        .route("/stats/cancellations", get(cancellation_stats_handler))
+        .route("/stats/discounts", get(discount_stats_handler))
+        .route("/stats/shop-performance", get(shop_performance_handler))
+        .route("/shops", get(list_shops_handler))
+        .route(
+            "/shops/{id}/settings",
+            get(get_shop_settings_handler).patch(update_shop_settings_handler),
+        )
+        .route("/fx/rates", get(fx_rates_handler))
+        .route("/exports/accounting", get(accounting_export_handler))
+        .route("/carriers", get(list_carriers_handler))
+        .route("/carriers/{key}", put(put_carrier_handler).delete(delete_carrier_handler))
+        .route("/sync/backfill", post(start_backfill_handler).get(list_backfills_handler))
+        .route("/sync/backfill/{id}", get(get_backfill_handler))
+        .route_layer(middleware::from_fn_with_state(state.clone(), require_tenant_key))
+        .route("/health", get(health_handler))
+        .route("/health/ready", get(readiness_handler))
+        .route("/auth/callback", get(oauth_callback_handler))
+        .route("/webhooks/tiktok", post(tiktok_webhook_handler))
+        .route("/webhooks/3pl", post(threepl_callback_handler))
+        .merge(admin)
+        .merge(operator)
+        .merge(SwaggerUi::new("/docs").url("/api-docs/openapi.json", ApiDoc::openapi()))
+        .with_state(state);
+
+    if config.is_sandbox() {
+        app = app.layer(middleware::from_fn(sandbox_watermark));
+    }
+    // gzip/br for clients that ask; event streams are left uncompressed
+    let app = app
+        .layer(CompressionLayer::new())
+        .layer(middleware::from_fn(request_logging));
+
+    let addr = config.bind_address();
+    info!("Starting server on {}", addr);
+
+    let listener = tokio::net::TcpListener::bind(&addr).await?;
+    let server = tokio::spawn(async move { axum::serve(listener, app).await });
+    if !config.startup_sync {
+        startup.advance(StartupPhase::Ready, format!("listening on {}", addr));
+    } else {
+        // Health checks are answered meanwhile, readiness checks fail
+        startup.advance(StartupPhase::InitialSync, format!("listening on {}", addr));
+        let detail = if !has_token {
+            "skipped: no token stored".to_string()
+        } else {
+            match order_sync.run().await {
+                Ok(TaskRun::Done) => "orders synced".to_string(),
+                Ok(TaskRun::Skipped(reason)) => format!("skipped: {}", reason),
+                Err(e) => {
+                    error!("Initial order sync failed: {}", e);
+                    format!("failed: {}", e)
+                }
+            }
+        };
+        startup.advance(StartupPhase::Ready, detail);
+    }
+    server.await??;
+
+    Ok(())
+}
+
+/// `toptop-order export-sample [--limit N] [--output FILE] [--salt SALT]`
+///
+/// Writes an anonymized JSON sample of stored orders, suitable for attaching
+/// to bug reports or seeding a staging database.
+async fn export_sample_command(
+    config: &Config,
+    args: &[String],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut limit: i64 = 50;
+    let mut output: Option<String> = None;
+    let mut salt = chrono::Utc::now()
+        .timestamp_nanos_opt()
+        .unwrap_or_default()
+        .to_string();
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let mut value = || {
+            iter.next()
+                .cloned()
+                .ok_or_else(|| format!("Missing value for {}", arg))
+        };
+        match arg.as_str() {
+            "--limit" => limit = value()?.parse()?,
+            "--output" => output = Some(value()?),
+            "--salt" => salt = value()?,
+            other => return Err(format!("Unknown option: {}", other).into()),
+        }
+    }
+
+    let mut db = Database::open(&config.database_path, config.sqlite_settings()).await?;
+    if let Some(cipher) = &config.pii_cipher {
+        db = db.with_pii_cipher(cipher.clone());
+    }
+    db.init().await?;
+
+    let anonymizer = Anonymizer::new(salt);
+    let orders: Vec<_> = db
+        .get_orders_paginated(limit, None, &OrderFilter::default())
+        .await?
+        .orders
+        .iter()
+        .map(|order| anonymizer.anonymize_order(order))
+        .collect();
+
+    let json = serde_json::to_string_pretty(&orders)?;
+    match output {
+        Some(path) => {
+            // Relative paths go to EXPORTS_DIR
+            let path = std::path::Path::new(&config.exports_dir).join(path);
+            std::fs::write(&path, json)?;
+            info!("Exported {} anonymized orders to {}", orders.len(), path.display());
+        }
+        None => println!("{}", json),
+    }
+
+    Ok(())
+}
+
+/// `toptop-order erase-customer <customer id>`
+///
+/// Same as `DELETE /customers/{id}/data`, for use without the server running.
+async fn erase_customer_command(
+    config: &Config,
+    args: &[String],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let [customer_id] = args else {
+        return Err("Usage: toptop-order erase-customer <customer id>".into());
+    };
+
+    let mut db = Database::open(&config.database_path, config.sqlite_settings()).await?;
+    if let Some(cipher) = &config.pii_cipher {
+        db = db.with_pii_cipher(cipher.clone());
+    }
+    db.init().await?;
+    CustomerStore::new(db.pool().clone()).init().await?;
+    AddressStore::new(db.pool().clone()).init().await?;
+    CommunicationStore::new(db.pool().clone()).init().await?;
+    let documents = DocumentStore::new(db.pool().clone(), &config.documents_dir);
+    documents.init().await?;
+
+    let eraser = PiiEraser::new(Arc::new(db), documents);
+    match eraser.erase_customer(customer_id).await? {
+        Some(report) => {
+            info!(
+                "Erased the personal data of customer {} from {} orders and deleted {} documents",
+                customer_id,
+                report.orders_scrubbed,
+                report.documents_deleted.len()
+            );
+            Ok(())
+        }
+        None => Err(format!("Unknown customer: {}", customer_id).into()),
+    }
+}
+
+/// How long `init` waits for the seller to authorize the app
+const INIT_CALLBACK_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// Query TikTok redirects the seller back with
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct AuthCallback {
+    code: Option<String>,
+    state: Option<String>,
+    /// Set instead of `code` when the seller didn't authorize the app
+    error: Option<String>,
+}
+
+/// Ask `question` on stderr and read the answer from stdin; an empty answer
+/// takes `default`
+fn prompt(question: &str, default: Option<&str>) -> Result<String, Box<dyn std::error::Error>> {
+    use std::io::Write;
+
+    match default {
+        Some(default) if !default.is_empty() => eprint!("{} [{}]: ", question, default),
+        _ => eprint!("{}: ", question),
+    }
+    std::io::stderr().flush()?;
+    let mut answer = String::new();
+    if std::io::stdin().read_line(&mut answer)? == 0 {
+        return Err("stdin closed".into());
+    }
+    let answer = answer.trim();
+    Ok(if answer.is_empty() {
+        default.unwrap_or_default().to_string()
+    } else {
+        answer.to_string()
+    })
+}
+
+/// `toptop-order init [--force]`
+///
+/// First-run setup: asks for the app key and secret, sends the seller to the
+/// authorize URL, captures the code on a local callback (or asks for it when
+/// the redirect URI isn't local), exchanges it for a token, picks the shop and
+/// writes the config file and the token file. `--force` updates an existing
+/// config file without asking.
+async fn init_command(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let force = match args {
+        [] => false,
+        [flag] if flag == "--force" => true,
+        _ => return Err("Usage: toptop-order init [--force]".into()),
+    };
+
+    let config_path = config::file_path();
+    let existing = match std::fs::read_to_string(&config_path) {
+        Ok(contents) => Some(contents.parse::<toml::Table>()?),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => return Err(format!("Cannot read {}: {}", config_path, e).into()),
+    };
+    let configured = |key: &str, var: &str| {
+        std::env::var(var).ok().or_else(|| {
+            existing
+                .as_ref()
+                .and_then(|table| table.get(key))
+                .and_then(|value| value.as_str())
+                .map(str::to_string)
+        })
+    };
+
+    eprintln!("Setting up toptop-order; the app key and secret are in Partner Center.");
+    let app_key = prompt("App key", configured("app_key", "TIKTOK_APP_KEY").as_deref())?;
+    let app_secret =
+        prompt("App secret", configured("app_secret", "TIKTOK_APP_SECRET").as_deref())?;
+    if app_key.is_empty() || app_secret.is_empty() {
+        return Err("The app key and secret are required".into());
+    }
+    // The token file goes where the server will look for it
+    let token_file = config::under(
+        configured("data_dir", "DATA_DIR").as_deref(),
+        configured("token_file", "TIKTOK_TOKEN_FILE")
+            .unwrap_or_else(|| TokenStorage::DEFAULT_STORAGE_FILE.to_string()),
+    );
+    let default_redirect_uri = configured("redirect_uri", "TIKTOK_REDIRECT_URI")
+        .unwrap_or_else(|| oauth_state::DEFAULT_REDIRECT_URI.to_string());
+    let redirect_uri = prompt("Redirect URI registered for the app", Some(&default_redirect_uri))?;
+
+    let oauth_client = TikTokShopOAuth::new(app_key.clone(), app_secret.clone());
+    let state = oauth_state::new_state();
+    let request = oauth_client.authorization_request(&state, &redirect_uri);
+    let authorize_url = oauth_client.get_authorization_url(&request)?;
+    eprintln!("\nOpen this URL as the seller and authorize the app:\n\n  {}\n", authorize_url);
+
+    let callback = reqwest::Url::parse(&redirect_uri)?;
+    let local = matches!(callback.host_str(), Some("localhost" | "127.0.0.1" | "[::1]"));
+    let code = if local {
+        let port = callback.port_or_known_default().ok_or("The redirect URI has no port")?;
+        let listener = tokio::net::TcpListener::bind(("127.0.0.1", port))
+            .await
+            .map_err(|e| {
+                format!(
+                    "Cannot listen for the callback on port {} (is the server running?): {}",
+                    port, e
+                )
+            })?;
+        eprintln!("Waiting for TikTok to redirect back to {} ...", redirect_uri);
+
+        let (sender, mut receiver) = tokio::sync::mpsc::channel::<AuthCallback>(1);
+        let app = Router::new().route(
+            callback.path(),
+            get(move |Query(params): Query<AuthCallback>| {
+                let sender = sender.clone();
+                async move {
+                    let _ = sender.send(params).await;
+                    "Authorization received; you can close this tab and return to the terminal."
+                }
+            }),
+        );
+        let server = tokio::spawn(async move { axum::serve(listener, app).await });
+        let params = tokio::time::timeout(INIT_CALLBACK_TIMEOUT, receiver.recv()).await;
+        server.abort();
+        let params = params
+            .map_err(|_| "Timed out waiting for the authorization callback")?
+            .ok_or("The callback listener stopped")?;
+
+        if let Some(error) = params.error {
+            return Err(format!("Authorization failed: {}", error).into());
+        }
+        if params.state.as_deref() != Some(state.as_str()) {
+            return Err("The callback's state doesn't match; start init again".into());
+        }
+        params.code.ok_or("The callback has no code")?
+    } else {
+        prompt(
+            "The redirect URI isn't local; paste the `code` parameter TikTok redirected to",
+            None,
+        )?
+    };
+
+    let token = TokenInfo::from_response(oauth_client.exchange_code_for_token(&code).await?);
+    info!("Authorized; the access token expires at {}", token.expires_at);
+
+    let shops = ShopClient::new(app_key.clone(), app_secret.clone())
+        .get_authorized_shops(&token.access_token)
+        .await?;
+    let shop = match shops.as_slice() {
+        [] => return Err("The token isn't authorized for any shop".into()),
+        [shop] => shop.clone(),
+        _ => {
+            for (i, shop) in shops.iter().enumerate() {
+                eprintln!("  {}. {} ({}, {})", i + 1, shop.shop_name, shop.shop_id, shop.region);
+            }
+            let choice = prompt("Shop to sync orders for", Some("1"))?;
+            let index = choice
+                .parse::<usize>()
+                .ok()
+                .filter(|i| (1..=shops.len()).contains(i))
+                .ok_or_else(|| format!("No shop {}", choice))?;
+            shops[index - 1].clone()
+        }
+    };
+    info!("Using shop {} ({})", shop.shop_name, shop.shop_id);
+
+    if existing.is_some() && !force {
+        let question = format!(
+            "Update the app and shop settings in {} (comments are not kept)? [y/N]",
+            config_path
+        );
+        let answer = prompt(&question, None)?;
+        if !answer.eq_ignore_ascii_case("y") {
+            return Err(
+                format!("{} left unchanged; rerun with --force to update it", config_path).into(),
+            );
+        }
+    }
+    let mut table = existing.unwrap_or_default();
+    for (key, value) in [
+        ("app_key", app_key),
+        ("app_secret", app_secret),
+        ("shop_id", shop.shop_id.clone()),
+        ("shop_cipher", shop.cipher.clone()),
+        ("redirect_uri", redirect_uri),
+    ] {
+        table.insert(key.to_string(), toml::Value::String(value));
+    }
+    std::fs::write(&config_path, toml::to_string(&table)?)?;
+    info!("Wrote {}", config_path);
+
+    if let Some(dir) = std::path::Path::new(&token_file).parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let mut storage = TokenStorage::with_path(&token_file);
+    storage.store(token).await?;
+    info!(
+        "Setup complete; start the server with `toptop-order` to sync orders of {}",
+        shop.shop_name
+    );
+    Ok(())
+}
+
+/// `toptop-order tenant <command>`, on the registry at `TENANT_REGISTRY`:
+///
+/// - `add <id> --name NAME --app-key KEY [--shop-id ID] [--shop-cipher CIPHER]`
+///   adds or updates a tenant, reading its app secret from stdin
+/// - `list`
+/// - `add-key <id> [--label LABEL]` prints a new API key, which isn't shown again
+/// - `keys <id>`
+/// - `revoke-key <id> <key id>`
+async fn tenant_command(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    const USAGE: &str = "Usage: toptop-order tenant <add|list|add-key|keys|revoke-key> ...";
+
+    let registry = config::tenant_registry()?;
+    let store = TenantStore::open(std::path::Path::new(&registry), config::pii_cipher()?).await?;
+    let (command, rest) = args.split_first().ok_or(USAGE)?;
+    let (tenant_id, options) = match rest.split_first() {
+        Some((id, options)) => (id.as_str(), options),
+        None if command == "list" => ("", rest),
+        None => return Err(USAGE.into()),
+    };
+
+    let mut name = None;
+    let mut app_key = None;
+    let mut shop_id = None;
+    let mut shop_cipher = None;
+    let mut label = None;
+    let mut positional = Vec::new();
+    let mut iter = options.iter();
+    while let Some(arg) = iter.next() {
+        let mut value = || {
+            iter.next()
+                .cloned()
+                .ok_or_else(|| format!("Missing value for {}", arg))
+        };
+        match arg.as_str() {
+            "--name" => name = Some(value()?),
+            "--app-key" => app_key = Some(value()?),
+            "--shop-id" => shop_id = Some(value()?),
+            "--shop-cipher" => shop_cipher = Some(value()?),
+            "--label" => label = Some(value()?),
+            other if !other.starts_with("--") => positional.push(other.to_string()),
+            other => return Err(format!("Unknown option: {}", other).into()),
+        }
+    }
+
+    if !tenant_id.is_empty() {
+        tenants::validate_id(tenant_id)?;
+    }
+    let require_tenant = || async {
+        match store.get(tenant_id).await? {
+            Some(_) => Ok::<_, Box<dyn std::error::Error>>(()),
+            None => Err(format!("Unknown tenant: {}", tenant_id).into()),
+        }
+    };
+
+    match command.as_str() {
+        "add" => {
+            let (Some(name), Some(app_key)) = (name, app_key) else {
+                return Err("tenant add needs --name and --app-key".into());
+            };
+            eprintln!("App secret for {}:", tenant_id);
+            let mut app_secret = String::new();
+            std::io::stdin().read_line(&mut app_secret)?;
+            let app_secret = app_secret.trim().to_string();
+            if app_secret.is_empty() {
+                return Err("No app secret given".into());
+            }
+            let created_at = match store.get(tenant_id).await? {
+                Some(existing) => existing.created_at,
+                None => chrono::Utc::now().timestamp(),
+            };
+            store
+                .upsert(&Tenant {
+                    id: tenant_id.to_string(),
+                    name,
+                    app_key,
+                    app_secret,
+                    shop_id,
+                    shop_cipher,
+                    created_at,
+                })
+                .await?;
+            info!("Saved tenant {} in {}", tenant_id, registry);
+        }
+        "list" => println!("{}", serde_json::to_string_pretty(&store.list().await?)?),
+        "add-key" => {
+            require_tenant().await?;
+            let (key, api_key) = store.create_key(tenant_id, label.as_deref()).await?;
+            eprintln!("API key {} ({}) for tenant {}:", api_key.id, api_key.prefix, tenant_id);
+            println!("{}", key);
+        }
+        "keys" => {
+            require_tenant().await?;
+            println!("{}", serde_json::to_string_pretty(&store.keys(tenant_id).await?)?);
+        }
+        "revoke-key" => {
+            let [key_id] = positional.as_slice() else {
+                return Err("Usage: toptop-order tenant revoke-key <id> <key id>".into());
+            };
+            if !store.revoke_key(tenant_id, key_id.parse()?).await? {
+                return Err(format!("No live key {} for tenant {}", key_id, tenant_id).into());
+            }
+            info!("Revoked API key {} of tenant {}", key_id, tenant_id);
+        }
+        _ => return Err(USAGE.into()),
+    }
+
+    Ok(())
+}
+
+/// Reconcile TikTok webhook subscriptions with config and record the outcome.
+///
+/// Guarded by a lease so that during a rolling deploy only one instance applies
+/// changes at a time.
+async fn reconcile_webhooks(
+    db: Arc<Database>,
+    store: WebhookStore,
+    shops: ShopRegistry,
+    config: Config,
+    oauth_client: TikTokShopOAuth,
+) {
+    const LEASE_NAME: &str = "webhook_reconcile";
+    const LEASE_TTL_SECS: i64 = 300;
+
+    let Some(address) = config.webhook_address.clone() else {
+        return;
+    };
+
+    match db
+        .try_acquire_lease(LEASE_NAME, &config.instance_id, LEASE_TTL_SECS)
+        .await
+    {
+        Ok(true) => {}
+        Ok(false) => {
+            info!("Another instance is reconciling webhooks, skipping");
+            return;
+        }
+        Err(e) => {
+            error!("Failed to acquire webhook reconciliation lease: {}", e);
+            return;
+        }
+    }
+
+    let (plan, result) =
+        run_webhook_reconciliation(&config, &shops, &address, &oauth_client).await;
+    let error = result.err().map(|e| e.to_string());
+
+    match &error {
+        None if plan.is_noop() => info!("Webhook subscriptions already up to date"),
+        None => info!(
+            "Reconciled webhooks: created {:?}, updated {:?}, removed {:?}",
+            plan.create, plan.update, plan.remove
+        ),
+        Some(e) => error!("Webhook reconciliation failed: {}", e),
+    }
+
+    if let Err(e) = store
+        .record_reconciliation(&config.instance_id, &address, &plan, error.as_deref())
+        .await
+    {
+        error!("Failed to record webhook reconciliation: {}", e);
+    }
+
+    if let Err(e) = db.release_lease(LEASE_NAME, &config.instance_id).await {
+        error!("Failed to release webhook reconciliation lease: {}", e);
+    }
+}
+
+/// Cipher and region for the configured shop, fetching the authorized shops
+/// into the registry first if it doesn't know the shop yet
+async fn resolve_shop_target(
+    shops: &ShopRegistry,
+    config: &Config,
+    access_token: &str,
+) -> ShopTarget {
+    match shops.target(config).await {
+        Ok(target) if target.cipher.is_some() && target.region.is_some() => return target,
+        Ok(_) => {}
+        Err(e) => error!("Failed to read shop registry: {}", e),
+    }
+
+    let client = ShopClient::with_api_client(config.tiktok_api_client());
+    match shops.refresh(&client, access_token).await {
+        Ok(authorized) => info!("Registered {} authorized shops", authorized.len()),
+        Err(e) => error!("Failed to fetch authorized shops: {}", e),
+    }
+
+    let target = shops.target(config).await.unwrap_or_else(|e| {
+        error!("Failed to read shop registry: {}", e);
+        ShopTarget {
+            cipher: config.shop_cipher.clone(),
+            region: None,
+        }
+    });
+    if target.cipher.is_none() {
+        warn!("Cannot determine shop cipher; set TIKTOK_SHOP_ID when several shops are authorized");
+    }
+    target
+}
+
+async fn run_webhook_reconciliation(
+    config: &Config,
+    shops: &ShopRegistry,
+    address: &str,
+    oauth_client: &TikTokShopOAuth,
+) -> (ReconcilePlan, Result<(), AppError>) {
+    let token_info = match load_valid_token(oauth_client).await {
+        Ok(token_info) => token_info,
+        Err(e) => return (ReconcilePlan::default(), Err(e)),
+    };
+
+    let target = resolve_shop_target(shops, config, &token_info.access_token).await;
+    let client = WebhookClient::with_api_client(target.api_client(config));
+    let shop_cipher = target.cipher.as_deref();
+
+    let registered = match client
+        .list_webhooks(&token_info.access_token, shop_cipher)
+        .await
+    {
+        Ok(registered) => registered,
+        Err(e) => return (ReconcilePlan::default(), Err(e)),
+    };
+
+    let plan = ReconcilePlan::new(&config.webhook_events, address, &registered);
+    if plan.is_noop() {
+        return (plan, Ok(()));
+    }
+
+    let result =
+        webhooks::apply_plan(&client, &token_info.access_token, shop_cipher, address, &plan).await;
+    (plan, result)
+}
+
+/// Log to stdout and, when an OTLP endpoint is configured, export spans to it
+#[cfg(feature = "otel")]
+fn init_tracing() -> Option<opentelemetry_sdk::trace::SdkTracerProvider> {
+    use tracing_subscriber::filter::LevelFilter;
+    use tracing_subscriber::layer::SubscriberExt;
+    use tracing_subscriber::util::SubscriberInitExt;
+
+    let provider = telemetry::tracer_provider();
+    let exported = provider.as_ref().ok().and_then(Option::as_ref);
+    tracing_subscriber::registry()
+        .with(LevelFilter::INFO)
+        .with(tracing_subscriber::fmt::layer().with_target(false).compact())
+        .with(exported.map(telemetry::layer))
+        .init();
+
+    match provider {
+        Ok(Some(provider)) => {
+            info!("Exporting traces over OTLP");
+            Some(provider)
+        }
+        Ok(None) => None,
+        Err(e) => {
+            error!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Tag each request with an ID (the caller's `x-request-id` or a new one), run it
+/// inside a span carrying that ID, and log method, path, status and duration.
+/// A W3C `traceparent` header makes the span part of the caller's trace.
+async fn request_logging(request: Request, next: Next) -> Response {
+    let id = request_id::from_header(
+        request
+            .headers()
+            .get(REQUEST_ID_HEADER)
+            .and_then(|v| v.to_str().ok()),
+    );
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+    let span = info_span!("request", request_id = %id, method = %method, path = %path);
+    #[cfg(feature = "otel")]
+    telemetry::continue_trace(&span, request.headers());
+
+    let started = Instant::now();
+    let mut response = request_id::scope(id.clone(), next.run(request))
+        .instrument(span.clone())
+        .await;
+
+    span.in_scope(|| {
+        info!(
+            status = response.status().as_u16(),
+            duration_ms = started.elapsed().as_millis() as u64,
+            "request completed"
+        )
+    });
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    response
+}
+
+/// Require `ADMIN_TOKEN`, as `Authorization: Bearer <token>` or as the
+/// password of HTTP basic auth so browsers can prompt for it
+async fn require_admin(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    if has_admin_token(&state, request.headers()) {
+        next.run(request).await
+    } else {
+        admin_token_required()
+    }
+}
+
+/// In multi-tenant mode, require an API key of the served tenant, as
+/// `X-Api-Key` or `Authorization: Bearer <key>`
+async fn require_tenant_key(
+    State(state): State<AppState>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    match check_tenant_key(&state, request.headers()).await {
+        Ok(()) => {
+            if state.tenants.is_some() && state.config.tenant_id.is_some() {
+                request.extensions_mut().insert(TenantKeyPresented);
+            }
+            next.run(request).await
+        }
+        Err(e) => e.into_response(),
+    }
+}
+
+/// For routes that act on the shop rather than read from it: the tenant's
+/// API key when a tenant is configured, `ADMIN_TOKEN` otherwise
+async fn require_operator(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if state.tenants.is_some() && state.config.tenant_id.is_some() {
+        return require_tenant_key(State(state), request, next).await;
+    }
+    require_admin(State(state), request, next).await
+}
+
+/// Whether `ADMIN_TOKEN` is unset or presented
+fn has_admin_token(state: &AppState, headers: &HeaderMap) -> bool {
+    let Some(expected) = state.config.admin_token.as_deref() else {
+        return true;
+    };
+
+    let presented = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| {
+            if let Some(token) = v.strip_prefix("Bearer ") {
+                return Some(token.trim().to_string());
+            }
+            let decoded = BASE64_STANDARD.decode(v.strip_prefix("Basic ")?.trim()).ok()?;
+            let credentials = String::from_utf8(decoded).ok()?;
+            Some(credentials.split_once(':')?.1.to_string())
+        });
+
+    // Compare digests so the comparison time says nothing about the token
+    presented.is_some_and(|token| {
+        Sha256::digest(token.as_bytes()) == Sha256::digest(expected.as_bytes())
+    })
+}
+
+fn admin_token_required() -> Response {
+    let mut response =
+        AppError::Unauthorized("admin token required".to_string()).into_response();
+    response.headers_mut().insert(
+        header::WWW_AUTHENTICATE,
+        HeaderValue::from_static("Basic realm=\"toptop-order admin\""),
+    );
+    response
+}
+
+/// Passes outside multi-tenant mode or with an API key of the served tenant
+async fn check_tenant_key(state: &AppState, headers: &HeaderMap) -> Result<(), AppError> {
+    let (Some(store), Some(tenant_id)) = (&state.tenants, &state.config.tenant_id) else {
+        return Ok(());
+    };
+
+    let presented = headers
+        .get(API_KEY_HEADER)
+        .or_else(|| headers.get(header::AUTHORIZATION))
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.strip_prefix("Bearer ").unwrap_or(v).trim().to_string());
+    let Some(key) = presented else {
+        return Err(AppError::Unauthorized("tenant API key required".to_string()));
+    };
+
+    match store.authenticate(&key).await? {
+        Some(owner) if owner == *tenant_id => Ok(()),
+        _ => Err(AppError::Unauthorized("invalid API key".to_string())),
+    }
+}
+
+/// Request extension set once `require_tenant_key` accepted a tenant's key
+#[derive(Debug, Clone, Copy)]
+struct TenantKeyPresented;
+
+/// Whether the caller sees buyer PII in plaintext: holders of the tenant's
+/// API key or of `ADMIN_TOKEN` (anyone while it is unset) do, everyone else
+/// gets it masked
+#[derive(Debug, Clone, Copy)]
+struct PiiAccess {
+    reveal: bool,
+}
+
+impl FromRequestParts<AppState> for PiiAccess {
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Self::Rejection> {
+        let reveal = parts.extensions.get::<TenantKeyPresented>().is_some()
+            || has_admin_token(state, &parts.headers);
+        Ok(Self { reveal })
+    }
+}
+
+impl PiiAccess {
+    /// `order`, read from the database or the API, as this caller may see it
+    fn order(self, db: &Database, order: Order) -> Order {
+        if self.reveal {
+            db.reveal_pii(order)
+        } else {
+            pii_crypto::mask_order(order)
+        }
+    }
+
+    fn value(self, value: Option<String>) -> Option<String> {
+        if self.reveal {
+            value
+        } else {
+            value.map(|_| pii_crypto::MASKED.to_string())
+        }
+    }
+}
+
+/// Mark every response as sandbox data: a header, plus `"sandbox": true` in JSON objects
+async fn sandbox_watermark(request: Request, next: Next) -> Response {
+    let response = next.run(request).await;
+    let (mut parts, body) = response.into_parts();
+    parts
+        .headers
+        .insert("x-toptop-mode", HeaderValue::from_static("sandbox"));
+
+    let is_json = parts
+        .headers
+        .get(header::CONTENT_TYPE)
+        .is_some_and(|v| v.as_bytes().starts_with(b"application/json"));
+    if !is_json {
+        return Response::from_parts(parts, body);
+    }
+
+    let Ok(bytes) = axum::body::to_bytes(body, usize::MAX).await else {
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    };
+
+    let body = match serde_json::from_slice::<serde_json::Value>(&bytes) {
+        Ok(serde_json::Value::Object(mut map)) => {
+            map.insert("sandbox".to_string(), serde_json::Value::Bool(true));
+            parts.headers.remove(header::CONTENT_LENGTH);
+            Body::from(serde_json::Value::Object(map).to_string())
+        }
+        _ => Body::from(bytes),
+    };
+
+    Response::from_parts(parts, body)
+}
+
+/// Send the seller to TikTok to authorize the app. TikTok redirects back to
+/// `/auth/callback` with a `state` that is accepted once, within ten minutes.
+#[utoipa::path(
+    get,
+    path = "/auth/tiktok",
+    tag = "admin",
+    responses(
+        (status = 303, description = "Redirect to TikTok's authorize page"),
+        (status = 401, body = api_docs::ErrorResponse)
+    )
+)]
+async fn authorize_handler(State(state): State<AppState>) -> Result<Response, AppError> {
+    let nonce = oauth_state::new_state();
+    state.oauth_states.insert(&nonce, oauth_state::STATE_TTL).await?;
+    let request = state
+        .oauth_client
+        .authorization_request(&nonce, &state.config.redirect_uri);
+    let url = state.oauth_client.get_authorization_url(&request)?;
+    Ok(axum::response::Redirect::to(&url).into_response())
+}
+
+/// Where TikTok sends the seller after `/auth/tiktok`: checks and consumes
+/// the `state`, exchanges the code and stores the token, which also
+/// reconnects a shop that had de-authorized the app
+#[utoipa::path(
+    get,
+    path = "/auth/callback",
+    tag = "admin",
+    params(AuthCallback),
+    responses(
+        (status = 200, description = "Token stored", body = serde_json::Value),
+        (status = 400, description = "Unknown, used or expired state, or authorization declined", body = api_docs::ErrorResponse)
+    )
+)]
+async fn oauth_callback_handler(
+    State(state): State<AppState>,
+    Query(params): Query<AuthCallback>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let issued = match params.state.as_deref() {
+        Some(nonce) => state.oauth_states.take(nonce).await?,
+        None => false,
+    };
+    if !issued {
+        return Err(AppError::BadRequest(
+            "unknown or expired state; start again at /auth/tiktok".to_string(),
+        ));
+    }
+    if let Some(error) = params.error {
+        return Err(AppError::BadRequest(format!("authorization failed: {}", error)));
+    }
+    let code = params
+        .code
+        .ok_or_else(|| AppError::BadRequest("the callback has no code".to_string()))?;
+
+    let token = TokenInfo::from_response(state.oauth_client.exchange_code_for_token(&code).await?);
+    let expires_at = token.expires_at;
+    state.config.token_manager().replace(token).await?;
+    info!("Authorized through /auth/callback; the access token expires at {}", expires_at);
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "expires_at": expires_at
+    })))
+}
+
+/// HTML overview of sync status, token expiry, error counts and recent orders
+#[utoipa::path(
+    get,
+    path = "/admin",
+    tag = "admin",
+    responses(
+        (status = 200, description = "Dashboard page", content_type = "text/html", body = String),
+        (status = 401, body = api_docs::ErrorResponse)
+    )
+)]
+async fn dashboard_handler(
+    State(state): State<AppState>,
+    access: PiiAccess,
+) -> Result<axum::response::Html<String>, AppError> {
+    let recent = state
+        .db
+        .get_orders_paginated(20, None, &OrderFilter::default())
+        .await?;
+
+    let view = DashboardView {
+        mode: state.config.mode.as_str().to_string(),
+        generated_at: chrono::Utc::now(),
+        token: TokenStorage::new().get().cloned(),
+        tasks: state.scheduler.tasks(),
+        api_health: state.api_health.snapshot(),
+        jobs: state.job_queue.counts().await?,
+        order_count: state.db.get_orders_count().await?,
+        orders_today: state
+            .db
+            .count_orders_created_since(reporting::day_start(
+                reporting::today(state.config.report_timezone),
+                state.config.report_timezone,
+            ))
+            .await?,
+        report_timezone: state.config.report_timezone,
+        recent_orders: recent
+            .orders
+            .into_iter()
+            .map(|o| access.order(&state.db, o))
+            .collect(),
+    };
+    Ok(axum::response::Html(dashboard::render(&view).into_string()))
+}
+
+/// Registered shops with their connection state, plus token expiry, last
+/// order sync and stored order count for the shop the token acts for
+#[utoipa::path(
+    get,
+    path = "/shops",
+    tag = "admin",
+    responses((status = 200, body = api_docs::ShopsResponse))
+)]
+async fn list_shops_handler(
+    State(state): State<AppState>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    // A stored token lifts the disconnection of its shop before listing
+    state.deauthorizer.disconnected().await?;
+    let token_shop_id = state.deauthorizer.shop_id().await?;
+    let token = TokenStorage::new().get().cloned();
+    let order_sync = state.scheduler.tasks().into_iter().find(|t| t.name == "order_sync");
+
+    let mut shops = Vec::new();
+    for (shop, refreshed_at) in state.shops.list_refreshed().await? {
+        let disconnection = state.shops.disconnection(&shop.shop_id).await?;
+        let is_token_shop = shop.shop_id == token_shop_id;
+        let token = token.as_ref().filter(|_| is_token_shop);
+        let order_sync = order_sync.as_ref().filter(|_| is_token_shop);
+        shops.push(ShopSummary {
+            connected: disconnection.is_none(),
+            disconnection,
+            token_expires_at: token.map(|t| t.expires_at),
+            refresh_token_expires_at: token.map(|t| t.refresh_token_expires_at),
+            last_sync_at: order_sync.and_then(|t| t.last_started_at),
+            last_sync_outcome: order_sync.and_then(|t| t.last_outcome.clone()),
+            order_count: if is_token_shop {
+                Some(state.db.get_orders_count().await?)
+            } else {
+                None
+            },
+            settings: state.shop_settings.get(&shop.shop_id).await?,
+            shop_id: shop.shop_id,
+            shop_name: shop.shop_name,
+            region: shop.region,
+            refreshed_at,
+        });
+    }
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "count": shops.len(),
+        "shops": shops
+    })))
+}
+
+/// Sync settings of a registered shop
+#[utoipa::path(
+    get,
+    path = "/shops/{id}/settings",
+    tag = "admin",
+    params(("id" = String, Path, description = "Shop id")),
+    responses(
+        (status = 200, body = api_docs::ShopSettingsResponse),
+        (status = 404, body = api_docs::ErrorResponse)
+    )
+)]
+async fn get_shop_settings_handler(
+    State(state): State<AppState>,
+    Path(shop_id): Path<String>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    require_registered_shop(&state, &shop_id).await?;
+    let settings = state.shop_settings.get(&shop_id).await?;
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "settings": settings
+    })))
+}
+
+/// Pause or resume a shop's scheduled order syncs, or set its sync interval
+/// and default backfill range. Omitted fields are unchanged; `null` resets one.
+#[utoipa::path(
+    patch,
+    path = "/shops/{id}/settings",
+    tag = "admin",
+    params(("id" = String, Path, description = "Shop id")),
+    request_body = ShopSettingsUpdate,
+    responses(
+        (status = 200, body = api_docs::ShopSettingsResponse),
+        (status = 400, body = api_docs::ErrorResponse),
+        (status = 404, body = api_docs::ErrorResponse)
+    )
+)]
+async fn update_shop_settings_handler(
+    State(state): State<AppState>,
+    Path(shop_id): Path<String>,
+    payload: Result<Json<ShopSettingsUpdate>, JsonRejection>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let Json(update) = payload.map_err(|e| AppError::BadRequest(e.body_text()))?;
+    update.validate().map_err(AppError::BadRequest)?;
+    require_registered_shop(&state, &shop_id).await?;
+
+    let settings = state.shop_settings.update(&shop_id, &update).await?;
+    info!(
+        "Updated settings of shop {:?}: paused {}, sync_interval_secs {:?}, backfill_days {:?}",
+        shop_id, settings.sync_paused, settings.sync_interval_secs, settings.backfill_days
+    );
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "settings": settings
+    })))
+}
+
+/// 404 unless the shop is registered
+async fn require_registered_shop(state: &AppState, shop_id: &str) -> Result<(), AppError> {
+    if state.shops.list().await?.iter().any(|shop| shop.shop_id == shop_id) {
+        Ok(())
+    } else {
+        Err(AppError::NotFound(format!("shop {}", shop_id)))
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/health",
+    tag = "admin",
+    responses((status = 200, body = api_docs::HealthResponse))
+)]
+async fn health_handler(State(state): State<AppState>) -> Json<serde_json::Value> {
+    let disconnection = state.deauthorizer.disconnected().await.unwrap_or_else(|e| {
+        error!("Failed to read shop disconnections: {}", e);
+        None
+    });
+    Json(serde_json::json!({
+        "status": "ok",
+        "mode": state.config.mode.as_str(),
+        "timestamp": chrono::Utc::now().to_rfc3339(),
+        "tiktok_api": state.api_health.snapshot(),
+        "shop_disconnected": disconnection
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/health/ready",
+    tag = "admin",
+    responses(
+        (status = 200, description = "Ready, or `degraded` if a scheduled task's last run failed", body = api_docs::ReadyResponse),
+        (status = 503, description = "Still starting up, or the database is unreachable", body = api_docs::ReadyResponse)
+    )
+)]
+async fn readiness_handler(State(state): State<AppState>) -> (StatusCode, Json<serde_json::Value>) {
+    let startup = state.startup.status();
+    let database = match state.db.ping().await {
+        Ok(()) => "ok".to_string(),
+        Err(e) => {
+            error!("Readiness check could not reach the database: {}", e);
+            e.to_string()
+        }
+    };
+    let tasks = state.scheduler.tasks();
+    let supervised = state.supervisor.tasks();
+
+    let (status_code, status) = if !startup.ready {
+        (StatusCode::SERVICE_UNAVAILABLE, "starting")
+    } else if database != "ok" {
+        (StatusCode::SERVICE_UNAVAILABLE, "unavailable")
+    } else if tasks.iter().any(|t| !t.is_healthy()) || supervised.iter().any(|t| !t.is_healthy()) {
+        (StatusCode::OK, "degraded")
+    } else {
+        (StatusCode::OK, "ready")
+    };
+
+    (
+        status_code,
+        Json(serde_json::json!({
+            "status": status,
+            "startup": startup,
+            "database": database,
+            "tasks": tasks,
+            "supervised": supervised
+        })),
+    )
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct OrdersQuery {
+    /// Page size; without `limit` or `cursor` every order is returned
+    limit: Option<i64>,
+    /// `next_cursor` from the previous page
+    cursor: Option<String>,
+    /// Also list orders moved out of the default listing by the archival policy
+    #[serde(default)]
+    include_archived: bool,
+    /// Only orders shipped to this province (normalized, case-insensitive)
+    province: Option<String>,
+    district: Option<String>,
+    ward: Option<String>,
+    /// Only orders with this tag, e.g. `sample` or `fraud-check`; comma-separate to require several
+    tag: Option<String>,
+    /// Only orders whose risk score, given when first synced, is at least this (0.0 - 1.0)
+    min_risk: Option<f64>,
+}
+
+impl OrdersQuery {
+    fn filter(&self) -> OrderFilter {
+        OrderFilter {
+            include_archived: self.include_archived,
+            province: self.province.as_deref().and_then(address::province_name),
+            district: self.district.as_deref().and_then(address::clean),
+            ward: self.ward.as_deref().and_then(address::clean),
+            tags: self
+                .tag
+                .as_deref()
+                .unwrap_or_default()
+                .split(',')
+                .map(|t| t.trim().to_ascii_lowercase())
+                .filter(|t| !t.is_empty())
+                .collect(),
+            min_risk: self.min_risk,
+        }
+    }
+}
+
+/// Stored orders, newest first: one page when `limit` or `cursor` is given,
+/// otherwise all of them, streamed as they are read. Responses carry an `ETag`
+/// and `Last-Modified`; send them back as `If-None-Match` / `If-Modified-Since`
+/// to get a 304 while nothing changed. Changes to orders, their tags, risk
+/// scores, normalized addresses and carriers count, as does an order turning late.
+#[utoipa::path(
+    get,
+    path = "/orders",
+    tag = "orders",
+    params(OrdersQuery),
+    responses(
+        (status = 200, body = api_docs::OrdersResponse),
+        (status = 304, description = "No order changed since the given ETag or date")
+    )
+)]
+async fn get_orders_handler(
+    State(state): State<AppState>,
+    Query(query): Query<OrdersQuery>,
+    access: PiiAccess,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let cursor = query
+        .cursor
+        .as_deref()
+        .map(|c| {
+            OrderCursor::decode(c).ok_or_else(|| AppError::BadRequest("invalid cursor".to_string()))
+        })
+        .transpose()?;
+
+    let version = match state.db.orders_version(chrono::Utc::now().timestamp()).await {
+        Ok(version) => Some(version),
+        Err(e) => {
+            error!("Failed to read orders version: {}", e);
+            None
+        }
+    };
+
+    let mut validators = HeaderMap::new();
+    if let Some(version) = version {
+        let last_modified = DateTime::from_timestamp(version.changed_at, 0)
+            .unwrap_or_default()
+            .format("%a, %d %b %Y %H:%M:%S GMT")
+            .to_string();
+        for (name, value) in [
+            (header::ETAG, version.etag()),
+            (header::LAST_MODIFIED, last_modified),
+            (header::CACHE_CONTROL, "no-cache".to_string()),
+            // Buyer PII is masked or not depending on the credentials
+            (header::VARY, "authorization, x-api-key".to_string()),
+        ] {
+            if let Ok(value) = HeaderValue::from_str(&value) {
+                validators.insert(name, value);
+            }
+        }
+
+        if is_not_modified(&headers, &version.etag(), version.changed_at) {
+            return Ok((StatusCode::NOT_MODIFIED, validators).into_response());
+        }
+    }
+
+    let carriers = state.carriers.directory().await?;
+    if query.limit.is_some() || cursor.is_some() {
+        let limit = query.limit.unwrap_or(100).clamp(1, 1000);
+        let page = state
+            .db
+            .get_orders_paginated(limit, cursor.as_ref(), &query.filter())
+            .await?;
+        let orders: Vec<_> = page
+            .orders
+            .into_iter()
+            .map(|o| carriers.track(access.order(&state.db, o)))
+            .collect();
+        let body = Json(serde_json::json!({
+            "success": true,
+            "count": orders.len(),
+            "orders": orders,
+            "next_cursor": page.next_cursor.map(|c| c.encode())
+        }));
+        return Ok((validators, body).into_response());
+    }
+
+    validators.insert(header::CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    let db = state.db.clone();
+    let orders = state
+        .db
+        .stream_orders(query.filter())
+        .map(move |order| order.map(|o| carriers.track(access.order(&db, o))));
+    Ok((validators, json_list_body("orders", orders)).into_response())
+}
+
+/// Stream `{"success":true,"<key>":[...],"count":N}` without building the whole
+/// list in memory. An error mid-stream aborts the body, so clients see a
+/// truncated response rather than a partial list that looks complete.
+fn json_list_body<T, E>(
+    key: &'static str,
+    mut items: impl Stream<Item = Result<T, E>> + Send + Unpin + 'static,
+) -> Body
+where
+    T: Serialize + Send + 'static,
+    E: std::fmt::Display + Send + 'static,
+{
+    const FLUSH_BYTES: usize = 64 * 1024;
+    let (tx, rx) = tokio::sync::mpsc::channel::<Result<Vec<u8>, std::io::Error>>(4);
+
+    tokio::spawn(async move {
+        let mut buf = format!(r#"{{"success":true,"{}":["#, key).into_bytes();
+        let mut count = 0usize;
+
+        while let Some(item) = items.next().await {
+            let item = match item {
+                Ok(item) => item,
+                Err(e) => {
+                    error!("Failed to read {} while streaming: {}", key, e);
+                    let _ = tx.send(Err(std::io::Error::other(e.to_string()))).await;
+                    return;
+                }
+            };
+
+            if count > 0 {
+                buf.push(b',');
+            }
+            if let Err(e) = serde_json::to_writer(&mut buf, &item) {
+                error!("Failed to serialize {} while streaming: {}", key, e);
+                let _ = tx.send(Err(std::io::Error::other(e))).await;
+                return;
+            }
+            count += 1;
+
+            if buf.len() >= FLUSH_BYTES && tx.send(Ok(std::mem::take(&mut buf))).await.is_err() {
+                // Client went away
+                return;
+            }
+        }
+
+        buf.extend_from_slice(format!(r#"],"count":{}}}"#, count).as_bytes());
+        let _ = tx.send(Ok(buf)).await;
+    });
+
+    Body::from_stream(ReceiverStream::new(rx))
+}
+
+/// Conditional GET check. `If-None-Match` takes precedence over `If-Modified-Since`.
+fn is_not_modified(headers: &HeaderMap, etag: &str, changed_at: i64) -> bool {
+    if let Some(if_none_match) = headers.get(header::IF_NONE_MATCH) {
+        let Ok(if_none_match) = if_none_match.to_str() else {
+            return false;
+        };
+        return if_none_match
+            .split(',')
+            .map(|tag| tag.trim().trim_start_matches("W/"))
+            .any(|tag| tag == "*" || tag == etag);
+    }
+
+    headers
+        .get(header::IF_MODIFIED_SINCE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| DateTime::parse_from_rfc2822(v).ok())
+        .is_some_and(|since| changed_at <= since.timestamp())
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct OrderStreamQuery {
+    /// Comma-separated statuses to include (all when omitted)
+    status: Option<String>,
+}
+
+/// Server-Sent Events stream of order created/updated events, with keep-alive pings
+#[utoipa::path(
+    get,
+    path = "/orders/stream",
+    tag = "orders",
+    params(OrderStreamQuery),
+    responses((
+        status = 200,
+        description = "`created`/`updated`/`sla_at_risk`/`sla_breached` events carrying an OrderEvent, plus `lagged` when events were dropped",
+        content_type = "text/event-stream",
+        body = OrderEvent
+    ))
+)]
+async fn order_stream_handler(
+    State(state): State<AppState>,
+    Query(query): Query<OrderStreamQuery>,
+) -> Sse<impl Stream<Item = Result<Event, axum::Error>>> {
+    let statuses: Vec<String> = query
+        .status
+        .unwrap_or_default()
+        .split(',')
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect();
+
+    let stream = BroadcastStream::new(state.events.subscribe()).filter_map(move |event| {
+        match event {
+            Ok(event) if statuses.is_empty() || statuses.contains(&event.status) => Some(
+                Event::default()
+                    .event(event.kind.as_str())
+                    .id(format!("{}:{}", event.order_id, event.update_time))
+                    .json_data(&event),
+            ),
+            Ok(_) => None,
+            // Tell the client it missed events so it can re-fetch /orders
+            Err(BroadcastStreamRecvError::Lagged(missed)) => {
+                Some(Ok(Event::default().event("lagged").data(missed.to_string())))
+            }
+        }
+    });
+
+    Sse::new(stream).keep_alive(
+        KeepAlive::new()
+            .interval(Duration::from_secs(15))
+            .text("ping"),
+    )
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct OrderDetailQuery {
+    /// Fetch the order from TikTok and store it before returning
+    #[serde(default)]
+    refresh: bool,
+}
+
+/// Return a stored order, or with `?refresh=true` fetch it from TikTok and store it first
+#[utoipa::path(
+    get,
+    path = "/orders/{id}",
+    tag = "orders",
+    params(("id" = String, Path, description = "TikTok order id"), OrderDetailQuery),
+    responses(
+        (status = 200, body = api_docs::OrderResponse),
+        (status = 404, body = api_docs::ErrorResponse)
+    )
+)]
+async fn get_order_handler(
+    State(state): State<AppState>,
+    Path(order_id): Path<String>,
+    Query(query): Query<OrderDetailQuery>,
+    access: PiiAccess,
+) -> Result<Json<serde_json::Value>, AppError> {
+    if !query.refresh {
+        let order = state
+            .db
+            .get_order_by_id(&order_id)
+            .await?
+            .ok_or_else(|| AppError::NotFound(format!("order {}", order_id)))?;
+
+        return Ok(Json(serde_json::json!({
+            "success": true,
+            "source": "local",
+            "replacement": state.replacements.links(&order.id).await?,
+            "risk": state.risk.get(&order.id).await?,
+            "external_ref": state.external_refs.get(&order.id).await?,
+            "warehouse": state.warehouses.get(&order.id).await?,
+            "fx": state.fx.get(&order.id).await?,
+            "items": state.item_fulfillment.for_order(&order.id).await?,
+            "order": state.carriers.directory().await?.track(access.order(&state.db, order))
+        })));
+    }
+
+    let order = fetch_and_store_order(&state, &order_id).await?;
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "source": "api",
+        "replacement": state.replacements.links(&order.id).await?,
+        "risk": state.risk.get(&order.id).await?,
+        "external_ref": state.external_refs.get(&order.id).await?,
+        "warehouse": state.warehouses.get(&order.id).await?,
+        "fx": state.fx.get(&order.id).await?,
+        "items": state.item_fulfillment.for_order(&order.id).await?,
+        "order": state.carriers.directory().await?.track(access.order(&state.db, order))
+    })))
+}
+
+/// Fetch an order from the TikTok API and upsert it locally
+async fn fetch_and_store_order(state: &AppState, order_id: &str) -> Result<Order, AppError> {
+    let order = fetch_remote_order(state, order_id).await?;
+
+    let stats = state.db.upsert_orders(std::slice::from_ref(&order)).await?;
+    info!("Refreshed order {} from API: {}", order_id, stats);
+
+    Ok(order)
+}
+
+/// Fetch the live copy of an order from the TikTok API
+async fn fetch_remote_order(state: &AppState, order_id: &str) -> Result<Order, AppError> {
+    let token_info = load_valid_token(&state.oauth_client).await?;
+    let order_client = resolve_shop_target(&state.shops, &state.config, &token_info.access_token)
+        .await
+        .order_client(&state.config)
+        .with_health(state.api_health.clone());
+
+    let response = order_client
+        .get_order_detail(&token_info.access_token, None, &[order_id.to_string()])
+        .await?;
+
+    response
+        .orders
+        .into_iter()
+        .find(|o| o.id == order_id)
+        .ok_or_else(|| AppError::NotFound(format!("order {}", order_id)))
+}
+
+/// Field-by-field differences between the stored order and the live one
+/// fetched from TikTok. The stored copy is left as it is.
+#[utoipa::path(
+    get,
+    path = "/orders/{id}/diff",
+    tag = "orders",
+    params(("id" = String, Path, description = "TikTok order id")),
+    responses(
+        (status = 200, body = api_docs::OrderDiffResponse),
+        (status = 404, body = api_docs::ErrorResponse)
+    )
+)]
+async fn get_order_diff_handler(
+    State(state): State<AppState>,
+    Path(order_id): Path<String>,
+    access: PiiAccess,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let local = state
+        .db
+        .get_order_by_id(&order_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("order {}", order_id)))?;
+    let local = access.order(&state.db, local);
+    let remote = access.order(&state.db, fetch_remote_order(&state, &order_id).await?);
+    let diff = order_diff::diff_orders(&local, &remote);
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "in_sync": diff.changes.is_empty(),
+        "diff": diff
+    })))
+}
+
+/// Tracking from the 3PL for an order pushed to it. The order is marked
+/// shipped in TikTok in the background; see `GET /orders/{id}/3pl`.
+#[utoipa::path(
+    post,
+    path = "/webhooks/3pl",
+    tag = "webhooks",
+    request_body = ThreePlCallback,
+    params(("Authorization" = String, Header, description = "`Bearer <THREEPL_CALLBACK_TOKEN>`")),
+    responses(
+        (status = 200, body = api_docs::ThreePlShipmentResponse),
+        (status = 400, body = api_docs::ErrorResponse),
+        (status = 401, body = api_docs::ErrorResponse),
+        (status = 404, body = api_docs::ErrorResponse)
+    )
+)]
+async fn threepl_callback_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    payload: Result<Json<ThreePlCallback>, JsonRejection>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let Some(settings) = &state.config.threepl else {
+        return Err(AppError::NotFound("3PL integration (THREEPL_URL is not set)".to_string()));
+    };
+    let presented = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .unwrap_or_default()
+        .trim();
+    // Compare digests so the comparison time says nothing about the token
+    if Sha256::digest(presented.as_bytes()) != Sha256::digest(settings.callback_token.as_bytes()) {
+        return Err(AppError::Unauthorized("invalid 3PL callback token".to_string()));
+    }
+
+    let Json(callback) = payload.map_err(|e| AppError::BadRequest(e.body_text()))?;
+    let tracking_number = callback.tracking_number.trim();
+    if tracking_number.is_empty() {
+        return Err(AppError::BadRequest("tracking_number must not be empty".to_string()));
+    }
+    let Some(provider_id) = callback
+        .shipping_provider_id
+        .as_deref()
+        .or(settings.shipping_provider_id.as_deref())
+    else {
+        return Err(AppError::BadRequest(
+            "shipping_provider_id is required unless THREEPL_SHIPPING_PROVIDER_ID is set"
+                .to_string(),
+        ));
+    };
+    let shipment = match (&callback.shipment_id, &callback.order_id) {
+        (Some(shipment_id), _) => state.threepl.find_by_shipment_id(shipment_id).await?,
+        (None, Some(order_id)) => state.threepl.get(order_id).await?,
+        (None, None) => {
+            return Err(AppError::BadRequest("shipment_id or order_id is required".to_string()))
+        }
+    }
+    .ok_or_else(|| AppError::NotFound("3PL shipment".to_string()))?;
+
+    state
+        .threepl
+        .record_tracking(&shipment.order_id, tracking_number, provider_id)
+        .await?;
+    let shipment = state
+        .threepl
+        .get(&shipment.order_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("3PL shipment".to_string()))?;
+    if shipment.shipped_at.is_none() {
+        threepl::enqueue_ship(&state.job_queue, &shipment, state.config.job_max_attempts).await?;
+    }
+    info!(
+        "3PL reported tracking {} for order {}",
+        tracking_number, shipment.order_id
+    );
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "shipment": shipment
+    })))
+}
+
+/// Receive TikTok push events.
+///
+/// Deliveries are signature-checked and deduplicated by notification id, so a
+/// re-delivered event doesn't trigger fulfillment twice.
+#[utoipa::path(
+    post,
+    path = "/webhooks/tiktok",
+    tag = "webhooks",
+    request_body(content = String, description = "Raw TikTok event JSON", content_type = "application/json"),
+    params(("Authorization" = String, Header, description = "Hex HMAC-SHA256 of `{app_key}{body}`, keyed with the app secret")),
+    responses(
+        (status = 200, body = api_docs::WebhookAckResponse),
+        (status = 401, body = api_docs::ErrorResponse)
+    )
+)]
+async fn tiktok_webhook_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<serde_json::Value>, AppError> {
+    // The signature covers the raw bytes, so check it before parsing
+    let signature = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    webhooks::verify_webhook_signature(
+        &state.config.app_key,
+        &state.config.app_secret,
+        &body,
+        signature,
+    )?;
+
+    let body = std::str::from_utf8(&body)
+        .map_err(|e| AppError::BadRequest(format!("Invalid webhook payload: {}", e)))?;
+    let event: WebhookEvent = serde_json::from_str(body)
+        .map_err(|e| AppError::BadRequest(format!("Invalid webhook payload: {}", e)))?;
+    webhooks::check_webhook_timestamp(event.timestamp)?;
+
+    // Archived as received, redeliveries included
+    if let Some(archive) = &state.payload_archive {
+        if let Err(e) = archive
+            .archive(PayloadSource::Webhook, event.shop_id.as_deref(), body)
+            .await
+        {
+            warn!("Failed to queue archiving webhook {}: {}", event.tts_notification_id, e);
+        }
+    }
+
+    if !state.webhook_store.mark_event_received(&event).await? {
+        info!("Ignoring duplicate webhook event {}", event.tts_notification_id);
+        return Ok(Json(serde_json::json!({
+            "success": true,
+            "duplicate": true
+        })));
+    }
+
+    if event.is_seller_deauthorization() {
+        let shop_id = match &event.shop_id {
+            Some(shop_id) => shop_id.clone(),
+            None => state.deauthorizer.shop_id().await?,
+        };
+        state
+            .deauthorizer
+            .disconnect(&shop_id, "seller de-authorized the app")
+            .await?;
+    }
+
+    if let Some(order_id) = event.order_id() {
+        if let Err(e) = process_order_event(&state, order_id).await {
+            // The event is already marked as seen, so retry it from the job queue
+            warn!("Processing webhook for order {} failed, queued for retry: {}", order_id, e);
+            state
+                .job_queue
+                .enqueue(
+                    order_jobs::REFRESH_JOB_KIND,
+                    Some(&format!("{}:{}", order_jobs::REFRESH_JOB_KIND, event.tts_notification_id)),
+                    &serde_json::json!({ "order_id": order_id }),
+                    state.config.job_max_attempts,
+                )
+                .await?;
+        }
+        // Cancellation status changes carry the request they are about
+        if event.data.get("cancel_id").is_some() {
+            if let Err(e) = state.cancellation_monitor.sync_order(order_id).await {
+                warn!("Syncing cancellation requests of order {} failed: {}", order_id, e);
+            }
+        }
+    }
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "duplicate": false
+    })))
+}
+
+/// Store the latest order; fulfillment follows through the processing pipeline
+async fn process_order_event(state: &AppState, order_id: &str) -> Result<(), AppError> {
+    fetch_and_store_order(state, order_id).await?;
+    Ok(())
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct CancellationsQuery {
+    /// TikTok cancel status, or `all` (default `CANCELLATION_REQUEST_PENDING`)
+    status: Option<String>,
+    order_id: Option<String>,
+    limit: Option<i64>,
+}
+
+/// Cancellation requests synced from TikTok, oldest first; pending ones by default
+#[utoipa::path(
+    get,
+    path = "/cancellations",
+    tag = "orders",
+    params(CancellationsQuery),
+    responses((status = 200, body = api_docs::CancellationsResponse))
+)]
+async fn list_cancellations_handler(
+    State(state): State<AppState>,
+    Query(query): Query<CancellationsQuery>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let status = match query.status.as_deref() {
+        Some("all") => None,
+        Some(status) => Some(status),
+        None => Some(CANCELLATION_PENDING),
+    };
+    let cancellations = state
+        .cancellations
+        .list(status, query.order_id.as_deref(), query.limit.unwrap_or(100))
+        .await?;
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "count": cancellations.len(),
+        "cancellations": cancellations
+    })))
+}
+
+/// A stored request that is still waiting for the seller
+async fn pending_cancellation(
+    state: &AppState,
+    cancel_id: &str,
+) -> Result<toptop_order::cancellations::StoredCancellation, AppError> {
+    let stored = state
+        .cancellations
+        .get(cancel_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("cancellation {}", cancel_id)))?;
+    if !stored.is_pending() {
+        return Err(AppError::BadRequest(format!(
+            "cancellation {} is {}, not pending",
+            cancel_id, stored.cancellation.cancel_status
+        )));
+    }
+    Ok(stored)
+}
+
+/// Record a decision, then store the request's and order's new state from TikTok
+async fn finish_cancellation_decision(
+    state: &AppState,
+    cancel_id: &str,
+    order_id: &str,
+    decision: &str,
+) -> Result<Json<serde_json::Value>, AppError> {
+    state.cancellations.record_decision(cancel_id, decision).await?;
+    info!("Cancellation {} of order {} {}", cancel_id, order_id, decision);
+    if let Err(e) = state.cancellation_monitor.sync_order(order_id).await {
+        warn!("Syncing cancellation requests of order {} failed: {}", order_id, e);
+    }
+    if let Err(e) = fetch_and_store_order(state, order_id).await {
+        warn!("Refreshing order {} failed: {}", order_id, e);
+    }
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "cancellation": state.cancellations.get(cancel_id).await?
+    })))
+}
+
+/// Accept a buyer's cancellation request; TikTok cancels and refunds the order
+#[utoipa::path(
+    post,
+    path = "/cancellations/{id}/approve",
+    tag = "orders",
+    params(("id" = String, Path, description = "TikTok cancel id")),
+    responses(
+        (status = 200, body = api_docs::CancellationResponse),
+        (status = 400, body = api_docs::ErrorResponse),
+        (status = 401, body = api_docs::ErrorResponse),
+        (status = 404, body = api_docs::ErrorResponse)
+    )
+)]
+async fn approve_cancellation_handler(
+    State(state): State<AppState>,
+    Path(cancel_id): Path<String>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let stored = pending_cancellation(&state, &cancel_id).await?;
+    let token_info = load_valid_token(&state.oauth_client).await?;
+    resolve_shop_target(&state.shops, &state.config, &token_info.access_token)
+        .await
+        .order_client(&state.config)
+        .with_health(state.api_health.clone())
+        .approve_cancellation(&token_info.access_token, None, &cancel_id)
+        .await?;
+
+    let order_id = stored.cancellation.order_id;
+    finish_cancellation_decision(&state, &cancel_id, &order_id, "approved").await
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+struct RejectCancellationBody {
+    /// TikTok reject reason key, e.g. `seller_reject_apply_product_has_been_packed`
+    reason: String,
+    comment: Option<String>,
+}
+
+/// Turn down a buyer's cancellation request, e.g. because the order has shipped
+#[utoipa::path(
+    post,
+    path = "/cancellations/{id}/reject",
+    tag = "orders",
+    params(("id" = String, Path, description = "TikTok cancel id")),
+    request_body = RejectCancellationBody,
+    responses(
+        (status = 200, body = api_docs::CancellationResponse),
+        (status = 400, body = api_docs::ErrorResponse),
+        (status = 401, body = api_docs::ErrorResponse),
+        (status = 404, body = api_docs::ErrorResponse)
+    )
+)]
+async fn reject_cancellation_handler(
+    State(state): State<AppState>,
+    Path(cancel_id): Path<String>,
+    payload: Result<Json<RejectCancellationBody>, JsonRejection>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let Json(body) = payload.map_err(|e| AppError::BadRequest(e.body_text()))?;
+    if body.reason.trim().is_empty() {
+        return Err(AppError::BadRequest("reason must not be empty".to_string()));
+    }
+    let stored = pending_cancellation(&state, &cancel_id).await?;
+    let token_info = load_valid_token(&state.oauth_client).await?;
+    let request = RejectCancellationRequest {
+        reject_reason: body.reason.trim().to_string(),
+        comment: body.comment.filter(|c| !c.trim().is_empty()),
+    };
+    resolve_shop_target(&state.shops, &state.config, &token_info.access_token)
+        .await
+        .order_client(&state.config)
+        .with_health(state.api_health.clone())
+        .reject_cancellation(&token_info.access_token, None, &cancel_id, &request)
+        .await?;
+
+    let order_id = stored.cancellation.order_id;
+    finish_cancellation_decision(&state, &cancel_id, &order_id, "rejected").await
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct AnomaliesQuery {
+    /// e.g. `total_mismatch` or `currency_mismatch`
+    kind: Option<String>,
+    order_id: Option<String>,
+    limit: Option<i64>,
+}
+
+/// Payment inconsistencies found when orders were stored, newest first
+#[utoipa::path(
+    get,
+    path = "/orders/anomalies",
+    tag = "orders",
+    params(AnomaliesQuery),
+    responses((status = 200, body = api_docs::AnomaliesResponse))
+)]
+async fn list_anomalies_handler(
+    State(state): State<AppState>,
+    Query(query): Query<AnomaliesQuery>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let anomalies = state
+        .anomalies
+        .list(
+            query.kind.as_deref(),
+            query.order_id.as_deref(),
+            query.limit.unwrap_or(100),
+        )
+        .await?;
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "count": anomalies.len(),
+        "anomalies": anomalies
+    })))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct SlaQuery {
+    /// Include deadlines due within this long, e.g. `6h` or `90m` (default `SLA_ALERT_WITHIN`)
+    due_within: Option<String>,
+}
+
+/// Open RTS/shipping deadlines of unshipped orders due within a window,
+/// breached ones included, soonest first
+#[utoipa::path(
+    get,
+    path = "/orders/sla",
+    tag = "orders",
+    params(SlaQuery),
+    responses(
+        (status = 200, body = api_docs::SlaResponse),
+        (status = 400, body = api_docs::ErrorResponse)
+    )
+)]
+async fn sla_handler(
+    State(state): State<AppState>,
+    Query(query): Query<SlaQuery>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let within = match query.due_within.as_deref() {
+        Some(value) => sla::parse_duration(value).map_err(AppError::BadRequest)?,
+        None => state.config.sla_alert_within_secs,
+    };
+    let deadlines = sla::due_within(&state.db, chrono::Utc::now().timestamp(), within).await?;
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "due_within_secs": within,
+        "count": deadlines.len(),
+        "deadlines": deadlines
+    })))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct LateOrdersQuery {
+    /// Only orders at least this late, e.g. `6h` or `2d`
+    min_late: Option<String>,
+    /// `AWAITING_SHIPMENT` or `AWAITING_COLLECTION`
+    status: Option<String>,
+    limit: Option<usize>,
+}
+
+/// Orders past their shipping deadline that haven't been handed to the
+/// carrier, latest first
+#[utoipa::path(
+    get,
+    path = "/orders/late",
+    tag = "orders",
+    params(LateOrdersQuery),
+    responses(
+        (status = 200, body = api_docs::LateOrdersResponse),
+        (status = 400, body = api_docs::ErrorResponse)
+    )
+)]
+async fn late_orders_handler(
+    State(state): State<AppState>,
+    Query(query): Query<LateOrdersQuery>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let min_late = match query.min_late.as_deref() {
+        Some(value) => sla::parse_duration(value).map_err(AppError::BadRequest)?,
+        None => 0,
+    };
+    if let Some(status) = query.status.as_deref() {
+        if !sla::PENDING_STATUSES.contains(&status) {
+            return Err(AppError::BadRequest(format!(
+                "status must be one of {}",
+                sla::PENDING_STATUSES.join(", ")
+            )));
+        }
+    }
+
+    let late: Vec<_> = sla::late_orders(&state.db, chrono::Utc::now().timestamp(), min_late)
+        .await?
+        .into_iter()
+        .filter(|order| query.status.as_deref().is_none_or(|s| order.status == s))
+        .take(query.limit.unwrap_or(100).clamp(1, 1000))
+        .collect();
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "count": late.len(),
+        "orders": late
+    })))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct BuyerMessagesQuery {
+    /// Only messages nobody has acknowledged yet
+    #[serde(default)]
+    unhandled: bool,
+    limit: Option<i64>,
+}
+
+/// Orders whose buyer left a message ("ship after Friday", "gift wrap"), newest first
+#[utoipa::path(
+    get,
+    path = "/orders/with-messages",
+    tag = "orders",
+    params(BuyerMessagesQuery),
+    responses((status = 200, body = api_docs::BuyerMessagesResponse))
+)]
+async fn buyer_messages_handler(
+    State(state): State<AppState>,
+    Query(query): Query<BuyerMessagesQuery>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let messages = state
+        .db
+        .get_buyer_messages(query.unhandled, query.limit.unwrap_or(100).clamp(1, 1000))
+        .await?;
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "count": messages.len(),
+        "messages": messages
+    })))
+}
+
+/// Mark an order's buyer message as handled. It is flagged again if the buyer changes it.
+#[utoipa::path(
+    post,
+    path = "/orders/{id}/message/ack",
+    tag = "orders",
+    params(("id" = String, Path, description = "TikTok order id")),
+    responses(
+        (status = 200, body = api_docs::BuyerMessageResponse),
+        (status = 400, body = api_docs::ErrorResponse),
+        (status = 404, body = api_docs::ErrorResponse)
+    )
+)]
+async fn ack_buyer_message_handler(
+    State(state): State<AppState>,
+    Path(order_id): Path<String>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    if !state.db.ack_buyer_message(&order_id).await? {
+        return Err(match state.db.get_buyer_message(&order_id).await? {
+            Some(_) => AppError::BadRequest(format!("order {} has no buyer message", order_id)),
+            None => AppError::NotFound(format!("order {}", order_id)),
+        });
+    }
+
+    let message = state
+        .db
+        .get_buyer_message(&order_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("order {}", order_id)))?;
+    info!("Acknowledged buyer message on order {}", order_id);
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "message": message
+    })))
+}
+
+/// 404 unless the order is stored
+async fn require_order(state: &AppState, order_id: &str) -> Result<(), AppError> {
+    state
+        .db
+        .get_order_by_id(order_id)
+        .await?
+        .map(|_| ())
+        .ok_or_else(|| AppError::NotFound(format!("order {}", order_id)))
+}
+
+/// Tags of an order, set by `ORDER_TAG_RULES` or by hand
+#[utoipa::path(
+    get,
+    path = "/orders/{id}/tags",
+    tag = "orders",
+    params(("id" = String, Path, description = "TikTok order id")),
+    responses(
+        (status = 200, body = api_docs::OrderTagsResponse),
+        (status = 404, body = api_docs::ErrorResponse)
+    )
+)]
+async fn list_order_tags_handler(
+    State(state): State<AppState>,
+    Path(order_id): Path<String>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    require_order(&state, &order_id).await?;
+    let tags = state.order_tags.tags(&order_id).await?;
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "order_id": order_id,
+        "tags": tags
+    })))
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+struct TagsRequest {
+    /// Lowercase letters, digits, `-` and `_`, e.g. `fraud-check` or `reship`
+    tags: Vec<String>,
+}
+
+/// Tag an order by hand
+#[utoipa::path(
+    post,
+    path = "/orders/{id}/tags",
+    tag = "orders",
+    params(("id" = String, Path, description = "TikTok order id")),
+    request_body = TagsRequest,
+    responses(
+        (status = 200, body = api_docs::OrderTagsResponse),
+        (status = 400, body = api_docs::ErrorResponse),
+        (status = 404, body = api_docs::ErrorResponse)
+    )
+)]
+async fn add_order_tags_handler(
+    State(state): State<AppState>,
+    Path(order_id): Path<String>,
+    payload: Result<Json<TagsRequest>, JsonRejection>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let Json(request) = payload.map_err(|e| AppError::BadRequest(e.body_text()))?;
+    let new_tags = request
+        .tags
+        .iter()
+        .map(|tag| {
+            tags::normalize_tag(tag).ok_or_else(|| {
+                AppError::BadRequest(format!(
+                    "invalid tag {:?}: use up to 64 letters, digits, - and _",
+                    tag
+                ))
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if new_tags.is_empty() {
+        return Err(AppError::BadRequest("tags must not be empty".to_string()));
+    }
+
+    require_order(&state, &order_id).await?;
+    state.order_tags.add(&order_id, &new_tags).await?;
+    info!("Tagged order {} with {}", order_id, new_tags.join(", "));
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "order_id": order_id,
+        "tags": state.order_tags.tags(&order_id).await?
+    })))
+}
+
+/// Remove a tag from an order. Tags set by a rule come back when the order is next synced.
+#[utoipa::path(
+    delete,
+    path = "/orders/{id}/tags/{tag}",
+    tag = "orders",
+    params(
+        ("id" = String, Path, description = "TikTok order id"),
+        ("tag" = String, Path, description = "Tag to remove")
+    ),
+    responses(
+        (status = 200, body = api_docs::OrderTagsResponse),
+        (status = 404, body = api_docs::ErrorResponse)
+    )
+)]
+async fn remove_order_tag_handler(
+    State(state): State<AppState>,
+    Path((order_id, tag)): Path<(String, String)>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let tag = tag.trim().to_ascii_lowercase();
+    if !state.order_tags.remove(&order_id, &tag).await? {
+        return Err(AppError::NotFound(format!("tag {} on order {}", tag, order_id)));
+    }
+    info!("Removed tag {} from order {}", tag, order_id);
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "order_id": order_id,
+        "tags": state.order_tags.tags(&order_id).await?
+    })))
+}
+
+/// Staff notes on an order, oldest first
+#[utoipa::path(
+    get,
+    path = "/orders/{id}/notes",
+    tag = "orders",
+    params(("id" = String, Path, description = "TikTok order id")),
+    responses(
+        (status = 200, body = api_docs::OrderNotesResponse),
+        (status = 404, body = api_docs::ErrorResponse)
+    )
+)]
+async fn list_order_notes_handler(
+    State(state): State<AppState>,
+    Path(order_id): Path<String>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    require_order(&state, &order_id).await?;
+    let notes = state.notes.list(&order_id).await?;
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "order_id": order_id,
+        "count": notes.len(),
+        "notes": notes
+    })))
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+struct NoteRequest {
+    body: String,
+    /// Who wrote the note
+    author: Option<String>,
+}
+
+/// Add a staff note to an order
+#[utoipa::path(
+    post,
+    path = "/orders/{id}/notes",
+    tag = "orders",
+    params(("id" = String, Path, description = "TikTok order id")),
+    request_body = NoteRequest,
+    responses(
+        (status = 200, body = api_docs::OrderNoteResponse),
+        (status = 400, body = api_docs::ErrorResponse),
+        (status = 404, body = api_docs::ErrorResponse)
+    )
+)]
+async fn add_order_note_handler(
+    State(state): State<AppState>,
+    Path(order_id): Path<String>,
+    payload: Result<Json<NoteRequest>, JsonRejection>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let Json(request) = payload.map_err(|e| AppError::BadRequest(e.body_text()))?;
+    let body = request.body.trim();
+    if body.is_empty() {
+        return Err(AppError::BadRequest("body must not be empty".to_string()));
+    }
+    if body.chars().count() > notes::MAX_NOTE_CHARS {
+        return Err(AppError::BadRequest(format!(
+            "body must be at most {} characters",
+            notes::MAX_NOTE_CHARS
+        )));
+    }
+    let author = request.author.as_deref().map(str::trim).filter(|a| !a.is_empty());
+
+    require_order(&state, &order_id).await?;
+    let note = state.notes.add(&order_id, body, author).await?;
+    info!("Added note {} to order {}", note.id, order_id);
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "note": note
+    })))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct EsimUsageQuery {
+    /// Snapshots to return, newest first (default 30)
+    limit: Option<i64>,
+}
+
+/// Data balance of a provisioned eSIM as last polled from WowEsim, with
+/// earlier snapshots
+#[utoipa::path(
+    get,
+    path = "/esims/{iccid}/usage",
+    tag = "fulfillment",
+    params(("iccid" = String, Path, description = "ICCID of the eSIM"), EsimUsageQuery),
+    responses(
+        (status = 200, body = api_docs::EsimUsageResponse),
+        (status = 404, body = api_docs::ErrorResponse)
+    )
+)]
+async fn get_esim_usage_handler(
+    State(state): State<AppState>,
+    Path(iccid): Path<String>,
+    Query(query): Query<EsimUsageQuery>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let esim = state
+        .esims
+        .provisioned_esim(&iccid)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("eSIM {}", iccid)))?;
+    let history = state
+        .esims
+        .usage_history(&iccid, query.limit.unwrap_or(30).clamp(1, 1000))
+        .await?;
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "esim": esim,
+        "latest": history.first(),
+        "history": history
+    })))
+}
+
+/// How the eSIMs of an order were delivered to the buyer, per item and channel
+#[utoipa::path(
+    get,
+    path = "/orders/{id}/esim",
+    tag = "fulfillment",
+    params(("id" = String, Path, description = "TikTok order id")),
+    responses(
+        (status = 200, body = api_docs::EsimDeliveriesResponse)
+    )
+)]
+async fn get_esim_deliveries_handler(
+    State(state): State<AppState>,
+    Path(order_id): Path<String>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let deliveries = state.esim_deliveries.for_order(&order_id).await?;
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "order_id": order_id,
+        "deliveries": deliveries
+    })))
+}
+
+/// Send the activation details of an order's provisioned eSIMs to the buyer
+/// again, over every configured channel; at most once per order every five
+/// minutes
+#[utoipa::path(
+    post,
+    path = "/orders/{id}/esim/resend",
+    tag = "fulfillment",
+    params(("id" = String, Path, description = "TikTok order id")),
+    responses(
+        (status = 200, body = api_docs::EsimResendResponse),
+        (status = 401, body = api_docs::ErrorResponse),
+        (status = 404, body = api_docs::ErrorResponse),
+        (status = 429, body = api_docs::ErrorResponse)
+    )
+)]
+async fn resend_esim_handler(
+    State(state): State<AppState>,
+    Path(order_id): Path<String>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let config = &state.config;
+    let channels = config.esim_smtp.is_some() || config.esim_sms.is_some();
+    if !config.provisions_esims() || !channels {
+        return Err(AppError::NotFound(
+            "eSIM delivery (ESIM_SMTP_URL and ESIM_SMS_URL are not set)".to_string(),
+        ));
+    }
+    let line_items = state.esims.provisioned_line_items(&order_id).await?;
+    if line_items.is_empty() {
+        return Err(AppError::NotFound(format!("provisioned eSIMs of order {}", order_id)));
+    }
+    let cooldown = esim_delivery::RESEND_COOLDOWN;
+    if !state.esim_deliveries.claim_resend(&order_id, cooldown).await? {
+        return Err(AppError::TooManyRequests(format!(
+            "eSIMs of order {} were resent less than {} minutes ago",
+            order_id,
+            cooldown.as_secs() / 60
+        )));
+    }
+
+    state.esim_deliveries.reset(&order_id).await?;
+    for line_item_id in &line_items {
+        esim_delivery::enqueue_delivery(
+            &state.job_queue,
+            &order_id,
+            line_item_id,
+            true,
+            config.job_max_attempts,
+        )
+        .await?;
+    }
+    info!("Queued eSIM resend for {} items of order {}", line_items.len(), order_id);
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "order_id": order_id,
+        "line_item_ids": line_items
+    })))
+}
+
+/// Where an order pushed to the 3PL stands: its shipment id, tracking and
+/// whether TikTok has it as shipped
+#[utoipa::path(
+    get,
+    path = "/orders/{id}/3pl",
+    tag = "orders",
+    params(("id" = String, Path, description = "TikTok order id")),
+    responses(
+        (status = 200, body = api_docs::ThreePlShipmentResponse),
+        (status = 404, body = api_docs::ErrorResponse)
+    )
+)]
+async fn get_threepl_shipment_handler(
+    State(state): State<AppState>,
+    Path(order_id): Path<String>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let shipment = state
+        .threepl
+        .get(&order_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("3PL shipment of order {}", order_id)))?;
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "shipment": shipment
+    })))
+}
+
+/// Copies of an order in WooCommerce/Shopify, with their ids there
+#[utoipa::path(
+    get,
+    path = "/orders/{id}/mirrors",
+    tag = "orders",
+    params(("id" = String, Path, description = "TikTok order id")),
+    responses(
+        (status = 200, body = api_docs::OrderMirrorsResponse),
+        (status = 404, body = api_docs::ErrorResponse)
+    )
+)]
+async fn list_order_mirrors_handler(
+    State(state): State<AppState>,
+    Path(order_id): Path<String>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    require_order(&state, &order_id).await?;
+    let mirrors = state.mirrors.list(&order_id).await?;
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "count": mirrors.len(),
+        "mirrors": mirrors
+    })))
+}
+
+/// The ERP/invoice reference attached to an order
+#[utoipa::path(
+    get,
+    path = "/orders/{id}/external-ref",
+    tag = "orders",
+    params(("id" = String, Path, description = "TikTok order id")),
+    responses(
+        (status = 200, body = api_docs::ExternalRefResponse),
+        (status = 404, body = api_docs::ErrorResponse)
+    )
+)]
+async fn get_external_ref_handler(
+    State(state): State<AppState>,
+    Path(order_id): Path<String>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let external_ref = state
+        .external_refs
+        .get(&order_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("external reference of order {}", order_id)))?;
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "external_ref": external_ref
+    })))
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+struct ExternalRefRequest {
+    /// Order or invoice number in your ERP
+    external_ref: String,
+    /// System the reference belongs to, as sent to TikTok (default `OTHER`)
+    platform: Option<String>,
+}
+
+/// Attach an ERP/invoice reference to an order, replacing any previous one.
+/// With `PUSH_EXTERNAL_REFS`, the reference is also sent to TikTok in the background.
+#[utoipa::path(
+    post,
+    path = "/orders/{id}/external-ref",
+    tag = "orders",
+    params(("id" = String, Path, description = "TikTok order id")),
+    request_body = ExternalRefRequest,
+    responses(
+        (status = 200, body = api_docs::SetExternalRefResponse),
+        (status = 400, body = api_docs::ErrorResponse),
+        (status = 404, body = api_docs::ErrorResponse)
+    )
+)]
+async fn set_external_ref_handler(
+    State(state): State<AppState>,
+    Path(order_id): Path<String>,
+    payload: Result<Json<ExternalRefRequest>, JsonRejection>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let Json(request) = payload.map_err(|e| AppError::BadRequest(e.body_text()))?;
+    let reference = request.external_ref.trim();
+    if reference.is_empty() {
+        return Err(AppError::BadRequest("external_ref must not be empty".to_string()));
+    }
+    if reference.chars().count() > external_refs::MAX_REF_CHARS {
+        return Err(AppError::BadRequest(format!(
+            "external_ref must be at most {} characters",
+            external_refs::MAX_REF_CHARS
+        )));
+    }
+    let platform = request
+        .platform
+        .as_deref()
+        .map(|p| p.trim().to_ascii_uppercase())
+        .filter(|p| !p.is_empty())
+        .unwrap_or_else(|| external_refs::DEFAULT_PLATFORM.to_string());
+    if !platform.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(AppError::BadRequest(
+            "platform must only contain letters, digits and underscores".to_string(),
+        ));
+    }
+
+    require_order(&state, &order_id).await?;
+    if let Some(existing) = state.external_refs.find_by_ref(reference).await? {
+        if existing.order_id != order_id {
+            return Err(AppError::BadRequest(format!(
+                "external_ref {} is already attached to order {}",
+                reference, existing.order_id
+            )));
+        }
+    }
+    let external_ref = state.external_refs.set(&order_id, reference, &platform).await?;
+    info!("Order {} is {} in {}", order_id, reference, platform);
+
+    let push_queued = state.config.push_external_refs
+        && external_refs::enqueue_push(&state.job_queue, &external_ref, state.config.job_max_attempts)
+            .await?;
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "external_ref": external_ref,
+        "push_queued": push_queued
+    })))
+}
+
+/// Detach an order's ERP/invoice reference. References already sent to TikTok stay there.
+#[utoipa::path(
+    delete,
+    path = "/orders/{id}/external-ref",
+    tag = "orders",
+    params(("id" = String, Path, description = "TikTok order id")),
+    responses(
+        (status = 200, body = api_docs::DeleteExternalRefResponse),
+        (status = 404, body = api_docs::ErrorResponse)
+    )
+)]
+async fn delete_external_ref_handler(
+    State(state): State<AppState>,
+    Path(order_id): Path<String>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    if !state.external_refs.remove(&order_id).await? {
+        return Err(AppError::NotFound(format!("external reference of order {}", order_id)));
+    }
+    info!("Removed external reference of order {}", order_id);
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "order_id": order_id
+    })))
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+struct BackfillRequest {
+    /// Start of the `create_time` range: RFC 3339, `YYYY-MM-DD` (midnight UTC) or unix seconds;
+    /// defaults to the shop's `backfill_days` setting
+    from: Option<String>,
+    /// Exclusive end of the range, in the same formats; defaults to now
+    to: Option<String>,
+    /// Days covered by each TikTok request (1-30, default 7)
+    window_days: Option<i64>,
+}
+
+/// Import historical orders created in a time range.
+///
+/// Runs in the background in resumable steps; poll `/sync/backfill/{id}` for progress.
+#[utoipa::path(
+    post,
+    path = "/sync/backfill",
+    tag = "admin",
+    request_body = BackfillRequest,
+    responses(
+        (status = 200, body = api_docs::BackfillResponse),
+        (status = 400, body = api_docs::ErrorResponse)
+    )
+)]
+async fn start_backfill_handler(
+    State(state): State<AppState>,
+    payload: Result<Json<BackfillRequest>, JsonRejection>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let Json(request) = payload.map_err(|e| AppError::BadRequest(e.body_text()))?;
+
+    let now = chrono::Utc::now().timestamp();
+    let from = match request.from.as_deref() {
+        Some(from) => backfill::parse_time(from)
+            .ok_or_else(|| AppError::BadRequest(format!("invalid from: {:?}", from)))?,
+        None => {
+            let shop_id = state.deauthorizer.shop_id().await?;
+            let days = state.shop_settings.get(&shop_id).await?.backfill_days.ok_or_else(|| {
+                AppError::BadRequest(
+                    "from is required unless the shop's backfill_days is set".to_string(),
+                )
+            })?;
+            now - i64::from(days) * 86_400
+        }
+    };
+    let to = match request.to.as_deref() {
+        Some(to) => backfill::parse_time(to)
+            .ok_or_else(|| AppError::BadRequest(format!("invalid to: {:?}", to)))?
+            .min(now),
+        None => now,
+    };
+    if from >= to {
+        return Err(AppError::BadRequest("from must be before to".to_string()));
+    }
+
+    let run = state
+        .backfills
+        .start(
+            &state.job_queue,
+            from,
+            to,
+            request.window_days.unwrap_or(backfill::DEFAULT_WINDOW_DAYS),
+            state.config.job_max_attempts,
+        )
+        .await?;
+    info!("Started backfill run {} ({} to {})", run.id, from, to);
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "run": run
+    })))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct BackfillsQuery {
+    limit: Option<i64>,
+}
+
+/// Backfill runs, newest first
+#[utoipa::path(
+    get,
+    path = "/sync/backfill",
+    tag = "admin",
+    params(BackfillsQuery),
+    responses((status = 200, body = api_docs::BackfillsResponse))
+)]
+async fn list_backfills_handler(
+    State(state): State<AppState>,
+    Query(query): Query<BackfillsQuery>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let runs = state.backfills.list(query.limit.unwrap_or(20)).await?;
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "count": runs.len(),
+        "runs": runs
+    })))
+}
+
+/// Progress of a backfill run
+#[utoipa::path(
+    get,
+    path = "/sync/backfill/{id}",
+    tag = "admin",
+    params(("id" = i64, Path, description = "Backfill run id")),
+    responses(
+        (status = 200, body = api_docs::BackfillResponse),
+        (status = 404, body = api_docs::ErrorResponse)
+    )
+)]
+async fn get_backfill_handler(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let run = state
+        .backfills
+        .get(id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("backfill run {}", id)))?;
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "run": run
+    })))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct DeadLettersQuery {
+    /// `open` or `retried`
+    status: Option<String>,
+    limit: Option<i64>,
+}
+
+/// Jobs that exhausted their retries, newest first
+#[utoipa::path(
+    get,
+    path = "/admin/dead-letters",
+    tag = "admin",
+    params(DeadLettersQuery),
+    responses((status = 200, body = api_docs::DeadLettersResponse))
+)]
+async fn list_dead_letters_handler(
+    State(state): State<AppState>,
+    Query(query): Query<DeadLettersQuery>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let dead_letters = state
+        .job_queue
+        .dead_letters(query.status.as_deref(), query.limit.unwrap_or(100))
+        .await?;
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "count": dead_letters.len(),
+        "dead_letters": dead_letters
+    })))
+}
+
+/// How each seller SKU is fulfilled; unmapped SKUs ship as parcels
+#[utoipa::path(
+    get,
+    path = "/admin/sku-mappings",
+    tag = "admin",
+    responses((status = 200, body = api_docs::SkuMappingsResponse))
+)]
+async fn list_sku_mappings_handler(
+    State(state): State<AppState>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let mappings = state.sku_mappings.list().await?;
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "count": mappings.len(),
+        "mappings": mappings
+    })))
+}
+
+/// Replace the SKU mapping. Orders entering fulfillment afterwards use it;
+/// items already queued keep the action they were queued with.
+#[utoipa::path(
+    put,
+    path = "/admin/sku-mappings",
+    tag = "admin",
+    request_body = SkuMappingsUpdate,
+    responses(
+        (status = 200, body = api_docs::SkuMappingsResponse),
+        (status = 400, body = api_docs::ErrorResponse)
+    )
+)]
+async fn put_sku_mappings_handler(
+    State(state): State<AppState>,
+    payload: Result<Json<SkuMappingsUpdate>, JsonRejection>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let Json(update) = payload.map_err(|e| AppError::BadRequest(e.body_text()))?;
+    update.validate().map_err(AppError::BadRequest)?;
+    let config = &state.config;
+    if update.has_esims() && !config.provisions_esims() {
+        return Err(AppError::BadRequest(
+            "esim_provision needs WOW_SECRET, which is not set".to_string(),
+        ));
+    }
+    if update.has_auto_complete()
+        && config.esim_shipping_provider_id.is_none()
+        && !config.is_sandbox()
+    {
+        return Err(AppError::BadRequest(
+            "auto_complete needs ESIM_SHIPPING_PROVIDER_ID, which is not set".to_string(),
+        ));
+    }
+
+    let mappings = state.sku_mappings.replace(&update.mappings).await?;
+    info!("Replaced SKU mappings ({} SKUs)", mappings.len());
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "count": mappings.len(),
+        "mappings": mappings
+    })))
+}
+
+/// Re-enqueue a dead letter with a fresh set of attempts
+#[utoipa::path(
+    post,
+    path = "/admin/dead-letters/{id}/retry",
+    tag = "admin",
+    params(("id" = i64, Path, description = "Dead letter id")),
+    responses(
+        (status = 200, body = api_docs::RetryDeadLetterResponse),
+        (status = 404, body = api_docs::ErrorResponse)
+    )
+)]
+async fn retry_dead_letter_handler(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let job_id = state
+        .job_queue
+        .retry_dead_letter(id, state.config.job_max_attempts)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("open dead letter {}", id)))?;
+
+    info!("Dead letter {} re-enqueued as job {}", id, job_id);
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "dead_letter_id": id,
+        "job_id": job_id
+    })))
+}
+
+/// A supervised background task with the last run of its schedule, if it has one
+#[derive(Debug, Serialize, ToSchema)]
+struct BackgroundTask {
+    #[serde(flatten)]
+    supervised: SupervisedStatus,
+    /// Last scheduled run, or else the last time the task did any work
+    last_run_at: Option<DateTime<chrono::Utc>>,
+    /// Error of the last scheduled run if it failed, or else the last panic
+    last_error: Option<String>,
+    /// Set for `scheduler:<name>` tasks
+    schedule: Option<TaskStatus>,
+}
+
+fn background_tasks(state: &AppState) -> Vec<BackgroundTask> {
+    let mut schedules: HashMap<String, TaskStatus> = state
+        .scheduler
+        .tasks()
+        .into_iter()
+        .map(|status| (format!("scheduler:{}", status.name), status))
+        .collect();
+    state
+        .supervisor
+        .tasks()
+        .into_iter()
+        .map(|supervised| {
+            let schedule = schedules.remove(&supervised.name);
+            let (last_run_at, last_error) = match &schedule {
+                Some(schedule) => (
+                    schedule.last_started_at,
+                    schedule
+                        .last_detail
+                        .clone()
+                        .filter(|_| schedule.last_outcome.as_deref() == Some("failed"))
+                        .or_else(|| supervised.last_panic.clone()),
+                ),
+                None => (supervised.last_heartbeat_at, supervised.last_panic.clone()),
+            };
+            BackgroundTask {
+                supervised,
+                last_run_at,
+                last_error,
+                schedule,
+            }
+        })
+        .collect()
+}
+
+fn background_task(state: &AppState, name: &str) -> Result<BackgroundTask, AppError> {
+    background_tasks(state)
+        .into_iter()
+        .find(|task| task.supervised.name == name)
+        .ok_or_else(|| AppError::NotFound(format!("background task {}", name)))
+}
+
+/// Background tasks: the job worker, event publishers and one per scheduled task
+#[utoipa::path(
+    get,
+    path = "/admin/tasks",
+    tag = "admin",
+    responses((status = 200, body = api_docs::TasksResponse))
+)]
+async fn list_tasks_handler(State(state): State<AppState>) -> Json<serde_json::Value> {
+    let tasks = background_tasks(&state);
+    Json(serde_json::json!({
+        "success": true,
+        "count": tasks.len(),
+        "tasks": tasks
+    }))
+}
+
+/// Stop a background task until it is started again or the server restarts
+#[utoipa::path(
+    post,
+    path = "/admin/tasks/{name}/stop",
+    tag = "admin",
+    params(("name" = String, Path, description = "Task name, e.g. `scheduler:order_sync`")),
+    responses(
+        (status = 200, body = api_docs::TaskResponse),
+        (status = 404, body = api_docs::ErrorResponse)
+    )
+)]
+async fn stop_task_handler(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    if !state.supervisor.stop(&name) {
+        return Err(AppError::NotFound(format!("background task {}", name)));
+    }
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "task": background_task(&state, &name)?
+    })))
+}
+
+/// Start a background task afresh; a running task is stopped first
+#[utoipa::path(
+    post,
+    path = "/admin/tasks/{name}/start",
+    tag = "admin",
+    params(("name" = String, Path, description = "Task name, e.g. `scheduler:order_sync`")),
+    responses(
+        (status = 200, body = api_docs::TaskResponse),
+        (status = 404, body = api_docs::ErrorResponse)
+    )
+)]
+async fn start_task_handler(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    if !state.supervisor.restart(&name) {
+        return Err(AppError::NotFound(format!("background task {}", name)));
+    }
+    info!("Background task {} started from the admin API", name);
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "task": background_task(&state, &name)?
+    })))
+}
+
+/// Reload config and move scheduled tasks onto their reloaded schedules
+fn reload_config(
+    live_config: &LiveConfig,
+    scheduler: &SchedulerStatus,
+) -> Result<ConfigReload, AppError> {
+    let reload = live_config.reload()?;
+    for (name, schedule) in live_config.current().task_schedules() {
+        scheduler.reschedule(name, schedule.clone());
+    }
+
+    if reload.applied.is_empty() {
+        info!("Config reloaded, nothing to apply");
+    } else {
+        info!("Config reloaded, applied {}", reload.applied.join(", "));
+    }
+    if reload.restart_required {
+        warn!("Config has changes that only apply after a restart");
+    }
+    Ok(reload)
+}
+
+/// Reload config every time the process gets SIGHUP
+#[cfg(unix)]
+async fn reload_on_sighup(live_config: LiveConfig, scheduler: SchedulerStatus) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangups = match signal(SignalKind::hangup()) {
+        Ok(hangups) => hangups,
+        Err(e) => {
+            error!("Cannot listen for SIGHUP, use POST /admin/reload instead: {}", e);
+            return;
+        }
+    };
+    while hangups.recv().await.is_some() {
+        info!("Got SIGHUP, reloading config");
+        if let Err(e) = reload_config(&live_config, &scheduler) {
+            error!("Config reload failed, keeping the running config: {}", e);
+        }
+    }
+}
+
+/// Re-read the environment and config file and apply the settings that can
+/// change without a restart
+#[utoipa::path(
+    post,
+    path = "/admin/reload",
+    tag = "admin",
+    responses(
+        (status = 200, body = api_docs::ConfigReloadResponse),
+        (status = 500, description = "The config is invalid; nothing was changed", body = api_docs::ErrorResponse)
+    )
+)]
+async fn reload_config_handler(
+    State(state): State<AppState>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let reload = reload_config(&state.live_config, &state.scheduler).map_err(|e| {
+        error!("Config reload failed, keeping the running config: {}", e);
+        e
+    })?;
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "applied": reload.applied,
+        "restart_required": reload.restart_required
+    })))
+}
+
+/// Database file size, row counts per table and the last maintenance run
+#[utoipa::path(
+    get,
+    path = "/admin/db/stats",
+    tag = "admin",
+    responses((status = 200, body = api_docs::DbStatsResponse))
+)]
+async fn db_stats_handler(
+    State(state): State<AppState>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let stats = state.maintenance.stats(&state.config.database_path).await?;
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "stats": stats
+    })))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct WebhookDeliveriesQuery {
+    /// Subscriber URL, as configured in `OUTBOUND_WEBHOOKS`
+    url: Option<String>,
+    order_id: Option<String>,
+    /// Only failed attempts
+    #[serde(default)]
+    failed: bool,
+    limit: Option<i64>,
+}
+
+/// Outbound webhook delivery attempts, newest first
+#[utoipa::path(
+    get,
+    path = "/admin/webhook-deliveries",
+    tag = "admin",
+    params(WebhookDeliveriesQuery),
+    responses((status = 200, body = api_docs::WebhookDeliveriesResponse))
+)]
+async fn list_webhook_deliveries_handler(
+    State(state): State<AppState>,
+    Query(query): Query<WebhookDeliveriesQuery>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let filter = DeliveryFilter {
+        url: query.url.as_deref(),
+        order_id: query.order_id.as_deref(),
+        failed: query.failed,
+    };
+    let deliveries = state
+        .webhook_deliveries
+        .list(&filter, query.limit.unwrap_or(100).clamp(1, 1000))
+        .await?;
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "count": deliveries.len(),
+        "deliveries": deliveries
+    })))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct ReconciliationQuery {
+    /// Runs to list (default 20)
+    limit: Option<i64>,
+}
+
+/// Reconciliation runs, newest first, with the orders each found missing,
+/// outdated or no longer listed by TikTok
+#[utoipa::path(
+    get,
+    path = "/admin/reconciliation",
+    tag = "admin",
+    params(ReconciliationQuery),
+    responses((status = 200, body = api_docs::ReconciliationRunsResponse))
+)]
+async fn list_reconciliation_runs_handler(
+    State(state): State<AppState>,
+    Query(query): Query<ReconciliationQuery>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let runs = state
+        .reconciliation
+        .list(query.limit.unwrap_or(20).clamp(1, 200))
+        .await?;
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "count": runs.len(),
+        "runs": runs
+    })))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct ApiCallsQuery {
+    /// Only calls to paths containing this, e.g. `orders/search`
+    path: Option<String>,
+    /// Only calls that errored, got a non-2xx status or a non-zero `code`
+    #[serde(default)]
+    failed: bool,
+    /// `next_before` from the previous page
+    before: Option<i64>,
+    limit: Option<i64>,
+}
+
+/// TikTok API calls recorded with `RECORD_API_CALLS`, newest first
+#[utoipa::path(
+    get,
+    path = "/admin/api-calls",
+    tag = "admin",
+    params(ApiCallsQuery),
+    responses((status = 200, body = api_docs::ApiCallsResponse))
+)]
+async fn list_api_calls_handler(
+    State(state): State<AppState>,
+    Query(query): Query<ApiCallsQuery>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let filter = ApiCallFilter {
+        path: query.path.as_deref(),
+        failed: query.failed,
+        before_id: query.before,
+    };
+    let limit = query.limit.unwrap_or(50).clamp(1, 500);
+    let calls = state.api_calls.list(&filter, limit).await?;
+    let next_before = (calls.len() as i64 == limit)
+        .then(|| calls.last().map(|c| c.id))
+        .flatten();
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "recording": state.config.record_api_calls,
+        "count": calls.len(),
+        "calls": calls,
+        "next_before": next_before
+    })))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct PriceDetailQuery {
+    /// Fetch from TikTok even if a stored copy exists
+    #[serde(default)]
+    refresh: bool,
+}
+
+/// Itemized prices, discounts, taxes and fees of an order. Served from the
+/// `order_price_details` table; fetched from TikTok when missing, outdated
+/// by a newer order version, or `refresh` is set.
+#[utoipa::path(
+    get,
+    path = "/orders/{id}/price-detail",
+    tag = "orders",
+    params(("id" = String, Path, description = "TikTok order id"), PriceDetailQuery),
+    responses(
+        (status = 200, body = api_docs::PriceDetailResponse),
+        (status = 404, body = api_docs::ErrorResponse)
+    )
+)]
+async fn get_order_price_detail_handler(
+    State(state): State<AppState>,
+    Path(order_id): Path<String>,
+    Query(query): Query<PriceDetailQuery>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let order = state
+        .db
+        .get_order_by_id(&order_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("order {}", order_id)))?;
+
+    let stored = state
+        .price_details
+        .get(&order_id)
+        .await?
+        .filter(|stored| !query.refresh && stored.order_update_time >= order.update_time);
+    let price_detail = match stored {
+        Some(stored) => stored,
+        None => {
+            let fetcher = PriceDetailFetcher::new(
+                state.price_details.clone(),
+                state.shops.clone(),
+                state.config.clone(),
+            );
+            fetcher.fetch(&order).await?
+        }
+    };
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "price_detail": price_detail
+    })))
+}
+
+/// Recipient address split into province, district and ward, with any
+/// problems found (e.g. an invalid postal code)
+#[utoipa::path(
+    get,
+    path = "/orders/{id}/address",
+    tag = "orders",
+    params(("id" = String, Path, description = "TikTok order id")),
+    responses(
+        (status = 200, body = api_docs::OrderAddressResponse),
+        (status = 404, body = api_docs::ErrorResponse)
+    )
+)]
+async fn get_order_address_handler(
+    State(state): State<AppState>,
+    Path(order_id): Path<String>,
+    access: PiiAccess,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let mut address = state
+        .addresses
+        .get(&order_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("address of order {}", order_id)))?;
+    address.street = access.value(address.street);
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "order_id": order_id,
+        "address": address
+    })))
+}
+
+/// Print-ready packing slip with the order's items, ship-to address and totals
+#[utoipa::path(
+    get,
+    path = "/orders/{id}/packing-slip.pdf",
+    tag = "orders",
+    params(("id" = String, Path, description = "TikTok order id")),
+    responses(
+        (status = 200, description = "Packing slip", content_type = "application/pdf", body = Vec<u8>),
+        (status = 404, body = api_docs::ErrorResponse)
+    )
+)]
+async fn packing_slip_handler(
+    State(state): State<AppState>,
+    Path(order_id): Path<String>,
+    access: PiiAccess,
+) -> Result<Response, AppError> {
+    let order = state
+        .db
+        .get_order_by_id(&order_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("order {}", order_id)))?;
+    let order = access.order(&state.db, order);
+
+    let template = state.config.packing_slip_template();
+    let pdf = tokio::task::spawn_blocking(move || packing_slip::render(&order, &template))
+        .await
+        .map_err(|_| AppError::InternalServerError)?
+        .map_err(|e| {
+            error!("Packing slip for order {} failed: {}", order_id, e);
+            AppError::InternalServerError
+        })?;
+
+    let headers = [
+        (header::CONTENT_TYPE, "application/pdf".to_string()),
+        (
+            header::CONTENT_DISPOSITION,
+            format!("inline; filename=\"packing-slip-{}.pdf\"", order_id),
+        ),
+    ];
+    Ok((headers, pdf).into_response())
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct LabelQuery {
+    /// Package to print; defaults to the order's first package
+    package_id: Option<String>,
+    /// Download a fresh copy from TikTok even if one is cached
+    #[serde(default)]
+    refresh: bool,
+}
+
+/// Shipping label PDF for one of the order's packages.
+///
+/// Fetched from TikTok on first request and served from the local cache
+/// afterwards; the `x-document-source` header says which.
+#[utoipa::path(
+    get,
+    path = "/orders/{id}/label",
+    tag = "orders",
+    params(("id" = String, Path, description = "TikTok order id"), LabelQuery),
+    responses(
+        (status = 200, description = "Shipping label", content_type = "application/pdf", body = Vec<u8>),
+        (status = 400, description = "The order has no package yet", body = api_docs::ErrorResponse),
+        (status = 404, body = api_docs::ErrorResponse)
+    )
+)]
+async fn shipping_label_handler(
+    State(state): State<AppState>,
+    Path(order_id): Path<String>,
+    Query(query): Query<LabelQuery>,
+) -> Result<Response, AppError> {
+    let order = state
+        .db
+        .get_order_by_id(&order_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("order {}", order_id)))?;
+
+    let package_id = match query.package_id {
+        Some(id) if order.packages.iter().any(|p| p.id == id) => id,
+        Some(id) => {
+            return Err(AppError::NotFound(format!("package {} of order {}", id, order_id)));
+        }
+        None => order
+            .packages
+            .first()
+            .map(|p| p.id.clone())
+            .ok_or_else(|| {
+                AppError::BadRequest(format!("order {} has no shipping package yet", order_id))
+            })?,
+    };
+
+    let cached = if query.refresh {
+        None
+    } else {
+        state.documents.get(&package_id, DocumentType::ShippingLabel).await?
+    };
+
+    let (source, pdf) = match cached {
+        Some((_, pdf)) => ("cache", pdf),
+        None => {
+            let (client, token_info) = fulfillment_client(&state).await?;
+            let document = client
+                .get_shipping_document(
+                    &token_info.access_token,
+                    None,
+                    &package_id,
+                    DocumentType::ShippingLabel,
+                )
+                .await?;
+            let pdf = client.download_document(&document).await?;
+            // Labels print the buyer's address, so none is kept after an erasure
+            if state.db.is_pii_scrubbed(&order_id).await? {
+                return Ok(label_response(&package_id, "api", pdf));
+            }
+            state
+                .documents
+                .save(
+                    &order_id,
+                    &package_id,
+                    DocumentType::ShippingLabel,
+                    document.tracking_number.as_deref(),
+                    &pdf,
+                )
+                .await?;
+            info!("Cached shipping label for package {} ({} bytes)", package_id, pdf.len());
+            ("api", pdf)
+        }
+    };
+
+    Ok(label_response(&package_id, source, pdf))
+}
+
+/// A shipping label PDF, `source` saying whether it came from the cache or the API
+fn label_response(package_id: &str, source: &str, pdf: Vec<u8>) -> Response {
+    let headers = [
+        (header::CONTENT_TYPE, "application/pdf".to_string()),
+        (
+            header::CONTENT_DISPOSITION,
+            format!("inline; filename=\"label-{}.pdf\"", package_id),
+        ),
+        (HeaderName::from_static("x-document-source"), source.to_string()),
+    ];
+    (headers, pdf).into_response()
+}
+
+/// Fulfillment client for the configured shop, with a valid token
+async fn fulfillment_client(state: &AppState) -> Result<(FulfillmentClient, TokenInfo), AppError> {
+    let token_info = load_valid_token(&state.oauth_client).await?;
+    let client = resolve_shop_target(&state.shops, &state.config, &token_info.access_token)
+        .await
+        .fulfillment_client(&state.config)
+        .with_health(state.api_health.clone());
+    Ok((client, token_info))
+}
+
+/// An order's packages: the ids TikTok reported at the last sync, and the
+/// packages created here by splitting or combining
+#[utoipa::path(
+    get,
+    path = "/orders/{id}/packages",
+    tag = "fulfillment",
+    params(("id" = String, Path, description = "TikTok order id")),
+    responses(
+        (status = 200, body = api_docs::OrderPackagesResponse),
+        (status = 404, body = api_docs::ErrorResponse)
+    )
+)]
+async fn list_order_packages_handler(
+    State(state): State<AppState>,
+    Path(order_id): Path<String>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let order = state
+        .db
+        .get_order_by_id(&order_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("order {}", order_id)))?;
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "order_id": order.id,
+        "packages": order.packages,
+        "tracked": state.packages.for_order(&order_id).await?
+    })))
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+struct SplitGroup {
+    /// Line items shipped in this package
+    line_item_ids: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+struct SplitRequest {
+    /// One group per package; every line item of the order must be in exactly one
+    groups: Vec<SplitGroup>,
+}
+
+/// Split an order that hasn't shipped into several packages
+#[utoipa::path(
+    post,
+    path = "/orders/{id}/packages/split",
+    tag = "fulfillment",
+    params(("id" = String, Path, description = "TikTok order id")),
+    request_body = SplitRequest,
+    responses(
+        (status = 200, body = api_docs::SplitOrderResponse),
+        (status = 400, body = api_docs::ErrorResponse),
+        (status = 404, body = api_docs::ErrorResponse)
+    )
+)]
+async fn split_order_handler(
+    State(state): State<AppState>,
+    Path(order_id): Path<String>,
+    payload: Result<Json<SplitRequest>, JsonRejection>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let Json(request) = payload.map_err(|e| AppError::BadRequest(e.body_text()))?;
+    let order = state
+        .db
+        .get_order_by_id(&order_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("order {}", order_id)))?;
+
+    if request.groups.len() < 2 {
+        return Err(AppError::BadRequest("a split needs at least two groups".to_string()));
+    }
+    let mut seen = std::collections::HashSet::new();
+    for id in request.groups.iter().flat_map(|g| &g.line_item_ids) {
+        if !order.item_list.iter().any(|item| &item.id == id) {
+            return Err(AppError::BadRequest(format!(
+                "line item {} is not part of order {}",
+                id, order_id
+            )));
+        }
+        if !seen.insert(id.as_str()) {
+            return Err(AppError::BadRequest(format!("line item {} is in two groups", id)));
+        }
+    }
+    if request.groups.iter().any(|g| g.line_item_ids.is_empty()) {
+        return Err(AppError::BadRequest("groups must not be empty".to_string()));
+    }
+    if let Some(missing) = order.item_list.iter().find(|item| !seen.contains(item.id.as_str())) {
+        return Err(AppError::BadRequest(format!("line item {} is in no group", missing.id)));
+    }
+
+    let split = request
+        .groups
+        .into_iter()
+        .enumerate()
+        .fold(SplitOrderRequest::new(), |split, (i, group)| {
+            split.with_group((i + 1).to_string(), group.line_item_ids)
+        });
+    let (client, token_info) = fulfillment_client(&state).await?;
+    let response = client
+        .split_order(&token_info.access_token, None, &order_id, &split)
+        .await?;
+    let tracked = state
+        .packages
+        .record_split(&order_id, &response.split_packages)
+        .await?;
+    info!("Split order {} into {} packages", order_id, tracked.len());
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "order_id": order_id,
+        "packages": tracked
+    })))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct CombinableQuery {
+    /// Default 20, at most 50
+    page_size: Option<i32>,
+    /// `next_page_token` of the previous page
+    page_token: Option<String>,
+}
+
+/// Packages TikTok would let ship together, with the orders in each
+#[utoipa::path(
+    get,
+    path = "/packages/combinable",
+    tag = "fulfillment",
+    params(CombinableQuery),
+    responses(
+        (status = 200, body = api_docs::CombinablePackagesResponse),
+        (status = 401, body = api_docs::ErrorResponse)
+    )
+)]
+async fn list_combinable_packages_handler(
+    State(state): State<AppState>,
+    Query(query): Query<CombinableQuery>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let (client, token_info) = fulfillment_client(&state).await?;
+    let page = client
+        .search_combinable_packages(
+            &token_info.access_token,
+            None,
+            query.page_size.unwrap_or(20),
+            query.page_token.as_deref(),
+        )
+        .await?;
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "combinable_packages": page.combinable_packages,
+        "total_count": page.total_count,
+        "next_page_token": page.next_page_token
+    })))
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+struct CombineRequest {
+    /// `id` of an entry from `GET /packages/combinable`
+    package_id: String,
+    /// Orders to ship together; at least two of the package's orders
+    order_ids: Vec<String>,
+}
+
+/// Combine orders into one shipment
+#[utoipa::path(
+    post,
+    path = "/packages/combine",
+    tag = "fulfillment",
+    request_body = CombineRequest,
+    responses(
+        (status = 200, body = api_docs::CombinePackagesResponse),
+        (status = 400, body = api_docs::ErrorResponse),
+        (status = 404, body = api_docs::ErrorResponse)
+    )
+)]
+async fn combine_packages_handler(
+    State(state): State<AppState>,
+    payload: Result<Json<CombineRequest>, JsonRejection>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let Json(request) = payload.map_err(|e| AppError::BadRequest(e.body_text()))?;
+    let mut order_ids = request.order_ids;
+    order_ids.sort();
+    order_ids.dedup();
+    if order_ids.len() < 2 {
+        return Err(AppError::BadRequest("combining needs at least two orders".to_string()));
+    }
+    for order_id in &order_ids {
+        require_order(&state, order_id).await?;
+    }
+
+    let (client, token_info) = fulfillment_client(&state).await?;
+    let response = client
+        .combine_packages(
+            &token_info.access_token,
+            None,
+            &CombinePackagesRequest::new().with_package(request.package_id, order_ids),
+        )
+        .await?;
+    if response.packages.is_empty() {
+        let reason = response
+            .errors
+            .first()
+            .map(|e| e.message.clone())
+            .unwrap_or_else(|| "no package was created".to_string());
+        return Err(AppError::BadRequest(format!("TikTok did not combine the orders: {}", reason)));
+    }
+    state.packages.record_combine(&response.packages).await?;
+    info!("Combined orders into {} packages", response.packages.len());
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "packages": response.packages,
+        "errors": response
+            .errors
+            .iter()
+            .map(|e| e.message.clone())
+            .collect::<Vec<_>>()
+    })))
+}
+
+/// The cached logistics snapshot, fetched from TikTok when missing, older
+/// than `LOGISTICS_CACHE_HOURS` or when `refresh` is set
+async fn logistics_snapshot(state: &AppState, refresh: bool) -> Result<LogisticsSnapshot, AppError> {
+    if !refresh {
+        if let Some(snapshot) = state.logistics.get().await? {
+            let age = chrono::Utc::now().timestamp() - snapshot.fetched_at;
+            if age < state.config.logistics_cache_hours * 3600 {
+                return Ok(snapshot);
+            }
+        }
+    }
+
+    let token_info = load_valid_token(&state.oauth_client).await?;
+    let client = resolve_shop_target(&state.shops, &state.config, &token_info.access_token)
+        .await
+        .logistics_client(&state.config);
+    let snapshot = state.logistics.refresh(&client, &token_info.access_token).await?;
+    info!("Cached logistics setup of {} warehouses", snapshot.warehouses.len());
+    Ok(snapshot)
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct LogisticsQuery {
+    /// Fetch from TikTok even if the cached copy is recent
+    #[serde(default)]
+    refresh: bool,
+}
+
+/// The shop's warehouses, each with its delivery options and their shipping providers
+#[utoipa::path(
+    get,
+    path = "/logistics",
+    tag = "fulfillment",
+    params(LogisticsQuery),
+    responses(
+        (status = 200, body = api_docs::LogisticsResponse),
+        (status = 401, body = api_docs::ErrorResponse)
+    )
+)]
+async fn get_logistics_handler(
+    State(state): State<AppState>,
+    Query(query): Query<LogisticsQuery>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let snapshot = logistics_snapshot(&state, query.refresh).await?;
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "warehouses": snapshot.warehouses,
+        "fetched_at": snapshot.fetched_at
+    })))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct ValidateShippingQuery {
+    /// Defaults to the preferred warehouse of `order_id`
+    warehouse_id: Option<String>,
+    /// Order whose `WAREHOUSE_ROUTES` assignment to check
+    order_id: Option<String>,
+    shipping_provider_id: Option<String>,
+}
+
+/// Check a warehouse and shipping provider against the cached logistics
+/// setup, e.g. an order's preferred warehouse before shipping it
+#[utoipa::path(
+    get,
+    path = "/logistics/validate",
+    tag = "fulfillment",
+    params(ValidateShippingQuery),
+    responses(
+        (status = 200, body = api_docs::ValidateShippingResponse),
+        (status = 400, body = api_docs::ErrorResponse),
+        (status = 401, body = api_docs::ErrorResponse),
+        (status = 404, body = api_docs::ErrorResponse)
+    )
+)]
+async fn validate_shipping_handler(
+    State(state): State<AppState>,
+    Query(query): Query<ValidateShippingQuery>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let warehouse_id = match (query.warehouse_id, query.order_id) {
+        (Some(warehouse_id), _) => warehouse_id,
+        (None, Some(order_id)) => {
+            require_order(&state, &order_id).await?;
+            state
+                .warehouses
+                .get(&order_id)
+                .await?
+                .map(|assignment| assignment.warehouse_id)
+                .ok_or_else(|| {
+                    AppError::NotFound(format!("preferred warehouse of order {}", order_id))
+                })?
+        }
+        (None, None) => {
+            return Err(AppError::BadRequest("pass warehouse_id or order_id".to_string()));
+        }
+    };
+    let snapshot = logistics_snapshot(&state, false).await?;
+    let error = snapshot
+        .validate(&warehouse_id, query.shipping_provider_id.as_deref())
+        .err();
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "warehouse_id": warehouse_id,
+        "valid": error.is_none(),
+        "error": error
+    })))
+}
+
+/// Unified communication timeline for an order: buyer note, CS messages, outbound emails;
+/// `events` adds TikTok milestones, processing transitions and staff notes
+#[utoipa::path(
+    get,
+    path = "/orders/{id}/timeline",
+    tag = "orders",
+    params(("id" = String, Path, description = "TikTok order id")),
+    responses(
+        (status = 200, body = api_docs::TimelineResponse),
+        (status = 404, body = api_docs::ErrorResponse)
+    )
+)]
+async fn get_order_timeline_handler(
+    State(state): State<AppState>,
+    Path(order_id): Path<String>,
+    access: PiiAccess,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let order = state
+        .db
+        .get_order_by_id(&order_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("order {}", order_id)))?;
+    let order = access.order(&state.db, order);
+
+    let recorded = state.communications.get_for_order(&order_id).await?;
+    let timeline = communications::build_timeline(&order, recorded);
+    let processing = state.processing.get(&order_id).await?;
+    let notes = state.notes.list(&order_id).await?;
+    let events = order_timeline::build(&order, processing.as_ref(), &notes, &timeline);
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "order_id": order_id,
+        "count": timeline.len(),
+        "timeline": timeline,
+        "events": events
+    })))
+}
+
+/// Internal processing state of an order (received -> validated -> fulfilling
+/// -> fulfilled, or failed) and every transition it went through
+#[utoipa::path(
+    get,
+    path = "/orders/{id}/processing",
+    tag = "orders",
+    params(("id" = String, Path, description = "TikTok order id")),
+    responses(
+        (status = 200, body = api_docs::OrderProcessingResponse),
+        (status = 404, body = api_docs::ErrorResponse)
+    )
+)]
+async fn get_order_processing_handler(
+    State(state): State<AppState>,
+    Path(order_id): Path<String>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let processing = state
+        .processing
+        .get(&order_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("order {}", order_id)))?;
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "processing": processing
+    })))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct LookupQuery {
+    /// Recipient phone number or its last digits (at least 4); formatting is ignored
+    phone: Option<String>,
+    /// Tracking number; falls back to a partial match when nothing matches exactly
+    tracking: Option<String>,
+    /// Default 20
+    limit: Option<i64>,
+}
+
+/// Find orders by recipient phone number or tracking number, for support agents
+#[utoipa::path(
+    get,
+    path = "/lookup",
+    tag = "orders",
+    params(LookupQuery),
+    responses(
+        (status = 200, body = api_docs::LookupResponse),
+        (status = 400, body = api_docs::ErrorResponse)
+    )
+)]
+async fn lookup_handler(
+    State(state): State<AppState>,
+    Query(query): Query<LookupQuery>,
+    access: PiiAccess,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let limit = query.limit.unwrap_or(20).clamp(1, 100);
+    let (matched, orders) = match (query.phone.as_deref(), query.tracking.as_deref()) {
+        (Some(phone), None) => {
+            let digits: String = phone.chars().filter(char::is_ascii_digit).collect();
+            if digits.len() < lookup::MIN_PHONE_DIGITS {
+                return Err(AppError::BadRequest(format!(
+                    "phone needs at least {} digits",
+                    lookup::MIN_PHONE_DIGITS
+                )));
+            }
+            let orders = state.db.find_orders_by_phone(&digits, limit as usize).await?;
+            // Fewer digits than a full number only match its end
+            let matched = if digits.len() > lookup::MIN_PHONE_DIGITS { "exact" } else { "partial" };
+            (matched, orders)
+        }
+        (None, Some(tracking)) => {
+            let tracking = lookup::normalize_tracking(tracking);
+            if tracking.is_empty() {
+                return Err(AppError::BadRequest("tracking must not be empty".to_string()));
+            }
+            let exact = state.db.find_orders_by_tracking(&tracking, false, limit).await?;
+            if !exact.is_empty() || tracking.len() < lookup::MIN_PARTIAL_TRACKING_CHARS {
+                ("exact", exact)
+            } else {
+                ("partial", state.db.find_orders_by_tracking(&tracking, true, limit).await?)
+            }
+        }
+        _ => {
+            return Err(AppError::BadRequest("pass exactly one of phone or tracking".to_string()));
+        }
+    };
+
+    let carriers = state.carriers.directory().await?;
+    let orders: Vec<_> = orders
+        .into_iter()
+        .map(|o| carriers.track(access.order(&state.db, o)))
+        .collect();
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "match": matched,
+        "count": orders.len(),
+        "orders": orders
+    })))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct CustomerOrdersQuery {
+    limit: Option<i64>,
+    /// Also list archived orders
+    #[serde(default)]
+    include_archived: bool,
+}
+
+/// A buyer's order history, newest first
+#[utoipa::path(
+    get,
+    path = "/customers/{id}/orders",
+    tag = "customers",
+    params(
+        ("id" = String, Path, description = "Customer id"),
+        CustomerOrdersQuery
+    ),
+    responses(
+        (status = 200, body = api_docs::CustomerOrdersResponse),
+        (status = 404, body = api_docs::ErrorResponse)
+    )
+)]
+async fn get_customer_orders_handler(
+    State(state): State<AppState>,
+    Path(customer_id): Path<String>,
+    Query(query): Query<CustomerOrdersQuery>,
+    access: PiiAccess,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let mut customer = state
+        .customers
+        .get(&customer_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("customer {}", customer_id)))?;
+    customer.name = access.value(customer.name);
+
+    let orders = state
+        .db
+        .get_orders_by_customer(&customer_id, query.limit.unwrap_or(100), query.include_archived)
+        .await?;
+    let carriers = state.carriers.directory().await?;
+    let orders: Vec<_> = orders
+        .into_iter()
+        .map(|o| carriers.track(access.order(&state.db, o)))
+        .collect();
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "customer": customer,
+        "count": orders.len(),
+        "orders": orders
+    })))
+}
+
+/// Erase a buyer's personal data: names, phones, emails and street addresses
+/// are scrubbed from all their orders, now and on later syncs, and their
+/// cached shipping labels are deleted. Order totals, items and the customer's
+/// order count are kept.
+#[utoipa::path(
+    delete,
+    path = "/customers/{id}/data",
+    tag = "customers",
+    params(("id" = String, Path, description = "Customer id")),
+    responses(
+        (status = 200, body = api_docs::CustomerErasureResponse),
+        (status = 401, body = api_docs::ErrorResponse),
+        (status = 404, body = api_docs::ErrorResponse)
+    )
+)]
+async fn erase_customer_data_handler(
+    State(state): State<AppState>,
+    Path(customer_id): Path<String>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let report = state
+        .eraser
+        .erase_customer(&customer_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("customer {}", customer_id)))?;
+    info!(
+        "Erased the personal data of customer {} from {} orders and deleted {} documents",
+        customer_id,
+        report.orders_scrubbed,
+        report.documents_deleted.len()
+    );
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "customer_id": customer_id,
+        "orders_scrubbed": report.orders_scrubbed,
+        "documents_deleted": report.documents_deleted
+    })))
+}
+
+/// Carriers used to link orders to their tracking pages
+#[utoipa::path(
+    get,
+    path = "/carriers",
+    tag = "carriers",
+    responses((status = 200, body = api_docs::CarriersResponse))
+)]
+async fn list_carriers_handler(
+    State(state): State<AppState>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let carriers = state.carriers.list().await?;
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "count": carriers.len(),
+        "carriers": carriers
+    })))
+}
+
+/// Create or replace the carrier for a shipping provider.
+///
+/// `key` is TikTok's `shipping_provider_id`, or the provider name for orders
+/// that only carry a name; ids are tried first.
+#[utoipa::path(
+    put,
+    path = "/carriers/{key}",
+    tag = "carriers",
+    params(("key" = String, Path, description = "Shipping provider id or name")),
+    request_body = CarrierUpdate,
+    responses(
+        (status = 200, body = api_docs::CarrierResponse),
+        (status = 400, body = api_docs::ErrorResponse)
+    )
+)]
+async fn put_carrier_handler(
+    State(state): State<AppState>,
+    Path(key): Path<String>,
+    payload: Result<Json<CarrierUpdate>, JsonRejection>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let Json(update) = payload.map_err(|e| AppError::BadRequest(e.body_text()))?;
+    if key.trim().is_empty() {
+        return Err(AppError::BadRequest("key must not be empty".to_string()));
+    }
+    update.validate().map_err(AppError::BadRequest)?;
+
+    let carrier = state.carriers.put(&key, &update).await?;
+    info!("Updated carrier {} ({})", carrier.key, carrier.name);
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "carrier": carrier
+    })))
+}
+
+/// Remove a carrier; its orders no longer get a `tracking_url`
+#[utoipa::path(
+    delete,
+    path = "/carriers/{key}",
+    tag = "carriers",
+    params(("key" = String, Path, description = "Shipping provider id or name")),
+    responses(
+        (status = 200, body = api_docs::DeleteCarrierResponse),
+        (status = 404, body = api_docs::ErrorResponse)
+    )
+)]
+async fn delete_carrier_handler(
+    State(state): State<AppState>,
+    Path(key): Path<String>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    if !state.carriers.delete(&key).await? {
+        return Err(AppError::NotFound(format!("carrier {}", key)));
+    }
+    info!("Deleted carrier {}", key);
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "key": key
+    })))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct SkuStatsQuery {
+    /// Days to aggregate, e.g. `7d` (default) or `4w`
+    window: Option<String>,
+}
+
+/// Units sold per seller SKU over a window, with low-stock flags from the last inventory check
+#[utoipa::path(
+    get,
+    path = "/stats/skus",
+    tag = "stats",
+    params(SkuStatsQuery),
+    responses(
+        (status = 200, body = api_docs::SkuStatsResponse),
+        (status = 400, body = api_docs::ErrorResponse)
+    )
+)]
+async fn sku_stats_handler(
+    State(state): State<AppState>,
+    Query(query): Query<SkuStatsQuery>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let window_days = sku_stats::parse_window(query.window.as_deref().unwrap_or("7d"))
+        .map_err(AppError::BadRequest)?;
+
+    let load = || {
+        state
+            .sku_stats
+            .summary(window_days, state.config.low_stock_cover_days)
+    };
+    let skus = match state.db.cache() {
+        Some(cache) => {
+            cache
+                .stats_or_load(&format!("skus:{}", window_days), load)
+                .await?
+        }
+        None => load().await?,
+    };
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "window_days": window_days,
+        "count": skus.len(),
+        "skus": skus
+    })))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct TimeseriesQuery {
+    /// `revenue` (default), `orders` or `aov`
+    metric: Option<String>,
+    /// `day` (default), `week` or `month`
+    granularity: Option<String>,
+    /// First day, `YYYY-MM-DD` (default: 30 days before `to`)
+    from: Option<String>,
+    /// Last day, `YYYY-MM-DD` (default: today in `REPORT_TIMEZONE`)
+    to: Option<String>,
+    /// Only this shop; all shops by default
+    shop_id: Option<String>,
+}
+
+fn parse_day(name: &str, value: &str) -> Result<chrono::NaiveDate, AppError> {
+    chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map_err(|_| AppError::BadRequest(format!("{} must be a YYYY-MM-DD date", name)))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct CancellationStatsQuery {
+    /// First day, `YYYY-MM-DD` (default: 30 days before `to`)
+    from: Option<String>,
+    /// Last day, `YYYY-MM-DD` (default: today in `REPORT_TIMEZONE`)
+    to: Option<String>,
+}
+
+/// Orders cancelled in a range of days, counted by reason and by who
+/// cancelled them
+#[utoipa::path(
+    get,
+    path = "/stats/cancellations",
+    tag = "stats",
+    params(CancellationStatsQuery),
+    responses(
+        (status = 200, body = api_docs::CancellationStatsResponse),
+        (status = 400, body = api_docs::ErrorResponse)
+    )
+)]
+async fn cancellation_stats_handler(
+    State(state): State<AppState>,
+    Query(query): Query<CancellationStatsQuery>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let timezone = state.config.report_timezone;
+    let to = match query.to.as_deref() {
+        Some(to) => parse_day("to", to)?,
+        None => reporting::today(timezone),
+    };
+    let from = match query.from.as_deref() {
+        Some(from) => parse_day("from", from)?,
+        None => to - chrono::Duration::days(29),
+    };
+    if from > to {
+        return Err(AppError::BadRequest("from must not be after to".to_string()));
+    }
+    if (to - from).num_days() >= cancellations::MAX_REPORT_DAYS {
+        return Err(AppError::BadRequest(format!(
+            "range must not exceed {} days",
+            cancellations::MAX_REPORT_DAYS
+        )));
+    }
+
+    let orders = state
+        .db
+        .get_orders_cancelled_between(
+            reporting::day_start(from, timezone),
+            reporting::day_start(to + chrono::Duration::days(1), timezone),
+        )
+        .await?;
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "from": from.to_string(),
+        "to": to.to_string(),
+        "breakdown": cancellations::breakdown(&orders)
+    })))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct DiscountStatsQuery {
+    /// Only this campaign from `PROMO_CAMPAIGNS`; `from`/`to` default to its window
+    campaign: Option<String>,
+    /// First day, `YYYY-MM-DD` (default: 30 days before `to`)
+    from: Option<String>,
+    /// Last day, `YYYY-MM-DD` (default: today in `REPORT_TIMEZONE`)
+    to: Option<String>,
+}
+
+/// Seller- and platform-funded discounts of paid orders created in a range of
+/// days, per `PROMO_CAMPAIGNS` window and currency
+#[utoipa::path(
+    get,
+    path = "/stats/discounts",
+    tag = "stats",
+    params(DiscountStatsQuery),
+    responses(
+        (status = 200, body = api_docs::DiscountStatsResponse),
+        (status = 400, body = api_docs::ErrorResponse)
+    )
+)]
+async fn discount_stats_handler(
+    State(state): State<AppState>,
+    Query(query): Query<DiscountStatsQuery>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let timezone = state.config.report_timezone;
+    let campaigns: Vec<promotions::Campaign> = match query.campaign.as_deref() {
+        Some(name) => {
+            let campaign = state
+                .config
+                .promo_campaigns
+                .iter()
+                .find(|c| c.name == name)
+                .ok_or_else(|| AppError::BadRequest(format!("unknown campaign {:?}", name)))?;
+            vec![campaign.clone()]
+        }
+        None => state.config.promo_campaigns.clone(),
+    };
+    let window = query.campaign.as_ref().and(campaigns.first());
+
+    let to = match (query.to.as_deref(), window) {
+        (Some(to), _) => parse_day("to", to)?,
+        (None, Some(campaign)) => campaign.end,
+        (None, None) => reporting::today(timezone),
+    };
+    let from = match (query.from.as_deref(), window) {
+        (Some(from), _) => parse_day("from", from)?,
+        (None, Some(campaign)) => campaign.start,
+        (None, None) => to - chrono::Duration::days(29),
+    };
+    if from > to {
+        return Err(AppError::BadRequest("from must not be after to".to_string()));
+    }
+    if (to - from).num_days() >= promotions::MAX_REPORT_DAYS {
+        return Err(AppError::BadRequest(format!(
+            "range must not exceed {} days",
+            promotions::MAX_REPORT_DAYS
+        )));
+    }
+
+    let orders = state
+        .db
+        .get_paid_orders_created_between(
+            reporting::day_start(from, timezone),
+            reporting::day_start(to + chrono::Duration::days(1), timezone),
+        )
+        .await?;
+    let mut campaigns_report = promotions::discount_report(&orders, &campaigns, timezone);
+    // With one campaign asked for, orders outside it are not part of the answer
+    if query.campaign.is_some() {
+        campaigns_report.retain(|totals| totals.campaign.is_some());
+    }
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "from": from.to_string(),
+        "to": to.to_string(),
+        "campaigns": campaigns_report
+    })))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct ShopPerformanceQuery {
+    /// First day, `YYYY-MM-DD` (default: 30 days before `to`)
+    from: Option<String>,
+    /// Last day, `YYYY-MM-DD` (default: yesterday in `REPORT_TIMEZONE`)
+    to: Option<String>,
+}
+
+/// Days from `from` to `to` of a shop performance query, yesterday and the 29
+/// days before it by default
+fn shop_performance_range(
+    query: &ShopPerformanceQuery,
+    timezone: chrono_tz::Tz,
+) -> Result<(chrono::NaiveDate, chrono::NaiveDate), AppError> {
+    let to = match query.to.as_deref() {
+        Some(to) => parse_day("to", to)?,
+        None => reporting::today(timezone) - chrono::Duration::days(1),
+    };
+    let from = match query.from.as_deref() {
+        Some(from) => parse_day("from", from)?,
+        None => to - chrono::Duration::days(29),
+    };
+    if from > to {
+        return Err(AppError::BadRequest("from must not be after to".to_string()));
+    }
+    if (to - from).num_days() >= shop_performance::MAX_RANGE_DAYS {
+        return Err(AppError::BadRequest(format!(
+            "range must not exceed {} days",
+            shop_performance::MAX_RANGE_DAYS
+        )));
+    }
+    Ok((from, to))
+}
+
+/// Daily shop metrics snapshotted from TikTok's Data Analytics API (GMV,
+/// orders, buyers, traffic), each next to the stored orders' totals that day
+#[utoipa::path(
+    get,
+    path = "/stats/shop-performance",
+    tag = "stats",
+    params(ShopPerformanceQuery),
+    responses(
+        (status = 200, body = api_docs::ShopPerformanceResponse),
+        (status = 400, body = api_docs::ErrorResponse)
+    )
+)]
+async fn shop_performance_handler(
+    State(state): State<AppState>,
+    Query(query): Query<ShopPerformanceQuery>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let (from, to) = shop_performance_range(&query, state.config.report_timezone)?;
+    let shop_id = state.shop_performance_sync.shop_id().await?;
+    let days = state.shop_performance.list(&shop_id, from, to).await?;
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "from": from.to_string(),
+        "to": to.to_string(),
+        "days": days
+    })))
+}
+
+/// Snapshot shop performance for a range of days now, e.g. to fill in the
+/// days before the scheduled snapshots started
+#[utoipa::path(
+    post,
+    path = "/admin/shop-performance/sync",
+    tag = "admin",
+    params(ShopPerformanceQuery),
+    responses(
+        (status = 200, body = api_docs::ShopPerformanceSyncResponse),
+        (status = 400, body = api_docs::ErrorResponse),
+        (status = 401, body = api_docs::ErrorResponse)
+    )
+)]
+async fn sync_shop_performance_handler(
+    State(state): State<AppState>,
+    Query(query): Query<ShopPerformanceQuery>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let (from, to) = shop_performance_range(&query, state.config.report_timezone)?;
+    match state.shop_performance_sync.snapshot(from, to).await? {
+        SnapshotOutcome::Stored(days) => Ok(Json(serde_json::json!({
+            "success": true,
+            "from": from.to_string(),
+            "to": to.to_string(),
+            "days": days
+        }))),
+        SnapshotOutcome::NotAuthorized(reason) => Err(AppError::Unauthorized(format!(
+            "not authorized for shop analytics: {}",
+            reason
+        ))),
+    }
+}
+
+/// Revenue, order count or average order value per day, week or month from
+/// the nightly `daily_stats` rollup, one point per period and currency
+#[utoipa::path(
+    get,
+    path = "/stats/timeseries",
+    tag = "stats",
+    params(TimeseriesQuery),
+    responses(
+        (status = 200, body = api_docs::TimeseriesResponse),
+        (status = 400, body = api_docs::ErrorResponse)
+    )
+)]
+async fn stats_timeseries_handler(
+    State(state): State<AppState>,
+    Query(query): Query<TimeseriesQuery>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let metric: daily_stats::Metric = query
+        .metric
+        .as_deref()
+        .unwrap_or("revenue")
+        .parse()
+        .map_err(AppError::BadRequest)?;
+    let granularity: daily_stats::Granularity = query
+        .granularity
+        .as_deref()
+        .unwrap_or("day")
+        .parse()
+        .map_err(AppError::BadRequest)?;
+    let to = match query.to.as_deref() {
+        Some(to) => parse_day("to", to)?,
+        None => reporting::today(state.config.report_timezone),
+    };
+    let from = match query.from.as_deref() {
+        Some(from) => parse_day("from", from)?,
+        None => to - chrono::Duration::days(daily_stats::DEFAULT_RANGE_DAYS - 1),
+    };
+    if from > to {
+        return Err(AppError::BadRequest("from must not be after to".to_string()));
+    }
+    if (to - from).num_days() >= daily_stats::MAX_RANGE_DAYS {
+        return Err(AppError::BadRequest(format!(
+            "range must not exceed {} days",
+            daily_stats::MAX_RANGE_DAYS
+        )));
+    }
+    let shop_id = query.shop_id.as_deref();
+
+    let load = || state.daily_stats.timeseries(metric, granularity, from, to, shop_id);
+    let points = match state.db.cache() {
+        Some(cache) => {
+            let name = format!(
+                "timeseries:{:?}:{:?}:{}:{}:{}",
+                metric,
+                granularity,
+                from,
+                to,
+                shop_id.unwrap_or("*")
+            );
+            cache.stats_or_load(&name, load).await?
+        }
+        None => load().await?,
+    };
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "metric": metric,
+        "granularity": granularity,
+        "from": from.to_string(),
+        "to": to.to_string(),
+        "points": points
+    })))
+}
+
+/// Exchange rates into `BASE_CURRENCY`; `FX_RATES` entries win over fetched ones
+#[utoipa::path(
+    get,
+    path = "/fx/rates",
+    tag = "stats",
+    responses((status = 200, body = api_docs::FxRatesResponse))
+)]
+async fn fx_rates_handler(
+    State(state): State<AppState>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let rates = match &state.config.base_currency {
+        Some(_) => state.fx.rates().await?,
+        None => Vec::new(),
+    };
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "base_currency": state.config.base_currency,
+        "count": rates.len(),
+        "rates": rates
+    })))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct AccountingExportQuery {
+    /// `quickbooks` or `xero`
+    format: String,
+    /// `invoices` (default) or `payouts`
+    kind: Option<String>,
+    /// First day, `YYYY-MM-DD` (default: first day of the month of `to`)
+    from: Option<String>,
+    /// Last day, `YYYY-MM-DD` (default: today in `REPORT_TIMEZONE`)
+    to: Option<String>,
+}
+
+/// Completed orders as sales invoices, or paid TikTok payouts as bank
+/// statement lines, in a QuickBooks or Xero import CSV. Accounts and items
+/// come from `ACCOUNTING_MAPPING`. Invoices carry TikTok's fees once the
+/// order's settlement is known (see `FETCH_SETTLEMENTS`); payouts are
+/// fetched from TikTok. Orders and payouts with an unreadable amount are
+/// left out and logged, and text cells that look like formulas are prefixed
+/// with `'`.
+#[utoipa::path(
+    get,
+    path = "/exports/accounting",
+    tag = "exports",
+    params(AccountingExportQuery),
+    responses(
+        (status = 200, description = "CSV file", content_type = "text/csv", body = String),
+        (status = 400, body = api_docs::ErrorResponse)
+    )
+)]
+async fn accounting_export_handler(
+    State(state): State<AppState>,
+    Query(query): Query<AccountingExportQuery>,
+) -> Result<Response, AppError> {
+    let format: ExportFormat = query.format.parse().map_err(AppError::BadRequest)?;
+    let kind: ExportKind = query
+        .kind
+        .as_deref()
+        .unwrap_or("invoices")
+        .parse()
+        .map_err(AppError::BadRequest)?;
+    let timezone = state.config.report_timezone;
+    let to = match query.to.as_deref() {
+        Some(to) => parse_day("to", to)?,
+        None => reporting::today(timezone),
+    };
+    let from = match query.from.as_deref() {
+        Some(from) => parse_day("from", from)?,
+        None => chrono::Datelike::with_day(&to, 1).unwrap_or(to),
+    };
+    if from > to {
+        return Err(AppError::BadRequest("from must not be after to".to_string()));
+    }
+    if (to - from).num_days() >= accounting::MAX_RANGE_DAYS {
+        return Err(AppError::BadRequest(format!(
+            "range must not exceed {} days",
+            accounting::MAX_RANGE_DAYS
+        )));
+    }
+    let start = reporting::day_start(from, timezone);
+    let end = reporting::day_start(to + chrono::Duration::days(1), timezone);
+    let mapping = &state.config.accounting_mapping;
+
+    let csv = match kind {
+        ExportKind::Invoices => {
+            let orders = state.db.get_orders_created_between("COMPLETED", start, end).await?;
+            let ids: Vec<String> = orders.iter().map(|o| o.id.clone()).collect();
+            let mut stored = state.settlements.get_many(&ids).await?;
+            if state.config.fetch_settlements {
+                let fetcher = SettlementFetcher::new(
+                    state.settlements.clone(),
+                    state.shops.clone(),
+                    state.config.clone(),
+                );
+                for id in &ids {
+                    if stored.get(id).is_some_and(|s| s.settled) {
+                        continue;
+                    }
+                    match fetcher.fetch(id).await {
+                        Ok(settlement) => {
+                            stored.insert(id.clone(), settlement);
+                        }
+                        Err(e) => warn!("Failed to fetch settlement of order {}: {}", id, e),
+                    }
+                }
+            }
+
+            let mut invoices = Vec::with_capacity(orders.len());
+            for order in orders {
+                let external_ref =
+                    state.external_refs.get(&order.id).await?.map(|r| r.external_ref);
+                let tax = match state.price_details.get(&order.id).await? {
+                    Some(stored) => stored.detail.tax_amount,
+                    None => order.payment.as_ref().and_then(|p| p.tax.clone()),
+                }
+                .and_then(|tax| tax.trim().parse::<f64>().ok());
+                let fees = stored.get(&order.id).filter(|s| s.settled).and_then(|s| s.fees());
+                invoices.push(InvoiceOrder {
+                    order,
+                    external_ref,
+                    tax,
+                    fees,
+                });
+            }
+            accounting::invoices_csv(format, mapping, timezone, &invoices)
+        }
+        ExportKind::Payouts => {
+            let token_info = load_valid_token(&state.oauth_client).await?;
+            let statements =
+                resolve_shop_target(&state.shops, &state.config, &token_info.access_token)
+                    .await
+                    .finance_client(&state.config)
+                    .get_all_statements(&token_info.access_token, None, start, end)
+                    .await?;
+            accounting::payouts_csv(format, mapping, timezone, &statements)
+        }
+    };
+
+    let file_name = accounting::file_name(format, kind, &from.to_string(), &to.to_string());
+    let headers = [
+        (header::CONTENT_TYPE, "text/csv; charset=utf-8".to_string()),
+        (
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}\"", file_name),
+        ),
+    ];
+    Ok((headers, csv).into_response())
+}
+
+/// Scheduled order sync. While the TikTok API is unhealthy, runs are skipped
+/// so syncs back off exponentially up to `SYNC_MAX_BACKOFF_SECS`.
+struct OrderSyncTask {
+    db: Arc<Database>,
+    /// Live, so the backoff follows a reloaded `SYNC_SCHEDULE`
+    config: LiveConfig,
+    job_queue: JobQueue,
+    shops: ShopRegistry,
+    oauth_client: TikTokShopOAuth,
+    api_health: Arc<ApiHealth>,
+    deauthorizer: Deauthorizer,
+    settings: ShopSettingsStore,
+    /// Start of the last sync that reached TikTok, for per-shop intervals
+    last_sync: std::sync::Mutex<Option<Instant>>,
+    backoff: std::sync::Mutex<SyncBackoff>,
+}
+
+/// Sync delay while TikTok is healthy, and the most it backs off to
+fn sync_backoff_bounds(config: &Config) -> (Duration, Duration) {
+    let base = scheduler::period(&config.sync_schedule).unwrap_or(Duration::from_secs(3600));
+    (base, Duration::from_secs(config.sync_max_backoff_secs))
+}
+
+struct SyncBackoff {
+    interval: AdaptiveInterval,
+    resume_at: Option<Instant>,
+}
+
+impl OrderSyncTask {
+    fn new(
+        db: Arc<Database>,
+        config: LiveConfig,
+        job_queue: JobQueue,
+        shops: ShopRegistry,
+        api_health: Arc<ApiHealth>,
+        deauthorizer: Deauthorizer,
+        settings: ShopSettingsStore,
+    ) -> Self {
+        let (base, max) = sync_backoff_bounds(&config.current());
+        let interval = AdaptiveInterval::new(base, max);
+
+        Self {
+            oauth_client: config.current().oauth_client(),
+            db,
+            config,
+            job_queue,
+            shops,
+            api_health,
+            deauthorizer,
+            settings,
+            last_sync: std::sync::Mutex::new(None),
+            backoff: std::sync::Mutex::new(SyncBackoff {
+                interval,
+                resume_at: None,
+            }),
+        }
+    }
+}
+
+#[async_trait]
+impl ScheduledTask for OrderSyncTask {
+    async fn run(&self) -> Result<TaskRun, String> {
+        if let Some(skipped) = skip_if_disconnected(&self.deauthorizer).await? {
+            return Ok(skipped);
+        }
+        let started = Instant::now();
+        let shop_id = self.deauthorizer.shop_id().await.map_err(|e| e.to_string())?;
+        let settings = self.settings.get(&shop_id).await.map_err(|e| e.to_string())?;
+        if settings.sync_paused {
+            return Ok(TaskRun::Skipped(format!("syncing is paused for shop {:?}", shop_id)));
+        }
+        let last_sync = *self.last_sync.lock().unwrap();
+        if let (Some(interval), Some(last)) = (settings.sync_interval_secs, last_sync) {
+            // Allow for the scheduler waking slightly early
+            let due_in = Duration::from_secs(interval).saturating_sub(started - last);
+            if due_in > Duration::from_secs(1) {
+                return Ok(TaskRun::Skipped(format!(
+                    "shop {:?} syncs every {}s, next sync due in {}s",
+                    shop_id,
+                    interval,
+                    due_in.as_secs()
+                )));
+            }
+        }
+        if let Some(resume_at) = self.backoff.lock().unwrap().resume_at {
+            // Allow for the scheduler waking slightly early
+            let remaining = resume_at.saturating_duration_since(started);
+            if remaining > Duration::from_secs(1) {
+                return Ok(TaskRun::Skipped(format!(
+                    "backing off while TikTok is unhealthy, resuming in {}s",
+                    remaining.as_secs()
+                )));
+            }
+        }
+
+        let config = self.config.current();
+        let result = if self.api_health.allows_requests() {
+            *self.last_sync.lock().unwrap() = Some(started);
+            sync_orders_once(
+                &self.db,
+                &config,
+                &self.job_queue,
+                &self.shops,
+                &self.oauth_client,
+                &self.api_health,
+                &self.deauthorizer,
+            )
+            .await
+        } else {
+            Ok(TaskRun::Skipped("TikTok API circuit is open".to_string()))
+        };
+
+        let healthy = self.api_health.circuit_state() == CircuitState::Closed;
+        let mut backoff = self.backoff.lock().unwrap();
+        let (base, max) = sync_backoff_bounds(&config);
+        backoff.interval.set_bounds(base, max);
+        let was_backing_off = backoff.interval.unhealthy_runs() > 0;
+        let delay = backoff.interval.next_delay(healthy);
+
+        if !healthy {
+            backoff.resume_at = Some(started + delay);
+            let snapshot = self.api_health.snapshot();
+            warn!(
+                "TikTok API unhealthy (circuit {:?}, error rate {:.0}%), next sync in about {}s",
+                snapshot.circuit,
+                snapshot.error_rate * 100.0,
+                delay.as_secs()
+            );
+        } else {
+            backoff.resume_at = None;
+            if was_backing_off {
+                info!("TikTok API recovered, resuming the sync schedule");
+            }
+        }
+
+        result
+    }
+}
+
+/// Refreshes the stored access token before it expires, so requests don't pay for it
+struct TokenRefreshTask {
+    oauth_client: TikTokShopOAuth,
+    deauthorizer: Deauthorizer,
+}
+
+#[async_trait]
+impl ScheduledTask for TokenRefreshTask {
+    async fn run(&self) -> Result<TaskRun, String> {
+        let tokens = TokenManager::new(self.oauth_client.clone());
+        match tokens.token_refreshed_within(chrono::Duration::hours(1)).await {
+            Ok(_) => Ok(TaskRun::Done),
+            Err(AppError::NoTokenStored) => Ok(TaskRun::Skipped("no token stored".to_string())),
+            Err(e) => {
+                self.deauthorizer.check(&e).await;
+                Err(e.to_string())
+            }
+        }
+    }
+}
+
+/// Runs a task that calls TikTok only while the shop is connected, so a
+/// shop that de-authorized the app isn't polled until it authorizes it again
+struct WhileConnected<T> {
+    deauthorizer: Deauthorizer,
+    task: T,
+}
+
+#[async_trait]
+impl<T: ScheduledTask> ScheduledTask for WhileConnected<T> {
+    async fn run(&self) -> Result<TaskRun, String> {
+        match skip_if_disconnected(&self.deauthorizer).await? {
+            Some(skipped) => Ok(skipped),
+            None => self.task.run().await,
+        }
+    }
+}
+
+/// A skipped run while the token's shop is disconnected
+async fn skip_if_disconnected(deauthorizer: &Deauthorizer) -> Result<Option<TaskRun>, String> {
+    let disconnection = deauthorizer.disconnected().await.map_err(|e| e.to_string())?;
+    Ok(disconnection.map(|d| {
+        TaskRun::Skipped(format!(
+            "shop {:?} is disconnected ({}); store a new token to resume",
+            d.shop_id, d.reason
+        ))
+    }))
+}
+
+/// Archives orders that haven't been updated within the retention period
+struct ArchiveTask {
+    db: Arc<Database>,
+    retention_days: u32,
+}
+
+#[async_trait]
+impl ScheduledTask for ArchiveTask {
+    async fn run(&self) -> Result<TaskRun, String> {
+        let cutoff = chrono::Utc::now() - chrono::Duration::days(i64::from(self.retention_days));
+        let archived = self
+            .db
+            .archive_orders(cutoff.timestamp())
+            .await
+            .map_err(|e| e.to_string())?;
+
+        if archived > 0 {
+            info!("Archived {} orders not updated since {}", archived, cutoff.date_naive());
+        }
+        Ok(TaskRun::Done)
+    }
+}
+
+/// Scrubs buyer PII from orders past the retention period
+struct PiiRetentionTask {
+    eraser: PiiEraser,
+    retention_days: u32,
+}
+
+#[async_trait]
+impl ScheduledTask for PiiRetentionTask {
+    async fn run(&self) -> Result<TaskRun, String> {
+        let cutoff = chrono::Utc::now() - chrono::Duration::days(i64::from(self.retention_days));
+        let report = self
+            .eraser
+            .scrub_older_than(cutoff.timestamp())
+            .await
+            .map_err(|e| e.to_string())?;
+
+        if report.orders_scrubbed > 0 {
+            info!(
+                "Scrubbed buyer PII from {} orders not updated since {}",
+                report.orders_scrubbed,
+                cutoff.date_naive()
+            );
+        }
+        if !report.documents_deleted.is_empty() {
+            info!(
+                "Deleted {} cached documents of scrubbed orders",
+                report.documents_deleted.len()
+            );
+        }
+        Ok(TaskRun::Done)
+    }
+}
+
+/// Vacuums, analyzes and checkpoints the database, after pruning old API
+/// calls and expired OAuth states
+struct MaintenanceTask {
+    maintenance: MaintenanceStore,
+    api_calls: ApiCallStore,
+    oauth_states: Arc<dyn OAuthStateStore>,
+    api_call_retention_secs: i64,
+}
+
+#[async_trait]
+impl ScheduledTask for MaintenanceTask {
+    async fn run(&self) -> Result<TaskRun, String> {
+        let pruned = self
+            .api_calls
+            .prune(self.api_call_retention_secs)
+            .await
+            .map_err(|e| e.to_string())?;
+        if pruned > 0 {
+            info!("Pruned {} recorded API calls", pruned);
+        }
+        let purged = self
+            .oauth_states
+            .purge_expired()
+            .await
+            .map_err(|e| e.to_string())?;
+        if purged > 0 {
+            info!("Purged {} expired OAuth states", purged);
+        }
+        let run = self.maintenance.run().await.map_err(|e| e.to_string())?;
+        info!(
+            "Database maintenance ({} vacuum) freed {} pages in {}ms",
+            run.vacuum, run.freed_pages, run.duration_ms
+        );
+        Ok(TaskRun::Done)
+    }
+}
+
+/// Alerts on orders about to miss (or past) their fulfillment deadlines
+struct SlaCheckTask {
+    monitor: SlaMonitor,
+}
+
+#[async_trait]
+impl ScheduledTask for SlaCheckTask {
+    async fn run(&self) -> Result<TaskRun, String> {
+        let sent = self.monitor.check().await.map_err(|e| e.to_string())?;
+        if sent > 0 {
+            info!("SLA check sent {} alerts", sent);
+        }
+        Ok(TaskRun::Done)
+    }
+}
+
+/// Sends the daily digest of orders past their shipping deadline
+struct LateDigestTask {
+    db: Arc<Database>,
+    events: EventBus,
+    shops: ShopRegistry,
+    config: Config,
+}
+
+#[async_trait]
+impl ScheduledTask for LateDigestTask {
+    async fn run(&self) -> Result<TaskRun, String> {
+        let late = sla::late_orders(&self.db, chrono::Utc::now().timestamp(), 0)
+            .await
+            .map_err(|e| e.to_string())?;
+        // Stored orders all belong to the rollup shop
+        let shop_id = self.shops.configured_shop_id(&self.config)
+            .await
+            .map_err(|e| e.to_string())?;
+        match sla::LateDigest::of(&shop_id, &late) {
+            Some(digest) => {
+                digest.send(&self.events);
+                Ok(TaskRun::Done)
+            }
+            None => Ok(TaskRun::Skipped("no late orders".to_string())),
+        }
+    }
+}
+
+/// Pulls pending buyer cancellation requests and announces new ones
+struct CancellationSyncTask {
+    monitor: CancellationMonitor,
+    deauthorizer: Deauthorizer,
+}
+
+#[async_trait]
+impl ScheduledTask for CancellationSyncTask {
+    async fn run(&self) -> Result<TaskRun, String> {
+        let announced = match self.monitor.sync().await {
+            Ok(announced) => announced,
+            Err(e) => {
+                self.deauthorizer.check(&e).await;
+                return Err(e.to_string());
+            }
+        };
+        if announced > 0 {
+            info!("Cancellation sync found {} new requests", announced);
+        }
+        Ok(TaskRun::Done)
+    }
+}
+
+/// Re-lists the token's authorized shops so renamed or moved shops keep
+/// their current name, region and cipher in the registry
+struct ShopRefreshTask {
+    shops: ShopRegistry,
+    config: Config,
+    oauth_client: TikTokShopOAuth,
+    deauthorizer: Deauthorizer,
+}
+
+#[async_trait]
+impl ScheduledTask for ShopRefreshTask {
+    async fn run(&self) -> Result<TaskRun, String> {
+        let token_info = match load_valid_token(&self.oauth_client).await {
+            Ok(token_info) => token_info,
+            Err(AppError::NoTokenStored) => {
+                return Ok(TaskRun::Skipped("no token stored".to_string()))
+            }
+            Err(e) => return Err(e.to_string()),
+        };
+        let client = ShopClient::with_api_client(self.config.tiktok_api_client());
+        match self.shops.refresh(&client, &token_info.access_token).await {
+            Ok(shops) => {
+                info!("Refreshed {} authorized shops", shops.len());
+                Ok(TaskRun::Done)
+            }
+            Err(e) => {
+                self.deauthorizer.check(&e).await;
+                Err(e.to_string())
+            }
+        }
+    }
+}
+
+/// Snapshots the data balance of provisioned eSIMs
+struct EsimUsageTask {
+    poller: EsimUsagePoller,
+}
+
+#[async_trait]
+impl ScheduledTask for EsimUsageTask {
+    async fn run(&self) -> Result<TaskRun, String> {
+        let poll = self.poller.poll().await.map_err(|e| e.to_string())?;
+        if poll.polled == 0 && poll.failed > 0 {
+            return Err(format!("WowEsim answered none of {} usage polls", poll.failed));
+        }
+        if poll.polled == 0 {
+            return Ok(TaskRun::Skipped("no eSIMs in use".to_string()));
+        }
+        info!("Polled usage of {} eSIMs ({} failed)", poll.polled, poll.failed);
+        Ok(TaskRun::Done)
+    }
+}
+
+/// Snapshots the last days of shop performance metrics; skipped while the
+/// app isn't authorized for analytics
+struct ShopPerformanceTask {
+    sync: ShopPerformanceSync,
+    deauthorizer: Deauthorizer,
+}
+
+#[async_trait]
+impl ScheduledTask for ShopPerformanceTask {
+    async fn run(&self) -> Result<TaskRun, String> {
+        let outcome = match self.sync.snapshot_recent().await {
+            Ok(outcome) => outcome,
+            Err(e) => {
+                self.deauthorizer.check(&e).await;
+                return Err(e.to_string());
+            }
+        };
+        match outcome {
+            SnapshotOutcome::Stored(days) => {
+                info!("Snapshotted {} days of shop performance", days);
+                Ok(TaskRun::Done)
+            }
+            SnapshotOutcome::NotAuthorized(reason) => Ok(TaskRun::Skipped(format!(
+                "not authorized for shop analytics: {}",
+                reason
+            ))),
+        }
+    }
+}
+
+/// Compares recently updated orders against TikTok and re-fetches the ones
+/// that differ
+struct ReconcileTask {
+    reconciler: Reconciler,
+    window_secs: i64,
+}
+
+#[async_trait]
+impl ScheduledTask for ReconcileTask {
+    async fn run(&self) -> Result<TaskRun, String> {
+        let run = self.reconciler.run(self.window_secs).await.map_err(|e| e.to_string())?;
+        match run.error {
+            Some(e) => Err(format!("reconciliation failed: {}", e)),
+            None => Ok(TaskRun::Done),
+        }
+    }
+}
+
+/// Recounts SKU stats and rolls up daily revenue from stored orders, then
+/// queues a low-stock check
+struct StatsRollupTask {
+    db: Arc<Database>,
+    job_queue: JobQueue,
+    shops: ShopRegistry,
+    config: Config,
+}
+
+#[async_trait]
+impl ScheduledTask for StatsRollupTask {
+    async fn run(&self) -> Result<TaskRun, String> {
+        let counted = self.db.rebuild_sku_stats().await.map_err(|e| e.to_string())?;
+        info!("Stats rollup recounted {} orders", counted);
+
+        let shop_id = self.shops.configured_shop_id(&self.config)
+            .await
+            .map_err(|e| e.to_string())?;
+        let days = self
+            .db
+            .rebuild_daily_stats(&shop_id)
+            .await
+            .map_err(|e| e.to_string())?;
+        info!("Stats rollup wrote daily stats for {} days", days);
+
+        sku_stats::enqueue_low_stock_check(&self.job_queue, &self.config)
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok(TaskRun::Done)
+    }
+}
+
+/// Fetches rates from `FX_RATES_URL` and converts orders that were waiting
+/// for a rate
+struct FxRefreshTask {
+    store: FxStore,
+    converter: FxConverter,
+    url: String,
+}
+
+#[async_trait]
+impl ScheduledTask for FxRefreshTask {
+    async fn run(&self) -> Result<TaskRun, String> {
+        let rates = fx::fetch_rates(&self.url, self.converter.base_currency()).await?;
+        self.store
+            .save_rates(RateSource::Api, &rates)
+            .await
+            .map_err(|e| e.to_string())?;
+        fx::load_rates(&self.store, &self.converter)
+            .await
+            .map_err(|e| e.to_string())?;
+        let converted = self
+            .store
+            .convert_pending(&self.converter)
+            .await
+            .map_err(|e| e.to_string())?;
+        info!("Fetched {} FX rates, converted {} pending orders", rates.len(), converted);
+        Ok(TaskRun::Done)
+    }
+}
+
+async fn sync_orders_once(
+    db: &Database,
+    config: &Config,
+    job_queue: &JobQueue,
+    shops: &ShopRegistry,
+    oauth_client: &TikTokShopOAuth,
+    api_health: &Arc<ApiHealth>,
+    deauthorizer: &Deauthorizer,
+) -> Result<TaskRun, String> {
+    info!("Running order sync...");
+
+    // Read token from file, refreshing it if expired
+    let token_info = match load_valid_token(oauth_client).await {
+        Ok(token_info) => token_info,
+        Err(AppError::NoTokenStored) => {
+            return Ok(TaskRun::Skipped("no token stored".to_string()));
+        }
+        Err(e) => {
+            deauthorizer.check(&e).await;
+            return Err(format!("failed to check/refresh token: {}", e));
+        }
+    };
+
+    // Create order client for the configured (or only authorized) shop
+    let order_client = resolve_shop_target(shops, config, &token_info.access_token)
+        .await
+        .order_client(config)
+        .with_health(api_health.clone());
+
+    // Fetch orders
+    let request = GetOrderListRequest::new().with_page_size(50);
+
+    let response = match order_client
+        .get_order_list(
+            &token_info.access_token,
+            None,
+            config.shop_id.as_deref(),
+            request,
+        )
+        .await
+    {
+        Ok(response) => response,
+        Err(e) if deauthorizer.check(&e).await => {
+            return Ok(TaskRun::Skipped(format!("shop de-authorized the app: {}", e)));
+        }
+        Err(e) => return Err(format!("failed to fetch orders from API: {}", e)),
+    };
+    info!("Fetched {} orders from API", response.orders.len());
+
+    // Save to database
+    let mut outcome = Ok(TaskRun::Done);
+    match db.upsert_orders(&response.orders).await {
+        Ok(stats) => {
+            info!("Synced orders to database: {}", stats);
+            if let Err(e) = sku_stats::enqueue_low_stock_check(job_queue, config).await {
+                error!("Failed to queue low-stock check: {}", e);
+            }
+            if let Err(e) = replacements::enqueue_resolution(
+                job_queue,
+                replacements::needs_resolution(&response.orders),
+                config.job_max_attempts,
+            )
+            .await
+            {
+                error!("Failed to queue replacement order lookups: {}", e);
+            }
+            if config.fetch_price_details {
+                let queued = price_details::enqueue_fetch(
+                    job_queue,
+                    &response.orders,
+                    config.job_max_attempts,
+                )
+                .await;
+                if let Err(e) = queued {
+                    error!("Failed to queue price detail fetches: {}", e);
+                }
+            }
+            if config.fetch_settlements {
+                let queued = settlements::enqueue_fetch(
+                    job_queue,
+                    &response.orders,
+                    config.job_max_attempts,
+                )
+                .await;
+                if let Err(e) = queued {
+                    error!("Failed to queue settlement fetches: {}", e);
+                }
+            }
+        }
+        Err(e) => {
+            outcome = Err(format!("failed to save orders to database, queued for retry: {}", e));
+            let payload = serde_json::json!({ "orders": &response.orders });
+            if let Err(e) = job_queue
+                .enqueue(order_jobs::UPSERT_JOB_KIND, None, &payload, config.job_max_attempts)
+                .await
+            {
+                error!("Failed to queue order write retry: {}", e);
+            }
+        }
+    }
+
+    outcome
+}
@@ -0,0 +1,181 @@
+use redis::aio::{ConnectionManager, ConnectionManagerConfig};
+use redis::{AsyncCommands, ErrorKind, RedisResult};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::future::Future;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, warn};
+
+/// Namespace of every key this service writes
+const KEY_PREFIX: &str = "toptop-order";
+
+/// Longest a cache operation may take before the read goes to SQLite
+const OP_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// How long the cache is bypassed after Redis fails, so an outage costs one
+/// timeout rather than one per request
+const BYPASS_SECS: i64 = 10;
+
+/// Cache-aside Redis layer for hot order and stats reads.
+///
+/// Redis problems are logged and treated as misses, so reads fall back to
+/// SQLite rather than failing, and the cache is bypassed for a few seconds
+/// after a failure. Orders are invalidated when upserted; stats
+/// entries are versioned by a generation counter bumped on every upsert, and
+/// otherwise expire after the TTL.
+#[derive(Clone)]
+pub struct OrderCache {
+    conn: ConnectionManager,
+    ttl_secs: u64,
+    /// Unix time until which Redis is not tried
+    bypass_until: Arc<AtomicI64>,
+}
+
+impl OrderCache {
+    pub async fn connect(url: &str, ttl_secs: u64) -> Result<Self, redis::RedisError> {
+        let client = redis::Client::open(url)?;
+        let config = ConnectionManagerConfig::new()
+            .set_connection_timeout(Duration::from_secs(2))
+            .set_response_timeout(OP_TIMEOUT);
+        let conn = ConnectionManager::new_with_config(client, config).await?;
+        Ok(Self {
+            conn,
+            ttl_secs,
+            bypass_until: Arc::new(AtomicI64::new(0)),
+        })
+    }
+
+    /// Run a Redis operation unless the cache is bypassed, bounded by
+    /// `OP_TIMEOUT`; failures start a bypass period
+    async fn run<T, Fut>(&self, op: impl FnOnce(ConnectionManager) -> Fut) -> RedisResult<T>
+    where
+        Fut: Future<Output = RedisResult<T>>,
+    {
+        let now = chrono::Utc::now().timestamp();
+        if self.bypass_until.load(Ordering::Relaxed) > now {
+            return Err((ErrorKind::IoError, "bypassed after a recent failure").into());
+        }
+
+        let result = tokio::time::timeout(OP_TIMEOUT, op(self.conn.clone()))
+            .await
+            .unwrap_or_else(|_| Err((ErrorKind::IoError, "timed out").into()));
+        if let Err(e) = &result {
+            warn!(
+                "Redis cache failed, bypassing it for {}s: {}",
+                BYPASS_SECS, e
+            );
+            self.bypass_until
+                .store(now + BYPASS_SECS, Ordering::Relaxed);
+        }
+        result
+    }
+
+    fn order_key(order_id: &str) -> String {
+        format!("{}:order:{}", KEY_PREFIX, order_id)
+    }
+
+    fn generation_key() -> String {
+        format!("{}:stats:generation", KEY_PREFIX)
+    }
+
+    pub async fn get_order<T: DeserializeOwned>(&self, order_id: &str) -> Option<T> {
+        self.get_json(&Self::order_key(order_id)).await
+    }
+
+    pub async fn put_order<T: Serialize>(&self, order_id: &str, order: &T) {
+        self.set_json(&Self::order_key(order_id), order).await;
+    }
+
+    /// Drop cached copies of changed orders and every cached stats entry
+    pub async fn invalidate_orders(&self, order_ids: &[&str]) {
+        if !order_ids.is_empty() {
+            let keys: Vec<String> = order_ids.iter().map(|id| Self::order_key(id)).collect();
+            let deleted = self
+                .run(|mut conn| async move { conn.del::<_, ()>(keys).await })
+                .await;
+            if let Err(e) = deleted {
+                warn!("Failed to invalidate cached orders: {}", e);
+            }
+        }
+        self.invalidate_stats().await;
+    }
+
+    /// Make every cached stats entry stale
+    pub async fn invalidate_stats(&self) {
+        let bumped = self
+            .run(|mut conn| async move { conn.incr::<_, _, ()>(Self::generation_key(), 1).await })
+            .await;
+        if let Err(e) = bumped {
+            warn!("Failed to invalidate cached stats: {}", e);
+        }
+    }
+
+    /// Cached stats entry `name`, or the result of `load` stored for next time
+    pub async fn stats_or_load<T, E, F, Fut>(&self, name: &str, load: F) -> Result<T, E>
+    where
+        T: Serialize + DeserializeOwned,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        let generation = self
+            .run(|mut conn| async move { conn.get::<_, Option<u64>>(Self::generation_key()).await })
+            .await;
+        let generation = match generation {
+            Ok(generation) => Some(generation.unwrap_or(0)),
+            Err(e) => {
+                debug!("Stats cache unavailable: {}", e);
+                None
+            }
+        };
+        let Some(generation) = generation else {
+            return load().await;
+        };
+
+        let key = format!("{}:stats:{}:{}", KEY_PREFIX, generation, name);
+        if let Some(value) = self.get_json(&key).await {
+            return Ok(value);
+        }
+        let value = load().await?;
+        self.set_json(&key, &value).await;
+        Ok(value)
+    }
+
+    async fn get_json<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        let key_owned = key.to_string();
+        let raw = self
+            .run(|mut conn| async move { conn.get::<_, Option<String>>(key_owned).await })
+            .await;
+        let raw = match raw {
+            Ok(raw) => raw,
+            Err(e) => {
+                debug!("Cache read of {} failed: {}", key, e);
+                return None;
+            }
+        };
+        match serde_json::from_str(raw.as_deref()?) {
+            Ok(value) => {
+                debug!("Cache hit for {}", key);
+                Some(value)
+            }
+            Err(e) => {
+                debug!("Ignoring undecodable cache entry {}: {}", key, e);
+                None
+            }
+        }
+    }
+
+    async fn set_json<T: Serialize>(&self, key: &str, value: &T) {
+        let Ok(raw) = serde_json::to_string(value) else {
+            return;
+        };
+        let (key_owned, ttl_secs) = (key.to_string(), self.ttl_secs);
+        let written = self
+            .run(|mut conn| async move { conn.set_ex::<_, _, ()>(key_owned, raw, ttl_secs).await })
+            .await;
+        if let Err(e) = written {
+            debug!("Cache write of {} failed: {}", key, e);
+        }
+    }
+}
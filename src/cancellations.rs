@@ -0,0 +1,371 @@
+use crate::config::Config;
+use crate::database::Database;
+use crate::error::AppError;
+use crate::events::{EventBus, OrderEvent, OrderEventKind};
+use crate::order::{
+    CancelReason, Cancellation, CancellationInitiator, Order, SearchCancellationsRequest,
+    CANCELLATION_PENDING,
+};
+use crate::shops::ShopRegistry;
+use serde::Serialize;
+use sqlx::sqlite::{SqlitePool, SqliteRow};
+use sqlx::Row;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tracing::warn;
+use utoipa::ToSchema;
+
+/// Longest range a cancellation report covers
+pub const MAX_REPORT_DAYS: i64 = 366;
+
+/// Key of orders without a reason or initiator
+const UNKNOWN: &str = "unknown";
+
+/// Cancelled orders with one reason or initiator
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct CancellationCount {
+    /// Reason key (e.g. `out_of_stock`) or initiator (e.g. `BUYER`); TikTok's
+    /// own value when not a known one, `unknown` when it sent none
+    pub key: String,
+    pub count: usize,
+    /// Fraction of all cancelled orders in the range
+    pub share: f64,
+}
+
+/// Why, and by whom, orders were cancelled
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct CancellationBreakdown {
+    pub total: usize,
+    /// Most common first
+    pub by_reason: Vec<CancellationCount>,
+    pub by_initiator: Vec<CancellationCount>,
+}
+
+/// Count cancelled orders by reason and by initiator
+pub fn breakdown(orders: &[Order]) -> CancellationBreakdown {
+    let total = orders.len();
+    CancellationBreakdown {
+        total,
+        by_reason: counts(
+            orders.iter().map(|o| {
+                o.cancel_reason
+                    .as_ref()
+                    .map_or(UNKNOWN, CancelReason::as_str)
+            }),
+            total,
+        ),
+        by_initiator: counts(
+            orders.iter().map(|o| {
+                o.cancellation_initiator
+                    .as_ref()
+                    .map_or(UNKNOWN, CancellationInitiator::as_str)
+            }),
+            total,
+        ),
+    }
+}
+
+/// Occurrences of each key, most common first
+fn counts<'a>(keys: impl Iterator<Item = &'a str>, total: usize) -> Vec<CancellationCount> {
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    for key in keys {
+        *counts.entry(key).or_default() += 1;
+    }
+    let mut counts: Vec<CancellationCount> = counts
+        .into_iter()
+        .map(|(key, count)| CancellationCount {
+            key: key.to_string(),
+            count,
+            share: count as f64 / total as f64,
+        })
+        .collect();
+    // Stable, so ties stay in key order
+    counts.sort_by_key(|c| std::cmp::Reverse(c.count));
+    counts
+}
+
+/// A cancellation request as last synced, with what was decided here
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct StoredCancellation {
+    #[serde(flatten)]
+    pub cancellation: Cancellation,
+    /// `approved` or `rejected` when answered through this service
+    pub decision: Option<String>,
+    pub decided_at: Option<i64>,
+    pub first_seen_at: i64,
+}
+
+impl StoredCancellation {
+    pub fn is_pending(&self) -> bool {
+        self.cancellation.cancel_status == CANCELLATION_PENDING
+    }
+}
+
+/// Cancellation requests of orders, in the `cancellation_requests` table
+#[derive(Clone)]
+pub struct CancellationStore {
+    pool: SqlitePool,
+}
+
+impl CancellationStore {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Initialize the cancellation_requests table
+    pub async fn init(&self) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS cancellation_requests (
+                cancel_id TEXT PRIMARY KEY,
+                order_id TEXT NOT NULL,
+                status TEXT NOT NULL,
+                create_time INTEGER NOT NULL,
+                cancellation TEXT NOT NULL,
+                decision TEXT,
+                decided_at INTEGER,
+                first_seen_at INTEGER NOT NULL
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            "CREATE INDEX IF NOT EXISTS idx_cancellation_requests_status
+            ON cancellation_requests(status, create_time)",
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Store the latest state of a request. Returns `true` if it had not
+    /// been seen before.
+    pub async fn save(&self, cancellation: &Cancellation) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            "INSERT INTO cancellation_requests (cancel_id, order_id, status, create_time,
+                cancellation, first_seen_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+            ON CONFLICT(cancel_id) DO NOTHING",
+        )
+        .bind(&cancellation.cancel_id)
+        .bind(&cancellation.order_id)
+        .bind(&cancellation.cancel_status)
+        .bind(cancellation.create_time)
+        .bind(serde_json::to_string(cancellation).unwrap_or_default())
+        .bind(chrono::Utc::now().timestamp())
+        .execute(&self.pool)
+        .await?;
+        if result.rows_affected() > 0 {
+            return Ok(true);
+        }
+
+        sqlx::query(
+            "UPDATE cancellation_requests SET status = ?2, cancellation = ?3 WHERE cancel_id = ?1",
+        )
+        .bind(&cancellation.cancel_id)
+        .bind(&cancellation.cancel_status)
+        .bind(serde_json::to_string(cancellation).unwrap_or_default())
+        .execute(&self.pool)
+        .await?;
+        Ok(false)
+    }
+
+    /// Note that a request was approved or rejected here
+    pub async fn record_decision(
+        &self,
+        cancel_id: &str,
+        decision: &str,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "UPDATE cancellation_requests SET decision = ?2, decided_at = ?3 WHERE cancel_id = ?1",
+        )
+        .bind(cancel_id)
+        .bind(decision)
+        .bind(chrono::Utc::now().timestamp())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn get(&self, cancel_id: &str) -> Result<Option<StoredCancellation>, sqlx::Error> {
+        sqlx::query(
+            "SELECT cancellation, decision, decided_at, first_seen_at
+            FROM cancellation_requests WHERE cancel_id = ?1",
+        )
+        .bind(cancel_id)
+        .fetch_optional(&self.pool)
+        .await?
+        .map(|row| Self::from_row(&row))
+        .transpose()
+    }
+
+    /// Requests with `status` (all when `None`), optionally of one order,
+    /// oldest first
+    pub async fn list(
+        &self,
+        status: Option<&str>,
+        order_id: Option<&str>,
+        limit: i64,
+    ) -> Result<Vec<StoredCancellation>, sqlx::Error> {
+        sqlx::query(
+            "SELECT cancellation, decision, decided_at, first_seen_at
+            FROM cancellation_requests
+            WHERE (?1 IS NULL OR status = ?1) AND (?2 IS NULL OR order_id = ?2)
+            ORDER BY create_time, cancel_id LIMIT ?3",
+        )
+        .bind(status)
+        .bind(order_id)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(Self::from_row)
+        .collect()
+    }
+
+    fn from_row(row: &SqliteRow) -> Result<StoredCancellation, sqlx::Error> {
+        let cancellation: String = row.try_get("cancellation")?;
+        Ok(StoredCancellation {
+            cancellation: serde_json::from_str(&cancellation)
+                .map_err(|e| sqlx::Error::Decode(e.into()))?,
+            decision: row.try_get("decision")?,
+            decided_at: row.try_get("decided_at")?,
+            first_seen_at: row.try_get("first_seen_at")?,
+        })
+    }
+}
+
+/// Pulls cancellation requests from TikTok into the store and announces new
+/// pending ones, so buyers' requests are seen before TikTok approves them on
+/// the seller's behalf
+#[derive(Clone)]
+pub struct CancellationMonitor {
+    db: Arc<Database>,
+    store: CancellationStore,
+    shops: ShopRegistry,
+    config: Config,
+    events: EventBus,
+}
+
+impl CancellationMonitor {
+    pub fn new(
+        db: Arc<Database>,
+        store: CancellationStore,
+        shops: ShopRegistry,
+        config: Config,
+        events: EventBus,
+    ) -> Self {
+        Self {
+            db,
+            store,
+            shops,
+            config,
+            events,
+        }
+    }
+
+    /// Store every pending request, and the current state of those stored as
+    /// pending that no longer are. Returns how many new requests were announced.
+    pub async fn sync(&self) -> Result<usize, AppError> {
+        let pending = self
+            .search(SearchCancellationsRequest {
+                cancel_status: vec![CANCELLATION_PENDING.to_string()],
+                ..Default::default()
+            })
+            .await?;
+        let announced = self.save_all(&pending).await?;
+
+        let resolved: Vec<String> = self
+            .store
+            .list(Some(CANCELLATION_PENDING), None, i64::MAX)
+            .await?
+            .into_iter()
+            .filter(|stored| {
+                !pending
+                    .iter()
+                    .any(|c| c.cancel_id == stored.cancellation.cancel_id)
+            })
+            .map(|stored| stored.cancellation.order_id)
+            .collect();
+        // A search takes at most 50 order ids
+        for order_ids in resolved.chunks(50) {
+            let cancellations = self
+                .search(SearchCancellationsRequest {
+                    order_ids: order_ids.to_vec(),
+                    ..Default::default()
+                })
+                .await?;
+            self.save_all(&cancellations).await?;
+        }
+        Ok(announced)
+    }
+
+    /// Store the requests of one order, e.g. after a cancellation webhook
+    pub async fn sync_order(&self, order_id: &str) -> Result<usize, AppError> {
+        let cancellations = self
+            .search(SearchCancellationsRequest {
+                order_ids: vec![order_id.to_string()],
+                ..Default::default()
+            })
+            .await?;
+        self.save_all(&cancellations).await
+    }
+
+    async fn search(
+        &self,
+        request: SearchCancellationsRequest,
+    ) -> Result<Vec<Cancellation>, AppError> {
+        let token = self.config.token_manager().valid_token().await?;
+        self.shops
+            .order_client(&self.config)
+            .await?
+            .search_all_cancellations(&token.access_token, None, request)
+            .await
+    }
+
+    async fn save_all(&self, cancellations: &[Cancellation]) -> Result<usize, AppError> {
+        let mut announced = 0;
+        for cancellation in cancellations {
+            let new = self.store.save(cancellation).await?;
+            if new && cancellation.cancel_status == CANCELLATION_PENDING {
+                self.announce(cancellation).await?;
+                announced += 1;
+            }
+        }
+        Ok(announced)
+    }
+
+    /// Log a new request and publish it as a `cancellation_requested` event
+    async fn announce(&self, cancellation: &Cancellation) -> Result<(), AppError> {
+        warn!(
+            "Order {} has a new cancellation request {} from {}: {}",
+            cancellation.order_id,
+            cancellation.cancel_id,
+            cancellation
+                .role
+                .as_ref()
+                .map_or(UNKNOWN, CancellationInitiator::as_str),
+            cancellation
+                .cancel_reason_text
+                .as_deref()
+                .or(cancellation
+                    .cancel_reason
+                    .as_ref()
+                    .map(CancelReason::as_str))
+                .unwrap_or("no reason given")
+        );
+        let event = match self.db.get_order_by_id(&cancellation.order_id).await? {
+            Some(order) => OrderEvent::new(OrderEventKind::CancellationRequested, &order),
+            None => OrderEvent {
+                kind: OrderEventKind::CancellationRequested,
+                order_id: cancellation.order_id.clone(),
+                status: String::new(),
+                update_time: cancellation.update_time,
+                shop_id: None,
+                order_ids: Vec::new(),
+            },
+        };
+        self.events.publish(event);
+        Ok(())
+    }
+}
@@ -0,0 +1,222 @@
+use crate::database;
+use crate::item_fulfillment::FulfillmentProgress;
+use crate::order::Order;
+use crate::sla;
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::{SqlitePool, SqliteRow};
+use sqlx::Row;
+use std::collections::HashMap;
+use utoipa::ToSchema;
+
+/// Placeholder replaced by the (URL-encoded) tracking number
+pub const TRACKING_NUMBER_PLACEHOLDER: &str = "{tracking_number}";
+
+/// Carrier metadata for a TikTok shipping provider
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Carrier {
+    /// TikTok `shipping_provider_id`, or the provider name for providers matched by name
+    pub key: String,
+    pub name: String,
+    /// Tracking page URL containing `{tracking_number}`
+    pub tracking_url_template: Option<String>,
+    /// Customer service phone number
+    pub phone: Option<String>,
+    pub updated_at: i64,
+}
+
+/// Body of `PUT /carriers/{key}`
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct CarrierUpdate {
+    pub name: String,
+    pub tracking_url_template: Option<String>,
+    pub phone: Option<String>,
+}
+
+impl CarrierUpdate {
+    /// Reason the update can't be stored, if any
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("name must not be empty".to_string());
+        }
+        if let Some(template) = &self.tracking_url_template {
+            if !template.contains(TRACKING_NUMBER_PLACEHOLDER) {
+                return Err(format!(
+                    "tracking_url_template must contain {}",
+                    TRACKING_NUMBER_PLACEHOLDER
+                ));
+            }
+            match reqwest::Url::parse(&template.replace(TRACKING_NUMBER_PLACEHOLDER, "0")) {
+                Ok(url) if url.scheme() == "http" || url.scheme() == "https" => {}
+                _ => return Err("tracking_url_template must be an http(s) URL".to_string()),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// An order as returned by the API, with a link to the carrier's tracking page
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct TrackedOrder {
+    #[serde(flatten)]
+    pub order: Order,
+    /// Set when the order has a tracking number and its carrier has a URL template
+    pub tracking_url: Option<String>,
+    /// How many of the order's live line items have shipped
+    pub progress: FulfillmentProgress,
+    /// Past its shipping deadline and still waiting to be handed to the carrier
+    pub late: bool,
+}
+
+/// Snapshot of the carrier table for enriching many orders at once
+#[derive(Debug, Clone, Default)]
+pub struct CarrierDirectory {
+    by_key: HashMap<String, Carrier>,
+}
+
+impl CarrierDirectory {
+    pub fn new(carriers: Vec<Carrier>) -> Self {
+        Self {
+            by_key: carriers
+                .into_iter()
+                .map(|c| (c.key.to_lowercase(), c))
+                .collect(),
+        }
+    }
+
+    /// Carrier of an order, by provider id first and then by provider name
+    pub fn carrier_of(&self, order: &Order) -> Option<&Carrier> {
+        [&order.shipping_provider_id, &order.shipping_provider]
+            .into_iter()
+            .flatten()
+            .find_map(|key| self.by_key.get(&key.trim().to_lowercase()))
+    }
+
+    pub fn tracking_url(&self, order: &Order) -> Option<String> {
+        let tracking_number = order
+            .tracking_number
+            .as_deref()
+            .map(str::trim)
+            .filter(|t| !t.is_empty())?;
+        let template = self.carrier_of(order)?.tracking_url_template.as_ref()?;
+        Some(template.replace(TRACKING_NUMBER_PLACEHOLDER, &encode(tracking_number)))
+    }
+
+    pub fn track(&self, order: Order) -> TrackedOrder {
+        TrackedOrder {
+            tracking_url: self.tracking_url(&order),
+            progress: FulfillmentProgress::of(&order),
+            late: sla::is_late(&order, chrono::Utc::now().timestamp()),
+            order,
+        }
+    }
+}
+
+/// Percent-encode everything but unreserved URL characters
+fn encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// Shipping provider -> carrier mapping, in the `carriers` table
+#[derive(Clone)]
+pub struct CarrierStore {
+    pool: SqlitePool,
+}
+
+impl CarrierStore {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Initialize the carriers table
+    pub async fn init(&self) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS carriers (
+                key TEXT PRIMARY KEY COLLATE NOCASE,
+                name TEXT NOT NULL,
+                tracking_url_template TEXT,
+                phone TEXT,
+                updated_at INTEGER NOT NULL
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+        // Listings carry tracking URLs from it, so changes invalidate their ETag
+        database::track_orders_version(&self.pool, "carriers").await?;
+
+        Ok(())
+    }
+
+    pub async fn list(&self) -> Result<Vec<Carrier>, sqlx::Error> {
+        sqlx::query(
+            "SELECT key, name, tracking_url_template, phone, updated_at FROM carriers ORDER BY key",
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(carrier_from_row)
+        .collect()
+    }
+
+    /// All carriers, for enriching orders
+    pub async fn directory(&self) -> Result<CarrierDirectory, sqlx::Error> {
+        Ok(CarrierDirectory::new(self.list().await?))
+    }
+
+    /// Create or replace the carrier for `key`
+    pub async fn put(&self, key: &str, update: &CarrierUpdate) -> Result<Carrier, sqlx::Error> {
+        let carrier = Carrier {
+            key: key.trim().to_string(),
+            name: update.name.trim().to_string(),
+            tracking_url_template: update.tracking_url_template.clone(),
+            phone: update.phone.clone().filter(|p| !p.trim().is_empty()),
+            updated_at: chrono::Utc::now().timestamp(),
+        };
+
+        sqlx::query(
+            "INSERT INTO carriers (key, name, tracking_url_template, phone, updated_at)
+            VALUES (?1, ?2, ?3, ?4, ?5)
+            ON CONFLICT(key) DO UPDATE SET
+                name = excluded.name,
+                tracking_url_template = excluded.tracking_url_template,
+                phone = excluded.phone,
+                updated_at = excluded.updated_at",
+        )
+        .bind(&carrier.key)
+        .bind(&carrier.name)
+        .bind(&carrier.tracking_url_template)
+        .bind(&carrier.phone)
+        .bind(carrier.updated_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(carrier)
+    }
+
+    /// Remove a carrier. Returns `false` if there was none.
+    pub async fn delete(&self, key: &str) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query("DELETE FROM carriers WHERE key = ?1")
+            .bind(key.trim())
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+fn carrier_from_row(row: &SqliteRow) -> Result<Carrier, sqlx::Error> {
+    Ok(Carrier {
+        key: row.try_get("key")?,
+        name: row.try_get("name")?,
+        tracking_url_template: row.try_get("tracking_url_template")?,
+        phone: row.try_get("phone")?,
+        updated_at: row.try_get("updated_at")?,
+    })
+}
@@ -0,0 +1,149 @@
+use crate::error::AppError;
+use crate::transport::{HttpRequest, HttpResponse, HttpTransport};
+use async_trait::async_trait;
+use reqwest::StatusCode;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::debug;
+
+/// Body of an injected 429, shaped like TikTok's own rate limit response
+const RATE_LIMITED_BODY: &str =
+    r#"{"code":36009003,"message":"Too many requests (injected fault)","data":null}"#;
+
+/// How often, and how, the fault-injecting transport misbehaves. Rates are
+/// probabilities between 0 and 1, drawn independently for every request.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FaultConfig {
+    /// Share of requests delayed by up to `latency_ms` before being sent
+    pub latency_rate: f64,
+    pub latency_ms: u64,
+    /// Share of requests answered with a 429 without reaching TikTok
+    pub rate_limit_rate: f64,
+    /// Share of responses whose body is cut short, so it no longer parses
+    pub malformed_rate: f64,
+    /// Fixed seed for a reproducible sequence of faults
+    pub seed: Option<u64>,
+}
+
+impl FaultConfig {
+    /// Whether any fault can be injected at all
+    pub fn is_active(&self) -> bool {
+        self.latency_rate > 0.0 || self.rate_limit_rate > 0.0 || self.malformed_rate > 0.0
+    }
+}
+
+impl FromStr for FaultConfig {
+    type Err = String;
+
+    /// Parse `latency=0.2,latency_ms=800,rate_limit=0.1,malformed=0.05,seed=7`
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let mut config = FaultConfig {
+            latency_ms: 1000,
+            ..Default::default()
+        };
+        for pair in value.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let (key, value) = pair
+                .split_once('=')
+                .map(|(k, v)| (k.trim(), v.trim()))
+                .ok_or_else(|| format!("expected key=value, got '{}'", pair))?;
+            match key {
+                "latency" => config.latency_rate = parse_rate(key, value)?,
+                "latency_ms" => {
+                    config.latency_ms = value
+                        .parse()
+                        .map_err(|_| format!("latency_ms must be a number, got '{}'", value))?
+                }
+                "rate_limit" => config.rate_limit_rate = parse_rate(key, value)?,
+                "malformed" => config.malformed_rate = parse_rate(key, value)?,
+                "seed" => {
+                    config.seed = Some(
+                        value
+                            .parse()
+                            .map_err(|_| format!("seed must be a number, got '{}'", value))?,
+                    )
+                }
+                _ => return Err(format!("unknown fault '{}'", key)),
+            }
+        }
+        Ok(config)
+    }
+}
+
+fn parse_rate(key: &str, value: &str) -> Result<f64, String> {
+    value
+        .parse::<f64>()
+        .ok()
+        .filter(|rate| (0.0..=1.0).contains(rate))
+        .ok_or_else(|| format!("{} must be a rate between 0 and 1, got '{}'", key, value))
+}
+
+/// Transport that wraps another one and injects latency, 429s and malformed
+/// JSON at the configured rates, to exercise retry and resilience paths
+pub struct FaultInjectingTransport {
+    inner: Arc<dyn HttpTransport>,
+    faults: FaultConfig,
+    rng: Mutex<u64>,
+}
+
+impl FaultInjectingTransport {
+    pub fn new(inner: Arc<dyn HttpTransport>, faults: FaultConfig) -> Self {
+        let seed = faults.seed.unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_nanos() as u64)
+                .unwrap_or_default()
+        });
+        Self {
+            inner,
+            faults,
+            // xorshift gets stuck on a zero state
+            rng: Mutex::new(seed | 1),
+        }
+    }
+
+    /// Next pseudo-random number in `[0, 1)` (xorshift64*)
+    fn next_f64(&self) -> f64 {
+        let mut state = self.rng.lock().unwrap();
+        *state ^= *state >> 12;
+        *state ^= *state << 25;
+        *state ^= *state >> 27;
+        let value = state.wrapping_mul(0x2545_F491_4F6C_DD1D);
+        (value >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn roll(&self, rate: f64) -> bool {
+        rate > 0.0 && self.next_f64() < rate
+    }
+}
+
+#[async_trait]
+impl HttpTransport for FaultInjectingTransport {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse, AppError> {
+        if self.roll(self.faults.latency_rate) {
+            let delay = (self.next_f64() * self.faults.latency_ms as f64) as u64;
+            debug!("Injecting {}ms latency into {}", delay, request.url);
+            tokio::time::sleep(Duration::from_millis(delay)).await;
+        }
+
+        if self.roll(self.faults.rate_limit_rate) {
+            debug!("Injecting 429 for {}", request.url);
+            return Ok(HttpResponse::new(
+                StatusCode::TOO_MANY_REQUESTS,
+                RATE_LIMITED_BODY,
+            ));
+        }
+
+        let url = request.url.clone();
+        let mut response = self.inner.send(request).await?;
+        if self.roll(self.faults.malformed_rate) {
+            debug!("Injecting malformed JSON for {}", url);
+            let mut cut = response.body.len() / 2;
+            while !response.body.is_char_boundary(cut) {
+                cut -= 1;
+            }
+            response.body.truncate(cut);
+        }
+        Ok(response)
+    }
+}
@@ -0,0 +1,153 @@
+use crate::order::Order;
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::SqlitePool;
+use sqlx::Row;
+use utoipa::ToSchema;
+
+/// Who sent a message relative to the shop
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    Inbound,
+    Outbound,
+}
+
+impl Direction {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Direction::Inbound => "inbound",
+            Direction::Outbound => "outbound",
+        }
+    }
+
+    fn parse(value: &str) -> Self {
+        match value {
+            "outbound" => Direction::Outbound,
+            _ => Direction::Inbound,
+        }
+    }
+}
+
+/// One message in an order's communication timeline
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct TimelineEntry {
+    /// Unix timestamp of the message
+    pub time: i64,
+    /// e.g. `buyer_message`, `customer_service`, `email`
+    pub channel: String,
+    pub direction: Direction,
+    pub author: Option<String>,
+    pub body: String,
+}
+
+/// Persistence for messages exchanged about an order (CS chats, outbound emails)
+#[derive(Clone)]
+pub struct CommunicationStore {
+    pool: SqlitePool,
+}
+
+impl CommunicationStore {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Initialize the communications table
+    pub async fn init(&self) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS order_communications (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                order_id TEXT NOT NULL,
+                channel TEXT NOT NULL,
+                direction TEXT NOT NULL,
+                author TEXT,
+                body TEXT NOT NULL,
+                created_at INTEGER NOT NULL
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            "CREATE INDEX IF NOT EXISTS idx_order_communications_order
+            ON order_communications (order_id, created_at)",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Record a message sent or received about an order
+    pub async fn record(
+        &self,
+        order_id: &str,
+        channel: &str,
+        direction: Direction,
+        author: Option<&str>,
+        body: &str,
+        time: i64,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "INSERT INTO order_communications (
+                order_id, channel, direction, author, body, created_at
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        )
+        .bind(order_id)
+        .bind(channel)
+        .bind(direction.as_str())
+        .bind(author)
+        .bind(body)
+        .bind(time)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Get recorded messages for an order, oldest first
+    pub async fn get_for_order(&self, order_id: &str) -> Result<Vec<TimelineEntry>, sqlx::Error> {
+        let rows = sqlx::query(
+            "SELECT channel, direction, author, body, created_at
+            FROM order_communications WHERE order_id = ?1 ORDER BY created_at, id",
+        )
+        .bind(order_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter()
+            .map(|row| {
+                let direction: String = row.try_get("direction")?;
+                Ok(TimelineEntry {
+                    time: row.try_get("created_at")?,
+                    channel: row.try_get("channel")?,
+                    direction: Direction::parse(&direction),
+                    author: row.try_get("author")?,
+                    body: row.try_get("body")?,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Merge the buyer's checkout message with recorded communications, oldest first
+pub fn build_timeline(order: &Order, recorded: Vec<TimelineEntry>) -> Vec<TimelineEntry> {
+    let mut timeline = recorded;
+
+    if let Some(message) = order.buyer_message.as_ref().filter(|m| !m.trim().is_empty()) {
+        timeline.push(TimelineEntry {
+            // Left at checkout, so it dates from order creation
+            time: order.create_time,
+            channel: "buyer_message".to_string(),
+            direction: Direction::Inbound,
+            author: order
+                .recipient_address
+                .as_ref()
+                .and_then(|a| a.name.clone()),
+            body: message.clone(),
+        });
+    }
+
+    // Stable sort keeps recorded order for entries with the same timestamp
+    timeline.sort_by_key(|entry| entry.time);
+    timeline
+}
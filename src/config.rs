@@ -1,29 +1,1432 @@
+use crate::accounting::{self, AccountingMapping};
+#[cfg(feature = "chaos")]
+use crate::chaos::FaultConfig;
+use crate::database::SqliteSettings;
 use crate::error::AppError;
+use crate::esim_delivery::{SmsWebhookSettings, SmtpSettings};
+use crate::fx::{self, FxConverter};
+use crate::mirror::{self, MirrorTarget};
+use crate::payload_archive::{self, ArchiveSettings};
+use crate::pii_crypto::PiiCipher;
+use crate::oauth::TikTokShopOAuth;
+use crate::oauth_state;
+use crate::outbound_webhooks::{self, WebhookSubscriber};
+use crate::outbox::{self, SinkTarget};
+use crate::packing_slip::{PackingSlipTemplate, Paper};
+use crate::promotions::{self, Campaign};
+use crate::requests::{ApiRecorder, TikTokShopApiClient};
+use crate::risk::{self, HighQuantity, RegionMismatch, RepeatedCancellations, RiskScorer};
+use crate::scheduler;
+#[cfg(feature = "aws-secrets")]
+use crate::secrets::AwsSecretsSettings;
+use crate::secrets::{self, SecretProvider, SecretSource};
+#[cfg(feature = "vault")]
+use crate::secrets::VaultSettings;
+use crate::sla;
+use crate::storage::{TokenManager, TokenStorage};
+use crate::tags::{self, OrderTagger, TagRule};
+use crate::tenants::{self, Tenant};
+use crate::threepl::{self, ThreePlSettings};
+use crate::warehouse_routing::{self, WarehouseRoute, WarehouseRouter};
+use crate::wow_requests::{self, WowEsimApiClient};
+use cron::Schedule;
+use sqlx::sqlite::{SqliteJournalMode, SqliteSynchronous};
+use std::collections::{BTreeMap, HashMap};
 use std::env;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+/// Which external systems the service talks to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    Production,
+    /// TikTok sandbox endpoints, stubbed Wow client, dry-run notifications
+    Sandbox,
+}
+
+impl Mode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Mode::Production => "production",
+            Mode::Sandbox => "sandbox",
+        }
+    }
+}
+
+impl std::str::FromStr for Mode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "" | "production" | "prod" => Ok(Mode::Production),
+            "sandbox" | "mock" => Ok(Mode::Sandbox),
+            other => Err(format!("expected production or sandbox, got {}", other)),
+        }
+    }
+}
 
 #[derive(Clone, Debug)]
 pub struct Config {
+    pub mode: Mode,
+    /// Tenant this process serves; its app credentials come from the tenant
+    /// registry and its data lives in its own directory under `tenant_root`
+    pub tenant_id: Option<String>,
+    /// SQLite database of tenants and their API keys
+    pub tenant_registry: String,
+    /// Directory holding one data directory per tenant
+    pub tenant_root: String,
+    /// Where secrets unset in the environment and config file are read from,
+    /// and where the OAuth token is kept when it isn't the environment
+    pub secret_source: SecretSource,
     pub app_key: String,
     pub app_secret: String,
     pub shop_cipher: Option<String>,
     pub shop_id: Option<String>,
+    /// Where TikTok sends the seller after they authorize the app; must be
+    /// the one registered for the app
+    pub redirect_uri: String,
+    /// Directory relative paths of persisted files resolve under, unset for
+    /// the working directory. In multi-tenant mode only the tenant registry
+    /// and `tenant_root` do; a tenant's own files resolve under its data
+    /// directory, `tenant_root/<tenant_id>`.
+    pub data_dir: Option<String>,
     pub token_file: String,
     pub database_path: String,
+    /// Directory for cached shipping labels
+    pub documents_dir: String,
+    /// Directory relative `export-sample --output` files are written to
+    pub exports_dir: String,
+    /// `wal` by default so reads don't block on a running sync
+    pub sqlite_journal_mode: SqliteJournalMode,
+    pub sqlite_synchronous: SqliteSynchronous,
+    pub sqlite_busy_timeout_ms: u64,
+    pub sqlite_foreign_keys: bool,
+    pub sqlite_max_connections: u32,
+    pub host: String,
+    pub port: u16,
+    /// Orders written per transaction when upserting
+    pub upsert_batch_size: usize,
+    /// When order syncs run (cron with seconds, UTC)
+    pub sync_schedule: Schedule,
+    /// Upper bound for the sync delay while the TikTok API is unhealthy
+    pub sync_max_backoff_secs: u64,
+    /// Sync orders once at startup before reporting ready
+    pub startup_sync: bool,
+    /// When the stored access token is checked and refreshed ahead of expiry
+    pub token_refresh_schedule: Schedule,
+    /// When SKU stats are recounted and daily revenue is rolled up from stored orders
+    pub stats_rollup_schedule: Schedule,
+    /// Timezone of the business day that stats, rollups and exports count by
+    pub report_timezone: chrono_tz::Tz,
+    /// Currency order totals are converted into for reporting; unset disables conversion
+    pub base_currency: Option<String>,
+    /// Fixed base-currency value of one unit of each currency; wins over fetched rates
+    pub fx_rates: BTreeMap<String, f64>,
+    /// Exchange rate API polled for rates not fixed in `fx_rates`
+    pub fx_rates_url: Option<String>,
+    /// When rates are fetched from `fx_rates_url`
+    pub fx_refresh_schedule: Schedule,
+    /// Pause between order list pages fetched by a backfill
+    pub backfill_page_delay_ms: u64,
+    /// Archive orders not updated for this many days; unset keeps every order listed
+    pub archive_after_days: Option<u32>,
+    /// When the archival policy is applied
+    pub archive_schedule: Schedule,
+    /// Scrub buyer PII from orders not updated for this many days; unset keeps it
+    pub pii_retention_days: Option<u32>,
+    /// When the PII retention policy is applied
+    pub pii_retention_schedule: Schedule,
+    /// Encrypts buyer PII columns at rest; unset stores them in plaintext
+    pub pii_cipher: Option<PiiCipher>,
+    /// When the database is vacuumed and its statistics refreshed
+    pub maintenance_schedule: Schedule,
+    /// When stored orders are checked for fulfillment deadlines about to be missed
+    pub sla_check_schedule: Schedule,
+    /// Seconds before an RTS/shipping deadline at which an order is reported at risk
+    pub sla_alert_within_secs: i64,
+    /// When the daily digest of orders past their shipping deadline is sent
+    pub late_digest_schedule: Schedule,
+    /// When recently updated orders are compared against TikTok and re-fetched if they differ
+    pub reconcile_schedule: Schedule,
+    /// How far back a reconciliation looks at order updates
+    pub reconcile_window_secs: i64,
+    /// When pending buyer cancellation requests are pulled from TikTok
+    pub cancellation_sync_schedule: Schedule,
+    /// When the last days of shop performance metrics are snapshotted from TikTok
+    pub shop_performance_schedule: Schedule,
+    /// When the token's authorized shops are re-listed to pick up renamed or moved shops
+    pub shop_refresh_schedule: Schedule,
+    /// Rules tagging orders on sync, e.g. samples and gifts that are fulfilled differently
+    pub order_tag_rules: Vec<TagRule>,
+    /// Orders with any of these tags are left out of SKU sales stats
+    pub stats_exclude_tags: Vec<String>,
+    /// Named date windows the discount report groups orders by
+    pub promo_campaigns: Vec<Campaign>,
+    /// Region/province -> warehouse routes giving unshipped orders a preferred warehouse
+    pub warehouse_routes: Vec<WarehouseRoute>,
+    /// Shipping region codes the shop sells to; new orders shipped elsewhere score as risky
+    pub risk_ship_regions: Vec<String>,
+    /// Units in one order above which it scores as risky
+    pub risk_high_quantity: i64,
+    /// Risk score (0.0 - 1.0) at or above which a new order is reported
+    pub risk_alert_threshold: f64,
+    /// Cancel line items in TikTok when eSIM provisioning permanently fails
+    pub esim_auto_cancel: bool,
+    /// TikTok shipping provider auto-completed eSIM orders are self-shipped with
+    pub esim_shipping_provider_id: Option<String>,
+    /// When the data balance of provisioned eSIMs is polled from WowEsim
+    pub esim_usage_schedule: Schedule,
+    /// SMTP server provisioned eSIMs are emailed to buyers through
+    pub esim_smtp: Option<SmtpSettings>,
+    /// SMS gateway provisioned eSIMs are texted to buyers through
+    pub esim_sms: Option<SmsWebhookSettings>,
+    pub wow_api_base_url: String,
+    /// Signs WowEsim API calls; eSIMs are only provisioned outside sandbox mode when set
+    pub wow_secret: Option<String>,
+    pub job_max_attempts: u32,
+    /// Callback URL TikTok should push events to; reconciled on startup when set
+    pub webhook_address: Option<String>,
+    /// Event types to subscribe `webhook_address` to
+    pub webhook_events: Vec<String>,
+    /// Our own systems that order events are pushed to
+    pub outbound_webhooks: Vec<WebhookSubscriber>,
+    /// Message broker order events are relayed to through the event outbox
+    pub event_sink: Option<SinkTarget>,
+    /// Third-party logistics API orders awaiting shipment are pushed to
+    pub threepl: Option<ThreePlSettings>,
+    /// WooCommerce and Shopify stores paid TikTok orders are copied into
+    pub mirror_targets: Vec<MirrorTarget>,
+    /// S3-compatible bucket raw order pages and webhook payloads are archived to
+    pub payload_archive: Option<ArchiveSettings>,
+    /// Subject/topic prefix for relayed events; the event kind is appended
+    pub event_sink_prefix: String,
+    /// Redis cache in front of order and stats reads; unset disables caching
+    pub redis_url: Option<String>,
+    pub cache_ttl_secs: u64,
+    /// Required (as a bearer token, or as the basic-auth password) for `/admin`
+    /// routes; unset leaves them open
+    pub admin_token: Option<String>,
+    /// Identifies this process in leases and audit records
+    pub instance_id: String,
+    /// Overrides the TikTok API host for every shop (defaults depend on `mode`)
+    pub tiktok_api_base_url: Option<String>,
+    /// Shop region code (e.g. `US`) -> API host for shops in that region's data center
+    pub tiktok_region_base_urls: HashMap<String, String>,
+    /// Log redacted HTTP bodies of upstream API calls at debug level
+    pub log_http_bodies: bool,
+    /// Latency, 429s and malformed JSON injected into TikTok API calls
+    #[cfg(feature = "chaos")]
+    pub api_faults: Option<FaultConfig>,
+    /// Keep redacted TikTok API calls in the `api_call_log` table
+    pub record_api_calls: bool,
+    pub api_call_retention_days: i64,
+    /// Where API clients built from this config record their calls; set at startup
+    pub api_recorder: Option<Arc<dyn ApiRecorder>>,
+    /// Send references set with `POST /orders/{id}/external-ref` to TikTok
+    pub push_external_refs: bool,
+    /// Fetch the price detail of every synced order for accounting exports
+    pub fetch_price_details: bool,
+    /// Fetch the settlement (fees and payout) of every completed order for accounting exports
+    pub fetch_settlements: bool,
+    /// Contact, account codes and items that accounting exports book orders to
+    pub accounting_mapping: AccountingMapping,
+    /// How long the cached warehouses, delivery options and shipping providers are used
+    pub logistics_cache_hours: i64,
+    /// Warn when a SKU's inventory covers fewer days of sales than this; unset disables the check
+    pub low_stock_cover_days: Option<f64>,
+    pub packing_slip_title: String,
+    /// Shop name and return address printed on packing slips, one entry per line
+    pub packing_slip_header: Vec<String>,
+    pub packing_slip_footer: Option<String>,
+    pub packing_slip_show_prices: bool,
+    pub packing_slip_paper: Paper,
+    /// TrueType font for packing slips; needed for text outside Windows-1252
+    pub packing_slip_font: Option<String>,
 }
 
 impl Config {
-    pub fn from_env() -> Result<Self, AppError> {
+    /// Load settings from the optional TOML file and the environment.
+    ///
+    /// The file is `CONFIG_FILE` (default `config.toml`, skipped if absent) and
+    /// uses the field names below as keys; environment variables take
+    /// precedence. All problems are collected and reported together.
+    pub fn load() -> Result<Self, AppError> {
+        let mut source = Source::new()?;
+
+        let mode = source.parse("MODE", "mode", Mode::Production);
+        let instance_id = source.optional("INSTANCE_ID", "instance_id").unwrap_or_else(|| {
+            format!(
+                "{}-{}",
+                env::var("HOSTNAME").unwrap_or_else(|_| "local".to_string()),
+                std::process::id()
+            )
+        });
+        let sla_alert_within = source
+            .optional("SLA_ALERT_WITHIN", "sla_alert_within")
+            .unwrap_or_else(|| "6h".to_string());
+        let sla_alert_within_secs = sla::parse_duration(&sla_alert_within).unwrap_or_else(|e| {
+            source.error(format!("SLA_ALERT_WITHIN (sla_alert_within): {}", e));
+            0
+        });
+
+        let reconcile_window = source
+            .optional("RECONCILE_WINDOW", "reconcile_window")
+            .unwrap_or_else(|| "24h".to_string());
+        let reconcile_window_secs = sla::parse_duration(&reconcile_window).unwrap_or_else(|e| {
+            source.error(format!("RECONCILE_WINDOW (reconcile_window): {}", e));
+            0
+        });
+
+        let chaos_faults = source.optional("CHAOS_FAULTS", "chaos_faults");
+        #[cfg(feature = "chaos")]
+        let api_faults = chaos_faults
+            .map(|v| {
+                v.parse::<FaultConfig>().unwrap_or_else(|e| {
+                    source.error(format!("CHAOS_FAULTS (chaos_faults): {}", e));
+                    FaultConfig::default()
+                })
+            })
+            .filter(FaultConfig::is_active);
+        #[cfg(not(feature = "chaos"))]
+        if chaos_faults.is_some() {
+            source.error("CHAOS_FAULTS (chaos_faults) needs the `chaos` build feature".to_string());
+        }
+
+        let order_tag_rules = tags::parse_rules(
+            &source
+                .optional("ORDER_TAG_RULES", "order_tag_rules")
+                .unwrap_or_else(|| tags::DEFAULT_RULES.to_string()),
+        )
+        .unwrap_or_else(|e| {
+            source.error(format!("ORDER_TAG_RULES (order_tag_rules): {}", e));
+            Vec::new()
+        });
+
+        let promo_campaigns = promotions::parse_campaigns(
+            &source
+                .optional("PROMO_CAMPAIGNS", "promo_campaigns")
+                .unwrap_or_default(),
+        )
+        .unwrap_or_else(|e| {
+            source.error(format!("PROMO_CAMPAIGNS (promo_campaigns): {}", e));
+            Vec::new()
+        });
+
+        let warehouse_routes = warehouse_routing::parse_routes(
+            &source
+                .optional("WAREHOUSE_ROUTES", "warehouse_routes")
+                .unwrap_or_default(),
+        )
+        .unwrap_or_else(|e| {
+            source.error(format!("WAREHOUSE_ROUTES (warehouse_routes): {}", e));
+            Vec::new()
+        });
+
+        let base_currency = source
+            .optional("BASE_CURRENCY", "base_currency")
+            .and_then(|value| {
+                fx::parse_currency(&value)
+                    .map_err(|e| source.error(format!("BASE_CURRENCY (base_currency): {}", e)))
+                    .ok()
+            });
+        let fx_rates = fx::parse_rates(&source.optional("FX_RATES", "fx_rates").unwrap_or_default())
+            .unwrap_or_else(|e| {
+                source.error(format!("FX_RATES (fx_rates): {}", e));
+                BTreeMap::new()
+            });
+
+        let accounting_mapping = accounting::parse_mapping(
+            &source
+                .optional("ACCOUNTING_MAPPING", "accounting_mapping")
+                .unwrap_or_default(),
+        )
+        .unwrap_or_else(|e| {
+            source.error(format!("ACCOUNTING_MAPPING (accounting_mapping): {}", e));
+            AccountingMapping::default()
+        });
+
+        let outbound_webhooks = outbound_webhooks::parse_subscribers(
+            &source
+                .optional("OUTBOUND_WEBHOOKS", "outbound_webhooks")
+                .unwrap_or_default(),
+        )
+        .unwrap_or_else(|e| {
+            source.error(format!("OUTBOUND_WEBHOOKS (outbound_webhooks): {}", e));
+            Vec::new()
+        });
+
+        let event_sink = source
+            .optional("EVENT_SINK", "event_sink")
+            .and_then(|v| match SinkTarget::parse(&v) {
+                Ok(target) => Some(target),
+                Err(e) => {
+                    source.error(format!("EVENT_SINK (event_sink): {}", e));
+                    None
+                }
+            });
+
+        let threepl = source.optional("THREEPL_URL", "threepl_url").and_then(|url| {
+            let settings = ThreePlSettings {
+                url,
+                api_key: source.optional("THREEPL_API_KEY", "threepl_api_key"),
+                auth_header: source
+                    .optional("THREEPL_AUTH_HEADER", "threepl_auth_header")
+                    .unwrap_or_else(|| threepl::DEFAULT_AUTH_HEADER.to_string()),
+                callback_token: source
+                    .optional("THREEPL_CALLBACK_TOKEN", "threepl_callback_token")
+                    .unwrap_or_default(),
+                shipping_provider_id: source
+                    .optional("THREEPL_SHIPPING_PROVIDER_ID", "threepl_shipping_provider_id"),
+            };
+            match settings.validate() {
+                Ok(()) => Some(settings),
+                Err(e) => {
+                    source.error(format!("THREEPL_URL (threepl_url): {}", e));
+                    None
+                }
+            }
+        });
+
+        let mut mirror_targets = Vec::new();
+        if let Some(url) = source.url("WOOCOMMERCE_URL", "woocommerce_url") {
+            mirror_targets.push(MirrorTarget::WooCommerce {
+                url,
+                consumer_key: source
+                    .required("WOOCOMMERCE_CONSUMER_KEY", "woocommerce_consumer_key"),
+                consumer_secret: source
+                    .required("WOOCOMMERCE_CONSUMER_SECRET", "woocommerce_consumer_secret"),
+            });
+        }
+        if let Some(url) = source.url("SHOPIFY_STORE_URL", "shopify_store_url") {
+            mirror_targets.push(MirrorTarget::Shopify {
+                url,
+                access_token: source.required("SHOPIFY_ACCESS_TOKEN", "shopify_access_token"),
+                api_version: source
+                    .optional("SHOPIFY_API_VERSION", "shopify_api_version")
+                    .unwrap_or_else(|| mirror::DEFAULT_SHOPIFY_API_VERSION.to_string()),
+            });
+        }
+
+        let payload_archive = source.url("ARCHIVE_S3_URL", "archive_s3_url").map(|url| {
+            ArchiveSettings {
+                url,
+                region: source
+                    .optional("ARCHIVE_S3_REGION", "archive_s3_region")
+                    .unwrap_or_else(|| payload_archive::DEFAULT_S3_REGION.to_string()),
+                access_key_id: source
+                    .required("ARCHIVE_S3_ACCESS_KEY_ID", "archive_s3_access_key_id"),
+                secret_access_key: source
+                    .required("ARCHIVE_S3_SECRET_ACCESS_KEY", "archive_s3_secret_access_key"),
+                prefix: source.optional("ARCHIVE_S3_PREFIX", "archive_s3_prefix"),
+            }
+        });
+
+        let pii_cipher = pii_cipher_of(&mut source);
+
+        let esim_shipping_provider_id =
+            source.optional("ESIM_SHIPPING_PROVIDER_ID", "esim_shipping_provider_id");
+
+        let esim_smtp = source.optional("ESIM_SMTP_URL", "esim_smtp_url").and_then(|url| {
+            let settings = SmtpSettings {
+                url,
+                from: source.required("ESIM_EMAIL_FROM", "esim_email_from"),
+            };
+            match settings.validate() {
+                Ok(()) => Some(settings),
+                Err(e) => {
+                    source.error(format!("ESIM_SMTP_URL (esim_smtp_url): {}", e));
+                    None
+                }
+            }
+        });
+        let esim_sms = source.url("ESIM_SMS_URL", "esim_sms_url").map(|url| SmsWebhookSettings {
+            url,
+            api_key: source.optional("ESIM_SMS_API_KEY", "esim_sms_api_key"),
+        });
+
+        let secret_provider = source
+            .optional("SECRET_PROVIDER", "secret_provider")
+            .unwrap_or_else(|| "env".to_string());
+        let secret_source = match secret_provider.as_str() {
+            "env" => SecretSource::Env,
+            #[cfg(feature = "vault")]
+            "vault" => SecretSource::Vault(VaultSettings {
+                addr: source.required("VAULT_ADDR", "vault_addr"),
+                token: source.required("VAULT_TOKEN", "vault_token"),
+                mount: source
+                    .optional("VAULT_MOUNT", "vault_mount")
+                    .unwrap_or_else(|| secrets::DEFAULT_VAULT_MOUNT.to_string()),
+                path: source.required("VAULT_SECRET_PATH", "vault_secret_path"),
+            }),
+            #[cfg(feature = "aws-secrets")]
+            "aws" => SecretSource::AwsSecretsManager(AwsSecretsSettings {
+                region: source.required("AWS_REGION", "aws_region"),
+                access_key_id: source.required("AWS_ACCESS_KEY_ID", "aws_access_key_id"),
+                secret_access_key: source
+                    .required("AWS_SECRET_ACCESS_KEY", "aws_secret_access_key"),
+                session_token: source.optional("AWS_SESSION_TOKEN", "aws_session_token"),
+                prefix: source
+                    .optional("AWS_SECRET_PREFIX", "aws_secret_prefix")
+                    .unwrap_or_default(),
+            }),
+            other => {
+                let feature = match other {
+                    "vault" => Some("vault"),
+                    "aws" => Some("aws-secrets"),
+                    _ => None,
+                };
+                source.error(match feature {
+                    Some(feature) => format!(
+                        "SECRET_PROVIDER (secret_provider) {} needs a build with the {} feature",
+                        other, feature
+                    ),
+                    None => format!(
+                        "SECRET_PROVIDER (secret_provider) must be env, vault or aws, got {:?}",
+                        other
+                    ),
+                });
+                SecretSource::Env
+            }
+        };
+
+        let tenant_id = source.optional("TENANT_ID", "tenant_id");
+        if let Some(id) = &tenant_id {
+            if let Err(e) = tenants::validate_id(id) {
+                source.error(format!("TENANT_ID (tenant_id): {}", e));
+            }
+        }
+        // A tenant's app credentials come from the tenant registry
+        let (app_key, app_secret) = if tenant_id.is_some() {
+            for (var, key) in [("TIKTOK_APP_KEY", "app_key"), ("TIKTOK_APP_SECRET", "app_secret")] {
+                if source.optional(var, key).is_some() {
+                    source.error(format!(
+                        "{} ({}) is taken from the tenant registry when TENANT_ID is set",
+                        var, key
+                    ));
+                }
+            }
+            (String::new(), String::new())
+        } else {
+            let app_key = source.required("TIKTOK_APP_KEY", "app_key");
+            // An external secret provider may hold the app secret instead
+            let app_secret = if secret_source.is_external() {
+                source.optional("TIKTOK_APP_SECRET", "app_secret").unwrap_or_default()
+            } else {
+                source.required("TIKTOK_APP_SECRET", "app_secret")
+            };
+            (app_key, app_secret)
+        };
+
+        let data_dir = source.optional("DATA_DIR", "data_dir");
+        let tenant_root = under(
+            data_dir.as_deref(),
+            source
+                .optional("TENANT_ROOT", "tenant_root")
+                .unwrap_or_else(|| "tenants".to_string()),
+        );
+        // A tenant's own files live in its data directory
+        let tenant_dir = tenant_id.as_ref().map(|id| {
+            tenants::data_dir(Path::new(&tenant_root), id)
+                .to_string_lossy()
+                .into_owned()
+        });
+        let file_dir = tenant_dir.as_deref().or(data_dir.as_deref());
+
+        let config = Self {
+            mode,
+            tenant_registry: tenant_registry_of(&source),
+            tenant_root,
+            tenant_id,
+            secret_source,
+            app_key,
+            app_secret,
+            shop_cipher: source.optional("TIKTOK_SHOP_CIPHER", "shop_cipher"),
+            shop_id: source.optional("TIKTOK_SHOP_ID", "shop_id"),
+            redirect_uri: source
+                .url("TIKTOK_REDIRECT_URI", "redirect_uri")
+                .unwrap_or_else(|| oauth_state::DEFAULT_REDIRECT_URI.to_string()),
+            token_file: under(
+                file_dir,
+                source
+                    .optional("TIKTOK_TOKEN_FILE", "token_file")
+                    .unwrap_or_else(|| TokenStorage::DEFAULT_STORAGE_FILE.to_string()),
+            ),
+            database_path: under(
+                file_dir,
+                source
+                    .optional("DATABASE_PATH", "database_path")
+                    .unwrap_or_else(|| "orders.db".to_string()),
+            ),
+            documents_dir: under(
+                file_dir,
+                source
+                    .optional("DOCUMENTS_DIR", "documents_dir")
+                    .unwrap_or_else(|| "documents".to_string()),
+            ),
+            exports_dir: under(
+                file_dir,
+                source.optional("EXPORTS_DIR", "exports_dir").unwrap_or_else(|| {
+                    let default = if data_dir.is_some() { "exports" } else { "." };
+                    default.to_string()
+                }),
+            ),
+            data_dir,
+            sqlite_journal_mode: source.parse(
+                "SQLITE_JOURNAL_MODE",
+                "sqlite_journal_mode",
+                SqliteJournalMode::Wal,
+            ),
+            sqlite_synchronous: source.parse(
+                "SQLITE_SYNCHRONOUS",
+                "sqlite_synchronous",
+                SqliteSynchronous::Normal,
+            ),
+            sqlite_busy_timeout_ms: source.parse(
+                "SQLITE_BUSY_TIMEOUT_MS",
+                "sqlite_busy_timeout_ms",
+                5000,
+            ),
+            sqlite_foreign_keys: source.flag_or("SQLITE_FOREIGN_KEYS", "sqlite_foreign_keys", true),
+            sqlite_max_connections: source.parse(
+                "SQLITE_MAX_CONNECTIONS",
+                "sqlite_max_connections",
+                5,
+            ),
+            host: source
+                .optional("HOST", "host")
+                .unwrap_or_else(|| "0.0.0.0".to_string()),
+            port: source.parse("PORT", "port", 3000),
+            upsert_batch_size: source.parse("UPSERT_BATCH_SIZE", "upsert_batch_size", 500),
+            sync_schedule: source.parse("SYNC_SCHEDULE", "sync_schedule", schedule("0 0 * * * *")),
+            sync_max_backoff_secs: source.parse(
+                "SYNC_MAX_BACKOFF_SECS",
+                "sync_max_backoff_secs",
+                6 * 3600,
+            ),
+            startup_sync: source.flag("STARTUP_SYNC", "startup_sync"),
+            token_refresh_schedule: source.parse(
+                "TOKEN_REFRESH_SCHEDULE",
+                "token_refresh_schedule",
+                schedule("0 */15 * * * *"),
+            ),
+            stats_rollup_schedule: source.parse(
+                "STATS_ROLLUP_SCHEDULE",
+                "stats_rollup_schedule",
+                schedule("0 30 3 * * *"),
+            ),
+            report_timezone: source.parse("REPORT_TIMEZONE", "report_timezone", chrono_tz::UTC),
+            base_currency,
+            fx_rates,
+            fx_rates_url: source.url("FX_RATES_URL", "fx_rates_url"),
+            fx_refresh_schedule: source.parse(
+                "FX_REFRESH_SCHEDULE",
+                "fx_refresh_schedule",
+                schedule("0 0 */6 * * *"),
+            ),
+            backfill_page_delay_ms: source.parse(
+                "BACKFILL_PAGE_DELAY_MS",
+                "backfill_page_delay_ms",
+                1000,
+            ),
+            archive_after_days: source.parse_optional("ARCHIVE_AFTER_DAYS", "archive_after_days"),
+            archive_schedule: source.parse(
+                "ARCHIVE_SCHEDULE",
+                "archive_schedule",
+                schedule("0 0 4 * * *"),
+            ),
+            pii_retention_days: source.parse_optional("PII_RETENTION_DAYS", "pii_retention_days"),
+            pii_retention_schedule: source.parse(
+                "PII_RETENTION_SCHEDULE",
+                "pii_retention_schedule",
+                schedule("0 15 4 * * *"),
+            ),
+            pii_cipher,
+            maintenance_schedule: source.parse(
+                "MAINTENANCE_SCHEDULE",
+                "maintenance_schedule",
+                schedule("0 30 4 * * *"),
+            ),
+            sla_check_schedule: source.parse(
+                "SLA_CHECK_SCHEDULE",
+                "sla_check_schedule",
+                schedule("0 */10 * * * *"),
+            ),
+            sla_alert_within_secs,
+            late_digest_schedule: source.parse(
+                "LATE_DIGEST_SCHEDULE",
+                "late_digest_schedule",
+                schedule("0 0 1 * * *"),
+            ),
+            reconcile_schedule: source.parse(
+                "RECONCILE_SCHEDULE",
+                "reconcile_schedule",
+                schedule("0 20 * * * *"),
+            ),
+            reconcile_window_secs,
+            cancellation_sync_schedule: source.parse(
+                "CANCELLATION_SYNC_SCHEDULE",
+                "cancellation_sync_schedule",
+                schedule("0 */5 * * * *"),
+            ),
+            shop_performance_schedule: source.parse(
+                "SHOP_PERFORMANCE_SCHEDULE",
+                "shop_performance_schedule",
+                schedule("0 0 5 * * *"),
+            ),
+            shop_refresh_schedule: source.parse(
+                "SHOP_REFRESH_SCHEDULE",
+                "shop_refresh_schedule",
+                schedule("0 10 */6 * * *"),
+            ),
+            order_tag_rules,
+            stats_exclude_tags: source
+                .optional("STATS_EXCLUDE_TAGS", "stats_exclude_tags")
+                .unwrap_or_default()
+                .split(',')
+                .filter_map(tags::normalize_tag)
+                .collect(),
+            promo_campaigns,
+            warehouse_routes,
+            risk_ship_regions: source
+                .optional("RISK_SHIP_REGIONS", "risk_ship_regions")
+                .unwrap_or_else(|| "VN".to_string())
+                .split(',')
+                .map(|r| r.trim().to_ascii_uppercase())
+                .filter(|r| !r.is_empty())
+                .collect(),
+            risk_high_quantity: source.parse("RISK_HIGH_QUANTITY", "risk_high_quantity", 10),
+            risk_alert_threshold: source.parse("RISK_ALERT_THRESHOLD", "risk_alert_threshold", 0.8),
+            esim_auto_cancel: source.flag("ESIM_AUTO_CANCEL_ON_FAILURE", "esim_auto_cancel"),
+            esim_shipping_provider_id,
+            esim_usage_schedule: source.parse(
+                "ESIM_USAGE_SCHEDULE",
+                "esim_usage_schedule",
+                schedule("0 40 */6 * * *"),
+            ),
+            esim_smtp,
+            esim_sms,
+            wow_api_base_url: source
+                .url("WOW_API_BASE_URL", "wow_api_base_url")
+                .unwrap_or_else(|| wow_requests::DEFAULT_API_BASE_URL.to_string()),
+            wow_secret: source.optional("WOW_SECRET", "wow_secret"),
+            job_max_attempts: source.parse("JOB_MAX_ATTEMPTS", "job_max_attempts", 5),
+            webhook_address: source.url("TIKTOK_WEBHOOK_URL", "webhook_address"),
+            webhook_events: source
+                .optional("TIKTOK_WEBHOOK_EVENTS", "webhook_events")
+                .unwrap_or_else(|| "ORDER_STATUS_CHANGE".to_string())
+                .split(',')
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty())
+                .collect(),
+            outbound_webhooks,
+            event_sink,
+            threepl,
+            mirror_targets,
+            payload_archive,
+            admin_token: source.optional("ADMIN_TOKEN", "admin_token"),
+            event_sink_prefix: source
+                .optional("EVENT_SINK_PREFIX", "event_sink_prefix")
+                .unwrap_or_else(|| outbox::DEFAULT_SUBJECT_PREFIX.to_string()),
+            redis_url: source.optional("REDIS_URL", "redis_url"),
+            cache_ttl_secs: source.parse("CACHE_TTL_SECS", "cache_ttl_secs", 60),
+            instance_id,
+            tiktok_api_base_url: source.url("TIKTOK_API_BASE_URL", "tiktok_api_base_url"),
+            tiktok_region_base_urls: source
+                .optional("TIKTOK_REGION_BASE_URLS", "tiktok_region_base_urls")
+                .map(|v| parse_key_value_list(&v))
+                .unwrap_or_default()
+                .into_iter()
+                .map(|(region, url)| (region.to_ascii_uppercase(), url))
+                .collect(),
+            log_http_bodies: source.flag("LOG_HTTP_BODIES", "log_http_bodies"),
+            #[cfg(feature = "chaos")]
+            api_faults,
+            record_api_calls: source.flag("RECORD_API_CALLS", "record_api_calls"),
+            api_call_retention_days: source.parse(
+                "API_CALL_RETENTION_DAYS",
+                "api_call_retention_days",
+                3,
+            ),
+            api_recorder: None,
+            fetch_price_details: source.flag("FETCH_PRICE_DETAILS", "fetch_price_details"),
+            fetch_settlements: source.flag("FETCH_SETTLEMENTS", "fetch_settlements"),
+            accounting_mapping,
+            push_external_refs: source.flag("PUSH_EXTERNAL_REFS", "push_external_refs"),
+            logistics_cache_hours: source.parse("LOGISTICS_CACHE_HOURS", "logistics_cache_hours", 24),
+            low_stock_cover_days: source.parse_optional("LOW_STOCK_COVER_DAYS", "low_stock_cover_days"),
+            packing_slip_title: source
+                .optional("PACKING_SLIP_TITLE", "packing_slip_title")
+                .unwrap_or_else(|| "Packing Slip".to_string()),
+            packing_slip_header: source
+                .optional("PACKING_SLIP_HEADER", "packing_slip_header")
+                .map(|v| v.split('|').map(|line| line.trim().to_string()).collect())
+                .unwrap_or_default(),
+            packing_slip_footer: source.optional("PACKING_SLIP_FOOTER", "packing_slip_footer"),
+            packing_slip_show_prices: source.flag_or(
+                "PACKING_SLIP_SHOW_PRICES",
+                "packing_slip_show_prices",
+                true,
+            ),
+            packing_slip_paper: source.parse("PACKING_SLIP_PAPER", "packing_slip_paper", Paper::A4),
+            packing_slip_font: source.optional("PACKING_SLIP_FONT", "packing_slip_font"),
+        };
+
+        for (region, url) in &config.tiktok_region_base_urls {
+            if reqwest::Url::parse(url).is_err() {
+                source.error(format!(
+                    "TIKTOK_REGION_BASE_URLS has an invalid URL for {}: {:?}",
+                    region, url
+                ));
+            }
+        }
+
+        if config.low_stock_cover_days.is_some_and(|d| d.is_nan() || d <= 0.0) {
+            source.error("LOW_STOCK_COVER_DAYS (low_stock_cover_days) must be positive".to_string());
+        }
+        if let Some(font) = &config.packing_slip_font {
+            if !std::path::Path::new(font).is_file() {
+                source.error(format!("PACKING_SLIP_FONT (packing_slip_font) {:?} is not a file", font));
+            }
+        }
+        if !(0.0..=1.0).contains(&config.risk_alert_threshold) {
+            source.error("RISK_ALERT_THRESHOLD (risk_alert_threshold) must be between 0 and 1".to_string());
+        }
+        if config.event_sink_prefix.trim_matches('.').is_empty() {
+            source.error("EVENT_SINK_PREFIX (event_sink_prefix) must not be empty".to_string());
+        }
+        if let Some(url) = &config.redis_url {
+            let scheme = reqwest::Url::parse(url).map(|u| u.scheme().to_string());
+            if !matches!(scheme.as_deref(), Ok("redis" | "rediss" | "redis+unix" | "unix")) {
+                source.error(format!("REDIS_URL (redis_url) must be a redis:// URL, got {:?}", url));
+            }
+        }
+        if config.cache_ttl_secs == 0 {
+            source.error("CACHE_TTL_SECS (cache_ttl_secs) must be at least 1".to_string());
+        }
+        if config.api_call_retention_days < 1 {
+            source.error("API_CALL_RETENTION_DAYS (api_call_retention_days) must be at least 1".to_string());
+        }
+        if config.logistics_cache_hours < 1 {
+            source.error("LOGISTICS_CACHE_HOURS (logistics_cache_hours) must be at least 1".to_string());
+        }
+        let fx_configured = !config.fx_rates.is_empty() || config.fx_rates_url.is_some();
+        if config.base_currency.is_none() && fx_configured {
+            source.error("FX_RATES and FX_RATES_URL need BASE_CURRENCY to convert into".to_string());
+        }
+        if config.reconcile_window_secs == 0 {
+            source.error("RECONCILE_WINDOW (reconcile_window) must be longer than 0".to_string());
+        }
+        if config.sqlite_max_connections == 0 {
+            source.error("SQLITE_MAX_CONNECTIONS (sqlite_max_connections) must be at least 1".to_string());
+        }
+        if config.archive_after_days == Some(0) {
+            source.error("ARCHIVE_AFTER_DAYS (archive_after_days) must be at least 1".to_string());
+        }
+        if config.pii_retention_days == Some(0) {
+            source.error("PII_RETENTION_DAYS (pii_retention_days) must be at least 1".to_string());
+        }
+        if config.upsert_batch_size == 0 {
+            source.error("UPSERT_BATCH_SIZE (upsert_batch_size) must be at least 1".to_string());
+        }
+        for (var, key) in [
+            ("ESIM_SKU_PACKAGES", "esim_sku_packages"),
+            ("ESIM_AUTO_COMPLETE_SKUS", "esim_auto_complete_skus"),
+        ] {
+            if source.optional(var, key).is_some() {
+                source.error(format!(
+                    "{} was replaced by the SKU mapping table; PUT the SKUs to /admin/sku-mappings as esim_provision actions",
+                    var
+                ));
+            }
+        }
+        if source.optional("SYNC_INTERVAL_SECS", "sync_interval_secs").is_some() {
+            source.error(
+                "SYNC_INTERVAL_SECS was replaced by SYNC_SCHEDULE, a cron expression with seconds (e.g. \"0 0 * * * *\" for hourly)"
+                    .to_string(),
+            );
+        }
+        let sync_period = scheduler::period(&config.sync_schedule).unwrap_or_default();
+        if config.sync_max_backoff_secs < sync_period.as_secs() {
+            source.error(
+                "SYNC_MAX_BACKOFF_SECS must not be smaller than the SYNC_SCHEDULE period".to_string(),
+            );
+        }
+
+        source.finish()?;
+        Ok(config)
+    }
+
+    /// Tagging rules and stats exclusions applied on upsert
+    pub fn order_tagger(&self) -> OrderTagger {
+        OrderTagger::new(self.order_tag_rules.clone())
+            .with_stats_excluded(self.stats_exclude_tags.clone())
+    }
+
+    /// Preferred-warehouse routing applied on upsert
+    pub fn warehouse_router(&self) -> WarehouseRouter {
+        WarehouseRouter::new(self.warehouse_routes.clone())
+    }
+
+    /// Order total conversion into `BASE_CURRENCY`, if set. Rates are loaded
+    /// separately since fetched ones are stored in the database.
+    pub fn fx_converter(&self) -> Option<FxConverter> {
+        self.base_currency.clone().map(FxConverter::new)
+    }
+
+    /// Fraud signals evaluated on each new order
+    pub fn risk_scorer(&self) -> RiskScorer {
+        RiskScorer::new(self.risk_alert_threshold)
+            .with_evaluator(RegionMismatch::new(self.risk_ship_regions.clone()))
+            .with_evaluator(HighQuantity::new(self.risk_high_quantity))
+            .with_evaluator(RepeatedCancellations::new(risk::REPEATED_CANCELLATIONS))
+    }
+
+    /// Connection settings for the order database
+    pub fn sqlite_settings(&self) -> SqliteSettings {
+        SqliteSettings {
+            journal_mode: self.sqlite_journal_mode,
+            synchronous: self.sqlite_synchronous,
+            busy_timeout: Duration::from_millis(self.sqlite_busy_timeout_ms),
+            foreign_keys: self.sqlite_foreign_keys,
+            max_connections: self.sqlite_max_connections,
+        }
+    }
+
+    /// Layout options for `GET /orders/{id}/packing-slip.pdf`
+    pub fn packing_slip_template(&self) -> PackingSlipTemplate {
+        PackingSlipTemplate {
+            title: self.packing_slip_title.clone(),
+            header_lines: self.packing_slip_header.clone(),
+            footer: self.packing_slip_footer.clone(),
+            show_prices: self.packing_slip_show_prices,
+            paper: self.packing_slip_paper,
+            font_path: self.packing_slip_font.as_ref().map(Into::into),
+        }
+    }
+
+    /// Address the HTTP server binds to
+    pub fn bind_address(&self) -> String {
+        format!("{}:{}", self.host, self.port)
+    }
+
+    pub fn is_sandbox(&self) -> bool {
+        self.mode == Mode::Sandbox
+    }
+
+    /// Take the app credentials, and the shop unless one is configured, from
+    /// the registry entry of the tenant this process serves
+    pub fn apply_tenant(&mut self, tenant: &Tenant) {
+        self.app_key = tenant.app_key.clone();
+        self.app_secret = tenant.app_secret.clone();
+        if self.shop_id.is_none() {
+            self.shop_id = tenant.shop_id.clone();
+        }
+        if self.shop_cipher.is_none() {
+            self.shop_cipher = tenant.shop_cipher.clone();
+        }
+    }
+
+    /// Data directory of the tenant this process serves, which its token
+    /// file, database and documents resolve under
+    pub fn tenant_dir(&self) -> Option<PathBuf> {
+        self.tenant_id
+            .as_ref()
+            .map(|id| tenants::data_dir(Path::new(&self.tenant_root), id))
+    }
+
+    /// Create the directories persisted files go to and check that each is
+    /// writable, so a bad volume mount fails at startup instead of on the
+    /// first write
+    pub fn prepare_directories(&self) -> Result<Vec<PathBuf>, AppError> {
+        let parent = |path: &str| {
+            Path::new(path)
+                .parent()
+                .map(Path::to_path_buf)
+                .unwrap_or_default()
+        };
+        let mut dirs = vec![
+            parent(&self.database_path),
+            parent(&self.token_file),
+            PathBuf::from(&self.documents_dir),
+            PathBuf::from(&self.exports_dir),
+        ];
+        dirs.extend(self.data_dir.as_ref().map(PathBuf::from));
+        if let Some(tenant_dir) = self.tenant_dir() {
+            dirs.push(parent(&self.tenant_registry));
+            dirs.push(PathBuf::from(&self.tenant_root));
+            dirs.push(tenant_dir);
+        }
+        for dir in dirs.iter_mut() {
+            if dir.as_os_str().is_empty() {
+                *dir = PathBuf::from(".");
+            }
+        }
+        dirs.sort();
+        dirs.dedup();
+
+        for dir in &dirs {
+            std::fs::create_dir_all(dir).map_err(|e| {
+                AppError::ConfigError(format!("Cannot create directory {}: {}", dir.display(), e))
+            })?;
+            let probe = dir.join(format!(".write-check-{}", std::process::id()));
+            std::fs::write(&probe, b"")
+                .and_then(|()| std::fs::remove_file(&probe))
+                .map_err(|e| {
+                    AppError::ConfigError(format!(
+                        "Directory {} is not writable ({}); check the owner and mode of the volume",
+                        dir.display(),
+                        e
+                    ))
+                })?;
+        }
+        Ok(dirs)
+    }
+
+    /// Fill the app secret, WowEsim secret and admin token from `provider`
+    /// where neither the environment nor the config file set them
+    pub async fn resolve_secrets(&mut self, provider: &dyn SecretProvider) -> Result<(), AppError> {
+        if self.tenant_id.is_none() && self.app_secret.is_empty() {
+            self.app_secret = provider.get(secrets::APP_SECRET).await?.ok_or_else(|| {
+                AppError::ConfigError(format!(
+                    "TIKTOK_APP_SECRET (app_secret) is set neither in the environment, the config file nor {}",
+                    provider.describe()
+                ))
+            })?;
+        }
+        if self.wow_secret.is_none() {
+            self.wow_secret = provider.get(secrets::WOW_SECRET).await?;
+        }
+        if self.admin_token.is_none() {
+            self.admin_token = provider.get(secrets::ADMIN_TOKEN).await?;
+        }
+        Ok(())
+    }
+
+    /// Keep the credentials `current` got at startup from the tenant registry
+    /// or the secret provider, which a reloaded config doesn't have
+    fn keep_resolved_secrets(&mut self, current: &Config) {
+        if self.app_key.is_empty() {
+            self.app_key = current.app_key.clone();
+        }
+        if self.app_secret.is_empty() {
+            self.app_secret = current.app_secret.clone();
+        }
+        if self.wow_secret.is_none() {
+            self.wow_secret = current.wow_secret.clone();
+        }
+        if self.admin_token.is_none() {
+            self.admin_token = current.admin_token.clone();
+        }
+    }
+
+    /// TikTok API client pointed at the global host for the configured mode
+    pub fn tiktok_api_client(&self) -> TikTokShopApiClient {
+        self.tiktok_api_client_for_region(None)
+    }
+
+    /// TikTok API client for a shop in `region`.
+    ///
+    /// `TIKTOK_API_BASE_URL` wins, then the sandbox host in sandbox mode, then
+    /// the region's entry in `TIKTOK_REGION_BASE_URLS`, then the global host.
+    pub fn tiktok_api_client_for_region(&self, region: Option<&str>) -> TikTokShopApiClient {
+        let regional = region
+            .map(str::to_ascii_uppercase)
+            .and_then(|region| self.tiktok_region_base_urls.get(&region));
+
+        let base_url = match (&self.tiktok_api_base_url, self.mode, regional) {
+            (Some(url), _, _) => url.as_str(),
+            (None, Mode::Sandbox, _) => TikTokShopApiClient::SANDBOX_API_BASE_URL,
+            (None, Mode::Production, Some(url)) => url.as_str(),
+            (None, Mode::Production, None) => TikTokShopApiClient::API_BASE_URL,
+        };
+
+        let client = TikTokShopApiClient::new(self.app_key.clone(), self.app_secret.clone())
+            .with_base_url(base_url)
+            .with_body_logging(self.log_http_bodies)
+            .with_recorder(self.api_recorder.clone());
+        #[cfg(feature = "chaos")]
+        let client = match &self.api_faults {
+            Some(faults) => client.with_faults(faults.clone()),
+            None => client,
+        };
+        client
+    }
+
+    /// Record calls of API clients built from this config
+    pub fn with_api_recorder(mut self, recorder: Arc<dyn ApiRecorder>) -> Self {
+        self.api_recorder = Some(recorder);
+        self
+    }
+
+    /// OAuth client for the configured app
+    pub fn oauth_client(&self) -> TikTokShopOAuth {
+        TikTokShopOAuth::new(self.app_key.clone(), self.app_secret.clone())
+            .with_body_logging(self.log_http_bodies)
+    }
+
+    /// Source of valid tokens, refreshed through [`Config::oauth_client`]
+    pub fn token_manager(&self) -> TokenManager {
+        TokenManager::new(self.oauth_client())
+    }
+
+    /// Whether eSIMs can be provisioned: always in sandbox mode, otherwise
+    /// with `WOW_SECRET` set
+    pub fn provisions_esims(&self) -> bool {
+        self.is_sandbox() || self.wow_secret.is_some()
+    }
+
+    /// WowEsim client for the configured mode: a stub that never touches the
+    /// network in sandbox mode
+    pub fn wow_client(&self) -> Result<WowEsimApiClient, AppError> {
+        if self.is_sandbox() {
+            return Ok(WowEsimApiClient::sandbox());
+        }
+        let secret = self.wow_secret.clone().ok_or_else(|| {
+            AppError::ConfigError("WOW_SECRET (wow_secret) is required but not set".to_string())
+        })?;
+        Ok(WowEsimApiClient::new(secret)
+            .with_base_url(&self.wow_api_base_url)
+            .with_body_logging(self.log_http_bodies))
+    }
+
+    /// Scheduler task names and the schedules they run on
+    pub fn task_schedules(&self) -> Vec<(&'static str, &Schedule)> {
+        vec![
+            ("order_sync", &self.sync_schedule),
+            ("token_refresh", &self.token_refresh_schedule),
+            ("stats_rollup", &self.stats_rollup_schedule),
+            ("fx_refresh", &self.fx_refresh_schedule),
+            ("order_archive", &self.archive_schedule),
+            ("pii_retention", &self.pii_retention_schedule),
+            ("db_maintenance", &self.maintenance_schedule),
+            ("sla_check", &self.sla_check_schedule),
+            ("late_digest", &self.late_digest_schedule),
+            ("reconcile", &self.reconcile_schedule),
+            ("cancellation_sync", &self.cancellation_sync_schedule),
+            ("shop_refresh", &self.shop_refresh_schedule),
+            ("shop_performance", &self.shop_performance_schedule),
+            ("esim_usage", &self.esim_usage_schedule),
+        ]
+    }
+
+    /// Copy of this config with the settings that are safe to change at
+    /// runtime taken from `new`, and the names of those that changed
+    fn with_reloadable(&self, new: &Config) -> (Config, Vec<String>) {
+        let mut next = self.clone();
+        let mut changed = Vec::new();
+
+        let schedules = [
+            ("sync_schedule", &mut next.sync_schedule, &new.sync_schedule),
+            (
+                "token_refresh_schedule",
+                &mut next.token_refresh_schedule,
+                &new.token_refresh_schedule,
+            ),
+            ("stats_rollup_schedule", &mut next.stats_rollup_schedule, &new.stats_rollup_schedule),
+            ("fx_refresh_schedule", &mut next.fx_refresh_schedule, &new.fx_refresh_schedule),
+            ("archive_schedule", &mut next.archive_schedule, &new.archive_schedule),
+            (
+                "pii_retention_schedule",
+                &mut next.pii_retention_schedule,
+                &new.pii_retention_schedule,
+            ),
+            ("maintenance_schedule", &mut next.maintenance_schedule, &new.maintenance_schedule),
+            ("sla_check_schedule", &mut next.sla_check_schedule, &new.sla_check_schedule),
+            ("late_digest_schedule", &mut next.late_digest_schedule, &new.late_digest_schedule),
+            ("reconcile_schedule", &mut next.reconcile_schedule, &new.reconcile_schedule),
+            (
+                "cancellation_sync_schedule",
+                &mut next.cancellation_sync_schedule,
+                &new.cancellation_sync_schedule,
+            ),
+            ("shop_refresh_schedule", &mut next.shop_refresh_schedule, &new.shop_refresh_schedule),
+            (
+                "shop_performance_schedule",
+                &mut next.shop_performance_schedule,
+                &new.shop_performance_schedule,
+            ),
+            ("esim_usage_schedule", &mut next.esim_usage_schedule, &new.esim_usage_schedule),
+        ];
+        for (name, current, new) in schedules {
+            if current.to_string() != new.to_string() {
+                *current = new.clone();
+                changed.push(name.to_string());
+            }
+        }
+        if next.sync_max_backoff_secs != new.sync_max_backoff_secs {
+            next.sync_max_backoff_secs = new.sync_max_backoff_secs;
+            changed.push("sync_max_backoff_secs".to_string());
+        }
+        if next.backfill_page_delay_ms != new.backfill_page_delay_ms {
+            next.backfill_page_delay_ms = new.backfill_page_delay_ms;
+            changed.push("backfill_page_delay_ms".to_string());
+        }
+        let subscribers = |config: &Config| -> Vec<(String, String)> {
+            config
+                .outbound_webhooks
+                .iter()
+                .map(|s| (s.url.clone(), s.secret.clone()))
+                .collect()
+        };
+        if subscribers(&next) != subscribers(new) {
+            next.outbound_webhooks = new.outbound_webhooks.clone();
+            changed.push("outbound_webhooks".to_string());
+        }
+
+        (next, changed)
+    }
+}
+
+/// What [`LiveConfig::reload`] changed
+#[derive(Debug, Clone)]
+pub struct ConfigReload {
+    /// Settings now in effect, e.g. `sync_schedule`
+    pub applied: Vec<String>,
+    /// Whether other settings changed too; those take a restart
+    pub restart_required: bool,
+}
+
+/// The running server's config. Reloading re-reads the environment and the
+/// config file and swaps in the schedules, sync backoff, backfill page delay
+/// and outbound webhooks; every other setting keeps its startup value.
+#[derive(Clone)]
+pub struct LiveConfig {
+    current: Arc<RwLock<Arc<Config>>>,
+}
+
+impl LiveConfig {
+    pub fn new(config: Config) -> Self {
+        Self {
+            current: Arc::new(RwLock::new(Arc::new(config))),
+        }
+    }
+
+    pub fn current(&self) -> Arc<Config> {
+        self.current.read().unwrap().clone()
+    }
+
+    /// Load the config again and apply what can change at runtime. An
+    /// invalid config changes nothing.
+    pub fn reload(&self) -> Result<ConfigReload, AppError> {
+        let mut loaded = Config::load()?;
+        let mut current = self.current.write().unwrap();
+        loaded.keep_resolved_secrets(&current);
+        let (next, applied) = current.with_reloadable(&loaded);
+
+        // Anything else that differs only applies after a restart
+        loaded.api_recorder = next.api_recorder.clone();
+        let restart_required = format!("{:?}", loaded) != format!("{:?}", next);
+
+        *current = Arc::new(next);
+        Ok(ConfigReload {
+            applied,
+            restart_required,
+        })
+    }
+}
+
+/// Looks settings up in the environment first, then in the config file,
+/// collecting every problem instead of stopping at the first
+struct Source {
+    file: toml::Table,
+    errors: Vec<String>,
+}
+
+impl Source {
+    fn new() -> Result<Self, AppError> {
+        let (path, explicit) = match env::var("CONFIG_FILE") {
+            Ok(path) => (path, true),
+            Err(_) => ("config.toml".to_string(), false),
+        };
+
+        let file = match std::fs::read_to_string(&path) {
+            Ok(contents) => contents.parse::<toml::Table>().map_err(|e| {
+                AppError::ConfigError(format!("Invalid config file {}: {}", path, e))
+            })?,
+            Err(e) if explicit || e.kind() != std::io::ErrorKind::NotFound => {
+                return Err(AppError::ConfigError(format!(
+                    "Cannot read config file {}: {}",
+                    path, e
+                )));
+            }
+            Err(_) => toml::Table::new(),
+        };
+
         Ok(Self {
-            app_key: env::var("TIKTOK_APP_KEY")
-                .map_err(|_| AppError::ConfigError("TIKTOK_APP_KEY not set".to_string()))?,
-            app_secret: env::var("TIKTOK_APP_SECRET")
-                .map_err(|_| AppError::ConfigError("TIKTOK_APP_SECRET not set".to_string()))?,
-            shop_cipher: env::var("TIKTOK_SHOP_CIPHER").ok(),
-            shop_id: env::var("TIKTOK_SHOP_ID").ok(),
-            token_file: env::var("TIKTOK_TOKEN_FILE")
-                .unwrap_or_else(|_| "token.json".to_string()),
-            database_path: env::var("DATABASE_PATH")
-                .unwrap_or_else(|_| "orders.db".to_string()),
+            file,
+            errors: Vec::new(),
+        })
+    }
+
+    fn optional(&self, var: &str, key: &str) -> Option<String> {
+        if let Ok(value) = env::var(var) {
+            return Some(value).filter(|v| !v.is_empty());
+        }
+
+        match self.file.get(key)? {
+            toml::Value::String(v) => Some(v.clone()).filter(|v| !v.is_empty()),
+            toml::Value::Array(items) => Some(
+                items
+                    .iter()
+                    .map(|v| v.as_str().map(str::to_string).unwrap_or_else(|| v.to_string()))
+                    .collect::<Vec<_>>()
+                    .join(","),
+            ),
+            toml::Value::Table(table) => Some(
+                table
+                    .iter()
+                    .map(|(k, v)| format!("{}={}", k, v.as_str().unwrap_or_default()))
+                    .collect::<Vec<_>>()
+                    .join(","),
+            ),
+            other => Some(other.to_string()),
+        }
+    }
+
+    fn required(&mut self, var: &str, key: &str) -> String {
+        self.optional(var, key).unwrap_or_else(|| {
+            self.errors
+                .push(format!("{} ({}) is required but not set", var, key));
+            String::new()
         })
     }
+
+    fn parse<T>(&mut self, var: &str, key: &str, default: T) -> T
+    where
+        T: std::str::FromStr,
+        T::Err: std::fmt::Display,
+    {
+        self.parse_optional(var, key).unwrap_or(default)
+    }
+
+    fn parse_optional<T>(&mut self, var: &str, key: &str) -> Option<T>
+    where
+        T: std::str::FromStr,
+        T::Err: std::fmt::Display,
+    {
+        let value = self.optional(var, key)?;
+        match value.trim().parse() {
+            Ok(parsed) => Some(parsed),
+            Err(e) => {
+                self.errors
+                    .push(format!("{} ({}) has invalid value {:?}: {}", var, key, value, e));
+                None
+            }
+        }
+    }
+
+    fn flag(&mut self, var: &str, key: &str) -> bool {
+        self.flag_or(var, key, false)
+    }
+
+    fn flag_or(&mut self, var: &str, key: &str, default: bool) -> bool {
+        match self.optional(var, key).as_deref().map(str::to_ascii_lowercase).as_deref() {
+            None => default,
+            Some("false") | Some("0") | Some("no") => false,
+            Some("true") | Some("1") | Some("yes") => true,
+            Some(other) => {
+                self.errors
+                    .push(format!("{} ({}) must be true or false, got {:?}", var, key, other));
+                default
+            }
+        }
+    }
+
+    fn url(&mut self, var: &str, key: &str) -> Option<String> {
+        let value = self.optional(var, key)?;
+        match reqwest::Url::parse(&value) {
+            Ok(url) if url.scheme() == "http" || url.scheme() == "https" => Some(value),
+            Ok(_) => {
+                self.errors
+                    .push(format!("{} ({}) must be an http(s) URL, got {:?}", var, key, value));
+                None
+            }
+            Err(e) => {
+                self.errors
+                    .push(format!("{} ({}) is not a valid URL ({}): {:?}", var, key, e, value));
+                None
+            }
+        }
+    }
+
+    fn error(&mut self, message: String) {
+        self.errors.push(message);
+    }
+
+    fn finish(self) -> Result<(), AppError> {
+        if self.errors.is_empty() {
+            return Ok(());
+        }
+
+        Err(AppError::ConfigError(format!(
+            "{} problem(s) found:\n  - {}",
+            self.errors.len(),
+            self.errors.join("\n  - ")
+        )))
+    }
+}
+
+/// A built-in default schedule
+/// Config file settings are read from: `CONFIG_FILE`, or `config.toml`
+pub fn file_path() -> String {
+    env::var("CONFIG_FILE").unwrap_or_else(|_| "config.toml".to_string())
+}
+
+/// `TENANT_REGISTRY`, for the `tenant` command, which runs without app credentials
+pub fn tenant_registry() -> Result<String, AppError> {
+    Ok(tenant_registry_of(&Source::new()?))
+}
+
+fn tenant_registry_of(source: &Source) -> String {
+    under(
+        source.optional("DATA_DIR", "data_dir").as_deref(),
+        source
+            .optional("TENANT_REGISTRY", "tenant_registry")
+            .unwrap_or_else(|| "tenants.db".to_string()),
+    )
+}
+
+/// `PII_ENCRYPTION_KEY`, for the `tenant` command, which runs without app credentials
+pub fn pii_cipher() -> Result<Option<PiiCipher>, AppError> {
+    let mut source = Source::new()?;
+    let cipher = pii_cipher_of(&mut source);
+    source.finish()?;
+    Ok(cipher)
+}
+
+fn pii_cipher_of(source: &mut Source) -> Option<PiiCipher> {
+    source
+        .optional("PII_ENCRYPTION_KEY", "pii_encryption_key")
+        .and_then(|key| match PiiCipher::from_base64(&key) {
+            Ok(cipher) => Some(cipher),
+            Err(e) => {
+                source.error(format!("PII_ENCRYPTION_KEY (pii_encryption_key): {}", e));
+                None
+            }
+        })
+}
+
+/// `path` resolved under `dir`, unless it is absolute or there is no `dir`
+pub fn under(dir: Option<&str>, path: String) -> String {
+    match dir {
+        Some(dir) if Path::new(&path).is_relative() => {
+            Path::new(dir).join(&path).to_string_lossy().into_owned()
+        }
+        _ => path,
+    }
+}
+
+fn schedule(expression: &str) -> Schedule {
+    scheduler::parse_schedule(expression).expect("default schedules are valid")
+}
+
+/// Parse `KEY1=VALUE1,KEY2=VALUE2` into a map, ignoring malformed entries
+fn parse_key_value_list(value: &str) -> HashMap<String, String> {
+    value
+        .split(',')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(k, v)| (k.trim().to_string(), v.trim().to_string()))
+        .filter(|(k, v)| !k.is_empty() && !v.is_empty())
+        .collect()
 }
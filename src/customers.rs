@@ -0,0 +1,204 @@
+use crate::database::Database;
+use crate::jobs::{Job, JobHandler};
+use crate::order::Order;
+use crate::pii_crypto::PiiCipher;
+use async_trait::async_trait;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use sqlx::sqlite::{Sqlite, SqlitePool};
+use sqlx::{Row, Transaction};
+use std::sync::Arc;
+use tracing::info;
+use utoipa::ToSchema;
+
+/// Job that links orders stored before customers existed
+pub const BACKFILL_JOB_KIND: &str = "customer_backfill";
+
+/// A buyer, keyed by a hash of their TikTok user ID (or email when there is none)
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Customer {
+    pub id: String,
+    /// SHA-256 of the TikTok `user_id`
+    pub user_id_hash: Option<String>,
+    /// SHA-256 of the lower-cased buyer email
+    pub email_hash: Option<String>,
+    /// Recipient name on the most recent order
+    pub name: Option<String>,
+    /// Recipient region on the most recent order
+    pub region: Option<String>,
+    pub first_order_time: i64,
+    pub last_order_time: i64,
+    pub order_count: i64,
+}
+
+/// Identity fields of the buyer of one order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuyerIdentity {
+    pub customer_id: String,
+    pub user_id_hash: Option<String>,
+    pub email_hash: Option<String>,
+}
+
+impl BuyerIdentity {
+    /// Identify the buyer of `order`, or `None` if it carries neither a user ID nor an email
+    pub fn of(order: &Order) -> Option<Self> {
+        let user_id = order.user_id.as_deref().map(str::trim).filter(|v| !v.is_empty());
+        let email = order
+            .buyer_email
+            .as_deref()
+            .map(|v| v.trim().to_lowercase())
+            .filter(|v| !v.is_empty());
+
+        let user_id_hash = user_id.map(hash);
+        let email_hash = email.as_deref().map(hash);
+        let key = match (user_id, &email) {
+            (Some(user_id), _) => format!("user:{}", user_id),
+            (None, Some(email)) => format!("email:{}", email),
+            (None, None) => return None,
+        };
+
+        Some(Self {
+            customer_id: hash(&key)[..32].to_string(),
+            user_id_hash,
+            email_hash,
+        })
+    }
+}
+
+fn hash(value: &str) -> String {
+    hex::encode(Sha256::digest(value.as_bytes()))
+}
+
+/// Buyers derived from stored orders, in the `customers` table
+#[derive(Clone)]
+pub struct CustomerStore {
+    pool: SqlitePool,
+    cipher: Option<PiiCipher>,
+}
+
+impl CustomerStore {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool, cipher: None }
+    }
+
+    /// Decrypt names stored encrypted (see [`PiiCipher`])
+    pub fn with_pii_cipher(mut self, cipher: Option<PiiCipher>) -> Self {
+        self.cipher = cipher;
+        self
+    }
+
+    /// Initialize the customers table
+    pub async fn init(&self) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS customers (
+                id TEXT PRIMARY KEY,
+                user_id_hash TEXT,
+                email_hash TEXT,
+                name TEXT,
+                region TEXT,
+                first_order_time INTEGER NOT NULL,
+                last_order_time INTEGER NOT NULL
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Create or update the buyer of `order` within an upsert transaction.
+    /// Name and region follow the buyer's most recent order; the name is
+    /// encrypted with `cipher` when set.
+    pub async fn record(
+        tx: &mut Transaction<'_, Sqlite>,
+        buyer: &BuyerIdentity,
+        order: &Order,
+        cipher: Option<&PiiCipher>,
+    ) -> Result<(), sqlx::Error> {
+        let address = order.recipient_address.as_ref();
+        let mut name = address.and_then(|a| a.name.clone());
+        if let Some(cipher) = cipher {
+            cipher.encrypt_field(&mut name);
+        }
+
+        sqlx::query(
+            "INSERT INTO customers (
+                id, user_id_hash, email_hash, name, region, first_order_time, last_order_time
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?6)
+            ON CONFLICT(id) DO UPDATE SET
+                user_id_hash = COALESCE(customers.user_id_hash, excluded.user_id_hash),
+                email_hash = COALESCE(customers.email_hash, excluded.email_hash),
+                name = CASE WHEN excluded.last_order_time >= customers.last_order_time
+                    THEN COALESCE(excluded.name, customers.name) ELSE customers.name END,
+                region = CASE WHEN excluded.last_order_time >= customers.last_order_time
+                    THEN COALESCE(excluded.region, customers.region) ELSE customers.region END,
+                first_order_time = MIN(customers.first_order_time, excluded.first_order_time),
+                last_order_time = MAX(customers.last_order_time, excluded.last_order_time)",
+        )
+        .bind(&buyer.customer_id)
+        .bind(&buyer.user_id_hash)
+        .bind(&buyer.email_hash)
+        .bind(name)
+        .bind(address.and_then(|a| a.region_code.as_deref()))
+        .bind(order.create_time)
+        .execute(&mut **tx)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get(&self, id: &str) -> Result<Option<Customer>, sqlx::Error> {
+        let row = sqlx::query(
+            "SELECT c.id, c.user_id_hash, c.email_hash, c.name, c.region,
+                c.first_order_time, c.last_order_time,
+                (SELECT COUNT(*) FROM orders o WHERE o.customer_id = c.id) AS order_count
+            FROM customers c WHERE c.id = ?1",
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.map(|row| {
+            let mut name = row.try_get("name")?;
+            if let Some(cipher) = &self.cipher {
+                cipher.decrypt_field(&mut name);
+            }
+            Ok(Customer {
+                id: row.try_get("id")?,
+                user_id_hash: row.try_get("user_id_hash")?,
+                email_hash: row.try_get("email_hash")?,
+                name,
+                region: row.try_get("region")?,
+                first_order_time: row.try_get("first_order_time")?,
+                last_order_time: row.try_get("last_order_time")?,
+                order_count: row.try_get("order_count")?,
+            })
+        })
+        .transpose()
+    }
+}
+
+/// Links orders stored before customer tracking existed
+pub struct CustomerBackfillHandler {
+    db: Arc<Database>,
+}
+
+impl CustomerBackfillHandler {
+    pub fn new(db: Arc<Database>) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl JobHandler for CustomerBackfillHandler {
+    async fn handle(&self, _job: &Job) -> Result<(), String> {
+        let linked = self
+            .db
+            .link_unlinked_customers()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        info!("Customer backfill linked {} orders", linked);
+        Ok(())
+    }
+}
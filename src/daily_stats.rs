@@ -0,0 +1,303 @@
+use crate::config::Config;
+use crate::database::Database;
+use crate::jobs::{Job, JobHandler};
+use crate::order::Order;
+use crate::reporting;
+use crate::shops::ShopRegistry;
+use async_trait::async_trait;
+use chrono::{NaiveDate, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::{Sqlite, SqlitePool};
+use sqlx::{Row, Transaction};
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
+use std::sync::Arc;
+use tracing::info;
+use utoipa::ToSchema;
+
+/// Rebuild `daily_stats` from every stored order
+pub const ROLLUP_JOB_KIND: &str = "daily_stats_rollup";
+
+/// Days shown by a timeseries without `from`
+pub const DEFAULT_RANGE_DAYS: i64 = 30;
+
+/// Most days a timeseries may span
+pub const MAX_RANGE_DAYS: i64 = 731;
+
+/// Order statuses that never count as revenue
+const EXCLUDED_STATUSES: &[&str] = &["UNPAID", "CANCELLED"];
+
+/// Revenue and order count of one shop, day and currency
+#[derive(Debug, Clone, PartialEq)]
+pub struct DailyTotal {
+    pub day: String,
+    pub currency: String,
+    pub revenue: f64,
+    pub order_count: i64,
+}
+
+/// Value plotted by a timeseries
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Metric {
+    Revenue,
+    Orders,
+    /// Average order value: revenue over orders
+    Aov,
+}
+
+impl FromStr for Metric {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "revenue" => Ok(Metric::Revenue),
+            "orders" => Ok(Metric::Orders),
+            "aov" => Ok(Metric::Aov),
+            _ => Err(format!(
+                "invalid metric {:?}, expected revenue, orders or aov",
+                value
+            )),
+        }
+    }
+}
+
+/// Width of a timeseries bucket
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Granularity {
+    Day,
+    /// Weeks start on Monday
+    Week,
+    Month,
+}
+
+impl Granularity {
+    /// SQLite expression for the first day of the bucket holding `day`
+    fn bucket_sql(&self) -> &'static str {
+        match self {
+            Granularity::Day => "day",
+            Granularity::Week => "date(day, '-6 days', 'weekday 1')",
+            Granularity::Month => "strftime('%Y-%m-01', day)",
+        }
+    }
+}
+
+impl FromStr for Granularity {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "day" => Ok(Granularity::Day),
+            "week" => Ok(Granularity::Week),
+            "month" => Ok(Granularity::Month),
+            _ => Err(format!(
+                "invalid granularity {:?}, expected day, week or month",
+                value
+            )),
+        }
+    }
+}
+
+/// One bucket of a timeseries, per currency
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TimeseriesPoint {
+    /// First day of the bucket, `YYYY-MM-DD`
+    pub period: String,
+    pub currency: String,
+    pub value: f64,
+}
+
+/// Revenue, order count and AOV per shop per business day (see
+/// `REPORT_TIMEZONE`), in the `daily_stats` table. Rebuilt from stored orders by the nightly stats rollup so charts
+/// read a few rows per day instead of aggregating raw orders.
+#[derive(Clone)]
+pub struct DailyStatsStore {
+    pool: SqlitePool,
+}
+
+impl DailyStatsStore {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Initialize the daily_stats table
+    pub async fn init(&self) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS daily_stats (
+                shop_id TEXT NOT NULL,
+                day TEXT NOT NULL,
+                currency TEXT NOT NULL,
+                revenue REAL NOT NULL,
+                order_count INTEGER NOT NULL,
+                aov REAL NOT NULL,
+                updated_at INTEGER NOT NULL,
+                PRIMARY KEY (shop_id, day, currency)
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Replace every row of `shop_id` within a transaction
+    pub async fn replace(
+        tx: &mut Transaction<'_, Sqlite>,
+        shop_id: &str,
+        totals: &[DailyTotal],
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query("DELETE FROM daily_stats WHERE shop_id = ?1")
+            .bind(shop_id)
+            .execute(&mut **tx)
+            .await?;
+
+        let now = Utc::now().timestamp();
+        for total in totals {
+            let aov = if total.order_count > 0 {
+                total.revenue / total.order_count as f64
+            } else {
+                0.0
+            };
+            sqlx::query(
+                "INSERT INTO daily_stats (shop_id, day, currency, revenue, order_count, aov,
+                    updated_at)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            )
+            .bind(shop_id)
+            .bind(&total.day)
+            .bind(&total.currency)
+            .bind(total.revenue)
+            .bind(total.order_count)
+            .bind(aov)
+            .bind(now)
+            .execute(&mut **tx)
+            .await?;
+        }
+        Ok(())
+    }
+
+    /// `metric` per bucket from `from` to `to` (inclusive days), summed over
+    /// shops unless `shop_id` is given
+    pub async fn timeseries(
+        &self,
+        metric: Metric,
+        granularity: Granularity,
+        from: NaiveDate,
+        to: NaiveDate,
+        shop_id: Option<&str>,
+    ) -> Result<Vec<TimeseriesPoint>, sqlx::Error> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} AS period, currency, SUM(revenue) AS revenue,
+                SUM(order_count) AS order_count
+            FROM daily_stats
+            WHERE day >= ?1 AND day <= ?2 AND (?3 IS NULL OR shop_id = ?3)
+            GROUP BY period, currency
+            ORDER BY period, currency",
+            granularity.bucket_sql()
+        ))
+        .bind(from.to_string())
+        .bind(to.to_string())
+        .bind(shop_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter()
+            .map(|row| {
+                let revenue: f64 = row.try_get("revenue")?;
+                let order_count: i64 = row.try_get("order_count")?;
+                let value = match metric {
+                    Metric::Revenue => revenue,
+                    Metric::Orders => order_count as f64,
+                    Metric::Aov if order_count > 0 => revenue / order_count as f64,
+                    Metric::Aov => 0.0,
+                };
+                Ok(TimeseriesPoint {
+                    period: row.try_get("period")?,
+                    currency: row.try_get("currency")?,
+                    value,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Revenue per business day in `timezone` and currency of the orders that
+/// count towards stats. Unpaid and cancelled orders, replacements and orders
+/// without payment details are left out.
+///
+/// With `converted` (a base currency and the converted total of each order
+/// id), orders are counted in the base currency where they have a converted
+/// total and in their own currency otherwise.
+pub fn daily_totals<'a>(
+    orders: impl IntoIterator<Item = &'a Order>,
+    timezone: Tz,
+    converted: Option<(&str, &HashMap<String, f64>)>,
+) -> Vec<DailyTotal> {
+    let mut totals: BTreeMap<(String, String), (f64, i64)> = BTreeMap::new();
+    for order in orders {
+        if EXCLUDED_STATUSES
+            .iter()
+            .any(|s| order.status.eq_ignore_ascii_case(s))
+            || order.is_replacement_order == Some(true)
+        {
+            continue;
+        }
+        let Some(payment) = &order.payment else {
+            continue;
+        };
+        let (currency, amount) =
+            match converted.and_then(|(base, amounts)| Some((base, *amounts.get(&order.id)?))) {
+                Some((base, amount)) => (base.to_string(), amount),
+                None => (
+                    payment.currency.clone(),
+                    payment.total_amount.trim().parse::<f64>().unwrap_or(0.0),
+                ),
+            };
+        let entry = totals
+            .entry((reporting::day_of(order.create_time, timezone), currency))
+            .or_default();
+        entry.0 += amount;
+        entry.1 += 1;
+    }
+
+    totals
+        .into_iter()
+        .map(|((day, currency), (revenue, order_count))| DailyTotal {
+            day,
+            currency,
+            revenue,
+            order_count,
+        })
+        .collect()
+}
+
+/// Rebuilds `daily_stats` outside the nightly rollup, e.g. on first start
+pub struct DailyStatsRollupHandler {
+    db: Arc<Database>,
+    shops: ShopRegistry,
+    config: Config,
+}
+
+impl DailyStatsRollupHandler {
+    pub fn new(db: Arc<Database>, shops: ShopRegistry, config: Config) -> Self {
+        Self { db, shops, config }
+    }
+}
+
+#[async_trait]
+impl JobHandler for DailyStatsRollupHandler {
+    async fn handle(&self, _job: &Job) -> Result<(), String> {
+        let shop_id = self.shops.configured_shop_id(&self.config)
+            .await
+            .map_err(|e| e.to_string())?;
+        let days = self
+            .db
+            .rebuild_daily_stats(&shop_id)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        info!("Rolled up daily stats for {} days", days);
+        Ok(())
+    }
+}
@@ -0,0 +1,274 @@
+use crate::health::{CircuitState, HealthSnapshot};
+use crate::jobs::JobCounts;
+use crate::order::Order;
+use crate::scheduler::TaskStatus;
+use crate::storage::TokenInfo;
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use maud::{html, Markup, PreEscaped, DOCTYPE};
+
+/// Seconds between automatic reloads of the dashboard
+const REFRESH_SECS: u32 = 30;
+
+/// Keeps the `data-until` countdowns ticking between reloads
+const COUNTDOWN_SCRIPT: &str = r#"
+function tick() {
+  const now = Date.now();
+  document.querySelectorAll('[data-until]').forEach((el) => {
+    let secs = Math.round((Number(el.dataset.until) - now) / 1000);
+    const past = secs < 0;
+    secs = Math.abs(secs);
+    const d = Math.floor(secs / 86400), h = Math.floor(secs % 86400 / 3600),
+      m = Math.floor(secs % 3600 / 60), s = secs % 60;
+    const span = d > 0 ? `${d}d ${h}h` : h > 0 ? `${h}h ${m}m` : `${m}m ${s}s`;
+    el.textContent = past ? `expired ${span} ago` : `in ${span}`;
+  });
+}
+tick();
+setInterval(tick, 1000);
+"#;
+
+const STYLE: &str = "
+body { font: 14px/1.4 system-ui, sans-serif; margin: 2rem; color: #222; }
+h1 { font-size: 1.4rem; margin-bottom: 0; }
+h2 { font-size: 1.1rem; margin-top: 2rem; }
+table { border-collapse: collapse; }
+th, td { text-align: left; padding: .25rem .75rem .25rem 0; border-bottom: 1px solid #eee; }
+.muted { color: #777; }
+.ok { color: #1a7f37; }
+.warn { color: #9a6700; }
+.bad { color: #cf222e; font-weight: 600; }
+.cards { display: flex; gap: 1rem; flex-wrap: wrap; }
+.card { border: 1px solid #ddd; border-radius: 6px; padding: .75rem 1rem; min-width: 10rem; }
+.card b { display: block; font-size: 1.4rem; }
+";
+
+/// Everything shown on `GET /admin`
+pub struct DashboardView {
+    pub mode: String,
+    pub generated_at: DateTime<Utc>,
+    pub token: Option<TokenInfo>,
+    pub tasks: Vec<TaskStatus>,
+    pub api_health: HealthSnapshot,
+    pub jobs: JobCounts,
+    pub order_count: i64,
+    /// Orders created since the business day began
+    pub orders_today: i64,
+    /// Timezone of the business day, also used for order times
+    pub report_timezone: Tz,
+    pub recent_orders: Vec<Order>,
+}
+
+/// Render the dashboard page
+pub fn render(view: &DashboardView) -> Markup {
+    html! {
+        (DOCTYPE)
+        html lang="en" {
+            head {
+                meta charset="utf-8";
+                meta http-equiv="refresh" content=(REFRESH_SECS);
+                title { "toptop-order admin" }
+                style { (PreEscaped(STYLE)) }
+            }
+            body {
+                h1 { "toptop-order" }
+                p.muted {
+                    (view.mode) " mode · "
+                    (view.generated_at.format("%Y-%m-%d %H:%M:%S UTC"))
+                    " · reloads every " (REFRESH_SECS) "s"
+                }
+                (errors(view))
+                (token(view.token.as_ref(), view.generated_at))
+                (tasks(&view.tasks, view.generated_at))
+                (recent_orders(view))
+                script { (PreEscaped(COUNTDOWN_SCRIPT)) }
+            }
+        }
+    }
+}
+
+fn errors(view: &DashboardView) -> Markup {
+    let failing_tasks = view.tasks.iter().filter(|t| !t.is_healthy()).count();
+    let health = &view.api_health;
+    html! {
+        h2 { "Errors" }
+        div.cards {
+            div.card {
+                b class=(level(failing_tasks as i64, 1)) { (failing_tasks) }
+                "failing scheduled tasks"
+            }
+            div.card {
+                b class=(level(view.jobs.dead_letters, 1)) { (view.jobs.dead_letters) }
+                a href="/admin/dead-letters" { "dead letters" }
+            }
+            div.card {
+                b class=(level(view.jobs.retrying, 10)) { (view.jobs.retrying) }
+                "jobs retrying, of " (view.jobs.pending) " pending"
+            }
+            div.card {
+                b class=(level(health.consecutive_failures as i64, 3)) {
+                    (format!("{:.0}%", health.error_rate * 100.0))
+                }
+                "TikTok API errors over " (health.recent_calls) " calls · circuit "
+                span class=(match health.circuit {
+                    CircuitState::Closed => "ok",
+                    CircuitState::HalfOpen => "warn",
+                    CircuitState::Open => "bad",
+                }) {
+                    (match health.circuit {
+                        CircuitState::Closed => "closed",
+                        CircuitState::HalfOpen => "half-open",
+                        CircuitState::Open => "open",
+                    })
+                }
+            }
+        }
+    }
+}
+
+fn token(token: Option<&TokenInfo>, now: DateTime<Utc>) -> Markup {
+    html! {
+        h2 { "Token" }
+        @match token {
+            Some(token) => table {
+                tr {
+                    th { "Access token" }
+                    td { (countdown(token.expires_at, now, Some(3600))) }
+                    td.muted { (token.expires_at.format("%Y-%m-%d %H:%M UTC")) }
+                }
+                tr {
+                    th { "Refresh token" }
+                    td { (countdown(token.refresh_token_expires_at, now, Some(7 * 86400))) }
+                    td.muted { (token.refresh_token_expires_at.format("%Y-%m-%d %H:%M UTC")) }
+                }
+            },
+            None => p.bad {
+                "No token stored. " a href="/auth/tiktok" { "Authorize the app" }
+            },
+        }
+    }
+}
+
+fn tasks(tasks: &[TaskStatus], now: DateTime<Utc>) -> Markup {
+    html! {
+        h2 { "Sync and scheduled tasks" }
+        table {
+            tr {
+                th { "Task" } th { "Last run" } th { "Outcome" } th { "Next run" } th { "Detail" }
+            }
+            @for task in tasks {
+                tr {
+                    td { (task.name) }
+                    td {
+                        @match task.last_started_at {
+                            Some(at) => (ago(at, now)),
+                            None => span.muted { "never" },
+                        }
+                    }
+                    td {
+                        @match task.last_outcome.as_deref() {
+                            Some("failed") => span.bad {
+                                "failed ×" (task.consecutive_failures)
+                            },
+                            Some("skipped") => span.warn { "skipped" },
+                            Some(outcome) => span.ok { (outcome) },
+                            None => span.muted { "-" },
+                        }
+                    }
+                    td {
+                        @if let Some(at) = task.next_run_at {
+                            (countdown(at, now, None))
+                        }
+                    }
+                    td.muted { (task.last_detail.as_deref().unwrap_or_default()) }
+                }
+            }
+        }
+    }
+}
+
+fn recent_orders(view: &DashboardView) -> Markup {
+    html! {
+        h2 {
+            "Recent orders"
+            span.muted {
+                " (" (view.order_count) " stored, " (view.orders_today) " today, "
+                (view.report_timezone.name()) ")"
+            }
+        }
+        table {
+            tr { th { "Order" } th { "Status" } th { "Created" } th { "Total" } }
+            @for order in &view.recent_orders {
+                tr {
+                    td { a href=(format!("/orders/{}", order.id)) { (order.id) } }
+                    td { (order.status) }
+                    td {
+                        (DateTime::from_timestamp(order.create_time, 0)
+                            .map(|t| {
+                                t.with_timezone(&view.report_timezone)
+                                    .format("%Y-%m-%d %H:%M")
+                                    .to_string()
+                            })
+                            .unwrap_or_default())
+                    }
+                    td {
+                        @if let Some(payment) = &order.payment {
+                            (payment.total_amount) " " (payment.currency)
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Server-rendered time until `until`, kept ticking by `COUNTDOWN_SCRIPT`.
+/// With `warn_within`, it is highlighted as that deadline approaches.
+fn countdown(until: DateTime<Utc>, now: DateTime<Utc>, warn_within: Option<i64>) -> Markup {
+    let secs = (until - now).num_seconds();
+    let class = match warn_within {
+        None => "",
+        Some(_) if secs < 0 => "bad",
+        Some(within) if secs < within => "warn",
+        Some(_) => "ok",
+    };
+    let text = if secs < 0 {
+        format!("expired {} ago", span(-secs))
+    } else {
+        format!("in {}", span(secs))
+    };
+    html! {
+        span class=(class) data-until=(until.timestamp_millis()) { (text) }
+    }
+}
+
+fn ago(at: DateTime<Utc>, now: DateTime<Utc>) -> String {
+    format!("{} ago", span((now - at).num_seconds().max(0)))
+}
+
+fn span(secs: i64) -> String {
+    let (d, h, m, s) = (
+        secs / 86400,
+        secs % 86400 / 3600,
+        secs % 3600 / 60,
+        secs % 60,
+    );
+    if d > 0 {
+        format!("{}d {}h", d, h)
+    } else if h > 0 {
+        format!("{}h {}m", h, m)
+    } else {
+        format!("{}m {}s", m, s)
+    }
+}
+
+/// CSS class for an error count
+fn level(count: i64, bad_at: i64) -> &'static str {
+    if count >= bad_at {
+        "bad"
+    } else if count > 0 {
+        "warn"
+    } else {
+        "ok"
+    }
+}
@@ -1,23 +1,474 @@
+use crate::address::{self, AddressStore};
+use crate::anonymize;
+use crate::cache::OrderCache;
+use crate::customers::{BuyerIdentity, CustomerStore};
+use crate::daily_stats::{self, DailyStatsStore};
+use crate::events::{EventBus, OrderEvent, OrderEventKind};
+use crate::fx::{FxConverter, FxStore};
+use crate::item_fulfillment::ItemFulfillmentStore;
+use crate::lookup;
 use crate::order::Order;
-use sqlx::sqlite::{SqlitePool, SqlitePoolOptions};
+use crate::order_schema::{self, ORDER_SCHEMA_VERSION};
+use crate::outbox::OutboxStore;
+use crate::pii_crypto::{self, PiiCipher};
+use crate::replacements::ReplacementStore;
+use crate::risk::{RiskScorer, RiskStore};
+use crate::sku_stats::SkuStatsStore;
+use crate::sla;
+use crate::tags::{OrderTagger, TagStore};
+use crate::validation::{AnomalyStore, OrderValidator};
+use crate::warehouse_routing::{WarehouseAssignmentStore, WarehouseRouter};
+use serde::Serialize;
+use sqlx::sqlite::{
+    Sqlite, SqliteArguments, SqliteAutoVacuum, SqliteConnectOptions, SqliteJournalMode, SqlitePool, SqlitePoolOptions, SqliteRow,
+    SqliteSynchronous,
+};
 use sqlx::Row;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
+use std::time::Duration;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::StreamExt;
+use tracing::warn;
+use utoipa::ToSchema;
 
 pub struct Database {
     pool: SqlitePool,
+    upsert_batch_size: usize,
+    events: Option<EventBus>,
+    validator: Option<OrderValidator>,
+    link_customers: bool,
+    track_sku_stats: bool,
+    normalize_addresses: bool,
+    track_item_fulfillment: bool,
+    tagger: Option<OrderTagger>,
+    link_replacements: bool,
+    risk_scorer: Option<RiskScorer>,
+    warehouse_router: Option<WarehouseRouter>,
+    fx: Option<FxConverter>,
+    report_timezone: chrono_tz::Tz,
+    record_outbox: bool,
+    cache: Option<OrderCache>,
+    pii_cipher: Option<PiiCipher>,
+}
+
+/// Pragmas and pool size applied to every SQLite connection
+#[derive(Debug, Clone, Copy)]
+pub struct SqliteSettings {
+    pub journal_mode: SqliteJournalMode,
+    pub synchronous: SqliteSynchronous,
+    /// How long a connection waits on a locked database before failing
+    pub busy_timeout: Duration,
+    pub foreign_keys: bool,
+    pub max_connections: u32,
+}
+
+impl Default for SqliteSettings {
+    /// WAL lets API reads proceed while a sync writes
+    fn default() -> Self {
+        Self {
+            journal_mode: SqliteJournalMode::Wal,
+            synchronous: SqliteSynchronous::Normal,
+            busy_timeout: Duration::from_secs(5),
+            foreign_keys: true,
+            max_connections: 5,
+        }
+    }
+}
+
+/// Outcome of an upsert batch
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct UpsertStats {
+    pub inserted: usize,
+    pub updated: usize,
+    pub skipped: usize,
+}
+
+/// Outcome of an eager order blob upgrade
+#[derive(Debug, Default, Clone, Copy, Serialize)]
+pub struct SchemaUpgradeReport {
+    pub upgraded: usize,
+    pub failed: usize,
+}
+
+/// A buyer's note on an order and whether someone has acted on it
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct BuyerMessage {
+    pub order_id: String,
+    pub status: String,
+    pub create_time: i64,
+    pub message: String,
+    /// When the message was acknowledged; cleared if the buyer changes it
+    pub acked_at: Option<i64>,
+}
+
+/// Change counter of the orders table, bumped by triggers on every write
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OrdersVersion {
+    pub counter: i64,
+    /// Unix time of the last write, or of the last shipping deadline that
+    /// passed if that is later
+    pub changed_at: i64,
+    /// When the counter was created, so a recreated database never reuses a version
+    pub created_at: i64,
+    /// Orders past their shipping deadline, which listings flag as late
+    /// without any write
+    pub late: i64,
+}
+
+impl OrdersVersion {
+    /// Strong entity tag for responses that list every stored order
+    pub fn etag(&self) -> String {
+        format!(
+            "\"orders-{:x}-{:x}-{:x}\"",
+            self.created_at, self.counter, self.late
+        )
+    }
+}
+
+/// Count writes to `table` as changes of order listings: the orders table
+/// itself, and the tables listings filter on or enrich orders from. Creates
+/// the counter if needed, so stores can call it from their `init`.
+pub async fn track_orders_version(pool: &SqlitePool, table: &str) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS table_versions (
+            name TEXT PRIMARY KEY,
+            counter INTEGER NOT NULL,
+            changed_at INTEGER NOT NULL,
+            created_at INTEGER NOT NULL
+        )"
+    )
+    .execute(pool)
+    .await?;
+
+    let now = chrono::Utc::now().timestamp();
+    sqlx::query(
+        "INSERT OR IGNORE INTO table_versions (name, counter, changed_at, created_at)
+        VALUES ('orders', 0, ?1, ?1)"
+    )
+    .bind(now)
+    .execute(pool)
+    .await?;
+
+    for event in ["INSERT", "UPDATE", "DELETE"] {
+        sqlx::query(&format!(
+            "CREATE TRIGGER IF NOT EXISTS {0}_version_{1} AFTER {2} ON {0} BEGIN
+                UPDATE table_versions
+                SET counter = counter + 1, changed_at = CAST(strftime('%s', 'now') AS INTEGER)
+                WHERE name = 'orders';
+            END",
+            table,
+            event.to_lowercase(),
+            event
+        ))
+        .execute(pool)
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Count and latest deadline of pending orders due before `?1`. Spells out
+/// the expression of `idx_orders_status_due`, so SQLite ranges over the index
+/// and reads only late orders instead of parsing every pending order's JSON.
+fn late_orders_query() -> String {
+    let placeholders = (2..2 + sla::PENDING_STATUSES.len())
+        .map(|i| format!("?{}", i))
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!(
+        "SELECT COUNT(*) AS late, MAX(json_extract(data, '$.shipping_due_time')) AS last_due
+        FROM orders
+        WHERE status IN ({})
+        AND json_extract(data, '$.shipping_due_time') > 0
+        AND json_extract(data, '$.shipping_due_time') < ?1",
+        placeholders
+    )
+}
+
+/// Position in the `create_time DESC, id DESC` order listing
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OrderCursor {
+    pub create_time: i64,
+    pub id: String,
+}
+
+impl OrderCursor {
+    /// Opaque token handed to clients
+    pub fn encode(&self) -> String {
+        hex::encode(format!("{}:{}", self.create_time, self.id))
+    }
+
+    pub fn decode(token: &str) -> Option<Self> {
+        let decoded = String::from_utf8(hex::decode(token.trim()).ok()?).ok()?;
+        let (create_time, id) = decoded.split_once(':')?;
+        Some(Self {
+            create_time: create_time.parse().ok()?,
+            id: id.to_string(),
+        })
+    }
+}
+
+/// Which stored orders a listing includes
+#[derive(Debug, Clone, Default)]
+pub struct OrderFilter {
+    /// Also include orders moved out of listings by the archival policy
+    pub include_archived: bool,
+    /// Match normalized addresses (see [`AddressStore`]), case-insensitively
+    pub province: Option<String>,
+    pub district: Option<String>,
+    pub ward: Option<String>,
+    /// Only orders carrying all of these tags (see [`TagStore`])
+    pub tags: Vec<String>,
+    /// Only orders with at least this risk score (see [`RiskStore`])
+    pub min_risk: Option<f64>,
+}
+
+impl OrderFilter {
+    fn filters_address(&self) -> bool {
+        self.province.is_some() || self.district.is_some() || self.ward.is_some()
+    }
+
+    /// SQL condition on `orders`, using parameters `?{first}` onwards as bound by [`Self::bind`]
+    fn condition(&self, first: usize) -> String {
+        let mut condition = format!("(?{} OR archived_at IS NULL)", first);
+        // order_addresses only exists when addresses are normalized, so it is
+        // only referenced when asked for
+        if self.filters_address() {
+            condition.push_str(&format!(
+                " AND id IN (SELECT order_id FROM order_addresses
+                    WHERE (?{0} IS NULL OR province = ?{0} COLLATE NOCASE)
+                    AND (?{1} IS NULL OR district = ?{1} COLLATE NOCASE)
+                    AND (?{2} IS NULL OR ward = ?{2} COLLATE NOCASE))",
+                first + 1,
+                first + 2,
+                first + 3
+            ));
+        }
+        let tags_first = if self.filters_address() { first + 4 } else { first + 1 };
+        for index in tags_first..tags_first + self.tags.len() {
+            condition.push_str(&format!(
+                " AND id IN (SELECT order_id FROM order_tags WHERE tag = ?{})",
+                index
+            ));
+        }
+        // Only referenced when asked for, like order_addresses
+        if self.min_risk.is_some() {
+            condition.push_str(&format!(
+                " AND id IN (SELECT order_id FROM order_risk WHERE score >= ?{})",
+                tags_first + self.tags.len()
+            ));
+        }
+        condition
+    }
+
+    fn bind<'q>(
+        &'q self,
+        query: sqlx::query::Query<'q, Sqlite, SqliteArguments<'q>>,
+    ) -> sqlx::query::Query<'q, Sqlite, SqliteArguments<'q>> {
+        let mut query = query.bind(self.include_archived);
+        if self.filters_address() {
+            query = query
+                .bind(self.province.as_deref())
+                .bind(self.district.as_deref())
+                .bind(self.ward.as_deref());
+        }
+        for tag in &self.tags {
+            query = query.bind(tag.as_str());
+        }
+        if let Some(min_risk) = self.min_risk {
+            query = query.bind(min_risk);
+        }
+        query
+    }
+}
+
+/// One page of the order listing
+#[derive(Debug, Clone)]
+pub struct OrderPage {
+    pub orders: Vec<Order>,
+    /// Set when more orders follow
+    pub next_cursor: Option<OrderCursor>,
+}
+
+impl std::fmt::Display for UpsertStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} inserted, {} updated, {} unchanged",
+            self.inserted, self.updated, self.skipped
+        )
+    }
 }
 
 impl Database {
+    const DEFAULT_UPSERT_BATCH_SIZE: usize = 500;
+
     /// Create a new database connection pool
     pub async fn new(path: &str) -> Result<Self, sqlx::Error> {
-        // Ensure the database file can be created
-        let database_url = format!("sqlite:{}?mode=rwc", path);
+        Self::open(path, SqliteSettings::default()).await
+    }
+
+    /// Open (creating if needed) the database at `path` with the given connection settings
+    pub async fn open(path: &str, settings: SqliteSettings) -> Result<Self, sqlx::Error> {
+        let options = SqliteConnectOptions::from_str(&format!("sqlite:{}", path))?
+            .create_if_missing(true)
+            .journal_mode(settings.journal_mode)
+            .synchronous(settings.synchronous)
+            .busy_timeout(settings.busy_timeout)
+            .foreign_keys(settings.foreign_keys)
+            // Lets maintenance hand freed pages back without a full VACUUM;
+            // only takes effect when the file is created
+            .auto_vacuum(SqliteAutoVacuum::Incremental);
 
         let pool = SqlitePoolOptions::new()
-            .max_connections(5)
-            .connect(&database_url)
+            .max_connections(settings.max_connections.max(1))
+            .connect_with(options)
             .await?;
 
-        Ok(Self { pool })
+        Ok(Self {
+            pool,
+            upsert_batch_size: Self::DEFAULT_UPSERT_BATCH_SIZE,
+            events: None,
+            validator: None,
+            link_customers: false,
+            track_sku_stats: false,
+            normalize_addresses: false,
+            track_item_fulfillment: false,
+            tagger: None,
+            link_replacements: false,
+            risk_scorer: None,
+            warehouse_router: None,
+            fx: None,
+            report_timezone: chrono_tz::UTC,
+            record_outbox: false,
+            cache: None,
+            pii_cipher: None,
+        })
+    }
+
+    /// Publish created/updated events for upserted orders on this bus
+    pub fn with_event_bus(mut self, events: EventBus) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Check payment totals of upserted orders and record anomalies in
+    /// `order_anomalies` (see [`AnomalyStore`])
+    pub fn with_validator(mut self, validator: OrderValidator) -> Self {
+        self.validator = Some(validator);
+        self
+    }
+
+    /// Record the buyer of each upserted order in `customers` (see
+    /// [`CustomerStore`]) and link the order to it
+    pub fn with_customer_linking(mut self) -> Self {
+        self.link_customers = true;
+        self
+    }
+
+    /// Keep per-SKU daily sales in `sku_stats` (see [`SkuStatsStore`]) up to date
+    pub fn with_sku_stats(mut self) -> Self {
+        self.track_sku_stats = true;
+        self
+    }
+
+    /// Store each upserted order's recipient address split into province,
+    /// district and ward in `order_addresses` (see [`AddressStore`])
+    pub fn with_address_normalization(mut self) -> Self {
+        self.normalize_addresses = true;
+        self
+    }
+
+    /// Keep the fulfillment status of each upserted order's line items in
+    /// `order_item_fulfillment` (see [`ItemFulfillmentStore`])
+    pub fn with_item_fulfillment(mut self) -> Self {
+        self.track_item_fulfillment = true;
+        self
+    }
+
+    /// Tag upserted orders by `tagger`'s rules in `order_tags` (see
+    /// [`TagStore`]), leaving the tags it excludes out of SKU stats
+    pub fn with_tagger(mut self, tagger: OrderTagger) -> Self {
+        self.tagger = Some(tagger);
+        self
+    }
+
+    /// Record which order each upserted replacement order replaces, when it
+    /// says so, in `order_replacements` (see [`ReplacementStore`])
+    pub fn with_replacement_linking(mut self) -> Self {
+        self.link_replacements = true;
+        self
+    }
+
+    /// Score newly stored orders with `scorer` in `order_risk` (see
+    /// [`RiskStore`]), warning about and publishing the high-risk ones
+    pub fn with_risk_scoring(mut self, scorer: RiskScorer) -> Self {
+        self.risk_scorer = Some(scorer);
+        self
+    }
+
+    /// Assign upserted orders a preferred warehouse by `router`'s routes in
+    /// `order_warehouses` (see [`WarehouseAssignmentStore`])
+    pub fn with_warehouse_routing(mut self, router: WarehouseRouter) -> Self {
+        self.warehouse_router = Some(router);
+        self
+    }
+
+    /// Convert each upserted order's total into `converter`'s base currency
+    /// in `order_fx` (see [`FxStore`]), and report daily stats in it
+    pub fn with_fx(mut self, converter: FxConverter) -> Self {
+        self.fx = Some(converter);
+        self
+    }
+
+    /// Count SKU stats and daily stats by business days in `timezone`
+    /// rather than UTC days
+    pub fn with_report_timezone(mut self, timezone: chrono_tz::Tz) -> Self {
+        self.report_timezone = timezone;
+        self
+    }
+
+    /// Write upsert events to the event outbox in the same transaction, for
+    /// relaying to a message broker (see [`OutboxStore`])
+    pub fn with_event_outbox(mut self) -> Self {
+        self.record_outbox = true;
+        self
+    }
+
+    /// Serve `get_order_by_id` through `cache`, invalidating it on upsert.
+    /// Orders are cached as they are stored, PII encrypted included.
+    pub fn with_cache(mut self, cache: OrderCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Encrypt PII in stored order blobs, normalized addresses and customers
+    /// (see [`PiiCipher`]). Orders are read with their PII still encrypted;
+    /// callers that need it call [`Self::reveal_pii`].
+    pub fn with_pii_cipher(mut self, cipher: PiiCipher) -> Self {
+        self.pii_cipher = Some(cipher);
+        self
+    }
+
+    /// `order`, as read, with its PII decrypted
+    pub fn reveal_pii(&self, order: Order) -> Order {
+        match &self.pii_cipher {
+            Some(cipher) => cipher.decrypt_order(order),
+            None => order,
+        }
+    }
+
+    /// The read cache, when `REDIS_URL` is set
+    pub fn cache(&self) -> Option<&OrderCache> {
+        self.cache.as_ref()
+    }
+
+    /// Set how many orders are written per transaction in `upsert_orders`
+    pub fn with_upsert_batch_size(mut self, size: usize) -> Self {
+        self.upsert_batch_size = size.max(1);
+        self
     }
 
     /// Initialize database schema
@@ -35,66 +486,548 @@ impl Database {
         .execute(&self.pool)
         .await?;
 
+        // Rows written before versioning existed are v1
+        self.add_column_if_missing("orders", "schema_version", "INTEGER NOT NULL DEFAULT 1")
+            .await?;
+        // NULL until linked; '' for orders without buyer details
+        self.add_column_if_missing("orders", "customer_id", "TEXT").await?;
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_orders_customer ON orders (customer_id)")
+            .execute(&self.pool)
+            .await?;
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_orders_create_time_id ON orders (create_time, id)")
+            .execute(&self.pool)
+            .await?;
+        // Set by the archival policy; archived orders are left out of listings by default
+        self.add_column_if_missing("orders", "archived_at", "INTEGER").await?;
+        // '' for orders without a message; NULL until extracted from the blob below
+        self.add_column_if_missing("orders", "buyer_message", "TEXT").await?;
+        self.add_column_if_missing("orders", "buyer_message_acked_at", "INTEGER").await?;
+        // Advanced by the processing pipeline (see `ProcessingStore`), never by upserts
+        self.add_column_if_missing("orders", "processing_state", "TEXT NOT NULL DEFAULT 'received'")
+            .await?;
+        self.add_column_if_missing("orders", "processing_updated_at", "INTEGER").await?;
+        // Set when an order is first stored by a backfill; kept on later upserts
+        self.add_column_if_missing("orders", "skip_fulfillment", "INTEGER NOT NULL DEFAULT 0")
+            .await?;
+        // Support lookups; NULL until filled by the lookup backfill, '' when unknown
+        self.add_column_if_missing("orders", "phone_last4", "TEXT").await?;
+        self.add_column_if_missing("orders", "tracking_number", "TEXT").await?;
+        // Set once buyer PII is erased; later upserts of the order are scrubbed too
+        self.add_column_if_missing("orders", "pii_scrubbed_at", "INTEGER").await?;
+        sqlx::query(
+            "CREATE INDEX IF NOT EXISTS idx_orders_phone_last4
+            ON orders (phone_last4) WHERE phone_last4 <> ''"
+        )
+        .execute(&self.pool)
+        .await?;
+        sqlx::query(
+            "CREATE INDEX IF NOT EXISTS idx_orders_tracking_number
+            ON orders (tracking_number) WHERE tracking_number <> ''"
+        )
+        .execute(&self.pool)
+        .await?;
+        sqlx::query(
+            "UPDATE orders SET buyer_message = COALESCE(TRIM(json_extract(data, '$.buyer_message')), '')
+            WHERE buyer_message IS NULL"
+        )
+        .execute(&self.pool)
+        .await?;
+        sqlx::query(
+            "CREATE INDEX IF NOT EXISTS idx_orders_buyer_message
+            ON orders (create_time) WHERE buyer_message <> ''"
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            "CREATE INDEX IF NOT EXISTS idx_orders_status_due
+            ON orders (status, json_extract(data, '$.shipping_due_time'))"
+        )
+        .execute(&self.pool)
+        .await?;
+
+        self.init_orders_version().await?;
+
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS leases (
+                name TEXT PRIMARY KEY,
+                holder TEXT NOT NULL,
+                expires_at INTEGER NOT NULL
+            )"
+        )
+        .execute(&self.pool)
+        .await?;
+
         Ok(())
     }
 
-    /// Insert or update orders in the database
-    pub async fn upsert_orders(&self, orders: &[Order]) -> Result<(), sqlx::Error> {
-        for order in orders {
-            let order_json = serde_json::to_string(&order)
-                .unwrap_or_default();
-            let synced_at = chrono::Utc::now().timestamp();
+    /// Keep a change counter for the orders table, whatever writes to it
+    async fn init_orders_version(&self) -> Result<(), sqlx::Error> {
+        track_orders_version(&self.pool, "orders").await
+    }
 
-            sqlx::query(
-                "INSERT OR REPLACE INTO orders (
-                    id, status, create_time, update_time, data, synced_at
-                ) VALUES (?1, ?2, ?3, ?4, ?5, ?6)"
-            )
-            .bind(&order.id)
-            .bind(&order.status)
-            .bind(order.create_time)
-            .bind(order.update_time)
-            .bind(&order_json)
-            .bind(synced_at)
-            .execute(&self.pool)
+    /// Current version of order listings at `now`; cheap enough to check on every request
+    pub async fn orders_version(&self, now: i64) -> Result<OrdersVersion, sqlx::Error> {
+        let row = sqlx::query(
+            "SELECT counter, changed_at, created_at FROM table_versions WHERE name = 'orders'"
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        let changed_at: i64 = row.try_get("changed_at")?;
+
+        // Orders turn late as time passes, without a write to bump the counter
+        let query = late_orders_query();
+        let mut late_query = sqlx::query(&query).bind(now);
+        for status in sla::PENDING_STATUSES {
+            late_query = late_query.bind(*status);
+        }
+        let late_row = late_query.fetch_one(&self.pool).await?;
+        let last_due: Option<i64> = late_row.try_get("last_due")?;
+
+        Ok(OrdersVersion {
+            counter: row.try_get("counter")?,
+            changed_at: changed_at.max(last_due.unwrap_or_default()),
+            created_at: row.try_get("created_at")?,
+            late: late_row.try_get("late")?,
+        })
+    }
+
+    /// Add a column to an existing table (SQLite has no ADD COLUMN IF NOT EXISTS)
+    pub async fn add_column_if_missing(
+        &self,
+        table: &str,
+        column: &str,
+        definition: &str,
+    ) -> Result<(), sqlx::Error> {
+        let columns = sqlx::query(&format!("PRAGMA table_info({})", table))
+            .fetch_all(&self.pool)
             .await?;
+
+        let exists = columns
+            .iter()
+            .any(|row| row.try_get::<String, _>("name").map(|n| n == column).unwrap_or(false));
+
+        if !exists {
+            sqlx::query(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition))
+                .execute(&self.pool)
+                .await?;
         }
 
         Ok(())
     }
 
-    /// Get all orders from the database
-    pub async fn get_orders(&self) -> Result<Vec<Order>, sqlx::Error> {
-        let rows = sqlx::query("SELECT data FROM orders ORDER BY create_time DESC")
-            .fetch_all(&self.pool)
+    /// Try to take (or renew) the named lease for `holder`.
+    ///
+    /// Returns `true` if `holder` owns the lease afterwards. Used to make sure only
+    /// one instance runs a given startup or maintenance task at a time.
+    pub async fn try_acquire_lease(
+        &self,
+        name: &str,
+        holder: &str,
+        ttl_secs: i64,
+    ) -> Result<bool, sqlx::Error> {
+        let now = chrono::Utc::now().timestamp();
+
+        let result = sqlx::query(
+            "INSERT INTO leases (name, holder, expires_at) VALUES (?1, ?2, ?3)
+            ON CONFLICT(name) DO UPDATE SET
+                holder = excluded.holder,
+                expires_at = excluded.expires_at
+            WHERE leases.expires_at <= ?4 OR leases.holder = excluded.holder"
+        )
+        .bind(name)
+        .bind(holder)
+        .bind(now + ttl_secs)
+        .bind(now)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Release a lease held by `holder`
+    pub async fn release_lease(&self, name: &str, holder: &str) -> Result<(), sqlx::Error> {
+        sqlx::query("DELETE FROM leases WHERE name = ?1 AND holder = ?2")
+            .bind(name)
+            .bind(holder)
+            .execute(&self.pool)
             .await?;
 
-        let mut orders = Vec::new();
-        for row in rows {
-            let data_json: String = row.try_get("data")?;
-            if let Ok(order) = serde_json::from_str::<Order>(&data_json) {
-                orders.push(order);
+        Ok(())
+    }
+
+    /// Insert new orders and update existing ones whose `update_time` moved forward.
+    ///
+    /// Orders whose stored `update_time` is the same or newer are left untouched,
+    /// so their `synced_at` keeps pointing at the last real change. Writes are
+    /// committed in transactions of `upsert_batch_size` orders.
+    pub async fn upsert_orders(&self, orders: &[Order]) -> Result<UpsertStats, sqlx::Error> {
+        self.upsert(orders, false).await
+    }
+
+    /// Like [`Self::upsert_orders`], but orders stored for the first time are
+    /// never fulfilled by the processing pipeline: historical orders must not
+    /// provision eSIMs
+    pub async fn upsert_backfilled_orders(
+        &self,
+        orders: &[Order],
+    ) -> Result<UpsertStats, sqlx::Error> {
+        self.upsert(orders, true).await
+    }
+
+    async fn upsert(
+        &self,
+        orders: &[Order],
+        skip_fulfillment: bool,
+    ) -> Result<UpsertStats, sqlx::Error> {
+        let mut stats = UpsertStats::default();
+
+        for batch in orders.chunks(self.upsert_batch_size) {
+            let batch = self.keep_scrubbed(batch).await?;
+            let mut tx = self.pool.begin().await?;
+            let mut events = Vec::new();
+            let mut high_risk = Vec::new();
+            let synced_at = chrono::Utc::now().timestamp();
+
+            for order in &batch {
+                // Owned copies are scrubbed ones, which keep the buyer they were linked to
+                let scrubbed = matches!(order, Cow::Owned(_));
+                let order: &Order = order;
+                let stored_update_time: Option<i64> =
+                    sqlx::query("SELECT update_time FROM orders WHERE id = ?1")
+                        .bind(&order.id)
+                        .fetch_optional(&mut *tx)
+                        .await?
+                        .map(|row| row.try_get("update_time"))
+                        .transpose()?;
+
+                if matches!(stored_update_time, Some(t) if t >= order.update_time) {
+                    stats.skipped += 1;
+                    continue;
+                }
+
+                let order_json = match &self.pii_cipher {
+                    Some(cipher) => serde_json::to_string(&cipher.encrypt_order(order)),
+                    None => serde_json::to_string(&order),
+                }
+                .unwrap_or_default();
+
+                let customer_id = if self.link_customers && !scrubbed {
+                    let buyer = BuyerIdentity::of(order);
+                    if let Some(buyer) = &buyer {
+                        let cipher = self.pii_cipher.as_ref();
+                        CustomerStore::record(&mut tx, buyer, order, cipher).await?;
+                    }
+                    Some(buyer.map(|b| b.customer_id).unwrap_or_default())
+                } else {
+                    None
+                };
+
+                sqlx::query(
+                    "INSERT INTO orders (
+                        id, status, create_time, update_time, data, synced_at, schema_version,
+                        customer_id, buyer_message, skip_fulfillment, phone_last4, tracking_number
+                    ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)
+                    ON CONFLICT(id) DO UPDATE SET
+                        status = excluded.status,
+                        create_time = excluded.create_time,
+                        update_time = excluded.update_time,
+                        data = excluded.data,
+                        synced_at = excluded.synced_at,
+                        schema_version = excluded.schema_version,
+                        customer_id = COALESCE(excluded.customer_id, orders.customer_id),
+                        buyer_message = excluded.buyer_message,
+                        phone_last4 = excluded.phone_last4,
+                        tracking_number = excluded.tracking_number,
+                        buyer_message_acked_at = CASE
+                            WHEN orders.buyer_message = excluded.buyer_message
+                            THEN orders.buyer_message_acked_at
+                        END,
+                        archived_at = NULL"
+                )
+                .bind(&order.id)
+                .bind(&order.status)
+                .bind(order.create_time)
+                .bind(order.update_time)
+                .bind(&order_json)
+                .bind(synced_at)
+                .bind(ORDER_SCHEMA_VERSION)
+                .bind(customer_id)
+                .bind(order.buyer_message.as_deref().map(str::trim).unwrap_or_default())
+                .bind(skip_fulfillment)
+                .bind(lookup::phone_last4(order))
+                .bind(lookup::tracking_key(order))
+                .execute(&mut *tx)
+                .await?;
+
+                if let Some(validator) = &self.validator {
+                    let anomalies = validator.validate(order);
+                    if !anomalies.is_empty() {
+                        warn!(
+                            "Order {} has inconsistent payment data: {}",
+                            order.id,
+                            anomalies.iter().map(|a| a.kind).collect::<Vec<_>>().join(", ")
+                        );
+                    }
+                    AnomalyStore::replace(&mut tx, order, &anomalies).await?;
+                }
+
+                let counted = match &self.tagger {
+                    Some(tagger) => {
+                        let tags = tagger.tags_for(order);
+                        TagStore::apply_rules(&mut tx, &order.id, &tags).await?;
+                        tagger.counts_in_stats(&tags)
+                    }
+                    None => true,
+                };
+
+                if self.track_sku_stats {
+                    SkuStatsStore::record(&mut tx, order, counted, self.report_timezone).await?;
+                }
+
+                if self.normalize_addresses {
+                    AddressStore::record(&mut tx, order, self.pii_cipher.as_ref()).await?;
+                }
+
+                if self.track_item_fulfillment {
+                    ItemFulfillmentStore::record(&mut tx, order).await?;
+                }
+
+                if self.link_replacements {
+                    ReplacementStore::record(&mut tx, order).await?;
+                }
+
+                if let Some(router) = &self.warehouse_router {
+                    WarehouseAssignmentStore::record(&mut tx, router, order).await?;
+                }
+
+                if let Some(fx) = &self.fx {
+                    FxStore::record(&mut tx, fx, order).await?;
+                }
+
+                // Orders are scored once, when first stored
+                let scorer = self.risk_scorer.as_ref().filter(|_| stored_update_time.is_none());
+                if let Some(scorer) = scorer {
+                    let history = RiskStore::buyer_history(&mut tx, order).await?;
+                    let assessment = scorer.assess(order, &history);
+                    RiskStore::record(&mut tx, &order.id, &assessment).await?;
+                    if scorer.is_high_risk(&assessment) {
+                        high_risk.push((order, assessment));
+                    }
+                }
+
+                let kind = if stored_update_time.is_some() {
+                    stats.updated += 1;
+                    OrderEventKind::Updated
+                } else {
+                    stats.inserted += 1;
+                    OrderEventKind::Created
+                };
+                events.push(OrderEvent::new(kind, order));
+            }
+
+            if self.record_outbox {
+                let outbox_events: Vec<OrderEvent> = events
+                    .iter()
+                    .cloned()
+                    .chain(
+                        high_risk
+                            .iter()
+                            .map(|(order, _)| OrderEvent::new(OrderEventKind::HighRisk, order)),
+                    )
+                    .collect();
+                OutboxStore::record(&mut tx, &outbox_events).await?;
+            }
+
+            tx.commit().await?;
+
+            // Only announce changes once they are visible to readers
+            for (order, assessment) in high_risk {
+                let reasons: Vec<&str> =
+                    assessment.signals.iter().map(|s| s.reason.as_str()).collect();
+                warn!(
+                    "Order {} is high risk ({:.2}): {}",
+                    order.id,
+                    assessment.score,
+                    reasons.join("; ")
+                );
+                events.push(OrderEvent::new(OrderEventKind::HighRisk, order));
+            }
+            if let (Some(cache), false) = (&self.cache, events.is_empty()) {
+                let changed: Vec<&str> = events.iter().map(|e| e.order_id.as_str()).collect();
+                cache.invalidate_orders(&changed).await;
+            }
+            if let Some(bus) = &self.events {
+                events.into_iter().for_each(|event| bus.publish(event));
             }
         }
 
-        Ok(orders)
+        Ok(stats)
+    }
+
+    /// `orders`, with those whose PII was erased replaced by scrubbed copies
+    async fn keep_scrubbed<'a>(
+        &self,
+        orders: &'a [Order],
+    ) -> Result<Vec<Cow<'a, Order>>, sqlx::Error> {
+        let mut kept = Vec::with_capacity(orders.len());
+        for order in orders {
+            kept.push(if self.is_pii_scrubbed(&order.id).await? {
+                Cow::Owned(anonymize::scrub_order(order))
+            } else {
+                Cow::Borrowed(order)
+            });
+        }
+        Ok(kept)
+    }
+
+    /// Remove buyer PII from the given orders (see [`anonymize::scrub_order`])
+    /// and keep it out of later upserts. Returns how many orders were scrubbed.
+    pub async fn scrub_orders(&self, order_ids: &[String]) -> Result<u64, sqlx::Error> {
+        let mut scrubbed = 0;
+        for batch in order_ids.chunks(self.upsert_batch_size) {
+            let mut rows = Vec::with_capacity(batch.len());
+            for order_id in batch {
+                rows.extend(
+                    sqlx::query("SELECT id, data, schema_version FROM orders WHERE id = ?1")
+                        .bind(order_id)
+                        .fetch_optional(&self.pool)
+                        .await?,
+                );
+            }
+            let orders = self.decode_orders(rows).await?;
+
+            let now = chrono::Utc::now().timestamp();
+            let mut tx = self.pool.begin().await?;
+            for order in &orders {
+                let order_json = serde_json::to_string(&anonymize::scrub_order(order))
+                    .unwrap_or_default();
+                sqlx::query(
+                    "UPDATE orders SET data = ?2, schema_version = ?3, buyer_message = '',
+                        buyer_message_acked_at = NULL, phone_last4 = '', pii_scrubbed_at = ?4
+                    WHERE id = ?1"
+                )
+                .bind(&order.id)
+                .bind(&order_json)
+                .bind(ORDER_SCHEMA_VERSION)
+                .bind(now)
+                .execute(&mut *tx)
+                .await?;
+            }
+            tx.commit().await?;
+
+            if let Some(cache) = &self.cache {
+                let ids: Vec<&str> = orders.iter().map(|o| o.id.as_str()).collect();
+                cache.invalidate_orders(&ids).await;
+            }
+            scrubbed += orders.len() as u64;
+        }
+        Ok(scrubbed)
+    }
+
+    /// Orders of `customer_id`
+    pub async fn customer_order_ids(&self, customer_id: &str) -> Result<Vec<String>, sqlx::Error> {
+        sqlx::query("SELECT id FROM orders WHERE customer_id = ?1")
+            .bind(customer_id)
+            .fetch_all(&self.pool)
+            .await?
+            .iter()
+            .map(|row| row.try_get("id"))
+            .collect()
+    }
+
+    /// Whether the buyer PII of `order_id` was erased
+    pub async fn is_pii_scrubbed(&self, order_id: &str) -> Result<bool, sqlx::Error> {
+        Ok(
+            sqlx::query("SELECT 1 FROM orders WHERE id = ?1 AND pii_scrubbed_at IS NOT NULL")
+                .bind(order_id)
+                .fetch_optional(&self.pool)
+                .await?
+                .is_some(),
+        )
+    }
+
+    /// Orders not updated since `cutoff` that still hold buyer PII
+    pub async fn unscrubbed_order_ids(&self, cutoff: i64) -> Result<Vec<String>, sqlx::Error> {
+        sqlx::query("SELECT id FROM orders WHERE pii_scrubbed_at IS NULL AND update_time < ?1")
+            .bind(cutoff)
+            .fetch_all(&self.pool)
+            .await?
+            .iter()
+            .map(|row| row.try_get("id"))
+            .collect()
+    }
+
+    /// Get all orders from the database
+    pub async fn get_orders(&self) -> Result<Vec<Order>, sqlx::Error> {
+        let rows = sqlx::query(
+            "SELECT id, data, schema_version FROM orders ORDER BY create_time DESC, id DESC"
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        self.decode_orders(rows).await
+    }
+
+    /// Stream every stored order, newest first, without holding them all in memory.
+    ///
+    /// Blobs from an older schema are upgraded in memory only: writing them back
+    /// while the read is open would contend with it.
+    pub fn stream_orders(&self, filter: OrderFilter) -> ReceiverStream<Result<Order, sqlx::Error>> {
+        let pool = self.pool.clone();
+        let (tx, rx) = tokio::sync::mpsc::channel(64);
+
+        tokio::spawn(async move {
+            let sql = format!(
+                "SELECT id, data, schema_version FROM orders
+                WHERE {}
+                ORDER BY create_time DESC, id DESC",
+                filter.condition(1)
+            );
+            let mut rows = filter.bind(sqlx::query(&sql)).fetch(&pool);
+
+            while let Some(row) = rows.next().await {
+                let item = match row.and_then(|row| decode_row(&row)) {
+                    Ok(Some(order)) => Ok(order),
+                    Ok(None) => continue,
+                    Err(e) => Err(e),
+                };
+                let failed = item.is_err();
+                // Stop once the reader is gone or after reporting an error
+                if tx.send(item).await.is_err() || failed {
+                    break;
+                }
+            }
+        });
+
+        ReceiverStream::new(rx)
     }
 
-    /// Get a single order by ID
+    /// Get a single order by ID, with its PII as stored
     pub async fn get_order_by_id(&self, order_id: &str) -> Result<Option<Order>, sqlx::Error> {
-        let row = sqlx::query("SELECT data FROM orders WHERE id = ?1")
+        if let Some(cache) = &self.cache {
+            if let Some(order) = cache.get_order(order_id).await {
+                return Ok(Some(order));
+            }
+        }
+
+        let row = sqlx::query("SELECT id, data, schema_version FROM orders WHERE id = ?1")
             .bind(order_id)
             .fetch_optional(&self.pool)
             .await?;
 
-        if let Some(row) = row {
-            let data_json: String = row.try_get("data")?;
-            if let Ok(order) = serde_json::from_str::<Order>(&data_json) {
-                return Ok(Some(order));
+        let order = match row {
+            Some(row) => self.decode_orders(vec![row]).await?.pop(),
+            None => None,
+        };
+        if let (Some(cache), Some(order)) = (&self.cache, &order) {
+            // Rows from before PII_ENCRYPTION_KEY was set may still hold plaintext
+            match &self.pii_cipher {
+                Some(cipher) => cache.put_order(order_id, &cipher.encrypt_order(order)).await,
+                None => cache.put_order(order_id, order).await,
             }
         }
-
-        Ok(None)
+        Ok(order)
     }
 
     /// Get the total count of orders
@@ -107,51 +1040,521 @@ impl Database {
         Ok(count)
     }
 
-    /// Get orders with pagination
+    /// Count orders created at or after `since` (unix seconds)
+    pub async fn count_orders_created_since(&self, since: i64) -> Result<i64, sqlx::Error> {
+        let row = sqlx::query("SELECT COUNT(*) as count FROM orders WHERE create_time >= ?1")
+            .bind(since)
+            .fetch_one(&self.pool)
+            .await?;
+
+        row.try_get("count")
+    }
+
+    /// Get a page of orders, newest first, continuing after `after`
     pub async fn get_orders_paginated(
         &self,
         limit: i64,
-        offset: i64,
+        after: Option<&OrderCursor>,
+        filter: &OrderFilter,
+    ) -> Result<OrderPage, sqlx::Error> {
+        let limit = limit.max(1);
+        let sql = format!(
+            "SELECT id, data, schema_version, create_time FROM orders
+            WHERE (?1 IS NULL OR (create_time, id) < (?1, ?2)) AND {}
+            ORDER BY create_time DESC, id DESC LIMIT ?3",
+            filter.condition(4)
+        );
+        // One extra row tells whether another page follows
+        let query = sqlx::query(&sql)
+            .bind(after.map(|c| c.create_time))
+            .bind(after.map(|c| c.id.as_str()))
+            .bind(limit + 1);
+        let mut rows = filter.bind(query).fetch_all(&self.pool).await?;
+
+        let has_more = rows.len() as i64 > limit;
+        rows.truncate(limit as usize);
+        // Taken from the row rather than the decoded order, which may be skipped
+        let next_cursor = match rows.last() {
+            Some(row) if has_more => Some(OrderCursor {
+                create_time: row.try_get("create_time")?,
+                id: row.try_get("id")?,
+            }),
+            _ => None,
+        };
+
+        Ok(OrderPage {
+            orders: self.decode_orders(rows).await?,
+            next_cursor,
+        })
+    }
+
+    /// Get orders by status
+    pub async fn get_orders_by_status(&self, status: &str) -> Result<Vec<Order>, sqlx::Error> {
+        let rows = sqlx::query(
+            "SELECT id, data, schema_version FROM orders WHERE status = ?1 ORDER BY create_time DESC"
+        )
+        .bind(status)
+        .fetch_all(&self.pool)
+        .await?;
+
+        self.decode_orders(rows).await
+    }
+
+    /// Orders with `status` created in `[start, end)` (unix seconds), oldest first
+    pub async fn get_orders_created_between(
+        &self,
+        status: &str,
+        start: i64,
+        end: i64,
     ) -> Result<Vec<Order>, sqlx::Error> {
         let rows = sqlx::query(
-            "SELECT data FROM orders ORDER BY create_time DESC LIMIT ?1 OFFSET ?2"
+            "SELECT id, data, schema_version FROM orders
+            WHERE status = ?1 AND create_time >= ?2 AND create_time < ?3
+            ORDER BY create_time, id",
         )
+        .bind(status)
+        .bind(start)
+        .bind(end)
+        .fetch_all(&self.pool)
+        .await?;
+
+        self.decode_orders(rows).await
+    }
+
+    /// Orders created in `[start, end)` that were paid for, cancelled ones excluded
+    pub async fn get_paid_orders_created_between(
+        &self,
+        start: i64,
+        end: i64,
+    ) -> Result<Vec<Order>, sqlx::Error> {
+        let rows = sqlx::query(
+            "SELECT id, data, schema_version FROM orders
+            WHERE status NOT IN ('UNPAID', 'CANCELLED') AND create_time >= ?1 AND create_time < ?2
+            ORDER BY create_time, id",
+        )
+        .bind(start)
+        .bind(end)
+        .fetch_all(&self.pool)
+        .await?;
+
+        self.decode_orders(rows).await
+    }
+
+    /// Cancelled orders whose `cancel_time` (or, without one, `update_time`)
+    /// is in `[start, end)`
+    pub async fn get_orders_cancelled_between(
+        &self,
+        start: i64,
+        end: i64,
+    ) -> Result<Vec<Order>, sqlx::Error> {
+        let rows = sqlx::query(
+            "SELECT id, data, schema_version FROM orders
+            WHERE status = 'CANCELLED'
+                AND COALESCE(json_extract(data, '$.cancel_time'), update_time) >= ?1
+                AND COALESCE(json_extract(data, '$.cancel_time'), update_time) < ?2
+            ORDER BY create_time, id",
+        )
+        .bind(start)
+        .bind(end)
+        .fetch_all(&self.pool)
+        .await?;
+
+        self.decode_orders(rows).await
+    }
+
+    /// `update_time` of each stored order updated in `[start, end)`, by id
+    pub async fn order_versions_updated_between(
+        &self,
+        start: i64,
+        end: i64,
+    ) -> Result<BTreeMap<String, i64>, sqlx::Error> {
+        sqlx::query(
+            "SELECT id, update_time FROM orders WHERE update_time >= ?1 AND update_time < ?2",
+        )
+        .bind(start)
+        .bind(end)
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(|row| Ok((row.try_get("id")?, row.try_get("update_time")?)))
+        .collect()
+    }
+
+    /// Stored `update_time` of each of `order_ids` that is stored
+    pub async fn order_update_times(
+        &self,
+        order_ids: &[String],
+    ) -> Result<HashMap<String, i64>, sqlx::Error> {
+        let mut times = HashMap::new();
+        for order_id in order_ids {
+            let row = sqlx::query("SELECT update_time FROM orders WHERE id = ?1")
+                .bind(order_id)
+                .fetch_optional(&self.pool)
+                .await?;
+            if let Some(row) = row {
+                times.insert(order_id.clone(), row.try_get("update_time")?);
+            }
+        }
+        Ok(times)
+    }
+
+    /// Orders linked to a customer, newest first
+    pub async fn get_orders_by_customer(
+        &self,
+        customer_id: &str,
+        limit: i64,
+        include_archived: bool,
+    ) -> Result<Vec<Order>, sqlx::Error> {
+        let rows = sqlx::query(
+            "SELECT id, data, schema_version FROM orders
+            WHERE customer_id = ?1 AND (?3 OR archived_at IS NULL)
+            ORDER BY create_time DESC LIMIT ?2"
+        )
+        .bind(customer_id)
         .bind(limit)
-        .bind(offset)
+        .bind(include_archived)
         .fetch_all(&self.pool)
         .await?;
 
-        let mut orders = Vec::new();
-        for row in rows {
-            let data_json: String = row.try_get("data")?;
-            if let Ok(order) = serde_json::from_str::<Order>(&data_json) {
-                orders.push(order);
+        self.decode_orders(rows).await
+    }
+
+    /// Orders with a buyer message, newest first; with `unhandled` only those
+    /// not acknowledged yet
+    pub async fn get_buyer_messages(
+        &self,
+        unhandled: bool,
+        limit: i64,
+    ) -> Result<Vec<BuyerMessage>, sqlx::Error> {
+        sqlx::query(
+            "SELECT id, status, create_time, buyer_message, buyer_message_acked_at FROM orders
+            WHERE buyer_message <> '' AND (NOT ?1 OR buyer_message_acked_at IS NULL)
+            ORDER BY create_time DESC LIMIT ?2"
+        )
+        .bind(unhandled)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(buyer_message_from_row)
+        .collect()
+    }
+
+    /// The buyer message of an order; `message` is empty if the buyer left none
+    pub async fn get_buyer_message(&self, order_id: &str) -> Result<Option<BuyerMessage>, sqlx::Error> {
+        sqlx::query(
+            "SELECT id, status, create_time, buyer_message, buyer_message_acked_at FROM orders
+            WHERE id = ?1"
+        )
+        .bind(order_id)
+        .fetch_optional(&self.pool)
+        .await?
+        .as_ref()
+        .map(buyer_message_from_row)
+        .transpose()
+    }
+
+    /// Mark an order's buyer message as handled. Acknowledging again keeps the
+    /// original time. Returns `false` if the order has no message.
+    pub async fn ack_buyer_message(&self, order_id: &str) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            "UPDATE orders SET buyer_message_acked_at = COALESCE(buyer_message_acked_at, ?2)
+            WHERE id = ?1 AND buyer_message <> ''"
+        )
+        .bind(order_id)
+        .bind(chrono::Utc::now().timestamp())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Link orders stored before customer tracking to their buyers.
+    /// Returns the number of orders linked to a customer.
+    pub async fn link_unlinked_customers(&self) -> Result<usize, sqlx::Error> {
+        let rows = sqlx::query(
+            "SELECT id, data, schema_version FROM orders WHERE customer_id IS NULL"
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let orders: Vec<Order> = self
+            .decode_orders(rows)
+            .await?
+            .into_iter()
+            .map(|order| self.reveal_pii(order))
+            .collect();
+        let mut linked = 0;
+        for chunk in orders.chunks(self.upsert_batch_size) {
+            let mut tx = self.pool.begin().await?;
+            for order in chunk {
+                let buyer = BuyerIdentity::of(order);
+                if let Some(buyer) = &buyer {
+                    CustomerStore::record(&mut tx, buyer, order, self.pii_cipher.as_ref()).await?;
+                    linked += 1;
+                }
+
+                sqlx::query("UPDATE orders SET customer_id = ?2 WHERE id = ?1")
+                    .bind(&order.id)
+                    .bind(buyer.map(|b| b.customer_id).unwrap_or_default())
+                    .execute(&mut *tx)
+                    .await?;
             }
+            tx.commit().await?;
         }
 
-        Ok(orders)
+        Ok(linked)
     }
 
-    /// Get orders by status
-    pub async fn get_orders_by_status(&self, status: &str) -> Result<Vec<Order>, sqlx::Error> {
+    /// Fill the lookup columns of orders stored before they existed.
+    /// Returns the number of orders indexed.
+    pub async fn index_lookup_columns(&self) -> Result<usize, sqlx::Error> {
         let rows = sqlx::query(
-            "SELECT data FROM orders WHERE status = ?1 ORDER BY create_time DESC"
+            "SELECT id, data, schema_version FROM orders
+            WHERE phone_last4 IS NULL OR tracking_number IS NULL"
         )
-        .bind(status)
         .fetch_all(&self.pool)
         .await?;
 
-        let mut orders = Vec::new();
-        for row in rows {
-            let data_json: String = row.try_get("data")?;
-            if let Ok(order) = serde_json::from_str::<Order>(&data_json) {
-                orders.push(order);
+        let orders: Vec<Order> = self
+            .decode_orders(rows)
+            .await?
+            .into_iter()
+            .map(|order| self.reveal_pii(order))
+            .collect();
+        for chunk in orders.chunks(self.upsert_batch_size) {
+            let mut tx = self.pool.begin().await?;
+            for order in chunk {
+                sqlx::query(
+                    "UPDATE orders SET phone_last4 = ?2, tracking_number = ?3 WHERE id = ?1"
+                )
+                .bind(&order.id)
+                .bind(lookup::phone_last4(order))
+                .bind(lookup::tracking_key(order))
+                .execute(&mut *tx)
+                .await?;
             }
+            tx.commit().await?;
         }
 
+        Ok(orders.len())
+    }
+
+    /// Convert the totals of orders stored before FX conversion was enabled,
+    /// or converted into another base currency. Returns the number of orders
+    /// converted.
+    pub async fn convert_stored_orders(&self) -> Result<usize, sqlx::Error> {
+        let Some(fx) = &self.fx else {
+            return Ok(0);
+        };
+        let rows = sqlx::query(
+            "SELECT id, data, schema_version FROM orders
+            WHERE id NOT IN (SELECT order_id FROM order_fx WHERE base_currency = ?1)"
+        )
+        .bind(fx.base_currency())
+        .fetch_all(&self.pool)
+        .await?;
+
+        let orders = self.decode_orders(rows).await?;
+        for chunk in orders.chunks(self.upsert_batch_size) {
+            let mut tx = self.pool.begin().await?;
+            for order in chunk {
+                FxStore::record(&mut tx, fx, order).await?;
+            }
+            tx.commit().await?;
+        }
+
+        Ok(orders.len())
+    }
+
+    /// Orders whose recipient phone ends in `digits` (at least
+    /// [`lookup::MIN_PHONE_DIGITS`]), newest first
+    pub async fn find_orders_by_phone(
+        &self,
+        digits: &str,
+        limit: usize,
+    ) -> Result<Vec<Order>, sqlx::Error> {
+        let last4 = &digits[digits.len().saturating_sub(lookup::MIN_PHONE_DIGITS)..];
+        let rows = sqlx::query(
+            "SELECT id, data, schema_version FROM orders
+            WHERE phone_last4 = ?1 ORDER BY create_time DESC"
+        )
+        .bind(last4)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut orders: Vec<Order> = self
+            .decode_orders(rows)
+            .await?
+            .into_iter()
+            .filter(|order| {
+                self.reveal_pii(order.clone())
+                    .recipient_address
+                    .and_then(|a| a.phone)
+                    .is_some_and(|phone| lookup::phone_matches(&phone, digits))
+            })
+            .collect();
+        orders.truncate(limit);
         Ok(orders)
     }
 
+    /// Orders with tracking number `tracking` (normalized), or containing it
+    /// with `partial`, newest first
+    pub async fn find_orders_by_tracking(
+        &self,
+        tracking: &str,
+        partial: bool,
+        limit: i64,
+    ) -> Result<Vec<Order>, sqlx::Error> {
+        let condition = if partial {
+            "tracking_number <> '' AND instr(tracking_number, ?1) > 0"
+        } else {
+            "tracking_number = ?1"
+        };
+        let rows = sqlx::query(&format!(
+            "SELECT id, data, schema_version FROM orders
+            WHERE {} ORDER BY create_time DESC LIMIT ?2",
+            condition
+        ))
+        .bind(tracking)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        self.decode_orders(rows).await
+    }
+
+    /// Recount `sku_stats` from every stored order. Returns the number of orders counted.
+    pub async fn rebuild_sku_stats(&self) -> Result<usize, sqlx::Error> {
+        let rows = sqlx::query("SELECT id, data, schema_version FROM orders")
+            .fetch_all(&self.pool)
+            .await?;
+
+        let orders = self.decode_orders(rows).await?;
+        for chunk in orders.chunks(self.upsert_batch_size) {
+            let mut tx = self.pool.begin().await?;
+            for order in chunk {
+                let counted = self.counts_in_stats(order);
+                SkuStatsStore::record(&mut tx, order, counted, self.report_timezone).await?;
+            }
+            tx.commit().await?;
+        }
+        if let Some(cache) = &self.cache {
+            cache.invalidate_stats().await;
+        }
+
+        Ok(orders.len())
+    }
+
+    /// Rebuild `daily_stats` of `shop_id` from every stored order that counts
+    /// towards stats. Returns the number of days with revenue.
+    pub async fn rebuild_daily_stats(&self, shop_id: &str) -> Result<usize, sqlx::Error> {
+        let rows = sqlx::query("SELECT id, data, schema_version FROM orders")
+            .fetch_all(&self.pool)
+            .await?;
+
+        let orders = self.decode_orders(rows).await?;
+        let converted = match &self.fx {
+            Some(fx) => {
+                let amounts = FxStore::new(self.pool.clone())
+                    .base_amounts(fx.base_currency())
+                    .await?;
+                Some((fx.base_currency(), amounts))
+            }
+            None => None,
+        };
+        let totals = daily_stats::daily_totals(
+            orders.iter().filter(|order| self.counts_in_stats(order)),
+            self.report_timezone,
+            converted.as_ref().map(|(base, amounts)| (*base, amounts)),
+        );
+        let mut tx = self.pool.begin().await?;
+        DailyStatsStore::replace(&mut tx, shop_id, &totals).await?;
+        tx.commit().await?;
+        if let Some(cache) = &self.cache {
+            cache.invalidate_stats().await;
+        }
+
+        let mut days: Vec<&str> = totals.iter().map(|t| t.day.as_str()).collect();
+        days.dedup();
+        Ok(days.len())
+    }
+
+    /// Normalize the addresses of orders that have none stored, or one from
+    /// older rules. Returns the number of orders normalized.
+    pub async fn normalize_stored_addresses(&self) -> Result<usize, sqlx::Error> {
+        let rows = sqlx::query(
+            "SELECT id, data, schema_version FROM orders
+            WHERE id NOT IN (SELECT order_id FROM order_addresses WHERE version >= ?1)"
+        )
+        .bind(address::NORMALIZATION_VERSION)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let orders: Vec<Order> = self
+            .decode_orders(rows)
+            .await?
+            .into_iter()
+            .map(|order| self.reveal_pii(order))
+            .collect();
+        for chunk in orders.chunks(self.upsert_batch_size) {
+            let mut tx = self.pool.begin().await?;
+            for order in chunk {
+                AddressStore::record(&mut tx, order, self.pii_cipher.as_ref()).await?;
+            }
+            tx.commit().await?;
+        }
+
+        Ok(orders.len())
+    }
+
+    /// Re-evaluate tag rules on every stored order, recounting SKU stats of
+    /// orders whose exclusion may have changed. Returns the number of orders tagged.
+    pub async fn retag_stored_orders(&self) -> Result<usize, sqlx::Error> {
+        let Some(tagger) = &self.tagger else {
+            return Ok(0);
+        };
+
+        let rows = sqlx::query("SELECT id, data, schema_version FROM orders")
+            .fetch_all(&self.pool)
+            .await?;
+
+        let orders = self.decode_orders(rows).await?;
+        for chunk in orders.chunks(self.upsert_batch_size) {
+            let mut tx = self.pool.begin().await?;
+            for order in chunk {
+                let tags = tagger.tags_for(order);
+                TagStore::apply_rules(&mut tx, &order.id, &tags).await?;
+                if self.track_sku_stats {
+                    let counted = tagger.counts_in_stats(&tags);
+                    SkuStatsStore::record(&mut tx, order, counted, self.report_timezone).await?;
+                }
+            }
+            tx.commit().await?;
+        }
+
+        Ok(orders.len())
+    }
+
+    fn counts_in_stats(&self, order: &Order) -> bool {
+        self.tagger
+            .as_ref()
+            .is_none_or(|tagger| tagger.counts_in_stats(&tagger.tags_for(order)))
+    }
+
+    /// Archive orders not updated since `cutoff` (unix seconds). Returns how many were archived.
+    pub async fn archive_orders(&self, cutoff: i64) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query(
+            "UPDATE orders SET archived_at = ?2 WHERE archived_at IS NULL AND update_time < ?1"
+        )
+        .bind(cutoff)
+        .bind(chrono::Utc::now().timestamp())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
     /// Delete an order by ID
     pub async fn delete_order(&self, order_id: &str) -> Result<(), sqlx::Error> {
         sqlx::query("DELETE FROM orders WHERE id = ?1")
@@ -162,8 +1565,193 @@ impl Database {
         Ok(())
     }
 
+    /// Decode `id, data, schema_version` rows, lazily upgrading and writing back
+    /// blobs stored by an older schema. Rows that can't be decoded are skipped;
+    /// PII is left as stored.
+    async fn decode_orders(&self, rows: Vec<SqliteRow>) -> Result<Vec<Order>, sqlx::Error> {
+        let mut orders = Vec::with_capacity(rows.len());
+
+        for row in rows {
+            let id: String = row.try_get("id")?;
+            let data_json: String = row.try_get("data")?;
+            let version: i64 = row.try_get("schema_version")?;
+
+            match order_schema::decode_order(&data_json, version) {
+                Ok((order, Some(upgraded))) => {
+                    self.write_upgraded_blob(&id, &upgraded).await?;
+                    orders.push(order);
+                }
+                Ok((order, None)) => orders.push(order),
+                Err(e) => warn!("Skipping undecodable order {} (schema v{}): {}", id, version, e),
+            }
+        }
+
+        Ok(orders)
+    }
+
+    async fn write_upgraded_blob(&self, order_id: &str, data: &str) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE orders SET data = ?2, schema_version = ?3 WHERE id = ?1")
+            .bind(order_id)
+            .bind(data)
+            .bind(ORDER_SCHEMA_VERSION)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Eagerly upgrade every blob stored by an older schema version
+    pub async fn upgrade_order_blobs(&self) -> Result<SchemaUpgradeReport, sqlx::Error> {
+        let rows = sqlx::query("SELECT id, data, schema_version FROM orders WHERE schema_version < ?1")
+            .bind(ORDER_SCHEMA_VERSION)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let mut report = SchemaUpgradeReport::default();
+        for row in rows {
+            let id: String = row.try_get("id")?;
+            let data_json: String = row.try_get("data")?;
+            let version: i64 = row.try_get("schema_version")?;
+
+            match order_schema::decode_order(&data_json, version) {
+                Ok((_, Some(upgraded))) => {
+                    self.write_upgraded_blob(&id, &upgraded).await?;
+                    report.upgraded += 1;
+                }
+                Ok((_, None)) => {}
+                Err(e) => {
+                    warn!("Failed to upgrade order {} from schema v{}: {}", id, version, e);
+                    report.failed += 1;
+                }
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Encrypt PII written before the cipher was set: order blobs, normalized
+    /// streets and customer names. Returns the number of rows encrypted.
+    pub async fn encrypt_stored_pii(&self) -> Result<u64, sqlx::Error> {
+        let Some(cipher) = &self.pii_cipher else {
+            return Ok(0);
+        };
+        let mut encrypted = 0;
+
+        let rows = sqlx::query("SELECT id, data, schema_version FROM orders")
+            .fetch_all(&self.pool)
+            .await?;
+        for row in rows {
+            let id: String = row.try_get("id")?;
+            let data_json: String = row.try_get("data")?;
+            let version: i64 = row.try_get("schema_version")?;
+            // Decoding upgrades older blobs, so the rewrite is at the current version
+            let Ok((order, _)) = order_schema::decode_order(&data_json, version) else {
+                continue;
+            };
+            if !PiiCipher::has_plaintext(&order) {
+                continue;
+            }
+            let data = serde_json::to_string(&cipher.encrypt_order(&order)).unwrap_or_default();
+            self.write_upgraded_blob(&id, &data).await?;
+            encrypted += 1;
+        }
+
+        for (table, key, column) in [
+            ("order_addresses", "order_id", "street"),
+            ("customers", "id", "name"),
+        ] {
+            let rows = sqlx::query(&format!(
+                "SELECT {key}, {column} FROM {table}
+                WHERE {column} IS NOT NULL AND {column} NOT LIKE ?1"
+            ))
+            .bind(format!("{}%", pii_crypto::ENCRYPTED_PREFIX))
+            .fetch_all(&self.pool)
+            .await?;
+            for row in rows {
+                let id: String = row.try_get(key)?;
+                let value: String = row.try_get(column)?;
+                sqlx::query(&format!("UPDATE {table} SET {column} = ?2 WHERE {key} = ?1"))
+                    .bind(&id)
+                    .bind(cipher.encrypt(&value))
+                    .execute(&self.pool)
+                    .await?;
+                encrypted += 1;
+            }
+        }
+
+        Ok(encrypted)
+    }
+
+    /// Check that the database answers queries
+    pub async fn ping(&self) -> Result<(), sqlx::Error> {
+        sqlx::query("SELECT 1").execute(&self.pool).await?;
+        Ok(())
+    }
+
     /// Get the underlying connection pool
     pub fn pool(&self) -> &SqlitePool {
         &self.pool
     }
 }
+
+/// Decode an `id, data, schema_version` row, skipping (with a warning) blobs that don't parse
+fn decode_row(row: &SqliteRow) -> Result<Option<Order>, sqlx::Error> {
+    let id: String = row.try_get("id")?;
+    let data_json: String = row.try_get("data")?;
+    let version: i64 = row.try_get("schema_version")?;
+
+    match order_schema::decode_order(&data_json, version) {
+        Ok((order, _)) => Ok(Some(order)),
+        Err(e) => {
+            warn!("Skipping undecodable order {} (schema v{}): {}", id, version, e);
+            Ok(None)
+        }
+    }
+}
+
+fn buyer_message_from_row(row: &SqliteRow) -> Result<BuyerMessage, sqlx::Error> {
+    Ok(BuyerMessage {
+        order_id: row.try_get("id")?,
+        status: row.try_get("status")?,
+        create_time: row.try_get("create_time")?,
+        message: row.try_get::<Option<String>, _>("buyer_message")?.unwrap_or_default(),
+        acked_at: row.try_get("buyer_message_acked_at")?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn late_orders_are_counted_from_the_index() {
+        // One connection, as each would open its own in-memory database
+        let settings = SqliteSettings {
+            max_connections: 1,
+            ..SqliteSettings::default()
+        };
+        let db = Database::open(":memory:", settings).await.unwrap();
+        db.init().await.unwrap();
+
+        let query = format!("EXPLAIN QUERY PLAN {}", late_orders_query());
+        let mut plan = sqlx::query(&query);
+        for _ in 0..=sla::PENDING_STATUSES.len() {
+            plan = plan.bind(0);
+        }
+        let details: Vec<String> = plan
+            .fetch_all(db.pool())
+            .await
+            .unwrap()
+            .iter()
+            .map(|row| row.get("detail"))
+            .collect();
+        assert!(
+            details.iter().any(|d| d.contains("USING INDEX idx_orders_status_due (status=? AND <expr>>? AND <expr><?)")),
+            "{:?}",
+            details
+        );
+
+        let version = db.orders_version(0).await.unwrap();
+        assert_eq!(version.late, 0);
+    }
+}
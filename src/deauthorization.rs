@@ -0,0 +1,88 @@
+use crate::config::Config;
+use crate::error::AppError;
+use crate::events::{EventBus, OrderEvent, OrderEventKind};
+use crate::shops::{ShopDisconnection, ShopRegistry};
+use tracing::{error, info};
+
+/// Status of a `shop_disconnected` event
+const DISCONNECTED: &str = "DISCONNECTED";
+
+/// Handles a seller de-authorizing the app: the shop is marked disconnected
+/// in the registry, the stored tokens are cleared and a `shop_disconnected`
+/// event is published. Scheduled syncs check [`Deauthorizer::disconnected`]
+/// and stay idle instead of failing every run.
+#[derive(Clone)]
+pub struct Deauthorizer {
+    shops: ShopRegistry,
+    config: Config,
+    events: EventBus,
+}
+
+impl Deauthorizer {
+    pub fn new(shops: ShopRegistry, config: Config, events: EventBus) -> Self {
+        Self {
+            shops,
+            config,
+            events,
+        }
+    }
+
+    /// Shop the stored token acts for
+    pub async fn shop_id(&self) -> Result<String, sqlx::Error> {
+        self.shops.configured_shop_id(&self.config).await
+    }
+
+    /// Disconnect `shop_id`. The stored tokens are cleared when they belong
+    /// to it. Returns `false` if the shop was already disconnected.
+    pub async fn disconnect(&self, shop_id: &str, reason: &str) -> Result<bool, AppError> {
+        // Cleared first, so `disconnected` doesn't take them for a new authorization
+        if shop_id == self.shop_id().await? {
+            self.config.token_manager().clear().await?;
+        }
+        if !self.shops.mark_disconnected(shop_id, reason).await? {
+            return Ok(false);
+        }
+        error!(
+            "Shop {:?} de-authorized the app ({}); its syncs are stopped until it is authorized again",
+            shop_id, reason
+        );
+        self.events.publish(OrderEvent::shop(
+            OrderEventKind::ShopDisconnected,
+            shop_id,
+            DISCONNECTED,
+        ));
+        Ok(true)
+    }
+
+    /// Disconnect the token's shop if `error` says the seller revoked access.
+    /// Returns whether it did.
+    pub async fn check(&self, error: &AppError) -> bool {
+        if !error.is_deauthorized() {
+            return false;
+        }
+        let result = match self.shop_id().await {
+            Ok(shop_id) => self.disconnect(&shop_id, &error.to_string()).await,
+            Err(e) => Err(e.into()),
+        };
+        if let Err(e) = result {
+            error!("Failed to disconnect the de-authorized shop: {}", e);
+        }
+        true
+    }
+
+    /// The token's shop's disconnection, if it is disconnected. A token
+    /// stored since means the seller authorized the app again, so the
+    /// disconnection is lifted instead.
+    pub async fn disconnected(&self) -> Result<Option<ShopDisconnection>, sqlx::Error> {
+        let shop_id = self.shop_id().await?;
+        let Some(disconnection) = self.shops.disconnection(&shop_id).await? else {
+            return Ok(None);
+        };
+        if self.config.token_manager().has_token() {
+            info!("Shop {:?} has a token again, resuming its syncs", shop_id);
+            self.shops.mark_connected(&shop_id).await?;
+            return Ok(None);
+        }
+        Ok(Some(disconnection))
+    }
+}
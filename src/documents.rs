@@ -0,0 +1,211 @@
+use crate::fulfillment::DocumentType;
+use sqlx::sqlite::{SqlitePool, SqliteRow};
+use sqlx::Row;
+use std::path::{Path, PathBuf};
+
+/// Metadata of a shipping document downloaded from TikTok
+#[derive(Debug, Clone)]
+pub struct StoredDocument {
+    pub order_id: String,
+    pub package_id: String,
+    pub document_type: String,
+    pub tracking_number: Option<String>,
+    pub size_bytes: i64,
+    pub fetched_at: i64,
+    /// File name under the documents directory
+    pub file_name: String,
+}
+
+/// Cache of shipping documents: PDFs live under `dir`, indexed by the `documents` table
+#[derive(Clone)]
+pub struct DocumentStore {
+    pool: SqlitePool,
+    dir: PathBuf,
+}
+
+impl DocumentStore {
+    pub fn new(pool: SqlitePool, dir: impl Into<PathBuf>) -> Self {
+        Self {
+            pool,
+            dir: dir.into(),
+        }
+    }
+
+    /// Create the documents directory and table
+    pub async fn init(&self) -> Result<(), sqlx::Error> {
+        tokio::fs::create_dir_all(&self.dir).await?;
+
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS documents (
+                package_id TEXT NOT NULL,
+                document_type TEXT NOT NULL,
+                order_id TEXT NOT NULL,
+                tracking_number TEXT,
+                file_name TEXT NOT NULL,
+                size_bytes INTEGER NOT NULL,
+                fetched_at INTEGER NOT NULL,
+                PRIMARY KEY (package_id, document_type)
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_documents_order ON documents(order_id)")
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Cached document and its bytes; `None` if it was never fetched or its file is gone
+    pub async fn get(
+        &self,
+        package_id: &str,
+        document_type: DocumentType,
+    ) -> Result<Option<(StoredDocument, Vec<u8>)>, sqlx::Error> {
+        let row = sqlx::query(
+            "SELECT order_id, package_id, document_type, tracking_number, file_name, size_bytes, fetched_at
+            FROM documents WHERE package_id = ?1 AND document_type = ?2",
+        )
+        .bind(package_id)
+        .bind(document_type.as_str())
+        .fetch_optional(&self.pool)
+        .await?;
+
+        let Some(document) = row.map(|row| document_from_row(&row)).transpose()? else {
+            return Ok(None);
+        };
+
+        match tokio::fs::read(self.dir.join(&document.file_name)).await {
+            Ok(bytes) => Ok(Some((document, bytes))),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Write a document's file and (re)index it
+    pub async fn save(
+        &self,
+        order_id: &str,
+        package_id: &str,
+        document_type: DocumentType,
+        tracking_number: Option<&str>,
+        bytes: &[u8],
+    ) -> Result<StoredDocument, sqlx::Error> {
+        let file_name = format!(
+            "{}-{}.pdf",
+            sanitize(package_id),
+            document_type.as_str().to_ascii_lowercase()
+        );
+        write_atomic(&self.dir.join(&file_name), bytes).await?;
+
+        let document = StoredDocument {
+            order_id: order_id.to_string(),
+            package_id: package_id.to_string(),
+            document_type: document_type.as_str().to_string(),
+            tracking_number: tracking_number.map(str::to_string),
+            size_bytes: bytes.len() as i64,
+            fetched_at: chrono::Utc::now().timestamp(),
+            file_name,
+        };
+
+        sqlx::query(
+            "INSERT OR REPLACE INTO documents (
+                package_id, document_type, order_id, tracking_number, file_name, size_bytes, fetched_at
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        )
+        .bind(&document.package_id)
+        .bind(&document.document_type)
+        .bind(&document.order_id)
+        .bind(&document.tracking_number)
+        .bind(&document.file_name)
+        .bind(document.size_bytes)
+        .bind(document.fetched_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(document)
+    }
+
+    /// Delete the documents of `order_ids`, their files first and then their
+    /// rows, so a failure leaves the rest to delete on the next attempt.
+    /// Returns the deleted documents.
+    pub async fn delete_for_orders(
+        &self,
+        order_ids: &[String],
+    ) -> Result<Vec<StoredDocument>, sqlx::Error> {
+        let mut deleted = Vec::new();
+        for order_id in order_ids {
+            let rows = sqlx::query(
+                "SELECT order_id, package_id, document_type, tracking_number, file_name, size_bytes, fetched_at
+                FROM documents WHERE order_id = ?1",
+            )
+            .bind(order_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+            for row in rows {
+                let document = document_from_row(&row)?;
+                match tokio::fs::remove_file(self.dir.join(&document.file_name)).await {
+                    Ok(()) => {}
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                    Err(e) => return Err(e.into()),
+                }
+                sqlx::query("DELETE FROM documents WHERE package_id = ?1 AND document_type = ?2")
+                    .bind(&document.package_id)
+                    .bind(&document.document_type)
+                    .execute(&self.pool)
+                    .await?;
+                deleted.push(document);
+            }
+        }
+        Ok(deleted)
+    }
+
+    /// Orders whose PII was scrubbed that still have documents, e.g. labels
+    /// cached before the erasure
+    pub async fn scrubbed_order_ids(&self) -> Result<Vec<String>, sqlx::Error> {
+        sqlx::query(
+            "SELECT DISTINCT d.order_id FROM documents d
+            JOIN orders o ON o.id = d.order_id
+            WHERE o.pii_scrubbed_at IS NOT NULL",
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(|row| row.try_get("order_id"))
+        .collect()
+    }
+}
+
+fn document_from_row(row: &SqliteRow) -> Result<StoredDocument, sqlx::Error> {
+    Ok(StoredDocument {
+        order_id: row.try_get("order_id")?,
+        package_id: row.try_get("package_id")?,
+        document_type: row.try_get("document_type")?,
+        tracking_number: row.try_get("tracking_number")?,
+        size_bytes: row.try_get("size_bytes")?,
+        fetched_at: row.try_get("fetched_at")?,
+        file_name: row.try_get("file_name")?,
+    })
+}
+
+/// Keep ids usable as file names
+fn sanitize(id: &str) -> String {
+    id.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Write through a temporary file so readers never see a partial PDF
+async fn write_atomic(path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+    let tmp = path.with_extension("pdf.tmp");
+    tokio::fs::write(&tmp, bytes).await?;
+    tokio::fs::rename(&tmp, path).await
+}
@@ -0,0 +1,135 @@
+use crate::database::Database;
+use crate::documents::{DocumentStore, StoredDocument};
+use serde::Serialize;
+use sqlx::sqlite::SqlitePool;
+use std::sync::Arc;
+use utoipa::ToSchema;
+
+/// Body left in place of scrubbed buyer communications
+const ERASED_BODY: &str = "[erased]";
+
+/// A cached shipping document deleted by an erasure
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ErasedDocument {
+    pub order_id: String,
+    pub package_id: String,
+    pub document_type: String,
+}
+
+impl From<StoredDocument> for ErasedDocument {
+    fn from(document: StoredDocument) -> Self {
+        Self {
+            order_id: document.order_id,
+            package_id: document.package_id,
+            document_type: document.document_type,
+        }
+    }
+}
+
+/// What an erasure removed
+#[derive(Debug, Clone, Default, Serialize, ToSchema)]
+pub struct ErasureReport {
+    /// Orders buyer PII was scrubbed from
+    pub orders_scrubbed: u64,
+    /// Cached shipping labels, whose files and `documents` rows were deleted
+    pub documents_deleted: Vec<ErasedDocument>,
+}
+
+/// Erases buyer PII, for one customer on request or for every order past the
+/// retention period. Orders, amounts and items stay, so stats, SKU totals and
+/// customer order counts are unchanged; see [`crate::anonymize::scrub_order`]
+/// for what is removed from each order. Also clears the street of the
+/// normalized address, the bodies of logged communications and, once none of
+/// their orders hold PII, the customer's name, and deletes the cached
+/// shipping labels, which print the buyer's name and address.
+#[derive(Clone)]
+pub struct PiiEraser {
+    db: Arc<Database>,
+    pool: SqlitePool,
+    documents: DocumentStore,
+}
+
+impl PiiEraser {
+    pub fn new(db: Arc<Database>, documents: DocumentStore) -> Self {
+        let pool = db.pool().clone();
+        Self {
+            db,
+            pool,
+            documents,
+        }
+    }
+
+    /// Scrub every order of `customer_id` and drop the customer's name and
+    /// identity hashes, so they can no longer be looked up by user ID or
+    /// email. Returns `None` for an unknown customer.
+    pub async fn erase_customer(
+        &self,
+        customer_id: &str,
+    ) -> Result<Option<ErasureReport>, sqlx::Error> {
+        let forgotten = sqlx::query(
+            "UPDATE customers SET name = NULL, user_id_hash = NULL, email_hash = NULL
+            WHERE id = ?1",
+        )
+        .bind(customer_id)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+        let order_ids = self.db.customer_order_ids(customer_id).await?;
+        if forgotten == 0 && order_ids.is_empty() {
+            return Ok(None);
+        }
+        self.scrub(&order_ids).await.map(Some)
+    }
+
+    /// Scrub orders not updated since `cutoff`, and delete documents left
+    /// over from earlier erasures
+    pub async fn scrub_older_than(&self, cutoff: i64) -> Result<ErasureReport, sqlx::Error> {
+        let order_ids = self.db.unscrubbed_order_ids(cutoff).await?;
+        let mut report = if order_ids.is_empty() {
+            ErasureReport::default()
+        } else {
+            self.scrub(&order_ids).await?
+        };
+
+        let leftover = self.documents.scrubbed_order_ids().await?;
+        let deleted = self.documents.delete_for_orders(&leftover).await?;
+        report
+            .documents_deleted
+            .extend(deleted.into_iter().map(ErasedDocument::from));
+        Ok(report)
+    }
+
+    async fn scrub(&self, order_ids: &[String]) -> Result<ErasureReport, sqlx::Error> {
+        let scrubbed = self.db.scrub_orders(order_ids).await?;
+
+        let mut tx = self.pool.begin().await?;
+        for order_id in order_ids {
+            sqlx::query("UPDATE order_addresses SET street = NULL WHERE order_id = ?1")
+                .bind(order_id)
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query("UPDATE order_communications SET body = ?2 WHERE order_id = ?1")
+                .bind(order_id)
+                .bind(ERASED_BODY)
+                .execute(&mut *tx)
+                .await?;
+        }
+        // A customer's name comes from their latest order
+        sqlx::query(
+            "UPDATE customers SET name = NULL
+            WHERE name IS NOT NULL AND NOT EXISTS (
+                SELECT 1 FROM orders o
+                WHERE o.customer_id = customers.id AND o.pii_scrubbed_at IS NULL
+            )",
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        let deleted = self.documents.delete_for_orders(order_ids).await?;
+        Ok(ErasureReport {
+            orders_scrubbed: scrubbed,
+            documents_deleted: deleted.into_iter().map(ErasedDocument::from).collect(),
+        })
+    }
+}
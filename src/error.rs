@@ -1,33 +1,76 @@
+#[cfg(feature = "server")]
 use axum::{
-    http::StatusCode,
     response::{IntoResponse, Response},
     Json,
 };
+use reqwest::StatusCode;
+#[cfg(feature = "server")]
 use serde_json::json;
 use thiserror::Error;
 
+/// TikTok API codes for an invalid or expired access token
+const TIKTOK_AUTH_ERROR_CODES: &[i32] = &[105001, 105002];
+
+/// TikTok API codes for a token revoked because the seller de-authorized the
+/// app; unlike an expired token, refreshing won't bring it back
+const TIKTOK_DEAUTHORIZED_CODES: &[i32] = &[105003];
+
 #[derive(Debug, Error)]
 pub enum AppError {
     #[error("No token stored")]
     NoTokenStored,
 
+    #[error("Not found: {0}")]
+    NotFound(String),
+
+    #[error("Unauthorized: {0}")]
+    Unauthorized(String),
+
+    #[error("Bad request: {0}")]
+    BadRequest(String),
+
+    /// The caller repeated an action before its cooldown ended
+    #[error("Too many requests: {0}")]
+    TooManyRequests(String),
+
     #[error("Invalid URL")]
     InvalidUrl,
 
-    #[error("HTTP error: {0}")]
-    HttpError(String),
+    /// The request never got a response (connection, timeout, reading the body)
+    #[error("HTTP transport error: {source}")]
+    Transport {
+        #[source]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    /// An upstream API answered with a non-success HTTP status
+    #[error("Upstream returned HTTP {status}: {body}")]
+    HttpStatus { status: StatusCode, body: String },
+
+    #[error("Token exchange failed (HTTP {status}): {body}")]
+    TokenExchangeFailed { status: StatusCode, body: String },
 
-    #[error("Token exchange failed: {0}")]
-    TokenExchangeFailed(String),
+    #[error("Token refresh failed (HTTP {status}): {body}")]
+    TokenRefreshFailed { status: StatusCode, body: String },
 
-    #[error("Token refresh failed: {0}")]
-    TokenRefreshFailed(String),
+    /// TikTok answered 200 with a non-zero `code`
+    #[error("API error (code {code}): {message}")]
+    ApiError {
+        code: i32,
+        message: String,
+        request_id: Option<String>,
+    },
 
-    #[error("API error (code {0}): {1}")]
-    ApiError(i32, String),
+    #[error("Parse error: {context}: {source}")]
+    ParseError {
+        context: String,
+        #[source]
+        source: serde_json::Error,
+    },
 
-    #[error("Parse error: {0}")]
-    ParseError(String),
+    /// WowEsim answered `success: false` or with a response that can't be read
+    #[error("WowEsim error: {0}")]
+    WowError(String),
 
     #[error("Configuration error: {0}")]
     ConfigError(String),
@@ -35,30 +78,151 @@ pub enum AppError {
     #[error("Signature generation error: {0}")]
     SignatureError(String),
 
+    #[cfg(feature = "server")]
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] sqlx::Error),
+
     #[error("Internal server error")]
     InternalServerError,
 }
 
+impl AppError {
+    /// Transport failure that isn't a `reqwest::Error` (e.g. from a test transport)
+    pub fn transport(message: impl Into<String>) -> Self {
+        AppError::Transport {
+            source: message.into().into(),
+        }
+    }
+
+    pub fn parse(context: impl Into<String>, source: serde_json::Error) -> Self {
+        AppError::ParseError {
+            context: context.into(),
+            source,
+        }
+    }
+
+    /// Whether the same call may succeed later: transport failures, 429 and
+    /// 5xx responses, and a busy or unreachable database
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            AppError::Transport { .. } | AppError::TooManyRequests(_) => true,
+            AppError::HttpStatus { status, .. } => {
+                *status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+            }
+            #[cfg(feature = "server")]
+            AppError::DatabaseError(e) => match e {
+                sqlx::Error::PoolTimedOut | sqlx::Error::Io(_) => true,
+                // SQLITE_BUSY / SQLITE_LOCKED
+                sqlx::Error::Database(db) => matches!(db.code().as_deref(), Some("5" | "6")),
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    /// Whether the failure is about credentials: missing, rejected or expired tokens
+    pub fn is_auth_error(&self) -> bool {
+        match self {
+            AppError::NoTokenStored
+            | AppError::Unauthorized(_)
+            | AppError::TokenExchangeFailed { .. }
+            | AppError::TokenRefreshFailed { .. } => true,
+            AppError::HttpStatus { status, .. } => {
+                *status == StatusCode::UNAUTHORIZED || *status == StatusCode::FORBIDDEN
+            }
+            AppError::ApiError { code, .. } => {
+                TIKTOK_AUTH_ERROR_CODES.contains(code) || TIKTOK_DEAUTHORIZED_CODES.contains(code)
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether the seller revoked the app's access to the shop
+    pub fn is_deauthorized(&self) -> bool {
+        matches!(self, AppError::ApiError { code, .. } if TIKTOK_DEAUTHORIZED_CODES.contains(code))
+    }
+
+    /// Stable machine-readable identifier for API error responses
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::NoTokenStored => "no_token",
+            AppError::NotFound(_) => "not_found",
+            AppError::Unauthorized(_) => "unauthorized",
+            AppError::BadRequest(_) => "bad_request",
+            AppError::TooManyRequests(_) => "rate_limited",
+            AppError::InvalidUrl => "invalid_url",
+            AppError::Transport { .. } => "upstream_unreachable",
+            AppError::HttpStatus { .. } => "upstream_http_error",
+            AppError::TokenExchangeFailed { .. } => "token_exchange_failed",
+            AppError::TokenRefreshFailed { .. } => "token_refresh_failed",
+            AppError::ApiError { .. } => "upstream_api_error",
+            AppError::ParseError { .. } => "parse_error",
+            AppError::WowError(_) => "wow_api_error",
+            AppError::ConfigError(_) => "config_error",
+            AppError::SignatureError(_) => "signature_error",
+            #[cfg(feature = "server")]
+            AppError::DatabaseError(_) => "database_error",
+            AppError::InternalServerError => "internal_error",
+        }
+    }
+
+    /// HTTP status this error is reported with by the server
+    pub fn status(&self) -> StatusCode {
+        if self.is_auth_error() {
+            return StatusCode::UNAUTHORIZED;
+        }
+
+        match self {
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            AppError::TooManyRequests(_) => StatusCode::TOO_MANY_REQUESTS,
+            AppError::Transport { source } => {
+                let timed_out = source
+                    .downcast_ref::<reqwest::Error>()
+                    .is_some_and(|e| e.is_timeout());
+                if timed_out {
+                    StatusCode::GATEWAY_TIMEOUT
+                } else {
+                    StatusCode::BAD_GATEWAY
+                }
+            }
+            AppError::HttpStatus { status, .. } if *status == StatusCode::TOO_MANY_REQUESTS => {
+                StatusCode::TOO_MANY_REQUESTS
+            }
+            AppError::HttpStatus { .. } | AppError::ApiError { .. } | AppError::WowError(_) => {
+                StatusCode::BAD_GATEWAY
+            }
+            #[cfg(feature = "server")]
+            AppError::DatabaseError(_) if self.is_retryable() => StatusCode::SERVICE_UNAVAILABLE,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl From<reqwest::Error> for AppError {
+    fn from(e: reqwest::Error) -> Self {
+        AppError::Transport {
+            source: Box::new(e),
+        }
+    }
+}
+
+#[cfg(feature = "server")]
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
-        let (status, error_message) = match self {
-            // AppError::InvalidState => (StatusCode::BAD_REQUEST, self.to_string()),
-            AppError::NoTokenStored => (StatusCode::NOT_FOUND, self.to_string()),
-            AppError::InvalidUrl => (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()),
-            AppError::HttpError(_) => (StatusCode::BAD_GATEWAY, self.to_string()),
-            AppError::TokenExchangeFailed(_) => (StatusCode::BAD_REQUEST, self.to_string()),
-            AppError::TokenRefreshFailed(_) => (StatusCode::BAD_REQUEST, self.to_string()),
-            AppError::ApiError(_, _) => (StatusCode::BAD_REQUEST, self.to_string()),
-            AppError::ParseError(_) => (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()),
-            AppError::ConfigError(_) => (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()),
-            AppError::SignatureError(_) => (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()),
-            AppError::InternalServerError => (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()),
-        };
-
-        let body = Json(json!({
-            "error": error_message,
-        }));
-
-        (status, body).into_response()
+        let mut body = json!({
+            "error": self.to_string(),
+            "code": self.code(),
+            "retryable": self.is_retryable(),
+        });
+        if let AppError::ApiError {
+            code, request_id, ..
+        } = &self
+        {
+            body["upstream_code"] = json!(code);
+            body["upstream_request_id"] = json!(request_id);
+        }
+
+        (self.status(), Json(body)).into_response()
     }
 }
@@ -0,0 +1,902 @@
+use crate::config::Config;
+use crate::database::Database;
+use crate::esim_delivery;
+use crate::fulfillment::ShipPackageRequest;
+use crate::jobs::{Job, JobHandler, JobQueue};
+use crate::order::{CancelOrderRequest, Order};
+use crate::shops::ShopRegistry;
+use crate::sku_mappings::{SkuMappingStore, SkuMappingTable};
+use crate::wow_requests::{EsimProvisionResult, EsimUsage, WowEsimApiClient};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::SqlitePool;
+use sqlx::Row;
+use std::collections::HashSet;
+use std::sync::Arc;
+use tracing::{error, info, warn};
+use utoipa::ToSchema;
+
+pub const PROVISION_JOB_KIND: &str = "esim_provision";
+
+/// Mark an order shipped in TikTok once all its eSIMs are provisioned
+pub const AUTO_COMPLETE_JOB_KIND: &str = "esim_auto_complete";
+
+/// Order status in which paid eSIM items are ready to be provisioned
+const PROVISIONABLE_STATUS: &str = "AWAITING_SHIPMENT";
+
+/// Provider statuses after which an eSIM's usage is no longer polled
+const FINISHED_STATUSES: &[&str] = &["EXPIRED", "TERMINATED", "CANCELLED"];
+
+/// Payload of an `esim_provision` job (one per order line item)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProvisionJobPayload {
+    pub order_id: String,
+    pub line_item_id: String,
+    pub sku_id: String,
+    pub seller_sku: String,
+    pub package_code: String,
+    pub quantity: i32,
+    /// The SKU opted in to auto-completing its orders
+    #[serde(default)]
+    pub auto_complete: bool,
+}
+
+impl ProvisionJobPayload {
+    fn dedup_key(&self) -> String {
+        format!(
+            "{}:{}:{}",
+            PROVISION_JOB_KIND, self.order_id, self.line_item_id
+        )
+    }
+}
+
+/// Payload of an `esim_auto_complete` job
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AutoCompleteJobPayload {
+    pub order_id: String,
+}
+
+/// A compensation recorded after provisioning permanently failed
+#[derive(Debug, Clone, Serialize)]
+pub struct CompensationAction {
+    pub id: i64,
+    pub order_id: String,
+    pub line_item_id: String,
+    pub action: String,
+    pub status: String,
+    pub detail: Option<String>,
+    pub created_at: i64,
+}
+
+/// An eSIM provisioned for an order line item
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ProvisionedEsim {
+    pub iccid: String,
+    pub order_id: String,
+    pub line_item_id: String,
+    pub package_code: String,
+    pub provisioned_at: i64,
+}
+
+/// What the buyer needs to install a provisioned eSIM
+#[derive(Debug, Clone)]
+pub struct EsimActivation {
+    pub order_id: String,
+    pub line_item_id: String,
+    pub iccid: Option<String>,
+    pub qr_code: Option<String>,
+    pub activation_code: Option<String>,
+}
+
+/// Data balance of an eSIM as polled from WowEsim
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct EsimUsageSnapshot {
+    /// Provider status, e.g. `ACTIVE` or `EXPIRED`
+    pub status: Option<String>,
+    pub data_total_mb: Option<f64>,
+    pub data_used_mb: Option<f64>,
+    pub data_remaining_mb: Option<f64>,
+    /// Share of the data cap used, 0-100
+    pub used_percent: Option<f64>,
+    pub expires_at: Option<String>,
+    pub captured_at: i64,
+}
+
+/// Share of `total` used, from whichever of used and remaining is known
+fn used_percent(total: Option<f64>, used: Option<f64>, remaining: Option<f64>) -> Option<f64> {
+    let total = total.filter(|total| *total > 0.0)?;
+    let used = used.or_else(|| remaining.map(|remaining| total - remaining))?;
+    Some((used / total * 100.0).clamp(0.0, 100.0))
+}
+
+/// Persistence for provisioned eSIMs and compensation actions
+#[derive(Clone)]
+pub struct EsimStore {
+    pool: SqlitePool,
+}
+
+impl EsimStore {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Initialize eSIM tables
+    pub async fn init(&self) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS esim_provisions (
+                line_item_id TEXT PRIMARY KEY,
+                order_id TEXT NOT NULL,
+                package_code TEXT NOT NULL,
+                iccid TEXT,
+                qr_code TEXT,
+                activation_code TEXT,
+                created_at INTEGER NOT NULL
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS esim_usage_snapshots (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                iccid TEXT NOT NULL,
+                status TEXT,
+                data_total_mb REAL,
+                data_used_mb REAL,
+                data_remaining_mb REAL,
+                expires_at TEXT,
+                captured_at INTEGER NOT NULL
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            "CREATE INDEX IF NOT EXISTS idx_esim_usage_snapshots_iccid
+            ON esim_usage_snapshots(iccid, captured_at)",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS esim_auto_completions (
+                order_id TEXT PRIMARY KEY,
+                status TEXT NOT NULL,
+                detail TEXT,
+                created_at INTEGER NOT NULL
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS compensation_actions (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                order_id TEXT NOT NULL,
+                line_item_id TEXT NOT NULL,
+                action TEXT NOT NULL,
+                status TEXT NOT NULL,
+                detail TEXT,
+                created_at INTEGER NOT NULL
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn is_provisioned(&self, line_item_id: &str) -> Result<bool, sqlx::Error> {
+        let row = sqlx::query("SELECT 1 FROM esim_provisions WHERE line_item_id = ?1")
+            .bind(line_item_id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(row.is_some())
+    }
+
+    pub async fn record_provision(
+        &self,
+        payload: &ProvisionJobPayload,
+        result: &EsimProvisionResult,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "INSERT OR REPLACE INTO esim_provisions (
+                line_item_id, order_id, package_code, iccid, qr_code, activation_code, created_at
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        )
+        .bind(&payload.line_item_id)
+        .bind(&payload.order_id)
+        .bind(&payload.package_code)
+        .bind(&result.iccid)
+        .bind(&result.qr_code)
+        .bind(&result.activation_code)
+        .bind(chrono::Utc::now().timestamp())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// The eSIM with `iccid`, if one was provisioned
+    pub async fn provisioned_esim(
+        &self,
+        iccid: &str,
+    ) -> Result<Option<ProvisionedEsim>, sqlx::Error> {
+        let row = sqlx::query(
+            "SELECT iccid, order_id, line_item_id, package_code, created_at
+            FROM esim_provisions WHERE iccid = ?1",
+        )
+        .bind(iccid)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.map(|row| provisioned_esim(&row)).transpose()
+    }
+
+    /// Activation details of the eSIM provisioned for `line_item_id`
+    pub async fn activation(
+        &self,
+        line_item_id: &str,
+    ) -> Result<Option<EsimActivation>, sqlx::Error> {
+        let row = sqlx::query(
+            "SELECT order_id, line_item_id, iccid, qr_code, activation_code
+            FROM esim_provisions WHERE line_item_id = ?1",
+        )
+        .bind(line_item_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.map(|row| {
+            Ok(EsimActivation {
+                order_id: row.try_get("order_id")?,
+                line_item_id: row.try_get("line_item_id")?,
+                iccid: row.try_get("iccid")?,
+                qr_code: row.try_get("qr_code")?,
+                activation_code: row.try_get("activation_code")?,
+            })
+        })
+        .transpose()
+    }
+
+    /// Line items of `order_id` with a provisioned eSIM
+    pub async fn provisioned_line_items(&self, order_id: &str) -> Result<Vec<String>, sqlx::Error> {
+        let rows = sqlx::query(
+            "SELECT line_item_id FROM esim_provisions WHERE order_id = ?1 ORDER BY created_at",
+        )
+        .bind(order_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter().map(|row| row.try_get("line_item_id")).collect()
+    }
+
+    /// Whether `order_id` was already marked shipped (or would have been, in
+    /// sandbox mode) after its eSIMs were provisioned
+    pub async fn is_auto_completed(&self, order_id: &str) -> Result<bool, sqlx::Error> {
+        let row = sqlx::query("SELECT 1 FROM esim_auto_completions WHERE order_id = ?1")
+            .bind(order_id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(row.is_some())
+    }
+
+    /// Record that `order_id` was marked shipped; `status` is `done` or `dry_run`
+    pub async fn record_auto_completion(
+        &self,
+        order_id: &str,
+        status: &str,
+        detail: &str,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "INSERT OR REPLACE INTO esim_auto_completions (order_id, status, detail, created_at)
+            VALUES (?1, ?2, ?3, ?4)",
+        )
+        .bind(order_id)
+        .bind(status)
+        .bind(detail)
+        .bind(chrono::Utc::now().timestamp())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// eSIMs whose usage is still worth polling: provisioned with an ICCID and
+    /// not last seen expired or terminated
+    pub async fn esims_to_poll(&self) -> Result<Vec<ProvisionedEsim>, sqlx::Error> {
+        let rows = sqlx::query(
+            "SELECT p.iccid, p.order_id, p.line_item_id, p.package_code, p.created_at,
+                (SELECT s.status FROM esim_usage_snapshots s WHERE s.iccid = p.iccid
+                ORDER BY s.captured_at DESC, s.id DESC LIMIT 1) AS last_status
+            FROM esim_provisions p
+            WHERE p.iccid IS NOT NULL
+            ORDER BY p.created_at",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut esims = Vec::new();
+        for row in &rows {
+            let last_status: Option<String> = row.try_get("last_status")?;
+            let finished = last_status.is_some_and(|status| {
+                FINISHED_STATUSES.contains(&status.to_ascii_uppercase().as_str())
+            });
+            if !finished {
+                esims.push(provisioned_esim(row)?);
+            }
+        }
+        Ok(esims)
+    }
+
+    pub async fn record_usage(&self, iccid: &str, usage: &EsimUsage) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "INSERT INTO esim_usage_snapshots (
+                iccid, status, data_total_mb, data_used_mb, data_remaining_mb, expires_at,
+                captured_at
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        )
+        .bind(iccid)
+        .bind(&usage.status)
+        .bind(usage.data_total_mb)
+        .bind(usage.data_used_mb)
+        .bind(usage.data_remaining_mb)
+        .bind(&usage.expires_at)
+        .bind(chrono::Utc::now().timestamp())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Usage snapshots of `iccid`, newest first
+    pub async fn usage_history(
+        &self,
+        iccid: &str,
+        limit: i64,
+    ) -> Result<Vec<EsimUsageSnapshot>, sqlx::Error> {
+        let rows = sqlx::query(
+            "SELECT status, data_total_mb, data_used_mb, data_remaining_mb, expires_at, captured_at
+            FROM esim_usage_snapshots WHERE iccid = ?1
+            ORDER BY captured_at DESC, id DESC LIMIT ?2",
+        )
+        .bind(iccid)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter()
+            .map(|row| {
+                let data_total_mb: Option<f64> = row.try_get("data_total_mb")?;
+                let data_used_mb: Option<f64> = row.try_get("data_used_mb")?;
+                let data_remaining_mb: Option<f64> = row.try_get("data_remaining_mb")?;
+                Ok(EsimUsageSnapshot {
+                    status: row.try_get("status")?,
+                    data_total_mb,
+                    data_used_mb,
+                    data_remaining_mb,
+                    used_percent: used_percent(data_total_mb, data_used_mb, data_remaining_mb),
+                    expires_at: row.try_get("expires_at")?,
+                    captured_at: row.try_get("captured_at")?,
+                })
+            })
+            .collect()
+    }
+
+    pub async fn record_compensation(
+        &self,
+        order_id: &str,
+        line_item_id: &str,
+        action: &str,
+        status: &str,
+        detail: Option<&str>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "INSERT INTO compensation_actions (
+                order_id, line_item_id, action, status, detail, created_at
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        )
+        .bind(order_id)
+        .bind(line_item_id)
+        .bind(action)
+        .bind(status)
+        .bind(detail)
+        .bind(chrono::Utc::now().timestamp())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Get compensation actions, newest first
+    pub async fn get_compensations(&self) -> Result<Vec<CompensationAction>, sqlx::Error> {
+        let rows = sqlx::query(
+            "SELECT id, order_id, line_item_id, action, status, detail, created_at
+            FROM compensation_actions ORDER BY created_at DESC",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter()
+            .map(|row| {
+                Ok(CompensationAction {
+                    id: row.try_get("id")?,
+                    order_id: row.try_get("order_id")?,
+                    line_item_id: row.try_get("line_item_id")?,
+                    action: row.try_get("action")?,
+                    status: row.try_get("status")?,
+                    detail: row.try_get("detail")?,
+                    created_at: row.try_get("created_at")?,
+                })
+            })
+            .collect()
+    }
+}
+
+fn provisioned_esim(row: &sqlx::sqlite::SqliteRow) -> Result<ProvisionedEsim, sqlx::Error> {
+    Ok(ProvisionedEsim {
+        iccid: row.try_get("iccid")?,
+        order_id: row.try_get("order_id")?,
+        line_item_id: row.try_get("line_item_id")?,
+        package_code: row.try_get("package_code")?,
+        provisioned_at: row.try_get("created_at")?,
+    })
+}
+
+/// Build provisioning payloads for the eSIM line items of an order
+pub fn provisioning_payloads(
+    order: &Order,
+    mappings: &SkuMappingTable,
+) -> Vec<ProvisionJobPayload> {
+    if order.status != PROVISIONABLE_STATUS {
+        return Vec::new();
+    }
+
+    order
+        .item_list
+        .iter()
+        .filter_map(|item| {
+            let package_code = mappings.esim_package(item)?;
+            Some(ProvisionJobPayload {
+                order_id: order.id.clone(),
+                line_item_id: item.id.clone(),
+                sku_id: item.sku_id.clone(),
+                seller_sku: item.seller_sku.clone().unwrap_or_default(),
+                package_code: package_code.to_string(),
+                quantity: item.quantity.unwrap_or(1),
+                auto_complete: mappings.auto_completes(item),
+            })
+        })
+        .collect()
+}
+
+/// Whether every live line item of `order` is an auto-complete SKU whose
+/// eSIM has been provisioned, so nothing is left to ship physically
+pub fn auto_completable(
+    order: &Order,
+    mappings: &SkuMappingTable,
+    provisioned: &HashSet<String>,
+) -> bool {
+    let mut items = order
+        .item_list
+        .iter()
+        .filter(|item| item.display_status.as_deref() != Some("CANCELLED"))
+        .peekable();
+
+    items.peek().is_some()
+        && items.all(|item| mappings.auto_completes(item) && provisioned.contains(&item.id))
+}
+
+/// Enqueue provisioning jobs for eSIM items. Already-enqueued items are skipped,
+/// so this is safe to call on every sync. Returns the number of new jobs.
+pub async fn enqueue_provisioning(
+    queue: &JobQueue,
+    orders: &[Order],
+    mappings: &SkuMappingTable,
+    max_attempts: u32,
+) -> Result<usize, sqlx::Error> {
+    let mut enqueued = 0;
+
+    for payload in orders
+        .iter()
+        .flat_map(|order| provisioning_payloads(order, mappings))
+    {
+        let value = serde_json::to_value(&payload).unwrap_or_default();
+        if queue
+            .enqueue(
+                PROVISION_JOB_KIND,
+                Some(&payload.dedup_key()),
+                &value,
+                max_attempts,
+            )
+            .await?
+        {
+            enqueued += 1;
+        }
+    }
+
+    Ok(enqueued)
+}
+
+/// Job handler that provisions eSIMs and compensates on permanent failure
+pub struct EsimProvisioningHandler {
+    wow_client: WowEsimApiClient,
+    store: EsimStore,
+    /// Follow-up jobs (buyer delivery, auto-complete) are enqueued on it
+    queue: JobQueue,
+    shops: ShopRegistry,
+    config: Config,
+    deliver: bool,
+}
+
+impl EsimProvisioningHandler {
+    pub fn new(
+        wow_client: WowEsimApiClient,
+        store: EsimStore,
+        queue: JobQueue,
+        shops: ShopRegistry,
+        config: Config,
+    ) -> Self {
+        Self {
+            wow_client,
+            store,
+            queue,
+            shops,
+            config,
+            deliver: false,
+        }
+    }
+
+    /// Send activation details to the buyer once an item is provisioned
+    pub fn with_delivery(mut self) -> Self {
+        self.deliver = true;
+        self
+    }
+
+    /// Queue the jobs that follow a provisioned item. Provisioning is already
+    /// recorded, so failing to enqueue is logged rather than retried.
+    async fn enqueue_follow_ups(&self, payload: &ProvisionJobPayload) {
+        let max_attempts = self.config.job_max_attempts;
+        if self.deliver {
+            if let Err(e) = esim_delivery::enqueue_delivery(
+                &self.queue,
+                &payload.order_id,
+                &payload.line_item_id,
+                false,
+                max_attempts,
+            )
+            .await
+            {
+                error!(
+                    "Failed to enqueue eSIM delivery for order {} item {}: {}",
+                    payload.order_id, payload.line_item_id, e
+                );
+            }
+        }
+
+        if payload.auto_complete {
+            let value = serde_json::to_value(AutoCompleteJobPayload {
+                order_id: payload.order_id.clone(),
+            })
+            .unwrap_or_default();
+            let dedup_key = format!(
+                "{}:{}:{}",
+                AUTO_COMPLETE_JOB_KIND, payload.order_id, payload.line_item_id
+            );
+            if let Err(e) = self
+                .queue
+                .enqueue(AUTO_COMPLETE_JOB_KIND, Some(&dedup_key), &value, max_attempts)
+                .await
+            {
+                error!(
+                    "Failed to enqueue auto-complete of order {}: {}",
+                    payload.order_id, e
+                );
+            }
+        }
+    }
+
+    fn payload(job: &Job) -> Result<ProvisionJobPayload, String> {
+        serde_json::from_value(job.payload.clone())
+            .map_err(|e| format!("Invalid provisioning payload: {}", e))
+    }
+
+    async fn cancel_item(&self, payload: &ProvisionJobPayload) -> Result<String, String> {
+        let token = self
+            .config
+            .token_manager()
+            .valid_token()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let request = CancelOrderRequest {
+            order_id: payload.order_id.clone(),
+            order_line_item_ids: vec![payload.line_item_id.clone()],
+            cancel_reason: "seller_out_of_stock".to_string(),
+        };
+
+        let order_client = self
+            .shops
+            .order_client(&self.config)
+            .await
+            .map_err(|e| e.to_string())?;
+        let response = order_client
+            .cancel_order(&token.access_token, None, &request)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        Ok(format!(
+            "cancel_id={} status={}",
+            response.cancel_id.unwrap_or_default(),
+            response.cancel_status.unwrap_or_default()
+        ))
+    }
+
+    async fn record(
+        &self,
+        payload: &ProvisionJobPayload,
+        action: &str,
+        status: &str,
+        detail: &str,
+    ) {
+        if let Err(e) = self
+            .store
+            .record_compensation(
+                &payload.order_id,
+                &payload.line_item_id,
+                action,
+                status,
+                Some(detail),
+            )
+            .await
+        {
+            error!(
+                "Failed to record {} compensation for order {}: {}",
+                action, payload.order_id, e
+            );
+        }
+    }
+}
+
+#[async_trait]
+impl JobHandler for EsimProvisioningHandler {
+    async fn handle(&self, job: &Job) -> Result<(), String> {
+        let payload = Self::payload(job)?;
+
+        if self
+            .store
+            .is_provisioned(&payload.line_item_id)
+            .await
+            .map_err(|e| e.to_string())?
+        {
+            return Ok(());
+        }
+
+        let reference = format!("{}-{}", payload.order_id, payload.line_item_id);
+        let result = self
+            .wow_client
+            .provision_esim(&payload.package_code, payload.quantity, &reference)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        self.store
+            .record_provision(&payload, &result)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        info!(
+            "Provisioned eSIM for order {} item {} (iccid {:?})",
+            payload.order_id, payload.line_item_id, result.iccid
+        );
+
+        self.enqueue_follow_ups(&payload).await;
+        Ok(())
+    }
+
+    async fn on_permanent_failure(&self, job: &Job, error: &str) {
+        let Ok(payload) = Self::payload(job) else {
+            return;
+        };
+
+        // Operator notification: surfaced in logs and the compensation table
+        error!(
+            "eSIM provisioning gave up for order {} item {} (sku {}): {}",
+            payload.order_id, payload.line_item_id, payload.seller_sku, error
+        );
+        // Sandbox mode records what would have happened without acting on it
+        let dry_run = self.config.is_sandbox();
+        let status = if dry_run { "dry_run" } else { "open" };
+        self.record(&payload, "notify_operator", status, error)
+            .await;
+
+        if !self.config.esim_auto_cancel {
+            return;
+        }
+
+        if dry_run {
+            self.record(&payload, "cancel_item", "dry_run", "sandbox mode, not cancelled")
+                .await;
+            return;
+        }
+
+        match self.cancel_item(&payload).await {
+            Ok(detail) => {
+                info!(
+                    "Requested cancellation of order {} item {}",
+                    payload.order_id, payload.line_item_id
+                );
+                self.record(&payload, "cancel_item", "done", &detail).await;
+            }
+            Err(e) => {
+                error!(
+                    "Failed to cancel order {} item {}: {}",
+                    payload.order_id, payload.line_item_id, e
+                );
+                self.record(&payload, "cancel_item", "failed", &e).await;
+            }
+        }
+    }
+}
+
+/// Marks an order's packages shipped in TikTok once every item is a
+/// provisioned auto-complete eSIM. There is nothing to hand to a carrier, so
+/// the packages are self-shipped under `ESIM_SHIPPING_PROVIDER_ID` with a
+/// placeholder tracking number.
+pub struct EsimAutoCompleteHandler {
+    db: Arc<Database>,
+    store: EsimStore,
+    mappings: SkuMappingStore,
+    shops: ShopRegistry,
+    config: Config,
+}
+
+impl EsimAutoCompleteHandler {
+    pub fn new(
+        db: Arc<Database>,
+        store: EsimStore,
+        mappings: SkuMappingStore,
+        shops: ShopRegistry,
+        config: Config,
+    ) -> Self {
+        Self {
+            db,
+            store,
+            mappings,
+            shops,
+            config,
+        }
+    }
+
+    async fn ship(&self, order: &Order, provider_id: &str) -> Result<String, String> {
+        let token = self
+            .config
+            .token_manager()
+            .valid_token()
+            .await
+            .map_err(|e| e.to_string())?;
+        let client = self
+            .shops
+            .target(&self.config)
+            .await
+            .map_err(|e| e.to_string())?
+            .fulfillment_client(&self.config);
+
+        let tracking_number = format!("ESIM-{}", order.id);
+        let request = ShipPackageRequest::self_shipment(&tracking_number, provider_id);
+        for package in &order.packages {
+            client
+                .ship_package(&token.access_token, None, &package.id, &request)
+                .await
+                .map_err(|e| format!("package {}: {}", package.id, e))?;
+        }
+        Ok(format!(
+            "{} packages shipped as {}",
+            order.packages.len(),
+            tracking_number
+        ))
+    }
+}
+
+#[async_trait]
+impl JobHandler for EsimAutoCompleteHandler {
+    async fn handle(&self, job: &Job) -> Result<(), String> {
+        let payload: AutoCompleteJobPayload = serde_json::from_value(job.payload.clone())
+            .map_err(|e| format!("Invalid auto-complete payload: {}", e))?;
+
+        if self
+            .store
+            .is_auto_completed(&payload.order_id)
+            .await
+            .map_err(|e| e.to_string())?
+        {
+            return Ok(());
+        }
+        let order = self
+            .db
+            .get_order_by_id(&payload.order_id)
+            .await
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| format!("order {} is not stored", payload.order_id))?;
+        if order.status != PROVISIONABLE_STATUS {
+            return Ok(());
+        }
+
+        let provisioned: HashSet<String> = self
+            .store
+            .provisioned_line_items(&order.id)
+            .await
+            .map_err(|e| e.to_string())?
+            .into_iter()
+            .collect();
+        let mappings = self.mappings.table().await.map_err(|e| e.to_string())?;
+        // Items still provisioning queue their own job; mixed orders ship by hand
+        if !auto_completable(&order, &mappings, &provisioned) {
+            return Ok(());
+        }
+        if order.packages.is_empty() {
+            return Err(format!("order {} has no package yet", order.id));
+        }
+
+        let (status, detail) = if self.config.is_sandbox() {
+            ("dry_run", "sandbox mode, not marked shipped".to_string())
+        } else {
+            let provider_id = self
+                .config
+                .esim_shipping_provider_id
+                .as_deref()
+                .ok_or_else(|| "ESIM_SHIPPING_PROVIDER_ID is not set".to_string())?;
+            ("done", self.ship(&order, provider_id).await?)
+        };
+        self.store
+            .record_auto_completion(&order.id, status, &detail)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        info!("Auto-completed eSIM order {}: {}", order.id, detail);
+        Ok(())
+    }
+}
+
+/// Outcome of one usage poll
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UsagePoll {
+    pub polled: usize,
+    pub failed: usize,
+}
+
+/// Snapshots the data balance of provisioned eSIMs from WowEsim
+#[derive(Clone)]
+pub struct EsimUsagePoller {
+    wow_client: WowEsimApiClient,
+    store: EsimStore,
+}
+
+impl EsimUsagePoller {
+    pub fn new(wow_client: WowEsimApiClient, store: EsimStore) -> Self {
+        Self { wow_client, store }
+    }
+
+    /// Poll every eSIM still in use. An eSIM WowEsim fails to answer for is
+    /// counted and skipped until the next poll.
+    pub async fn poll(&self) -> Result<UsagePoll, sqlx::Error> {
+        let mut poll = UsagePoll::default();
+
+        for esim in self.store.esims_to_poll().await? {
+            match self.wow_client.esim_usage(&esim.iccid).await {
+                Ok(usage) => {
+                    self.store.record_usage(&esim.iccid, &usage).await?;
+                    poll.polled += 1;
+                }
+                Err(e) => {
+                    warn!(
+                        "Failed to poll usage of eSIM {} (order {}): {}",
+                        esim.iccid, esim.order_id, e
+                    );
+                    poll.failed += 1;
+                }
+            }
+        }
+        Ok(poll)
+    }
+}
@@ -0,0 +1,607 @@
+use crate::communications::{CommunicationStore, Direction};
+use crate::database::Database;
+use crate::esim::{EsimActivation, EsimStore};
+use crate::jobs::{Job, JobHandler, JobQueue};
+use crate::redact;
+use crate::transport::{HttpRequest, HttpTransport};
+use async_trait::async_trait;
+use lettre::message::Mailbox;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::{SqlitePool, SqliteRow};
+use sqlx::Row;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{error, info, warn};
+use utoipa::ToSchema;
+
+/// Send a provisioned eSIM's activation details to the buyer
+pub const DELIVERY_JOB_KIND: &str = "esim_delivery";
+
+/// How long after a resend `/orders/{id}/esim/resend` refuses another for
+/// the same order, so the buyer isn't flooded with copies
+pub const RESEND_COOLDOWN: Duration = Duration::from_secs(300);
+
+pub const EMAIL_CHANNEL: &str = "email";
+pub const SMS_CHANNEL: &str = "sms";
+
+/// SMTP server activation emails are handed to
+#[derive(Clone)]
+pub struct SmtpSettings {
+    /// `smtp://` or `smtps://` URL, with credentials if the server wants them
+    pub url: String,
+    /// Sender address, e.g. `Shop <esim@example.com>`
+    pub from: String,
+}
+
+impl std::fmt::Debug for SmtpSettings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SmtpSettings")
+            .field("url", &redact::mask(&self.url))
+            .field("from", &self.from)
+            .finish()
+    }
+}
+
+impl SmtpSettings {
+    /// Check the URL is one lettre can connect with and the sender parses
+    pub fn validate(&self) -> Result<(), String> {
+        AsyncSmtpTransport::<Tokio1Executor>::from_url(&self.url)
+            .map_err(|e| format!("invalid SMTP URL: {}", e))?;
+        self.from
+            .parse::<Mailbox>()
+            .map_err(|e| format!("invalid sender {:?}: {}", self.from, e))?;
+        Ok(())
+    }
+}
+
+/// HTTP endpoint activation texts are POSTed to as `{"to": ..., "text": ...}`
+#[derive(Clone)]
+pub struct SmsWebhookSettings {
+    pub url: String,
+    /// Sent as `Authorization: Bearer <key>`
+    pub api_key: Option<String>,
+}
+
+impl std::fmt::Debug for SmsWebhookSettings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SmsWebhookSettings")
+            .field("url", &self.url)
+            .field("api_key", &self.api_key.as_deref().map(redact::mask))
+            .finish()
+    }
+}
+
+/// Sends a text message to a phone number
+#[async_trait]
+pub trait SmsProvider: Send + Sync {
+    async fn send(&self, phone: &str, text: &str) -> Result<(), String>;
+}
+
+/// SMS gateway reached through a JSON webhook
+pub struct WebhookSmsProvider {
+    settings: SmsWebhookSettings,
+    transport: Arc<dyn HttpTransport>,
+}
+
+impl WebhookSmsProvider {
+    pub fn new(settings: SmsWebhookSettings, transport: Arc<dyn HttpTransport>) -> Self {
+        Self {
+            settings,
+            transport,
+        }
+    }
+}
+
+#[async_trait]
+impl SmsProvider for WebhookSmsProvider {
+    async fn send(&self, phone: &str, text: &str) -> Result<(), String> {
+        let body = serde_json::json!({ "to": phone, "text": text }).to_string();
+        let mut request = HttpRequest::post(&self.settings.url)
+            .with_header("Content-Type", "application/json")
+            .with_body(body);
+        if let Some(key) = &self.settings.api_key {
+            request = request.with_header("Authorization", &format!("Bearer {}", key));
+        }
+
+        let response = self
+            .transport
+            .send(request)
+            .await
+            .map_err(|e| format!("SMS gateway: {}", e))?;
+        if !response.status.is_success() {
+            return Err(format!("SMS gateway answered HTTP {}", response.status));
+        }
+        Ok(())
+    }
+}
+
+/// Hands activation emails to the configured SMTP server
+#[derive(Clone)]
+pub struct SmtpMailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl SmtpMailer {
+    pub fn new(settings: &SmtpSettings) -> Result<Self, String> {
+        let transport = AsyncSmtpTransport::<Tokio1Executor>::from_url(&settings.url)
+            .map_err(|e| format!("invalid SMTP URL: {}", e))?
+            .build();
+        let from = settings
+            .from
+            .parse()
+            .map_err(|e| format!("invalid sender {:?}: {}", settings.from, e))?;
+        Ok(Self { transport, from })
+    }
+
+    pub async fn send(&self, to: &str, subject: &str, body: String) -> Result<(), String> {
+        let to: Mailbox = to
+            .parse()
+            .map_err(|e| format!("invalid recipient address: {}", e))?;
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(to)
+            .subject(subject)
+            .body(body)
+            .map_err(|e| e.to_string())?;
+
+        self.transport
+            .send(message)
+            .await
+            .map_err(|e| format!("SMTP: {}", e))?;
+        Ok(())
+    }
+}
+
+/// Payload of an `esim_delivery` job
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeliveryJobPayload {
+    pub order_id: String,
+    pub line_item_id: String,
+}
+
+/// Enqueue delivery of a line item's eSIM. The first delivery is enqueued once
+/// per item; a `resend` is always enqueued.
+pub async fn enqueue_delivery(
+    queue: &JobQueue,
+    order_id: &str,
+    line_item_id: &str,
+    resend: bool,
+    max_attempts: u32,
+) -> Result<bool, sqlx::Error> {
+    let payload = DeliveryJobPayload {
+        order_id: order_id.to_string(),
+        line_item_id: line_item_id.to_string(),
+    };
+    let dedup_key = format!("{}:{}:{}", DELIVERY_JOB_KIND, order_id, line_item_id);
+    let value = serde_json::to_value(&payload).unwrap_or_default();
+
+    queue
+        .enqueue(
+            DELIVERY_JOB_KIND,
+            (!resend).then_some(dedup_key.as_str()),
+            &value,
+            max_attempts,
+        )
+        .await
+}
+
+/// Delivery of one eSIM over one channel
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct EsimDelivery {
+    pub line_item_id: String,
+    /// `email` or `sms`
+    pub channel: String,
+    /// Masked address or phone number the details went to
+    pub recipient: Option<String>,
+    /// `pending`, `sent`, `failed`, `skipped` (no address on the order) or
+    /// `dry_run` (sandbox mode)
+    pub status: String,
+    pub attempts: i64,
+    pub error: Option<String>,
+    pub sent_at: Option<i64>,
+    pub updated_at: i64,
+}
+
+/// Per-channel delivery status of each provisioned eSIM
+#[derive(Clone)]
+pub struct EsimDeliveryStore {
+    pool: SqlitePool,
+}
+
+impl EsimDeliveryStore {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Initialize the deliveries table
+    pub async fn init(&self) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS esim_deliveries (
+                line_item_id TEXT NOT NULL,
+                channel TEXT NOT NULL,
+                order_id TEXT NOT NULL,
+                recipient TEXT,
+                status TEXT NOT NULL,
+                attempts INTEGER NOT NULL DEFAULT 0,
+                error TEXT,
+                sent_at INTEGER,
+                updated_at INTEGER NOT NULL,
+                PRIMARY KEY (line_item_id, channel)
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            "CREATE INDEX IF NOT EXISTS idx_esim_deliveries_order ON esim_deliveries (order_id)",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS esim_resends (
+                order_id TEXT PRIMARY KEY,
+                resent_at INTEGER NOT NULL
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Whether `channel` already delivered the eSIM of `line_item_id`
+    pub async fn is_sent(&self, line_item_id: &str, channel: &str) -> Result<bool, sqlx::Error> {
+        let row = sqlx::query(
+            "SELECT 1 FROM esim_deliveries
+            WHERE line_item_id = ?1 AND channel = ?2 AND status = 'sent'",
+        )
+        .bind(line_item_id)
+        .bind(channel)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.is_some())
+    }
+
+    /// Record an attempt to deliver over `channel`
+    pub async fn record(
+        &self,
+        activation: &EsimActivation,
+        channel: &str,
+        recipient: Option<&str>,
+        status: &str,
+        error: Option<&str>,
+    ) -> Result<(), sqlx::Error> {
+        let now = chrono::Utc::now().timestamp();
+        let sent_at = (status == "sent").then_some(now);
+
+        sqlx::query(
+            "INSERT INTO esim_deliveries (
+                line_item_id, channel, order_id, recipient, status, attempts, error, sent_at,
+                updated_at
+            ) VALUES (?1, ?2, ?3, ?4, ?5, 1, ?6, ?7, ?8)
+            ON CONFLICT(line_item_id, channel) DO UPDATE SET
+                recipient = excluded.recipient,
+                status = excluded.status,
+                attempts = esim_deliveries.attempts + 1,
+                error = excluded.error,
+                sent_at = COALESCE(excluded.sent_at, esim_deliveries.sent_at),
+                updated_at = excluded.updated_at",
+        )
+        .bind(&activation.line_item_id)
+        .bind(channel)
+        .bind(&activation.order_id)
+        .bind(recipient.map(redact::mask))
+        .bind(status)
+        .bind(error)
+        .bind(sent_at)
+        .bind(now)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Record a resend of `order_id`, unless the last one was less than
+    /// `cooldown` ago; returns whether it was recorded. Of concurrent
+    /// resends of one order, only one is.
+    pub async fn claim_resend(
+        &self,
+        order_id: &str,
+        cooldown: Duration,
+    ) -> Result<bool, sqlx::Error> {
+        let now = chrono::Utc::now().timestamp();
+        let result = sqlx::query(
+            "INSERT INTO esim_resends (order_id, resent_at) VALUES (?1, ?2)
+            ON CONFLICT(order_id) DO UPDATE SET resent_at = excluded.resent_at
+            WHERE esim_resends.resent_at <= ?3",
+        )
+        .bind(order_id)
+        .bind(now)
+        .bind(now - cooldown.as_secs() as i64)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    /// Mark every delivery of `order_id` pending so the next job sends it again
+    pub async fn reset(&self, order_id: &str) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "UPDATE esim_deliveries SET status = 'pending', error = NULL, updated_at = ?2
+            WHERE order_id = ?1",
+        )
+        .bind(order_id)
+        .bind(chrono::Utc::now().timestamp())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Deliveries of the eSIMs of `order_id`
+    pub async fn for_order(&self, order_id: &str) -> Result<Vec<EsimDelivery>, sqlx::Error> {
+        let rows = sqlx::query(
+            "SELECT line_item_id, channel, recipient, status, attempts, error, sent_at, updated_at
+            FROM esim_deliveries WHERE order_id = ?1 ORDER BY line_item_id, channel",
+        )
+        .bind(order_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter().map(delivery_from_row).collect()
+    }
+}
+
+fn delivery_from_row(row: &SqliteRow) -> Result<EsimDelivery, sqlx::Error> {
+    Ok(EsimDelivery {
+        line_item_id: row.try_get("line_item_id")?,
+        channel: row.try_get("channel")?,
+        recipient: row.try_get("recipient")?,
+        status: row.try_get("status")?,
+        attempts: row.try_get("attempts")?,
+        error: row.try_get("error")?,
+        sent_at: row.try_get("sent_at")?,
+        updated_at: row.try_get("updated_at")?,
+    })
+}
+
+fn email_subject(activation: &EsimActivation) -> String {
+    format!("Your eSIM for order {}", activation.order_id)
+}
+
+fn email_body(activation: &EsimActivation) -> String {
+    let mut body = format!("Your eSIM for order {} is ready.\n\n", activation.order_id);
+    if let Some(iccid) = &activation.iccid {
+        body.push_str(&format!("ICCID: {}\n", iccid));
+    }
+    if let Some(qr_code) = &activation.qr_code {
+        body.push_str(&format!("QR code: {}\n", qr_code));
+    }
+    if let Some(code) = &activation.activation_code {
+        body.push_str(&format!("Activation code: {}\n", code));
+    }
+    body.push_str(
+        "\nTo install it, scan the QR code or enter the activation code under \
+        Settings > Mobile data > Add eSIM on your phone.\n",
+    );
+    body
+}
+
+fn sms_text(activation: &EsimActivation) -> String {
+    let details = activation
+        .qr_code
+        .as_deref()
+        .or(activation.activation_code.as_deref())
+        .unwrap_or_default();
+    format!("Your eSIM for order {}: {}", activation.order_id, details)
+}
+
+/// Sends a provisioned eSIM's activation details to the buyer over each
+/// configured channel. A channel that failed is retried with the job queue's
+/// backoff; one that already sent is not sent again.
+pub struct EsimDeliveryHandler {
+    db: Arc<Database>,
+    esims: EsimStore,
+    store: EsimDeliveryStore,
+    communications: CommunicationStore,
+    mailer: Option<SmtpMailer>,
+    sms: Option<Arc<dyn SmsProvider>>,
+    /// Sandbox mode records what would have been sent without sending it
+    dry_run: bool,
+}
+
+impl EsimDeliveryHandler {
+    pub fn new(
+        db: Arc<Database>,
+        esims: EsimStore,
+        store: EsimDeliveryStore,
+        communications: CommunicationStore,
+        dry_run: bool,
+    ) -> Self {
+        Self {
+            db,
+            esims,
+            store,
+            communications,
+            mailer: None,
+            sms: None,
+            dry_run,
+        }
+    }
+
+    pub fn with_mailer(mut self, mailer: SmtpMailer) -> Self {
+        self.mailer = Some(mailer);
+        self
+    }
+
+    pub fn with_sms(mut self, provider: Arc<dyn SmsProvider>) -> Self {
+        self.sms = Some(provider);
+        self
+    }
+
+    /// Deliver over one channel and record the outcome. Only a failed send is
+    /// an error.
+    async fn deliver(
+        &self,
+        activation: &EsimActivation,
+        channel: &str,
+        recipient: Option<&str>,
+    ) -> Result<(), String> {
+        let recipient = recipient.map(str::trim).filter(|r| !r.is_empty());
+        let outcome = match recipient {
+            None => Ok("skipped"),
+            Some(_) if self.dry_run => Ok("dry_run"),
+            Some(to) => match channel {
+                EMAIL_CHANNEL => match &self.mailer {
+                    Some(mailer) => mailer
+                        .send(to, &email_subject(activation), email_body(activation))
+                        .await
+                        .map(|()| "sent"),
+                    None => return Ok(()),
+                },
+                _ => match &self.sms {
+                    Some(sms) => sms.send(to, &sms_text(activation)).await.map(|()| "sent"),
+                    None => return Ok(()),
+                },
+            },
+        };
+
+        let (status, error) = match &outcome {
+            Ok(status) => (*status, None),
+            Err(e) => ("failed", Some(e.as_str())),
+        };
+        self.store
+            .record(activation, channel, recipient, status, error)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        if status == "sent" {
+            info!(
+                "Sent eSIM details for order {} item {} by {}",
+                activation.order_id, activation.line_item_id, channel
+            );
+            if let Err(e) = self
+                .communications
+                .record(
+                    &activation.order_id,
+                    channel,
+                    Direction::Outbound,
+                    None,
+                    &format!(
+                        "eSIM activation details for item {}",
+                        activation.line_item_id
+                    ),
+                    chrono::Utc::now().timestamp(),
+                )
+                .await
+            {
+                error!(
+                    "Failed to record eSIM {} for order {}: {}",
+                    channel, activation.order_id, e
+                );
+            }
+        }
+        outcome.map(|_| ())
+    }
+}
+
+#[async_trait]
+impl JobHandler for EsimDeliveryHandler {
+    async fn handle(&self, job: &Job) -> Result<(), String> {
+        let payload: DeliveryJobPayload = serde_json::from_value(job.payload.clone())
+            .map_err(|e| format!("Invalid eSIM delivery payload: {}", e))?;
+
+        let Some(activation) = self
+            .esims
+            .activation(&payload.line_item_id)
+            .await
+            .map_err(|e| e.to_string())?
+        else {
+            warn!(
+                "Dropping eSIM delivery for order {} item {}: not provisioned",
+                payload.order_id, payload.line_item_id
+            );
+            return Ok(());
+        };
+        let order = self
+            .db
+            .get_order_by_id(&payload.order_id)
+            .await
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| format!("Order {} not found", payload.order_id))?;
+        let order = self.db.reveal_pii(order);
+
+        let phone = order
+            .recipient_address
+            .as_ref()
+            .and_then(|a| a.phone.clone());
+        let channels = [
+            (
+                EMAIL_CHANNEL,
+                self.mailer.is_some(),
+                order.buyer_email.clone(),
+            ),
+            (SMS_CHANNEL, self.sms.is_some(), phone),
+        ];
+
+        let mut errors = Vec::new();
+        for (channel, enabled, recipient) in channels {
+            if !enabled
+                || self
+                    .store
+                    .is_sent(&activation.line_item_id, channel)
+                    .await
+                    .map_err(|e| e.to_string())?
+            {
+                continue;
+            }
+            if let Err(e) = self
+                .deliver(&activation, channel, recipient.as_deref())
+                .await
+            {
+                errors.push(format!("{}: {}", channel, e));
+            }
+        }
+
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(errors.join("; ")),
+        }
+    }
+
+    async fn on_permanent_failure(&self, job: &Job, error: &str) {
+        let Ok(payload) = serde_json::from_value::<DeliveryJobPayload>(job.payload.clone()) else {
+            return;
+        };
+        error!(
+            "eSIM delivery gave up for order {} item {}: {}",
+            payload.order_id, payload.line_item_id, error
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn resends_of_an_order_wait_for_the_cooldown() {
+        // One connection, as each would open its own in-memory database
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        let store = EsimDeliveryStore::new(pool);
+        store.init().await.unwrap();
+
+        assert!(store.claim_resend("o1", RESEND_COOLDOWN).await.unwrap());
+        assert!(!store.claim_resend("o1", RESEND_COOLDOWN).await.unwrap());
+        assert!(store.claim_resend("o2", RESEND_COOLDOWN).await.unwrap());
+        assert!(store.claim_resend("o1", Duration::ZERO).await.unwrap());
+    }
+}
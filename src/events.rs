@@ -0,0 +1,113 @@
+use crate::order::Order;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use utoipa::ToSchema;
+
+/// What happened to an order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderEventKind {
+    Created,
+    Updated,
+    /// A fulfillment deadline is close; see `GET /orders/sla`
+    SlaAtRisk,
+    /// A fulfillment deadline passed before the order shipped
+    SlaBreached,
+    /// A new order scored above `RISK_ALERT_THRESHOLD`
+    HighRisk,
+    /// A buyer asked to cancel the order; see `GET /cancellations`
+    CancellationRequested,
+    /// The seller de-authorized the app; `shop_id` says which shop and
+    /// `order_id` is empty
+    ShopDisconnected,
+    /// Daily summary of a shop's late orders; `shop_id` says which shop,
+    /// `order_ids` lists the orders and `order_id` is empty. See `GET /orders/late`
+    LateDigest,
+}
+
+impl OrderEventKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OrderEventKind::Created => "created",
+            OrderEventKind::Updated => "updated",
+            OrderEventKind::SlaAtRisk => "sla_at_risk",
+            OrderEventKind::SlaBreached => "sla_breached",
+            OrderEventKind::HighRisk => "high_risk",
+            OrderEventKind::CancellationRequested => "cancellation_requested",
+            OrderEventKind::ShopDisconnected => "shop_disconnected",
+            OrderEventKind::LateDigest => "late_digest",
+        }
+    }
+}
+
+/// An order change published after it has been committed to the database,
+/// or an SLA, risk or cancellation alert about an order
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct OrderEvent {
+    pub kind: OrderEventKind,
+    pub order_id: String,
+    pub status: String,
+    pub update_time: i64,
+    /// Set on shop-level events
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shop_id: Option<String>,
+    /// Orders a shop-level event is about
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub order_ids: Vec<String>,
+}
+
+impl OrderEvent {
+    pub fn new(kind: OrderEventKind, order: &Order) -> Self {
+        Self {
+            kind,
+            order_id: order.id.clone(),
+            status: order.status.clone(),
+            update_time: order.update_time,
+            shop_id: None,
+            order_ids: Vec::new(),
+        }
+    }
+
+    /// A shop-level event, about no order in particular
+    pub fn shop(kind: OrderEventKind, shop_id: &str, status: &str) -> Self {
+        Self {
+            kind,
+            order_id: String::new(),
+            status: status.to_string(),
+            update_time: chrono::Utc::now().timestamp(),
+            shop_id: Some(shop_id.to_string()),
+            order_ids: Vec::new(),
+        }
+    }
+}
+
+/// In-process fan-out of order events to any number of subscribers.
+///
+/// Subscribers that fall more than the channel capacity behind miss events
+/// rather than slowing down publishers.
+#[derive(Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<OrderEvent>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new(1024)
+    }
+}
+
+impl EventBus {
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity.max(1));
+        Self { sender }
+    }
+
+    /// Publish an event; a no-op when nobody is subscribed
+    pub fn publish(&self, event: OrderEvent) {
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<OrderEvent> {
+        self.sender.subscribe()
+    }
+}
@@ -0,0 +1,278 @@
+use crate::config::Config;
+use crate::jobs::{Job, JobHandler, JobQueue};
+use crate::order::{
+    AddExternalOrderReferencesRequest, ExternalOrderReference, ExternalOrderReferences,
+};
+use crate::shops::ShopRegistry;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::{SqlitePool, SqliteRow};
+use sqlx::Row;
+use tracing::info;
+use utoipa::ToSchema;
+
+/// Send an order's external reference to TikTok
+pub const PUSH_JOB_KIND: &str = "external_ref_push";
+
+/// Longest reference accepted, in characters
+pub const MAX_REF_CHARS: usize = 100;
+
+/// Platform sent to TikTok when none is given
+pub const DEFAULT_PLATFORM: &str = "OTHER";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PushJobPayload {
+    pub order_id: String,
+}
+
+/// An order's number in another system, e.g. an ERP invoice number
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ExternalRef {
+    pub order_id: String,
+    pub external_ref: String,
+    /// System the reference belongs to, as reported to TikTok
+    pub platform: String,
+    pub created_at: i64,
+    pub updated_at: i64,
+    /// When TikTok last accepted the reference
+    pub pushed_at: Option<i64>,
+    pub push_error: Option<String>,
+}
+
+/// Order -> external reference mapping, in the `order_external_refs` table.
+/// Each order has at most one reference and a reference names one order.
+#[derive(Clone)]
+pub struct ExternalRefStore {
+    pool: SqlitePool,
+}
+
+impl ExternalRefStore {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Initialize the order_external_refs table
+    pub async fn init(&self) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS order_external_refs (
+                order_id TEXT PRIMARY KEY,
+                external_ref TEXT NOT NULL UNIQUE,
+                platform TEXT NOT NULL,
+                created_at INTEGER NOT NULL,
+                updated_at INTEGER NOT NULL,
+                pushed_at INTEGER,
+                push_error TEXT
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Attach `external_ref` to an order, replacing any previous reference.
+    /// Changing it clears the push status.
+    pub async fn set(
+        &self,
+        order_id: &str,
+        external_ref: &str,
+        platform: &str,
+    ) -> Result<ExternalRef, sqlx::Error> {
+        let now = chrono::Utc::now().timestamp();
+        let row = sqlx::query(
+            "INSERT INTO order_external_refs (order_id, external_ref, platform, created_at, updated_at)
+            VALUES (?1, ?2, ?3, ?4, ?4)
+            ON CONFLICT(order_id) DO UPDATE SET
+                external_ref = excluded.external_ref,
+                platform = excluded.platform,
+                updated_at = excluded.updated_at,
+                pushed_at = NULL,
+                push_error = NULL
+            RETURNING order_id, external_ref, platform, created_at, updated_at, pushed_at,
+                push_error",
+        )
+        .bind(order_id)
+        .bind(external_ref)
+        .bind(platform)
+        .bind(now)
+        .fetch_one(&self.pool)
+        .await?;
+        ref_from_row(&row)
+    }
+
+    pub async fn get(&self, order_id: &str) -> Result<Option<ExternalRef>, sqlx::Error> {
+        sqlx::query(
+            "SELECT order_id, external_ref, platform, created_at, updated_at, pushed_at, push_error
+            FROM order_external_refs WHERE order_id = ?1",
+        )
+        .bind(order_id)
+        .fetch_optional(&self.pool)
+        .await?
+        .as_ref()
+        .map(ref_from_row)
+        .transpose()
+    }
+
+    /// The mapping holding `external_ref`, whichever order it is on
+    pub async fn find_by_ref(
+        &self,
+        external_ref: &str,
+    ) -> Result<Option<ExternalRef>, sqlx::Error> {
+        sqlx::query(
+            "SELECT order_id, external_ref, platform, created_at, updated_at, pushed_at, push_error
+            FROM order_external_refs WHERE external_ref = ?1",
+        )
+        .bind(external_ref)
+        .fetch_optional(&self.pool)
+        .await?
+        .as_ref()
+        .map(ref_from_row)
+        .transpose()
+    }
+
+    /// Detach an order's reference; returns whether it had one
+    pub async fn remove(&self, order_id: &str) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query("DELETE FROM order_external_refs WHERE order_id = ?1")
+            .bind(order_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Record the outcome of pushing `external_ref`, unless it changed meanwhile
+    pub async fn record_push(
+        &self,
+        order_id: &str,
+        external_ref: &str,
+        error: Option<&str>,
+    ) -> Result<(), sqlx::Error> {
+        let pushed_at = error.is_none().then(|| chrono::Utc::now().timestamp());
+        sqlx::query(
+            "UPDATE order_external_refs SET pushed_at = COALESCE(?3, pushed_at), push_error = ?4
+            WHERE order_id = ?1 AND external_ref = ?2",
+        )
+        .bind(order_id)
+        .bind(external_ref)
+        .bind(pushed_at)
+        .bind(error)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+}
+
+fn ref_from_row(row: &SqliteRow) -> Result<ExternalRef, sqlx::Error> {
+    Ok(ExternalRef {
+        order_id: row.try_get("order_id")?,
+        external_ref: row.try_get("external_ref")?,
+        platform: row.try_get("platform")?,
+        created_at: row.try_get("created_at")?,
+        updated_at: row.try_get("updated_at")?,
+        pushed_at: row.try_get("pushed_at")?,
+        push_error: row.try_get("push_error")?,
+    })
+}
+
+/// Queue sending an order's current reference to TikTok
+pub async fn enqueue_push(
+    job_queue: &JobQueue,
+    external_ref: &ExternalRef,
+    max_attempts: u32,
+) -> Result<bool, sqlx::Error> {
+    let payload = serde_json::to_value(PushJobPayload {
+        order_id: external_ref.order_id.clone(),
+    })
+    .unwrap_or_default();
+    job_queue
+        .enqueue(
+            PUSH_JOB_KIND,
+            Some(&format!(
+                "{}:{}:{}",
+                PUSH_JOB_KIND, external_ref.order_id, external_ref.external_ref
+            )),
+            &payload,
+            max_attempts,
+        )
+        .await
+}
+
+/// Adds stored references to orders through TikTok's external order API
+pub struct ExternalRefPushHandler {
+    store: ExternalRefStore,
+    shops: ShopRegistry,
+    config: Config,
+}
+
+impl ExternalRefPushHandler {
+    pub fn new(store: ExternalRefStore, shops: ShopRegistry, config: Config) -> Self {
+        Self {
+            store,
+            shops,
+            config,
+        }
+    }
+
+    async fn push(&self, external_ref: &ExternalRef) -> Result<(), String> {
+        let token = self
+            .config
+            .token_manager()
+            .valid_token()
+            .await
+            .map_err(|e| e.to_string())?;
+        let order_client = self
+            .shops
+            .order_client(&self.config)
+            .await
+            .map_err(|e| e.to_string())?;
+        let request = AddExternalOrderReferencesRequest {
+            orders: vec![ExternalOrderReferences {
+                id: external_ref.order_id.clone(),
+                external_orders: vec![ExternalOrderReference {
+                    id: external_ref.external_ref.clone(),
+                    platform: external_ref.platform.clone(),
+                }],
+            }],
+        };
+        let response = order_client
+            .add_external_order_references(&token.access_token, None, &request)
+            .await
+            .map_err(|e| e.to_string())?;
+        match response.errors.first() {
+            Some(error) => Err(format!("TikTok rejected the reference: {}", error.message)),
+            None => Ok(()),
+        }
+    }
+}
+
+#[async_trait]
+impl JobHandler for ExternalRefPushHandler {
+    async fn handle(&self, job: &Job) -> Result<(), String> {
+        let payload: PushJobPayload = serde_json::from_value(job.payload.clone())
+            .map_err(|e| format!("Invalid external ref payload: {}", e))?;
+        // Removed since it was queued
+        let Some(external_ref) = self
+            .store
+            .get(&payload.order_id)
+            .await
+            .map_err(|e| e.to_string())?
+        else {
+            return Ok(());
+        };
+
+        let result = self.push(&external_ref).await;
+        self.store
+            .record_push(
+                &external_ref.order_id,
+                &external_ref.external_ref,
+                result.as_ref().err().map(String::as_str),
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+        result?;
+
+        info!(
+            "Added external reference {} to order {} on TikTok",
+            external_ref.external_ref, external_ref.order_id
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,173 @@
+use crate::error::AppError;
+use crate::requests::TikTokShopApiClient;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use utoipa::ToSchema;
+
+/// Client for the Finance API (order settlements and payout statements)
+pub struct FinanceClient {
+    api_client: TikTokShopApiClient,
+    shop_cipher: Option<String>,
+}
+
+/// What TikTok settled for one order: its revenue, the fees and taxes
+/// withheld and the amount paid out to the seller
+#[derive(Debug, Clone, Default, Deserialize, Serialize, ToSchema)]
+#[serde(default)]
+pub struct OrderStatement {
+    pub order_id: String,
+    pub currency: String,
+    pub revenue_amount: Option<String>,
+    /// Platform commission, transaction fees and withheld taxes
+    pub fee_and_tax_amount: Option<String>,
+    pub shipping_cost_amount: Option<String>,
+    pub settlement_amount: Option<String>,
+    pub statement_transactions: Vec<StatementTransaction>,
+}
+
+/// One settlement entry of an order; an order may settle in several
+#[derive(Debug, Clone, Default, Deserialize, Serialize, ToSchema)]
+#[serde(default)]
+pub struct StatementTransaction {
+    pub id: String,
+    /// `SETTLED` once paid out, `PROCESSING` before
+    pub status: Option<String>,
+    /// Statement (payout) the transaction was settled in
+    pub statement_id: Option<String>,
+    pub revenue_amount: Option<String>,
+    pub fee_and_tax_amount: Option<String>,
+    pub settlement_amount: Option<String>,
+}
+
+/// A payout statement: the amount TikTok paid to the seller's bank account
+/// for the transactions settled on one day
+#[derive(Debug, Clone, Default, Deserialize, Serialize, ToSchema)]
+#[serde(default)]
+pub struct Statement {
+    pub id: String,
+    pub statement_time: i64,
+    pub currency: String,
+    pub revenue_amount: Option<String>,
+    pub fee_amount: Option<String>,
+    pub adjustment_amount: Option<String>,
+    pub settlement_amount: Option<String>,
+    /// `PAID`, `FAILED` or `PROCESSING`
+    pub payment_status: Option<String>,
+    pub payment_id: Option<String>,
+}
+
+/// Statements whose `statement_time` is in `[statement_time_ge, statement_time_lt)`
+#[derive(Debug, Clone)]
+pub struct GetStatementsRequest {
+    pub statement_time_ge: i64,
+    pub statement_time_lt: i64,
+    pub page_size: u32,
+    pub page_token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GetStatementsResponse {
+    #[serde(default)]
+    pub statements: Vec<Statement>,
+    #[serde(default)]
+    pub next_page_token: Option<String>,
+}
+
+impl FinanceClient {
+    /// Largest page of statements TikTok returns
+    pub const MAX_STATEMENTS_PAGE_SIZE: u32 = 100;
+
+    pub fn new(app_key: String, app_secret: String) -> Self {
+        Self::with_api_client(TikTokShopApiClient::new(app_key, app_secret))
+    }
+
+    pub fn with_api_client(api_client: TikTokShopApiClient) -> Self {
+        Self {
+            api_client,
+            shop_cipher: None,
+        }
+    }
+
+    /// Cipher used when a call doesn't pass one explicitly
+    pub fn with_shop_cipher(mut self, shop_cipher: Option<String>) -> Self {
+        self.shop_cipher = shop_cipher;
+        self
+    }
+
+    /// Settlement of one order, with fees and the amount paid out
+    pub async fn get_order_statement(
+        &self,
+        access_token: &str,
+        shop_cipher: Option<&str>,
+        order_id: &str,
+    ) -> Result<OrderStatement, AppError> {
+        let shop_cipher = shop_cipher.or(self.shop_cipher.as_deref());
+        self.api_client
+            .get(
+                &format!("/finance/202309/orders/{}/statement_transactions", order_id),
+                Some(access_token),
+                shop_cipher,
+                BTreeMap::new(),
+            )
+            .await
+    }
+
+    /// One page of payout statements
+    pub async fn get_statements(
+        &self,
+        access_token: &str,
+        shop_cipher: Option<&str>,
+        request: &GetStatementsRequest,
+    ) -> Result<GetStatementsResponse, AppError> {
+        let shop_cipher = shop_cipher.or(self.shop_cipher.as_deref());
+        let mut params = BTreeMap::new();
+        params.insert(
+            "statement_time_ge".to_string(),
+            request.statement_time_ge.to_string(),
+        );
+        params.insert(
+            "statement_time_lt".to_string(),
+            request.statement_time_lt.to_string(),
+        );
+        params.insert("page_size".to_string(), request.page_size.to_string());
+        params.insert("sort_field".to_string(), "statement_time".to_string());
+        if let Some(token) = &request.page_token {
+            params.insert("page_token".to_string(), token.clone());
+        }
+        self.api_client
+            .get(
+                "/finance/202309/statements",
+                Some(access_token),
+                shop_cipher,
+                params,
+            )
+            .await
+    }
+
+    /// Every payout statement in `[statement_time_ge, statement_time_lt)`
+    pub async fn get_all_statements(
+        &self,
+        access_token: &str,
+        shop_cipher: Option<&str>,
+        statement_time_ge: i64,
+        statement_time_lt: i64,
+    ) -> Result<Vec<Statement>, AppError> {
+        let mut request = GetStatementsRequest {
+            statement_time_ge,
+            statement_time_lt,
+            page_size: Self::MAX_STATEMENTS_PAGE_SIZE,
+            page_token: None,
+        };
+        let mut statements = Vec::new();
+        loop {
+            let page = self
+                .get_statements(access_token, shop_cipher, &request)
+                .await?;
+            statements.extend(page.statements);
+            match page.next_page_token.filter(|t| !t.is_empty()) {
+                Some(token) => request.page_token = Some(token),
+                None => return Ok(statements),
+            }
+        }
+    }
+}
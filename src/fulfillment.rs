@@ -0,0 +1,330 @@
+use crate::error::AppError;
+use crate::health::ApiHealth;
+use crate::requests::TikTokShopApiClient;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use utoipa::ToSchema;
+
+/// Client for the Fulfillment API (shipping documents, shipping, splitting and combining packages)
+pub struct FulfillmentClient {
+    api_client: TikTokShopApiClient,
+    shop_cipher: Option<String>,
+    http: reqwest::Client,
+}
+
+/// Printable document TikTok generates for a package
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DocumentType {
+    ShippingLabel,
+    PackingSlip,
+    ShippingLabelAndPackingSlip,
+}
+
+impl DocumentType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DocumentType::ShippingLabel => "SHIPPING_LABEL",
+            DocumentType::PackingSlip => "PACKING_SLIP",
+            DocumentType::ShippingLabelAndPackingSlip => "SHIPPING_LABEL_AND_PACKING_SLIP",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ShippingDocument {
+    /// Short-lived URL of the PDF
+    pub doc_url: String,
+    #[serde(default)]
+    pub tracking_number: Option<String>,
+}
+
+/// Line items of an order that should ship in one package
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SplittableGroup {
+    /// Caller-chosen id, unique within the request
+    pub id: String,
+    pub order_line_item_ids: Vec<String>,
+}
+
+/// Split an order into several packages. Every line item must be in exactly one group.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SplitOrderRequest {
+    pub splittable_groups: Vec<SplittableGroup>,
+}
+
+impl SplitOrderRequest {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_group(mut self, id: impl Into<String>, order_line_item_ids: Vec<String>) -> Self {
+        self.splittable_groups.push(SplittableGroup {
+            id: id.into(),
+            order_line_item_ids,
+        });
+        self
+    }
+}
+
+/// A package created by splitting an order
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SplitPackage {
+    pub id: String,
+    #[serde(default)]
+    pub order_line_item_ids: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct SplitOrderResponse {
+    #[serde(default)]
+    pub split_packages: Vec<SplitPackage>,
+}
+
+/// Orders that can ship together, and the package they would share
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct CombinablePackage {
+    pub id: String,
+    #[serde(default)]
+    pub order_ids: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct SearchCombinablePackagesResponse {
+    #[serde(default)]
+    pub combinable_packages: Vec<CombinablePackage>,
+    #[serde(default)]
+    pub next_page_token: Option<String>,
+    #[serde(default)]
+    pub total_count: i64,
+}
+
+/// Combine orders into one shipment. Candidates come from
+/// [`FulfillmentClient::search_combinable_packages`].
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CombinePackagesRequest {
+    pub combinable_packages: Vec<CombinablePackage>,
+}
+
+impl CombinePackagesRequest {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ship `order_ids` in combinable package `id`; a subset of its orders is allowed
+    pub fn with_package(mut self, id: impl Into<String>, order_ids: Vec<String>) -> Self {
+        self.combinable_packages.push(CombinablePackage {
+            id: id.into(),
+            order_ids,
+        });
+        self
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CombinePackageError {
+    pub code: i64,
+    pub message: String,
+    #[serde(default)]
+    pub detail: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct CombinePackagesResponse {
+    /// Packages the orders now ship in
+    #[serde(default)]
+    pub packages: Vec<CombinablePackage>,
+    #[serde(default)]
+    pub errors: Vec<CombinePackageError>,
+}
+
+/// Tracking of a package the seller ships with their own carrier
+#[derive(Debug, Clone, Serialize)]
+pub struct SelfShipment {
+    pub tracking_number: String,
+    pub shipping_provider_id: String,
+}
+
+/// Mark a package shipped. Seller-shipped packages carry their tracking in
+/// `self_shipment`; TikTok-arranged ones choose a `handover_method`.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ShipPackageRequest {
+    /// `PICKUP` or `DROP_OFF`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub handover_method: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub self_shipment: Option<SelfShipment>,
+}
+
+impl ShipPackageRequest {
+    /// Shipped by the seller with `shipping_provider_id` under `tracking_number`
+    pub fn self_shipment(
+        tracking_number: impl Into<String>,
+        shipping_provider_id: impl Into<String>,
+    ) -> Self {
+        Self {
+            handover_method: None,
+            self_shipment: Some(SelfShipment {
+                tracking_number: tracking_number.into(),
+                shipping_provider_id: shipping_provider_id.into(),
+            }),
+        }
+    }
+}
+
+impl FulfillmentClient {
+    pub fn new(app_key: String, app_secret: String) -> Self {
+        Self::with_api_client(TikTokShopApiClient::new(app_key, app_secret))
+    }
+
+    pub fn with_api_client(api_client: TikTokShopApiClient) -> Self {
+        Self {
+            api_client,
+            shop_cipher: None,
+            http: reqwest::Client::new(),
+        }
+    }
+
+    /// Cipher used when a call doesn't pass one explicitly
+    pub fn with_shop_cipher(mut self, shop_cipher: Option<String>) -> Self {
+        self.shop_cipher = shop_cipher;
+        self
+    }
+
+    /// Record API call outcomes in a shared health tracker
+    pub fn with_health(mut self, health: Arc<ApiHealth>) -> Self {
+        self.api_client = self.api_client.with_health(health);
+        self
+    }
+
+    /// Link to a package's shipping document. The package must have been
+    /// shipped (arranged with the carrier) for TikTok to have generated it.
+    pub async fn get_shipping_document(
+        &self,
+        access_token: &str,
+        shop_cipher: Option<&str>,
+        package_id: &str,
+        document_type: DocumentType,
+    ) -> Result<ShippingDocument, AppError> {
+        let shop_cipher = shop_cipher.or(self.shop_cipher.as_deref());
+        let mut params = BTreeMap::new();
+        params.insert(
+            "document_type".to_string(),
+            document_type.as_str().to_string(),
+        );
+
+        self.api_client
+            .get(
+                &format!(
+                    "/fulfillment/202309/packages/{}/shipping_documents",
+                    package_id
+                ),
+                Some(access_token),
+                shop_cipher,
+                params,
+            )
+            .await
+    }
+
+    /// Split an order that has not shipped yet into one package per group
+    pub async fn split_order(
+        &self,
+        access_token: &str,
+        shop_cipher: Option<&str>,
+        order_id: &str,
+        request: &SplitOrderRequest,
+    ) -> Result<SplitOrderResponse, AppError> {
+        let shop_cipher = shop_cipher.or(self.shop_cipher.as_deref());
+        self.api_client
+            .post(
+                &format!("/fulfillment/202309/orders/{}/split", order_id),
+                Some(access_token),
+                shop_cipher,
+                request,
+                None,
+            )
+            .await
+    }
+
+    /// One page of packages whose orders could be shipped together
+    pub async fn search_combinable_packages(
+        &self,
+        access_token: &str,
+        shop_cipher: Option<&str>,
+        page_size: i32,
+        page_token: Option<&str>,
+    ) -> Result<SearchCombinablePackagesResponse, AppError> {
+        let shop_cipher = shop_cipher.or(self.shop_cipher.as_deref());
+        let mut params = BTreeMap::new();
+        params.insert("page_size".to_string(), page_size.clamp(1, 50).to_string());
+        if let Some(token) = page_token {
+            params.insert("page_token".to_string(), token.to_string());
+        }
+
+        self.api_client
+            .get(
+                "/fulfillment/202309/combinable_packages/search",
+                Some(access_token),
+                shop_cipher,
+                params,
+            )
+            .await
+    }
+
+    /// Combine orders into shared packages. Rejected combinations are listed
+    /// in `errors` rather than failing the call.
+    pub async fn combine_packages(
+        &self,
+        access_token: &str,
+        shop_cipher: Option<&str>,
+        request: &CombinePackagesRequest,
+    ) -> Result<CombinePackagesResponse, AppError> {
+        let shop_cipher = shop_cipher.or(self.shop_cipher.as_deref());
+        self.api_client
+            .post(
+                "/fulfillment/202309/packages/combine",
+                Some(access_token),
+                shop_cipher,
+                request,
+                None,
+            )
+            .await
+    }
+
+    /// Mark a package shipped, moving its orders to `IN_TRANSIT`
+    pub async fn ship_package(
+        &self,
+        access_token: &str,
+        shop_cipher: Option<&str>,
+        package_id: &str,
+        request: &ShipPackageRequest,
+    ) -> Result<(), AppError> {
+        let shop_cipher = shop_cipher.or(self.shop_cipher.as_deref());
+        let _: serde_json::Value = self
+            .api_client
+            .post(
+                &format!("/fulfillment/202309/packages/{}/ship", package_id),
+                Some(access_token),
+                shop_cipher,
+                request,
+                None,
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Download the PDF behind a `ShippingDocument::doc_url`
+    pub async fn download_document(
+        &self,
+        document: &ShippingDocument,
+    ) -> Result<Vec<u8>, AppError> {
+        let response = self.http.get(&document.doc_url).send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(AppError::HttpStatus { status, body });
+        }
+
+        Ok(response.bytes().await?.to_vec())
+    }
+}
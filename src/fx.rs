@@ -0,0 +1,463 @@
+use crate::database::Database;
+use crate::jobs::{Job, JobHandler};
+use crate::order::Order;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::{Sqlite, SqlitePool, SqliteRow};
+use sqlx::{Row, Transaction};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tracing::info;
+use utoipa::ToSchema;
+
+/// Job converting orders stored before FX conversion was enabled
+pub const BACKFILL_JOB_KIND: &str = "fx_backfill";
+
+/// How long a rates request may take
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Parse `CUR=rate` pairs separated by commas, each rate being the value of
+/// one unit of `CUR` in the base currency, e.g. `USD=25400,THB=700`
+pub fn parse_rates(value: &str) -> Result<BTreeMap<String, f64>, String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (currency, rate) = pair
+                .split_once('=')
+                .ok_or_else(|| format!("rate {:?} is not CURRENCY=rate", pair))?;
+            let currency = parse_currency(currency)?;
+            let rate = rate
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|r| r.is_finite() && *r > 0.0)
+                .ok_or_else(|| format!("rate {:?} is not a positive number", pair))?;
+            Ok((currency, rate))
+        })
+        .collect()
+}
+
+/// A three-letter currency code, uppercased
+pub fn parse_currency(value: &str) -> Result<String, String> {
+    let value = value.trim();
+    if value.len() != 3 || !value.chars().all(|c| c.is_ascii_alphabetic()) {
+        return Err(format!("{:?} is not a three-letter currency code", value));
+    }
+    Ok(value.to_ascii_uppercase())
+}
+
+/// Where a rate came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum RateSource {
+    /// `FX_RATES`
+    Static,
+    /// `FX_RATES_URL`
+    Api,
+}
+
+impl RateSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RateSource::Static => "static",
+            RateSource::Api => "api",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "static" => Some(RateSource::Static),
+            "api" => Some(RateSource::Api),
+            _ => None,
+        }
+    }
+}
+
+/// Value of one unit of `currency` in the base currency
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct FxRate {
+    pub currency: String,
+    pub rate: f64,
+    pub source: RateSource,
+    pub updated_at: i64,
+}
+
+/// An order's total as paid and in the base currency
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct OrderFx {
+    pub currency: String,
+    pub amount: f64,
+    pub base_currency: String,
+    /// `None` until a rate for `currency` is known
+    pub base_amount: Option<f64>,
+    /// Rate fixed when the order was first converted
+    pub rate: Option<f64>,
+    pub converted_at: Option<i64>,
+}
+
+/// Current rates into the base currency, shared between the database (which
+/// converts orders on upsert) and the task refreshing them
+#[derive(Debug, Clone)]
+pub struct FxConverter {
+    base_currency: String,
+    rates: Arc<RwLock<BTreeMap<String, f64>>>,
+}
+
+impl FxConverter {
+    pub fn new(base_currency: String) -> Self {
+        Self {
+            base_currency,
+            rates: Arc::new(RwLock::new(BTreeMap::new())),
+        }
+    }
+
+    pub fn base_currency(&self) -> &str {
+        &self.base_currency
+    }
+
+    /// Value of one unit of `currency` in the base currency
+    pub fn rate(&self, currency: &str) -> Option<f64> {
+        if currency.eq_ignore_ascii_case(&self.base_currency) {
+            return Some(1.0);
+        }
+        self.rates
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(&currency.to_ascii_uppercase())
+            .copied()
+    }
+
+    /// Replace the rates in use
+    pub fn set_rates(&self, rates: BTreeMap<String, f64>) {
+        *self.rates.write().unwrap_or_else(|e| e.into_inner()) = rates;
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct RatesResponse {
+    #[serde(default, alias = "base_code")]
+    base: Option<String>,
+    rates: BTreeMap<String, f64>,
+}
+
+/// Fetch rates from `url`, which answers like common exchange rate APIs with
+/// `{"base": "VND", "rates": {"USD": 0.0000394, ...}}`: how much of each
+/// currency one unit of the base buys. Returned rates are inverted into the
+/// base-currency value of one unit.
+pub async fn fetch_rates(url: &str, base_currency: &str) -> Result<BTreeMap<String, f64>, String> {
+    let response: RatesResponse = reqwest::Client::new()
+        .get(url)
+        .timeout(FETCH_TIMEOUT)
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| format!("FX rates request failed: {}", e))?
+        .json()
+        .await
+        .map_err(|e| format!("FX rates response is not valid: {}", e))?;
+
+    if let Some(base) = response
+        .base
+        .as_deref()
+        .filter(|b| !b.eq_ignore_ascii_case(base_currency))
+    {
+        return Err(format!(
+            "FX rates are quoted in {}, expected {}",
+            base, base_currency
+        ));
+    }
+    Ok(response
+        .rates
+        .into_iter()
+        .filter(|(currency, rate)| {
+            rate.is_finite() && *rate > 0.0 && !currency.eq_ignore_ascii_case(base_currency)
+        })
+        .map(|(currency, rate)| (currency.to_ascii_uppercase(), invert(rate)))
+        .collect())
+}
+
+/// `1 / rate` to 12 significant digits, so that 0.00004 becomes 25000 rather
+/// than 24999.999999999996
+fn invert(rate: f64) -> f64 {
+    let inverted = 1.0 / rate;
+    format!("{:.11e}", inverted).parse().unwrap_or(inverted)
+}
+
+/// Rates in the `fx_rates` table and each order's converted total in
+/// `order_fx`, one row per order
+#[derive(Clone)]
+pub struct FxStore {
+    pool: SqlitePool,
+}
+
+impl FxStore {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Initialize the fx_rates and order_fx tables
+    pub async fn init(&self) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS fx_rates (
+                currency TEXT NOT NULL,
+                rate REAL NOT NULL,
+                source TEXT NOT NULL,
+                updated_at INTEGER NOT NULL,
+                PRIMARY KEY (currency, source)
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS order_fx (
+                order_id TEXT PRIMARY KEY,
+                currency TEXT NOT NULL,
+                amount REAL NOT NULL,
+                base_currency TEXT NOT NULL,
+                base_amount REAL,
+                rate REAL,
+                converted_at INTEGER
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            "CREATE INDEX IF NOT EXISTS idx_order_fx_unconverted
+            ON order_fx (currency) WHERE base_amount IS NULL",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Replace the stored rates from `source`
+    pub async fn save_rates(
+        &self,
+        source: RateSource,
+        rates: &BTreeMap<String, f64>,
+    ) -> Result<(), sqlx::Error> {
+        let now = chrono::Utc::now().timestamp();
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("DELETE FROM fx_rates WHERE source = ?1")
+            .bind(source.as_str())
+            .execute(&mut *tx)
+            .await?;
+        for (currency, rate) in rates {
+            sqlx::query(
+                "INSERT OR REPLACE INTO fx_rates (currency, rate, source, updated_at)
+                VALUES (?1, ?2, ?3, ?4)",
+            )
+            .bind(currency)
+            .bind(rate)
+            .bind(source.as_str())
+            .bind(now)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Every stored rate, static rates winning over fetched ones
+    pub async fn rates(&self) -> Result<Vec<FxRate>, sqlx::Error> {
+        sqlx::query(
+            "SELECT currency, rate, source, updated_at FROM fx_rates
+            ORDER BY currency, source = 'static' DESC",
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(rate_from_row)
+        .collect::<Result<Vec<_>, _>>()
+        .map(|mut rates| {
+            rates.dedup_by(|b, a| a.currency == b.currency);
+            rates
+        })
+    }
+
+    /// Convert an order's total within an upsert transaction. An order keeps
+    /// the rate it was first converted at while its currency stays the same.
+    pub async fn record(
+        tx: &mut Transaction<'_, Sqlite>,
+        converter: &FxConverter,
+        order: &Order,
+    ) -> Result<(), sqlx::Error> {
+        let Some(payment) = &order.payment else {
+            return Ok(());
+        };
+        let Ok(amount) = payment.total_amount.trim().parse::<f64>() else {
+            return Ok(());
+        };
+        let currency = payment.currency.to_ascii_uppercase();
+        let rate = converter.rate(&currency);
+
+        sqlx::query(
+            "INSERT INTO order_fx (order_id, currency, amount, base_currency, base_amount, rate,
+                converted_at)
+            VALUES (?1, ?2, ?3, ?4, ?3 * ?5, ?5, CASE WHEN ?5 IS NULL THEN NULL ELSE ?6 END)
+            ON CONFLICT(order_id) DO UPDATE SET
+                rate = CASE
+                    WHEN order_fx.currency = excluded.currency
+                        AND order_fx.base_currency = excluded.base_currency
+                        AND order_fx.rate IS NOT NULL
+                    THEN order_fx.rate
+                    ELSE excluded.rate
+                END,
+                converted_at = CASE
+                    WHEN order_fx.currency = excluded.currency
+                        AND order_fx.base_currency = excluded.base_currency
+                        AND order_fx.rate IS NOT NULL
+                    THEN order_fx.converted_at
+                    ELSE excluded.converted_at
+                END,
+                currency = excluded.currency,
+                amount = excluded.amount,
+                base_currency = excluded.base_currency",
+        )
+        .bind(&order.id)
+        .bind(&currency)
+        .bind(amount)
+        .bind(converter.base_currency())
+        .bind(rate)
+        .bind(chrono::Utc::now().timestamp())
+        .execute(&mut **tx)
+        .await?;
+
+        sqlx::query("UPDATE order_fx SET base_amount = amount * rate WHERE order_id = ?1")
+            .bind(&order.id)
+            .execute(&mut **tx)
+            .await?;
+        Ok(())
+    }
+
+    /// Convert orders that had no rate for their currency when stored.
+    /// Returns how many were converted.
+    pub async fn convert_pending(&self, converter: &FxConverter) -> Result<u64, sqlx::Error> {
+        let currencies: Vec<String> = sqlx::query(
+            "SELECT DISTINCT currency FROM order_fx WHERE base_amount IS NULL AND base_currency = ?1",
+        )
+        .bind(converter.base_currency())
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(|row| row.try_get("currency"))
+        .collect::<Result<_, _>>()?;
+
+        let now = chrono::Utc::now().timestamp();
+        let mut converted = 0;
+        for currency in currencies {
+            let Some(rate) = converter.rate(&currency) else {
+                continue;
+            };
+            converted += sqlx::query(
+                "UPDATE order_fx SET rate = ?3, base_amount = amount * ?3, converted_at = ?4
+                WHERE currency = ?1 AND base_currency = ?2 AND base_amount IS NULL",
+            )
+            .bind(&currency)
+            .bind(converter.base_currency())
+            .bind(rate)
+            .bind(now)
+            .execute(&self.pool)
+            .await?
+            .rows_affected();
+        }
+        Ok(converted)
+    }
+
+    pub async fn get(&self, order_id: &str) -> Result<Option<OrderFx>, sqlx::Error> {
+        sqlx::query(
+            "SELECT currency, amount, base_currency, base_amount, rate, converted_at
+            FROM order_fx WHERE order_id = ?1",
+        )
+        .bind(order_id)
+        .fetch_optional(&self.pool)
+        .await?
+        .as_ref()
+        .map(order_fx_from_row)
+        .transpose()
+    }
+
+    /// Converted totals in `base_currency` by order id
+    pub async fn base_amounts(
+        &self,
+        base_currency: &str,
+    ) -> Result<HashMap<String, f64>, sqlx::Error> {
+        sqlx::query(
+            "SELECT order_id, base_amount FROM order_fx
+            WHERE base_currency = ?1 AND base_amount IS NOT NULL",
+        )
+        .bind(base_currency)
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(|row| Ok((row.try_get("order_id")?, row.try_get("base_amount")?)))
+        .collect()
+    }
+}
+
+fn rate_from_row(row: &SqliteRow) -> Result<FxRate, sqlx::Error> {
+    let source: String = row.try_get("source")?;
+    Ok(FxRate {
+        currency: row.try_get("currency")?,
+        rate: row.try_get("rate")?,
+        source: RateSource::parse(&source)
+            .ok_or_else(|| sqlx::Error::Decode(format!("unknown rate source {}", source).into()))?,
+        updated_at: row.try_get("updated_at")?,
+    })
+}
+
+fn order_fx_from_row(row: &SqliteRow) -> Result<OrderFx, sqlx::Error> {
+    Ok(OrderFx {
+        currency: row.try_get("currency")?,
+        amount: row.try_get("amount")?,
+        base_currency: row.try_get("base_currency")?,
+        base_amount: row.try_get("base_amount")?,
+        rate: row.try_get("rate")?,
+        converted_at: row.try_get("converted_at")?,
+    })
+}
+
+/// Load stored rates into `converter`, static ones winning. Returns how many
+/// currencies have a rate.
+pub async fn load_rates(store: &FxStore, converter: &FxConverter) -> Result<usize, sqlx::Error> {
+    let rates: BTreeMap<String, f64> = store
+        .rates()
+        .await?
+        .into_iter()
+        .map(|rate| (rate.currency, rate.rate))
+        .collect();
+    let count = rates.len();
+    converter.set_rates(rates);
+    Ok(count)
+}
+
+/// Converts the totals of orders stored before FX conversion was enabled
+pub struct FxBackfillHandler {
+    db: Arc<Database>,
+}
+
+impl FxBackfillHandler {
+    pub fn new(db: Arc<Database>) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl JobHandler for FxBackfillHandler {
+    async fn handle(&self, _job: &Job) -> Result<(), String> {
+        let converted = self
+            .db
+            .convert_stored_orders()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        info!("FX backfill converted {} orders", converted);
+        Ok(())
+    }
+}
@@ -0,0 +1,184 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::Duration;
+use utoipa::ToSchema;
+
+/// Circuit breaker state for an upstream API
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitState {
+    /// Requests flow normally
+    Closed,
+    /// Too many consecutive failures; callers should back off
+    Open,
+    /// Cooldown elapsed; the next request is a trial
+    HalfOpen,
+}
+
+/// Point-in-time view of an upstream API's health
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct HealthSnapshot {
+    pub circuit: CircuitState,
+    pub error_rate: f64,
+    pub recent_calls: usize,
+    pub consecutive_failures: u32,
+    pub last_success_at: Option<DateTime<Utc>>,
+    pub last_failure_at: Option<DateTime<Utc>>,
+    pub open_until: Option<DateTime<Utc>>,
+}
+
+struct HealthState {
+    /// Outcomes of the most recent calls, `true` for success
+    window: VecDeque<bool>,
+    consecutive_failures: u32,
+    opened_at: Option<DateTime<Utc>>,
+    last_success_at: Option<DateTime<Utc>>,
+    last_failure_at: Option<DateTime<Utc>>,
+}
+
+/// Tracks error rate and circuit breaker state for an upstream API.
+///
+/// Clients record the outcome of each call; schedulers read the state to decide
+/// how long to wait before the next run.
+pub struct ApiHealth {
+    state: Mutex<HealthState>,
+    failure_threshold: u32,
+    cooldown: chrono::Duration,
+}
+
+impl Default for ApiHealth {
+    fn default() -> Self {
+        Self::new(5, Duration::from_secs(15 * 60))
+    }
+}
+
+impl ApiHealth {
+    const WINDOW_SIZE: usize = 50;
+
+    /// Open the circuit after `failure_threshold` consecutive failures and keep
+    /// it open for `cooldown` before allowing a trial request.
+    pub fn new(failure_threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            state: Mutex::new(HealthState {
+                window: VecDeque::with_capacity(Self::WINDOW_SIZE),
+                consecutive_failures: 0,
+                opened_at: None,
+                last_success_at: None,
+                last_failure_at: None,
+            }),
+            failure_threshold: failure_threshold.max(1),
+            cooldown: chrono::Duration::from_std(cooldown)
+                .unwrap_or_else(|_| chrono::Duration::minutes(15)),
+        }
+    }
+
+    pub fn record_success(&self) {
+        let mut state = self.state.lock().unwrap();
+        Self::push(&mut state.window, true);
+        state.consecutive_failures = 0;
+        state.opened_at = None;
+        state.last_success_at = Some(Utc::now());
+    }
+
+    pub fn record_failure(&self) {
+        let now = Utc::now();
+        let mut state = self.state.lock().unwrap();
+        Self::push(&mut state.window, false);
+        state.consecutive_failures += 1;
+        state.last_failure_at = Some(now);
+
+        // A failed trial in half-open re-opens the circuit for another cooldown
+        if state.consecutive_failures >= self.failure_threshold {
+            state.opened_at = Some(now);
+        }
+    }
+
+    pub fn circuit_state(&self) -> CircuitState {
+        let state = self.state.lock().unwrap();
+        self.circuit_state_of(&state)
+    }
+
+    /// Whether a caller should attempt a request right now
+    pub fn allows_requests(&self) -> bool {
+        self.circuit_state() != CircuitState::Open
+    }
+
+    pub fn snapshot(&self) -> HealthSnapshot {
+        let state = self.state.lock().unwrap();
+        let failures = state.window.iter().filter(|ok| !**ok).count();
+
+        HealthSnapshot {
+            circuit: self.circuit_state_of(&state),
+            error_rate: if state.window.is_empty() {
+                0.0
+            } else {
+                failures as f64 / state.window.len() as f64
+            },
+            recent_calls: state.window.len(),
+            consecutive_failures: state.consecutive_failures,
+            last_success_at: state.last_success_at,
+            last_failure_at: state.last_failure_at,
+            open_until: state.opened_at.map(|t| t + self.cooldown),
+        }
+    }
+
+    fn circuit_state_of(&self, state: &HealthState) -> CircuitState {
+        match state.opened_at {
+            None => CircuitState::Closed,
+            Some(opened_at) if Utc::now() < opened_at + self.cooldown => CircuitState::Open,
+            Some(_) => CircuitState::HalfOpen,
+        }
+    }
+
+    fn push(window: &mut VecDeque<bool>, outcome: bool) {
+        if window.len() == Self::WINDOW_SIZE {
+            window.pop_front();
+        }
+        window.push_back(outcome);
+    }
+}
+
+/// Computes the delay before the next scheduled run from recent outcomes.
+///
+/// Healthy runs use the base interval; each consecutive unhealthy run doubles
+/// the delay up to `max`, and the first healthy run resets it.
+pub struct AdaptiveInterval {
+    base: Duration,
+    max: Duration,
+    unhealthy_runs: u32,
+}
+
+impl AdaptiveInterval {
+    pub fn new(base: Duration, max: Duration) -> Self {
+        Self {
+            base,
+            max: max.max(base),
+            unhealthy_runs: 0,
+        }
+    }
+
+    /// Change the bounds, e.g. after the schedule was reloaded, keeping the
+    /// current streak of unhealthy runs
+    pub fn set_bounds(&mut self, base: Duration, max: Duration) {
+        self.base = base;
+        self.max = max.max(base);
+    }
+
+    /// Record a run outcome and return the delay before the next run
+    pub fn next_delay(&mut self, healthy: bool) -> Duration {
+        if healthy {
+            self.unhealthy_runs = 0;
+            return self.base;
+        }
+
+        self.unhealthy_runs = self.unhealthy_runs.saturating_add(1);
+        let factor = 2_u32.saturating_pow(self.unhealthy_runs.min(16));
+        self.base.saturating_mul(factor).min(self.max)
+    }
+
+    pub fn unhealthy_runs(&self) -> u32 {
+        self.unhealthy_runs
+    }
+}
@@ -0,0 +1,212 @@
+use crate::order::{Order, OrderItem};
+use serde::Serialize;
+use sqlx::sqlite::{SqlitePool, SqliteRow};
+use sqlx::{Row, Sqlite, Transaction};
+use utoipa::ToSchema;
+
+/// Item statuses after its package left the seller
+const SHIPPED_ITEM_STATUSES: &[&str] = &["IN_TRANSIT", "DELIVERED", "COMPLETED"];
+
+/// Package statuses after the package left the seller
+const SHIPPED_PACKAGE_STATUSES: &[&str] = &["FULFILLING", "COMPLETED"];
+
+/// Where a single line item is in fulfillment
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ItemState {
+    /// Not shipped yet
+    Pending,
+    Shipped,
+    /// Cancelled, and not counted towards the order's progress
+    Cancelled,
+}
+
+impl ItemState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ItemState::Pending => "pending",
+            ItemState::Shipped => "shipped",
+            ItemState::Cancelled => "cancelled",
+        }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "pending" => Some(ItemState::Pending),
+            "shipped" => Some(ItemState::Shipped),
+            "cancelled" => Some(ItemState::Cancelled),
+            _ => None,
+        }
+    }
+
+    /// State of `item`, by its own status first, then by its package's and
+    /// finally by `order`'s: TikTok leaves item statuses out of some responses
+    pub fn of(order: &Order, item: &OrderItem) -> Self {
+        let package_status = item.package_status.as_deref();
+        let status = item
+            .display_status
+            .as_deref()
+            .unwrap_or(order.status.as_str());
+        if status == "CANCELLED" || package_status == Some("CANCELLED") {
+            ItemState::Cancelled
+        } else if SHIPPED_ITEM_STATUSES.contains(&status)
+            || package_status.is_some_and(|s| SHIPPED_PACKAGE_STATUSES.contains(&s))
+        {
+            ItemState::Shipped
+        } else {
+            ItemState::Pending
+        }
+    }
+}
+
+/// Order-level fulfillment progress: `shipped` of `total` live line items
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, ToSchema)]
+pub struct FulfillmentProgress {
+    pub shipped: u32,
+    /// Line items that weren't cancelled
+    pub total: u32,
+    /// Some, but not all, items have shipped
+    pub partially_shipped: bool,
+}
+
+impl FulfillmentProgress {
+    pub fn of(order: &Order) -> Self {
+        let (mut shipped, mut total) = (0, 0);
+        for item in &order.item_list {
+            match ItemState::of(order, item) {
+                ItemState::Shipped => {
+                    shipped += 1;
+                    total += 1;
+                }
+                ItemState::Pending => total += 1,
+                ItemState::Cancelled => {}
+            }
+        }
+        Self {
+            shipped,
+            total,
+            partially_shipped: shipped > 0 && shipped < total,
+        }
+    }
+}
+
+/// Fulfillment status of one line item
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ItemFulfillment {
+    pub line_item_id: String,
+    pub seller_sku: Option<String>,
+    pub package_id: Option<String>,
+    /// TikTok package status, e.g. `TO_FULFILL` or `FULFILLING`
+    pub package_status: Option<String>,
+    /// TikTok item status, e.g. `AWAITING_SHIPMENT` or `IN_TRANSIT`
+    pub display_status: Option<String>,
+    pub tracking_number: Option<String>,
+    pub state: ItemState,
+    pub updated_at: i64,
+}
+
+/// Per-line-item fulfillment status in the `order_item_fulfillment` table,
+/// kept up to date on upsert
+#[derive(Clone)]
+pub struct ItemFulfillmentStore {
+    pool: SqlitePool,
+}
+
+impl ItemFulfillmentStore {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Initialize the order_item_fulfillment table
+    pub async fn init(&self) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS order_item_fulfillment (
+                order_id TEXT NOT NULL,
+                line_item_id TEXT NOT NULL,
+                seller_sku TEXT,
+                package_id TEXT,
+                package_status TEXT,
+                display_status TEXT,
+                tracking_number TEXT,
+                state TEXT NOT NULL,
+                updated_at INTEGER NOT NULL,
+                PRIMARY KEY (order_id, line_item_id)
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            "CREATE INDEX IF NOT EXISTS idx_order_item_fulfillment_package
+            ON order_item_fulfillment (package_id)",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Replace the item statuses of `order` within an upsert transaction
+    pub async fn record(
+        tx: &mut Transaction<'_, Sqlite>,
+        order: &Order,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query("DELETE FROM order_item_fulfillment WHERE order_id = ?1")
+            .bind(&order.id)
+            .execute(&mut **tx)
+            .await?;
+
+        for item in &order.item_list {
+            sqlx::query(
+                "INSERT OR REPLACE INTO order_item_fulfillment (
+                    order_id, line_item_id, seller_sku, package_id, package_status,
+                    display_status, tracking_number, state, updated_at
+                ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            )
+            .bind(&order.id)
+            .bind(&item.id)
+            .bind(&item.seller_sku)
+            .bind(&item.package_id)
+            .bind(&item.package_status)
+            .bind(&item.display_status)
+            .bind(&item.tracking_number)
+            .bind(ItemState::of(order, item).as_str())
+            .bind(order.update_time)
+            .execute(&mut **tx)
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn for_order(&self, order_id: &str) -> Result<Vec<ItemFulfillment>, sqlx::Error> {
+        sqlx::query(
+            "SELECT line_item_id, seller_sku, package_id, package_status, display_status,
+                tracking_number, state, updated_at
+            FROM order_item_fulfillment WHERE order_id = ?1 ORDER BY line_item_id",
+        )
+        .bind(order_id)
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(item_from_row)
+        .collect()
+    }
+}
+
+fn item_from_row(row: &SqliteRow) -> Result<ItemFulfillment, sqlx::Error> {
+    let state: String = row.try_get("state")?;
+    Ok(ItemFulfillment {
+        line_item_id: row.try_get("line_item_id")?,
+        seller_sku: row.try_get("seller_sku")?,
+        package_id: row.try_get("package_id")?,
+        package_status: row.try_get("package_status")?,
+        display_status: row.try_get("display_status")?,
+        tracking_number: row.try_get("tracking_number")?,
+        state: ItemState::parse(&state).ok_or_else(|| sqlx::Error::ColumnDecode {
+            index: "state".to_string(),
+            source: format!("unknown item state {:?}", state).into(),
+        })?,
+        updated_at: row.try_get("updated_at")?,
+    })
+}
@@ -0,0 +1,441 @@
+use crate::supervisor::panic_message;
+use async_trait::async_trait;
+use serde::Serialize;
+use sqlx::sqlite::SqlitePool;
+use sqlx::Row;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::{error, info, info_span, warn, Instrument};
+use utoipa::ToSchema;
+
+/// A unit of background work persisted in the `jobs` table
+#[derive(Debug, Clone, Serialize)]
+pub struct Job {
+    pub id: i64,
+    pub kind: String,
+    pub payload: serde_json::Value,
+    pub status: String,
+    pub attempts: i64,
+    pub max_attempts: i64,
+    pub last_error: Option<String>,
+}
+
+/// A job that exhausted its attempts, kept with its payload for inspection and manual retry
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct DeadLetter {
+    pub id: i64,
+    pub job_id: i64,
+    pub kind: String,
+    pub payload: serde_json::Value,
+    pub error: String,
+    pub attempts: i64,
+    /// `open`, or `retried` once re-enqueued
+    pub status: String,
+    pub created_at: i64,
+    pub retried_at: Option<i64>,
+}
+
+/// How much work is queued, and how much of it is going wrong
+#[derive(Debug, Clone, Default, Serialize, ToSchema)]
+pub struct JobCounts {
+    pub pending: i64,
+    /// Pending jobs whose last attempt failed
+    pub retrying: i64,
+    /// Open dead letters
+    pub dead_letters: i64,
+}
+
+/// Processes jobs of a single kind
+#[async_trait]
+pub trait JobHandler: Send + Sync {
+    /// Run the job. Errors are retried with backoff until `max_attempts` is reached.
+    async fn handle(&self, job: &Job) -> Result<(), String>;
+
+    /// Called once when the job has exhausted its attempts
+    async fn on_permanent_failure(&self, _job: &Job, _error: &str) {}
+}
+
+/// SQLite-backed job queue with retries and exponential backoff
+#[derive(Clone)]
+pub struct JobQueue {
+    pool: SqlitePool,
+}
+
+impl JobQueue {
+    const BASE_BACKOFF_SECS: i64 = 30;
+    const MAX_BACKOFF_SECS: i64 = 3600;
+
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Initialize the jobs table
+    pub async fn init(&self) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS jobs (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                kind TEXT NOT NULL,
+                dedup_key TEXT UNIQUE,
+                payload TEXT NOT NULL,
+                status TEXT NOT NULL,
+                attempts INTEGER NOT NULL DEFAULT 0,
+                max_attempts INTEGER NOT NULL,
+                run_at INTEGER NOT NULL,
+                last_error TEXT,
+                created_at INTEGER NOT NULL,
+                updated_at INTEGER NOT NULL
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_jobs_status_run_at ON jobs (status, run_at)")
+            .execute(&self.pool)
+            .await?;
+
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS failed_jobs (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                job_id INTEGER NOT NULL,
+                kind TEXT NOT NULL,
+                payload TEXT NOT NULL,
+                error TEXT NOT NULL,
+                attempts INTEGER NOT NULL,
+                status TEXT NOT NULL,
+                created_at INTEGER NOT NULL,
+                retried_at INTEGER
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Enqueue a job. If `dedup_key` is set and a job with that key already
+    /// exists, nothing is inserted and `Ok(false)` is returned.
+    pub async fn enqueue(
+        &self,
+        kind: &str,
+        dedup_key: Option<&str>,
+        payload: &serde_json::Value,
+        max_attempts: u32,
+    ) -> Result<bool, sqlx::Error> {
+        let now = chrono::Utc::now().timestamp();
+
+        let result = sqlx::query(
+            "INSERT OR IGNORE INTO jobs (
+                kind, dedup_key, payload, status, attempts, max_attempts, run_at, created_at, updated_at
+            ) VALUES (?1, ?2, ?3, 'pending', 0, ?4, ?5, ?5, ?5)",
+        )
+        .bind(kind)
+        .bind(dedup_key)
+        .bind(payload.to_string())
+        .bind(max_attempts.max(1) as i64)
+        .bind(now)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Atomically claim the next due job, marking it as running
+    pub async fn claim_next(&self) -> Result<Option<Job>, sqlx::Error> {
+        let now = chrono::Utc::now().timestamp();
+
+        let row = sqlx::query(
+            "UPDATE jobs SET status = 'running', attempts = attempts + 1, updated_at = ?1
+            WHERE id = (
+                SELECT id FROM jobs WHERE status = 'pending' AND run_at <= ?1
+                ORDER BY run_at, id LIMIT 1
+            )
+            RETURNING id, kind, payload, status, attempts, max_attempts, last_error",
+        )
+        .bind(now)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.map(|row| Self::row_to_job(&row)).transpose()
+    }
+
+    /// Mark a job as finished successfully
+    pub async fn complete(&self, job_id: i64) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE jobs SET status = 'succeeded', last_error = NULL, updated_at = ?2 WHERE id = ?1")
+            .bind(job_id)
+            .bind(chrono::Utc::now().timestamp())
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Record a failed attempt. Returns `true` if the job will be retried,
+    /// `false` if it has exhausted its attempts and is now permanently failed.
+    pub async fn fail(&self, job: &Job, error: &str) -> Result<bool, sqlx::Error> {
+        let now = chrono::Utc::now().timestamp();
+        let will_retry = job.attempts < job.max_attempts;
+
+        if will_retry {
+            let run_at = now + Self::backoff_secs(job.attempts);
+            sqlx::query(
+                "UPDATE jobs SET status = 'pending', last_error = ?2, run_at = ?3, updated_at = ?4 WHERE id = ?1",
+            )
+            .bind(job.id)
+            .bind(error)
+            .bind(run_at)
+            .bind(now)
+            .execute(&self.pool)
+            .await?;
+        } else {
+            sqlx::query(
+                "UPDATE jobs SET status = 'failed', last_error = ?2, updated_at = ?3 WHERE id = ?1",
+            )
+            .bind(job.id)
+            .bind(error)
+            .bind(now)
+            .execute(&self.pool)
+            .await?;
+        }
+
+        Ok(will_retry)
+    }
+
+    /// Copy a permanently failed job into the dead-letter table
+    pub async fn dead_letter(&self, job: &Job, error: &str) -> Result<i64, sqlx::Error> {
+        let result = sqlx::query(
+            "INSERT INTO failed_jobs (job_id, kind, payload, error, attempts, status, created_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, 'open', ?6)",
+        )
+        .bind(job.id)
+        .bind(&job.kind)
+        .bind(job.payload.to_string())
+        .bind(error)
+        .bind(job.attempts)
+        .bind(chrono::Utc::now().timestamp())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.last_insert_rowid())
+    }
+
+    /// Dead letters, newest first, optionally filtered by status
+    pub async fn dead_letters(
+        &self,
+        status: Option<&str>,
+        limit: i64,
+    ) -> Result<Vec<DeadLetter>, sqlx::Error> {
+        let rows = sqlx::query(
+            "SELECT id, job_id, kind, payload, error, attempts, status, created_at, retried_at
+            FROM failed_jobs WHERE ?1 IS NULL OR status = ?1
+            ORDER BY id DESC LIMIT ?2",
+        )
+        .bind(status)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter().map(Self::row_to_dead_letter).collect()
+    }
+
+    /// Re-enqueue a dead letter as a fresh job. Returns the new job id, or
+    /// `None` if no open dead letter has that id.
+    pub async fn retry_dead_letter(
+        &self,
+        id: i64,
+        max_attempts: u32,
+    ) -> Result<Option<i64>, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let now = chrono::Utc::now().timestamp();
+
+        let row = sqlx::query(
+            "UPDATE failed_jobs SET status = 'retried', retried_at = ?2
+            WHERE id = ?1 AND status = 'open'
+            RETURNING kind, payload",
+        )
+        .bind(id)
+        .bind(now)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let Some(row) = row else {
+            return Ok(None);
+        };
+
+        let kind: String = row.try_get("kind")?;
+        let payload: String = row.try_get("payload")?;
+
+        let result = sqlx::query(
+            "INSERT INTO jobs (
+                kind, dedup_key, payload, status, attempts, max_attempts, run_at, created_at, updated_at
+            ) VALUES (?1, NULL, ?2, 'pending', 0, ?3, ?4, ?4, ?4)",
+        )
+        .bind(kind)
+        .bind(payload)
+        .bind(max_attempts.max(1) as i64)
+        .bind(now)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(Some(result.last_insert_rowid()))
+    }
+
+    /// Put jobs left in `running` (e.g. after a crash) back in the queue
+    pub async fn requeue_stale(&self) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query(
+            "UPDATE jobs SET status = 'pending', updated_at = ?1 WHERE status = 'running'",
+        )
+        .bind(chrono::Utc::now().timestamp())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    pub async fn counts(&self) -> Result<JobCounts, sqlx::Error> {
+        let row = sqlx::query(
+            "SELECT
+                (SELECT COUNT(*) FROM jobs WHERE status = 'pending') AS pending,
+                (SELECT COUNT(*) FROM jobs WHERE status = 'pending' AND last_error IS NOT NULL)
+                    AS retrying,
+                (SELECT COUNT(*) FROM failed_jobs WHERE status = 'open') AS dead_letters",
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(JobCounts {
+            pending: row.try_get("pending")?,
+            retrying: row.try_get("retrying")?,
+            dead_letters: row.try_get("dead_letters")?,
+        })
+    }
+
+    fn backoff_secs(attempts: i64) -> i64 {
+        let exponent = attempts.clamp(1, 16) as u32 - 1;
+        (Self::BASE_BACKOFF_SECS * 2_i64.pow(exponent)).min(Self::MAX_BACKOFF_SECS)
+    }
+
+    fn row_to_job(row: &sqlx::sqlite::SqliteRow) -> Result<Job, sqlx::Error> {
+        let payload: String = row.try_get("payload")?;
+        Ok(Job {
+            id: row.try_get("id")?,
+            kind: row.try_get("kind")?,
+            payload: serde_json::from_str(&payload).unwrap_or(serde_json::Value::Null),
+            status: row.try_get("status")?,
+            attempts: row.try_get("attempts")?,
+            max_attempts: row.try_get("max_attempts")?,
+            last_error: row.try_get("last_error")?,
+        })
+    }
+
+    fn row_to_dead_letter(row: &sqlx::sqlite::SqliteRow) -> Result<DeadLetter, sqlx::Error> {
+        let payload: String = row.try_get("payload")?;
+        Ok(DeadLetter {
+            id: row.try_get("id")?,
+            job_id: row.try_get("job_id")?,
+            kind: row.try_get("kind")?,
+            payload: serde_json::from_str(&payload).unwrap_or(serde_json::Value::Null),
+            error: row.try_get("error")?,
+            attempts: row.try_get("attempts")?,
+            status: row.try_get("status")?,
+            created_at: row.try_get("created_at")?,
+            retried_at: row.try_get("retried_at")?,
+        })
+    }
+}
+
+/// Polls the queue and dispatches jobs to their handler by kind
+#[derive(Clone)]
+pub struct JobWorker {
+    queue: JobQueue,
+    handlers: HashMap<String, Arc<dyn JobHandler>>,
+}
+
+impl JobWorker {
+    const POLL_INTERVAL_SECS: u64 = 5;
+
+    pub fn new(queue: JobQueue) -> Self {
+        Self {
+            queue,
+            handlers: HashMap::new(),
+        }
+    }
+
+    pub fn register(mut self, kind: &str, handler: Arc<dyn JobHandler>) -> Self {
+        self.handlers.insert(kind.to_string(), handler);
+        self
+    }
+
+    /// Run forever, processing jobs as they become due
+    pub async fn run(self) {
+        match self.queue.requeue_stale().await {
+            Ok(0) => {}
+            Ok(n) => info!("Requeued {} jobs left running by a previous process", n),
+            Err(e) => error!("Failed to requeue stale jobs: {}", e),
+        }
+
+        let mut interval =
+            tokio::time::interval(tokio::time::Duration::from_secs(Self::POLL_INTERVAL_SECS));
+
+        loop {
+            interval.tick().await;
+
+            // Drain everything that is due before sleeping again
+            loop {
+                match self.queue.claim_next().await {
+                    Ok(Some(job)) => self.process(job).await,
+                    Ok(None) => break,
+                    Err(e) => {
+                        error!("Failed to claim job: {}", e);
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    async fn process(&self, job: Job) {
+        let Some(handler) = self.handlers.get(&job.kind) else {
+            warn!("No handler registered for job kind '{}'", job.kind);
+            if let Err(e) = self.queue.fail(&job, "no handler registered").await {
+                error!("Failed to update job {}: {}", job.id, e);
+            }
+            return;
+        };
+
+        // A panicking handler fails the attempt instead of taking the worker down
+        let run = tokio::spawn({
+            let (handler, job) = (handler.clone(), job.clone());
+            let span = info_span!("job", kind = %job.kind, id = job.id, attempt = job.attempts);
+            async move { handler.handle(&job).await }.instrument(span)
+        });
+        let result = run
+            .await
+            .unwrap_or_else(|e| Err(format!("handler panicked: {}", panic_message(e))));
+
+        match result {
+            Ok(()) => {
+                if let Err(e) = self.queue.complete(job.id).await {
+                    error!("Failed to mark job {} complete: {}", job.id, e);
+                }
+            }
+            Err(err) => match self.queue.fail(&job, &err).await {
+                Ok(true) => warn!(
+                    "Job {} ({}) failed on attempt {}/{}: {}",
+                    job.id, job.kind, job.attempts, job.max_attempts, err
+                ),
+                Ok(false) => {
+                    error!(
+                        "Job {} ({}) permanently failed after {} attempts: {}",
+                        job.id, job.kind, job.attempts, err
+                    );
+                    if let Err(e) = self.queue.dead_letter(&job, &err).await {
+                        error!("Failed to dead-letter job {}: {}", job.id, e);
+                    }
+                    handler.on_permanent_failure(&job, &err).await;
+                }
+                Err(e) => error!("Failed to update job {}: {}", job.id, e),
+            },
+        }
+    }
+}
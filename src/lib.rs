@@ -1,8 +1,153 @@
-pub mod config;
-pub mod database;
+//! TikTok Shop API client library.
+//!
+//! The `oauth`, `order`, `requests`, `signing`, `storage` and `transport`
+//! modules form the SDK and never read environment variables. Modules behind
+//! the default `server` feature back the bundled order-sync server
+//! (`src/bin/server/main.rs`).
+
+pub mod address;
+pub mod analytics;
+pub mod anonymize;
+#[cfg(feature = "chaos")]
+pub mod chaos;
 pub mod error;
+pub mod finance;
+pub mod fulfillment;
+pub mod health;
+pub mod logistics;
 pub mod oauth;
 pub mod order;
+pub mod product;
+pub mod redact;
+pub mod request_id;
 pub mod requests;
+pub mod shops;
+pub mod signing;
 pub mod storage;
+pub mod transport;
+pub mod webhooks;
+
+#[cfg(feature = "server")]
+pub mod accounting;
+#[cfg(feature = "server")]
+pub mod api_calls;
+#[cfg(feature = "server")]
+pub mod aws_sigv4;
+#[cfg(feature = "server")]
+pub mod backfill;
+#[cfg(feature = "server")]
+pub mod cache;
+#[cfg(feature = "server")]
+pub mod cancellations;
+#[cfg(feature = "server")]
+pub mod carriers;
+#[cfg(feature = "server")]
+pub mod communications;
+#[cfg(feature = "server")]
+pub mod config;
+#[cfg(feature = "server")]
+pub mod customers;
+#[cfg(feature = "server")]
+pub mod daily_stats;
+#[cfg(feature = "server")]
+pub mod dashboard;
+#[cfg(feature = "server")]
+pub mod database;
+#[cfg(feature = "server")]
+pub mod deauthorization;
+#[cfg(feature = "server")]
+pub mod documents;
+#[cfg(feature = "server")]
+pub mod erasure;
+#[cfg(feature = "server")]
+pub mod esim;
+#[cfg(feature = "server")]
+pub mod esim_delivery;
+#[cfg(feature = "server")]
+pub mod events;
+#[cfg(feature = "server")]
+pub mod external_refs;
+#[cfg(feature = "server")]
+pub mod fx;
+#[cfg(feature = "server")]
+pub mod item_fulfillment;
+#[cfg(feature = "server")]
+pub mod jobs;
+#[cfg(feature = "server")]
+pub mod lookup;
+#[cfg(feature = "server")]
+pub mod maintenance;
+#[cfg(feature = "server")]
+pub mod mirror;
+#[cfg(feature = "server")]
+pub mod notes;
+#[cfg(feature = "server")]
+pub mod oauth_state;
+#[cfg(feature = "server")]
+pub mod order_diff;
+#[cfg(feature = "server")]
+pub mod order_jobs;
+#[cfg(feature = "server")]
+pub mod order_schema;
+#[cfg(feature = "server")]
+pub mod order_timeline;
+#[cfg(feature = "server")]
+pub mod outbound_webhooks;
+#[cfg(feature = "server")]
+pub mod outbox;
+#[cfg(feature = "server")]
+pub mod packages;
+#[cfg(feature = "server")]
+pub mod packing_slip;
+#[cfg(feature = "server")]
+pub mod payload_archive;
+#[cfg(feature = "server")]
+pub mod pii_crypto;
+#[cfg(feature = "server")]
+pub mod price_details;
+#[cfg(feature = "server")]
+pub mod processing;
+#[cfg(feature = "server")]
+pub mod promotions;
+#[cfg(feature = "server")]
+pub mod reconciliation;
+#[cfg(feature = "server")]
+pub mod replacements;
+#[cfg(feature = "server")]
+pub mod reporting;
+#[cfg(feature = "server")]
+pub mod risk;
+#[cfg(feature = "server")]
+pub mod scheduler;
+#[cfg(feature = "server")]
+pub mod secrets;
+#[cfg(feature = "server")]
+pub mod settlements;
+#[cfg(feature = "server")]
+pub mod shop_performance;
+#[cfg(feature = "server")]
+pub mod shop_settings;
+#[cfg(feature = "server")]
+pub mod sku_mappings;
+#[cfg(feature = "server")]
+pub mod sku_stats;
+#[cfg(feature = "server")]
+pub mod sla;
+#[cfg(feature = "server")]
+pub mod startup;
+#[cfg(feature = "server")]
+pub mod supervisor;
+#[cfg(feature = "server")]
+pub mod tags;
+#[cfg(feature = "otel")]
+pub mod telemetry;
+#[cfg(feature = "server")]
+pub mod tenants;
+#[cfg(feature = "server")]
+pub mod threepl;
+#[cfg(feature = "server")]
+pub mod validation;
+#[cfg(feature = "server")]
+pub mod warehouse_routing;
+#[cfg(feature = "server")]
 pub mod wow_requests;
@@ -0,0 +1,325 @@
+use crate::error::AppError;
+use crate::requests::TikTokShopApiClient;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use utoipa::ToSchema;
+
+/// Client for the Logistics API (warehouses, delivery options, shipping providers)
+pub struct LogisticsClient {
+    api_client: TikTokShopApiClient,
+    shop_cipher: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize, ToSchema)]
+pub struct WarehouseAddress {
+    #[serde(default)]
+    pub region_code: Option<String>,
+    #[serde(default)]
+    pub state: Option<String>,
+    #[serde(default)]
+    pub city: Option<String>,
+    #[serde(default)]
+    pub district: Option<String>,
+    #[serde(default)]
+    pub postal_code: Option<String>,
+    #[serde(default)]
+    pub full_address: Option<String>,
+}
+
+/// A seller warehouse of the shop
+#[derive(Debug, Clone, Deserialize, Serialize, ToSchema)]
+pub struct Warehouse {
+    pub id: String,
+    #[serde(default)]
+    pub name: String,
+    /// `ENABLED`, `DISABLED` or `RESTRICTED`
+    #[serde(default)]
+    pub effect_status: Option<String>,
+    /// `SALES_WAREHOUSE` or `RETURN_WAREHOUSE`
+    #[serde(default, rename = "type")]
+    pub warehouse_type: Option<String>,
+    #[serde(default)]
+    pub is_default: bool,
+    #[serde(default)]
+    pub address: Option<WarehouseAddress>,
+}
+
+impl Warehouse {
+    /// Whether orders can ship from it; warehouses without a status count as enabled
+    pub fn is_enabled(&self) -> bool {
+        self.effect_status.as_deref().is_none_or(|s| s == "ENABLED")
+            && self.warehouse_type.as_deref() != Some("RETURN_WAREHOUSE")
+    }
+}
+
+/// A shipping service offered from a warehouse
+#[derive(Debug, Clone, Deserialize, Serialize, ToSchema)]
+pub struct DeliveryOption {
+    pub id: String,
+    #[serde(default)]
+    pub name: String,
+    /// `STANDARD`, `EXPRESS`, `ECONOMY` or `SEND_BY_SELLER`
+    #[serde(default, rename = "type")]
+    pub delivery_type: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+/// A carrier available for a delivery option
+#[derive(Debug, Clone, Deserialize, Serialize, ToSchema)]
+pub struct ShippingProvider {
+    pub id: String,
+    #[serde(default)]
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct GetWarehousesResponse {
+    #[serde(default)]
+    warehouses: Vec<Warehouse>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GetDeliveryOptionsResponse {
+    #[serde(default)]
+    delivery_options: Vec<DeliveryOption>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GetShippingProvidersResponse {
+    #[serde(default)]
+    shipping_providers: Vec<ShippingProvider>,
+}
+
+impl LogisticsClient {
+    pub fn new(app_key: String, app_secret: String) -> Self {
+        Self::with_api_client(TikTokShopApiClient::new(app_key, app_secret))
+    }
+
+    pub fn with_api_client(api_client: TikTokShopApiClient) -> Self {
+        Self {
+            api_client,
+            shop_cipher: None,
+        }
+    }
+
+    /// Cipher used when a call doesn't pass one explicitly
+    pub fn with_shop_cipher(mut self, shop_cipher: Option<String>) -> Self {
+        self.shop_cipher = shop_cipher;
+        self
+    }
+
+    pub async fn get_warehouses(
+        &self,
+        access_token: &str,
+        shop_cipher: Option<&str>,
+    ) -> Result<Vec<Warehouse>, AppError> {
+        let shop_cipher = shop_cipher.or(self.shop_cipher.as_deref());
+        let response: GetWarehousesResponse = self
+            .api_client
+            .get(
+                "/logistics/202309/warehouses",
+                Some(access_token),
+                shop_cipher,
+                BTreeMap::new(),
+            )
+            .await?;
+        Ok(response.warehouses)
+    }
+
+    pub async fn get_delivery_options(
+        &self,
+        access_token: &str,
+        shop_cipher: Option<&str>,
+        warehouse_id: &str,
+    ) -> Result<Vec<DeliveryOption>, AppError> {
+        let shop_cipher = shop_cipher.or(self.shop_cipher.as_deref());
+        let response: GetDeliveryOptionsResponse = self
+            .api_client
+            .get(
+                &format!(
+                    "/logistics/202309/warehouses/{}/delivery_options",
+                    warehouse_id
+                ),
+                Some(access_token),
+                shop_cipher,
+                BTreeMap::new(),
+            )
+            .await?;
+        Ok(response.delivery_options)
+    }
+
+    pub async fn get_shipping_providers(
+        &self,
+        access_token: &str,
+        shop_cipher: Option<&str>,
+        delivery_option_id: &str,
+    ) -> Result<Vec<ShippingProvider>, AppError> {
+        let shop_cipher = shop_cipher.or(self.shop_cipher.as_deref());
+        let response: GetShippingProvidersResponse = self
+            .api_client
+            .get(
+                &format!(
+                    "/logistics/202309/delivery_options/{}/shipping_providers",
+                    delivery_option_id
+                ),
+                Some(access_token),
+                shop_cipher,
+                BTreeMap::new(),
+            )
+            .await?;
+        Ok(response.shipping_providers)
+    }
+}
+
+/// A delivery option of a warehouse with its carriers
+#[cfg(feature = "server")]
+#[derive(Debug, Clone, Deserialize, Serialize, ToSchema)]
+pub struct CachedDeliveryOption {
+    #[serde(flatten)]
+    pub option: DeliveryOption,
+    pub shipping_providers: Vec<ShippingProvider>,
+}
+
+/// A warehouse with everything that can ship from it
+#[cfg(feature = "server")]
+#[derive(Debug, Clone, Deserialize, Serialize, ToSchema)]
+pub struct CachedWarehouse {
+    #[serde(flatten)]
+    pub warehouse: Warehouse,
+    pub delivery_options: Vec<CachedDeliveryOption>,
+}
+
+/// The shop's logistics setup as last fetched
+#[cfg(feature = "server")]
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct LogisticsSnapshot {
+    pub warehouses: Vec<CachedWarehouse>,
+    pub fetched_at: i64,
+}
+
+#[cfg(feature = "server")]
+impl LogisticsSnapshot {
+    /// Check that an order can ship from `warehouse_id` with
+    /// `shipping_provider_id`, before asking TikTok to ship it
+    pub fn validate(
+        &self,
+        warehouse_id: &str,
+        shipping_provider_id: Option<&str>,
+    ) -> Result<(), String> {
+        let warehouse = self
+            .warehouses
+            .iter()
+            .find(|w| w.warehouse.id == warehouse_id)
+            .ok_or_else(|| format!("unknown warehouse {}", warehouse_id))?;
+        if !warehouse.warehouse.is_enabled() {
+            return Err(format!("warehouse {} cannot ship orders", warehouse_id));
+        }
+        let Some(provider_id) = shipping_provider_id else {
+            return Ok(());
+        };
+        let offered = warehouse
+            .delivery_options
+            .iter()
+            .flat_map(|o| &o.shipping_providers)
+            .any(|p| p.id == provider_id);
+        if !offered {
+            return Err(format!(
+                "shipping provider {} is not offered from warehouse {}",
+                provider_id, warehouse_id
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Local copy of the shop's warehouses, delivery options and shipping
+/// providers, in the `logistics_cache` table. TikTok changes these rarely,
+/// so the whole tree is stored as one JSON snapshot.
+#[cfg(feature = "server")]
+#[derive(Clone)]
+pub struct LogisticsStore {
+    pool: sqlx::sqlite::SqlitePool,
+}
+
+#[cfg(feature = "server")]
+impl LogisticsStore {
+    pub fn new(pool: sqlx::sqlite::SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Initialize the logistics_cache table
+    pub async fn init(&self) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS logistics_cache (
+                id INTEGER PRIMARY KEY CHECK (id = 1),
+                snapshot TEXT NOT NULL,
+                fetched_at INTEGER NOT NULL
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn get(&self) -> Result<Option<LogisticsSnapshot>, sqlx::Error> {
+        use sqlx::Row;
+
+        let Some(row) =
+            sqlx::query("SELECT snapshot, fetched_at FROM logistics_cache WHERE id = 1")
+                .fetch_optional(&self.pool)
+                .await?
+        else {
+            return Ok(None);
+        };
+        let snapshot: String = row.try_get("snapshot")?;
+        Ok(Some(LogisticsSnapshot {
+            warehouses: serde_json::from_str(&snapshot)
+                .map_err(|e| sqlx::Error::Decode(e.into()))?,
+            fetched_at: row.try_get("fetched_at")?,
+        }))
+    }
+
+    /// Fetch the whole tree from TikTok and replace the cached copy
+    pub async fn refresh(
+        &self,
+        client: &LogisticsClient,
+        access_token: &str,
+    ) -> Result<LogisticsSnapshot, AppError> {
+        let mut warehouses = Vec::new();
+        for warehouse in client.get_warehouses(access_token, None).await? {
+            let mut delivery_options = Vec::new();
+            if warehouse.is_enabled() {
+                for option in client
+                    .get_delivery_options(access_token, None, &warehouse.id)
+                    .await?
+                {
+                    let shipping_providers = client
+                        .get_shipping_providers(access_token, None, &option.id)
+                        .await?;
+                    delivery_options.push(CachedDeliveryOption {
+                        option,
+                        shipping_providers,
+                    });
+                }
+            }
+            warehouses.push(CachedWarehouse {
+                warehouse,
+                delivery_options,
+            });
+        }
+
+        let snapshot = LogisticsSnapshot {
+            warehouses,
+            fetched_at: chrono::Utc::now().timestamp(),
+        };
+        sqlx::query(
+            "INSERT OR REPLACE INTO logistics_cache (id, snapshot, fetched_at) VALUES (1, ?1, ?2)",
+        )
+        .bind(serde_json::to_string(&snapshot.warehouses).unwrap_or_default())
+        .bind(snapshot.fetched_at)
+        .execute(&self.pool)
+        .await?;
+        Ok(snapshot)
+    }
+}
@@ -0,0 +1,103 @@
+use crate::database::Database;
+use crate::jobs::{Job, JobHandler};
+use crate::order::Order;
+use async_trait::async_trait;
+use std::sync::Arc;
+use tracing::info;
+
+/// Job filling the lookup columns of orders stored before they existed
+pub const BACKFILL_JOB_KIND: &str = "lookup_backfill";
+
+/// Fewest phone digits a lookup accepts
+pub const MIN_PHONE_DIGITS: usize = 4;
+
+/// Fewest characters a partial tracking number lookup accepts
+pub const MIN_PARTIAL_TRACKING_CHARS: usize = 6;
+
+/// Digits of a phone number, keeping `*` where TikTok masked one
+fn phone_chars(phone: &str) -> Vec<char> {
+    phone
+        .chars()
+        .filter(|c| c.is_ascii_digit() || *c == '*')
+        .collect()
+}
+
+/// Last four digits of the recipient's phone number, `''` if it has fewer
+/// or any of them is masked
+pub fn phone_last4(order: &Order) -> String {
+    let chars = order
+        .recipient_address
+        .as_ref()
+        .and_then(|a| a.phone.as_deref())
+        .map(phone_chars)
+        .unwrap_or_default();
+    if chars.len() < MIN_PHONE_DIGITS {
+        return String::new();
+    }
+    let last4: String = chars[chars.len() - MIN_PHONE_DIGITS..].iter().collect();
+    if last4.contains('*') {
+        String::new()
+    } else {
+        last4
+    }
+}
+
+/// Whether a stored phone number ends in `query`'s digits. Masked digits
+/// match anything, so `0901234567` finds `(+84)90*****567`.
+pub fn phone_matches(stored: &str, query: &str) -> bool {
+    let stored = phone_chars(stored);
+    let query: Vec<char> = query.chars().filter(char::is_ascii_digit).collect();
+    if query.is_empty() {
+        return false;
+    }
+    // A query with a country code or trunk prefix is longer than the stored number
+    stored
+        .iter()
+        .rev()
+        .zip(query.iter().rev())
+        .all(|(s, q)| *s == '*' || s == q)
+}
+
+/// Tracking number as stored for lookups: uppercase letters and digits only,
+/// so `jt 123/4` and `JT1234` are the same
+pub fn normalize_tracking(value: &str) -> String {
+    value
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_uppercase())
+        .collect()
+}
+
+/// The order's normalized tracking number, `''` if it has none yet
+pub fn tracking_key(order: &Order) -> String {
+    order
+        .tracking_number
+        .as_deref()
+        .map(normalize_tracking)
+        .unwrap_or_default()
+}
+
+/// Fills `phone_last4` and `tracking_number` of orders stored before lookups
+pub struct LookupBackfillHandler {
+    db: Arc<Database>,
+}
+
+impl LookupBackfillHandler {
+    pub fn new(db: Arc<Database>) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl JobHandler for LookupBackfillHandler {
+    async fn handle(&self, _job: &Job) -> Result<(), String> {
+        let indexed = self
+            .db
+            .index_lookup_columns()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        info!("Lookup backfill indexed {} orders", indexed);
+        Ok(())
+    }
+}
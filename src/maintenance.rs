@@ -0,0 +1,212 @@
+use serde::Serialize;
+use sqlx::sqlite::SqlitePool;
+use sqlx::Row;
+use std::time::Instant;
+use utoipa::ToSchema;
+
+/// `PRAGMA auto_vacuum` value for incremental mode
+const AUTO_VACUUM_INCREMENTAL: i64 = 2;
+
+/// Outcome of one maintenance pass
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct MaintenanceRun {
+    pub started_at: i64,
+    pub duration_ms: i64,
+    /// `incremental`, or `full` when the database was first converted to incremental vacuum
+    pub vacuum: String,
+    /// Pages returned to the filesystem
+    pub freed_pages: i64,
+    /// Full-text indexes merged
+    pub fts_tables_optimized: i64,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct TableStats {
+    pub name: String,
+    pub rows: i64,
+}
+
+/// Size of the database and its tables
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct DatabaseStats {
+    pub file_bytes: u64,
+    /// Size of the write-ahead log, when WAL is enabled
+    pub wal_bytes: u64,
+    pub page_size: i64,
+    pub page_count: i64,
+    /// Unused pages that a vacuum would give back
+    pub freelist_count: i64,
+    pub tables: Vec<TableStats>,
+    pub last_maintenance: Option<MaintenanceRun>,
+}
+
+/// Vacuum, statistics and index upkeep for the SQLite database, with a log
+/// of past runs in the `maintenance_runs` table
+#[derive(Clone)]
+pub struct MaintenanceStore {
+    pool: SqlitePool,
+}
+
+impl MaintenanceStore {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Initialize the maintenance_runs table
+    pub async fn init(&self) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS maintenance_runs (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                started_at INTEGER NOT NULL,
+                duration_ms INTEGER NOT NULL,
+                vacuum TEXT NOT NULL,
+                freed_pages INTEGER NOT NULL,
+                fts_tables_optimized INTEGER NOT NULL
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Reclaim free pages, refresh planner statistics, merge full-text indexes
+    /// and truncate the WAL.
+    ///
+    /// Databases created before incremental vacuum was enabled are converted
+    /// with one full `VACUUM`, which locks the database while it runs.
+    pub async fn run(&self) -> Result<MaintenanceRun, sqlx::Error> {
+        let started_at = chrono::Utc::now().timestamp();
+        let started = Instant::now();
+        let free_before = self.pragma("freelist_count").await?;
+
+        let vacuum = if self.pragma("auto_vacuum").await? == AUTO_VACUUM_INCREMENTAL {
+            sqlx::query("PRAGMA incremental_vacuum")
+                .execute(&self.pool)
+                .await?;
+            "incremental"
+        } else {
+            sqlx::query("PRAGMA auto_vacuum = INCREMENTAL")
+                .execute(&self.pool)
+                .await?;
+            sqlx::query("VACUUM").execute(&self.pool).await?;
+            "full"
+        };
+        let freed_pages = (free_before - self.pragma("freelist_count").await?).max(0);
+
+        sqlx::query("ANALYZE").execute(&self.pool).await?;
+
+        let fts_tables: Vec<String> = sqlx::query(
+            "SELECT name FROM sqlite_master
+            WHERE type = 'table' AND sql LIKE 'CREATE VIRTUAL TABLE%USING fts%'",
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(|row| row.try_get("name"))
+        .collect::<Result<_, _>>()?;
+        for table in &fts_tables {
+            let table = quote_identifier(table);
+            sqlx::query(&format!("INSERT INTO {0}({0}) VALUES ('optimize')", table))
+                .execute(&self.pool)
+                .await?;
+        }
+
+        sqlx::query("PRAGMA wal_checkpoint(TRUNCATE)")
+            .execute(&self.pool)
+            .await?;
+
+        let run = MaintenanceRun {
+            started_at,
+            duration_ms: started.elapsed().as_millis() as i64,
+            vacuum: vacuum.to_string(),
+            freed_pages,
+            fts_tables_optimized: fts_tables.len() as i64,
+        };
+
+        sqlx::query(
+            "INSERT INTO maintenance_runs (
+                started_at, duration_ms, vacuum, freed_pages, fts_tables_optimized
+            ) VALUES (?1, ?2, ?3, ?4, ?5)",
+        )
+        .bind(run.started_at)
+        .bind(run.duration_ms)
+        .bind(&run.vacuum)
+        .bind(run.freed_pages)
+        .bind(run.fts_tables_optimized)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(run)
+    }
+
+    pub async fn last_run(&self) -> Result<Option<MaintenanceRun>, sqlx::Error> {
+        let row = sqlx::query(
+            "SELECT started_at, duration_ms, vacuum, freed_pages, fts_tables_optimized
+            FROM maintenance_runs ORDER BY id DESC LIMIT 1",
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.map(|row| {
+            Ok(MaintenanceRun {
+                started_at: row.try_get("started_at")?,
+                duration_ms: row.try_get("duration_ms")?,
+                vacuum: row.try_get("vacuum")?,
+                freed_pages: row.try_get("freed_pages")?,
+                fts_tables_optimized: row.try_get("fts_tables_optimized")?,
+            })
+        })
+        .transpose()
+    }
+
+    /// File sizes, page usage and row counts of the database at `path`
+    pub async fn stats(&self, path: &str) -> Result<DatabaseStats, sqlx::Error> {
+        let file_size = |path: &str| std::fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+
+        let names: Vec<String> = sqlx::query(
+            "SELECT name FROM sqlite_master
+            WHERE type = 'table' AND name NOT LIKE 'sqlite_%'
+                AND sql NOT LIKE 'CREATE VIRTUAL TABLE%'
+            ORDER BY name",
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(|row| row.try_get("name"))
+        .collect::<Result<_, _>>()?;
+
+        let mut tables = Vec::with_capacity(names.len());
+        for name in names {
+            let rows: i64 = sqlx::query(&format!(
+                "SELECT COUNT(*) AS n FROM {}",
+                quote_identifier(&name)
+            ))
+            .fetch_one(&self.pool)
+            .await?
+            .try_get("n")?;
+            tables.push(TableStats { name, rows });
+        }
+
+        Ok(DatabaseStats {
+            file_bytes: file_size(path),
+            wal_bytes: file_size(&format!("{}-wal", path)),
+            page_size: self.pragma("page_size").await?,
+            page_count: self.pragma("page_count").await?,
+            freelist_count: self.pragma("freelist_count").await?,
+            tables,
+            last_maintenance: self.last_run().await?,
+        })
+    }
+
+    async fn pragma(&self, name: &str) -> Result<i64, sqlx::Error> {
+        sqlx::query(&format!("PRAGMA {}", name))
+            .fetch_one(&self.pool)
+            .await?
+            .try_get(0)
+    }
+}
+
+fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
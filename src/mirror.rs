@@ -0,0 +1,1033 @@
+use crate::database::Database;
+use crate::events::{OrderEvent, OrderEventKind};
+use crate::jobs::{Job, JobHandler, JobQueue};
+use crate::order::{Order, OrderItem, RecipientAddress};
+use crate::redact;
+use crate::transport::{HttpRequest, HttpResponse, HttpTransport};
+use async_trait::async_trait;
+use base64::prelude::*;
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::sqlite::{SqlitePool, SqliteRow};
+use sqlx::Row;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::{error, info, warn};
+use utoipa::ToSchema;
+
+/// Create or update an order's mirror on one platform
+pub const MIRROR_JOB_KIND: &str = "order_mirror";
+
+/// Shopify Admin API version used unless `SHOPIFY_API_VERSION` is set
+pub const DEFAULT_SHOPIFY_API_VERSION: &str = "2024-10";
+
+/// Statuses in which an order isn't mirrored yet: unpaid, or cancelled
+/// before it ever was
+const UNMIRRORED_STATUSES: &[&str] = &["UNPAID", "CANCELLED"];
+
+/// How far before a reservation a retry looks for the mirror a failed
+/// attempt may have created, for clocks that disagree with the platform's
+const LOOKUP_SLACK_SECS: i64 = 300;
+
+/// A store TikTok orders are copied into
+#[derive(Clone)]
+pub enum MirrorTarget {
+    /// WooCommerce REST API (`/wp-json/wc/v3`), authenticated with a consumer key pair
+    WooCommerce {
+        url: String,
+        consumer_key: String,
+        consumer_secret: String,
+    },
+    /// Shopify Admin REST API, authenticated with a custom app's access token
+    Shopify {
+        url: String,
+        access_token: String,
+        api_version: String,
+    },
+}
+
+impl std::fmt::Debug for MirrorTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MirrorTarget::WooCommerce {
+                url, consumer_key, ..
+            } => f
+                .debug_struct("WooCommerce")
+                .field("url", url)
+                .field("consumer_key", &redact::mask(consumer_key))
+                .finish_non_exhaustive(),
+            MirrorTarget::Shopify {
+                url, api_version, ..
+            } => f
+                .debug_struct("Shopify")
+                .field("url", url)
+                .field("api_version", api_version)
+                .finish_non_exhaustive(),
+        }
+    }
+}
+
+impl MirrorTarget {
+    /// Name stored in the mapping table and job payloads
+    pub fn platform(&self) -> &'static str {
+        match self {
+            MirrorTarget::WooCommerce { .. } => "woocommerce",
+            MirrorTarget::Shopify { .. } => "shopify",
+        }
+    }
+}
+
+/// Key identifying one TikTok order's mirror on every platform. Stored on
+/// the mirrored order (WooCommerce `_idempotency_key` meta, Shopify
+/// `idempotency_key` note attribute), where a retry looks for it; neither
+/// platform honors the `Idempotency-Key` header it is also sent as.
+pub fn idempotency_key(order_id: &str) -> String {
+    format!("tiktok-{}", order_id)
+}
+
+/// A TikTok order and its copy on another platform
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct OrderMirror {
+    pub order_id: String,
+    /// `woocommerce` or `shopify`
+    pub platform: String,
+    /// The order's id on the platform
+    pub platform_order_id: String,
+    /// TikTok status the mirror was last brought up to date with
+    pub mirrored_status: String,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+/// TikTok order -> platform order mapping, in the `order_mirrors` table
+#[derive(Clone)]
+pub struct MirrorStore {
+    pool: SqlitePool,
+}
+
+impl MirrorStore {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Initialize the order_mirrors table
+    pub async fn init(&self) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS order_mirrors (
+                order_id TEXT NOT NULL,
+                platform TEXT NOT NULL,
+                platform_order_id TEXT NOT NULL,
+                mirrored_status TEXT NOT NULL,
+                created_at INTEGER NOT NULL,
+                updated_at INTEGER NOT NULL,
+                PRIMARY KEY (order_id, platform),
+                UNIQUE (platform, platform_order_id)
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS mirror_reservations (
+                order_id TEXT NOT NULL,
+                platform TEXT NOT NULL,
+                reserved_at INTEGER NOT NULL,
+                PRIMARY KEY (order_id, platform)
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Note that the mirror of an order is about to be created, so a retry
+    /// knows an earlier attempt may have created it. Returns when the first
+    /// attempt reserved it.
+    pub async fn reserve(&self, order_id: &str, platform: &str) -> Result<i64, sqlx::Error> {
+        sqlx::query(
+            "INSERT INTO mirror_reservations (order_id, platform, reserved_at)
+            VALUES (?1, ?2, ?3)
+            ON CONFLICT(order_id, platform) DO NOTHING",
+        )
+        .bind(order_id)
+        .bind(platform)
+        .bind(chrono::Utc::now().timestamp())
+        .execute(&self.pool)
+        .await?;
+        sqlx::query_scalar(
+            "SELECT reserved_at FROM mirror_reservations WHERE order_id = ?1 AND platform = ?2",
+        )
+        .bind(order_id)
+        .bind(platform)
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    /// When an attempt to create the mirror of an order started, if one has
+    /// without the mirror being recorded
+    pub async fn reservation(
+        &self,
+        order_id: &str,
+        platform: &str,
+    ) -> Result<Option<i64>, sqlx::Error> {
+        sqlx::query_scalar(
+            "SELECT reserved_at FROM mirror_reservations WHERE order_id = ?1 AND platform = ?2",
+        )
+        .bind(order_id)
+        .bind(platform)
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    pub async fn get(
+        &self,
+        order_id: &str,
+        platform: &str,
+    ) -> Result<Option<OrderMirror>, sqlx::Error> {
+        sqlx::query(
+            "SELECT order_id, platform, platform_order_id, mirrored_status, created_at, updated_at
+            FROM order_mirrors WHERE order_id = ?1 AND platform = ?2",
+        )
+        .bind(order_id)
+        .bind(platform)
+        .fetch_optional(&self.pool)
+        .await?
+        .as_ref()
+        .map(mirror_from_row)
+        .transpose()
+    }
+
+    /// Mirrors of an order, one per platform
+    pub async fn list(&self, order_id: &str) -> Result<Vec<OrderMirror>, sqlx::Error> {
+        sqlx::query(
+            "SELECT order_id, platform, platform_order_id, mirrored_status, created_at, updated_at
+            FROM order_mirrors WHERE order_id = ?1 ORDER BY platform",
+        )
+        .bind(order_id)
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(mirror_from_row)
+        .collect()
+    }
+
+    /// Record the mirror of an order and the status it was created or
+    /// updated with, releasing its reservation
+    pub async fn record(
+        &self,
+        order_id: &str,
+        platform: &str,
+        platform_order_id: &str,
+        mirrored_status: &str,
+    ) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(
+            "INSERT INTO order_mirrors (order_id, platform, platform_order_id, mirrored_status,
+                created_at, updated_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?5)
+            ON CONFLICT(order_id, platform) DO UPDATE SET
+                platform_order_id = excluded.platform_order_id,
+                mirrored_status = excluded.mirrored_status,
+                updated_at = excluded.updated_at",
+        )
+        .bind(order_id)
+        .bind(platform)
+        .bind(platform_order_id)
+        .bind(mirrored_status)
+        .bind(chrono::Utc::now().timestamp())
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query("DELETE FROM mirror_reservations WHERE order_id = ?1 AND platform = ?2")
+            .bind(order_id)
+            .bind(platform)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+}
+
+fn mirror_from_row(row: &SqliteRow) -> Result<OrderMirror, sqlx::Error> {
+    Ok(OrderMirror {
+        order_id: row.try_get("order_id")?,
+        platform: row.try_get("platform")?,
+        platform_order_id: row.try_get("platform_order_id")?,
+        mirrored_status: row.try_get("mirrored_status")?,
+        created_at: row.try_get("created_at")?,
+        updated_at: row.try_get("updated_at")?,
+    })
+}
+
+/// Payload of an `order_mirror` job
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MirrorJobPayload {
+    pub order_id: String,
+    pub platform: String,
+}
+
+/// Queues a mirror job per target for every created or updated order
+pub struct MirrorPublisher {
+    targets: Vec<MirrorTarget>,
+    job_queue: JobQueue,
+    max_attempts: u32,
+}
+
+impl MirrorPublisher {
+    pub fn new(targets: Vec<MirrorTarget>, job_queue: JobQueue, max_attempts: u32) -> Self {
+        Self {
+            targets,
+            job_queue,
+            max_attempts,
+        }
+    }
+
+    /// Follow `events` until the bus is dropped
+    pub async fn run(self, mut events: broadcast::Receiver<OrderEvent>) {
+        loop {
+            match events.recv().await {
+                Ok(event) => {
+                    if !matches!(
+                        event.kind,
+                        OrderEventKind::Created | OrderEventKind::Updated
+                    ) {
+                        continue;
+                    }
+                    if let Err(e) = self.enqueue(&event).await {
+                        error!(
+                            "Failed to queue mirroring of order {}: {}",
+                            event.order_id, e
+                        );
+                    }
+                }
+                Err(RecvError::Lagged(missed)) => {
+                    warn!("Order mirror publisher missed {} order events", missed);
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    }
+
+    /// Queue mirroring the order `event` is about, once per target and status
+    pub async fn enqueue(&self, event: &OrderEvent) -> Result<usize, sqlx::Error> {
+        let mut queued = 0;
+        for target in &self.targets {
+            let payload = serde_json::to_value(MirrorJobPayload {
+                order_id: event.order_id.clone(),
+                platform: target.platform().to_string(),
+            })
+            .unwrap_or_default();
+            let dedup_key = format!(
+                "{}:{}:{}:{}",
+                MIRROR_JOB_KIND,
+                target.platform(),
+                event.order_id,
+                event.status
+            );
+            if self
+                .job_queue
+                .enqueue(
+                    MIRROR_JOB_KIND,
+                    Some(&dedup_key),
+                    &payload,
+                    self.max_attempts,
+                )
+                .await?
+            {
+                queued += 1;
+            }
+        }
+        Ok(queued)
+    }
+}
+
+/// WooCommerce order status for a TikTok status
+pub fn woocommerce_status(status: &str) -> &'static str {
+    match status {
+        "UNPAID" => "pending",
+        "ON_HOLD" => "on-hold",
+        "CANCELLED" => "cancelled",
+        "IN_TRANSIT" | "DELIVERED" | "COMPLETED" => "completed",
+        _ => "processing",
+    }
+}
+
+/// Buyer name, split the way both platforms want it
+fn names(address: Option<&RecipientAddress>) -> (String, String) {
+    let Some(address) = address else {
+        return (String::new(), String::new());
+    };
+    match (&address.first_name, &address.last_name) {
+        (Some(first), last) if !first.is_empty() => {
+            (first.clone(), last.clone().unwrap_or_default())
+        }
+        _ => (address.name.clone().unwrap_or_default(), String::new()),
+    }
+}
+
+/// `(city, state)` from TikTok's district levels: L2 is the district or
+/// city, L1 the province or state
+fn locality(address: Option<&RecipientAddress>) -> (String, String) {
+    let level = |code: &str| {
+        address
+            .and_then(|a| {
+                a.district_info
+                    .iter()
+                    .find(|d| d.address_level.eq_ignore_ascii_case(code))
+            })
+            .map(|d| d.address_name.clone())
+            .unwrap_or_default()
+    };
+    (level("L2"), level("L1"))
+}
+
+fn address_lines(address: Option<&RecipientAddress>) -> (String, String) {
+    let Some(address) = address else {
+        return (String::new(), String::new());
+    };
+    match &address.address_line1 {
+        Some(line1) if !line1.is_empty() => (
+            line1.clone(),
+            address.address_line2.clone().unwrap_or_default(),
+        ),
+        _ => (
+            address
+                .address_detail
+                .clone()
+                .or_else(|| address.full_address.clone())
+                .unwrap_or_default(),
+            String::new(),
+        ),
+    }
+}
+
+/// Line items that weren't cancelled, summed per SKU:
+/// `(sku, name, variant, quantity, unit price)`
+fn items(order: &Order) -> Vec<(String, String, Option<String>, i64, String)> {
+    let mut by_sku: BTreeMap<String, (String, String, Option<String>, i64, String)> =
+        BTreeMap::new();
+    for item in order
+        .item_list
+        .iter()
+        .filter(|item| item.display_status.as_deref() != Some("CANCELLED"))
+    {
+        let sku = sku_of(item);
+        by_sku
+            .entry(sku.clone())
+            .or_insert_with(|| {
+                (
+                    sku,
+                    item.product_name.clone(),
+                    item.sku_name.clone(),
+                    0,
+                    item.sale_price.clone(),
+                )
+            })
+            .3 += i64::from(item.quantity.unwrap_or(1));
+    }
+    by_sku.into_values().collect()
+}
+
+fn sku_of(item: &OrderItem) -> String {
+    item.seller_sku
+        .clone()
+        .filter(|sku| !sku.trim().is_empty())
+        .unwrap_or_else(|| item.sku_id.clone())
+}
+
+/// Line total in the item's currency, as a decimal string
+fn line_total(unit_price: &str, quantity: i64) -> String {
+    let unit: f64 = unit_price.trim().parse().unwrap_or_default();
+    format!("{:.2}", unit * quantity as f64)
+}
+
+fn shipping_title(order: &Order) -> String {
+    order
+        .shipping_provider
+        .clone()
+        .unwrap_or_else(|| "TikTok Shop".to_string())
+}
+
+/// Body of `POST /wp-json/wc/v3/orders` for `order`, which has been paid
+pub fn woocommerce_order(order: &Order) -> serde_json::Value {
+    let address = order.recipient_address.as_ref();
+    let (first_name, last_name) = names(address);
+    let (address_1, address_2) = address_lines(address);
+    let (city, state) = locality(address);
+    let contact = json!({
+        "first_name": first_name,
+        "last_name": last_name,
+        "address_1": address_1,
+        "address_2": address_2,
+        "city": city,
+        "state": state,
+        "postcode": address.and_then(|a| a.postal_code.clone()).unwrap_or_default(),
+        "country": address.and_then(|a| a.region_code.clone()).unwrap_or_default(),
+        "phone": address.and_then(|a| a.phone.clone()).unwrap_or_default(),
+    });
+    let mut billing = contact.clone();
+    billing["email"] = json!(order.buyer_email.clone().unwrap_or_default());
+
+    json!({
+        "status": woocommerce_status(&order.status),
+        "currency": order.payment.as_ref().map(|p| p.currency.clone()),
+        "set_paid": true,
+        "customer_note": order.buyer_message.clone().unwrap_or_default(),
+        "billing": billing,
+        "shipping": contact,
+        "line_items": items(order)
+            .into_iter()
+            .map(|(sku, name, variant, quantity, price)| json!({
+                "name": match variant {
+                    Some(variant) if !variant.is_empty() => format!("{} - {}", name, variant),
+                    _ => name,
+                },
+                "quantity": quantity,
+                "total": line_total(&price, quantity),
+                "meta_data": [{"key": "seller_sku", "value": sku}],
+            }))
+            .collect::<Vec<_>>(),
+        "shipping_lines": [{
+            "method_id": "tiktok_shop",
+            "method_title": shipping_title(order),
+            "total": order.payment.as_ref().map(|p| p.shipping_fee.clone()).unwrap_or_default(),
+        }],
+        "meta_data": [
+            {"key": "_tiktok_order_id", "value": order.id},
+            {"key": "_idempotency_key", "value": idempotency_key(&order.id)},
+        ],
+    })
+}
+
+/// Body of `POST /admin/api/{version}/orders.json` for `order`, which has
+/// been paid. Receipts and inventory changes are left to TikTok.
+pub fn shopify_order(order: &Order) -> serde_json::Value {
+    let address = order.recipient_address.as_ref();
+    let (first_name, last_name) = names(address);
+    let (address1, address2) = address_lines(address);
+    let (city, province) = locality(address);
+
+    json!({
+        "order": {
+            "source_name": "tiktok",
+            "source_identifier": order.id,
+            "tags": "tiktok",
+            "currency": order.payment.as_ref().map(|p| p.currency.clone()),
+            "financial_status": "paid",
+            "email": order.buyer_email,
+            "phone": address.and_then(|a| a.phone.clone()),
+            "note": order.buyer_message,
+            "note_attributes": [
+                {"name": "tiktok_order_id", "value": order.id},
+                {"name": "idempotency_key", "value": idempotency_key(&order.id)},
+            ],
+            "send_receipt": false,
+            "send_fulfillment_receipt": false,
+            "inventory_behaviour": "bypass",
+            "shipping_address": {
+                "first_name": first_name,
+                "last_name": last_name,
+                "address1": address1,
+                "address2": address2,
+                "city": city,
+                "province": province,
+                "zip": address.and_then(|a| a.postal_code.clone()),
+                "country_code": address.and_then(|a| a.region_code.clone()),
+                "phone": address.and_then(|a| a.phone.clone()),
+            },
+            "line_items": items(order)
+                .into_iter()
+                .map(|(sku, name, variant, quantity, price)| json!({
+                    "title": name,
+                    "variant_title": variant,
+                    "sku": sku,
+                    "quantity": quantity,
+                    "price": price,
+                }))
+                .collect::<Vec<_>>(),
+            "shipping_lines": [{
+                "title": shipping_title(order),
+                "price": order.payment.as_ref().map(|p| p.shipping_fee.clone()).unwrap_or_default(),
+            }],
+        }
+    })
+}
+
+#[derive(Debug, Deserialize)]
+struct WooCommerceOrder {
+    id: serde_json::Value,
+}
+
+#[derive(Debug, Deserialize)]
+struct ShopifyOrderEnvelope {
+    order: WooCommerceOrder,
+}
+
+/// Key/value pair of WooCommerce `meta_data`
+#[derive(Debug, Deserialize)]
+struct WooCommerceMeta {
+    key: String,
+    value: serde_json::Value,
+}
+
+#[derive(Debug, Deserialize)]
+struct WooCommerceListedOrder {
+    id: serde_json::Value,
+    #[serde(default)]
+    meta_data: Vec<WooCommerceMeta>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ShopifyNoteAttribute {
+    name: String,
+    value: serde_json::Value,
+}
+
+#[derive(Debug, Deserialize)]
+struct ShopifyListedOrder {
+    id: serde_json::Value,
+    source_identifier: Option<String>,
+    #[serde(default)]
+    note_attributes: Vec<ShopifyNoteAttribute>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ShopifyOrderList {
+    orders: Vec<ShopifyListedOrder>,
+}
+
+/// Id as a string, whether the platform sent a number or a string
+fn id_string(id: &serde_json::Value) -> String {
+    match id {
+        serde_json::Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Creates and updates mirrored orders over a platform's REST API
+pub struct MirrorClient {
+    target: MirrorTarget,
+    transport: Arc<dyn HttpTransport>,
+}
+
+impl MirrorClient {
+    pub fn new(target: MirrorTarget, transport: Arc<dyn HttpTransport>) -> Self {
+        Self { target, transport }
+    }
+
+    pub fn platform(&self) -> &'static str {
+        self.target.platform()
+    }
+
+    fn request(&self, method: Method, path: &str, body: &serde_json::Value) -> HttpRequest {
+        self.base_request(method, path)
+            .with_header("Content-Type", "application/json")
+            .with_body(body.to_string())
+    }
+
+    fn base_request(&self, method: Method, path: &str) -> HttpRequest {
+        let request = match &self.target {
+            MirrorTarget::WooCommerce {
+                url,
+                consumer_key,
+                consumer_secret,
+            } => {
+                let credentials =
+                    BASE64_STANDARD.encode(format!("{}:{}", consumer_key, consumer_secret));
+                HttpRequest::new(
+                    method,
+                    format!("{}/wp-json/wc/v3{}", url.trim_end_matches('/'), path),
+                )
+                .with_header("Authorization", &format!("Basic {}", credentials))
+            }
+            MirrorTarget::Shopify {
+                url,
+                access_token,
+                api_version,
+            } => HttpRequest::new(
+                method,
+                format!(
+                    "{}/admin/api/{}{}",
+                    url.trim_end_matches('/'),
+                    api_version,
+                    path
+                ),
+            )
+            .with_header("X-Shopify-Access-Token", access_token),
+        };
+        request
+    }
+
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse, String> {
+        let url = request.url.clone();
+        let response = self
+            .transport
+            .send(request)
+            .await
+            .map_err(|e| format!("{}: {}", url, e))?;
+        if !response.status.is_success() {
+            return Err(format!(
+                "{} answered HTTP {}: {}",
+                url, response.status, response.body
+            ));
+        }
+        Ok(response)
+    }
+
+    /// Create the mirror of `order`; returns its id on the platform
+    pub async fn create(&self, order: &Order) -> Result<String, String> {
+        let (path, body) = match &self.target {
+            MirrorTarget::WooCommerce { .. } => ("/orders", woocommerce_order(order)),
+            MirrorTarget::Shopify { .. } => ("/orders.json", shopify_order(order)),
+        };
+        let request = self
+            .request(Method::POST, path, &body)
+            .with_header("Idempotency-Key", &idempotency_key(&order.id));
+        let response = self.send(request).await?;
+
+        let id = match &self.target {
+            MirrorTarget::WooCommerce { .. } => {
+                serde_json::from_str::<WooCommerceOrder>(&response.body).map(|o| o.id)
+            }
+            MirrorTarget::Shopify { .. } => {
+                serde_json::from_str::<ShopifyOrderEnvelope>(&response.body).map(|o| o.order.id)
+            }
+        }
+        .map_err(|e| format!("{} response has no order id: {}", self.platform(), e))?;
+        Ok(id_string(&id))
+    }
+
+    /// Id of the mirror of `order` if one was created at or after `since`
+    /// (unix seconds), found by the idempotency key stored on it. The
+    /// platforms can't filter on the key, so orders created since are
+    /// listed and checked; a retry of a failed create looks here first.
+    pub async fn find(&self, order: &Order, since: i64) -> Result<Option<String>, String> {
+        let key = idempotency_key(&order.id);
+        let since = chrono::DateTime::from_timestamp(since - LOOKUP_SLACK_SECS, 0)
+            .unwrap_or_default()
+            .format("%Y-%m-%dT%H:%M:%SZ")
+            .to_string();
+        let (path, query) = match &self.target {
+            MirrorTarget::WooCommerce { .. } => {
+                ("/orders", vec![("after", since), ("per_page", "100".to_string())])
+            }
+            MirrorTarget::Shopify { .. } => (
+                "/orders.json",
+                vec![
+                    ("status", "any".to_string()),
+                    ("created_at_min", since),
+                    ("limit", "250".to_string()),
+                ],
+            ),
+        };
+        let mut request = self.base_request(Method::GET, path);
+        request
+            .query
+            .extend(query.into_iter().map(|(k, v)| (k.to_string(), v)));
+        let response = self.send(request).await?;
+
+        let found = match &self.target {
+            MirrorTarget::WooCommerce { .. } => {
+                serde_json::from_str::<Vec<WooCommerceListedOrder>>(&response.body).map(|orders| {
+                    orders
+                        .into_iter()
+                        .find(|o| {
+                            o.meta_data
+                                .iter()
+                                .any(|m| m.key == "_idempotency_key" && m.value == key.as_str())
+                        })
+                        .map(|o| o.id)
+                })
+            }
+            MirrorTarget::Shopify { .. } => {
+                serde_json::from_str::<ShopifyOrderList>(&response.body).map(|list| {
+                    list.orders
+                        .into_iter()
+                        .find(|o| {
+                            o.source_identifier.as_deref() == Some(order.id.as_str())
+                                || o.note_attributes.iter().any(|a| {
+                                    a.name == "idempotency_key" && a.value == key.as_str()
+                                })
+                        })
+                        .map(|o| o.id)
+                })
+            }
+        }
+        .map_err(|e| format!("{} order list can't be read: {}", self.platform(), e))?;
+        Ok(found.as_ref().map(id_string))
+    }
+
+    /// Bring an existing mirror up to `order`'s status. WooCommerce takes the
+    /// mapped status; Shopify orders are only cancelled, since their other
+    /// states follow fulfillments made in Shopify.
+    pub async fn update_status(
+        &self,
+        platform_order_id: &str,
+        order: &Order,
+    ) -> Result<(), String> {
+        let request = match &self.target {
+            MirrorTarget::WooCommerce { .. } => self.request(
+                Method::PUT,
+                &format!("/orders/{}", platform_order_id),
+                &json!({ "status": woocommerce_status(&order.status) }),
+            ),
+            MirrorTarget::Shopify { .. } if order.status == "CANCELLED" => self.request(
+                Method::POST,
+                &format!("/orders/{}/cancel.json", platform_order_id),
+                &json!({ "email": false }),
+            ),
+            MirrorTarget::Shopify { .. } => return Ok(()),
+        };
+        self.send(request).await.map(|_| ())
+    }
+}
+
+/// Creates the mirror of an order on its first paid sync and keeps its status
+/// in step afterwards
+pub struct MirrorHandler {
+    clients: Vec<MirrorClient>,
+    store: MirrorStore,
+    db: Arc<Database>,
+}
+
+impl MirrorHandler {
+    pub fn new(clients: Vec<MirrorClient>, store: MirrorStore, db: Arc<Database>) -> Self {
+        Self { clients, store, db }
+    }
+}
+
+#[async_trait]
+impl JobHandler for MirrorHandler {
+    async fn handle(&self, job: &Job) -> Result<(), String> {
+        let payload: MirrorJobPayload = serde_json::from_value(job.payload.clone())
+            .map_err(|e| format!("Invalid order mirror payload: {}", e))?;
+        let Some(client) = self
+            .clients
+            .iter()
+            .find(|c| c.platform() == payload.platform)
+        else {
+            warn!(
+                "Dropping mirror job {} for order {}: {} is no longer configured",
+                job.id, payload.order_id, payload.platform
+            );
+            return Ok(());
+        };
+        // Read the order as it is now, which may be past the event that queued the job
+        let Some(order) = self
+            .db
+            .get_order_by_id(&payload.order_id)
+            .await
+            .map_err(|e| e.to_string())?
+        else {
+            return Ok(());
+        };
+        // The copy ships to the buyer, so it carries their address
+        let order = self.db.reveal_pii(order);
+
+        match self
+            .store
+            .get(&order.id, client.platform())
+            .await
+            .map_err(|e| e.to_string())?
+        {
+            Some(mirror) if mirror.mirrored_status == order.status => {}
+            Some(mirror) => {
+                client
+                    .update_status(&mirror.platform_order_id, &order)
+                    .await?;
+                self.store
+                    .record(
+                        &order.id,
+                        client.platform(),
+                        &mirror.platform_order_id,
+                        &order.status,
+                    )
+                    .await
+                    .map_err(|e| e.to_string())?;
+                info!(
+                    "Updated {} mirror {} of order {} to {}",
+                    client.platform(),
+                    mirror.platform_order_id,
+                    order.id,
+                    order.status
+                );
+            }
+            None if UNMIRRORED_STATUSES.contains(&order.status.as_str()) => {}
+            None => {
+                // A failed earlier attempt may have created the mirror
+                // before its response or the mapping got lost
+                let reserved_at = self
+                    .store
+                    .reservation(&order.id, client.platform())
+                    .await
+                    .map_err(|e| e.to_string())?;
+                let existing = match reserved_at {
+                    Some(since) => client.find(&order, since).await?,
+                    None => None,
+                };
+                let platform_order_id = match existing {
+                    Some(id) => {
+                        info!(
+                            "Found the {} mirror {} of order {} from an earlier attempt",
+                            client.platform(),
+                            id,
+                            order.id
+                        );
+                        id
+                    }
+                    None => {
+                        self.store
+                            .reserve(&order.id, client.platform())
+                            .await
+                            .map_err(|e| e.to_string())?;
+                        client.create(&order).await?
+                    }
+                };
+                self.store
+                    .record(
+                        &order.id,
+                        client.platform(),
+                        &platform_order_id,
+                        &order.status,
+                    )
+                    .await
+                    .map_err(|e| e.to_string())?;
+                info!(
+                    "Mirrored order {} to {} as {}",
+                    order.id,
+                    client.platform(),
+                    platform_order_id
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::SqliteSettings;
+    use crate::error::AppError;
+    use crate::transport::MockTransport;
+
+    const ORDER_ID: &str = "576461413038785752";
+
+    fn woocommerce() -> MirrorTarget {
+        MirrorTarget::WooCommerce {
+            url: "https://shop.example".to_string(),
+            consumer_key: "ck_test".to_string(),
+            consumer_secret: "cs_test".to_string(),
+        }
+    }
+
+    fn shopify() -> MirrorTarget {
+        MirrorTarget::Shopify {
+            url: "https://shop.myshopify.com".to_string(),
+            access_token: "shpat_test".to_string(),
+            api_version: DEFAULT_SHOPIFY_API_VERSION.to_string(),
+        }
+    }
+
+    /// Handler mirroring to `target` over `transport`, with one paid order stored
+    async fn handler(target: MirrorTarget, transport: Arc<MockTransport>) -> MirrorHandler {
+        // One connection, as each would open its own in-memory database
+        let settings = SqliteSettings {
+            max_connections: 1,
+            ..SqliteSettings::default()
+        };
+        let db = Database::open(":memory:", settings).await.unwrap();
+        db.init().await.unwrap();
+        let order: Order = serde_json::from_value(json!({
+            "id": ORDER_ID,
+            "status": "AWAITING_SHIPMENT",
+            "create_time": 1_700_000_000,
+            "update_time": 1_700_000_000
+        }))
+        .unwrap();
+        db.upsert_orders(&[order]).await.unwrap();
+        let store = MirrorStore::new(db.pool().clone());
+        store.init().await.unwrap();
+
+        MirrorHandler::new(
+            vec![MirrorClient::new(target, transport)],
+            store,
+            Arc::new(db),
+        )
+    }
+
+    fn job(platform: &str) -> Job {
+        Job {
+            id: 1,
+            kind: MIRROR_JOB_KIND.to_string(),
+            payload: json!({ "order_id": ORDER_ID, "platform": platform }),
+            status: "running".to_string(),
+            attempts: 1,
+            max_attempts: 5,
+            last_error: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn a_retry_finds_the_woocommerce_order_a_lost_response_created() {
+        let transport = Arc::new(MockTransport::new());
+        let handler = handler(woocommerce(), transport.clone()).await;
+
+        // WooCommerce created the order, but the response never arrived
+        transport.push_error(AppError::transport("operation timed out"));
+        assert!(handler.handle(&job("woocommerce")).await.is_err());
+        assert!(handler.store.get(ORDER_ID, "woocommerce").await.unwrap().is_none());
+
+        transport.push_json(json!([
+            {"id": 41, "meta_data": [{"id": 1, "key": "_idempotency_key", "value": "tiktok-1"}]},
+            {"id": 42, "meta_data": [
+                {"id": 2, "key": "_tiktok_order_id", "value": ORDER_ID},
+                {"id": 3, "key": "_idempotency_key", "value": idempotency_key(ORDER_ID)}
+            ]}
+        ]));
+        handler.handle(&job("woocommerce")).await.unwrap();
+
+        let mirror = handler.store.get(ORDER_ID, "woocommerce").await.unwrap().unwrap();
+        assert_eq!(mirror.platform_order_id, "42");
+        assert_eq!(mirror.mirrored_status, "AWAITING_SHIPMENT");
+        assert_eq!(handler.store.reservation(ORDER_ID, "woocommerce").await.unwrap(), None);
+
+        let requests = transport.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].method, Method::POST);
+        assert_eq!(requests[1].method, Method::GET);
+        assert_eq!(requests[1].url, "https://shop.example/wp-json/wc/v3/orders");
+        assert!(requests[1].query_param("after").is_some());
+        assert_eq!(requests[1].body, None);
+    }
+
+    #[tokio::test]
+    async fn a_retry_creates_the_shopify_order_when_none_was_created() {
+        let transport = Arc::new(MockTransport::new());
+        let handler = handler(shopify(), transport.clone()).await;
+
+        transport.push_error(AppError::transport("connection reset"));
+        assert!(handler.handle(&job("shopify")).await.is_err());
+
+        transport.push_json(json!({"orders": [
+            {"id": 1001, "source_identifier": "576461413038785000", "note_attributes": []}
+        ]}));
+        transport.push_json(json!({"order": {"id": 1002}}));
+        handler.handle(&job("shopify")).await.unwrap();
+
+        let mirror = handler.store.get(ORDER_ID, "shopify").await.unwrap().unwrap();
+        assert_eq!(mirror.platform_order_id, "1002");
+
+        let requests = transport.requests();
+        assert_eq!(requests.len(), 3);
+        let lookup = &requests[1];
+        assert_eq!(
+            lookup.url,
+            "https://shop.myshopify.com/admin/api/2024-10/orders.json"
+        );
+        assert_eq!(lookup.query_param("status"), Some("any"));
+        assert!(lookup.query_param("created_at_min").is_some());
+        assert_eq!(lookup.header("X-Shopify-Access-Token"), Some("shpat_test"));
+        assert_eq!(requests[2].method, Method::POST);
+    }
+}
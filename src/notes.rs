@@ -0,0 +1,100 @@
+use serde::Serialize;
+use sqlx::sqlite::{SqlitePool, SqliteRow};
+use sqlx::Row;
+use utoipa::ToSchema;
+
+/// Longest note accepted, in characters
+pub const MAX_NOTE_CHARS: usize = 4000;
+
+/// A free-text note left on an order by staff
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct OrderNote {
+    pub id: i64,
+    pub order_id: String,
+    pub body: String,
+    pub author: Option<String>,
+    pub created_at: i64,
+}
+
+/// Staff notes on orders, in the `order_notes` table. Notes are append-only.
+#[derive(Clone)]
+pub struct NoteStore {
+    pool: SqlitePool,
+}
+
+impl NoteStore {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Initialize the order_notes table
+    pub async fn init(&self) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS order_notes (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                order_id TEXT NOT NULL,
+                body TEXT NOT NULL,
+                author TEXT,
+                created_at INTEGER NOT NULL
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_order_notes_order ON order_notes (order_id)")
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn add(
+        &self,
+        order_id: &str,
+        body: &str,
+        author: Option<&str>,
+    ) -> Result<OrderNote, sqlx::Error> {
+        let created_at = chrono::Utc::now().timestamp();
+        let result = sqlx::query(
+            "INSERT INTO order_notes (order_id, body, author, created_at) VALUES (?1, ?2, ?3, ?4)",
+        )
+        .bind(order_id)
+        .bind(body)
+        .bind(author)
+        .bind(created_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(OrderNote {
+            id: result.last_insert_rowid(),
+            order_id: order_id.to_string(),
+            body: body.to_string(),
+            author: author.map(str::to_string),
+            created_at,
+        })
+    }
+
+    /// Notes on an order, oldest first
+    pub async fn list(&self, order_id: &str) -> Result<Vec<OrderNote>, sqlx::Error> {
+        sqlx::query(
+            "SELECT id, order_id, body, author, created_at FROM order_notes
+            WHERE order_id = ?1 ORDER BY id",
+        )
+        .bind(order_id)
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(note_from_row)
+        .collect()
+    }
+}
+
+fn note_from_row(row: &SqliteRow) -> Result<OrderNote, sqlx::Error> {
+    Ok(OrderNote {
+        id: row.try_get("id")?,
+        order_id: row.try_get("order_id")?,
+        body: row.try_get("body")?,
+        author: row.try_get("author")?,
+        created_at: row.try_get("created_at")?,
+    })
+}
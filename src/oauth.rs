@@ -1,7 +1,9 @@
 use crate::error::AppError;
-use reqwest::Client;
+use crate::redact;
+use crate::transport::{HttpRequest, HttpTransport, ReqwestTransport};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::BTreeMap;
+use std::sync::Arc;
 use tracing::{debug, info};
 
 /// TikTok Shop OAuth client
@@ -9,15 +11,47 @@ use tracing::{debug, info};
 pub struct TikTokShopOAuth {
     app_key: String,
     app_secret: String,
-    http_client: Client,
+    transport: Arc<dyn HttpTransport>,
+    log_bodies: bool,
 }
 
 /// Authorization request parameters
 #[derive(Debug, Serialize)]
 pub struct AuthorizationRequest {
     pub app_key: String,
+    /// Opaque value echoed back to the callback, e.g. a nonce plus where to
+    /// return the seller to
     pub state: String,
     pub redirect_uri: String,
+    /// Language of the authorization page, e.g. `en` or `vi-VN`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub locale: Option<String>,
+    /// Service id from Partner Center; apps listed as services authorize on
+    /// the services host instead
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub service_id: Option<String>,
+}
+
+impl AuthorizationRequest {
+    pub fn new(app_key: String, state: String, redirect_uri: String) -> Self {
+        Self {
+            app_key,
+            state,
+            redirect_uri,
+            locale: None,
+            service_id: None,
+        }
+    }
+
+    pub fn with_locale(mut self, locale: impl Into<String>) -> Self {
+        self.locale = Some(locale.into());
+        self
+    }
+
+    pub fn with_service_id(mut self, service_id: impl Into<String>) -> Self {
+        self.service_id = Some(service_id.into());
+        self
+    }
 }
 
 /// OAuth callback parameters
@@ -40,7 +74,9 @@ pub struct TokenResponse {
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct AuthorizedShop {
     pub cipher: String,
+    #[serde(alias = "id")]
     pub shop_id: String,
+    #[serde(alias = "name")]
     pub shop_name: String,
     pub region: String,
 }
@@ -56,106 +92,282 @@ struct ApiResponse<T> {
 impl TikTokShopOAuth {
     const TOKEN_URL: &'static str = "https://auth.tiktok-shops.com/api/v2/token/get";
     const REFRESH_TOKEN_URL: &'static str = "https://auth.tiktok-shops.com/api/v2/token/refresh";
+    const AUTHORIZE_URL: &'static str = "https://auth.tiktok-shops.com/oauth/authorize";
+    const SERVICE_AUTHORIZE_URL: &'static str = "https://services.tiktokshop.com/open/authorize";
 
     pub fn new(app_key: String, app_secret: String) -> Self {
+        Self::with_transport(app_key, app_secret, Arc::new(ReqwestTransport::new()))
+    }
+
+    /// Create an OAuth client that sends requests through a custom transport
+    pub fn with_transport(
+        app_key: String,
+        app_secret: String,
+        transport: Arc<dyn HttpTransport>,
+    ) -> Self {
         Self {
             app_key,
             app_secret,
-            http_client: Client::new(),
+            transport,
+            log_bodies: false,
         }
     }
 
+    /// Log (redacted) token responses at debug level
+    pub fn with_body_logging(mut self, enabled: bool) -> Self {
+        self.log_bodies = enabled;
+        self
+    }
+
+    fn log_response(&self, status: reqwest::StatusCode, body: &str) {
+        if self.log_bodies {
+            debug!("Token response status: {}, body: {}", status, redact::json(body));
+        } else {
+            debug!("Token response status: {}, {} bytes", status, body.len());
+        }
+    }
+
+    /// Authorization request for this app; add a locale or service id with
+    /// the `with_*` methods
+    pub fn authorization_request(&self, state: &str, redirect_uri: &str) -> AuthorizationRequest {
+        AuthorizationRequest::new(self.app_key.clone(), state.to_string(), redirect_uri.to_string())
+    }
+
+    /// URL to send the seller to for authorizing the app. TikTok Shop's token
+    /// endpoint takes no PKCE verifier, so no `code_challenge` is sent; check
+    /// `state` in the callback instead.
+    pub fn get_authorization_url(&self, request: &AuthorizationRequest) -> Result<String, AppError> {
+        let mut params = vec![("state", request.state.as_str())];
+        let base = match &request.service_id {
+            Some(service_id) => {
+                params.push(("service_id", service_id));
+                Self::SERVICE_AUTHORIZE_URL
+            }
+            None => {
+                params.push(("app_key", &request.app_key));
+                Self::AUTHORIZE_URL
+            }
+        };
+        if !request.redirect_uri.is_empty() {
+            params.push(("redirect_uri", &request.redirect_uri));
+        }
+        if let Some(locale) = &request.locale {
+            params.push(("locale", locale));
+        }
+
+        reqwest::Url::parse_with_params(base, &params)
+            .map(String::from)
+            .map_err(|_| AppError::InvalidUrl)
+    }
+
     /// Exchange authorization code for access token
     pub async fn exchange_code_for_token(&self, code: &str) -> Result<TokenResponse, AppError> {
         info!("Exchanging authorization code for access token");
-        info!("Authorization code: {}", code);
-        let mut params = HashMap::new();
-        params.insert("app_key", self.app_key.as_str());
-        params.insert("app_secret", self.app_secret.as_str());
-        params.insert("auth_code", code);
-        params.insert("grant_type", "authorized_code");
-
-        // let url = format!("{} {}", (Self::TOKEN_URL.to_owned() + "?{}"), urlencoding::encode(&params));
-        let response = self
-            .http_client
-            .get(Self::TOKEN_URL)
-            .query(&params)
-            .header("Content-Type", "application/json")
-            .send()
-            .await
-            .map_err(|e| AppError::HttpError(e.to_string()))?;
+        debug!("Authorization code: {}", redact::mask(code));
+        let mut params = BTreeMap::new();
+        params.insert("app_key".to_string(), self.app_key.clone());
+        params.insert("app_secret".to_string(), self.app_secret.clone());
+        params.insert("auth_code".to_string(), code.to_string());
+        params.insert("grant_type".to_string(), "authorized_code".to_string());
 
-        let status = response.status();
-        let body = response
-            .text()
-            .await
-            .map_err(|e| AppError::HttpError(e.to_string()))?;
+        let request = HttpRequest::get(Self::TOKEN_URL)
+            .with_query(&params)
+            .with_header("Content-Type", "application/json");
+
+        let response = self.transport.send(request).await?;
+        let status = response.status;
+        let body = response.body;
 
-        debug!("Token response status: {}, body: {}", status, body);
+        self.log_response(status, &body);
 
         if !status.is_success() {
-            return Err(AppError::TokenExchangeFailed(body));
+            return Err(AppError::TokenExchangeFailed { status, body });
         }
 
         let api_response: ApiResponse<TokenResponse> = serde_json::from_str(&body)
-            .map_err(|e| AppError::ParseError(format!("Failed to parse token response: {}", e)))?;
+            .map_err(|e| AppError::parse("Failed to parse token response", e))?;
 
         if api_response.code != 0 {
-            return Err(AppError::ApiError(
-                api_response.code,
-                api_response.message,
-            ));
+            return Err(AppError::ApiError {
+                code: api_response.code,
+                message: api_response.message,
+                request_id: None,
+            });
         }
 
         api_response
             .data
-            .ok_or_else(|| AppError::ApiError(api_response.code, "No token data in response".to_string()))
+            .ok_or_else(|| AppError::ApiError {
+                code: api_response.code,
+                message: "No token data in response".to_string(),
+                request_id: None,
+            })
     }
 
     /// Refresh access token using refresh token
     pub async fn refresh_access_token(&self, refresh_token: &str) -> Result<TokenResponse, AppError> {
         info!("Refreshing access token");
 
-        let mut params = HashMap::new();
-        params.insert("app_key", self.app_key.as_str());
-        params.insert("app_secret", self.app_secret.as_str());
-        params.insert("refresh_token", refresh_token);
-        params.insert("grant_type", "refresh_token");
-
-        let response = self
-            .http_client
-            .post(Self::REFRESH_TOKEN_URL)
-            .header("Content-Type", "application/x-www-form-urlencoded")
-            .form(&params)
-            .send()
-            .await
-            .map_err(|e| AppError::HttpError(e.to_string()))?;
+        let params = [
+            ("app_key", self.app_key.as_str()),
+            ("app_secret", self.app_secret.as_str()),
+            ("refresh_token", refresh_token),
+            ("grant_type", "refresh_token"),
+        ];
 
-        let status = response.status();
-        let body = response
-            .text()
-            .await
-            .map_err(|e| AppError::HttpError(e.to_string()))?;
+        let request = HttpRequest::post(Self::REFRESH_TOKEN_URL)
+            .with_header("Content-Type", "application/x-www-form-urlencoded")
+            .with_form(params);
+
+        let response = self.transport.send(request).await?;
+        let status = response.status;
+        let body = response.body;
 
-        debug!("Refresh token response status: {}, body: {}", status, body);
+        self.log_response(status, &body);
 
         if !status.is_success() {
-            return Err(AppError::TokenRefreshFailed(body));
+            return Err(AppError::TokenRefreshFailed { status, body });
         }
 
         let api_response: ApiResponse<TokenResponse> = serde_json::from_str(&body)
-            .map_err(|e| AppError::ParseError(format!("Failed to parse refresh response: {}", e)))?;
+            .map_err(|e| AppError::parse("Failed to parse refresh response", e))?;
 
         if api_response.code != 0 {
-            return Err(AppError::ApiError(
-                api_response.code,
-                api_response.message,
-            ));
+            return Err(AppError::ApiError {
+                code: api_response.code,
+                message: api_response.message,
+                request_id: None,
+            });
         }
 
         api_response
             .data
-            .ok_or_else(|| AppError::ApiError(api_response.code, "No token data in response".to_string()))
+            .ok_or_else(|| AppError::ApiError {
+                code: api_response.code,
+                message: "No token data in response".to_string(),
+                request_id: None,
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::{HttpBody, HttpResponse, MockTransport};
+    use reqwest::{Method, StatusCode};
+
+    fn oauth(transport: Arc<MockTransport>) -> TikTokShopOAuth {
+        TikTokShopOAuth::with_transport("testkey".to_string(), "testsecret".to_string(), transport)
+    }
+
+    fn tokens() -> serde_json::Value {
+        serde_json::json!({
+            "code": 0,
+            "message": "success",
+            "data": {
+                "access_token": "ROW_access",
+                "access_token_expire_in": 1_700_604_800,
+                "refresh_token": "ROW_refresh",
+                "refresh_token_expire_in": 1_731_536_000
+            }
+        })
     }
 
+    #[tokio::test]
+    async fn exchanges_the_code_with_a_get_on_the_token_endpoint() {
+        let transport = Arc::new(MockTransport::new());
+        transport.push_json(tokens());
+
+        let token = oauth(transport.clone())
+            .exchange_code_for_token("auth_code_1")
+            .await
+            .unwrap();
+        assert_eq!(token.access_token, "ROW_access");
+        assert_eq!(token.refresh_token_expire_in, 1_731_536_000);
+
+        let request = transport.last_request().unwrap();
+        assert_eq!(request.method, Method::GET);
+        assert_eq!(request.url, "https://auth.tiktok-shops.com/api/v2/token/get");
+        assert_eq!(request.query_param("app_key"), Some("testkey"));
+        assert_eq!(request.query_param("app_secret"), Some("testsecret"));
+        assert_eq!(request.query_param("auth_code"), Some("auth_code_1"));
+        assert_eq!(request.query_param("grant_type"), Some("authorized_code"));
+        assert_eq!(request.body, None);
+    }
+
+    #[tokio::test]
+    async fn refreshes_with_a_form_post() {
+        let transport = Arc::new(MockTransport::new());
+        transport.push_json(tokens());
+
+        let token = oauth(transport.clone())
+            .refresh_access_token("ROW_old_refresh")
+            .await
+            .unwrap();
+        assert_eq!(token.refresh_token, "ROW_refresh");
+
+        let request = transport.last_request().unwrap();
+        assert_eq!(request.method, Method::POST);
+        assert_eq!(request.url, "https://auth.tiktok-shops.com/api/v2/token/refresh");
+        assert!(request.query.is_empty());
+        assert_eq!(
+            request.header("content-type"),
+            Some("application/x-www-form-urlencoded")
+        );
+        assert_eq!(
+            request.body,
+            Some(HttpBody::Form(
+                [
+                    ("app_key", "testkey"),
+                    ("app_secret", "testsecret"),
+                    ("refresh_token", "ROW_old_refresh"),
+                    ("grant_type", "refresh_token"),
+                ]
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()
+            ))
+        );
+    }
+
+    #[tokio::test]
+    async fn maps_token_endpoint_failures() {
+        let transport = Arc::new(MockTransport::new());
+        transport.push_response(HttpResponse::new(StatusCode::BAD_GATEWAY, "upstream down"));
+        transport.push_response(HttpResponse::new(StatusCode::UNAUTHORIZED, "bad secret"));
+        transport.push_json(serde_json::json!({
+            "code": 36004004,
+            "message": "invalid auth code",
+            "data": null
+        }));
+        transport.push_json(serde_json::json!({ "code": 0, "message": "success", "data": null }));
+        transport.push_response(HttpResponse::ok("<html>"));
+        transport.push_error(AppError::transport("connection reset"));
+        let oauth = oauth(transport);
+
+        assert!(matches!(
+            oauth.exchange_code_for_token("code").await,
+            Err(AppError::TokenExchangeFailed { status: StatusCode::BAD_GATEWAY, .. })
+        ));
+        assert!(matches!(
+            oauth.refresh_access_token("refresh").await,
+            Err(AppError::TokenRefreshFailed { status: StatusCode::UNAUTHORIZED, .. })
+        ));
+        assert!(matches!(
+            oauth.exchange_code_for_token("code").await,
+            Err(AppError::ApiError { code: 36004004, .. })
+        ));
+        assert!(matches!(
+            oauth.refresh_access_token("refresh").await,
+            Err(AppError::ApiError { code: 0, .. })
+        ));
+        assert!(matches!(
+            oauth.exchange_code_for_token("code").await,
+            Err(AppError::ParseError { .. })
+        ));
+        assert!(matches!(
+            oauth.refresh_access_token("refresh").await,
+            Err(AppError::Transport { .. })
+        ));
+    }
 }
@@ -0,0 +1,198 @@
+use crate::error::AppError;
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::OsRng;
+use async_trait::async_trait;
+use redis::aio::{ConnectionManager, ConnectionManagerConfig};
+use redis::AsyncCommands;
+use sqlx::sqlite::SqlitePool;
+use std::time::Duration;
+
+/// Redirect URI of the server's `/auth/callback` unless
+/// `TIKTOK_REDIRECT_URI` names another
+pub const DEFAULT_REDIRECT_URI: &str = "http://localhost:3000/auth/callback";
+
+/// How long the seller has to authorize the app after `/auth/tiktok`
+pub const STATE_TTL: Duration = Duration::from_secs(600);
+
+/// Namespace of the Redis keys, as in the order cache
+const KEY_PREFIX: &str = "toptop-order";
+
+/// A random `state` for one authorization
+pub fn new_state() -> String {
+    let mut nonce = [0u8; 16];
+    OsRng.fill_bytes(&mut nonce);
+    hex::encode(nonce)
+}
+
+/// OAuth `state` values issued by `/auth/tiktok` and checked by
+/// `/auth/callback`.
+///
+/// Kept outside the process, so the callback may land on any instance behind
+/// a load balancer and a restart between the two doesn't lose them.
+#[async_trait]
+pub trait OAuthStateStore: Send + Sync {
+    /// Where the states are kept, for logs
+    fn describe(&self) -> &'static str;
+    /// Remember `state` for `ttl`
+    async fn insert(&self, state: &str, ttl: Duration) -> Result<(), AppError>;
+    /// Forget `state`, returning whether it was issued and hasn't expired;
+    /// of concurrent callbacks with one state, only one gets `true`
+    async fn take(&self, state: &str) -> Result<bool, AppError>;
+    /// Delete expired states, returning how many were deleted
+    async fn purge_expired(&self) -> Result<u64, AppError>;
+}
+
+/// States in the `oauth_states` table, for instances sharing the database
+#[derive(Debug, Clone)]
+pub struct SqliteOAuthStateStore {
+    pool: SqlitePool,
+}
+
+impl SqliteOAuthStateStore {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Initialize the oauth_states table
+    pub async fn init(&self) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS oauth_states (
+                state TEXT PRIMARY KEY,
+                expires_at INTEGER NOT NULL
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+        sqlx::query(
+            "CREATE INDEX IF NOT EXISTS idx_oauth_states_expires_at
+             ON oauth_states (expires_at)",
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl OAuthStateStore for SqliteOAuthStateStore {
+    fn describe(&self) -> &'static str {
+        "SQLite"
+    }
+
+    async fn insert(&self, state: &str, ttl: Duration) -> Result<(), AppError> {
+        let expires_at = chrono::Utc::now().timestamp() + ttl.as_secs() as i64;
+        sqlx::query("INSERT OR REPLACE INTO oauth_states (state, expires_at) VALUES (?, ?)")
+            .bind(state)
+            .bind(expires_at)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn take(&self, state: &str) -> Result<bool, AppError> {
+        let result = sqlx::query("DELETE FROM oauth_states WHERE state = ? AND expires_at > ?")
+            .bind(state)
+            .bind(chrono::Utc::now().timestamp())
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn purge_expired(&self) -> Result<u64, AppError> {
+        let result = sqlx::query("DELETE FROM oauth_states WHERE expires_at <= ?")
+            .bind(chrono::Utc::now().timestamp())
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+}
+
+/// States as Redis keys with a TTL, for instances that don't share the
+/// database file; Redis expires them itself
+#[derive(Clone)]
+pub struct RedisOAuthStateStore {
+    conn: ConnectionManager,
+}
+
+impl RedisOAuthStateStore {
+    pub async fn connect(url: &str) -> Result<Self, redis::RedisError> {
+        let client = redis::Client::open(url)?;
+        let config = ConnectionManagerConfig::new()
+            .set_connection_timeout(Duration::from_secs(2))
+            .set_response_timeout(Duration::from_secs(2));
+        let conn = ConnectionManager::new_with_config(client, config).await?;
+        Ok(Self { conn })
+    }
+
+    fn key(state: &str) -> String {
+        format!("{}:oauth_state:{}", KEY_PREFIX, state)
+    }
+}
+
+fn redis_error(e: redis::RedisError) -> AppError {
+    AppError::transport(format!("Redis: {}", e))
+}
+
+#[async_trait]
+impl OAuthStateStore for RedisOAuthStateStore {
+    fn describe(&self) -> &'static str {
+        "Redis"
+    }
+
+    async fn insert(&self, state: &str, ttl: Duration) -> Result<(), AppError> {
+        let mut conn = self.conn.clone();
+        conn.set_ex::<_, _, ()>(Self::key(state), 1, ttl.as_secs().max(1))
+            .await
+            .map_err(redis_error)
+    }
+
+    async fn take(&self, state: &str) -> Result<bool, AppError> {
+        let mut conn = self.conn.clone();
+        let deleted: i64 = conn.del(Self::key(state)).await.map_err(redis_error)?;
+        Ok(deleted == 1)
+    }
+
+    async fn purge_expired(&self) -> Result<u64, AppError> {
+        Ok(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn store() -> SqliteOAuthStateStore {
+        // One connection, as each would open its own in-memory database
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        let store = SqliteOAuthStateStore::new(pool);
+        store.init().await.unwrap();
+        store
+    }
+
+    #[tokio::test]
+    async fn a_state_validates_once() {
+        let store = store().await;
+        let state = new_state();
+        store.insert(&state, STATE_TTL).await.unwrap();
+
+        assert!(store.take(&state).await.unwrap());
+        assert!(!store.take(&state).await.unwrap());
+        assert!(!store.take(&new_state()).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn expired_states_are_rejected_and_purged() {
+        let store = store().await;
+        store.insert("expired", Duration::ZERO).await.unwrap();
+        store.insert("live", STATE_TTL).await.unwrap();
+
+        assert!(!store.take("expired").await.unwrap());
+        store.insert("expired", Duration::ZERO).await.unwrap();
+        assert_eq!(store.purge_expired().await.unwrap(), 1);
+        assert!(store.take("live").await.unwrap());
+    }
+}
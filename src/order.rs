@@ -1,19 +1,41 @@
 use crate::error::AppError;
+use crate::health::ApiHealth;
 use crate::requests::TikTokShopApiClient;
+use futures::stream::{self, Stream, TryStreamExt};
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
+use std::sync::Arc;
+use utoipa::ToSchema;
 
 pub struct OrderClient {
     api_client: TikTokShopApiClient,
+    shop_cipher: Option<String>,
 }
 
 impl OrderClient {
     pub fn new(app_key: String, app_secret: String) -> Self {
+        Self::with_api_client(TikTokShopApiClient::new(app_key, app_secret))
+    }
+
+    pub fn with_api_client(api_client: TikTokShopApiClient) -> Self {
         Self {
-            api_client: TikTokShopApiClient::new(app_key, app_secret),
+            api_client,
+            shop_cipher: None,
         }
     }
 
+    /// Cipher used when a call doesn't pass one explicitly
+    pub fn with_shop_cipher(mut self, shop_cipher: Option<String>) -> Self {
+        self.shop_cipher = shop_cipher;
+        self
+    }
+
+    /// Record API call outcomes in a shared health tracker
+    pub fn with_health(mut self, health: Arc<ApiHealth>) -> Self {
+        self.api_client = self.api_client.with_health(health);
+        self
+    }
+
     pub async fn get_order_list(
         &self,
         access_token: &str,
@@ -21,6 +43,7 @@ impl OrderClient {
         shop_id: Option<&str>,
         request: GetOrderListRequest,
     ) -> Result<GetOrderListResponse, AppError> {
+        let shop_cipher = shop_cipher.or(self.shop_cipher.as_deref());
         // Based on working cURL: body should be empty {}, all params in query string
         let empty_body = serde_json::json!({});
 
@@ -72,6 +95,357 @@ impl OrderClient {
             )
             .await
     }
+
+    /// Every order matching `request`, following `next_page_token` until the
+    /// last page
+    pub fn stream_orders<'a>(
+        &'a self,
+        access_token: &'a str,
+        shop_cipher: Option<&'a str>,
+        shop_id: Option<&'a str>,
+        request: GetOrderListRequest,
+    ) -> impl Stream<Item = Result<Order, AppError>> + 'a {
+        self.paginate(access_token, shop_cipher, shop_id, std::iter::once(request))
+    }
+
+    /// Every order created in `[from, to)`, fetched window by window and page
+    /// by page. `request` supplies the other filters and the page size.
+    pub fn get_orders_in_range<'a>(
+        &'a self,
+        access_token: &'a str,
+        shop_cipher: Option<&'a str>,
+        shop_id: Option<&'a str>,
+        request: GetOrderListRequest,
+        from: i64,
+        to: i64,
+    ) -> impl Stream<Item = Result<Order, AppError>> + 'a {
+        let windows = request.create_time_windows(TimeWindowIterator::new(from, to));
+        self.paginate(access_token, shop_cipher, shop_id, windows)
+    }
+
+    /// Run each request in turn, following its pages
+    fn paginate<'a>(
+        &'a self,
+        access_token: &'a str,
+        shop_cipher: Option<&'a str>,
+        shop_id: Option<&'a str>,
+        requests: impl Iterator<Item = GetOrderListRequest> + 'a,
+    ) -> impl Stream<Item = Result<Order, AppError>> + 'a {
+        stream::try_unfold(
+            (requests, None),
+            move |(mut requests, next_page)| async move {
+                let Some(request) = next_page.or_else(|| requests.next()) else {
+                    return Ok::<_, AppError>(None);
+                };
+                let response = self
+                    .get_order_list(access_token, shop_cipher, shop_id, request.clone())
+                    .await?;
+                // An empty token means the request is exhausted
+                let next_page = response
+                    .next_page_token
+                    .filter(|t| !t.is_empty())
+                    .map(|token| request.with_page_token(token));
+                Ok(Some((response.orders, (requests, next_page))))
+            },
+        )
+        .map_ok(|orders| stream::iter(orders.into_iter().map(Ok)))
+        .try_flatten()
+    }
+
+    /// Fetch full details for up to 50 orders by ID
+    pub async fn get_order_detail(
+        &self,
+        access_token: &str,
+        shop_cipher: Option<&str>,
+        order_ids: &[String],
+    ) -> Result<GetOrderDetailResponse, AppError> {
+        let shop_cipher = shop_cipher.or(self.shop_cipher.as_deref());
+        let mut params = BTreeMap::new();
+        params.insert("ids".to_string(), order_ids.join(","));
+
+        self.api_client
+            .get(
+                "/order/202309/orders",
+                Some(access_token),
+                shop_cipher,
+                params,
+            )
+            .await
+    }
+
+    /// Itemized prices, discounts, taxes and fees of one order
+    pub async fn get_price_detail(
+        &self,
+        access_token: &str,
+        shop_cipher: Option<&str>,
+        order_id: &str,
+    ) -> Result<PriceDetail, AppError> {
+        let shop_cipher = shop_cipher.or(self.shop_cipher.as_deref());
+        self.api_client
+            .get(
+                &format!("/order/202407/orders/{}/price_detail", order_id),
+                Some(access_token),
+                shop_cipher,
+                BTreeMap::new(),
+            )
+            .await
+    }
+
+    /// Attach order numbers from other systems (e.g. an ERP) to TikTok orders
+    pub async fn add_external_order_references(
+        &self,
+        access_token: &str,
+        shop_cipher: Option<&str>,
+        request: &AddExternalOrderReferencesRequest,
+    ) -> Result<AddExternalOrderReferencesResponse, AppError> {
+        let shop_cipher = shop_cipher.or(self.shop_cipher.as_deref());
+        self.api_client
+            .post(
+                "/order/202406/orders/external_orders",
+                Some(access_token),
+                shop_cipher,
+                request,
+                None,
+            )
+            .await
+    }
+
+    /// Seller-initiated cancellation of an order or some of its line items
+    pub async fn cancel_order(
+        &self,
+        access_token: &str,
+        shop_cipher: Option<&str>,
+        request: &CancelOrderRequest,
+    ) -> Result<CancelOrderResponse, AppError> {
+        let shop_cipher = shop_cipher.or(self.shop_cipher.as_deref());
+        self.api_client
+            .post(
+                "/return_refund/202309/cancellations",
+                Some(access_token),
+                shop_cipher,
+                request,
+                None,
+            )
+            .await
+    }
+
+    /// One page of cancellation requests, e.g. the pending buyer requests
+    pub async fn search_cancellations(
+        &self,
+        access_token: &str,
+        shop_cipher: Option<&str>,
+        request: &SearchCancellationsRequest,
+    ) -> Result<SearchCancellationsResponse, AppError> {
+        let shop_cipher = shop_cipher.or(self.shop_cipher.as_deref());
+        let mut params = BTreeMap::new();
+        params.insert("page_size".to_string(), request.page_size.to_string());
+        if let Some(token) = &request.page_token {
+            params.insert("page_token".to_string(), token.clone());
+        }
+        self.api_client
+            .post(
+                "/return_refund/202309/cancellations/search",
+                Some(access_token),
+                shop_cipher,
+                request,
+                Some(params),
+            )
+            .await
+    }
+
+    /// Every cancellation request matching `request`, across pages
+    pub async fn search_all_cancellations(
+        &self,
+        access_token: &str,
+        shop_cipher: Option<&str>,
+        mut request: SearchCancellationsRequest,
+    ) -> Result<Vec<Cancellation>, AppError> {
+        let mut cancellations = Vec::new();
+        loop {
+            let page = self
+                .search_cancellations(access_token, shop_cipher, &request)
+                .await?;
+            cancellations.extend(page.cancellations);
+            match page.next_page_token.filter(|t| !t.is_empty()) {
+                Some(token) => request.page_token = Some(token),
+                None => return Ok(cancellations),
+            }
+        }
+    }
+
+    /// Accept a buyer's cancellation request; TikTok cancels and refunds the order
+    pub async fn approve_cancellation(
+        &self,
+        access_token: &str,
+        shop_cipher: Option<&str>,
+        cancel_id: &str,
+    ) -> Result<(), AppError> {
+        let shop_cipher = shop_cipher.or(self.shop_cipher.as_deref());
+        let _: serde_json::Value = self
+            .api_client
+            .post(
+                &format!("/return_refund/202309/cancellations/{}/approve", cancel_id),
+                Some(access_token),
+                shop_cipher,
+                &serde_json::json!({}),
+                None,
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Turn down a buyer's cancellation request, e.g. because the order has shipped
+    pub async fn reject_cancellation(
+        &self,
+        access_token: &str,
+        shop_cipher: Option<&str>,
+        cancel_id: &str,
+        request: &RejectCancellationRequest,
+    ) -> Result<(), AppError> {
+        let shop_cipher = shop_cipher.or(self.shop_cipher.as_deref());
+        let _: serde_json::Value = self
+            .api_client
+            .post(
+                &format!("/return_refund/202309/cancellations/{}/reject", cancel_id),
+                Some(access_token),
+                shop_cipher,
+                request,
+                None,
+            )
+            .await?;
+        Ok(())
+    }
+}
+
+/// Body for attaching other systems' order numbers to TikTok orders
+#[derive(Debug, Clone, Serialize)]
+pub struct AddExternalOrderReferencesRequest {
+    pub orders: Vec<ExternalOrderReferences>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExternalOrderReferences {
+    /// TikTok order id
+    pub id: String,
+    pub external_orders: Vec<ExternalOrderReference>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExternalOrderReference {
+    /// Order number in the other system
+    pub id: String,
+    /// The other system, e.g. `SHOPIFY` or `OTHER`
+    pub platform: String,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct AddExternalOrderReferencesResponse {
+    /// One entry per rejected reference
+    pub errors: Vec<ExternalOrderReferenceError>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ExternalOrderReferenceError {
+    pub code: i64,
+    pub message: String,
+}
+
+/// Body for a seller-initiated cancellation
+#[derive(Debug, Clone, Serialize)]
+pub struct CancelOrderRequest {
+    pub order_id: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub order_line_item_ids: Vec<String>,
+    pub cancel_reason: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CancelOrderResponse {
+    #[serde(default)]
+    pub cancel_id: Option<String>,
+    #[serde(default)]
+    pub cancel_status: Option<String>,
+}
+
+/// Status of a buyer cancellation request waiting for the seller
+pub const CANCELLATION_PENDING: &str = "CANCELLATION_REQUEST_PENDING";
+
+/// Filters for searching cancellation requests; unset filters match all
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchCancellationsRequest {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub cancel_status: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub order_ids: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub update_time_ge: Option<i64>,
+    #[serde(skip)]
+    pub page_size: u32,
+    #[serde(skip)]
+    pub page_token: Option<String>,
+}
+
+impl Default for SearchCancellationsRequest {
+    fn default() -> Self {
+        Self {
+            cancel_status: Vec::new(),
+            order_ids: Vec::new(),
+            update_time_ge: None,
+            page_size: 50,
+            page_token: None,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SearchCancellationsResponse {
+    pub cancellations: Vec<Cancellation>,
+    pub next_page_token: Option<String>,
+    pub total_count: i64,
+}
+
+/// A request to cancel an order or some of its line items
+#[derive(Debug, Clone, Default, Deserialize, Serialize, ToSchema)]
+#[serde(default)]
+pub struct Cancellation {
+    pub cancel_id: String,
+    pub order_id: String,
+    /// e.g. `CANCELLATION_REQUEST_PENDING`, `CANCELLATION_REQUEST_SUCCESS`
+    /// or `CANCELLATION_REQUEST_CANCEL`
+    pub cancel_status: String,
+    /// Who asked: `BUYER`, `SELLER`, `OPERATOR` or `SYSTEM`
+    #[schema(value_type = Option<String>)]
+    pub role: Option<CancellationInitiator>,
+    pub cancel_type: Option<String>,
+    #[schema(value_type = Option<String>)]
+    pub cancel_reason: Option<CancelReason>,
+    pub cancel_reason_text: Option<String>,
+    pub create_time: i64,
+    pub update_time: i64,
+    /// When the request is approved automatically if the seller doesn't respond
+    pub seller_response_due_time: Option<i64>,
+    pub cancel_line_items: Vec<CancelLineItem>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize, ToSchema)]
+#[serde(default)]
+pub struct CancelLineItem {
+    pub order_line_item_id: String,
+    pub sku_id: Option<String>,
+    pub seller_sku: Option<String>,
+    pub product_name: Option<String>,
+}
+
+/// Body for rejecting a cancellation request
+#[derive(Debug, Clone, Serialize)]
+pub struct RejectCancellationRequest {
+    /// One of TikTok's reject reason keys, e.g. `seller_reject_apply_product_has_been_packed`
+    pub reject_reason: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
 }
 
 /// Request parameters for getting order list
@@ -128,6 +502,60 @@ impl GetOrderListRequest {
         self.sort_order = Some(order.to_string());
         self
     }
+
+    /// A copy of this request for each window, starting from the first page
+    pub fn create_time_windows(
+        &self,
+        windows: TimeWindowIterator,
+    ) -> impl Iterator<Item = GetOrderListRequest> {
+        let base = Self {
+            page_token: None,
+            ..self.clone()
+        };
+        windows.map(move |(start, end)| base.clone().with_create_time_range(start, end))
+    }
+}
+
+/// Widest `create_time` range TikTok accepts in one order search
+pub const MAX_CREATE_TIME_WINDOW_SECS: i64 = 30 * 86_400;
+
+/// Splits `[from, to)` into consecutive `[start, end)` windows no wider than
+/// the API allows
+#[derive(Debug, Clone)]
+pub struct TimeWindowIterator {
+    next: i64,
+    to: i64,
+    window_secs: i64,
+}
+
+impl TimeWindowIterator {
+    pub fn new(from: i64, to: i64) -> Self {
+        Self {
+            next: from,
+            to,
+            window_secs: MAX_CREATE_TIME_WINDOW_SECS,
+        }
+    }
+
+    /// Use narrower windows, e.g. to keep each one under the page limit
+    pub fn with_window_secs(mut self, secs: i64) -> Self {
+        self.window_secs = secs.clamp(1, MAX_CREATE_TIME_WINDOW_SECS);
+        self
+    }
+}
+
+impl Iterator for TimeWindowIterator {
+    type Item = (i64, i64);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next >= self.to {
+            return None;
+        }
+        let start = self.next;
+        let end = start.saturating_add(self.window_secs).min(self.to);
+        self.next = end;
+        Some((start, end))
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -163,6 +591,187 @@ impl std::fmt::Display for OrderStatus {
     }
 }
 
+/// Why an order or line item was cancelled. TikTok sends reason keys or
+/// display text depending on the API; known values map to a variant and
+/// anything else is kept as [`CancelReason::Other`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum CancelReason {
+    OutOfStock,
+    PricingError,
+    AddressUndeliverable,
+    BuyerRequested,
+    BuyerChangedMind,
+    /// Cancelled by the system because the buyer didn't pay in time
+    PaymentOverdue,
+    /// Cancelled by the system because the seller didn't ship in time
+    ShippingOverdue,
+    DeliveryFailed,
+    Other(String),
+}
+
+/// Values TikTok sends for each [`CancelReason`], compared case-insensitively
+const CANCEL_REASON_ALIASES: &[(CancelReason, &[&str])] = &[
+    (
+        CancelReason::OutOfStock,
+        &[
+            "out_of_stock",
+            "seller_out_of_stock",
+            "seller_cancel_reason_out_of_stock",
+            "seller_cancel_unpaid_reason_out_of_stock",
+            "seller_cancel_paid_reason_out_of_stock",
+            "Out of stock",
+        ],
+    ),
+    (
+        CancelReason::PricingError,
+        &[
+            "pricing_error",
+            "seller_cancel_reason_wrong_price",
+            "seller_cancel_unpaid_reason_wrong_price",
+            "seller_cancel_paid_reason_wrong_price",
+            "Pricing error",
+        ],
+    ),
+    (
+        CancelReason::AddressUndeliverable,
+        &[
+            "address_undeliverable",
+            "seller_cancel_unpaid_reason_address_not_deliver",
+            "seller_cancel_paid_reason_address_not_deliver",
+            "Unable to deliver to buyer address",
+        ],
+    ),
+    (
+        CancelReason::BuyerRequested,
+        &[
+            "buyer_requested",
+            "seller_cancel_unpaid_reason_buyer_requested_cancellation",
+            "seller_cancel_paid_reason_buyer_requested_cancellation",
+            "Buyer requested cancellation",
+        ],
+    ),
+    (
+        CancelReason::BuyerChangedMind,
+        &["buyer_changed_mind", "No longer needed", "Changed my mind"],
+    ),
+    (
+        CancelReason::PaymentOverdue,
+        &[
+            "payment_overdue",
+            "Payment overdue",
+            "Order was not paid in time",
+        ],
+    ),
+    (
+        CancelReason::ShippingOverdue,
+        &["shipping_overdue", "Package wasn't shipped in time"],
+    ),
+    (
+        CancelReason::DeliveryFailed,
+        &["delivery_failed", "Package delivery failed"],
+    ),
+];
+
+impl CancelReason {
+    /// Stable key of a known reason, or the value TikTok sent
+    pub fn as_str(&self) -> &str {
+        match self {
+            CancelReason::OutOfStock => "out_of_stock",
+            CancelReason::PricingError => "pricing_error",
+            CancelReason::AddressUndeliverable => "address_undeliverable",
+            CancelReason::BuyerRequested => "buyer_requested",
+            CancelReason::BuyerChangedMind => "buyer_changed_mind",
+            CancelReason::PaymentOverdue => "payment_overdue",
+            CancelReason::ShippingOverdue => "shipping_overdue",
+            CancelReason::DeliveryFailed => "delivery_failed",
+            CancelReason::Other(value) => value,
+        }
+    }
+}
+
+impl From<&str> for CancelReason {
+    fn from(value: &str) -> Self {
+        let value = value.trim();
+        CANCEL_REASON_ALIASES
+            .iter()
+            .find(|(_, aliases)| aliases.iter().any(|a| a.eq_ignore_ascii_case(value)))
+            .map(|(reason, _)| reason.clone())
+            .unwrap_or_else(|| CancelReason::Other(value.to_string()))
+    }
+}
+
+impl std::fmt::Display for CancelReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Serialize for CancelReason {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for CancelReason {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(String::deserialize(deserializer)?.as_str().into())
+    }
+}
+
+/// Who cancelled an order, or asked to
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum CancellationInitiator {
+    Buyer,
+    Seller,
+    System,
+    /// TikTok customer service
+    Operator,
+    Other(String),
+}
+
+impl CancellationInitiator {
+    /// TikTok's value for a known initiator, or the value it sent
+    pub fn as_str(&self) -> &str {
+        match self {
+            CancellationInitiator::Buyer => "BUYER",
+            CancellationInitiator::Seller => "SELLER",
+            CancellationInitiator::System => "SYSTEM",
+            CancellationInitiator::Operator => "OPERATOR",
+            CancellationInitiator::Other(value) => value,
+        }
+    }
+}
+
+impl From<&str> for CancellationInitiator {
+    fn from(value: &str) -> Self {
+        match value.trim().to_ascii_uppercase().as_str() {
+            "BUYER" => CancellationInitiator::Buyer,
+            "SELLER" => CancellationInitiator::Seller,
+            "SYSTEM" => CancellationInitiator::System,
+            "OPERATOR" | "PLATFORM" => CancellationInitiator::Operator,
+            _ => CancellationInitiator::Other(value.trim().to_string()),
+        }
+    }
+}
+
+impl std::fmt::Display for CancellationInitiator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Serialize for CancellationInitiator {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for CancellationInitiator {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(String::deserialize(deserializer)?.as_str().into())
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SortOrder {
     Ascending,
@@ -186,7 +795,13 @@ pub struct GetOrderListResponse {
     pub next_page_token: Option<String>,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize)]
+pub struct GetOrderDetailResponse {
+    #[serde(default)]
+    pub orders: Vec<Order>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, ToSchema)]
 pub struct Order {
     pub id: String,
     pub status: String,
@@ -209,11 +824,13 @@ pub struct Order {
     #[serde(default)]
     pub cancel_order_sla_time: Option<i64>,
     #[serde(default)]
-    pub cancel_reason: Option<String>,
+    #[schema(value_type = Option<String>)]
+    pub cancel_reason: Option<CancelReason>,
     #[serde(default)]
     pub cancel_time: Option<i64>,
     #[serde(default)]
-    pub cancellation_initiator: Option<String>,
+    #[schema(value_type = Option<String>)]
+    pub cancellation_initiator: Option<CancellationInitiator>,
     #[serde(default)]
     pub collection_due_time: Option<i64>,
     #[serde(default)]
@@ -234,6 +851,9 @@ pub struct Order {
     pub is_replacement_order: Option<bool>,
     #[serde(default)]
     pub is_sample_order: Option<bool>,
+    /// Order a replacement was sent for; only in order detail responses
+    #[serde(default)]
+    pub replaced_order_id: Option<String>,
     #[serde(default)]
     pub order_type: Option<String>,
     #[serde(default)]
@@ -266,12 +886,12 @@ pub struct Order {
     pub delivery_time: Option<i64>,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, ToSchema)]
 pub struct Package {
     pub id: String,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, ToSchema)]
 pub struct PaymentInfo {
     pub currency: String,
     pub total_amount: String,
@@ -293,7 +913,59 @@ pub struct PaymentInfo {
     pub shipping_fee_seller_discount: Option<String>,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+/// Price breakdown from the price detail API. Amounts are decimal strings in
+/// `currency`; fields TikTok leaves out for a market are unset.
+#[derive(Debug, Deserialize, Serialize, Clone, Default, ToSchema)]
+#[serde(default)]
+pub struct PriceDetail {
+    pub currency: String,
+    /// What the buyer paid
+    pub payment: Option<String>,
+    pub total: Option<String>,
+    /// Item price after discounts
+    pub subtotal: Option<String>,
+    pub sku_list_price: Option<String>,
+    pub sku_sale_price: Option<String>,
+    pub subtotal_deduction_seller: Option<String>,
+    pub subtotal_deduction_platform: Option<String>,
+    pub shipping_list_price: Option<String>,
+    pub shipping_sale_price: Option<String>,
+    pub shipping_fee_deduction_seller: Option<String>,
+    pub shipping_fee_deduction_platform: Option<String>,
+    pub shipping_fee_cofunded_discount: Option<String>,
+    pub voucher_deduction_seller: Option<String>,
+    pub voucher_deduction_platform: Option<String>,
+    /// All taxes on the order
+    pub tax_amount: Option<String>,
+    pub sales_tax: Option<String>,
+    pub product_tax: Option<String>,
+    pub shipping_fee_tax: Option<String>,
+    pub retail_delivery_fee: Option<String>,
+    /// Price net of taxes and fees
+    pub net_price_amount: Option<String>,
+    pub line_items: Vec<LineItemPriceDetail>,
+}
+
+/// Price breakdown of one line item
+#[derive(Debug, Deserialize, Serialize, Clone, Default, ToSchema)]
+#[serde(default)]
+pub struct LineItemPriceDetail {
+    pub id: String,
+    pub payment: Option<String>,
+    pub sku_list_price: Option<String>,
+    pub sku_sale_price: Option<String>,
+    pub subtotal: Option<String>,
+    pub subtotal_deduction_seller: Option<String>,
+    pub subtotal_deduction_platform: Option<String>,
+    pub voucher_deduction_seller: Option<String>,
+    pub voucher_deduction_platform: Option<String>,
+    pub tax_amount: Option<String>,
+    pub sales_tax: Option<String>,
+    pub product_tax: Option<String>,
+    pub net_price_amount: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, ToSchema)]
 pub struct RecipientAddress {
     #[serde(default)]
     pub full_address: Option<String>,
@@ -327,14 +999,14 @@ pub struct RecipientAddress {
     pub last_name_local_script: Option<String>,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, ToSchema)]
 pub struct DistrictInfo {
     pub address_level: String,
     pub address_level_name: String,
     pub address_name: String,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, ToSchema)]
 pub struct OrderItem {
     pub id: String,
     pub product_id: String,
@@ -356,7 +1028,8 @@ pub struct OrderItem {
     #[serde(default)]
     pub seller_discount: Option<String>,
     #[serde(default)]
-    pub cancel_reason: Option<String>,
+    #[schema(value_type = Option<String>)]
+    pub cancel_reason: Option<CancelReason>,
     #[serde(default)]
     pub cancel_user: Option<String>,
     #[serde(default)]
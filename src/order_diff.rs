@@ -0,0 +1,118 @@
+use crate::order::Order;
+use serde::Serialize;
+use serde_json::Value;
+use utoipa::ToSchema;
+
+/// A field whose stored value differs from TikTok's
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct FieldChange {
+    /// Dotted path to the field; array elements with an `id` are addressed as
+    /// `item_list[id=123]`, others by index
+    pub path: String,
+    /// Stored value, `null` if the field is absent locally
+    pub local: Value,
+    /// Live value, `null` if TikTok no longer returns the field
+    pub remote: Value,
+}
+
+/// Stored copy of an order compared against the live one
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct OrderDiff {
+    pub order_id: String,
+    pub local_status: String,
+    pub remote_status: String,
+    pub local_update_time: i64,
+    pub remote_update_time: i64,
+    /// Whether TikTok has a newer version than the one stored
+    pub stale: bool,
+    pub changes: Vec<FieldChange>,
+}
+
+/// Field-by-field differences between the stored and live copy of an order
+pub fn diff_orders(local: &Order, remote: &Order) -> OrderDiff {
+    let mut changes = Vec::new();
+    diff_values(
+        "",
+        &serde_json::to_value(local).unwrap_or_default(),
+        &serde_json::to_value(remote).unwrap_or_default(),
+        &mut changes,
+    );
+
+    OrderDiff {
+        order_id: remote.id.clone(),
+        local_status: local.status.clone(),
+        remote_status: remote.status.clone(),
+        local_update_time: local.update_time,
+        remote_update_time: remote.update_time,
+        stale: local.update_time < remote.update_time,
+        changes,
+    }
+}
+
+fn join(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+/// `id` of an array element, if it has one
+fn element_id(value: &Value) -> Option<&str> {
+    value.get("id").and_then(Value::as_str)
+}
+
+/// Append the differences between `local` and `remote` under `path`
+pub fn diff_values(path: &str, local: &Value, remote: &Value, changes: &mut Vec<FieldChange>) {
+    match (local, remote) {
+        (Value::Object(local), Value::Object(remote)) => {
+            for (key, local_value) in local {
+                let remote_value = remote.get(key).unwrap_or(&Value::Null);
+                diff_values(&join(path, key), local_value, remote_value, changes);
+            }
+            for (key, remote_value) in remote {
+                if !local.contains_key(key) {
+                    diff_values(&join(path, key), &Value::Null, remote_value, changes);
+                }
+            }
+        }
+        (Value::Array(local), Value::Array(remote))
+            if local.iter().chain(remote).all(|v| element_id(v).is_some()) =>
+        {
+            // Match elements by id so a reordered or inserted element doesn't
+            // show as every later element changing
+            for local_value in local {
+                let id = element_id(local_value).unwrap_or_default();
+                let remote_value = remote
+                    .iter()
+                    .find(|v| element_id(v) == Some(id))
+                    .unwrap_or(&Value::Null);
+                let path = format!("{}[id={}]", path, id);
+                diff_values(&path, local_value, remote_value, changes);
+            }
+            for remote_value in remote {
+                let id = element_id(remote_value).unwrap_or_default();
+                if !local.iter().any(|v| element_id(v) == Some(id)) {
+                    let path = format!("{}[id={}]", path, id);
+                    diff_values(&path, &Value::Null, remote_value, changes);
+                }
+            }
+        }
+        (Value::Array(local), Value::Array(remote)) => {
+            for index in 0..local.len().max(remote.len()) {
+                diff_values(
+                    &format!("{}[{}]", path, index),
+                    local.get(index).unwrap_or(&Value::Null),
+                    remote.get(index).unwrap_or(&Value::Null),
+                    changes,
+                );
+            }
+        }
+        _ if local != remote => changes.push(FieldChange {
+            path: path.to_string(),
+            local: local.clone(),
+            remote: remote.clone(),
+        }),
+        _ => {}
+    }
+}
@@ -0,0 +1,113 @@
+use crate::config::Config;
+use crate::database::Database;
+use crate::jobs::{Job, JobHandler};
+use crate::order::Order;
+use crate::shops::ShopRegistry;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tracing::info;
+
+/// Re-fetch an order from TikTok and store it for the processing pipeline
+pub const REFRESH_JOB_KIND: &str = "order_refresh";
+/// Write orders that a sync fetched but failed to store
+pub const UPSERT_JOB_KIND: &str = "order_upsert";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RefreshJobPayload {
+    pub order_id: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpsertJobPayload {
+    pub orders: Vec<Order>,
+}
+
+/// Retries order refreshes whose webhook-triggered processing failed
+pub struct OrderRefreshHandler {
+    db: Arc<Database>,
+    shops: ShopRegistry,
+    config: Config,
+}
+
+impl OrderRefreshHandler {
+    pub fn new(db: Arc<Database>, shops: ShopRegistry, config: Config) -> Self {
+        Self {
+            db,
+            shops,
+            config,
+        }
+    }
+}
+
+#[async_trait]
+impl JobHandler for OrderRefreshHandler {
+    async fn handle(&self, job: &Job) -> Result<(), String> {
+        let payload: RefreshJobPayload = serde_json::from_value(job.payload.clone())
+            .map_err(|e| format!("Invalid refresh payload: {}", e))?;
+
+        let token = self
+            .config
+            .token_manager()
+            .valid_token()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let order_client = self
+            .shops
+            .order_client(&self.config)
+            .await
+            .map_err(|e| e.to_string())?;
+        let response = order_client
+            .get_order_detail(
+                &token.access_token,
+                None,
+                std::slice::from_ref(&payload.order_id),
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let order = response
+            .orders
+            .into_iter()
+            .find(|o| o.id == payload.order_id)
+            .ok_or_else(|| format!("Order {} not returned by API", payload.order_id))?;
+
+        let stats = self
+            .db
+            .upsert_orders(std::slice::from_ref(&order))
+            .await
+            .map_err(|e| e.to_string())?;
+        info!("Refreshed order {} on retry: {}", payload.order_id, stats);
+
+        Ok(())
+    }
+}
+
+/// Retries storing a batch of synced orders
+pub struct OrderUpsertHandler {
+    db: Arc<Database>,
+}
+
+impl OrderUpsertHandler {
+    pub fn new(db: Arc<Database>) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl JobHandler for OrderUpsertHandler {
+    async fn handle(&self, job: &Job) -> Result<(), String> {
+        let payload: UpsertJobPayload = serde_json::from_value(job.payload.clone())
+            .map_err(|e| format!("Invalid upsert payload: {}", e))?;
+
+        let stats = self
+            .db
+            .upsert_orders(&payload.orders)
+            .await
+            .map_err(|e| e.to_string())?;
+        info!("Stored {} orders on retry: {}", payload.orders.len(), stats);
+
+        Ok(())
+    }
+}
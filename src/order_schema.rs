@@ -0,0 +1,87 @@
+use crate::database::Database;
+use crate::jobs::{Job, JobHandler};
+use crate::order::Order;
+use async_trait::async_trait;
+use std::sync::Arc;
+use tracing::info;
+
+/// Version stamped on order blobs written by this build.
+///
+/// When `Order` changes in a way that breaks deserialization of stored blobs,
+/// bump this and register a hook in `UPGRADES` that rewrites the previous
+/// version's JSON into the new shape.
+pub const ORDER_SCHEMA_VERSION: i64 = 1;
+
+/// Rewrites a blob from version `n` to `n + 1` in place
+pub type UpgradeFn = fn(&mut serde_json::Value) -> Result<(), String>;
+
+/// Upgrade hooks keyed by the version they upgrade from
+const UPGRADES: &[(i64, UpgradeFn)] = &[];
+
+pub const UPGRADE_JOB_KIND: &str = "order_schema_upgrade";
+
+/// Run upgrade hooks until `value` is at `ORDER_SCHEMA_VERSION`
+pub fn upgrade_blob(value: &mut serde_json::Value, from_version: i64) -> Result<(), String> {
+    let mut version = from_version;
+
+    while version < ORDER_SCHEMA_VERSION {
+        let (_, hook) = UPGRADES
+            .iter()
+            .find(|(v, _)| *v == version)
+            .ok_or_else(|| format!("No upgrade hook from order schema v{}", version))?;
+        hook(value)?;
+        version += 1;
+    }
+
+    Ok(())
+}
+
+/// Decode a stored blob, upgrading it first if it was written by an older schema.
+///
+/// Returns the order and, if an upgrade happened, the re-serialized blob so the
+/// caller can write it back.
+pub fn decode_order(data: &str, version: i64) -> Result<(Order, Option<String>), String> {
+    if version >= ORDER_SCHEMA_VERSION {
+        let order = serde_json::from_str(data).map_err(|e| e.to_string())?;
+        return Ok((order, None));
+    }
+
+    let mut value: serde_json::Value = serde_json::from_str(data).map_err(|e| e.to_string())?;
+    upgrade_blob(&mut value, version)?;
+    let upgraded = value.to_string();
+    let order = serde_json::from_value(value).map_err(|e| e.to_string())?;
+
+    Ok((order, Some(upgraded)))
+}
+
+/// Maintenance job that eagerly upgrades all stored blobs to the current version
+pub struct OrderSchemaUpgradeHandler {
+    db: Arc<Database>,
+}
+
+impl OrderSchemaUpgradeHandler {
+    pub fn new(db: Arc<Database>) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl JobHandler for OrderSchemaUpgradeHandler {
+    async fn handle(&self, _job: &Job) -> Result<(), String> {
+        let report = self
+            .db
+            .upgrade_order_blobs()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        info!(
+            "Order schema upgrade to v{}: {} upgraded, {} failed",
+            ORDER_SCHEMA_VERSION, report.upgraded, report.failed
+        );
+
+        if report.failed > 0 {
+            return Err(format!("{} order blobs could not be upgraded", report.failed));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,156 @@
+use crate::communications::{Direction, TimelineEntry};
+use crate::notes::OrderNote;
+use crate::order::Order;
+use crate::processing::OrderProcessing;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// Where a timeline event comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum EventSource {
+    /// A timestamp on the TikTok order
+    Tiktok,
+    /// This service's own processing of the order
+    Processing,
+    /// A staff note
+    Note,
+    /// A message about the order (see [`TimelineEntry`])
+    Communication,
+}
+
+/// One event in an order's history, for support screens
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct OrderTimelineEvent {
+    /// Unix timestamp
+    pub time: i64,
+    pub source: EventSource,
+    /// e.g. `created`, `paid`, `delivered`, `processing_fulfilling`, `note`
+    pub event: String,
+    /// Human-readable summary
+    pub description: String,
+}
+
+impl OrderTimelineEvent {
+    fn new(time: i64, source: EventSource, event: &str, description: String) -> Self {
+        Self {
+            time,
+            source,
+            event: event.to_string(),
+            description,
+        }
+    }
+}
+
+/// Every event known about `order`, oldest first: its TikTok milestones, the
+/// processing transitions, staff notes and `communications`
+pub fn build(
+    order: &Order,
+    processing: Option<&OrderProcessing>,
+    notes: &[OrderNote],
+    communications: &[TimelineEntry],
+) -> Vec<OrderTimelineEvent> {
+    let mut events = milestones(order);
+
+    for transition in processing.iter().flat_map(|p| &p.transitions) {
+        events.push(OrderTimelineEvent::new(
+            transition.created_at,
+            EventSource::Processing,
+            &format!("processing_{}", transition.to_state.as_str()),
+            format!(
+                "Processing moved from {} to {}: {}",
+                transition.from_state.as_str(),
+                transition.to_state.as_str(),
+                transition.reason
+            ),
+        ));
+    }
+
+    for note in notes {
+        let author = note.author.as_deref().unwrap_or("staff");
+        events.push(OrderTimelineEvent::new(
+            note.created_at,
+            EventSource::Note,
+            "note",
+            format!("Note by {}: {}", author, note.body),
+        ));
+    }
+
+    for entry in communications {
+        let verb = match entry.direction {
+            Direction::Inbound => "received",
+            Direction::Outbound => "sent",
+        };
+        let author = entry
+            .author
+            .as_deref()
+            .map(|a| format!(" from {}", a))
+            .unwrap_or_default();
+        events.push(OrderTimelineEvent::new(
+            entry.time,
+            EventSource::Communication,
+            &entry.channel,
+            format!(
+                "{} message {}{}: {}",
+                entry.channel, verb, author, entry.body
+            ),
+        ));
+    }
+
+    // Stable sort keeps milestones ahead of local events with the same timestamp
+    events.sort_by_key(|event| event.time);
+    events
+}
+
+/// Events for the timestamps TikTok sets on an order
+fn milestones(order: &Order) -> Vec<OrderTimelineEvent> {
+    let tiktok = |time: i64, event: &str, description: String| {
+        OrderTimelineEvent::new(time, EventSource::Tiktok, event, description)
+    };
+    let mut events = vec![tiktok(
+        order.create_time,
+        "created",
+        "Order placed".to_string(),
+    )];
+
+    if let Some(time) = order.paid_time {
+        let description = match order.payment_method_name.as_deref() {
+            Some(method) => format!("Paid with {}", method),
+            None => "Paid".to_string(),
+        };
+        events.push(tiktok(time, "paid", description));
+    }
+    if let Some(time) = order.rts_time {
+        events.push(tiktok(
+            time,
+            "ready_to_ship",
+            "Marked ready to ship".to_string(),
+        ));
+    }
+    if let Some(time) = order.collection_time {
+        let description = match order.shipping_provider.as_deref() {
+            Some(provider) => format!("Collected by {}", provider),
+            None => "Collected by the carrier".to_string(),
+        };
+        events.push(tiktok(time, "collected", description));
+    }
+    if let Some(time) = order.delivery_time {
+        events.push(tiktok(
+            time,
+            "delivered",
+            "Delivered to the buyer".to_string(),
+        ));
+    }
+    if let Some(time) = order.cancel_time {
+        let mut description = match &order.cancellation_initiator {
+            Some(initiator) => format!("Cancelled by {}", initiator.as_str().to_lowercase()),
+            None => "Cancelled".to_string(),
+        };
+        if let Some(reason) = &order.cancel_reason {
+            description.push_str(&format!(": {}", reason));
+        }
+        events.push(tiktok(time, "cancelled", description));
+    }
+
+    events
+}
@@ -0,0 +1,405 @@
+use crate::config::LiveConfig;
+use crate::events::OrderEvent;
+use crate::jobs::{Job, JobHandler, JobQueue};
+use crate::redact;
+use crate::transport::{HttpRequest, HttpTransport};
+use async_trait::async_trait;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use sqlx::sqlite::{SqlitePool, SqliteRow};
+use sqlx::Row;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::{error, info, warn};
+use utoipa::ToSchema;
+
+/// POST one order event to one subscriber
+pub const DELIVERY_JOB_KIND: &str = "webhook_delivery";
+
+/// `sha256=` + hex HMAC-SHA256 of `{timestamp}{body}`, keyed with the subscriber's secret
+pub const SIGNATURE_HEADER: &str = "X-Toptop-Signature";
+/// Unix seconds the delivery was signed at
+pub const TIMESTAMP_HEADER: &str = "X-Toptop-Timestamp";
+/// Order event kind, e.g. `created`
+pub const EVENT_HEADER: &str = "X-Toptop-Event";
+
+/// A URL order events are pushed to
+#[derive(Clone)]
+pub struct WebhookSubscriber {
+    pub url: String,
+    pub secret: String,
+}
+
+impl std::fmt::Debug for WebhookSubscriber {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WebhookSubscriber")
+            .field("url", &self.url)
+            .field("secret", &redact::mask(&self.secret))
+            .finish()
+    }
+}
+
+/// Parse `url=secret` pairs separated by commas. URLs must be http(s) and
+/// contain no `=`.
+pub fn parse_subscribers(value: &str) -> Result<Vec<WebhookSubscriber>, String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (url, secret) = pair
+                .split_once('=')
+                .ok_or_else(|| format!("{:?} is not url=secret", pair))?;
+            let (url, secret) = (url.trim(), secret.trim());
+            match reqwest::Url::parse(url) {
+                Ok(parsed) if parsed.scheme() == "http" || parsed.scheme() == "https" => {}
+                _ => return Err(format!("{:?} is not an http(s) URL", url)),
+            }
+            if secret.is_empty() {
+                return Err(format!("no secret for {}", url));
+            }
+            Ok(WebhookSubscriber {
+                url: url.to_string(),
+                secret: secret.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Value of [`SIGNATURE_HEADER`] for `body` sent at `timestamp`
+pub fn sign(secret: &str, timestamp: i64, body: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(body.as_bytes());
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// Payload of a `webhook_delivery` job. The secret is looked up from config
+/// when sending, so it never lands in the jobs table.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeliveryJobPayload {
+    pub url: String,
+    pub event: OrderEvent,
+}
+
+/// Body POSTed to subscribers
+#[derive(Debug, Serialize)]
+struct DeliveryBody<'a> {
+    /// Same across retries of one delivery, for deduplication on the receiving end
+    delivery_id: String,
+    #[serde(flatten)]
+    event: &'a OrderEvent,
+}
+
+/// Queues a delivery per subscriber for every order event. Subscribers are
+/// read from the live config, so reloading `OUTBOUND_WEBHOOKS` takes effect
+/// from the next event.
+pub struct WebhookPublisher {
+    config: LiveConfig,
+    job_queue: JobQueue,
+    max_attempts: u32,
+}
+
+impl WebhookPublisher {
+    pub fn new(config: LiveConfig, job_queue: JobQueue, max_attempts: u32) -> Self {
+        Self {
+            config,
+            job_queue,
+            max_attempts,
+        }
+    }
+
+    /// Follow `events` until the bus is dropped. Events published while the
+    /// receiver lagged behind are lost and logged.
+    pub async fn run(self, mut events: broadcast::Receiver<OrderEvent>) {
+        loop {
+            match events.recv().await {
+                Ok(event) => {
+                    if let Err(e) = self.enqueue(&event).await {
+                        error!(
+                            "Failed to queue webhook deliveries for order {}: {}",
+                            event.order_id, e
+                        );
+                    }
+                }
+                Err(RecvError::Lagged(missed)) => {
+                    warn!("Webhook publisher missed {} order events", missed);
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    }
+
+    /// Queue `event` for every subscriber. The same event is only queued once
+    /// per subscriber, even when published twice.
+    pub async fn enqueue(&self, event: &OrderEvent) -> Result<usize, sqlx::Error> {
+        let mut queued = 0;
+        for subscriber in &self.config.current().outbound_webhooks {
+            let dedup_key = format!(
+                "{}:{}:{}:{}:{}",
+                DELIVERY_JOB_KIND,
+                subscriber.url,
+                event.order_id,
+                event.kind.as_str(),
+                event.update_time
+            );
+            let payload = serde_json::to_value(DeliveryJobPayload {
+                url: subscriber.url.clone(),
+                event: event.clone(),
+            })
+            .unwrap_or_default();
+            if self
+                .job_queue
+                .enqueue(
+                    DELIVERY_JOB_KIND,
+                    Some(&dedup_key),
+                    &payload,
+                    self.max_attempts,
+                )
+                .await?
+            {
+                queued += 1;
+            }
+        }
+        Ok(queued)
+    }
+}
+
+/// One delivery attempt
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct WebhookDelivery {
+    pub id: i64,
+    /// Delivery job; retries of a delivery share it
+    pub job_id: i64,
+    pub url: String,
+    pub event_kind: String,
+    pub order_id: String,
+    pub attempt: i64,
+    /// HTTP status, unset when no response came back
+    pub status_code: Option<i64>,
+    pub error: Option<String>,
+    pub duration_ms: i64,
+    pub created_at: i64,
+}
+
+/// Filter for [`WebhookDeliveryStore::list`]
+#[derive(Debug, Clone, Default)]
+pub struct DeliveryFilter<'a> {
+    pub url: Option<&'a str>,
+    pub order_id: Option<&'a str>,
+    /// Only attempts that failed
+    pub failed: bool,
+}
+
+/// Log of delivery attempts, in the `webhook_deliveries` table
+#[derive(Clone)]
+pub struct WebhookDeliveryStore {
+    pool: SqlitePool,
+}
+
+impl WebhookDeliveryStore {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Initialize the webhook_deliveries table
+    pub async fn init(&self) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS webhook_deliveries (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                job_id INTEGER NOT NULL,
+                url TEXT NOT NULL,
+                event_kind TEXT NOT NULL,
+                order_id TEXT NOT NULL,
+                attempt INTEGER NOT NULL,
+                status_code INTEGER,
+                error TEXT,
+                duration_ms INTEGER NOT NULL,
+                created_at INTEGER NOT NULL
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            "CREATE INDEX IF NOT EXISTS idx_webhook_deliveries_order
+            ON webhook_deliveries (order_id)",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn record(
+        &self,
+        job: &Job,
+        payload: &DeliveryJobPayload,
+        status_code: Option<u16>,
+        error: Option<&str>,
+        duration_ms: i64,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "INSERT INTO webhook_deliveries (
+                job_id, url, event_kind, order_id, attempt, status_code, error, duration_ms, created_at
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        )
+        .bind(job.id)
+        .bind(&payload.url)
+        .bind(payload.event.kind.as_str())
+        .bind(&payload.event.order_id)
+        .bind(job.attempts)
+        .bind(status_code.map(i64::from))
+        .bind(error)
+        .bind(duration_ms)
+        .bind(chrono::Utc::now().timestamp())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Attempts, newest first
+    pub async fn list(
+        &self,
+        filter: &DeliveryFilter<'_>,
+        limit: i64,
+    ) -> Result<Vec<WebhookDelivery>, sqlx::Error> {
+        sqlx::query(
+            "SELECT id, job_id, url, event_kind, order_id, attempt, status_code, error,
+                duration_ms, created_at
+            FROM webhook_deliveries
+            WHERE (?1 IS NULL OR url = ?1)
+            AND (?2 IS NULL OR order_id = ?2)
+            AND (?3 = 0 OR error IS NOT NULL)
+            ORDER BY id DESC LIMIT ?4",
+        )
+        .bind(filter.url)
+        .bind(filter.order_id)
+        .bind(filter.failed)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(delivery_from_row)
+        .collect()
+    }
+}
+
+fn delivery_from_row(row: &SqliteRow) -> Result<WebhookDelivery, sqlx::Error> {
+    Ok(WebhookDelivery {
+        id: row.try_get("id")?,
+        job_id: row.try_get("job_id")?,
+        url: row.try_get("url")?,
+        event_kind: row.try_get("event_kind")?,
+        order_id: row.try_get("order_id")?,
+        attempt: row.try_get("attempt")?,
+        status_code: row.try_get("status_code")?,
+        error: row.try_get("error")?,
+        duration_ms: row.try_get("duration_ms")?,
+        created_at: row.try_get("created_at")?,
+    })
+}
+
+/// Signs and POSTs queued deliveries. Anything but a 2xx is retried with the
+/// job queue's backoff.
+pub struct WebhookDeliveryHandler {
+    config: LiveConfig,
+    transport: Arc<dyn HttpTransport>,
+    store: WebhookDeliveryStore,
+}
+
+impl WebhookDeliveryHandler {
+    pub fn new(
+        config: LiveConfig,
+        transport: Arc<dyn HttpTransport>,
+        store: WebhookDeliveryStore,
+    ) -> Self {
+        Self {
+            config,
+            transport,
+            store,
+        }
+    }
+}
+
+#[async_trait]
+impl JobHandler for WebhookDeliveryHandler {
+    async fn handle(&self, job: &Job) -> Result<(), String> {
+        let payload: DeliveryJobPayload = serde_json::from_value(job.payload.clone())
+            .map_err(|e| format!("Invalid webhook delivery payload: {}", e))?;
+
+        let config = self.config.current();
+        let Some(subscriber) = config
+            .outbound_webhooks
+            .iter()
+            .find(|s| s.url == payload.url)
+        else {
+            warn!(
+                "Dropping webhook delivery {} to {}: no longer a subscriber",
+                job.id, payload.url
+            );
+            return Ok(());
+        };
+
+        let body = serde_json::to_string(&DeliveryBody {
+            delivery_id: job.id.to_string(),
+            event: &payload.event,
+        })
+        .map_err(|e| e.to_string())?;
+        let timestamp = chrono::Utc::now().timestamp();
+        let request = HttpRequest::post(&subscriber.url)
+            .with_header("Content-Type", "application/json")
+            .with_header(
+                SIGNATURE_HEADER,
+                &sign(&subscriber.secret, timestamp, &body),
+            )
+            .with_header(TIMESTAMP_HEADER, &timestamp.to_string())
+            .with_header(EVENT_HEADER, payload.event.kind.as_str())
+            .with_body(body);
+
+        let started = Instant::now();
+        let result = self.transport.send(request).await;
+        let duration_ms = started.elapsed().as_millis() as i64;
+
+        let (status_code, outcome) = match result {
+            Ok(response) if response.status.is_success() => {
+                (Some(response.status.as_u16()), Ok(()))
+            }
+            Ok(response) => (
+                Some(response.status.as_u16()),
+                Err(format!(
+                    "{} answered HTTP {}",
+                    subscriber.url, response.status
+                )),
+            ),
+            Err(e) => (None, Err(format!("{}: {}", subscriber.url, e))),
+        };
+
+        if let Err(e) = self
+            .store
+            .record(
+                job,
+                &payload,
+                status_code,
+                outcome.as_ref().err().map(String::as_str),
+                duration_ms,
+            )
+            .await
+        {
+            error!("Failed to log webhook delivery {}: {}", job.id, e);
+        }
+        if outcome.is_ok() {
+            info!(
+                "Delivered {} event for order {} to {}",
+                payload.event.kind.as_str(),
+                payload.event.order_id,
+                subscriber.url
+            );
+        }
+        outcome
+    }
+}
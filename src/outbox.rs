@@ -0,0 +1,378 @@
+use crate::events::OrderEvent;
+use async_trait::async_trait;
+use serde::Serialize;
+use sqlx::sqlite::{Sqlite, SqlitePool};
+use sqlx::{Row, Transaction};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// Default prefix of the subjects/topics events are published to; the event
+/// kind is appended, e.g. `toptop.orders.created`
+pub const DEFAULT_SUBJECT_PREFIX: &str = "toptop.orders";
+
+/// Outbox rows relayed per round
+const RELAY_BATCH: i64 = 100;
+
+/// How long published rows are kept before pruning
+const PUBLISHED_RETENTION_SECS: i64 = 7 * 24 * 3600;
+
+/// Message broker that outbox events are relayed to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SinkTarget {
+    /// NATS server URL; events go to JetStream and wait for its ack
+    Nats(String),
+    /// Comma-separated Kafka bootstrap servers
+    Kafka(String),
+}
+
+impl SinkTarget {
+    /// Parse `nats://host:4222` or `kafka://broker1:9092,broker2:9092`.
+    /// Fails for brokers this binary was built without.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let value = value.trim();
+        if value.starts_with("nats://") || value.starts_with("tls://") {
+            if !cfg!(feature = "nats") {
+                return Err("NATS support needs the `nats` feature".to_string());
+            }
+            return Ok(SinkTarget::Nats(value.to_string()));
+        }
+        if let Some(brokers) = value.strip_prefix("kafka://") {
+            if !cfg!(feature = "kafka") {
+                return Err("Kafka support needs the `kafka` feature".to_string());
+            }
+            if brokers.split(',').all(|b| b.trim().is_empty()) {
+                return Err("no Kafka brokers given".to_string());
+            }
+            return Ok(SinkTarget::Kafka(brokers.to_string()));
+        }
+        Err(format!(
+            "{:?} is neither a nats:// nor a kafka:// address",
+            value
+        ))
+    }
+
+    pub fn broker(&self) -> &'static str {
+        match self {
+            SinkTarget::Nats(_) => "NATS",
+            SinkTarget::Kafka(_) => "Kafka",
+        }
+    }
+}
+
+/// A broker that acknowledges published messages
+#[async_trait]
+pub trait EventSink: Send + Sync {
+    /// Publish `payload` to `subject`, returning once the broker has it.
+    /// `key` is the order id, used for partitioning where supported.
+    async fn publish(&self, subject: &str, key: &str, payload: &[u8]) -> Result<(), String>;
+}
+
+/// Connect to `target`. Connections are established lazily, so an
+/// unreachable broker only delays relaying.
+pub async fn connect(target: &SinkTarget) -> Result<Arc<dyn EventSink>, String> {
+    match target {
+        #[cfg(feature = "nats")]
+        SinkTarget::Nats(url) => Ok(Arc::new(NatsSink::connect(url).await?)),
+        #[cfg(feature = "kafka")]
+        SinkTarget::Kafka(brokers) => Ok(Arc::new(KafkaSink::connect(brokers)?)),
+        #[allow(unreachable_patterns)]
+        _ => Err(format!("{} support was not compiled in", target.broker())),
+    }
+}
+
+/// Publishes to NATS JetStream; a stream must capture the subjects
+#[cfg(feature = "nats")]
+pub struct NatsSink {
+    jetstream: async_nats::jetstream::Context,
+}
+
+#[cfg(feature = "nats")]
+impl NatsSink {
+    pub async fn connect(url: &str) -> Result<Self, String> {
+        let client = async_nats::ConnectOptions::new()
+            .retry_on_initial_connect()
+            .connect(url)
+            .await
+            .map_err(|e| format!("NATS connect to {} failed: {}", url, e))?;
+        Ok(Self {
+            jetstream: async_nats::jetstream::new(client),
+        })
+    }
+}
+
+#[cfg(feature = "nats")]
+#[async_trait]
+impl EventSink for NatsSink {
+    async fn publish(&self, subject: &str, _key: &str, payload: &[u8]) -> Result<(), String> {
+        self.jetstream
+            .publish(subject.to_string(), payload.to_vec().into())
+            .await
+            .map_err(|e| e.to_string())?
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+}
+
+/// Publishes to Kafka topics with `acks=all`
+#[cfg(feature = "kafka")]
+pub struct KafkaSink {
+    producer: rdkafka::producer::FutureProducer,
+}
+
+#[cfg(feature = "kafka")]
+impl KafkaSink {
+    pub fn connect(brokers: &str) -> Result<Self, String> {
+        let producer = rdkafka::ClientConfig::new()
+            .set("bootstrap.servers", brokers)
+            .set("acks", "all")
+            .set("enable.idempotence", "true")
+            .create()
+            .map_err(|e| format!("Kafka producer for {} failed: {}", brokers, e))?;
+        Ok(Self { producer })
+    }
+}
+
+#[cfg(feature = "kafka")]
+#[async_trait]
+impl EventSink for KafkaSink {
+    async fn publish(&self, subject: &str, key: &str, payload: &[u8]) -> Result<(), String> {
+        let record = rdkafka::producer::FutureRecord::to(subject)
+            .key(key)
+            .payload(payload);
+        self.producer
+            .send(record, Duration::from_secs(10))
+            .await
+            .map_err(|(e, _)| e.to_string())?;
+        Ok(())
+    }
+}
+
+/// A recorded event waiting to be relayed
+#[derive(Debug, Clone)]
+pub struct OutboxEntry {
+    pub id: i64,
+    pub kind: String,
+    pub order_id: String,
+    pub payload: String,
+    pub attempts: i64,
+}
+
+/// What consumers receive; `event_id` repeats when a publish is retried
+#[derive(Serialize)]
+struct OutboxMessage<'a> {
+    event_id: i64,
+    #[serde(flatten)]
+    event: &'a serde_json::Value,
+}
+
+/// Order events waiting for the broker, in the `event_outbox` table.
+///
+/// Events are written in the same transaction as the order change, so a crash
+/// between commit and publish only delays them.
+#[derive(Clone)]
+pub struct OutboxStore {
+    pool: SqlitePool,
+}
+
+impl OutboxStore {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Initialize the event_outbox table
+    pub async fn init(&self) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS event_outbox (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                kind TEXT NOT NULL,
+                order_id TEXT NOT NULL,
+                payload TEXT NOT NULL,
+                created_at INTEGER NOT NULL,
+                attempts INTEGER NOT NULL DEFAULT 0,
+                last_error TEXT,
+                published_at INTEGER
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            "CREATE INDEX IF NOT EXISTS idx_event_outbox_published
+            ON event_outbox (published_at, id)",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Store events within an upsert transaction
+    pub async fn record(
+        tx: &mut Transaction<'_, Sqlite>,
+        events: &[OrderEvent],
+    ) -> Result<(), sqlx::Error> {
+        let created_at = chrono::Utc::now().timestamp();
+        for event in events {
+            sqlx::query(
+                "INSERT INTO event_outbox (kind, order_id, payload, created_at)
+                VALUES (?1, ?2, ?3, ?4)",
+            )
+            .bind(event.kind.as_str())
+            .bind(&event.order_id)
+            .bind(serde_json::to_string(event).unwrap_or_default())
+            .bind(created_at)
+            .execute(&mut **tx)
+            .await?;
+        }
+        Ok(())
+    }
+
+    /// Oldest unpublished events first
+    pub async fn pending(&self, limit: i64) -> Result<Vec<OutboxEntry>, sqlx::Error> {
+        sqlx::query(
+            "SELECT id, kind, order_id, payload, attempts FROM event_outbox
+            WHERE published_at IS NULL ORDER BY id LIMIT ?1",
+        )
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(|row| {
+            Ok(OutboxEntry {
+                id: row.try_get("id")?,
+                kind: row.try_get("kind")?,
+                order_id: row.try_get("order_id")?,
+                payload: row.try_get("payload")?,
+                attempts: row.try_get("attempts")?,
+            })
+        })
+        .collect()
+    }
+
+    pub async fn mark_published(&self, id: i64) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "UPDATE event_outbox SET published_at = ?1, attempts = attempts + 1, last_error = NULL
+            WHERE id = ?2",
+        )
+        .bind(chrono::Utc::now().timestamp())
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn mark_failed(&self, id: i64, error: &str) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "UPDATE event_outbox SET attempts = attempts + 1, last_error = ?1 WHERE id = ?2",
+        )
+        .bind(error)
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Delete events published more than `retention_secs` ago
+    pub async fn prune(&self, retention_secs: i64) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query(
+            "DELETE FROM event_outbox WHERE published_at IS NOT NULL AND published_at < ?1",
+        )
+        .bind(chrono::Utc::now().timestamp() - retention_secs)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+}
+
+/// Publishes outbox events to the broker in the order they were recorded.
+///
+/// Delivery is at least once: an event is marked published only after the
+/// broker acknowledged it, and a failed publish holds back later events until
+/// it succeeds.
+pub struct OutboxRelay {
+    store: OutboxStore,
+    sink: Arc<dyn EventSink>,
+    subject_prefix: String,
+}
+
+impl OutboxRelay {
+    pub fn new(store: OutboxStore, sink: Arc<dyn EventSink>, subject_prefix: String) -> Self {
+        Self {
+            store,
+            sink,
+            subject_prefix,
+        }
+    }
+
+    pub async fn run(self) {
+        let mut failures: u32 = 0;
+        loop {
+            let delay = match self.relay_once().await {
+                Ok(0) => {
+                    failures = 0;
+                    if let Err(e) = self.store.prune(PUBLISHED_RETENTION_SECS).await {
+                        warn!("Failed to prune event outbox: {}", e);
+                    }
+                    Duration::from_secs(1)
+                }
+                Ok(published) => {
+                    failures = 0;
+                    info!("Published {} order events", published);
+                    Duration::ZERO
+                }
+                Err(e) => {
+                    failures = failures.saturating_add(1);
+                    let delay = Duration::from_secs(2u64.saturating_pow(failures).min(60));
+                    warn!("Event relay stalled, retrying in {:?}: {}", delay, e);
+                    delay
+                }
+            };
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    /// Publish the next batch; stops at the first failure
+    async fn relay_once(&self) -> Result<usize, String> {
+        let entries = self
+            .store
+            .pending(RELAY_BATCH)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let mut published = 0;
+        for entry in entries {
+            let event: serde_json::Value = serde_json::from_str(&entry.payload)
+                .map_err(|e| format!("Outbox event {} is not JSON: {}", entry.id, e))?;
+            let message = serde_json::to_vec(&OutboxMessage {
+                event_id: entry.id,
+                event: &event,
+            })
+            .unwrap_or_default();
+            let subject = format!("{}.{}", self.subject_prefix, entry.kind);
+
+            if let Err(e) = self.sink.publish(&subject, &entry.order_id, &message).await {
+                let error = format!(
+                    "{} for order {} (attempt {}): {}",
+                    subject,
+                    entry.order_id,
+                    entry.attempts + 1,
+                    e
+                );
+                self.store
+                    .mark_failed(entry.id, &e)
+                    .await
+                    .map_err(|e| e.to_string())?;
+                return Err(error);
+            }
+            self.store
+                .mark_published(entry.id)
+                .await
+                .map_err(|e| e.to_string())?;
+            published += 1;
+        }
+
+        Ok(published)
+    }
+}
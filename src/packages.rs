@@ -0,0 +1,200 @@
+use crate::fulfillment::{CombinablePackage, SplitPackage};
+use serde::Serialize;
+use sqlx::sqlite::{SqlitePool, SqliteRow};
+use sqlx::{Row, Sqlite, Transaction};
+use utoipa::ToSchema;
+
+/// How a tracked package came about
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PackageOrigin {
+    /// One of several packages an order was split into
+    Split,
+    /// Shared by several combined orders
+    Combine,
+}
+
+impl PackageOrigin {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PackageOrigin::Split => "split",
+            PackageOrigin::Combine => "combine",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "split" => Some(PackageOrigin::Split),
+            "combine" => Some(PackageOrigin::Combine),
+            _ => None,
+        }
+    }
+}
+
+/// A package created through this service, per order it contains
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct TrackedPackage {
+    pub package_id: String,
+    pub order_id: String,
+    pub origin: PackageOrigin,
+    /// Line items of the order in this package; empty for combined packages,
+    /// which hold whole orders
+    pub line_item_ids: Vec<String>,
+    /// Other orders sharing the package
+    pub combined_order_ids: Vec<String>,
+    pub created_at: i64,
+}
+
+/// Packages created by splitting and combining orders, in the
+/// `order_packages` table, one row per package and order
+#[derive(Clone)]
+pub struct PackageStore {
+    pool: SqlitePool,
+}
+
+impl PackageStore {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Initialize the order_packages table
+    pub async fn init(&self) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS order_packages (
+                package_id TEXT NOT NULL,
+                order_id TEXT NOT NULL,
+                origin TEXT NOT NULL,
+                line_item_ids TEXT NOT NULL,
+                combined_order_ids TEXT NOT NULL,
+                created_at INTEGER NOT NULL,
+                PRIMARY KEY (package_id, order_id)
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            "CREATE INDEX IF NOT EXISTS idx_order_packages_order_id
+            ON order_packages (order_id)",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Replace an order's packages with the ones it was split into
+    pub async fn record_split(
+        &self,
+        order_id: &str,
+        packages: &[SplitPackage],
+    ) -> Result<Vec<TrackedPackage>, sqlx::Error> {
+        let now = chrono::Utc::now().timestamp();
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("DELETE FROM order_packages WHERE order_id = ?1")
+            .bind(order_id)
+            .execute(&mut *tx)
+            .await?;
+        for package in packages {
+            insert(
+                &mut tx,
+                &package.id,
+                order_id,
+                PackageOrigin::Split,
+                &package.order_line_item_ids,
+                &[],
+                now,
+            )
+            .await?;
+        }
+        tx.commit().await?;
+        self.for_order(order_id).await
+    }
+
+    /// Record the packages orders were combined into, replacing what was
+    /// tracked for those orders before
+    pub async fn record_combine(&self, packages: &[CombinablePackage]) -> Result<(), sqlx::Error> {
+        let now = chrono::Utc::now().timestamp();
+        let mut tx = self.pool.begin().await?;
+        for package in packages {
+            for order_id in &package.order_ids {
+                sqlx::query("DELETE FROM order_packages WHERE order_id = ?1")
+                    .bind(order_id)
+                    .execute(&mut *tx)
+                    .await?;
+                let others: Vec<String> = package
+                    .order_ids
+                    .iter()
+                    .filter(|id| *id != order_id)
+                    .cloned()
+                    .collect();
+                insert(
+                    &mut tx,
+                    &package.id,
+                    order_id,
+                    PackageOrigin::Combine,
+                    &[],
+                    &others,
+                    now,
+                )
+                .await?;
+            }
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    pub async fn for_order(&self, order_id: &str) -> Result<Vec<TrackedPackage>, sqlx::Error> {
+        sqlx::query(
+            "SELECT package_id, order_id, origin, line_item_ids, combined_order_ids, created_at
+            FROM order_packages WHERE order_id = ?1 ORDER BY package_id",
+        )
+        .bind(order_id)
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(package_from_row)
+        .collect()
+    }
+}
+
+async fn insert(
+    tx: &mut Transaction<'_, Sqlite>,
+    package_id: &str,
+    order_id: &str,
+    origin: PackageOrigin,
+    line_item_ids: &[String],
+    combined_order_ids: &[String],
+    created_at: i64,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT OR REPLACE INTO order_packages (package_id, order_id, origin, line_item_ids,
+            combined_order_ids, created_at)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+    )
+    .bind(package_id)
+    .bind(order_id)
+    .bind(origin.as_str())
+    .bind(serde_json::to_string(line_item_ids).unwrap_or_default())
+    .bind(serde_json::to_string(combined_order_ids).unwrap_or_default())
+    .bind(created_at)
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+fn package_from_row(row: &SqliteRow) -> Result<TrackedPackage, sqlx::Error> {
+    let origin: String = row.try_get("origin")?;
+    let line_item_ids: String = row.try_get("line_item_ids")?;
+    let combined_order_ids: String = row.try_get("combined_order_ids")?;
+    Ok(TrackedPackage {
+        package_id: row.try_get("package_id")?,
+        order_id: row.try_get("order_id")?,
+        origin: PackageOrigin::parse(&origin).ok_or_else(|| {
+            sqlx::Error::Decode(format!("unknown package origin {}", origin).into())
+        })?,
+        line_item_ids: serde_json::from_str(&line_item_ids).unwrap_or_default(),
+        combined_order_ids: serde_json::from_str(&combined_order_ids).unwrap_or_default(),
+        created_at: row.try_get("created_at")?,
+    })
+}
@@ -0,0 +1,378 @@
+use crate::order::{Order, RecipientAddress};
+use printpdf::{
+    BuiltinFont, IndirectFontRef, Line, Mm, PdfDocument, PdfDocumentReference, PdfLayerReference,
+    Point,
+};
+use std::path::PathBuf;
+
+const MARGIN: f32 = 15.0;
+/// Millimetres per typographic point
+const PT: f32 = 0.3528;
+/// Average Helvetica glyph width as a fraction of the font size, used for wrapping
+const AVG_GLYPH_WIDTH: f32 = 0.5;
+
+const TITLE_SIZE: f32 = 18.0;
+const BODY_SIZE: f32 = 10.0;
+const SMALL_SIZE: f32 = 8.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Paper {
+    A4,
+    Letter,
+}
+
+impl Paper {
+    /// Width and height in millimetres
+    fn size(self) -> (f32, f32) {
+        match self {
+            Paper::A4 => (210.0, 297.0),
+            Paper::Letter => (215.9, 279.4),
+        }
+    }
+}
+
+impl std::str::FromStr for Paper {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "a4" => Ok(Paper::A4),
+            "letter" => Ok(Paper::Letter),
+            other => Err(format!("expected a4 or letter, got {}", other)),
+        }
+    }
+}
+
+/// Shop-specific parts of the packing slip
+#[derive(Debug, Clone)]
+pub struct PackingSlipTemplate {
+    pub title: String,
+    /// Printed top right, e.g. shop name and return address
+    pub header_lines: Vec<String>,
+    pub footer: Option<String>,
+    /// Include unit prices and order totals (off for gift-style slips)
+    pub show_prices: bool,
+    pub paper: Paper,
+    /// TrueType font used for all text. The built-in Helvetica only covers
+    /// Windows-1252, so other scripts (e.g. Vietnamese) need one.
+    pub font_path: Option<PathBuf>,
+}
+
+impl Default for PackingSlipTemplate {
+    fn default() -> Self {
+        Self {
+            title: "Packing Slip".to_string(),
+            header_lines: Vec::new(),
+            footer: None,
+            show_prices: true,
+            paper: Paper::A4,
+            font_path: None,
+        }
+    }
+}
+
+/// One slip row: items of the same SKU are listed once with their total quantity
+struct SlipLine {
+    quantity: i32,
+    name: String,
+    seller_sku: String,
+    unit_price: String,
+}
+
+fn slip_lines(order: &Order) -> Vec<SlipLine> {
+    let mut lines: Vec<(String, SlipLine)> = Vec::new();
+
+    for item in &order.item_list {
+        let quantity = item.quantity.unwrap_or(1);
+        if let Some((_, line)) = lines.iter_mut().find(|(sku, _)| *sku == item.sku_id) {
+            line.quantity += quantity;
+            continue;
+        }
+
+        let name = match item.sku_name.as_deref().filter(|s| !s.is_empty()) {
+            Some(sku_name) => format!("{} ({})", item.product_name, sku_name),
+            None => item.product_name.clone(),
+        };
+        lines.push((
+            item.sku_id.clone(),
+            SlipLine {
+                quantity,
+                name,
+                seller_sku: item.seller_sku.clone().unwrap_or_default(),
+                unit_price: item.sale_price.clone(),
+            },
+        ));
+    }
+
+    lines.into_iter().map(|(_, line)| line).collect()
+}
+
+/// Ship-to block: name, street, districts from smallest to largest, postal code, phone
+fn address_lines(address: &RecipientAddress) -> Vec<String> {
+    let mut lines = Vec::new();
+    lines.extend(address.name.clone());
+
+    let mut street: Vec<String> = [
+        &address.address_line1,
+        &address.address_line2,
+        &address.address_line3,
+        &address.address_line4,
+    ]
+    .into_iter()
+    .flatten()
+    .filter(|l| !l.is_empty())
+    .cloned()
+    .collect();
+
+    if street.is_empty() {
+        street.extend(address.address_detail.clone().filter(|d| !d.is_empty()));
+    }
+    let districts: Vec<&str> = address
+        .district_info
+        .iter()
+        .rev()
+        .map(|d| d.address_name.as_str())
+        .filter(|n| !n.is_empty())
+        .collect();
+
+    match address.full_address.as_ref().filter(|f| !f.is_empty()) {
+        Some(full) if street.is_empty() || districts.is_empty() => lines.push(full.clone()),
+        _ => {
+            lines.extend(street);
+            if !districts.is_empty() {
+                lines.push(districts.join(", "));
+            }
+        }
+    }
+
+    lines.extend(address.postal_code.clone().filter(|p| !p.is_empty()));
+    lines.extend(address.phone.clone().filter(|p| !p.is_empty()));
+    lines
+}
+
+/// Greedy word wrap to roughly `width` millimetres at `size` points
+fn wrap(text: &str, width: f32, size: f32) -> Vec<String> {
+    let max_chars = ((width / (size * PT * AVG_GLYPH_WIDTH)) as usize).max(1);
+    let mut lines = Vec::new();
+    let mut current = String::new();
+
+    for word in text.split_whitespace() {
+        let len = current.chars().count();
+        if len > 0 && len + 1 + word.chars().count() > max_chars {
+            lines.push(std::mem::take(&mut current));
+        }
+        if !current.is_empty() {
+            current.push(' ');
+        }
+        current.push_str(word);
+    }
+    if !current.is_empty() || lines.is_empty() {
+        lines.push(current);
+    }
+    lines
+}
+
+/// Places text top-down, starting a new page when the current one is full
+struct SlipWriter {
+    doc: PdfDocumentReference,
+    layer: PdfLayerReference,
+    regular: IndirectFontRef,
+    bold: IndirectFontRef,
+    paper: Paper,
+    y: f32,
+}
+
+impl SlipWriter {
+    fn new(title: &str, template: &PackingSlipTemplate) -> Result<Self, String> {
+        let (width, height) = template.paper.size();
+        let (doc, page, layer) = PdfDocument::new(title, Mm(width), Mm(height), "Slip");
+        let layer = doc.get_page(page).get_layer(layer);
+
+        let (regular, bold) = match &template.font_path {
+            Some(path) => {
+                let file = std::fs::File::open(path)
+                    .map_err(|e| format!("cannot open font {}: {}", path.display(), e))?;
+                let font = doc.add_external_font(file).map_err(|e| e.to_string())?;
+                (font.clone(), font)
+            }
+            None => (
+                doc.add_builtin_font(BuiltinFont::Helvetica)
+                    .map_err(|e| e.to_string())?,
+                doc.add_builtin_font(BuiltinFont::HelveticaBold)
+                    .map_err(|e| e.to_string())?,
+            ),
+        };
+
+        Ok(Self {
+            doc,
+            layer,
+            regular,
+            bold,
+            paper: template.paper,
+            y: height - MARGIN,
+        })
+    }
+
+    fn width(&self) -> f32 {
+        self.paper.size().0 - 2.0 * MARGIN
+    }
+
+    fn line_height(size: f32) -> f32 {
+        size * PT * 1.4
+    }
+
+    /// Move down `height` millimetres, breaking the page if that would cross the bottom margin
+    fn advance(&mut self, height: f32) {
+        if self.y - height < MARGIN {
+            let (width, page_height) = self.paper.size();
+            let (page, layer) = self.doc.add_page(Mm(width), Mm(page_height), "Slip");
+            self.layer = self.doc.get_page(page).get_layer(layer);
+            self.y = page_height - MARGIN;
+        }
+        self.y -= height;
+    }
+
+    /// Write `text` at offset `x` from the left margin on the current baseline
+    fn put(&self, x: f32, text: &str, size: f32, bold: bool) {
+        let font = if bold { &self.bold } else { &self.regular };
+        self.layer
+            .use_text(text, size, Mm(MARGIN + x), Mm(self.y), font);
+    }
+
+    fn line(&mut self, text: &str, size: f32, bold: bool) {
+        self.advance(Self::line_height(size));
+        self.put(0.0, text, size, bold);
+    }
+
+    fn rule(&mut self) {
+        self.advance(2.0);
+        self.layer.add_line(Line {
+            points: vec![
+                (Point::new(Mm(MARGIN), Mm(self.y)), false),
+                (Point::new(Mm(MARGIN + self.width()), Mm(self.y)), false),
+            ],
+            is_closed: false,
+        });
+        self.advance(1.0);
+    }
+
+    fn gap(&mut self) {
+        self.advance(Self::line_height(BODY_SIZE) / 2.0);
+    }
+}
+
+/// Render a print-ready packing slip for `order`
+pub fn render(order: &Order, template: &PackingSlipTemplate) -> Result<Vec<u8>, String> {
+    let mut w = SlipWriter::new(&format!("{} {}", template.title, order.id), template)?;
+    let half = w.width() / 2.0;
+
+    // Title on the left, shop header on the right
+    w.line(&template.title, TITLE_SIZE, true);
+    let title_y = w.y;
+    for (i, header) in template.header_lines.iter().enumerate() {
+        let size = if i == 0 { BODY_SIZE } else { SMALL_SIZE };
+        if i > 0 {
+            w.y -= SlipWriter::line_height(size);
+        }
+        w.put(half, header, size, i == 0);
+    }
+    w.y = w.y.min(title_y);
+
+    w.gap();
+    w.line(&format!("Order {}", order.id), BODY_SIZE, true);
+    let placed = chrono::DateTime::from_timestamp(order.create_time, 0)
+        .map(|t| t.format("%Y-%m-%d %H:%M UTC").to_string())
+        .unwrap_or_default();
+    w.line(&format!("Placed {}", placed), BODY_SIZE, false);
+    if let Some(option) = &order.delivery_option_name {
+        w.line(&format!("Delivery: {}", option), BODY_SIZE, false);
+    }
+
+    if let Some(address) = &order.recipient_address {
+        w.gap();
+        w.line("Ship to", BODY_SIZE, true);
+        for line in address_lines(address) {
+            for wrapped in wrap(&line, half, BODY_SIZE) {
+                w.line(&wrapped, BODY_SIZE, false);
+            }
+        }
+    }
+
+    // Items table
+    let item_x = 12.0;
+    let (sku_x, price_x) = if template.show_prices {
+        (w.width() - 70.0, w.width() - 28.0)
+    } else {
+        (w.width() - 40.0, w.width())
+    };
+    let item_width = sku_x - item_x - 3.0;
+
+    w.gap();
+    w.line("Qty", BODY_SIZE, true);
+    w.put(item_x, "Item", BODY_SIZE, true);
+    w.put(sku_x, "SKU", BODY_SIZE, true);
+    if template.show_prices {
+        w.put(price_x, "Unit price", BODY_SIZE, true);
+    }
+    w.rule();
+
+    for line in slip_lines(order) {
+        let mut name = wrap(&line.name, item_width, BODY_SIZE).into_iter();
+        w.line(&line.quantity.to_string(), BODY_SIZE, false);
+        w.put(item_x, &name.next().unwrap_or_default(), BODY_SIZE, false);
+        w.put(sku_x, &line.seller_sku, SMALL_SIZE, false);
+        if template.show_prices {
+            w.put(price_x, &line.unit_price, BODY_SIZE, false);
+        }
+        for rest in name {
+            w.advance(SlipWriter::line_height(BODY_SIZE));
+            w.put(item_x, &rest, BODY_SIZE, false);
+        }
+    }
+    w.rule();
+
+    if let (true, Some(payment)) = (template.show_prices, &order.payment) {
+        let amount = |value: &str| format!("{} {}", value, payment.currency);
+        let mut totals = vec![
+            ("Subtotal", amount(&payment.sub_total)),
+            ("Shipping", amount(&payment.shipping_fee)),
+        ];
+        for (label, value) in [
+            ("Seller discount", &payment.seller_discount),
+            ("Platform discount", &payment.platform_discount),
+        ] {
+            if value.parse::<f64>().is_ok_and(|v| v != 0.0) {
+                totals.push((label, format!("-{}", amount(value))));
+            }
+        }
+        if let Some(tax) = payment.tax.as_deref().filter(|t| !t.is_empty()) {
+            totals.push(("Tax", amount(tax)));
+        }
+
+        for (label, value) in totals {
+            w.advance(SlipWriter::line_height(BODY_SIZE));
+            w.put(sku_x, label, BODY_SIZE, false);
+            w.put(price_x, &value, BODY_SIZE, false);
+        }
+        w.advance(SlipWriter::line_height(BODY_SIZE));
+        w.put(sku_x, "Total", BODY_SIZE, true);
+        w.put(price_x, &amount(&payment.total_amount), BODY_SIZE, true);
+    }
+
+    if let Some(message) = order.buyer_message.as_ref().filter(|m| !m.is_empty()) {
+        w.gap();
+        w.line("Buyer note", BODY_SIZE, true);
+        for wrapped in wrap(message, w.width(), BODY_SIZE) {
+            w.line(&wrapped, BODY_SIZE, false);
+        }
+    }
+
+    if let Some(footer) = &template.footer {
+        w.gap();
+        for wrapped in wrap(footer, w.width(), SMALL_SIZE) {
+            w.line(&wrapped, SMALL_SIZE, false);
+        }
+    }
+
+    w.doc.save_to_bytes().map_err(|e| e.to_string())
+}
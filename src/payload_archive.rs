@@ -0,0 +1,293 @@
+use crate::aws_sigv4::{self, SigningParams};
+use crate::config::Config;
+use crate::jobs::{Job, JobHandler, JobQueue};
+use crate::redact;
+use crate::requests::{ApiRecorder, RecordedCall};
+use crate::shops::ShopRegistry;
+use crate::transport::{HttpRequest, HttpTransport};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use reqwest::{Method, StatusCode};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use tracing::{debug, warn};
+
+/// Upload one raw payload to the archive bucket
+pub const ARCHIVE_JOB_KIND: &str = "payload_archive";
+
+/// Region signed for unless `ARCHIVE_S3_REGION` names another
+pub const DEFAULT_S3_REGION: &str = "us-east-1";
+
+/// API path whose responses are archived as order pages
+const ORDER_SEARCH_PATH: &str = "/orders/search";
+
+/// Shop partition of payloads whose shop isn't known
+const UNKNOWN_SHOP: &str = "unknown";
+
+/// S3-compatible bucket raw API payloads are archived to
+#[derive(Clone)]
+pub struct ArchiveSettings {
+    /// Path-style bucket URL, e.g. `https://s3.eu-west-1.amazonaws.com/my-bucket`
+    pub url: String,
+    pub region: String,
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    /// Prepended to every object key
+    pub prefix: Option<String>,
+}
+
+impl std::fmt::Debug for ArchiveSettings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ArchiveSettings")
+            .field("url", &self.url)
+            .field("region", &self.region)
+            .field("access_key_id", &self.access_key_id)
+            .field("secret_access_key", &redact::mask(&self.secret_access_key))
+            .field("prefix", &self.prefix)
+            .finish()
+    }
+}
+
+/// Kind of archived payload, the first partition of its key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PayloadSource {
+    /// Response of one `orders/search` page
+    OrderPage,
+    /// Body of a TikTok webhook delivery
+    Webhook,
+}
+
+impl PayloadSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PayloadSource::OrderPage => "order_pages",
+            PayloadSource::Webhook => "webhooks",
+        }
+    }
+}
+
+/// Key `body` is stored under:
+/// `[prefix/]<source>/dt=<YYYY-MM-DD>/shop=<shop id>/<unix millis>-<sha256 prefix>.json`
+pub fn object_key(
+    prefix: Option<&str>,
+    source: PayloadSource,
+    shop_id: &str,
+    at: DateTime<Utc>,
+    body: &str,
+) -> String {
+    let digest = hex::encode(Sha256::digest(body.as_bytes()));
+    let key = format!(
+        "{}/dt={}/shop={}/{}-{}.json",
+        source.as_str(),
+        at.format("%Y-%m-%d"),
+        shop_id,
+        at.timestamp_millis(),
+        &digest[..12]
+    );
+    match prefix
+        .map(|p| p.trim_matches('/'))
+        .filter(|p| !p.is_empty())
+    {
+        Some(prefix) => format!("{}/{}", prefix, key),
+        None => key,
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ArchiveJobPayload {
+    key: String,
+    body: String,
+}
+
+/// Queues raw payloads for upload. As an [`ApiRecorder`] it archives every
+/// successful `orders/search` response, then hands the call on to `inner`.
+#[derive(Clone)]
+pub struct PayloadArchive {
+    job_queue: JobQueue,
+    shops: ShopRegistry,
+    config: Config,
+    prefix: Option<String>,
+    inner: Option<Arc<dyn ApiRecorder>>,
+}
+
+impl std::fmt::Debug for PayloadArchive {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PayloadArchive")
+            .field("prefix", &self.prefix)
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
+
+impl PayloadArchive {
+    pub fn new(job_queue: JobQueue, shops: ShopRegistry, config: Config) -> Self {
+        let prefix = config
+            .payload_archive
+            .as_ref()
+            .and_then(|settings| settings.prefix.clone());
+        Self {
+            job_queue,
+            shops,
+            config,
+            prefix,
+            inner: None,
+        }
+    }
+
+    /// Recorder that also gets every call, e.g. the API call log
+    pub fn with_inner(mut self, inner: Option<Arc<dyn ApiRecorder>>) -> Self {
+        self.inner = inner;
+        self
+    }
+
+    /// Queue `body` for upload. Payloads without a shop go under the shop
+    /// the stored token acts for.
+    pub async fn archive(
+        &self,
+        source: PayloadSource,
+        shop_id: Option<&str>,
+        body: &str,
+    ) -> Result<(), sqlx::Error> {
+        let shop_id = match shop_id {
+            Some(shop_id) => shop_id.to_string(),
+            None => self.shops.configured_shop_id(&self.config).await?,
+        };
+        let shop_id = if shop_id.is_empty() {
+            UNKNOWN_SHOP
+        } else {
+            &shop_id
+        };
+        let key = object_key(self.prefix.as_deref(), source, shop_id, Utc::now(), body);
+        let payload = serde_json::to_value(ArchiveJobPayload {
+            key: key.clone(),
+            body: body.to_string(),
+        })
+        .unwrap_or_default();
+        self.job_queue
+            .enqueue(
+                ARCHIVE_JOB_KIND,
+                Some(&format!("{}:{}", ARCHIVE_JOB_KIND, key)),
+                &payload,
+                self.config.job_max_attempts,
+            )
+            .await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ApiRecorder for PayloadArchive {
+    async fn record(&self, call: RecordedCall) {
+        let archivable = call.path.ends_with(ORDER_SEARCH_PATH)
+            && call.status == Some(StatusCode::OK.as_u16())
+            && call.api_code == Some(0);
+        if let (true, Some(body)) = (archivable, &call.response_body) {
+            let shop_id = call.params.get("shop_id").map(String::as_str);
+            if let Err(e) = self.archive(PayloadSource::OrderPage, shop_id, body).await {
+                warn!("Failed to queue archiving an order page: {}", e);
+            }
+        }
+        if let Some(inner) = &self.inner {
+            inner.record(call).await;
+        }
+    }
+}
+
+/// Minimal S3 client: `PutObject` signed with AWS Signature Version 4
+pub struct S3Client {
+    settings: ArchiveSettings,
+    transport: Arc<dyn HttpTransport>,
+}
+
+impl S3Client {
+    pub fn new(settings: ArchiveSettings, transport: Arc<dyn HttpTransport>) -> Self {
+        Self {
+            settings,
+            transport,
+        }
+    }
+
+    /// Store `body` under `key`. Objects are never overwritten; a key that
+    /// already exists counts as stored.
+    pub async fn put_object(&self, key: &str, body: &str) -> Result<(), String> {
+        let encoded_key: Vec<String> = key.split('/').map(aws_sigv4::uri_encode).collect();
+        let url = format!(
+            "{}/{}",
+            self.settings.url.trim_end_matches('/'),
+            encoded_key.join("/")
+        );
+        let parsed = reqwest::Url::parse(&url).map_err(|e| format!("{}: {}", url, e))?;
+        let host = match (parsed.host_str(), parsed.port()) {
+            (Some(host), Some(port)) => format!("{}:{}", host, port),
+            (Some(host), None) => host.to_string(),
+            (None, _) => return Err(format!("{} has no host", url)),
+        };
+
+        let params = SigningParams {
+            access_key_id: &self.settings.access_key_id,
+            secret_access_key: &self.settings.secret_access_key,
+            region: &self.settings.region,
+            service: "s3",
+            time: Utc::now(),
+        };
+        let amz_date = params.amz_date();
+        let payload_hash = aws_sigv4::payload_hash(body.as_bytes());
+        let authorization = aws_sigv4::authorization(
+            &params,
+            "PUT",
+            parsed.path(),
+            "",
+            &[
+                ("host", &host),
+                ("x-amz-content-sha256", &payload_hash),
+                ("x-amz-date", &amz_date),
+            ],
+            &payload_hash,
+        );
+
+        let request = HttpRequest::new(Method::PUT, url.clone())
+            .with_header("Authorization", &authorization)
+            .with_header("x-amz-content-sha256", &payload_hash)
+            .with_header("x-amz-date", &amz_date)
+            .with_header("Content-Type", "application/json")
+            .with_header("If-None-Match", "*")
+            .with_body(body.to_string());
+        let response = self
+            .transport
+            .send(request)
+            .await
+            .map_err(|e| format!("{}: {}", url, e))?;
+        if response.status == StatusCode::PRECONDITION_FAILED {
+            debug!("{} is already archived", key);
+            return Ok(());
+        }
+        if !response.status.is_success() {
+            return Err(format!(
+                "{} answered HTTP {}: {}",
+                url, response.status, response.body
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Uploads queued payloads
+pub struct ArchiveHandler {
+    client: S3Client,
+}
+
+impl ArchiveHandler {
+    pub fn new(client: S3Client) -> Self {
+        Self { client }
+    }
+}
+
+#[async_trait]
+impl JobHandler for ArchiveHandler {
+    async fn handle(&self, job: &Job) -> Result<(), String> {
+        let payload: ArchiveJobPayload = serde_json::from_value(job.payload.clone())
+            .map_err(|e| format!("Invalid payload archive job: {}", e))?;
+        self.client.put_object(&payload.key, &payload.body).await
+    }
+}
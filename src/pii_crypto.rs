@@ -0,0 +1,236 @@
+use crate::database::Database;
+use crate::jobs::{Job, JobHandler};
+use crate::order::Order;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Nonce};
+use async_trait::async_trait;
+use base64::prelude::*;
+use std::sync::Arc;
+use tracing::{info, warn};
+
+/// Encrypt PII stored in plaintext, e.g. before `PII_ENCRYPTION_KEY` was set
+pub const ENCRYPT_JOB_KIND: &str = "pii_encrypt";
+
+/// Prefix of encrypted values; values without it are read as plaintext
+pub const ENCRYPTED_PREFIX: &str = "enc:v1:";
+
+/// Bytes of the AES-GCM nonce stored in front of each ciphertext
+const NONCE_LEN: usize = 12;
+
+/// AES-256-GCM encryption of buyer PII columns: recipient names, phones and
+/// street addresses, buyer emails, normalized streets and customer names.
+/// Each value gets its own random nonce and is stored as
+/// `enc:v1:<base64 nonce + ciphertext>`.
+#[derive(Clone)]
+pub struct PiiCipher {
+    cipher: Arc<Aes256Gcm>,
+}
+
+impl std::fmt::Debug for PiiCipher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("PiiCipher(***)")
+    }
+}
+
+impl PiiCipher {
+    /// Cipher for a base64-encoded 32-byte key, e.g. from `openssl rand -base64 32`
+    pub fn from_base64(key: &str) -> Result<Self, String> {
+        let key = BASE64_STANDARD
+            .decode(key.trim())
+            .map_err(|e| format!("key is not valid base64: {}", e))?;
+        let cipher = Aes256Gcm::new_from_slice(&key)
+            .map_err(|_| format!("key must be 32 bytes, got {}", key.len()))?;
+        Ok(Self {
+            cipher: Arc::new(cipher),
+        })
+    }
+
+    /// Whether `value` was written by [`Self::encrypt`]
+    pub fn is_encrypted(value: &str) -> bool {
+        value.starts_with(ENCRYPTED_PREFIX)
+    }
+
+    pub fn encrypt(&self, value: &str) -> String {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, value.as_bytes())
+            .expect("AES-GCM encrypts any plaintext that fits in memory");
+        let mut sealed = nonce.to_vec();
+        sealed.extend(ciphertext);
+        format!("{}{}", ENCRYPTED_PREFIX, BASE64_STANDARD.encode(sealed))
+    }
+
+    /// Plaintext of `value`. Values that aren't encrypted come back as they are.
+    pub fn decrypt(&self, value: &str) -> Result<String, String> {
+        let Some(encoded) = value.strip_prefix(ENCRYPTED_PREFIX) else {
+            return Ok(value.to_string());
+        };
+        let sealed = BASE64_STANDARD
+            .decode(encoded)
+            .map_err(|e| format!("malformed ciphertext: {}", e))?;
+        if sealed.len() < NONCE_LEN {
+            return Err("malformed ciphertext: too short".to_string());
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        let plaintext = self
+            .cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| "ciphertext doesn't match PII_ENCRYPTION_KEY".to_string())?;
+        String::from_utf8(plaintext).map_err(|e| format!("decrypted value isn't UTF-8: {}", e))
+    }
+
+    /// Encrypt `value` unless it is unset or already encrypted
+    pub fn encrypt_field(&self, value: &mut Option<String>) {
+        if let Some(plain) = value.as_deref().filter(|v| !Self::is_encrypted(v)) {
+            *value = Some(self.encrypt(plain));
+        }
+    }
+
+    /// Decrypt `value` in place. Values that fail to decrypt, e.g. under
+    /// another key, are left encrypted.
+    pub fn decrypt_field(&self, value: &mut Option<String>) {
+        if let Some(sealed) = value.as_deref().filter(|v| Self::is_encrypted(v)) {
+            match self.decrypt(sealed) {
+                Ok(plain) => *value = Some(plain),
+                Err(e) => warn!("Leaving a PII value encrypted: {}", e),
+            }
+        }
+    }
+
+    /// Copy of `order` with its PII fields encrypted
+    pub fn encrypt_order(&self, order: &Order) -> Order {
+        let mut order = order.clone();
+        pii_fields(&mut order)
+            .into_iter()
+            .for_each(|field| self.encrypt_field(field));
+        order
+    }
+
+    /// `order` with its PII fields decrypted
+    pub fn decrypt_order(&self, mut order: Order) -> Order {
+        pii_fields(&mut order)
+            .into_iter()
+            .for_each(|field| self.decrypt_field(field));
+        order
+    }
+
+    /// Whether any PII field of `order` is stored in plaintext
+    pub fn has_plaintext(order: &Order) -> bool {
+        let mut order = order.clone();
+        pii_fields(&mut order)
+            .into_iter()
+            .any(|field| field.as_deref().is_some_and(|v| !Self::is_encrypted(v)))
+    }
+}
+
+/// What a PII field reads as for callers not allowed to see it
+pub const MASKED: &str = "***";
+
+/// `order` with every PII field that is set replaced by [`MASKED`], for
+/// callers that may see the order but not who bought it
+pub fn mask_order(mut order: Order) -> Order {
+    pii_fields(&mut order)
+        .into_iter()
+        .filter(|field| field.is_some())
+        .for_each(|field| *field = Some(MASKED.to_string()));
+    order
+}
+
+/// Fields of `order` that are encrypted at rest
+fn pii_fields(order: &mut Order) -> Vec<&mut Option<String>> {
+    let mut fields = vec![&mut order.buyer_email];
+    if let Some(address) = order.recipient_address.as_mut() {
+        fields.extend([
+            &mut address.full_address,
+            &mut address.name,
+            &mut address.phone,
+            &mut address.address_detail,
+            &mut address.address_line1,
+            &mut address.address_line2,
+            &mut address.address_line3,
+            &mut address.address_line4,
+            &mut address.first_name,
+            &mut address.last_name,
+            &mut address.first_name_local_script,
+            &mut address.last_name_local_script,
+        ]);
+    }
+    fields
+}
+
+/// Encrypts PII stored before `PII_ENCRYPTION_KEY` was set
+pub struct PiiEncryptHandler {
+    db: Arc<Database>,
+}
+
+impl PiiEncryptHandler {
+    pub fn new(db: Arc<Database>) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl JobHandler for PiiEncryptHandler {
+    async fn handle(&self, _job: &Job) -> Result<(), String> {
+        let encrypted = self
+            .db
+            .encrypt_stored_pii()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        if encrypted > 0 {
+            info!("Encrypted PII stored in plaintext in {} rows", encrypted);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn order() -> Order {
+        serde_json::from_value(serde_json::json!({
+            "id": "576461413038785752",
+            "status": "AWAITING_SHIPMENT",
+            "create_time": 1_700_000_000,
+            "update_time": 1_700_000_000,
+            "buyer_email": "buyer@scs.tiktokw.us",
+            "recipient_address": {
+                "name": "Nguyen Van A",
+                "phone_number": "(+84)901234512",
+                "region_code": "VN"
+            }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn masked_orders_keep_everything_but_pii() {
+        let masked = mask_order(order());
+        let address = masked.recipient_address.unwrap();
+
+        assert_eq!(masked.buyer_email.as_deref(), Some(MASKED));
+        assert_eq!(address.name.as_deref(), Some(MASKED));
+        assert_eq!(address.phone.as_deref(), Some(MASKED));
+        assert_eq!(address.full_address, None);
+        assert_eq!(address.region_code.as_deref(), Some("VN"));
+        assert_eq!(masked.status, "AWAITING_SHIPMENT");
+    }
+
+    #[test]
+    fn encrypted_orders_decrypt_to_the_original() {
+        let cipher = PiiCipher::from_base64(&BASE64_STANDARD.encode([7u8; 32])).unwrap();
+        let encrypted = cipher.encrypt_order(&order());
+
+        assert!(encrypted.buyer_email.as_deref().is_some_and(PiiCipher::is_encrypted));
+        assert!(!PiiCipher::has_plaintext(&encrypted));
+        let decrypted = cipher.decrypt_order(encrypted);
+        assert_eq!(decrypted.buyer_email.as_deref(), Some("buyer@scs.tiktokw.us"));
+        assert_eq!(
+            decrypted.recipient_address.and_then(|a| a.phone).as_deref(),
+            Some("(+84)901234512")
+        );
+    }
+}
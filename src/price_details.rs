@@ -0,0 +1,229 @@
+use crate::config::Config;
+use crate::database::Database;
+use crate::error::AppError;
+use crate::jobs::{Job, JobHandler, JobQueue};
+use crate::order::{Order, PriceDetail};
+use crate::shops::ShopRegistry;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::SqlitePool;
+use sqlx::Row;
+use std::sync::Arc;
+use utoipa::ToSchema;
+
+/// Fetch an order's price detail from TikTok and store it
+pub const FETCH_JOB_KIND: &str = "price_detail_fetch";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FetchJobPayload {
+    pub order_id: String,
+}
+
+/// An order's price detail as last fetched
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct StoredPriceDetail {
+    pub order_id: String,
+    /// `update_time` of the order when it was fetched
+    pub order_update_time: i64,
+    pub detail: PriceDetail,
+    pub fetched_at: i64,
+}
+
+/// Price breakdowns of orders, in the `order_price_details` table. The main
+/// amounts get their own columns for exports; the full detail is kept as JSON.
+#[derive(Clone)]
+pub struct PriceDetailStore {
+    pool: SqlitePool,
+}
+
+impl PriceDetailStore {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Initialize the order_price_details table
+    pub async fn init(&self) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS order_price_details (
+                order_id TEXT PRIMARY KEY,
+                order_update_time INTEGER NOT NULL,
+                currency TEXT NOT NULL,
+                payment TEXT,
+                subtotal TEXT,
+                shipping_fee TEXT,
+                seller_discount TEXT,
+                platform_discount TEXT,
+                tax_amount TEXT,
+                net_price_amount TEXT,
+                detail TEXT NOT NULL,
+                fetched_at INTEGER NOT NULL
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn save(
+        &self,
+        order_id: &str,
+        order_update_time: i64,
+        detail: &PriceDetail,
+    ) -> Result<StoredPriceDetail, sqlx::Error> {
+        let fetched_at = chrono::Utc::now().timestamp();
+        sqlx::query(
+            "INSERT INTO order_price_details (order_id, order_update_time, currency, payment,
+                subtotal, shipping_fee, seller_discount, platform_discount, tax_amount,
+                net_price_amount, detail, fetched_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)
+            ON CONFLICT(order_id) DO UPDATE SET
+                order_update_time = excluded.order_update_time,
+                currency = excluded.currency,
+                payment = excluded.payment,
+                subtotal = excluded.subtotal,
+                shipping_fee = excluded.shipping_fee,
+                seller_discount = excluded.seller_discount,
+                platform_discount = excluded.platform_discount,
+                tax_amount = excluded.tax_amount,
+                net_price_amount = excluded.net_price_amount,
+                detail = excluded.detail,
+                fetched_at = excluded.fetched_at",
+        )
+        .bind(order_id)
+        .bind(order_update_time)
+        .bind(&detail.currency)
+        .bind(&detail.payment)
+        .bind(&detail.subtotal)
+        .bind(&detail.shipping_sale_price)
+        .bind(&detail.subtotal_deduction_seller)
+        .bind(&detail.subtotal_deduction_platform)
+        .bind(&detail.tax_amount)
+        .bind(&detail.net_price_amount)
+        .bind(serde_json::to_string(detail).unwrap_or_default())
+        .bind(fetched_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(StoredPriceDetail {
+            order_id: order_id.to_string(),
+            order_update_time,
+            detail: detail.clone(),
+            fetched_at,
+        })
+    }
+
+    pub async fn get(&self, order_id: &str) -> Result<Option<StoredPriceDetail>, sqlx::Error> {
+        let Some(row) = sqlx::query(
+            "SELECT order_id, order_update_time, detail, fetched_at
+            FROM order_price_details WHERE order_id = ?1",
+        )
+        .bind(order_id)
+        .fetch_optional(&self.pool)
+        .await?
+        else {
+            return Ok(None);
+        };
+
+        let detail: String = row.try_get("detail")?;
+        Ok(Some(StoredPriceDetail {
+            order_id: row.try_get("order_id")?,
+            order_update_time: row.try_get("order_update_time")?,
+            detail: serde_json::from_str(&detail).map_err(|e| sqlx::Error::Decode(e.into()))?,
+            fetched_at: row.try_get("fetched_at")?,
+        }))
+    }
+}
+
+/// Queue a price detail fetch for each order. Jobs are deduplicated per order
+/// version, so an order is fetched again only after it changed.
+pub async fn enqueue_fetch(
+    job_queue: &JobQueue,
+    orders: &[Order],
+    max_attempts: u32,
+) -> Result<usize, sqlx::Error> {
+    let mut queued = 0;
+    for order in orders {
+        let payload = serde_json::to_value(FetchJobPayload {
+            order_id: order.id.clone(),
+        })
+        .unwrap_or_default();
+        if job_queue
+            .enqueue(
+                FETCH_JOB_KIND,
+                Some(&format!(
+                    "{}:{}:{}",
+                    FETCH_JOB_KIND, order.id, order.update_time
+                )),
+                &payload,
+                max_attempts,
+            )
+            .await?
+        {
+            queued += 1;
+        }
+    }
+    Ok(queued)
+}
+
+/// Fetches price details from TikTok into the store
+pub struct PriceDetailFetcher {
+    store: PriceDetailStore,
+    shops: ShopRegistry,
+    config: Config,
+}
+
+impl PriceDetailFetcher {
+    pub fn new(store: PriceDetailStore, shops: ShopRegistry, config: Config) -> Self {
+        Self {
+            store,
+            shops,
+            config,
+        }
+    }
+
+    /// Fetch and store the price detail of a stored order
+    pub async fn fetch(&self, order: &Order) -> Result<StoredPriceDetail, AppError> {
+        let token = self.config.token_manager().valid_token().await?;
+        let detail = self
+            .shops
+            .order_client(&self.config)
+            .await?
+            .get_price_detail(&token.access_token, None, &order.id)
+            .await?;
+        Ok(self
+            .store
+            .save(&order.id, order.update_time, &detail)
+            .await?)
+    }
+}
+
+/// Runs [`FETCH_JOB_KIND`] jobs; needs the order to be stored already
+pub struct PriceDetailFetchHandler {
+    fetcher: PriceDetailFetcher,
+    db: Arc<Database>,
+}
+
+impl PriceDetailFetchHandler {
+    pub fn new(fetcher: PriceDetailFetcher, db: Arc<Database>) -> Self {
+        Self { fetcher, db }
+    }
+}
+
+#[async_trait]
+impl JobHandler for PriceDetailFetchHandler {
+    async fn handle(&self, job: &Job) -> Result<(), String> {
+        let payload: FetchJobPayload = serde_json::from_value(job.payload.clone())
+            .map_err(|e| format!("Invalid price detail payload: {}", e))?;
+        let order = self
+            .db
+            .get_order_by_id(&payload.order_id)
+            .await
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| format!("Order {} is not stored", payload.order_id))?;
+        self.fetcher
+            .fetch(&order)
+            .await
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+}
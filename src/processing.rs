@@ -0,0 +1,476 @@
+use crate::config::Config;
+use crate::database::Database;
+use crate::esim;
+use crate::events::{OrderEvent, OrderEventKind};
+use crate::jobs::JobQueue;
+use crate::order::Order;
+use crate::sku_mappings::SkuMappingStore;
+use crate::threepl;
+use serde::Serialize;
+use sqlx::sqlite::{SqlitePool, SqliteRow};
+use sqlx::Row;
+use std::sync::Arc;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::{error, info, warn};
+use utoipa::ToSchema;
+
+/// Order statuses in which the buyer has not paid yet, or TikTok holds the order
+const UNCONFIRMED_STATUSES: &[&str] = &["UNPAID", "ON_HOLD"];
+
+/// Order statuses waiting for the seller to ship
+const AWAITING_STATUSES: &[&str] = &["AWAITING_SHIPMENT", "AWAITING_COLLECTION"];
+
+/// Order statuses after the package left the seller
+const SHIPPED_STATUSES: &[&str] = &["IN_TRANSIT", "DELIVERED", "COMPLETED"];
+
+/// Where an order is in this service's own processing, independent of its
+/// TikTok status
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ProcessingState {
+    /// Stored, not looked at yet
+    Received,
+    /// Paid and has line items
+    Validated,
+    /// Fulfillment started (eSIM provisioning or the 3PL push queued)
+    Fulfilling,
+    /// Shipped
+    Fulfilled,
+    /// Cancelled or not fulfillable
+    Failed,
+}
+
+impl ProcessingState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProcessingState::Received => "received",
+            ProcessingState::Validated => "validated",
+            ProcessingState::Fulfilling => "fulfilling",
+            ProcessingState::Fulfilled => "fulfilled",
+            ProcessingState::Failed => "failed",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "received" => Some(ProcessingState::Received),
+            "validated" => Some(ProcessingState::Validated),
+            "fulfilling" => Some(ProcessingState::Fulfilling),
+            "fulfilled" => Some(ProcessingState::Fulfilled),
+            "failed" => Some(ProcessingState::Failed),
+            _ => None,
+        }
+    }
+
+    /// Whether an order may move from `self` to `next`. Fulfilled and failed
+    /// orders stay where they are.
+    pub fn can_transition_to(&self, next: ProcessingState) -> bool {
+        use ProcessingState::*;
+        matches!(
+            (self, next),
+            (Received, Validated | Failed)
+                | (Validated, Fulfilling | Fulfilled | Failed)
+                | (Fulfilling, Fulfilled | Failed)
+        )
+    }
+
+    pub fn is_final(&self) -> bool {
+        matches!(self, ProcessingState::Fulfilled | ProcessingState::Failed)
+    }
+}
+
+impl std::fmt::Display for ProcessingState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The step `order` is ready for from `state`, and why. Without `fulfill`
+/// (backfilled orders) a validated order waits for shipment instead of
+/// entering `fulfilling`.
+pub fn next_step(
+    state: ProcessingState,
+    order: &Order,
+    fulfill: bool,
+) -> Option<(ProcessingState, &'static str)> {
+    let status = order.status.as_str();
+    if state.is_final() {
+        return None;
+    }
+    if status == "CANCELLED" {
+        return Some((ProcessingState::Failed, "order cancelled"));
+    }
+
+    match state {
+        ProcessingState::Received if order.item_list.is_empty() => {
+            Some((ProcessingState::Failed, "order has no line items"))
+        }
+        ProcessingState::Received if !UNCONFIRMED_STATUSES.contains(&status) => {
+            Some((ProcessingState::Validated, "order paid"))
+        }
+        ProcessingState::Validated if fulfill && AWAITING_STATUSES.contains(&status) => {
+            Some((ProcessingState::Fulfilling, "awaiting shipment"))
+        }
+        ProcessingState::Validated | ProcessingState::Fulfilling
+            if SHIPPED_STATUSES.contains(&status) =>
+        {
+            Some((ProcessingState::Fulfilled, "order shipped"))
+        }
+        _ => None,
+    }
+}
+
+/// Why a transition was not made
+#[derive(Debug, thiserror::Error)]
+pub enum TransitionError {
+    #[error("order cannot move from {from} to {to}")]
+    Invalid {
+        from: ProcessingState,
+        to: ProcessingState,
+    },
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+}
+
+/// A recorded state change
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ProcessingTransition {
+    pub from_state: ProcessingState,
+    pub to_state: ProcessingState,
+    pub reason: String,
+    pub created_at: i64,
+}
+
+/// Processing state of an order and how it got there
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct OrderProcessing {
+    pub order_id: String,
+    pub state: ProcessingState,
+    /// When the state last changed; unset while still `received`
+    pub updated_at: Option<i64>,
+    /// Oldest first
+    pub transitions: Vec<ProcessingTransition>,
+}
+
+/// Processing states in the `orders.processing_state` column, with every
+/// change logged in `processing_transitions`.
+///
+/// Transitions are compare-and-set on the current state, so when two syncs
+/// (or instances) advance the same order only one of them wins each step and
+/// runs what follows it.
+#[derive(Clone)]
+pub struct ProcessingStore {
+    pool: SqlitePool,
+}
+
+impl ProcessingStore {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Initialize the processing_transitions table
+    pub async fn init(&self) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS processing_transitions (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                order_id TEXT NOT NULL,
+                from_state TEXT NOT NULL,
+                to_state TEXT NOT NULL,
+                reason TEXT NOT NULL,
+                created_at INTEGER NOT NULL
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            "CREATE INDEX IF NOT EXISTS idx_processing_transitions_order
+            ON processing_transitions (order_id)",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// `None` if the order isn't stored
+    pub async fn state(&self, order_id: &str) -> Result<Option<ProcessingState>, sqlx::Error> {
+        Ok(
+            sqlx::query("SELECT processing_state FROM orders WHERE id = ?1")
+                .bind(order_id)
+                .fetch_optional(&self.pool)
+                .await?
+                .map(|row| row.try_get::<String, _>("processing_state"))
+                .transpose()?
+                .map(|state| ProcessingState::parse(&state).unwrap_or(ProcessingState::Received)),
+        )
+    }
+
+    /// Whether the order was first stored by a backfill, so it is never fulfilled
+    pub async fn skips_fulfillment(&self, order_id: &str) -> Result<bool, sqlx::Error> {
+        Ok(
+            sqlx::query("SELECT skip_fulfillment FROM orders WHERE id = ?1")
+                .bind(order_id)
+                .fetch_optional(&self.pool)
+                .await?
+                .map(|row| row.try_get("skip_fulfillment"))
+                .transpose()?
+                .unwrap_or(false),
+        )
+    }
+
+    /// Move an order from `from` to `to`. Returns `false` if it is no longer in
+    /// `from`, i.e. someone else moved it first.
+    pub async fn transition(
+        &self,
+        order_id: &str,
+        from: ProcessingState,
+        to: ProcessingState,
+        reason: &str,
+    ) -> Result<bool, TransitionError> {
+        if !from.can_transition_to(to) {
+            return Err(TransitionError::Invalid { from, to });
+        }
+
+        let now = chrono::Utc::now().timestamp();
+        let mut tx = self.pool.begin().await?;
+        let result = sqlx::query(
+            "UPDATE orders SET processing_state = ?3, processing_updated_at = ?4
+            WHERE id = ?1 AND processing_state = ?2",
+        )
+        .bind(order_id)
+        .bind(from.as_str())
+        .bind(to.as_str())
+        .bind(now)
+        .execute(&mut *tx)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Ok(false);
+        }
+
+        sqlx::query(
+            "INSERT INTO processing_transitions (order_id, from_state, to_state, reason, created_at)
+            VALUES (?1, ?2, ?3, ?4, ?5)",
+        )
+        .bind(order_id)
+        .bind(from.as_str())
+        .bind(to.as_str())
+        .bind(reason)
+        .bind(now)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    pub async fn get(&self, order_id: &str) -> Result<Option<OrderProcessing>, sqlx::Error> {
+        let Some(row) =
+            sqlx::query("SELECT processing_state, processing_updated_at FROM orders WHERE id = ?1")
+                .bind(order_id)
+                .fetch_optional(&self.pool)
+                .await?
+        else {
+            return Ok(None);
+        };
+
+        let state: String = row.try_get("processing_state")?;
+        let transitions = sqlx::query(
+            "SELECT from_state, to_state, reason, created_at FROM processing_transitions
+            WHERE order_id = ?1 ORDER BY id",
+        )
+        .bind(order_id)
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(transition_from_row)
+        .collect::<Result<_, _>>()?;
+
+        Ok(Some(OrderProcessing {
+            order_id: order_id.to_string(),
+            state: ProcessingState::parse(&state).unwrap_or(ProcessingState::Received),
+            updated_at: row.try_get("processing_updated_at")?,
+            transitions,
+        }))
+    }
+
+    /// Orders not yet fulfilled or failed
+    pub async fn unfinished(&self) -> Result<Vec<(String, ProcessingState)>, sqlx::Error> {
+        sqlx::query(
+            "SELECT id, processing_state FROM orders
+            WHERE processing_state NOT IN ('fulfilled', 'failed')
+            ORDER BY create_time",
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(|row| {
+            let state: String = row.try_get("processing_state")?;
+            Ok((
+                row.try_get("id")?,
+                ProcessingState::parse(&state).unwrap_or(ProcessingState::Received),
+            ))
+        })
+        .collect()
+    }
+}
+
+fn transition_from_row(row: &SqliteRow) -> Result<ProcessingTransition, sqlx::Error> {
+    let state = |column: &str| -> Result<ProcessingState, sqlx::Error> {
+        let value: String = row.try_get(column)?;
+        ProcessingState::parse(&value).ok_or_else(|| sqlx::Error::ColumnDecode {
+            index: column.to_string(),
+            source: format!("unknown processing state {:?}", value).into(),
+        })
+    };
+
+    Ok(ProcessingTransition {
+        from_state: state("from_state")?,
+        to_state: state("to_state")?,
+        reason: row.try_get("reason")?,
+        created_at: row.try_get("created_at")?,
+    })
+}
+
+/// Advances orders through their processing states as order events arrive,
+/// and starts fulfillment when an order enters `fulfilling`
+pub struct ProcessingPipeline {
+    db: Arc<Database>,
+    store: ProcessingStore,
+    job_queue: JobQueue,
+    sku_mappings: SkuMappingStore,
+    config: Config,
+}
+
+impl ProcessingPipeline {
+    pub fn new(
+        db: Arc<Database>,
+        store: ProcessingStore,
+        job_queue: JobQueue,
+        sku_mappings: SkuMappingStore,
+        config: Config,
+    ) -> Self {
+        Self {
+            db,
+            store,
+            job_queue,
+            sku_mappings,
+            config,
+        }
+    }
+
+    /// Catch up on unfinished orders, then follow `events` until the bus is
+    /// dropped. Subscribe before calling so nothing published meanwhile is
+    /// missed; if the receiver falls behind, unfinished orders are swept again.
+    pub async fn run(self, mut events: broadcast::Receiver<OrderEvent>) {
+        self.sweep_logged().await;
+
+        loop {
+            match events.recv().await {
+                Ok(event)
+                    if matches!(
+                        event.kind,
+                        OrderEventKind::Created | OrderEventKind::Updated
+                    ) =>
+                {
+                    if let Err(e) = self.advance(&event.order_id).await {
+                        error!("Failed to process order {}: {}", event.order_id, e);
+                    }
+                }
+                Ok(_) => {}
+                Err(RecvError::Lagged(missed)) => {
+                    warn!(
+                        "Processing pipeline missed {} order events, sweeping",
+                        missed
+                    );
+                    self.sweep_logged().await;
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    }
+
+    async fn sweep_logged(&self) {
+        match self.sweep().await {
+            Ok(0) => {}
+            Ok(n) => info!("Processing sweep advanced {} orders", n),
+            Err(e) => error!("Processing sweep failed: {}", e),
+        }
+    }
+
+    /// Advance every unfinished order. Orders already `fulfilling` have their
+    /// fulfillment queued again, which only fills gaps left by a failed
+    /// enqueue since jobs are deduplicated. Returns how many orders moved.
+    pub async fn sweep(&self) -> Result<usize, TransitionError> {
+        let mut advanced = 0;
+        for (order_id, state) in self.store.unfinished().await? {
+            if state == ProcessingState::Fulfilling {
+                if let Some(order) = self.db.get_order_by_id(&order_id).await? {
+                    self.start_fulfillment(&order).await?;
+                }
+            }
+            if self.advance(&order_id).await? != Some(state) {
+                advanced += 1;
+            }
+        }
+        Ok(advanced)
+    }
+
+    /// Take an order as far as its stored data allows. Returns the state it
+    /// ended in, or `None` if it isn't stored.
+    pub async fn advance(
+        &self,
+        order_id: &str,
+    ) -> Result<Option<ProcessingState>, TransitionError> {
+        let Some(order) = self.db.get_order_by_id(order_id).await? else {
+            return Ok(None);
+        };
+        let Some(mut state) = self.store.state(order_id).await? else {
+            return Ok(None);
+        };
+
+        let fulfill = !self.store.skips_fulfillment(order_id).await?;
+
+        while let Some((next, reason)) = next_step(state, &order, fulfill) {
+            if !self.store.transition(order_id, state, next, reason).await? {
+                // Someone else took this step and runs what follows it
+                return Ok(self.store.state(order_id).await?);
+            }
+            info!("Order {} {} -> {} ({})", order_id, state, next, reason);
+
+            if next == ProcessingState::Fulfilling {
+                self.start_fulfillment(&order).await?;
+            }
+            state = next;
+        }
+
+        Ok(Some(state))
+    }
+
+    /// Queue the fulfillment of each item by its SKU's action: eSIMs are
+    /// provisioned, and the order is pushed to the 3PL if anything ships as a
+    /// parcel
+    async fn start_fulfillment(&self, order: &Order) -> Result<(), sqlx::Error> {
+        let mappings = self.sku_mappings.table().await?;
+        let enqueued = esim::enqueue_provisioning(
+            &self.job_queue,
+            std::slice::from_ref(order),
+            &mappings,
+            self.config.job_max_attempts,
+        )
+        .await?;
+        if enqueued > 0 {
+            info!(
+                "Enqueued {} eSIM provisioning jobs for order {}",
+                enqueued, order.id
+            );
+        }
+        if self.config.threepl.is_some()
+            && mappings.has_physical(order)
+            && threepl::enqueue_push(&self.job_queue, order, self.config.job_max_attempts).await?
+        {
+            info!("Enqueued 3PL push of order {}", order.id);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,105 @@
+use crate::error::AppError;
+use crate::requests::TikTokShopApiClient;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Client for the Product API (inventory lookups)
+pub struct ProductClient {
+    api_client: TikTokShopApiClient,
+    shop_cipher: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct InventorySearchRequest {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub product_ids: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub sku_ids: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct InventorySearchResponse {
+    #[serde(default)]
+    pub inventory: Vec<ProductInventory>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ProductInventory {
+    pub product_id: String,
+    #[serde(default)]
+    pub skus: Vec<SkuInventory>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SkuInventory {
+    pub id: String,
+    #[serde(default)]
+    pub total_available_quantity: Option<i64>,
+    #[serde(default)]
+    pub total_committed_quantity: Option<i64>,
+    #[serde(default)]
+    pub warehouse_inventory: Vec<WarehouseInventory>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct WarehouseInventory {
+    pub warehouse_id: String,
+    #[serde(default)]
+    pub available_quantity: Option<i64>,
+    #[serde(default)]
+    pub committed_quantity: Option<i64>,
+}
+
+impl ProductClient {
+    /// TikTok accepts at most this many SKU ids per inventory search
+    pub const MAX_SKUS_PER_SEARCH: usize = 100;
+
+    pub fn new(app_key: String, app_secret: String) -> Self {
+        Self::with_api_client(TikTokShopApiClient::new(app_key, app_secret))
+    }
+
+    pub fn with_api_client(api_client: TikTokShopApiClient) -> Self {
+        Self {
+            api_client,
+            shop_cipher: None,
+        }
+    }
+
+    /// Cipher used when a call doesn't pass one explicitly
+    pub fn with_shop_cipher(mut self, shop_cipher: Option<String>) -> Self {
+        self.shop_cipher = shop_cipher;
+        self
+    }
+
+    /// Current inventory of the given SKUs
+    pub async fn search_inventory(
+        &self,
+        access_token: &str,
+        shop_cipher: Option<&str>,
+        sku_ids: &[String],
+    ) -> Result<Vec<SkuInventory>, AppError> {
+        let shop_cipher = shop_cipher.or(self.shop_cipher.as_deref());
+        let mut skus = Vec::with_capacity(sku_ids.len());
+
+        for chunk in sku_ids.chunks(Self::MAX_SKUS_PER_SEARCH) {
+            let request = InventorySearchRequest {
+                product_ids: Vec::new(),
+                sku_ids: chunk.to_vec(),
+            };
+            let response: InventorySearchResponse = self
+                .api_client
+                .post(
+                    "/product/202309/inventory/search",
+                    Some(access_token),
+                    shop_cipher,
+                    &request,
+                    Some(BTreeMap::new()),
+                )
+                .await?;
+
+            skus.extend(response.inventory.into_iter().flat_map(|p| p.skus));
+        }
+
+        Ok(skus)
+    }
+}
@@ -0,0 +1,146 @@
+use crate::order::Order;
+use crate::reporting;
+use chrono::NaiveDate;
+use chrono_tz::Tz;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use utoipa::ToSchema;
+
+/// Longest range a discount report covers
+pub const MAX_REPORT_DAYS: i64 = 366;
+
+/// A promotion running on the days `start..=end` in `REPORT_TIMEZONE`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct Campaign {
+    pub name: String,
+    #[schema(value_type = String)]
+    pub start: NaiveDate,
+    #[schema(value_type = String)]
+    pub end: NaiveDate,
+}
+
+impl Campaign {
+    pub fn contains(&self, day: NaiveDate) -> bool {
+        self.start <= day && day <= self.end
+    }
+}
+
+/// Parse `name=YYYY-MM-DD..YYYY-MM-DD` pairs separated by commas, e.g.
+/// `9_9_sale=2026-09-09..2026-09-11,payday=2026-09-25`; a single date is a
+/// one-day campaign
+pub fn parse_campaigns(value: &str) -> Result<Vec<Campaign>, String> {
+    let mut campaigns: Vec<Campaign> = Vec::new();
+    for pair in value.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let (name, window) = pair
+            .split_once('=')
+            .map(|(n, w)| (n.trim(), w.trim()))
+            .ok_or_else(|| format!("campaign {:?} is not name=start..end", pair))?;
+        if name.is_empty() {
+            return Err(format!("campaign {:?} has no name", pair));
+        }
+        if campaigns.iter().any(|c| c.name == name) {
+            return Err(format!("campaign {:?} is defined twice", name));
+        }
+        let (start, end) = window.split_once("..").unwrap_or((window, window));
+        let parse = |day: &str| {
+            NaiveDate::parse_from_str(day.trim(), "%Y-%m-%d")
+                .map_err(|_| format!("campaign {:?}: {:?} is not a YYYY-MM-DD date", name, day))
+        };
+        let (start, end) = (parse(start)?, parse(end)?);
+        if start > end {
+            return Err(format!("campaign {:?} ends before it starts", name));
+        }
+        campaigns.push(Campaign {
+            name: name.to_string(),
+            start,
+            end,
+        });
+    }
+    Ok(campaigns)
+}
+
+/// Discount spend of the orders created during one campaign (or outside any)
+/// in one currency. Seller-funded amounts are the promo's cost to the shop;
+/// platform-funded ones are paid for by TikTok.
+#[derive(Debug, Clone, Default, Serialize, ToSchema)]
+pub struct CampaignDiscounts {
+    /// Unset for orders created outside every campaign
+    pub campaign: Option<String>,
+    pub currency: String,
+    pub orders: usize,
+    /// Orders with any product or shipping discount
+    pub discounted_orders: usize,
+    /// Product value before discounts
+    pub gross_merchandise: f64,
+    pub seller_discount: f64,
+    pub platform_discount: f64,
+    pub shipping_seller_discount: f64,
+    pub shipping_platform_discount: f64,
+    /// `seller_discount + shipping_seller_discount`
+    pub seller_funded: f64,
+    /// `platform_discount + shipping_platform_discount`
+    pub platform_funded: f64,
+    /// What buyers paid, shipping and tax included
+    pub buyer_paid: f64,
+}
+
+fn amount(value: Option<&str>) -> f64 {
+    value
+        .and_then(|v| v.trim().parse::<f64>().ok())
+        .unwrap_or(0.0)
+}
+
+/// Discount spend per campaign and currency. An order created on a day that
+/// several campaigns cover counts towards each of them.
+pub fn discount_report(orders: &[Order], campaigns: &[Campaign], tz: Tz) -> Vec<CampaignDiscounts> {
+    let mut totals: BTreeMap<(Option<String>, String), CampaignDiscounts> = BTreeMap::new();
+    for order in orders {
+        let Some(payment) = &order.payment else {
+            continue;
+        };
+        let day = reporting::date_of(order.create_time, tz);
+        let mut matched: Vec<Option<String>> = campaigns
+            .iter()
+            .filter(|c| c.contains(day))
+            .map(|c| Some(c.name.clone()))
+            .collect();
+        if matched.is_empty() {
+            matched.push(None);
+        }
+
+        let seller_discount = amount(Some(&payment.seller_discount));
+        let platform_discount = amount(Some(&payment.platform_discount));
+        let shipping_seller_discount = amount(payment.shipping_fee_seller_discount.as_deref());
+        let shipping_platform_discount = amount(payment.shipping_fee_platform_discount.as_deref());
+        let gross_merchandise = match payment.original_total_product_price.as_deref() {
+            Some(price) => amount(Some(price)),
+            None => amount(Some(&payment.sub_total)) + seller_discount + platform_discount,
+        };
+        let discounted = seller_discount
+            + platform_discount
+            + shipping_seller_discount
+            + shipping_platform_discount
+            > 0.0;
+
+        for campaign in matched {
+            let totals = totals
+                .entry((campaign.clone(), payment.currency.clone()))
+                .or_insert_with(|| CampaignDiscounts {
+                    campaign,
+                    currency: payment.currency.clone(),
+                    ..Default::default()
+                });
+            totals.orders += 1;
+            totals.discounted_orders += usize::from(discounted);
+            totals.gross_merchandise += gross_merchandise;
+            totals.seller_discount += seller_discount;
+            totals.platform_discount += platform_discount;
+            totals.shipping_seller_discount += shipping_seller_discount;
+            totals.shipping_platform_discount += shipping_platform_discount;
+            totals.seller_funded += seller_discount + shipping_seller_discount;
+            totals.platform_funded += platform_discount + shipping_platform_discount;
+            totals.buyer_paid += amount(Some(&payment.total_amount));
+        }
+    }
+    totals.into_values().collect()
+}
@@ -0,0 +1,352 @@
+use crate::config::Config;
+use crate::database::Database;
+use crate::error::AppError;
+use crate::order::GetOrderListRequest;
+use crate::shops::ShopRegistry;
+use serde::Serialize;
+use sqlx::sqlite::SqlitePool;
+use sqlx::Row;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use tokio_stream::StreamExt;
+use tracing::{info, warn};
+use utoipa::ToSchema;
+
+/// Most ids one order detail request takes
+const DETAIL_BATCH_SIZE: usize = 50;
+
+/// How a local row differed from TikTok
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum DiscrepancyKind {
+    /// Listed by TikTok but not stored
+    Missing,
+    /// Stored with an older `update_time` than TikTok's
+    Stale,
+    /// Stored as updated in the window but not listed by TikTok
+    Unlisted,
+}
+
+impl DiscrepancyKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DiscrepancyKind::Missing => "missing",
+            DiscrepancyKind::Stale => "stale",
+            DiscrepancyKind::Unlisted => "unlisted",
+        }
+    }
+
+    fn parse(value: &str) -> Self {
+        match value {
+            "missing" => DiscrepancyKind::Missing,
+            "stale" => DiscrepancyKind::Stale,
+            _ => DiscrepancyKind::Unlisted,
+        }
+    }
+}
+
+/// An order whose local row did not match TikTok
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Discrepancy {
+    pub order_id: String,
+    pub kind: DiscrepancyKind,
+    pub api_update_time: Option<i64>,
+    pub local_update_time: Option<i64>,
+}
+
+/// Outcome of one reconciliation pass
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ReconciliationRun {
+    pub id: i64,
+    pub started_at: i64,
+    pub finished_at: i64,
+    /// Orders updated in `[window_start, window_end)` were compared
+    pub window_start: i64,
+    pub window_end: i64,
+    /// Orders TikTok listed as updated in the window
+    pub api_count: i64,
+    /// Stored orders updated in the window
+    pub local_count: i64,
+    /// Orders fetched again and stored
+    pub refetched: i64,
+    pub error: Option<String>,
+    pub discrepancies: Vec<Discrepancy>,
+}
+
+/// Orders that differ between TikTok's listing and the local rows, given
+/// the `update_time` of each order id on both sides. `stored` holds the
+/// stored `update_time` of any listed order, wherever it falls.
+pub fn compare(
+    api: &BTreeMap<String, i64>,
+    local: &BTreeMap<String, i64>,
+    stored: &HashMap<String, i64>,
+) -> Vec<Discrepancy> {
+    let mut discrepancies: Vec<Discrepancy> = api
+        .iter()
+        .filter_map(|(id, &api_time)| {
+            let local_time = local.get(id).or_else(|| stored.get(id)).copied();
+            let kind = match local_time {
+                None => DiscrepancyKind::Missing,
+                Some(local_time) if local_time < api_time => DiscrepancyKind::Stale,
+                Some(_) => return None,
+            };
+            Some(Discrepancy {
+                order_id: id.clone(),
+                kind,
+                api_update_time: Some(api_time),
+                local_update_time: local_time,
+            })
+        })
+        .collect();
+
+    discrepancies.extend(local.iter().filter(|(id, _)| !api.contains_key(*id)).map(
+        |(id, &local_time)| Discrepancy {
+            order_id: id.clone(),
+            kind: DiscrepancyKind::Unlisted,
+            api_update_time: None,
+            local_update_time: Some(local_time),
+        },
+    ));
+    discrepancies
+}
+
+/// Past reconciliation passes and the discrepancies each found, in the
+/// `reconciliation_runs` and `reconciliation_discrepancies` tables
+#[derive(Clone)]
+pub struct ReconciliationStore {
+    pool: SqlitePool,
+}
+
+impl ReconciliationStore {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Initialize the reconciliation tables
+    pub async fn init(&self) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS reconciliation_runs (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                started_at INTEGER NOT NULL,
+                finished_at INTEGER NOT NULL,
+                window_start INTEGER NOT NULL,
+                window_end INTEGER NOT NULL,
+                api_count INTEGER NOT NULL,
+                local_count INTEGER NOT NULL,
+                refetched INTEGER NOT NULL,
+                error TEXT
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS reconciliation_discrepancies (
+                run_id INTEGER NOT NULL,
+                order_id TEXT NOT NULL,
+                kind TEXT NOT NULL,
+                api_update_time INTEGER,
+                local_update_time INTEGER,
+                PRIMARY KEY (run_id, order_id)
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Store a finished run with its discrepancies; returns it with its id
+    pub async fn record(&self, run: ReconciliationRun) -> Result<ReconciliationRun, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let id: i64 = sqlx::query(
+            "INSERT INTO reconciliation_runs (started_at, finished_at, window_start, window_end,
+                api_count, local_count, refetched, error)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+            RETURNING id",
+        )
+        .bind(run.started_at)
+        .bind(run.finished_at)
+        .bind(run.window_start)
+        .bind(run.window_end)
+        .bind(run.api_count)
+        .bind(run.local_count)
+        .bind(run.refetched)
+        .bind(&run.error)
+        .fetch_one(&mut *tx)
+        .await?
+        .try_get("id")?;
+
+        for discrepancy in &run.discrepancies {
+            sqlx::query(
+                "INSERT INTO reconciliation_discrepancies (run_id, order_id, kind,
+                    api_update_time, local_update_time)
+                VALUES (?1, ?2, ?3, ?4, ?5)",
+            )
+            .bind(id)
+            .bind(&discrepancy.order_id)
+            .bind(discrepancy.kind.as_str())
+            .bind(discrepancy.api_update_time)
+            .bind(discrepancy.local_update_time)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+
+        Ok(ReconciliationRun { id, ..run })
+    }
+
+    /// The latest runs, newest first, with their discrepancies
+    pub async fn list(&self, limit: i64) -> Result<Vec<ReconciliationRun>, sqlx::Error> {
+        let rows = sqlx::query(
+            "SELECT id, started_at, finished_at, window_start, window_end, api_count,
+                local_count, refetched, error
+            FROM reconciliation_runs ORDER BY id DESC LIMIT ?1",
+        )
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut runs = Vec::with_capacity(rows.len());
+        for row in rows {
+            let id: i64 = row.try_get("id")?;
+            let discrepancies = sqlx::query(
+                "SELECT order_id, kind, api_update_time, local_update_time
+                FROM reconciliation_discrepancies WHERE run_id = ?1 ORDER BY order_id",
+            )
+            .bind(id)
+            .fetch_all(&self.pool)
+            .await?
+            .iter()
+            .map(|row| {
+                let kind: String = row.try_get("kind")?;
+                Ok(Discrepancy {
+                    order_id: row.try_get("order_id")?,
+                    kind: DiscrepancyKind::parse(&kind),
+                    api_update_time: row.try_get("api_update_time")?,
+                    local_update_time: row.try_get("local_update_time")?,
+                })
+            })
+            .collect::<Result<Vec<_>, sqlx::Error>>()?;
+
+            runs.push(ReconciliationRun {
+                id,
+                started_at: row.try_get("started_at")?,
+                finished_at: row.try_get("finished_at")?,
+                window_start: row.try_get("window_start")?,
+                window_end: row.try_get("window_end")?,
+                api_count: row.try_get("api_count")?,
+                local_count: row.try_get("local_count")?,
+                refetched: row.try_get("refetched")?,
+                error: row.try_get("error")?,
+                discrepancies,
+            });
+        }
+        Ok(runs)
+    }
+}
+
+/// Compares orders TikTok lists as recently updated against the local rows
+/// and fetches again any that are missing or out of date, catching webhooks
+/// that never arrived and changes the polling sync has not reached yet
+pub struct Reconciler {
+    db: Arc<Database>,
+    store: ReconciliationStore,
+    shops: ShopRegistry,
+    config: Config,
+}
+
+impl Reconciler {
+    pub fn new(
+        db: Arc<Database>,
+        store: ReconciliationStore,
+        shops: ShopRegistry,
+        config: Config,
+    ) -> Self {
+        Self {
+            db,
+            store,
+            shops,
+            config,
+        }
+    }
+
+    /// Reconcile orders updated in the last `window_secs` and record the run,
+    /// including a failed one
+    pub async fn run(&self, window_secs: i64) -> Result<ReconciliationRun, sqlx::Error> {
+        let started_at = chrono::Utc::now().timestamp();
+        let mut run = ReconciliationRun {
+            id: 0,
+            started_at,
+            finished_at: started_at,
+            window_start: started_at - window_secs,
+            window_end: started_at,
+            api_count: 0,
+            local_count: 0,
+            refetched: 0,
+            error: None,
+            discrepancies: Vec::new(),
+        };
+        if let Err(e) = self.reconcile(&mut run).await {
+            warn!("Reconciliation failed: {}", e);
+            run.error = Some(e.to_string());
+        }
+        run.finished_at = chrono::Utc::now().timestamp();
+
+        if !run.discrepancies.is_empty() {
+            info!(
+                "Reconciliation found {} discrepancies, refetched {} orders",
+                run.discrepancies.len(),
+                run.refetched
+            );
+        }
+        self.store.record(run).await
+    }
+
+    async fn reconcile(&self, run: &mut ReconciliationRun) -> Result<(), AppError> {
+        let token = self.config.token_manager().valid_token().await?;
+        let order_client = self.shops.order_client(&self.config).await?;
+
+        let request = GetOrderListRequest::new()
+            .with_update_time_range(run.window_start, run.window_end)
+            .with_page_size(50);
+        let mut api = BTreeMap::new();
+        let mut orders = std::pin::pin!(order_client.stream_orders(
+            &token.access_token,
+            None,
+            self.config.shop_id.as_deref(),
+            request,
+        ));
+        while let Some(order) = orders.next().await {
+            let order = order?;
+            api.insert(order.id, order.update_time);
+        }
+
+        let local = self
+            .db
+            .order_versions_updated_between(run.window_start, run.window_end)
+            .await?;
+        let outside: Vec<String> = api
+            .keys()
+            .filter(|id| !local.contains_key(*id))
+            .cloned()
+            .collect();
+        let stored = self.db.order_update_times(&outside).await?;
+        run.api_count = api.len() as i64;
+        run.local_count = local.len() as i64;
+        run.discrepancies = compare(&api, &local, &stored);
+
+        let ids: Vec<String> = run
+            .discrepancies
+            .iter()
+            .map(|d| d.order_id.clone())
+            .collect();
+        for batch in ids.chunks(DETAIL_BATCH_SIZE) {
+            let response = order_client
+                .get_order_detail(&token.access_token, None, batch)
+                .await?;
+            self.db.upsert_orders(&response.orders).await?;
+            run.refetched += response.orders.len() as i64;
+        }
+        Ok(())
+    }
+}
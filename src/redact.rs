@@ -0,0 +1,75 @@
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// Query, form and JSON keys whose values are never logged verbatim
+const SENSITIVE_KEYS: &[&str] = &[
+    "access_token",
+    "refresh_token",
+    "app_secret",
+    "auth_code",
+    "sign",
+    "signature",
+    "secret",
+    "x-tts-access-token",
+];
+
+/// Whether values under `key` must be masked
+pub fn is_sensitive(key: &str) -> bool {
+    SENSITIVE_KEYS.iter().any(|k| k.eq_ignore_ascii_case(key))
+}
+
+/// Mask a secret, keeping a short prefix of long values so they can still be told apart
+pub fn mask(value: &str) -> String {
+    if value.chars().count() <= 12 {
+        return "***".to_string();
+    }
+    let prefix: String = value.chars().take(4).collect();
+    format!("{}***", prefix)
+}
+
+/// Query or form parameters with sensitive values masked
+pub fn params(params: &BTreeMap<String, String>) -> BTreeMap<&str, String> {
+    params
+        .iter()
+        .map(|(k, v)| {
+            let value = if is_sensitive(k) { mask(v) } else { v.clone() };
+            (k.as_str(), value)
+        })
+        .collect()
+}
+
+/// JSON body with sensitive fields masked at any depth. Bodies that aren't
+/// JSON are replaced by their length, since their structure is unknown.
+pub fn json(body: &str) -> String {
+    match serde_json::from_str::<Value>(body) {
+        Ok(mut value) => {
+            mask_value(&mut value);
+            value.to_string()
+        }
+        Err(_) => format!("<{} bytes, not JSON>", body.len()),
+    }
+}
+
+/// Replace every occurrence of the given secrets in free-form text (e.g. a sign string)
+pub fn scrub(text: &str, secrets: &[&str]) -> String {
+    secrets
+        .iter()
+        .filter(|s| !s.is_empty())
+        .fold(text.to_string(), |text, secret| text.replace(secret, &mask(secret)))
+}
+
+fn mask_value(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                if is_sensitive(key) && !value.is_null() {
+                    *value = Value::String(mask(value.as_str().unwrap_or_default()));
+                } else {
+                    mask_value(value);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(mask_value),
+        _ => {}
+    }
+}
@@ -0,0 +1,251 @@
+use crate::config::Config;
+use crate::jobs::{Job, JobHandler, JobQueue};
+use crate::order::Order;
+use crate::shops::ShopRegistry;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::{Sqlite, SqlitePool};
+use sqlx::{Row, Transaction};
+use tracing::{info, warn};
+use utoipa::ToSchema;
+
+/// Fetch a replacement order's detail to learn which order it replaces
+pub const RESOLVE_JOB_KIND: &str = "replacement_resolve";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResolveJobPayload {
+    pub order_id: String,
+}
+
+/// How an order relates to the orders replacing it, or the one it replaces
+#[derive(Debug, Clone, Default, Serialize, ToSchema)]
+pub struct ReplacementLinks {
+    /// Original order this replacement was sent for
+    pub replaces: Option<String>,
+    /// Replacement orders sent for this order
+    pub replaced_by: Vec<String>,
+}
+
+/// Replacement -> original order links, in the `order_replacements` table.
+///
+/// Order list responses don't carry `replaced_order_id`, and a later list sync
+/// overwrites the stored order, so links are kept here rather than in the blob.
+#[derive(Clone)]
+pub struct ReplacementStore {
+    pool: SqlitePool,
+}
+
+impl ReplacementStore {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Initialize the order_replacements table
+    pub async fn init(&self) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS order_replacements (
+                order_id TEXT PRIMARY KEY,
+                replaced_order_id TEXT NOT NULL,
+                linked_at INTEGER NOT NULL
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            "CREATE INDEX IF NOT EXISTS idx_order_replacements_replaced
+            ON order_replacements (replaced_order_id)",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Store the link of a replacement order that carries `replaced_order_id`,
+    /// within an upsert transaction
+    pub async fn record(
+        tx: &mut Transaction<'_, Sqlite>,
+        order: &Order,
+    ) -> Result<(), sqlx::Error> {
+        let Some(replaced_order_id) = order
+            .replaced_order_id
+            .as_deref()
+            .filter(|id| !id.is_empty())
+        else {
+            return Ok(());
+        };
+
+        sqlx::query(
+            "INSERT OR REPLACE INTO order_replacements (order_id, replaced_order_id, linked_at)
+            VALUES (?1, ?2, ?3)",
+        )
+        .bind(&order.id)
+        .bind(replaced_order_id)
+        .bind(chrono::Utc::now().timestamp())
+        .execute(&mut **tx)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn link(&self, order_id: &str, replaced_order_id: &str) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "INSERT OR REPLACE INTO order_replacements (order_id, replaced_order_id, linked_at)
+            VALUES (?1, ?2, ?3)",
+        )
+        .bind(order_id)
+        .bind(replaced_order_id)
+        .bind(chrono::Utc::now().timestamp())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn links(&self, order_id: &str) -> Result<ReplacementLinks, sqlx::Error> {
+        let replaces =
+            sqlx::query("SELECT replaced_order_id FROM order_replacements WHERE order_id = ?1")
+                .bind(order_id)
+                .fetch_optional(&self.pool)
+                .await?
+                .map(|row| row.try_get("replaced_order_id"))
+                .transpose()?;
+
+        let replaced_by = sqlx::query(
+            "SELECT order_id FROM order_replacements WHERE replaced_order_id = ?1 ORDER BY order_id",
+        )
+        .bind(order_id)
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(|row| row.try_get("order_id"))
+        .collect::<Result<_, _>>()?;
+
+        Ok(ReplacementLinks {
+            replaces,
+            replaced_by,
+        })
+    }
+
+    /// Stored replacement orders whose original isn't known yet
+    pub async fn unresolved(&self) -> Result<Vec<String>, sqlx::Error> {
+        sqlx::query(
+            "SELECT id FROM orders
+            WHERE json_extract(data, '$.is_replacement_order') = 1
+            AND id NOT IN (SELECT order_id FROM order_replacements)",
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(|row| row.try_get("id"))
+        .collect()
+    }
+}
+
+/// Queue a detail lookup for each of `order_ids`, once per order. Returns how
+/// many were queued.
+pub async fn enqueue_resolution<'a>(
+    job_queue: &JobQueue,
+    order_ids: impl IntoIterator<Item = &'a str>,
+    max_attempts: u32,
+) -> Result<usize, sqlx::Error> {
+    let mut queued = 0;
+    for order_id in order_ids {
+        let payload = serde_json::to_value(ResolveJobPayload {
+            order_id: order_id.to_string(),
+        })
+        .unwrap_or_default();
+        if job_queue
+            .enqueue(
+                RESOLVE_JOB_KIND,
+                Some(&format!("{}:{}", RESOLVE_JOB_KIND, order_id)),
+                &payload,
+                max_attempts,
+            )
+            .await?
+        {
+            queued += 1;
+        }
+    }
+    Ok(queued)
+}
+
+/// Replacement orders among `orders` that need their original looked up
+pub fn needs_resolution(orders: &[Order]) -> impl Iterator<Item = &str> {
+    orders
+        .iter()
+        .filter(|o| o.is_replacement_order == Some(true) && o.replaced_order_id.is_none())
+        .map(|o| o.id.as_str())
+}
+
+/// Looks up `replaced_order_id` through the order detail API
+pub struct ReplacementResolveHandler {
+    store: ReplacementStore,
+    shops: ShopRegistry,
+    config: Config,
+}
+
+impl ReplacementResolveHandler {
+    pub fn new(store: ReplacementStore, shops: ShopRegistry, config: Config) -> Self {
+        Self {
+            store,
+            shops,
+            config,
+        }
+    }
+}
+
+#[async_trait]
+impl JobHandler for ReplacementResolveHandler {
+    async fn handle(&self, job: &Job) -> Result<(), String> {
+        let payload: ResolveJobPayload = serde_json::from_value(job.payload.clone())
+            .map_err(|e| format!("Invalid replacement payload: {}", e))?;
+
+        let token = self
+            .config
+            .token_manager()
+            .valid_token()
+            .await
+            .map_err(|e| e.to_string())?;
+        let order_client = self
+            .shops
+            .order_client(&self.config)
+            .await
+            .map_err(|e| e.to_string())?;
+        let response = order_client
+            .get_order_detail(
+                &token.access_token,
+                None,
+                std::slice::from_ref(&payload.order_id),
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let order = response
+            .orders
+            .into_iter()
+            .find(|o| o.id == payload.order_id)
+            .ok_or_else(|| format!("Order {} not returned by API", payload.order_id))?;
+
+        match order
+            .replaced_order_id
+            .as_deref()
+            .filter(|id| !id.is_empty())
+        {
+            Some(replaced_order_id) => {
+                self.store
+                    .link(&order.id, replaced_order_id)
+                    .await
+                    .map_err(|e| e.to_string())?;
+                info!("Order {} replaces order {}", order.id, replaced_order_id);
+            }
+            None => warn!(
+                "Replacement order {} has no replaced_order_id in its detail",
+                order.id
+            ),
+        }
+
+        Ok(())
+    }
+}
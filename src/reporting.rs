@@ -0,0 +1,30 @@
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+use chrono_tz::Tz;
+
+/// The business day `timestamp` falls on in `tz`
+pub fn date_of(timestamp: i64, tz: Tz) -> NaiveDate {
+    DateTime::<Utc>::from_timestamp(timestamp, 0)
+        .unwrap_or_default()
+        .with_timezone(&tz)
+        .date_naive()
+}
+
+/// [`date_of`] as `YYYY-MM-DD`, the form stats tables key days by
+pub fn day_of(timestamp: i64, tz: Tz) -> String {
+    date_of(timestamp, tz).format("%Y-%m-%d").to_string()
+}
+
+/// The current business day in `tz`
+pub fn today(tz: Tz) -> NaiveDate {
+    Utc::now().with_timezone(&tz).date_naive()
+}
+
+/// Unix time at which `day` starts in `tz`. A day starting inside a DST gap
+/// starts at the first hour that exists.
+pub fn day_start(day: NaiveDate, tz: Tz) -> i64 {
+    (0..24)
+        .filter_map(|hour| day.and_hms_opt(hour, 0, 0))
+        .find_map(|local| tz.from_local_datetime(&local).earliest())
+        .map(|start| start.timestamp())
+        .unwrap_or_else(|| day.and_time(Default::default()).and_utc().timestamp())
+}
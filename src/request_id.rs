@@ -0,0 +1,45 @@
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Header carrying the request ID on inbound requests, responses and outgoing API calls
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Longest caller-supplied request ID that is reused as-is
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+static NEXT_REQUEST_ID: AtomicU64 = AtomicU64::new(1);
+
+tokio::task_local! {
+    static CURRENT_REQUEST_ID: String;
+}
+
+/// New request ID: process start time plus a counter, unique within this process
+pub fn generate() -> String {
+    static PREFIX: std::sync::OnceLock<String> = std::sync::OnceLock::new();
+    let prefix = PREFIX.get_or_init(|| format!("{:x}", chrono::Utc::now().timestamp_millis()));
+    format!("{}-{:06x}", prefix, NEXT_REQUEST_ID.fetch_add(1, Ordering::Relaxed))
+}
+
+/// Reuse a caller's request ID when it is short, printable ASCII; otherwise generate one
+pub fn from_header(value: Option<&str>) -> String {
+    match value {
+        Some(id)
+            if !id.is_empty()
+                && id.len() <= MAX_REQUEST_ID_LEN
+                && id.bytes().all(|b| b.is_ascii_graphic()) =>
+        {
+            id.to_string()
+        }
+        _ => generate(),
+    }
+}
+
+/// Run `future` with `id` as the current request ID
+pub async fn scope<F: Future>(id: String, future: F) -> F::Output {
+    CURRENT_REQUEST_ID.scope(id, future).await
+}
+
+/// Request ID of the request being handled on this task, if any
+pub fn current() -> Option<String> {
+    CURRENT_REQUEST_ID.try_with(|id| id.clone()).ok()
+}
@@ -1,19 +1,52 @@
 use crate::error::AppError;
-use hmac::{Hmac, Mac};
-use reqwest::Client;
+use crate::health::ApiHealth;
+use crate::redact;
+use crate::request_id::{self, REQUEST_ID_HEADER};
+use crate::signing;
+use crate::transport::{HttpBody, HttpRequest, HttpResponse, HttpTransport, ReqwestTransport};
+use async_trait::async_trait;
+use reqwest::{Method, StatusCode};
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use sha2::Sha256;
 use std::collections::BTreeMap;
-use tracing::debug;
-
-type HmacSha256 = Hmac<Sha256>;
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::{debug, field, info, Span};
 
 #[derive(Clone)]
 pub struct TikTokShopApiClient {
     app_key: String,
     app_secret: String,
-    http_client: Client,
+    base_url: String,
+    transport: Arc<dyn HttpTransport>,
+    health: Option<Arc<ApiHealth>>,
+    log_bodies: bool,
+    recorder: Option<Arc<dyn ApiRecorder>>,
+    fixed_timestamp: Option<i64>,
+}
+
+/// An upstream call with secrets masked, as handed to an [`ApiRecorder`]
+#[derive(Debug, Clone, Serialize)]
+pub struct RecordedCall {
+    pub method: String,
+    pub path: String,
+    pub params: BTreeMap<String, String>,
+    pub request_body: Option<String>,
+    /// HTTP status, unset when no response came back
+    pub status: Option<u16>,
+    /// `code` from the response envelope; non-zero means TikTok rejected the call
+    pub api_code: Option<i64>,
+    pub response_body: Option<String>,
+    /// Transport error, when no response came back
+    pub error: Option<String>,
+    pub duration_ms: u64,
+    pub request_id: Option<String>,
+}
+
+/// Keeps request/response pairs for debugging, e.g. in a database table
+#[async_trait]
+pub trait ApiRecorder: Send + Sync + std::fmt::Debug {
+    async fn record(&self, call: RecordedCall);
 }
 
 #[derive(Debug, Deserialize)]
@@ -25,86 +58,186 @@ pub struct ApiResponse<T> {
 }
 
 impl TikTokShopApiClient {
-    const API_BASE_URL: &'static str = "https://open-api.tiktokglobalshop.com";
+    pub const API_BASE_URL: &'static str = "https://open-api.tiktokglobalshop.com";
+    pub const SANDBOX_API_BASE_URL: &'static str = "https://open-api-sandbox.tiktokglobalshop.com";
 
     pub fn new(app_key: String, app_secret: String) -> Self {
+        Self::with_transport(app_key, app_secret, Arc::new(ReqwestTransport::new()))
+    }
+
+    /// Create a client that sends requests through a custom transport
+    pub fn with_transport(
+        app_key: String,
+        app_secret: String,
+        transport: Arc<dyn HttpTransport>,
+    ) -> Self {
         Self {
             app_key,
             app_secret,
-            http_client: Client::new(),
+            base_url: Self::API_BASE_URL.to_string(),
+            transport,
+            health: None,
+            log_bodies: false,
+            recorder: None,
+            fixed_timestamp: None,
         }
     }
 
-    fn generate_signature(
-        &self,
-        path: &str,
-        params: &BTreeMap<String, String>,
-        timestamp: i64,
-        access_token: Option<&str>,
-        shop_cipher: Option<&str>,
-    ) -> Result<String, AppError> {
-        let mut sign_string = String::new();
-        sign_string.push_str(&self.app_key);
-        sign_string.push_str(&timestamp.to_string());
-
-        if let Some(token) = access_token {
-            sign_string.push_str(token);
-        }
+    /// Send requests to a different API host (e.g. the sandbox)
+    pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into().trim_end_matches('/').to_string();
+        self
+    }
 
-        if let Some(cipher) = shop_cipher {
-            sign_string.push_str(cipher);
-        }
+    /// Log (redacted) request and response bodies and sign strings at debug level
+    pub fn with_body_logging(mut self, enabled: bool) -> Self {
+        self.log_bodies = enabled;
+        self
+    }
 
-        sign_string.push_str(path);
+    /// Record the outcome of every request in a shared health tracker
+    pub fn with_health(mut self, health: Arc<ApiHealth>) -> Self {
+        self.health = Some(health);
+        self
+    }
 
-        for (key, value) in params.iter() {
-            sign_string.push_str(key);
-            sign_string.push_str(value);
-        }
+    /// Hand every request/response pair, secrets masked, to `recorder`
+    pub fn with_recorder(mut self, recorder: Option<Arc<dyn ApiRecorder>>) -> Self {
+        self.recorder = recorder;
+        self
+    }
 
-        debug!("Sign string: {}", sign_string);
+    /// Sign every request with `timestamp` instead of the current time, so
+    /// requests are reproducible (e.g. in golden tests)
+    pub fn with_fixed_timestamp(mut self, timestamp: i64) -> Self {
+        self.fixed_timestamp = Some(timestamp);
+        self
+    }
 
-        let mut mac = HmacSha256::new_from_slice(self.app_secret.as_bytes())
-            .map_err(|e| AppError::SignatureError(e.to_string()))?;
-        mac.update(sign_string.as_bytes());
-        let result = mac.finalize();
-        let signature = hex::encode(result.into_bytes());
-        Ok(signature)
+    /// Inject latency, 429s and malformed JSON into requests at the given rates
+    #[cfg(feature = "chaos")]
+    pub fn with_faults(mut self, faults: crate::chaos::FaultConfig) -> Self {
+        self.transport = Arc::new(crate::chaos::FaultInjectingTransport::new(
+            self.transport,
+            faults,
+        ));
+        self
     }
 
-    fn generate_signature_with_body(
-        &self,
-        path: &str,
-        params: &BTreeMap<String, String>,
-        body_json: &str,
-    ) -> Result<String, AppError> {
-        let mut params_string = String::new();
-        for (key, value) in params.iter() {
-            // Skip access_token and sign as per docs
-            if key == "access_token" || key == "sign" {
-                continue;
+    /// Send a request, recording transport failures, 5xx and 429 as unhealthy.
+    /// The current request ID, if any, is forwarded so upstream calls can be correlated.
+    /// The HTTP status is recorded on the current `tiktok_api` span.
+    async fn send(&self, mut request: HttpRequest) -> Result<HttpResponse, AppError> {
+        if let Some(id) = request_id::current() {
+            request = request.with_header(REQUEST_ID_HEADER, &id);
+        }
+        let recording = self.recorder.as_ref().map(|_| request.clone());
+        let started = Instant::now();
+        let result = self.transport.send(request).await;
+
+        let span = Span::current();
+        match &result {
+            Ok(response) => {
+                span.record("http.response.status_code", response.status.as_u16());
+                if !response.status.is_success() {
+                    span.record("otel.status_code", "ERROR");
+                }
+            }
+            Err(_) => {
+                span.record("otel.status_code", "ERROR");
+            }
+        }
+
+        if let (Some(recorder), Some(request)) = (&self.recorder, recording) {
+            recorder
+                .record(self.recorded_call(request, &result, started))
+                .await;
+        }
+
+        if let Some(health) = &self.health {
+            match &result {
+                Ok(response)
+                    if !response.status.is_server_error()
+                        && response.status != StatusCode::TOO_MANY_REQUESTS =>
+                {
+                    health.record_success()
+                }
+                _ => health.record_failure(),
             }
-            params_string.push_str(key);
-            params_string.push_str(value);
         }
 
-        let sign_string = format!("{}{}{}", path, params_string, body_json);
-        let wrapped_string = format!("{}{}{}", self.app_secret, sign_string, self.app_secret);
+        result
+    }
 
-        debug!("Sign string: {}", sign_string);
-        debug!("Wrapped string: {}", wrapped_string);
+    fn recorded_call(
+        &self,
+        request: HttpRequest,
+        result: &Result<HttpResponse, AppError>,
+        started: Instant,
+    ) -> RecordedCall {
+        let params: BTreeMap<String, String> = request.query.into_iter().collect();
+        let response = result.as_ref().ok();
+        RecordedCall {
+            method: request.method.to_string(),
+            path: request
+                .url
+                .strip_prefix(&self.base_url)
+                .unwrap_or(&request.url)
+                .to_string(),
+            params: redact::params(&params)
+                .into_iter()
+                .map(|(k, v)| (k.to_string(), v))
+                .collect(),
+            request_body: match &request.body {
+                Some(HttpBody::Raw(body)) => Some(redact::json(body)),
+                Some(HttpBody::Form(_)) | None => None,
+            },
+            status: response.map(|r| r.status.as_u16()),
+            api_code: response
+                .and_then(|r| serde_json::from_str::<serde_json::Value>(&r.body).ok())
+                .and_then(|body| body.get("code")?.as_i64()),
+            response_body: response.map(|r| redact::json(&r.body)),
+            error: result.as_ref().err().map(|e| e.to_string()),
+            duration_ms: started.elapsed().as_millis() as u64,
+            request_id: request_id::current(),
+        }
+    }
 
-        let mut mac = HmacSha256::new_from_slice(self.app_secret.as_bytes())
-            .map_err(|e| AppError::SignatureError(e.to_string()))?;
-        mac.update(wrapped_string.as_bytes());
-        let result = mac.finalize();
-        let signature = hex::encode(result.into_bytes());
+    fn log_response(&self, status: StatusCode, body: &str) {
+        if self.log_bodies {
+            debug!("Response status: {}, body: {}", status, redact::json(body));
+        } else {
+            debug!("Response status: {}, {} bytes", status, body.len());
+        }
+    }
 
-        debug!("Generated signature: {}", signature);
+    /// `sign` parameter for a request; see [`signing`]
+    fn timestamp(&self) -> i64 {
+        self.fixed_timestamp
+            .unwrap_or_else(|| chrono::Utc::now().timestamp())
+    }
 
-        Ok(signature)
+    fn sign(&self, path: &str, params: &BTreeMap<String, String>, body: Option<&str>) -> String {
+        let sign_string = signing::sign_string(path, params, body);
+        if self.log_bodies {
+            debug!("Sign string: {}", sign_string);
+        }
+        signing::sign_wrapped(&self.app_secret, &sign_string)
     }
 
+    #[tracing::instrument(
+        name = "tiktok_api",
+        skip_all,
+        fields(
+            otel.name = %format_args!("GET {}", path),
+            http.request.method = "GET",
+            endpoint = path,
+            http.response.status_code = field::Empty,
+            tiktok.code = field::Empty,
+            tiktok.request_id = field::Empty,
+            otel.status_code = field::Empty,
+        )
+    )]
     pub async fn get<T: DeserializeOwned>(
         &self,
         path: &str,
@@ -112,7 +245,7 @@ impl TikTokShopApiClient {
         shop_cipher: Option<&str>,
         mut params: BTreeMap<String, String>,
     ) -> Result<T, AppError> {
-        let timestamp = chrono::Utc::now().timestamp();
+        let timestamp = self.timestamp();
 
         // Add required common parameters
         params.insert("app_key".to_string(), self.app_key.clone());
@@ -126,55 +259,50 @@ impl TikTokShopApiClient {
             params.insert("shop_cipher".to_string(), cipher.to_string());
         }
 
-        let signature = self.generate_signature(path, &params, timestamp, access_token, shop_cipher)?;
+        let signature = self.sign(path, &params, None);
         params.insert("sign".to_string(), signature);
-        let url = format!("{}{}", Self::API_BASE_URL, path);
+        let url = format!("{}{}", self.base_url, path);
         debug!("Making GET request to: {}", url);
-        debug!("Parameters: {:?}", params);
+        debug!("Parameters: {:?}", redact::params(&params));
 
-        let mut request_builder = self
-            .http_client
-            .get(&url)
-            .query(&params)
-            .header("Content-Type", "application/json");
+        let mut request = HttpRequest::get(url)
+            .with_query(&params)
+            .with_header("Content-Type", "application/json");
 
         if let Some(token) = access_token {
-            request_builder = request_builder.header("x-tts-access-token", token);
+            request = request.with_header("x-tts-access-token", token);
         }
 
-        let response = request_builder
-            .send()
-            .await
-            .map_err(|e| AppError::HttpError(e.to_string()))?;
+        let started = Instant::now();
+        let response = self.send(request).await?;
+        let status = response.status;
+        let body = response.body;
 
-        let status = response.status();
-        let body = response
-            .text()
-            .await
-            .map_err(|e| AppError::HttpError(e.to_string()))?;
-
-        debug!("Response status: {}, body: {}", status, body);
+        self.log_response(status, &body);
 
         if !status.is_success() {
-            return Err(AppError::HttpError(format!(
-                "API request failed with status {}: {}",
-                status, body
-            )));
+            return Err(AppError::HttpStatus { status, body });
         }
 
         let api_response: ApiResponse<T> = serde_json::from_str(&body)
-            .map_err(|e| AppError::ParseError(format!("Failed to parse response: {}", e)))?;
+            .map_err(|e| AppError::parse("Failed to parse response", e))?;
+        log_api_response(&Method::GET, path, started, &api_response);
 
         if api_response.code != 0 {
-            return Err(AppError::ApiError(
-                api_response.code,
-                api_response.message,
-            ));
+            return Err(AppError::ApiError {
+                code: api_response.code,
+                message: api_response.message,
+                request_id: api_response.request_id,
+            });
         }
 
         api_response
             .data
-            .ok_or_else(|| AppError::ApiError(api_response.code, "No data in response".to_string()))
+            .ok_or_else(|| AppError::ApiError {
+                code: api_response.code,
+                message: "No data in response".to_string(),
+                request_id: api_response.request_id,
+            })
     }
 
     pub async fn post<T: DeserializeOwned, B: Serialize>(
@@ -185,11 +313,62 @@ impl TikTokShopApiClient {
         body: &B,
         extra_params: Option<BTreeMap<String, String>>,
     ) -> Result<T, AppError> {
-        let timestamp = chrono::Utc::now().timestamp();
+        self.send_with_body(Method::POST, path, access_token, shop_cipher, body, extra_params)
+            .await
+    }
+
+    pub async fn put<T: DeserializeOwned, B: Serialize>(
+        &self,
+        path: &str,
+        access_token: Option<&str>,
+        shop_cipher: Option<&str>,
+        body: &B,
+        extra_params: Option<BTreeMap<String, String>>,
+    ) -> Result<T, AppError> {
+        self.send_with_body(Method::PUT, path, access_token, shop_cipher, body, extra_params)
+            .await
+    }
+
+    pub async fn delete<T: DeserializeOwned, B: Serialize>(
+        &self,
+        path: &str,
+        access_token: Option<&str>,
+        shop_cipher: Option<&str>,
+        body: &B,
+        extra_params: Option<BTreeMap<String, String>>,
+    ) -> Result<T, AppError> {
+        self.send_with_body(Method::DELETE, path, access_token, shop_cipher, body, extra_params)
+            .await
+    }
+
+    /// Signed request carrying a JSON body (POST, PUT, DELETE)
+    #[tracing::instrument(
+        name = "tiktok_api",
+        skip_all,
+        fields(
+            otel.name = %format_args!("{} {}", method, path),
+            http.request.method = %method,
+            endpoint = path,
+            http.response.status_code = field::Empty,
+            tiktok.code = field::Empty,
+            tiktok.request_id = field::Empty,
+            otel.status_code = field::Empty,
+        )
+    )]
+    async fn send_with_body<T: DeserializeOwned, B: Serialize>(
+        &self,
+        method: Method,
+        path: &str,
+        access_token: Option<&str>,
+        shop_cipher: Option<&str>,
+        body: &B,
+        extra_params: Option<BTreeMap<String, String>>,
+    ) -> Result<T, AppError> {
+        let timestamp = self.timestamp();
 
         // Serialize body to JSON string
         let body_json = serde_json::to_string(body)
-            .map_err(|e| AppError::ParseError(format!("Failed to serialize body: {}", e)))?;
+            .map_err(|e| AppError::parse("Failed to serialize body", e))?;
 
         let mut params = BTreeMap::new();
         params.insert("app_key".to_string(), self.app_key.clone());
@@ -211,61 +390,80 @@ impl TikTokShopApiClient {
             }
         }
 
-        // For POST requests, generate signature including ALL query params and the request body
-        let signature = self.generate_signature_with_body(path, &params, &body_json)?;
+        let signature = self.sign(path, &params, Some(&body_json));
         params.insert("sign".to_string(), signature);
 
-        let url = format!("{}{}", Self::API_BASE_URL, path);
+        let url = format!("{}{}", self.base_url, path);
 
-        debug!("Making POST request to: {}", url);
-        debug!("Query parameters: {:?}", params);
-        debug!("Request body: {}", body_json);
+        debug!("Making {} request to: {}", method, url);
+        debug!("Query parameters: {:?}", redact::params(&params));
+        if self.log_bodies {
+            debug!("Request body: {}", redact::json(&body_json));
+        }
 
         // Make request with required headers
-        let mut request_builder = self
-            .http_client
-            .post(&url)
-            .query(&params)
-            .header("Content-Type", "application/json");
+        let mut request = HttpRequest::new(method.clone(), url)
+            .with_query(&params)
+            .with_header("Content-Type", "application/json");
 
         if let Some(token) = access_token {
-            request_builder = request_builder.header("x-tts-access-token", token);
+            request = request.with_header("x-tts-access-token", token);
         }
 
-        let response = request_builder
-            .body(body_json)
-            .send()
-            .await
-            .map_err(|e| AppError::HttpError(e.to_string()))?;
-
-        let status = response.status();
-        let response_body = response
-            .text()
-            .await
-            .map_err(|e| AppError::HttpError(e.to_string()))?;
+        let started = Instant::now();
+        let response = self.send(request.with_body(body_json)).await?;
+        let status = response.status;
+        let response_body = response.body;
 
-        debug!("Response status: {}, body: {}", status, response_body);
+        self.log_response(status, &response_body);
 
         if !status.is_success() {
-            return Err(AppError::HttpError(format!(
-                "API request failed with status {}: {}",
-                status, response_body
-            )));
+            return Err(AppError::HttpStatus {
+                status,
+                body: response_body,
+            });
         }
 
         // Parse response;
         let api_response: ApiResponse<T> = serde_json::from_str(&response_body)
-            .map_err(|e| AppError::ParseError(format!("Failed to parse response: {}", e)))?;
+            .map_err(|e| AppError::parse("Failed to parse response", e))?;
+        log_api_response(&method, path, started, &api_response);
 
         if api_response.code != 0 {
-            return Err(AppError::ApiError(
-                api_response.code,
-                api_response.message,
-            ));
+            return Err(AppError::ApiError {
+                code: api_response.code,
+                message: api_response.message,
+                request_id: api_response.request_id,
+            });
         }
 
         api_response
             .data
-            .ok_or_else(|| AppError::ApiError(api_response.code, "No data in response".to_string()))
+            .ok_or_else(|| AppError::ApiError {
+                code: api_response.code,
+                message: "No data in response".to_string(),
+                request_id: api_response.request_id,
+            })
+    }
+}
+
+/// Log a TikTok API call with the upstream `request_id`, for correlating with TikTok
+/// support, and record both on the current `tiktok_api` span
+fn log_api_response<T>(method: &Method, path: &str, started: Instant, response: &ApiResponse<T>) {
+    let span = Span::current();
+    span.record("tiktok.code", response.code);
+    if let Some(request_id) = &response.request_id {
+        span.record("tiktok.request_id", request_id.as_str());
+    }
+    if response.code != 0 {
+        span.record("otel.status_code", "ERROR");
     }
+    info!(
+        method = %method,
+        path,
+        code = response.code,
+        upstream_request_id = response.request_id.as_deref().unwrap_or_default(),
+        duration_ms = started.elapsed().as_millis() as u64,
+        "TikTok API call"
+    );
 }
@@ -0,0 +1,267 @@
+use crate::database;
+use crate::order::Order;
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::{Sqlite, SqlitePool};
+use sqlx::{Row, Transaction};
+use std::sync::Arc;
+use utoipa::ToSchema;
+
+/// Prior cancelled orders from the same buyer before it counts as a signal
+pub const REPEATED_CANCELLATIONS: i64 = 2;
+
+/// What is known about the buyer of an order being scored
+#[derive(Debug, Clone, Default)]
+pub struct BuyerHistory {
+    /// Other stored orders of the same `user_id` that were cancelled
+    pub cancelled_orders: i64,
+}
+
+/// Something about an order that makes it look risky
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct RiskSignal {
+    /// Short identifier, e.g. `region_mismatch`
+    pub signal: String,
+    /// How much this adds to the score, 0.0 - 1.0
+    pub weight: f64,
+    pub reason: String,
+}
+
+/// Looks for one kind of risk in a newly stored order
+pub trait RiskEvaluator: Send + Sync {
+    fn evaluate(&self, order: &Order, history: &BuyerHistory) -> Option<RiskSignal>;
+}
+
+/// Shipping country outside the regions the shop sells to
+pub struct RegionMismatch {
+    regions: Vec<String>,
+}
+
+impl RegionMismatch {
+    pub fn new(regions: Vec<String>) -> Self {
+        Self { regions }
+    }
+}
+
+impl RiskEvaluator for RegionMismatch {
+    fn evaluate(&self, order: &Order, _history: &BuyerHistory) -> Option<RiskSignal> {
+        let region = order
+            .recipient_address
+            .as_ref()?
+            .region_code
+            .as_deref()
+            .map(str::trim)
+            .filter(|r| !r.is_empty())?;
+        if self.regions.is_empty() || self.regions.iter().any(|r| r.eq_ignore_ascii_case(region)) {
+            return None;
+        }
+
+        Some(RiskSignal {
+            signal: "region_mismatch".to_string(),
+            weight: 0.5,
+            reason: format!(
+                "ships to {} outside the shop's regions ({})",
+                region.to_ascii_uppercase(),
+                self.regions.join(", ")
+            ),
+        })
+    }
+}
+
+/// More units in one order than a buyer normally takes
+pub struct HighQuantity {
+    max_units: i64,
+}
+
+impl HighQuantity {
+    pub fn new(max_units: i64) -> Self {
+        Self { max_units }
+    }
+}
+
+impl RiskEvaluator for HighQuantity {
+    fn evaluate(&self, order: &Order, _history: &BuyerHistory) -> Option<RiskSignal> {
+        let units: i64 = order
+            .item_list
+            .iter()
+            .map(|item| i64::from(item.quantity.unwrap_or(1).max(1)))
+            .sum();
+        (units > self.max_units).then(|| RiskSignal {
+            signal: "high_quantity".to_string(),
+            weight: 0.3,
+            reason: format!("{} units, more than {}", units, self.max_units),
+        })
+    }
+}
+
+/// Buyer cancelled several orders before
+pub struct RepeatedCancellations {
+    threshold: i64,
+}
+
+impl RepeatedCancellations {
+    pub fn new(threshold: i64) -> Self {
+        Self { threshold }
+    }
+}
+
+impl RiskEvaluator for RepeatedCancellations {
+    fn evaluate(&self, _order: &Order, history: &BuyerHistory) -> Option<RiskSignal> {
+        (history.cancelled_orders >= self.threshold).then(|| RiskSignal {
+            signal: "repeated_cancellations".to_string(),
+            weight: 0.5,
+            reason: format!(
+                "buyer cancelled {} earlier orders",
+                history.cancelled_orders
+            ),
+        })
+    }
+}
+
+/// Risk score of an order and the signals behind it
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct RiskAssessment {
+    /// Sum of signal weights, capped at 1.0
+    pub score: f64,
+    pub signals: Vec<RiskSignal>,
+    pub scored_at: i64,
+}
+
+/// Runs every evaluator over new orders on upsert
+#[derive(Clone)]
+pub struct RiskScorer {
+    evaluators: Vec<Arc<dyn RiskEvaluator>>,
+    alert_threshold: f64,
+}
+
+impl RiskScorer {
+    pub fn new(alert_threshold: f64) -> Self {
+        Self {
+            evaluators: Vec::new(),
+            alert_threshold,
+        }
+    }
+
+    pub fn with_evaluator(mut self, evaluator: impl RiskEvaluator + 'static) -> Self {
+        self.evaluators.push(Arc::new(evaluator));
+        self
+    }
+
+    pub fn assess(&self, order: &Order, history: &BuyerHistory) -> RiskAssessment {
+        let signals: Vec<RiskSignal> = self
+            .evaluators
+            .iter()
+            .filter_map(|e| e.evaluate(order, history))
+            .collect();
+        RiskAssessment {
+            score: signals
+                .iter()
+                .map(|s| s.weight)
+                .sum::<f64>()
+                .clamp(0.0, 1.0),
+            signals,
+            scored_at: chrono::Utc::now().timestamp(),
+        }
+    }
+
+    /// Whether `assessment` should be reported as high risk
+    pub fn is_high_risk(&self, assessment: &RiskAssessment) -> bool {
+        !assessment.signals.is_empty() && assessment.score >= self.alert_threshold
+    }
+}
+
+/// Risk scores of orders, in the `order_risk` table. Orders are scored once,
+/// when first stored.
+#[derive(Clone)]
+pub struct RiskStore {
+    pool: SqlitePool,
+}
+
+impl RiskStore {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Initialize the order_risk table
+    pub async fn init(&self) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS order_risk (
+                order_id TEXT PRIMARY KEY,
+                score REAL NOT NULL,
+                signals TEXT NOT NULL,
+                scored_at INTEGER NOT NULL
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_order_risk_score ON order_risk (score)")
+            .execute(&self.pool)
+            .await?;
+        // Listings filter on it, so changes invalidate their ETag
+        database::track_orders_version(&self.pool, "order_risk").await?;
+
+        Ok(())
+    }
+
+    /// Cancelled orders of `order`'s buyer stored so far, within an upsert
+    /// transaction
+    pub async fn buyer_history(
+        tx: &mut Transaction<'_, Sqlite>,
+        order: &Order,
+    ) -> Result<BuyerHistory, sqlx::Error> {
+        let Some(user_id) = order.user_id.as_deref().filter(|id| !id.is_empty()) else {
+            return Ok(BuyerHistory::default());
+        };
+
+        let cancelled_orders = sqlx::query(
+            "SELECT COUNT(*) AS cancelled FROM orders
+            WHERE status = 'CANCELLED' AND id != ?1
+            AND json_extract(data, '$.user_id') = ?2",
+        )
+        .bind(&order.id)
+        .bind(user_id)
+        .fetch_one(&mut **tx)
+        .await?
+        .try_get("cancelled")?;
+
+        Ok(BuyerHistory { cancelled_orders })
+    }
+
+    /// Store the assessment of an order within an upsert transaction
+    pub async fn record(
+        tx: &mut Transaction<'_, Sqlite>,
+        order_id: &str,
+        assessment: &RiskAssessment,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "INSERT OR REPLACE INTO order_risk (order_id, score, signals, scored_at)
+            VALUES (?1, ?2, ?3, ?4)",
+        )
+        .bind(order_id)
+        .bind(assessment.score)
+        .bind(serde_json::to_string(&assessment.signals).unwrap_or_default())
+        .bind(assessment.scored_at)
+        .execute(&mut **tx)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get(&self, order_id: &str) -> Result<Option<RiskAssessment>, sqlx::Error> {
+        let Some(row) =
+            sqlx::query("SELECT score, signals, scored_at FROM order_risk WHERE order_id = ?1")
+                .bind(order_id)
+                .fetch_optional(&self.pool)
+                .await?
+        else {
+            return Ok(None);
+        };
+
+        let signals: String = row.try_get("signals")?;
+        Ok(Some(RiskAssessment {
+            score: row.try_get("score")?,
+            signals: serde_json::from_str(&signals).unwrap_or_default(),
+            scored_at: row.try_get("scored_at")?,
+        }))
+    }
+}
@@ -0,0 +1,265 @@
+use crate::supervisor::{panic_message, Supervisor};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use cron::Schedule;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+use tracing::{error, info, info_span, warn, Instrument};
+use utoipa::ToSchema;
+
+/// What a scheduled run did
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TaskRun {
+    Done,
+    /// The run had nothing to do or chose not to run (e.g. backing off)
+    Skipped(String),
+}
+
+/// Periodic work run by the [`Scheduler`]
+#[async_trait]
+pub trait ScheduledTask: Send + Sync {
+    async fn run(&self) -> Result<TaskRun, String>;
+}
+
+/// Last-run status of a scheduled task
+#[derive(Debug, Clone, Default, Serialize, ToSchema)]
+pub struct TaskStatus {
+    pub name: String,
+    /// Cron expression (`sec min hour day-of-month month day-of-week`)
+    pub schedule: String,
+    pub next_run_at: Option<DateTime<Utc>>,
+    pub last_started_at: Option<DateTime<Utc>>,
+    pub last_duration_ms: Option<u64>,
+    /// `ok`, `skipped` or `failed`; unset until the first run finishes
+    pub last_outcome: Option<String>,
+    /// Error of a failed run, or why a run was skipped
+    pub last_detail: Option<String>,
+    pub consecutive_failures: u32,
+}
+
+impl TaskStatus {
+    /// A task is healthy until it fails
+    pub fn is_healthy(&self) -> bool {
+        self.consecutive_failures == 0
+    }
+}
+
+/// Shared view of every task's last run, for readiness checks, and handle
+/// to change their schedules
+#[derive(Clone, Default)]
+pub struct SchedulerStatus {
+    tasks: Arc<RwLock<BTreeMap<String, TaskStatus>>>,
+    schedules: Arc<RwLock<BTreeMap<String, watch::Sender<Schedule>>>>,
+}
+
+impl SchedulerStatus {
+    pub fn tasks(&self) -> Vec<TaskStatus> {
+        self.tasks.read().unwrap().values().cloned().collect()
+    }
+
+    /// Run the task registered under `name` on `schedule` from its next run
+    /// on. Returns `false` if there is no such task or it already runs on
+    /// `schedule`.
+    pub fn reschedule(&self, name: &str, schedule: Schedule) -> bool {
+        let schedules = self.schedules.read().unwrap();
+        let Some(sender) = schedules.get(name) else {
+            return false;
+        };
+        if sender.borrow().to_string() == schedule.to_string() {
+            return false;
+        }
+        info!("Rescheduled {} ({})", name, schedule);
+        self.update(name, |s| {
+            s.schedule = schedule.to_string();
+            s.next_run_at = schedule.upcoming(Utc).next();
+        });
+        sender.send_replace(schedule);
+        true
+    }
+
+    fn update(&self, name: &str, f: impl FnOnce(&mut TaskStatus)) {
+        if let Some(status) = self.tasks.write().unwrap().get_mut(name) {
+            f(status);
+        }
+    }
+}
+
+/// Parse a cron expression with a seconds field, e.g. `0 0 * * * *` for hourly
+pub fn parse_schedule(expression: &str) -> Result<Schedule, String> {
+    Schedule::from_str(expression.trim()).map_err(|e| e.to_string())
+}
+
+/// Gap between the next two runs of `schedule`, used as its nominal period
+pub fn period(schedule: &Schedule) -> Option<Duration> {
+    let mut upcoming = schedule.upcoming(Utc);
+    let (first, second) = (upcoming.next()?, upcoming.next()?);
+    (second - first).to_std().ok()
+}
+
+/// Runs registered tasks on cron schedules (UTC). Each task runs in its own
+/// loop, so a slow run delays only that task and runs never overlap.
+pub struct Scheduler {
+    tasks: Vec<Registration>,
+    status: SchedulerStatus,
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        Self {
+            tasks: Vec::new(),
+            status: SchedulerStatus::default(),
+        }
+    }
+
+    /// Register a task under `name` to run on `schedule`
+    pub fn register(self, name: &str, schedule: Schedule, task: Arc<dyn ScheduledTask>) -> Self {
+        self.add(name, schedule, task, false)
+    }
+
+    /// Like [`register`](Self::register), but also run the task once as soon as the scheduler starts
+    pub fn register_at_startup(
+        self,
+        name: &str,
+        schedule: Schedule,
+        task: Arc<dyn ScheduledTask>,
+    ) -> Self {
+        self.add(name, schedule, task, true)
+    }
+
+    fn add(
+        mut self,
+        name: &str,
+        schedule: Schedule,
+        task: Arc<dyn ScheduledTask>,
+        at_startup: bool,
+    ) -> Self {
+        self.status.tasks.write().unwrap().insert(
+            name.to_string(),
+            TaskStatus {
+                name: name.to_string(),
+                schedule: schedule.to_string(),
+                next_run_at: schedule.upcoming(Utc).next(),
+                ..TaskStatus::default()
+            },
+        );
+        let (sender, schedule) = watch::channel(schedule);
+        self.status
+            .schedules
+            .write()
+            .unwrap()
+            .insert(name.to_string(), sender);
+        self.tasks.push(Registration {
+            name: name.to_string(),
+            schedule,
+            task,
+            at_startup,
+        });
+        self
+    }
+
+    pub fn status(&self) -> SchedulerStatus {
+        self.status.clone()
+    }
+
+    /// Spawn one loop per registered task under `supervisor`
+    pub fn start(self, supervisor: &Supervisor) {
+        for registration in self.tasks {
+            info!(
+                "Scheduled {} ({})",
+                registration.name,
+                *registration.schedule.borrow()
+            );
+            let status = self.status.clone();
+            supervisor.spawn(&format!("scheduler:{}", registration.name), move || {
+                run_task(registration.clone(), status.clone())
+            });
+        }
+    }
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Clone)]
+struct Registration {
+    name: String,
+    schedule: watch::Receiver<Schedule>,
+    task: Arc<dyn ScheduledTask>,
+    at_startup: bool,
+}
+
+async fn run_task(registration: Registration, status: SchedulerStatus) {
+    let Registration {
+        name,
+        mut schedule,
+        task,
+        mut at_startup,
+    } = registration;
+
+    loop {
+        if !at_startup {
+            let next = schedule.borrow_and_update().upcoming(Utc).next();
+            let Some(next) = next else {
+                warn!("Schedule for {} has no upcoming runs, stopping it", name);
+                return;
+            };
+            status.update(&name, |s| s.next_run_at = Some(next));
+
+            let wait = (next - Utc::now()).to_std().unwrap_or(Duration::ZERO);
+            tokio::select! {
+                _ = tokio::time::sleep(wait) => {}
+                // Rescheduled: wait for the next run of the new schedule instead
+                Ok(()) = schedule.changed() => continue,
+            }
+        }
+        at_startup = false;
+
+        let started_at = Utc::now();
+        let started = Instant::now();
+        status.update(&name, |s| s.last_started_at = Some(started_at));
+
+        // A panicking run counts as a failed one rather than ending the loop
+        let run = tokio::spawn({
+            let task = task.clone();
+            let span = info_span!("scheduled_task", task = %name);
+            async move { task.run().await }.instrument(span)
+        });
+        let result = run
+            .await
+            .unwrap_or_else(|e| Err(format!("panicked: {}", panic_message(e))));
+        let duration_ms = started.elapsed().as_millis() as u64;
+
+        status.update(&name, |s| {
+            s.last_duration_ms = Some(duration_ms);
+            match &result {
+                Ok(TaskRun::Done) => {
+                    s.last_outcome = Some("ok".to_string());
+                    s.last_detail = None;
+                    s.consecutive_failures = 0;
+                }
+                Ok(TaskRun::Skipped(reason)) => {
+                    s.last_outcome = Some("skipped".to_string());
+                    s.last_detail = Some(reason.clone());
+                }
+                Err(e) => {
+                    s.last_outcome = Some("failed".to_string());
+                    s.last_detail = Some(e.clone());
+                    s.consecutive_failures += 1;
+                }
+            }
+        });
+
+        match result {
+            Ok(TaskRun::Done) => {}
+            Ok(TaskRun::Skipped(reason)) => info!("Skipped scheduled {}: {}", name, reason),
+            Err(e) => error!("Scheduled {} failed: {}", name, e),
+        }
+    }
+}
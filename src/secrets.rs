@@ -0,0 +1,424 @@
+#[cfg(feature = "aws-secrets")]
+use crate::aws_sigv4;
+use crate::error::AppError;
+#[cfg(any(feature = "vault", feature = "aws-secrets"))]
+use crate::redact;
+use crate::storage::{TokenBackend, TokenInfo};
+#[cfg(any(feature = "vault", feature = "aws-secrets"))]
+use crate::transport::{HttpRequest, HttpTransport, ReqwestTransport};
+use async_trait::async_trait;
+#[cfg(any(feature = "vault", feature = "aws-secrets"))]
+use reqwest::{Method, StatusCode};
+use std::sync::{Arc, RwLock};
+#[cfg(any(feature = "vault", feature = "aws-secrets"))]
+use std::time::Duration;
+
+/// Secret holding the TikTok app secret
+pub const APP_SECRET: &str = "TIKTOK_APP_SECRET";
+/// Secret holding the WowEsim API secret
+pub const WOW_SECRET: &str = "WOW_SECRET";
+/// Secret holding the admin API token
+pub const ADMIN_TOKEN: &str = "ADMIN_TOKEN";
+/// Secret holding the TikTok OAuth token, as the JSON of the token file
+pub const TOKEN: &str = "TIKTOK_TOKEN";
+
+/// Secret holding the OAuth token of `tenant_id`, or of the single app
+pub fn token_secret(tenant_id: Option<&str>) -> String {
+    match tenant_id {
+        Some(id) => format!("{}_{}", TOKEN, id),
+        None => TOKEN.to_string(),
+    }
+}
+
+/// Mount of the KV v2 engine unless `VAULT_MOUNT` names another
+#[cfg(feature = "vault")]
+pub const DEFAULT_VAULT_MOUNT: &str = "secret";
+
+/// Timeout of a single secret manager request
+#[cfg(any(feature = "vault", feature = "aws-secrets"))]
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Somewhere secrets are read from and written to
+#[async_trait]
+pub trait SecretProvider: Send + Sync {
+    /// Where the secrets are kept, for logs
+    fn describe(&self) -> String;
+    /// Value of secret `name`, if it is set
+    async fn get(&self, name: &str) -> Result<Option<String>, AppError>;
+    /// Set secret `name` to `value`
+    async fn put(&self, name: &str, value: &str) -> Result<(), AppError>;
+}
+
+/// Which [`SecretProvider`] the server uses (`SECRET_PROVIDER`)
+#[derive(Clone, Debug)]
+pub enum SecretSource {
+    /// Environment variables and the config file only
+    Env,
+    #[cfg(feature = "vault")]
+    Vault(VaultSettings),
+    #[cfg(feature = "aws-secrets")]
+    AwsSecretsManager(AwsSecretsSettings),
+}
+
+impl SecretSource {
+    /// Whether secrets may live outside the environment and the config file
+    pub fn is_external(&self) -> bool {
+        !matches!(self, SecretSource::Env)
+    }
+
+    pub fn provider(&self) -> Arc<dyn SecretProvider> {
+        match self {
+            SecretSource::Env => Arc::new(EnvSecretProvider),
+            #[cfg(feature = "vault")]
+            SecretSource::Vault(settings) => Arc::new(VaultSecretProvider::new(
+                settings.clone(),
+                Arc::new(secret_transport()),
+            )),
+            #[cfg(feature = "aws-secrets")]
+            SecretSource::AwsSecretsManager(settings) => Arc::new(AwsSecretsProvider::new(
+                settings.clone(),
+                Arc::new(secret_transport()),
+            )),
+        }
+    }
+}
+
+#[cfg(any(feature = "vault", feature = "aws-secrets"))]
+fn secret_transport() -> ReqwestTransport {
+    let client = reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .expect("reqwest client builds");
+    ReqwestTransport::with_client(client)
+}
+
+/// Secrets from environment variables of the same name. Read-only.
+pub struct EnvSecretProvider;
+
+#[async_trait]
+impl SecretProvider for EnvSecretProvider {
+    fn describe(&self) -> String {
+        "the environment".to_string()
+    }
+
+    async fn get(&self, name: &str) -> Result<Option<String>, AppError> {
+        Ok(std::env::var(name).ok().filter(|value| !value.is_empty()))
+    }
+
+    async fn put(&self, name: &str, _value: &str) -> Result<(), AppError> {
+        Err(AppError::ConfigError(format!(
+            "cannot store {}: environment secrets are read-only",
+            name
+        )))
+    }
+}
+
+/// HashiCorp Vault KV v2 secret holding one field per secret
+#[cfg(feature = "vault")]
+#[derive(Clone)]
+pub struct VaultSettings {
+    /// e.g. `https://vault.internal:8200`
+    pub addr: String,
+    pub token: String,
+    pub mount: String,
+    /// Path of the secret within the mount, e.g. `toptop-order/production`
+    pub path: String,
+}
+
+#[cfg(feature = "vault")]
+impl std::fmt::Debug for VaultSettings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("VaultSettings")
+            .field("addr", &self.addr)
+            .field("token", &redact::mask(&self.token))
+            .field("mount", &self.mount)
+            .field("path", &self.path)
+            .finish()
+    }
+}
+
+/// Secrets in a Vault KV v2 secret, one field each
+#[cfg(feature = "vault")]
+pub struct VaultSecretProvider {
+    settings: VaultSettings,
+    transport: Arc<dyn HttpTransport>,
+}
+
+#[cfg(feature = "vault")]
+impl VaultSecretProvider {
+    pub fn new(settings: VaultSettings, transport: Arc<dyn HttpTransport>) -> Self {
+        Self {
+            settings,
+            transport,
+        }
+    }
+
+    fn url(&self) -> String {
+        format!(
+            "{}/v1/{}/data/{}",
+            self.settings.addr.trim_end_matches('/'),
+            self.settings.mount.trim_matches('/'),
+            self.settings.path.trim_matches('/')
+        )
+    }
+
+    fn request(&self, method: Method) -> HttpRequest {
+        HttpRequest::new(method, self.url()).with_header("X-Vault-Token", &self.settings.token)
+    }
+}
+
+#[cfg(feature = "vault")]
+#[async_trait]
+impl SecretProvider for VaultSecretProvider {
+    fn describe(&self) -> String {
+        format!("Vault {}", self.url())
+    }
+
+    async fn get(&self, name: &str) -> Result<Option<String>, AppError> {
+        let response = self.transport.send(self.request(Method::GET)).await?;
+        if response.status == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !response.status.is_success() {
+            return Err(AppError::HttpStatus {
+                status: response.status,
+                body: response.body,
+            });
+        }
+        let body: serde_json::Value = serde_json::from_str(&response.body)
+            .map_err(|e| AppError::parse("Vault secret", e))?;
+        Ok(body["data"]["data"][name].as_str().map(str::to_string))
+    }
+
+    async fn put(&self, name: &str, value: &str) -> Result<(), AppError> {
+        let body = serde_json::json!({ "data": { name: value } }).to_string();
+        // PATCH keeps the other fields, but needs the secret to exist already
+        let patch = self
+            .request(Method::PATCH)
+            .with_header("Content-Type", "application/merge-patch+json")
+            .with_body(body.clone());
+        let mut response = self.transport.send(patch).await?;
+        if response.status == StatusCode::NOT_FOUND {
+            let create = self
+                .request(Method::POST)
+                .with_header("Content-Type", "application/json")
+                .with_body(body);
+            response = self.transport.send(create).await?;
+        }
+        if !response.status.is_success() {
+            return Err(AppError::HttpStatus {
+                status: response.status,
+                body: response.body,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// AWS Secrets Manager secrets, one per secret name
+#[cfg(feature = "aws-secrets")]
+#[derive(Clone)]
+pub struct AwsSecretsSettings {
+    pub region: String,
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    /// Session token of temporary credentials
+    pub session_token: Option<String>,
+    /// Prepended to secret names, e.g. `toptop-order/production/`
+    pub prefix: String,
+}
+
+#[cfg(feature = "aws-secrets")]
+impl std::fmt::Debug for AwsSecretsSettings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AwsSecretsSettings")
+            .field("region", &self.region)
+            .field("access_key_id", &self.access_key_id)
+            .field("secret_access_key", &redact::mask(&self.secret_access_key))
+            .field("session_token", &self.session_token.as_deref().map(redact::mask))
+            .field("prefix", &self.prefix)
+            .finish()
+    }
+}
+
+/// Secrets in AWS Secrets Manager, called with AWS Signature Version 4
+#[cfg(feature = "aws-secrets")]
+pub struct AwsSecretsProvider {
+    settings: AwsSecretsSettings,
+    transport: Arc<dyn HttpTransport>,
+}
+
+#[cfg(feature = "aws-secrets")]
+impl AwsSecretsProvider {
+    pub fn new(settings: AwsSecretsSettings, transport: Arc<dyn HttpTransport>) -> Self {
+        Self {
+            settings,
+            transport,
+        }
+    }
+
+    fn host(&self) -> String {
+        format!("secretsmanager.{}.amazonaws.com", self.settings.region)
+    }
+
+    fn secret_id(&self, name: &str) -> String {
+        format!("{}{}", self.settings.prefix, name)
+    }
+
+    /// Call `action` (e.g. `GetSecretValue`) with `body`. Returns the status
+    /// and the parsed response body.
+    async fn call(
+        &self,
+        action: &str,
+        body: serde_json::Value,
+    ) -> Result<(StatusCode, serde_json::Value), AppError> {
+        let body = body.to_string();
+        let host = self.host();
+        let target = format!("secretsmanager.{}", action);
+        let content_type = "application/x-amz-json-1.1";
+        let params = aws_sigv4::SigningParams {
+            access_key_id: &self.settings.access_key_id,
+            secret_access_key: &self.settings.secret_access_key,
+            region: &self.settings.region,
+            service: "secretsmanager",
+            time: chrono::Utc::now(),
+        };
+        let amz_date = params.amz_date();
+
+        let mut headers = vec![
+            ("content-type", content_type),
+            ("host", host.as_str()),
+            ("x-amz-date", amz_date.as_str()),
+            ("x-amz-target", target.as_str()),
+        ];
+        if let Some(token) = &self.settings.session_token {
+            headers.push(("x-amz-security-token", token));
+        }
+        let authorization = aws_sigv4::authorization(
+            &params,
+            "POST",
+            "/",
+            "",
+            &headers,
+            &aws_sigv4::payload_hash(body.as_bytes()),
+        );
+
+        let mut request = HttpRequest::post(format!("https://{}/", host))
+            .with_header("Authorization", &authorization)
+            .with_header("Content-Type", content_type)
+            .with_header("X-Amz-Date", &amz_date)
+            .with_header("X-Amz-Target", &target)
+            .with_body(body);
+        if let Some(token) = &self.settings.session_token {
+            request = request.with_header("X-Amz-Security-Token", token);
+        }
+        let response = self.transport.send(request).await?;
+        let parsed = serde_json::from_str(&response.body).unwrap_or(serde_json::Value::Null);
+        if !response.status.is_success() && !is_not_found(&parsed) {
+            return Err(AppError::HttpStatus {
+                status: response.status,
+                body: response.body,
+            });
+        }
+        Ok((response.status, parsed))
+    }
+}
+
+/// Whether a Secrets Manager error body says the secret doesn't exist
+#[cfg(feature = "aws-secrets")]
+fn is_not_found(body: &serde_json::Value) -> bool {
+    body["__type"]
+        .as_str()
+        .is_some_and(|kind| kind.ends_with("ResourceNotFoundException"))
+}
+
+#[cfg(feature = "aws-secrets")]
+#[async_trait]
+impl SecretProvider for AwsSecretsProvider {
+    fn describe(&self) -> String {
+        format!(
+            "AWS Secrets Manager {}/{}*",
+            self.settings.region, self.settings.prefix
+        )
+    }
+
+    async fn get(&self, name: &str) -> Result<Option<String>, AppError> {
+        let (status, body) = self
+            .call(
+                "GetSecretValue",
+                serde_json::json!({ "SecretId": self.secret_id(name) }),
+            )
+            .await?;
+        if !status.is_success() {
+            return Ok(None);
+        }
+        Ok(body["SecretString"].as_str().map(str::to_string))
+    }
+
+    async fn put(&self, name: &str, value: &str) -> Result<(), AppError> {
+        let (status, _) = self
+            .call(
+                "PutSecretValue",
+                serde_json::json!({ "SecretId": self.secret_id(name), "SecretString": value }),
+            )
+            .await?;
+        if !status.is_success() {
+            self.call(
+                "CreateSecret",
+                serde_json::json!({ "Name": self.secret_id(name), "SecretString": value }),
+            )
+            .await?;
+        }
+        Ok(())
+    }
+}
+
+/// Keeps the OAuth token in a [`SecretProvider`] instead of the token file.
+/// The token is read once and cached; saves write through to the provider.
+pub struct SecretTokenBackend {
+    provider: Arc<dyn SecretProvider>,
+    name: String,
+    token: RwLock<Option<TokenInfo>>,
+}
+
+impl SecretTokenBackend {
+    /// Backend holding the token currently stored in secret `name` of `provider`
+    pub async fn load(provider: Arc<dyn SecretProvider>, name: String) -> Result<Self, AppError> {
+        let stored = provider.get(&name).await?;
+        let token = match stored.filter(|json| !json.is_empty()) {
+            Some(json) => Some(
+                serde_json::from_str(&json)
+                    .map_err(|e| AppError::parse(format!("{} secret", name), e))?,
+            ),
+            None => None,
+        };
+        Ok(Self {
+            provider,
+            name,
+            token: RwLock::new(token),
+        })
+    }
+}
+
+#[async_trait]
+impl TokenBackend for SecretTokenBackend {
+    fn describe(&self) -> String {
+        format!("{} in {}", self.name, self.provider.describe())
+    }
+
+    fn load(&self) -> Result<Option<TokenInfo>, AppError> {
+        Ok(self.token.read().unwrap().clone())
+    }
+
+    async fn save(&self, token: Option<&TokenInfo>) -> Result<(), AppError> {
+        // A cleared token is stored as empty
+        let value = match token {
+            Some(token) => serde_json::to_string(token)
+                .map_err(|e| AppError::parse("serializing token", e))?,
+            None => String::new(),
+        };
+        self.provider.put(&self.name, &value).await?;
+        *self.token.write().unwrap() = token.cloned();
+        Ok(())
+    }
+}
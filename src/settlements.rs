@@ -0,0 +1,257 @@
+use crate::config::Config;
+use crate::database::Database;
+use crate::error::AppError;
+use crate::finance::OrderStatement;
+use crate::jobs::{Job, JobHandler, JobQueue};
+use crate::order::Order;
+use crate::shops::ShopRegistry;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::SqlitePool;
+use sqlx::Row;
+use std::collections::HashMap;
+use std::sync::Arc;
+use utoipa::ToSchema;
+
+/// Fetch a completed order's settlement from TikTok and store it
+pub const FETCH_JOB_KIND: &str = "settlement_fetch";
+
+/// Status of a statement transaction that has been paid out
+const SETTLED: &str = "SETTLED";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FetchJobPayload {
+    pub order_id: String,
+}
+
+/// An order's settlement as last fetched
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct StoredSettlement {
+    pub order_id: String,
+    /// Whether every statement transaction of the order has been paid out
+    pub settled: bool,
+    pub statement: OrderStatement,
+    pub fetched_at: i64,
+}
+
+impl StoredSettlement {
+    /// Fees and taxes TikTok withheld, as a positive amount
+    pub fn fees(&self) -> Option<f64> {
+        self.statement
+            .fee_and_tax_amount
+            .as_deref()
+            .and_then(|amount| amount.trim().parse::<f64>().ok())
+            .map(f64::abs)
+    }
+}
+
+/// Whether TikTok has paid out every transaction of the order
+pub fn is_settled(statement: &OrderStatement) -> bool {
+    !statement.statement_transactions.is_empty()
+        && statement
+            .statement_transactions
+            .iter()
+            .all(|t| t.status.as_deref() == Some(SETTLED))
+}
+
+/// Settlements of completed orders (revenue, fees and payout), in the
+/// `order_settlements` table, for accounting exports
+#[derive(Clone)]
+pub struct SettlementStore {
+    pool: SqlitePool,
+}
+
+impl SettlementStore {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Initialize the order_settlements table
+    pub async fn init(&self) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS order_settlements (
+                order_id TEXT PRIMARY KEY,
+                currency TEXT NOT NULL,
+                revenue_amount TEXT,
+                fee_and_tax_amount TEXT,
+                shipping_cost_amount TEXT,
+                settlement_amount TEXT,
+                settled INTEGER NOT NULL,
+                statement TEXT NOT NULL,
+                fetched_at INTEGER NOT NULL
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn save(
+        &self,
+        order_id: &str,
+        statement: &OrderStatement,
+    ) -> Result<StoredSettlement, sqlx::Error> {
+        let fetched_at = chrono::Utc::now().timestamp();
+        let settled = is_settled(statement);
+        sqlx::query(
+            "INSERT INTO order_settlements (order_id, currency, revenue_amount,
+                fee_and_tax_amount, shipping_cost_amount, settlement_amount, settled, statement,
+                fetched_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+            ON CONFLICT(order_id) DO UPDATE SET
+                currency = excluded.currency,
+                revenue_amount = excluded.revenue_amount,
+                fee_and_tax_amount = excluded.fee_and_tax_amount,
+                shipping_cost_amount = excluded.shipping_cost_amount,
+                settlement_amount = excluded.settlement_amount,
+                settled = excluded.settled,
+                statement = excluded.statement,
+                fetched_at = excluded.fetched_at",
+        )
+        .bind(order_id)
+        .bind(&statement.currency)
+        .bind(&statement.revenue_amount)
+        .bind(&statement.fee_and_tax_amount)
+        .bind(&statement.shipping_cost_amount)
+        .bind(&statement.settlement_amount)
+        .bind(settled)
+        .bind(serde_json::to_string(statement).unwrap_or_default())
+        .bind(fetched_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(StoredSettlement {
+            order_id: order_id.to_string(),
+            settled,
+            statement: statement.clone(),
+            fetched_at,
+        })
+    }
+
+    /// Stored settlements of the given orders, by order id
+    pub async fn get_many(
+        &self,
+        order_ids: &[String],
+    ) -> Result<HashMap<String, StoredSettlement>, sqlx::Error> {
+        let mut settlements = HashMap::new();
+        for order_id in order_ids {
+            let Some(row) = sqlx::query(
+                "SELECT order_id, settled, statement, fetched_at
+                FROM order_settlements WHERE order_id = ?1",
+            )
+            .bind(order_id)
+            .fetch_optional(&self.pool)
+            .await?
+            else {
+                continue;
+            };
+
+            let statement: String = row.try_get("statement")?;
+            settlements.insert(
+                order_id.clone(),
+                StoredSettlement {
+                    order_id: row.try_get("order_id")?,
+                    settled: row.try_get("settled")?,
+                    statement: serde_json::from_str(&statement)
+                        .map_err(|e| sqlx::Error::Decode(e.into()))?,
+                    fetched_at: row.try_get("fetched_at")?,
+                },
+            );
+        }
+        Ok(settlements)
+    }
+}
+
+/// Queue a settlement fetch for each completed order. Jobs are deduplicated
+/// per order version; orders TikTok has not paid out yet are fetched again
+/// by the accounting export.
+pub async fn enqueue_fetch(
+    job_queue: &JobQueue,
+    orders: &[Order],
+    max_attempts: u32,
+) -> Result<usize, sqlx::Error> {
+    let mut queued = 0;
+    for order in orders.iter().filter(|o| o.status == "COMPLETED") {
+        let payload = serde_json::to_value(FetchJobPayload {
+            order_id: order.id.clone(),
+        })
+        .unwrap_or_default();
+        if job_queue
+            .enqueue(
+                FETCH_JOB_KIND,
+                Some(&format!(
+                    "{}:{}:{}",
+                    FETCH_JOB_KIND, order.id, order.update_time
+                )),
+                &payload,
+                max_attempts,
+            )
+            .await?
+        {
+            queued += 1;
+        }
+    }
+    Ok(queued)
+}
+
+/// Fetches order settlements from TikTok into the store
+#[derive(Clone)]
+pub struct SettlementFetcher {
+    store: SettlementStore,
+    shops: ShopRegistry,
+    config: Config,
+}
+
+impl SettlementFetcher {
+    pub fn new(store: SettlementStore, shops: ShopRegistry, config: Config) -> Self {
+        Self {
+            store,
+            shops,
+            config,
+        }
+    }
+
+    /// Fetch and store the settlement of an order
+    pub async fn fetch(&self, order_id: &str) -> Result<StoredSettlement, AppError> {
+        let token = self.config.token_manager().valid_token().await?;
+        let statement = self
+            .shops
+            .target(&self.config)
+            .await?
+            .finance_client(&self.config)
+            .get_order_statement(&token.access_token, None, order_id)
+            .await?;
+        Ok(self.store.save(order_id, &statement).await?)
+    }
+}
+
+/// Runs [`FETCH_JOB_KIND`] jobs; needs the order to be stored already
+pub struct SettlementFetchHandler {
+    fetcher: SettlementFetcher,
+    db: Arc<Database>,
+}
+
+impl SettlementFetchHandler {
+    pub fn new(fetcher: SettlementFetcher, db: Arc<Database>) -> Self {
+        Self { fetcher, db }
+    }
+}
+
+#[async_trait]
+impl JobHandler for SettlementFetchHandler {
+    async fn handle(&self, job: &Job) -> Result<(), String> {
+        let payload: FetchJobPayload = serde_json::from_value(job.payload.clone())
+            .map_err(|e| format!("Invalid settlement payload: {}", e))?;
+        let order = self
+            .db
+            .get_order_by_id(&payload.order_id)
+            .await
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| format!("Order {} is not stored", payload.order_id))?;
+        self.fetcher
+            .fetch(&order.id)
+            .await
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+}
@@ -0,0 +1,222 @@
+use crate::analytics::{self, GetShopPerformanceRequest, ShopPerformanceInterval};
+use crate::config::Config;
+use crate::error::AppError;
+use crate::reporting;
+use crate::shops::ShopRegistry;
+use chrono::{Duration, NaiveDate};
+use serde::Serialize;
+use sqlx::sqlite::SqlitePool;
+use sqlx::Row;
+use utoipa::ToSchema;
+
+/// Days fetched again on every snapshot, since TikTok keeps revising recent
+/// days' metrics for a while
+pub const REFRESH_DAYS: i64 = 7;
+
+/// Most days one snapshot or query may span
+pub const MAX_RANGE_DAYS: i64 = 366;
+
+/// One day of TikTok shop metrics, next to what the stored orders add up to
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ShopPerformanceDay {
+    pub shop_id: String,
+    /// `YYYY-MM-DD`
+    pub day: String,
+    /// Currency of `gmv`; empty when TikTok reported none
+    pub currency: String,
+    pub gmv: Option<f64>,
+    pub orders: Option<i64>,
+    pub units_sold: Option<i64>,
+    pub buyers: Option<i64>,
+    /// Every metric TikTok returned for the day
+    pub metrics: ShopPerformanceInterval,
+    pub fetched_at: i64,
+    /// Orders counted by the nightly stats rollup for the same day
+    pub stored_orders: Option<i64>,
+    /// Revenue of the stored orders in `currency`, from the same rollup
+    pub stored_revenue: Option<f64>,
+}
+
+/// Daily shop performance snapshots from the Data Analytics API, in the
+/// `shop_performance` table
+#[derive(Clone)]
+pub struct ShopPerformanceStore {
+    pool: SqlitePool,
+}
+
+impl ShopPerformanceStore {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Initialize the shop_performance table
+    pub async fn init(&self) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS shop_performance (
+                shop_id TEXT NOT NULL,
+                day TEXT NOT NULL,
+                currency TEXT NOT NULL,
+                gmv REAL,
+                orders INTEGER,
+                units_sold INTEGER,
+                buyers INTEGER,
+                metrics TEXT NOT NULL,
+                fetched_at INTEGER NOT NULL,
+                PRIMARY KEY (shop_id, day)
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Store the metrics of one day, replacing an earlier snapshot of it
+    pub async fn save(
+        &self,
+        shop_id: &str,
+        interval: &ShopPerformanceInterval,
+    ) -> Result<(), sqlx::Error> {
+        let gmv = interval.gmv.first();
+        sqlx::query(
+            "INSERT INTO shop_performance (shop_id, day, currency, gmv, orders, units_sold,
+                buyers, metrics, fetched_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+            ON CONFLICT(shop_id, day) DO UPDATE SET
+                currency = excluded.currency,
+                gmv = excluded.gmv,
+                orders = excluded.orders,
+                units_sold = excluded.units_sold,
+                buyers = excluded.buyers,
+                metrics = excluded.metrics,
+                fetched_at = excluded.fetched_at",
+        )
+        .bind(shop_id)
+        .bind(&interval.start_date)
+        .bind(gmv.map(|g| g.currency.as_str()).unwrap_or_default())
+        .bind(gmv.and_then(|g| g.value()))
+        .bind(interval.orders)
+        .bind(interval.units_sold)
+        .bind(interval.buyers)
+        .bind(serde_json::to_string(interval).unwrap_or_default())
+        .bind(chrono::Utc::now().timestamp())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Snapshots of `shop_id` from `from` to `to` inclusive, oldest first,
+    /// joined with the `daily_stats` rollup of the same days
+    pub async fn list(
+        &self,
+        shop_id: &str,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<Vec<ShopPerformanceDay>, sqlx::Error> {
+        let rows = sqlx::query(
+            "SELECT p.shop_id, p.day, p.currency, p.gmv, p.orders, p.units_sold, p.buyers,
+                p.metrics, p.fetched_at,
+                (SELECT SUM(d.order_count) FROM daily_stats d
+                    WHERE d.shop_id = p.shop_id AND d.day = p.day) AS stored_orders,
+                (SELECT d.revenue FROM daily_stats d
+                    WHERE d.shop_id = p.shop_id AND d.day = p.day
+                    AND d.currency = p.currency) AS stored_revenue
+            FROM shop_performance p
+            WHERE p.shop_id = ?1 AND p.day >= ?2 AND p.day <= ?3
+            ORDER BY p.day",
+        )
+        .bind(shop_id)
+        .bind(from.to_string())
+        .bind(to.to_string())
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter()
+            .map(|row| {
+                let metrics: String = row.try_get("metrics")?;
+                Ok(ShopPerformanceDay {
+                    shop_id: row.try_get("shop_id")?,
+                    day: row.try_get("day")?,
+                    currency: row.try_get("currency")?,
+                    gmv: row.try_get("gmv")?,
+                    orders: row.try_get("orders")?,
+                    units_sold: row.try_get("units_sold")?,
+                    buyers: row.try_get("buyers")?,
+                    metrics: serde_json::from_str(&metrics)
+                        .map_err(|e| sqlx::Error::Decode(e.into()))?,
+                    fetched_at: row.try_get("fetched_at")?,
+                    stored_orders: row.try_get("stored_orders")?,
+                    stored_revenue: row.try_get("stored_revenue")?,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Outcome of a snapshot
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SnapshotOutcome {
+    /// Days stored
+    Stored(usize),
+    /// The app or shop isn't authorized for the analytics endpoints
+    NotAuthorized(String),
+}
+
+/// Copies daily shop performance from TikTok into the store
+#[derive(Clone)]
+pub struct ShopPerformanceSync {
+    store: ShopPerformanceStore,
+    shops: ShopRegistry,
+    config: Config,
+}
+
+impl ShopPerformanceSync {
+    pub fn new(store: ShopPerformanceStore, shops: ShopRegistry, config: Config) -> Self {
+        Self {
+            store,
+            shops,
+            config,
+        }
+    }
+
+    /// Shop the snapshots are stored under, the same one `daily_stats` uses
+    pub async fn shop_id(&self) -> Result<String, sqlx::Error> {
+        self.shops.configured_shop_id(&self.config).await
+    }
+
+    /// Fetch and store the days from `from` to `to` inclusive
+    pub async fn snapshot(
+        &self,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<SnapshotOutcome, AppError> {
+        let token = self.config.token_manager().valid_token().await?;
+        let request = GetShopPerformanceRequest::daily(from, to + Duration::days(1));
+        let intervals = match self
+            .shops
+            .target(&self.config)
+            .await?
+            .analytics_client(&self.config)
+            .get_shop_performance(&token.access_token, None, &request)
+            .await
+        {
+            Ok(intervals) => intervals,
+            Err(e) if analytics::is_not_authorized(&e) => {
+                return Ok(SnapshotOutcome::NotAuthorized(e.to_string()))
+            }
+            Err(e) => return Err(e),
+        };
+
+        let shop_id = self.shop_id().await?;
+        for interval in &intervals {
+            self.store.save(&shop_id, interval).await?;
+        }
+        Ok(SnapshotOutcome::Stored(intervals.len()))
+    }
+
+    /// Snapshot the last [`REFRESH_DAYS`] complete days (see `REPORT_TIMEZONE`)
+    pub async fn snapshot_recent(&self) -> Result<SnapshotOutcome, AppError> {
+        let yesterday = reporting::today(self.config.report_timezone) - Duration::days(1);
+        self.snapshot(yesterday - Duration::days(REFRESH_DAYS - 1), yesterday)
+            .await
+    }
+}
@@ -0,0 +1,172 @@
+use serde::{Deserialize, Deserializer, Serialize};
+use sqlx::sqlite::SqlitePool;
+use sqlx::Row;
+use utoipa::ToSchema;
+
+/// Shortest per-shop sync interval
+pub const MIN_SYNC_INTERVAL_SECS: u64 = 60;
+
+/// Longest per-shop sync interval
+pub const MAX_SYNC_INTERVAL_SECS: u64 = 7 * 86_400;
+
+/// Most days a default backfill may reach back
+pub const MAX_BACKFILL_DAYS: u32 = 3650;
+
+/// Sync settings of one shop. Shops without stored settings sync on
+/// `SYNC_SCHEDULE` and have no default backfill range.
+#[derive(Debug, Clone, Default, Serialize, ToSchema)]
+pub struct ShopSettings {
+    pub shop_id: String,
+    /// Scheduled order syncs are skipped while set
+    pub sync_paused: bool,
+    /// Least time between scheduled order syncs; `SYNC_SCHEDULE` still
+    /// decides when a sync may start
+    pub sync_interval_secs: Option<u64>,
+    /// Days `POST /sync/backfill` reaches back when it is given no `from`
+    pub backfill_days: Option<u32>,
+    pub updated_at: Option<i64>,
+}
+
+/// Body of `PATCH /shops/{id}/settings`. Omitted fields are left as they
+/// are; `null` resets one to its default.
+#[derive(Debug, Clone, Default, Deserialize, ToSchema)]
+pub struct ShopSettingsUpdate {
+    pub sync_paused: Option<bool>,
+    #[serde(default, deserialize_with = "present")]
+    #[schema(value_type = Option<u64>)]
+    pub sync_interval_secs: Option<Option<u64>>,
+    #[serde(default, deserialize_with = "present")]
+    #[schema(value_type = Option<u32>)]
+    pub backfill_days: Option<Option<u32>>,
+}
+
+/// Tell an explicit `null` (`Some(None)`) from an omitted field (`None`)
+fn present<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+impl ShopSettingsUpdate {
+    /// Reason the update can't be stored, if any
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(Some(secs)) = self.sync_interval_secs {
+            if !(MIN_SYNC_INTERVAL_SECS..=MAX_SYNC_INTERVAL_SECS).contains(&secs) {
+                return Err(format!(
+                    "sync_interval_secs must be between {} and {}",
+                    MIN_SYNC_INTERVAL_SECS, MAX_SYNC_INTERVAL_SECS
+                ));
+            }
+        }
+        if let Some(Some(days)) = self.backfill_days {
+            if !(1..=MAX_BACKFILL_DAYS).contains(&days) {
+                return Err(format!(
+                    "backfill_days must be between 1 and {}",
+                    MAX_BACKFILL_DAYS
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// `settings` with this update applied
+    pub fn apply(&self, mut settings: ShopSettings) -> ShopSettings {
+        if let Some(paused) = self.sync_paused {
+            settings.sync_paused = paused;
+        }
+        if let Some(secs) = self.sync_interval_secs {
+            settings.sync_interval_secs = secs;
+        }
+        if let Some(days) = self.backfill_days {
+            settings.backfill_days = days;
+        }
+        settings
+    }
+}
+
+/// Per-shop sync settings, in the `shop_settings` table
+#[derive(Clone)]
+pub struct ShopSettingsStore {
+    pool: SqlitePool,
+}
+
+impl ShopSettingsStore {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Initialize the shop_settings table
+    pub async fn init(&self) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS shop_settings (
+                shop_id TEXT PRIMARY KEY,
+                sync_paused INTEGER NOT NULL,
+                sync_interval_secs INTEGER,
+                backfill_days INTEGER,
+                updated_at INTEGER NOT NULL
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Settings of `shop_id`, or the defaults if none are stored
+    pub async fn get(&self, shop_id: &str) -> Result<ShopSettings, sqlx::Error> {
+        let row = sqlx::query(
+            "SELECT shop_id, sync_paused, sync_interval_secs, backfill_days, updated_at
+            FROM shop_settings WHERE shop_id = ?1",
+        )
+        .bind(shop_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        let Some(row) = row else {
+            return Ok(ShopSettings {
+                shop_id: shop_id.to_string(),
+                ..ShopSettings::default()
+            });
+        };
+        Ok(ShopSettings {
+            shop_id: row.try_get("shop_id")?,
+            sync_paused: row.try_get("sync_paused")?,
+            sync_interval_secs: row
+                .try_get::<Option<i64>, _>("sync_interval_secs")?
+                .map(|secs| secs as u64),
+            backfill_days: row
+                .try_get::<Option<i64>, _>("backfill_days")?
+                .map(|days| days as u32),
+            updated_at: row.try_get("updated_at")?,
+        })
+    }
+
+    /// Apply `update` to the settings of `shop_id` and return the result
+    pub async fn update(
+        &self,
+        shop_id: &str,
+        update: &ShopSettingsUpdate,
+    ) -> Result<ShopSettings, sqlx::Error> {
+        let mut settings = update.apply(self.get(shop_id).await?);
+        settings.updated_at = Some(chrono::Utc::now().timestamp());
+        sqlx::query(
+            "INSERT INTO shop_settings (shop_id, sync_paused, sync_interval_secs, backfill_days,
+                updated_at)
+            VALUES (?1, ?2, ?3, ?4, ?5)
+            ON CONFLICT(shop_id) DO UPDATE SET
+                sync_paused = excluded.sync_paused,
+                sync_interval_secs = excluded.sync_interval_secs,
+                backfill_days = excluded.backfill_days,
+                updated_at = excluded.updated_at",
+        )
+        .bind(shop_id)
+        .bind(settings.sync_paused)
+        .bind(settings.sync_interval_secs.map(|secs| secs as i64))
+        .bind(settings.backfill_days.map(i64::from))
+        .bind(settings.updated_at)
+        .execute(&self.pool)
+        .await?;
+        Ok(settings)
+    }
+}
@@ -0,0 +1,361 @@
+#[cfg(feature = "server")]
+use crate::analytics::AnalyticsClient;
+#[cfg(feature = "server")]
+use crate::config::Config;
+use crate::error::AppError;
+use crate::oauth::AuthorizedShop;
+#[cfg(feature = "server")]
+use crate::finance::FinanceClient;
+#[cfg(feature = "server")]
+use crate::fulfillment::FulfillmentClient;
+#[cfg(feature = "server")]
+use crate::logistics::LogisticsClient;
+#[cfg(feature = "server")]
+use crate::order::OrderClient;
+#[cfg(feature = "server")]
+use crate::product::ProductClient;
+#[cfg(feature = "server")]
+use crate::shop_settings::ShopSettings;
+use crate::requests::TikTokShopApiClient;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Deserialize)]
+struct GetAuthorizedShopsResponse {
+    #[serde(default)]
+    shops: Vec<AuthorizedShop>,
+}
+
+/// Client for the shop authorization endpoints
+pub struct ShopClient {
+    api_client: TikTokShopApiClient,
+}
+
+impl ShopClient {
+    pub fn new(app_key: String, app_secret: String) -> Self {
+        Self {
+            api_client: TikTokShopApiClient::new(app_key, app_secret),
+        }
+    }
+
+    pub fn with_api_client(api_client: TikTokShopApiClient) -> Self {
+        Self { api_client }
+    }
+
+    /// Shops the access token is authorized for, with the cipher each one's calls need
+    pub async fn get_authorized_shops(
+        &self,
+        access_token: &str,
+    ) -> Result<Vec<AuthorizedShop>, AppError> {
+        let response: GetAuthorizedShopsResponse = self
+            .api_client
+            .get(
+                "/authorization/202309/shops",
+                Some(access_token),
+                None,
+                BTreeMap::new(),
+            )
+            .await?;
+
+        Ok(response.shops)
+    }
+}
+
+/// Cipher and data-center region that calls for the configured shop should use
+#[cfg(feature = "server")]
+#[derive(Debug, Clone, Default)]
+pub struct ShopTarget {
+    pub cipher: Option<String>,
+    pub region: Option<String>,
+}
+
+#[cfg(feature = "server")]
+impl ShopTarget {
+    /// API client routed to the shop's regional host
+    pub fn api_client(&self, config: &Config) -> TikTokShopApiClient {
+        config.tiktok_api_client_for_region(self.region.as_deref())
+    }
+
+    /// Order client whose calls use the shop's cipher and regional host
+    pub fn order_client(&self, config: &Config) -> OrderClient {
+        OrderClient::with_api_client(self.api_client(config)).with_shop_cipher(self.cipher.clone())
+    }
+
+    /// Analytics client whose calls use the shop's cipher and regional host
+    pub fn analytics_client(&self, config: &Config) -> AnalyticsClient {
+        AnalyticsClient::with_api_client(self.api_client(config)).with_shop_cipher(self.cipher.clone())
+    }
+
+    /// Finance client whose calls use the shop's cipher and regional host
+    pub fn finance_client(&self, config: &Config) -> FinanceClient {
+        FinanceClient::with_api_client(self.api_client(config)).with_shop_cipher(self.cipher.clone())
+    }
+
+    /// Fulfillment client whose calls use the shop's cipher and regional host
+    pub fn fulfillment_client(&self, config: &Config) -> FulfillmentClient {
+        FulfillmentClient::with_api_client(self.api_client(config)).with_shop_cipher(self.cipher.clone())
+    }
+
+    /// Logistics client whose calls use the shop's cipher and regional host
+    pub fn logistics_client(&self, config: &Config) -> LogisticsClient {
+        LogisticsClient::with_api_client(self.api_client(config)).with_shop_cipher(self.cipher.clone())
+    }
+
+    /// Product client whose calls use the shop's cipher and regional host
+    pub fn product_client(&self, config: &Config) -> ProductClient {
+        ProductClient::with_api_client(self.api_client(config)).with_shop_cipher(self.cipher.clone())
+    }
+}
+
+/// A shop whose seller de-authorized the app; its syncs stay stopped until
+/// the app is authorized again
+#[cfg(feature = "server")]
+#[derive(Debug, Clone, serde::Serialize, utoipa::ToSchema)]
+pub struct ShopDisconnection {
+    pub shop_id: String,
+    pub reason: String,
+    pub disconnected_at: i64,
+}
+
+/// A registered shop and its connection, for `GET /shops`. Token, sync and
+/// order fields are only set for the shop the stored token acts for.
+#[cfg(feature = "server")]
+#[derive(Debug, Clone, serde::Serialize, utoipa::ToSchema)]
+pub struct ShopSummary {
+    pub shop_id: String,
+    pub shop_name: String,
+    pub region: String,
+    /// When TikTok last listed the shop as authorized
+    pub refreshed_at: i64,
+    pub connected: bool,
+    pub disconnection: Option<ShopDisconnection>,
+    pub token_expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub refresh_token_expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// Start of the last scheduled order sync
+    pub last_sync_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// `ok`, `skipped` or `failed`
+    pub last_sync_outcome: Option<String>,
+    pub order_count: Option<i64>,
+    pub settings: ShopSettings,
+}
+
+/// Authorized shops persisted after token exchange, used to resolve `shop_cipher`
+#[cfg(feature = "server")]
+#[derive(Clone)]
+pub struct ShopRegistry {
+    pool: sqlx::sqlite::SqlitePool,
+}
+
+#[cfg(feature = "server")]
+impl ShopRegistry {
+    pub fn new(pool: sqlx::sqlite::SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Initialize the shops table
+    pub async fn init(&self) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS shops (
+                shop_id TEXT PRIMARY KEY,
+                cipher TEXT NOT NULL,
+                shop_name TEXT NOT NULL,
+                region TEXT NOT NULL,
+                updated_at INTEGER NOT NULL
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS shop_disconnections (
+                shop_id TEXT PRIMARY KEY,
+                reason TEXT NOT NULL,
+                disconnected_at INTEGER NOT NULL
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Insert or update shops returned by `get_authorized_shops`
+    pub async fn upsert_shops(&self, shops: &[AuthorizedShop]) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let now = chrono::Utc::now().timestamp();
+
+        for shop in shops {
+            sqlx::query(
+                "INSERT INTO shops (shop_id, cipher, shop_name, region, updated_at)
+                VALUES (?1, ?2, ?3, ?4, ?5)
+                ON CONFLICT(shop_id) DO UPDATE SET
+                    cipher = excluded.cipher,
+                    shop_name = excluded.shop_name,
+                    region = excluded.region,
+                    updated_at = excluded.updated_at",
+            )
+            .bind(&shop.shop_id)
+            .bind(&shop.cipher)
+            .bind(&shop.shop_name)
+            .bind(&shop.region)
+            .bind(now)
+            .execute(&mut *tx)
+            .await?;
+            // Listed again, so the seller has authorized the app again
+            sqlx::query("DELETE FROM shop_disconnections WHERE shop_id = ?1")
+                .bind(&shop.shop_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        tx.commit().await
+    }
+
+    /// Mark a shop as disconnected. Returns `false` if it already was.
+    pub async fn mark_disconnected(
+        &self,
+        shop_id: &str,
+        reason: &str,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            "INSERT OR IGNORE INTO shop_disconnections (shop_id, reason, disconnected_at)
+            VALUES (?1, ?2, ?3)",
+        )
+        .bind(shop_id)
+        .bind(reason)
+        .bind(chrono::Utc::now().timestamp())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Lift a shop's disconnection once the app is authorized again
+    pub async fn mark_connected(&self, shop_id: &str) -> Result<(), sqlx::Error> {
+        sqlx::query("DELETE FROM shop_disconnections WHERE shop_id = ?1")
+            .bind(shop_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn disconnection(
+        &self,
+        shop_id: &str,
+    ) -> Result<Option<ShopDisconnection>, sqlx::Error> {
+        use sqlx::Row;
+
+        let row = sqlx::query(
+            "SELECT shop_id, reason, disconnected_at FROM shop_disconnections WHERE shop_id = ?1",
+        )
+        .bind(shop_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.map(|row| {
+            Ok(ShopDisconnection {
+                shop_id: row.try_get("shop_id")?,
+                reason: row.try_get("reason")?,
+                disconnected_at: row.try_get("disconnected_at")?,
+            })
+        })
+        .transpose()
+    }
+
+    pub async fn list(&self) -> Result<Vec<AuthorizedShop>, sqlx::Error> {
+        use sqlx::Row;
+
+        let rows = sqlx::query("SELECT shop_id, cipher, shop_name, region FROM shops ORDER BY shop_name")
+            .fetch_all(&self.pool)
+            .await?;
+
+        rows.iter()
+            .map(|row| {
+                Ok(AuthorizedShop {
+                    shop_id: row.try_get("shop_id")?,
+                    cipher: row.try_get("cipher")?,
+                    shop_name: row.try_get("shop_name")?,
+                    region: row.try_get("region")?,
+                })
+            })
+            .collect()
+    }
+
+    /// Registered shops with when each was last listed as authorized
+    pub async fn list_refreshed(&self) -> Result<Vec<(AuthorizedShop, i64)>, sqlx::Error> {
+        use sqlx::Row;
+
+        let rows = sqlx::query(
+            "SELECT shop_id, cipher, shop_name, region, updated_at FROM shops ORDER BY shop_name",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter()
+            .map(|row| {
+                let shop = AuthorizedShop {
+                    shop_id: row.try_get("shop_id")?,
+                    cipher: row.try_get("cipher")?,
+                    shop_name: row.try_get("shop_name")?,
+                    region: row.try_get("region")?,
+                };
+                Ok((shop, row.try_get("updated_at")?))
+            })
+            .collect()
+    }
+
+    /// The shop calls should target: `shop_id` if given, otherwise the only
+    /// registered shop. `None` when it can't be determined.
+    pub async fn resolve(&self, shop_id: Option<&str>) -> Result<Option<AuthorizedShop>, sqlx::Error> {
+        let shops = self.list().await?;
+
+        Ok(match shop_id {
+            Some(id) => shops.into_iter().find(|shop| shop.shop_id == id),
+            None if shops.len() == 1 => shops.into_iter().next(),
+            None => None,
+        })
+    }
+
+    /// Fetch the token's authorized shops and store them
+    pub async fn refresh(
+        &self,
+        client: &ShopClient,
+        access_token: &str,
+    ) -> Result<Vec<AuthorizedShop>, AppError> {
+        let shops = client.get_authorized_shops(access_token).await?;
+        self.upsert_shops(&shops).await?;
+        Ok(shops)
+    }
+
+    /// Shop the token acts for and the stored orders belong to:
+    /// `TIKTOK_SHOP_ID`, else the only registered shop, else `''`
+    pub async fn configured_shop_id(&self, config: &Config) -> Result<String, sqlx::Error> {
+        if let Some(shop_id) = &config.shop_id {
+            return Ok(shop_id.clone());
+        }
+        Ok(self
+            .resolve(None)
+            .await?
+            .map(|shop| shop.shop_id)
+            .unwrap_or_default())
+    }
+
+    /// Target for the configured shop. `TIKTOK_SHOP_CIPHER` overrides the
+    /// registered cipher; the region always comes from the registry.
+    pub async fn target(&self, config: &Config) -> Result<ShopTarget, sqlx::Error> {
+        let shop = self.resolve(config.shop_id.as_deref()).await?;
+
+        Ok(ShopTarget {
+            cipher: config
+                .shop_cipher
+                .clone()
+                .or_else(|| shop.as_ref().map(|s| s.cipher.clone())),
+            region: shop.map(|s| s.region),
+        })
+    }
+
+    /// Order client for the configured shop
+    pub async fn order_client(&self, config: &Config) -> Result<OrderClient, sqlx::Error> {
+        Ok(self.target(config).await?.order_client(config))
+    }
+}
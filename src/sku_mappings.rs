@@ -0,0 +1,248 @@
+use crate::order::{Order, OrderItem};
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::{SqlitePool, SqliteRow};
+use sqlx::Row;
+use std::collections::{HashMap, HashSet};
+use utoipa::ToSchema;
+
+/// How the fulfillment pipeline handles items of a seller SKU
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum FulfillmentAction {
+    /// Provisioned as an eSIM through WowEsim
+    EsimProvision,
+    /// Shipped as a parcel (pushed to the 3PL when one is configured);
+    /// the action of unmapped SKUs
+    Physical,
+    /// Not fulfilled by this service
+    Ignore,
+}
+
+impl FulfillmentAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FulfillmentAction::EsimProvision => "esim_provision",
+            FulfillmentAction::Physical => "physical",
+            FulfillmentAction::Ignore => "ignore",
+        }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "esim_provision" => Some(FulfillmentAction::EsimProvision),
+            "physical" => Some(FulfillmentAction::Physical),
+            "ignore" => Some(FulfillmentAction::Ignore),
+            _ => None,
+        }
+    }
+}
+
+/// Fulfillment action of a seller SKU
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct SkuMapping {
+    pub seller_sku: String,
+    pub action: FulfillmentAction,
+    /// WowEsim package provisioned for `esim_provision` SKUs
+    pub package_code: Option<String>,
+    /// Mark orders made only of this and other auto-complete SKUs shipped once
+    /// their eSIMs are provisioned
+    pub auto_complete: bool,
+    pub updated_at: i64,
+}
+
+/// One entry of `PUT /admin/sku-mappings`
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct SkuMappingUpdate {
+    pub seller_sku: String,
+    pub action: FulfillmentAction,
+    /// Required for `esim_provision`
+    pub package_code: Option<String>,
+    #[serde(default)]
+    pub auto_complete: bool,
+}
+
+/// Body of `PUT /admin/sku-mappings`: the complete new mapping
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct SkuMappingsUpdate {
+    pub mappings: Vec<SkuMappingUpdate>,
+}
+
+impl SkuMappingsUpdate {
+    /// Reason the mappings can't be stored, if any
+    pub fn validate(&self) -> Result<(), String> {
+        let mut seen = HashSet::new();
+        for mapping in &self.mappings {
+            let sku = mapping.seller_sku.trim();
+            if sku.is_empty() {
+                return Err("seller_sku must not be empty".to_string());
+            }
+            if !seen.insert(sku) {
+                return Err(format!("seller_sku {:?} is mapped twice", sku));
+            }
+            let has_package = mapping
+                .package_code
+                .as_deref()
+                .is_some_and(|code| !code.trim().is_empty());
+            match mapping.action {
+                FulfillmentAction::EsimProvision if !has_package => {
+                    return Err(format!("{:?}: esim_provision needs a package_code", sku));
+                }
+                FulfillmentAction::Physical | FulfillmentAction::Ignore
+                    if has_package || mapping.auto_complete =>
+                {
+                    return Err(format!(
+                        "{:?}: package_code and auto_complete only apply to esim_provision",
+                        sku
+                    ));
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    pub fn has_esims(&self) -> bool {
+        self.mappings
+            .iter()
+            .any(|m| m.action == FulfillmentAction::EsimProvision)
+    }
+
+    pub fn has_auto_complete(&self) -> bool {
+        self.mappings.iter().any(|m| m.auto_complete)
+    }
+}
+
+/// Snapshot of the mapping for resolving many items at once
+#[derive(Debug, Clone, Default)]
+pub struct SkuMappingTable {
+    by_sku: HashMap<String, SkuMapping>,
+}
+
+impl SkuMappingTable {
+    pub fn new(mappings: Vec<SkuMapping>) -> Self {
+        Self {
+            by_sku: mappings
+                .into_iter()
+                .map(|m| (m.seller_sku.clone(), m))
+                .collect(),
+        }
+    }
+
+    fn mapping_of(&self, item: &OrderItem) -> Option<&SkuMapping> {
+        self.by_sku.get(item.seller_sku.as_deref()?)
+    }
+
+    pub fn action_of(&self, item: &OrderItem) -> FulfillmentAction {
+        self.mapping_of(item)
+            .map_or(FulfillmentAction::Physical, |m| m.action)
+    }
+
+    /// WowEsim package to provision for `item`, if it is an eSIM
+    pub fn esim_package(&self, item: &OrderItem) -> Option<&str> {
+        self.mapping_of(item)
+            .filter(|m| m.action == FulfillmentAction::EsimProvision)
+            .and_then(|m| m.package_code.as_deref())
+    }
+
+    pub fn auto_completes(&self, item: &OrderItem) -> bool {
+        self.mapping_of(item).is_some_and(|m| m.auto_complete)
+    }
+
+    /// Whether any live item of `order` ships as a parcel
+    pub fn has_physical(&self, order: &Order) -> bool {
+        order
+            .item_list
+            .iter()
+            .filter(|item| item.display_status.as_deref() != Some("CANCELLED"))
+            .any(|item| self.action_of(item) == FulfillmentAction::Physical)
+    }
+}
+
+/// Seller SKU -> fulfillment action, in the `sku_mappings` table
+#[derive(Clone)]
+pub struct SkuMappingStore {
+    pool: SqlitePool,
+}
+
+impl SkuMappingStore {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Initialize the sku_mappings table
+    pub async fn init(&self) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS sku_mappings (
+                seller_sku TEXT PRIMARY KEY,
+                action TEXT NOT NULL,
+                package_code TEXT,
+                auto_complete INTEGER NOT NULL DEFAULT 0,
+                updated_at INTEGER NOT NULL
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn list(&self) -> Result<Vec<SkuMapping>, sqlx::Error> {
+        sqlx::query(
+            "SELECT seller_sku, action, package_code, auto_complete, updated_at
+            FROM sku_mappings ORDER BY seller_sku",
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(mapping_from_row)
+        .collect()
+    }
+
+    /// All mappings, for resolving the items of orders
+    pub async fn table(&self) -> Result<SkuMappingTable, sqlx::Error> {
+        Ok(SkuMappingTable::new(self.list().await?))
+    }
+
+    /// Replace the whole mapping with `mappings`
+    pub async fn replace(
+        &self,
+        mappings: &[SkuMappingUpdate],
+    ) -> Result<Vec<SkuMapping>, sqlx::Error> {
+        let now = chrono::Utc::now().timestamp();
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query("DELETE FROM sku_mappings")
+            .execute(&mut *tx)
+            .await?;
+        for mapping in mappings {
+            sqlx::query(
+                "INSERT INTO sku_mappings (seller_sku, action, package_code, auto_complete, updated_at)
+                VALUES (?1, ?2, ?3, ?4, ?5)",
+            )
+            .bind(mapping.seller_sku.trim())
+            .bind(mapping.action.as_str())
+            .bind(mapping.package_code.as_deref().map(str::trim))
+            .bind(mapping.auto_complete)
+            .bind(now)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        self.list().await
+    }
+}
+
+fn mapping_from_row(row: &SqliteRow) -> Result<SkuMapping, sqlx::Error> {
+    let action: String = row.try_get("action")?;
+    Ok(SkuMapping {
+        seller_sku: row.try_get("seller_sku")?,
+        action: FulfillmentAction::parse(&action).ok_or_else(|| sqlx::Error::ColumnDecode {
+            index: "action".to_string(),
+            source: format!("unknown fulfillment action {:?}", action).into(),
+        })?,
+        package_code: row.try_get("package_code")?,
+        auto_complete: row.try_get("auto_complete")?,
+        updated_at: row.try_get("updated_at")?,
+    })
+}
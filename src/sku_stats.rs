@@ -0,0 +1,449 @@
+use crate::config::Config;
+use crate::database::Database;
+use crate::jobs::{Job, JobHandler, JobQueue};
+use crate::order::Order;
+use crate::reporting;
+use crate::shops::ShopRegistry;
+use async_trait::async_trait;
+use chrono::{Duration, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::{Sqlite, SqlitePool};
+use sqlx::{Row, Transaction};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::Arc;
+use tracing::{info, warn};
+use utoipa::ToSchema;
+
+/// Rebuild `sku_stats` from every stored order
+pub const REBUILD_JOB_KIND: &str = "sku_stats_rebuild";
+/// Compare recent sales with Product API inventory and warn about low stock
+pub const LOW_STOCK_JOB_KIND: &str = "low_stock_check";
+
+/// Sales velocity used by the low-stock check is averaged over this many days
+pub const LOW_STOCK_WINDOW_DAYS: i64 = 7;
+
+/// Sales of one SKU over a window, with the last known inventory
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SkuSales {
+    pub seller_sku: String,
+    pub sku_id: Option<String>,
+    pub units: i64,
+    pub orders: i64,
+    pub units_per_day: f64,
+    /// Available quantity from the last inventory check
+    pub available: Option<i64>,
+    pub inventory_checked_at: Option<i64>,
+    /// How long `available` lasts at the current `units_per_day`
+    pub days_of_cover: Option<f64>,
+    /// `days_of_cover` is below `LOW_STOCK_COVER_DAYS`
+    pub low_stock: bool,
+}
+
+/// Units sold per seller SKU per business day (see `REPORT_TIMEZONE`), kept
+/// up to date on upsert.
+///
+/// `sku_order_lines` holds each order's contribution so that an updated or
+/// cancelled order replaces, rather than adds to, what it counted before;
+/// `sku_stats` is the per-day rollup of those lines. Items without a
+/// `seller_sku` are counted under their TikTok `sku_id`.
+#[derive(Clone)]
+pub struct SkuStatsStore {
+    pool: SqlitePool,
+    timezone: Tz,
+}
+
+impl SkuStatsStore {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self {
+            pool,
+            timezone: chrono_tz::UTC,
+        }
+    }
+
+    /// Business day that windows and daily alerts count by; UTC by default
+    pub fn with_timezone(mut self, timezone: Tz) -> Self {
+        self.timezone = timezone;
+        self
+    }
+
+    /// Initialize the sku_order_lines, sku_stats and sku_inventory tables
+    pub async fn init(&self) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS sku_order_lines (
+                order_id TEXT NOT NULL,
+                seller_sku TEXT NOT NULL,
+                sku_id TEXT,
+                day TEXT NOT NULL,
+                units INTEGER NOT NULL,
+                PRIMARY KEY (order_id, seller_sku)
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            "CREATE INDEX IF NOT EXISTS idx_sku_order_lines_sku_day
+            ON sku_order_lines (seller_sku, day)",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS sku_stats (
+                seller_sku TEXT NOT NULL,
+                day TEXT NOT NULL,
+                sku_id TEXT,
+                units INTEGER NOT NULL,
+                orders INTEGER NOT NULL,
+                PRIMARY KEY (seller_sku, day)
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS sku_inventory (
+                seller_sku TEXT PRIMARY KEY,
+                sku_id TEXT NOT NULL,
+                available INTEGER NOT NULL,
+                checked_at INTEGER NOT NULL,
+                alerted_day TEXT
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Replace `order`'s contribution to the stats within an upsert transaction.
+    /// Cancelled and replacement orders, and those not `counted` (see
+    /// [`OrderTagger`]), contribute nothing: a replacement re-ships a sale
+    /// already counted under the original order.
+    ///
+    /// [`OrderTagger`]: crate::tags::OrderTagger
+    pub async fn record(
+        tx: &mut Transaction<'_, Sqlite>,
+        order: &Order,
+        counted: bool,
+        timezone: Tz,
+    ) -> Result<(), sqlx::Error> {
+        let mut affected: BTreeSet<(String, String)> = BTreeSet::new();
+
+        let previous = sqlx::query("SELECT seller_sku, day FROM sku_order_lines WHERE order_id = ?1")
+            .bind(&order.id)
+            .fetch_all(&mut **tx)
+            .await?;
+        for row in previous {
+            affected.insert((row.try_get("seller_sku")?, row.try_get("day")?));
+        }
+
+        sqlx::query("DELETE FROM sku_order_lines WHERE order_id = ?1")
+            .bind(&order.id)
+            .execute(&mut **tx)
+            .await?;
+
+        let day = reporting::day_of(order.create_time, timezone);
+        let lines = if counted { order_lines(order) } else { BTreeMap::new() };
+        for (seller_sku, (sku_id, units)) in lines {
+            sqlx::query(
+                "INSERT INTO sku_order_lines (order_id, seller_sku, sku_id, day, units)
+                VALUES (?1, ?2, ?3, ?4, ?5)",
+            )
+            .bind(&order.id)
+            .bind(&seller_sku)
+            .bind(&sku_id)
+            .bind(&day)
+            .bind(units)
+            .execute(&mut **tx)
+            .await?;
+            affected.insert((seller_sku, day.clone()));
+        }
+
+        for (seller_sku, day) in affected {
+            sqlx::query("DELETE FROM sku_stats WHERE seller_sku = ?1 AND day = ?2")
+                .bind(&seller_sku)
+                .bind(&day)
+                .execute(&mut **tx)
+                .await?;
+
+            sqlx::query(
+                "INSERT INTO sku_stats (seller_sku, day, sku_id, units, orders)
+                SELECT seller_sku, day, MAX(sku_id), SUM(units), COUNT(*)
+                FROM sku_order_lines WHERE seller_sku = ?1 AND day = ?2
+                GROUP BY seller_sku, day",
+            )
+            .bind(&seller_sku)
+            .bind(&day)
+            .execute(&mut **tx)
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Sales per SKU over the last `window_days` days (including today), best sellers first
+    pub async fn summary(
+        &self,
+        window_days: i64,
+        low_stock_cover_days: Option<f64>,
+    ) -> Result<Vec<SkuSales>, sqlx::Error> {
+        let since = (reporting::today(self.timezone) - Duration::days(window_days - 1))
+            .format("%Y-%m-%d")
+            .to_string();
+
+        let rows = sqlx::query(
+            "SELECT s.seller_sku, MAX(s.sku_id) AS sku_id, SUM(s.units) AS units,
+                SUM(s.orders) AS orders, i.available, i.checked_at
+            FROM sku_stats s
+            LEFT JOIN sku_inventory i ON i.seller_sku = s.seller_sku
+            WHERE s.day >= ?1
+            GROUP BY s.seller_sku
+            ORDER BY units DESC, s.seller_sku",
+        )
+        .bind(since)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter()
+            .map(|row| {
+                let units: i64 = row.try_get("units")?;
+                let available: Option<i64> = row.try_get("available")?;
+                let units_per_day = units as f64 / window_days as f64;
+                let days_of_cover = available
+                    .filter(|_| units_per_day > 0.0)
+                    .map(|a| a.max(0) as f64 / units_per_day);
+
+                Ok(SkuSales {
+                    seller_sku: row.try_get("seller_sku")?,
+                    sku_id: row.try_get("sku_id")?,
+                    units,
+                    orders: row.try_get("orders")?,
+                    units_per_day,
+                    available,
+                    inventory_checked_at: row.try_get("checked_at")?,
+                    days_of_cover,
+                    low_stock: matches!(
+                        (days_of_cover, low_stock_cover_days),
+                        (Some(cover), Some(threshold)) if cover < threshold
+                    ),
+                })
+            })
+            .collect()
+    }
+
+    /// Store the available quantity of a SKU from an inventory check
+    pub async fn record_inventory(
+        &self,
+        seller_sku: &str,
+        sku_id: &str,
+        available: i64,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "INSERT INTO sku_inventory (seller_sku, sku_id, available, checked_at)
+            VALUES (?1, ?2, ?3, ?4)
+            ON CONFLICT(seller_sku) DO UPDATE SET
+                sku_id = excluded.sku_id,
+                available = excluded.available,
+                checked_at = excluded.checked_at",
+        )
+        .bind(seller_sku)
+        .bind(sku_id)
+        .bind(available)
+        .bind(Utc::now().timestamp())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Mark a SKU as alerted today. Returns `false` if it already was.
+    pub async fn mark_alerted(&self, seller_sku: &str) -> Result<bool, sqlx::Error> {
+        let today = reporting::today(self.timezone).format("%Y-%m-%d").to_string();
+        let result = sqlx::query(
+            "UPDATE sku_inventory SET alerted_day = ?2
+            WHERE seller_sku = ?1 AND (alerted_day IS NULL OR alerted_day <> ?2)",
+        )
+        .bind(seller_sku)
+        .bind(today)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+/// Units per seller SKU in `order` (with the TikTok sku_id), empty if it was cancelled
+fn order_lines(order: &Order) -> BTreeMap<String, (String, i64)> {
+    let mut lines: BTreeMap<String, (String, i64)> = BTreeMap::new();
+    if order.status.eq_ignore_ascii_case("CANCELLED") || order.is_replacement_order == Some(true) {
+        return lines;
+    }
+
+    for item in &order.item_list {
+        let seller_sku = item
+            .seller_sku
+            .clone()
+            .filter(|s| !s.is_empty())
+            .unwrap_or_else(|| item.sku_id.clone());
+        let units = i64::from(item.quantity.unwrap_or(1).max(1));
+        lines
+            .entry(seller_sku)
+            .or_insert_with(|| (item.sku_id.clone(), 0))
+            .1 += units;
+    }
+
+    lines
+}
+
+/// Parse a stats window such as `7d`, `2w` or `30` (days) into days
+pub fn parse_window(window: &str) -> Result<i64, String> {
+    let window = window.trim();
+    let (number, unit_days) = match window.chars().last() {
+        Some('d') => (&window[..window.len() - 1], 1),
+        Some('w') => (&window[..window.len() - 1], 7),
+        _ => (window, 1),
+    };
+
+    let days = number
+        .parse::<i64>()
+        .ok()
+        .and_then(|n| n.checked_mul(unit_days))
+        .filter(|d| (1..=366).contains(d))
+        .ok_or_else(|| format!("invalid window {:?}, expected e.g. 7d or 4w (max 366 days)", window))?;
+
+    Ok(days)
+}
+
+/// Queue a low-stock check, at most once per hour
+pub async fn enqueue_low_stock_check(job_queue: &JobQueue, config: &Config) -> Result<bool, sqlx::Error> {
+    if config.low_stock_cover_days.is_none() {
+        return Ok(false);
+    }
+
+    let dedup_key = format!("{}:{}", LOW_STOCK_JOB_KIND, Utc::now().format("%Y-%m-%dT%H"));
+    job_queue
+        .enqueue(
+            LOW_STOCK_JOB_KIND,
+            Some(&dedup_key),
+            &serde_json::json!({}),
+            config.job_max_attempts,
+        )
+        .await
+}
+
+/// Rebuilds the stats for orders stored before they were tracked
+pub struct SkuStatsRebuildHandler {
+    db: Arc<Database>,
+}
+
+impl SkuStatsRebuildHandler {
+    pub fn new(db: Arc<Database>) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl JobHandler for SkuStatsRebuildHandler {
+    async fn handle(&self, _job: &Job) -> Result<(), String> {
+        let orders = self
+            .db
+            .rebuild_sku_stats()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        info!("Rebuilt SKU stats from {} orders", orders);
+        Ok(())
+    }
+}
+
+/// Fetches inventory for recently sold SKUs and warns (once a day per SKU)
+/// when it covers fewer than `LOW_STOCK_COVER_DAYS` days of sales
+pub struct LowStockCheckHandler {
+    store: SkuStatsStore,
+    shops: ShopRegistry,
+    config: Config,
+}
+
+impl LowStockCheckHandler {
+    pub fn new(store: SkuStatsStore, shops: ShopRegistry, config: Config) -> Self {
+        Self {
+            store,
+            shops,
+            config,
+        }
+    }
+}
+
+#[async_trait]
+impl JobHandler for LowStockCheckHandler {
+    async fn handle(&self, _job: &Job) -> Result<(), String> {
+        let Some(threshold) = self.config.low_stock_cover_days else {
+            return Ok(());
+        };
+
+        let sold = self
+            .store
+            .summary(LOW_STOCK_WINDOW_DAYS, None)
+            .await
+            .map_err(|e| e.to_string())?;
+        let seller_skus: HashMap<String, String> = sold
+            .iter()
+            .filter_map(|s| Some((s.sku_id.clone()?, s.seller_sku.clone())))
+            .collect();
+        if seller_skus.is_empty() {
+            return Ok(());
+        }
+
+        let token = self
+            .config
+            .token_manager()
+            .valid_token()
+            .await
+            .map_err(|e| e.to_string())?;
+        let product_client = self
+            .shops
+            .target(&self.config)
+            .await
+            .map_err(|e| e.to_string())?
+            .product_client(&self.config);
+
+        let sku_ids: Vec<String> = seller_skus.keys().cloned().collect();
+        let inventory = product_client
+            .search_inventory(&token.access_token, None, &sku_ids)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        for sku in inventory {
+            let (Some(seller_sku), Some(available)) =
+                (seller_skus.get(&sku.id), sku.total_available_quantity)
+            else {
+                continue;
+            };
+            self.store
+                .record_inventory(seller_sku, &sku.id, available)
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+
+        let summary = self
+            .store
+            .summary(LOW_STOCK_WINDOW_DAYS, Some(threshold))
+            .await
+            .map_err(|e| e.to_string())?;
+        for sku in summary.iter().filter(|s| s.low_stock) {
+            if self.store.mark_alerted(&sku.seller_sku).await.map_err(|e| e.to_string())? {
+                warn!(
+                    "Low stock: {} has {} available, {:.1} days at {:.1} units/day",
+                    sku.seller_sku,
+                    sku.available.unwrap_or_default(),
+                    sku.days_of_cover.unwrap_or_default(),
+                    sku.units_per_day
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,341 @@
+use crate::database::Database;
+use crate::events::{EventBus, OrderEvent, OrderEventKind};
+use crate::order::Order;
+use serde::Serialize;
+use sqlx::sqlite::SqlitePool;
+use std::sync::Arc;
+use tracing::warn;
+use utoipa::ToSchema;
+
+/// Statuses in which an order still has to be handed to the carrier
+pub const PENDING_STATUSES: &[&str] = &["AWAITING_SHIPMENT", "AWAITING_COLLECTION"];
+
+/// Deadlines further in the past than this are no longer alerted on
+const ALERT_RETENTION_SECS: i64 = 7 * 86400;
+
+/// Which fulfillment deadline
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SlaKind {
+    /// `rts_sla_time`: mark the order ready to ship
+    Rts,
+    /// `tts_sla_time`: get the package in transit
+    Tts,
+    /// `shipping_due_time`: hand the package to the carrier
+    Shipping,
+}
+
+impl SlaKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SlaKind::Rts => "rts",
+            SlaKind::Tts => "tts",
+            SlaKind::Shipping => "shipping",
+        }
+    }
+}
+
+/// An open deadline of an order
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct SlaDeadline {
+    pub order_id: String,
+    pub status: String,
+    pub kind: SlaKind,
+    pub due_at: i64,
+    /// Seconds until the deadline; negative once breached
+    pub seconds_to_breach: i64,
+    pub breached: bool,
+}
+
+/// Deadlines `order` still has to meet. Ready-to-ship stops counting once the
+/// order has an `rts_time`; the others once it leaves the pending statuses.
+pub fn deadlines(order: &Order, now: i64) -> Vec<SlaDeadline> {
+    if !PENDING_STATUSES.contains(&order.status.as_str()) {
+        return Vec::new();
+    }
+
+    let rts = order
+        .rts_sla_time
+        .filter(|_| order.rts_time.is_none() && order.status == "AWAITING_SHIPMENT");
+    [
+        (SlaKind::Rts, rts),
+        (SlaKind::Tts, order.tts_sla_time),
+        (SlaKind::Shipping, order.shipping_due_time),
+    ]
+    .into_iter()
+    .filter_map(|(kind, due_at)| {
+        let due_at = due_at.filter(|t| *t > 0)?;
+        Some(SlaDeadline {
+            order_id: order.id.clone(),
+            status: order.status.clone(),
+            kind,
+            due_at,
+            seconds_to_breach: due_at - now,
+            breached: due_at <= now,
+        })
+    })
+    .collect()
+}
+
+/// Open deadlines of stored orders that fall due within `within` seconds
+/// (breached ones included), soonest first
+pub async fn due_within(
+    db: &Database,
+    now: i64,
+    within: i64,
+) -> Result<Vec<SlaDeadline>, sqlx::Error> {
+    let mut due: Vec<SlaDeadline> = due_orders(db, now, within)
+        .await?
+        .into_iter()
+        .map(|(_, deadline)| deadline)
+        .collect();
+
+    due.sort_by_key(|d| (d.due_at, d.order_id.clone()));
+    Ok(due)
+}
+
+async fn due_orders(
+    db: &Database,
+    now: i64,
+    within: i64,
+) -> Result<Vec<(Order, SlaDeadline)>, sqlx::Error> {
+    let mut due = Vec::new();
+    for status in PENDING_STATUSES {
+        for order in db.get_orders_by_status(status).await? {
+            for deadline in deadlines(&order, now) {
+                if deadline.seconds_to_breach <= within {
+                    due.push((order.clone(), deadline));
+                }
+            }
+        }
+    }
+    Ok(due)
+}
+
+/// Whether `order` is past its `shipping_due_time` and still waiting to be
+/// handed to the carrier
+pub fn is_late(order: &Order, now: i64) -> bool {
+    PENDING_STATUSES.contains(&order.status.as_str())
+        && order.shipping_due_time.is_some_and(|due| due > 0 && due < now)
+}
+
+/// An order past its shipping deadline
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct LateOrder {
+    pub order_id: String,
+    pub status: String,
+    pub shipping_due_time: i64,
+    pub seconds_late: i64,
+    pub shipping_provider: Option<String>,
+}
+
+/// Stored orders that are late (see [`is_late`]) by at least `min_late`
+/// seconds, latest first
+pub async fn late_orders(
+    db: &Database,
+    now: i64,
+    min_late: i64,
+) -> Result<Vec<LateOrder>, sqlx::Error> {
+    let mut late = Vec::new();
+    for status in PENDING_STATUSES {
+        for order in db.get_orders_by_status(status).await? {
+            if !is_late(&order, now) {
+                continue;
+            }
+            let due = order.shipping_due_time.unwrap_or_default();
+            if now - due < min_late {
+                continue;
+            }
+            late.push(LateOrder {
+                order_id: order.id,
+                status: order.status,
+                shipping_due_time: due,
+                seconds_late: now - due,
+                shipping_provider: order.shipping_provider,
+            });
+        }
+    }
+
+    late.sort_by_key(|o| (o.shipping_due_time, o.order_id.clone()));
+    Ok(late)
+}
+
+/// Daily summary of a shop's late orders
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct LateDigest {
+    pub shop_id: String,
+    pub late_orders: usize,
+    /// How late the latest order is
+    pub max_seconds_late: i64,
+    pub order_ids: Vec<String>,
+}
+
+impl LateDigest {
+    /// Digest of `late` for `shop_id`, or `None` when nothing is late
+    pub fn of(shop_id: &str, late: &[LateOrder]) -> Option<Self> {
+        if late.is_empty() {
+            return None;
+        }
+        Some(Self {
+            shop_id: shop_id.to_string(),
+            late_orders: late.len(),
+            max_seconds_late: late.iter().map(|o| o.seconds_late).max().unwrap_or_default(),
+            order_ids: late.iter().map(|o| o.order_id.clone()).collect(),
+        })
+    }
+
+    /// Log the digest and publish it as a `late_digest` event
+    pub fn send(&self, events: &EventBus) {
+        warn!(
+            "Shop {:?} has {} late orders, the latest {}h past its shipping deadline",
+            self.shop_id,
+            self.late_orders,
+            self.max_seconds_late / 3600
+        );
+        let mut event = OrderEvent::shop(OrderEventKind::LateDigest, &self.shop_id, "late");
+        event.order_ids = self.order_ids.clone();
+        events.publish(event);
+    }
+}
+
+/// Scans stored orders and alerts on deadlines about to be (or already) missed
+pub struct SlaMonitor {
+    db: Arc<Database>,
+    store: SlaStore,
+    events: EventBus,
+    /// Seconds before a deadline at which an order is reported at risk
+    alert_within: i64,
+}
+
+impl SlaMonitor {
+    pub fn new(db: Arc<Database>, store: SlaStore, events: EventBus, alert_within: i64) -> Self {
+        Self {
+            db,
+            store,
+            events,
+            alert_within,
+        }
+    }
+
+    /// Alert on every deadline not alerted on before. Alerts are logged and
+    /// published as `sla_at_risk`/`sla_breached` order events. Returns how
+    /// many were sent.
+    pub async fn check(&self) -> Result<usize, sqlx::Error> {
+        let now = chrono::Utc::now().timestamp();
+        let mut sent = 0;
+
+        for (order, deadline) in due_orders(&self.db, now, self.alert_within).await? {
+            if deadline.seconds_to_breach < -ALERT_RETENTION_SECS
+                || !self.store.mark_alerted(&deadline).await?
+            {
+                continue;
+            }
+
+            let kind = if deadline.breached {
+                warn!(
+                    "Order {} missed its {} SLA {}s ago",
+                    deadline.order_id,
+                    deadline.kind.as_str(),
+                    -deadline.seconds_to_breach
+                );
+                OrderEventKind::SlaBreached
+            } else {
+                warn!(
+                    "Order {} breaches its {} SLA in {}m",
+                    deadline.order_id,
+                    deadline.kind.as_str(),
+                    deadline.seconds_to_breach / 60
+                );
+                OrderEventKind::SlaAtRisk
+            };
+            self.events.publish(OrderEvent::new(kind, &order));
+            sent += 1;
+        }
+
+        self.store.prune(ALERT_RETENTION_SECS).await?;
+        Ok(sent)
+    }
+}
+
+/// Parse a duration such as `6h`, `90m`, `2d` or `3600` (seconds) into seconds
+pub fn parse_duration(value: &str) -> Result<i64, String> {
+    let value = value.trim();
+    let (number, unit_secs) = match value.chars().last() {
+        Some('s') => (&value[..value.len() - 1], 1),
+        Some('m') => (&value[..value.len() - 1], 60),
+        Some('h') => (&value[..value.len() - 1], 3600),
+        Some('d') => (&value[..value.len() - 1], 86400),
+        _ => (value, 1),
+    };
+
+    number
+        .parse::<i64>()
+        .ok()
+        .and_then(|n| n.checked_mul(unit_secs))
+        .filter(|secs| (0..=30 * 86400).contains(secs))
+        .ok_or_else(|| {
+            format!(
+                "invalid duration {:?}, expected e.g. 90m or 6h (max 30d)",
+                value
+            )
+        })
+}
+
+/// Alerts already sent, in the `sla_alerts` table, so each deadline is
+/// reported once as at risk and once as breached
+#[derive(Clone)]
+pub struct SlaStore {
+    pool: SqlitePool,
+}
+
+impl SlaStore {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Initialize the sla_alerts table
+    pub async fn init(&self) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS sla_alerts (
+                order_id TEXT NOT NULL,
+                kind TEXT NOT NULL,
+                due_at INTEGER NOT NULL,
+                breached INTEGER NOT NULL,
+                alerted_at INTEGER NOT NULL,
+                PRIMARY KEY (order_id, kind, due_at, breached)
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Record an alert for `deadline`. Returns `false` if it was already sent.
+    pub async fn mark_alerted(&self, deadline: &SlaDeadline) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            "INSERT OR IGNORE INTO sla_alerts (order_id, kind, due_at, breached, alerted_at)
+            VALUES (?1, ?2, ?3, ?4, ?5)",
+        )
+        .bind(&deadline.order_id)
+        .bind(deadline.kind.as_str())
+        .bind(deadline.due_at)
+        .bind(deadline.breached)
+        .bind(chrono::Utc::now().timestamp())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Forget alerts for deadlines that passed more than `older_than` seconds ago
+    pub async fn prune(&self, older_than: i64) -> Result<u64, sqlx::Error> {
+        let cutoff = chrono::Utc::now().timestamp() - older_than;
+        let result = sqlx::query("DELETE FROM sla_alerts WHERE due_at < ?1")
+            .bind(cutoff)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+}
@@ -0,0 +1,129 @@
+use serde::Serialize;
+use std::sync::{Arc, RwLock};
+use std::time::Instant;
+use tracing::info;
+use utoipa::ToSchema;
+
+/// Phases the server goes through before it takes traffic, in order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum StartupPhase {
+    /// Loading and validating configuration and secrets
+    Config,
+    /// Opening the database and creating or migrating tables
+    Database,
+    /// Loading, and if needed refreshing, the OAuth token
+    Token,
+    /// Starting background jobs, scheduled tasks and the HTTP listener
+    Services,
+    /// Syncing orders once, with `STARTUP_SYNC`, while the server already
+    /// answers health checks
+    InitialSync,
+    Ready,
+}
+
+impl StartupPhase {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StartupPhase::Config => "config",
+            StartupPhase::Database => "database",
+            StartupPhase::Token => "token",
+            StartupPhase::Services => "services",
+            StartupPhase::InitialSync => "initial_sync",
+            StartupPhase::Ready => "ready",
+        }
+    }
+
+    fn activity(&self) -> &'static str {
+        match self {
+            StartupPhase::Config => "validating configuration",
+            StartupPhase::Database => "running database migrations",
+            StartupPhase::Token => "loading the OAuth token",
+            StartupPhase::Services => "starting background work and the HTTP listener",
+            StartupPhase::InitialSync => "running the initial order sync",
+            StartupPhase::Ready => "ready for traffic",
+        }
+    }
+}
+
+/// A finished startup phase
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct StartupStep {
+    pub phase: StartupPhase,
+    pub duration_ms: u64,
+    /// What the phase found, e.g. when the token expires
+    pub detail: String,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct StartupStatus {
+    /// Phase in progress, or `ready`
+    pub phase: StartupPhase,
+    pub ready: bool,
+    pub steps: Vec<StartupStep>,
+}
+
+struct State {
+    phase: StartupPhase,
+    phase_started: Instant,
+    steps: Vec<StartupStep>,
+}
+
+/// Progress through the startup phases; readiness checks fail until the
+/// last one is done
+#[derive(Clone)]
+pub struct Startup {
+    state: Arc<RwLock<State>>,
+}
+
+impl Startup {
+    /// Start with the config phase
+    pub fn new() -> Self {
+        Self {
+            state: Arc::new(RwLock::new(State {
+                phase: StartupPhase::Config,
+                phase_started: Instant::now(),
+                steps: Vec::new(),
+            })),
+        }
+    }
+
+    /// Finish the current phase, noting `detail`, and move on to `next`
+    pub fn advance(&self, next: StartupPhase, detail: impl Into<String>) {
+        let mut state = self.state.write().unwrap();
+        let step = StartupStep {
+            phase: state.phase,
+            duration_ms: state.phase_started.elapsed().as_millis() as u64,
+            detail: detail.into(),
+        };
+        info!(
+            "Startup: {} done in {} ms ({})",
+            step.phase.as_str(),
+            step.duration_ms,
+            step.detail
+        );
+        info!("Startup: {}", next.activity());
+        state.steps.push(step);
+        state.phase = next;
+        state.phase_started = Instant::now();
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.state.read().unwrap().phase == StartupPhase::Ready
+    }
+
+    pub fn status(&self) -> StartupStatus {
+        let state = self.state.read().unwrap();
+        StartupStatus {
+            phase: state.phase,
+            ready: state.phase == StartupPhase::Ready,
+            steps: state.steps.clone(),
+        }
+    }
+}
+
+impl Default for Startup {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,9 +1,14 @@
 use crate::error::AppError;
+use crate::oauth::{TikTokShopOAuth, TokenResponse};
+use async_trait::async_trait;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::fs;
+use std::io::Write;
 use std::path::{Path, PathBuf};
-use tracing::info;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+use tracing::{debug, error, info, warn};
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct TokenInfo {
@@ -25,17 +30,62 @@ impl TokenInfo {
             refresh_token_expires_at,
         }
     }
+
+    /// Token from a token exchange or refresh response, whose expiries are
+    /// Unix timestamps
+    pub fn from_response(token_response: TokenResponse) -> Self {
+        Self {
+            access_token: token_response.access_token,
+            refresh_token: token_response.refresh_token,
+            expires_at: DateTime::from_timestamp(token_response.access_token_expire_in, 0)
+                .unwrap_or_else(|| Utc::now() + chrono::Duration::hours(12)),
+            refresh_token_expires_at: DateTime::from_timestamp(
+                token_response.refresh_token_expire_in,
+                0,
+            )
+            .unwrap_or_else(|| Utc::now() + chrono::Duration::days(30)),
+        }
+    }
+
+    /// Whether the access token expires within `within`
+    pub fn expires_within(&self, within: chrono::Duration) -> bool {
+        self.expires_at - within < Utc::now()
+    }
+}
+
+/// Somewhere other than a file that tokens are kept, e.g. a secret manager
+#[async_trait]
+pub trait TokenBackend: Send + Sync {
+    /// Where the token is kept, for logs
+    fn describe(&self) -> String;
+    fn load(&self) -> Result<Option<TokenInfo>, AppError>;
+    /// Persist `token`, or remove the stored one when `None`
+    async fn save(&self, token: Option<&TokenInfo>) -> Result<(), AppError>;
 }
 
+/// Backend of every [`TokenStorage::new`], once set with [`TokenStorage::use_backend`]
+static DEFAULT_BACKEND: OnceLock<Arc<dyn TokenBackend>> = OnceLock::new();
+
+/// Token file of [`TokenStorage::new`], once set with [`TokenStorage::use_file`]
+static DEFAULT_FILE: OnceLock<PathBuf> = OnceLock::new();
+
 pub struct TokenStorage {
     token: Option<TokenInfo>,
     storage_path: PathBuf,
+    backend: Option<Arc<dyn TokenBackend>>,
 }
 
 impl TokenStorage {
-    const DEFAULT_STORAGE_FILE: &'static str = "tiktok_tokens.json";
+    /// Token file unless [`TokenStorage::use_file`] names another
+    pub const DEFAULT_STORAGE_FILE: &'static str = "tiktok_tokens.json";
     pub fn new() -> Self {
-        Self::with_path(Self::DEFAULT_STORAGE_FILE)
+        match DEFAULT_BACKEND.get() {
+            Some(backend) => Self::with_backend(backend.clone()),
+            None => match DEFAULT_FILE.get() {
+                Some(path) => Self::with_path(path),
+                None => Self::with_path(Self::DEFAULT_STORAGE_FILE),
+            },
+        }
     }
     pub fn with_path<P: AsRef<Path>>(path: P) -> Self {
         let storage_path = PathBuf::from(path.as_ref());
@@ -44,10 +94,57 @@ impl TokenStorage {
         Self {
             token,
             storage_path,
+            backend: None,
+        }
+    }
+
+    /// Storage kept in `backend` instead of a file
+    pub fn with_backend(backend: Arc<dyn TokenBackend>) -> Self {
+        let token = backend.load().ok().flatten();
+        Self {
+            token,
+            storage_path: PathBuf::from(backend.describe()),
+            backend: Some(backend),
         }
     }
 
+    /// Keep the token of every `TokenStorage::new()` from now on in `path`
+    /// instead of `DEFAULT_STORAGE_FILE`. Returns `false` if a file was
+    /// already set.
+    pub fn use_file(path: impl Into<PathBuf>) -> bool {
+        DEFAULT_FILE.set(path.into()).is_ok()
+    }
+
+    /// Keep the tokens of every `TokenStorage::new()` from now on in
+    /// `backend`. Returns `false` if a backend was already set.
+    pub fn use_backend(backend: Arc<dyn TokenBackend>) -> bool {
+        DEFAULT_BACKEND.set(backend).is_ok()
+    }
+
     fn load_from_file(path: &Path) -> Result<TokenInfo, AppError> {
+        match Self::read_token_file(path) {
+            Ok(token_info) => {
+                info!("Loaded token from file: {}", path.display());
+                Ok(token_info)
+            }
+            // A token file that can't be read falls back to the previous generation
+            Err(e) => {
+                let backup = Self::backup_path(path);
+                if !path.exists() || !backup.exists() {
+                    return Err(e);
+                }
+                let token_info = Self::read_token_file(&backup).map_err(|_| e)?;
+                warn!(
+                    "Token file {} is unreadable, using its backup {}",
+                    path.display(),
+                    backup.display()
+                );
+                Ok(token_info)
+            }
+        }
+    }
+
+    fn read_token_file(path: &Path) -> Result<TokenInfo, AppError> {
         if !path.exists() {
             return Err(AppError::ConfigError("Token file not found".to_string()));
         }
@@ -56,32 +153,88 @@ impl TokenStorage {
             .map_err(|e| AppError::ConfigError(format!("Failed to read token file: {}", e)))?;
 
         let token_info: TokenInfo = serde_json::from_str(&content)
-            .map_err(|e| AppError::ParseError(format!("Failed to parse token file: {}", e)))?;
+            .map_err(|e| AppError::parse("Failed to parse token file", e))?;
 
-        info!("Loaded token from file: {}", path.display());
         Ok(token_info)
     }
 
-    /// Save token to file
+    /// Previous generation of the token file at `path`
+    fn backup_path(path: &Path) -> PathBuf {
+        let mut name = path.file_name().unwrap_or_default().to_os_string();
+        name.push(".bak");
+        path.with_file_name(name)
+    }
+
+    /// Save token to file: written to a temporary file that replaces the
+    /// token file by rename, so a crash leaves either the old or the new
+    /// token. The previous token, if readable, is kept as the backup.
     fn save_to_file(&self, token_info: &TokenInfo) -> Result<(), AppError> {
         let json = serde_json::to_string_pretty(token_info)
-            .map_err(|e| AppError::ParseError(format!("Failed to serialize token: {}", e)))?;
-
-        fs::write(&self.storage_path, json).map_err(|e| {
+            .map_err(|e| AppError::parse("Failed to serialize token", e))?;
+        let write_error = |e: std::io::Error| {
             AppError::ConfigError(format!(
                 "Failed to write token file {}: {}",
                 self.storage_path.display(),
                 e
             ))
-        })?;
+        };
+
+        // Unique per write, so concurrent saves never share a temporary file
+        static WRITES: AtomicU64 = AtomicU64::new(0);
+        let mut temp_name = self.storage_path.file_name().unwrap_or_default().to_os_string();
+        temp_name.push(format!(
+            ".{}.{}.tmp",
+            std::process::id(),
+            WRITES.fetch_add(1, Ordering::Relaxed)
+        ));
+        let temp_path = self.storage_path.with_file_name(temp_name);
+
+        let written = fs::File::create(&temp_path).and_then(|mut file| {
+            file.write_all(json.as_bytes())?;
+            file.sync_all()
+        });
+        if let Err(e) = written {
+            let _ = fs::remove_file(&temp_path);
+            return Err(write_error(e));
+        }
+
+        if Self::read_token_file(&self.storage_path).is_ok() {
+            let backup = Self::backup_path(&self.storage_path);
+            if let Err(e) = fs::copy(&self.storage_path, &backup) {
+                warn!("Failed to back up token file to {}: {}", backup.display(), e);
+            }
+        }
+        if let Err(e) = fs::rename(&temp_path, &self.storage_path) {
+            let _ = fs::remove_file(&temp_path);
+            return Err(write_error(e));
+        }
+        Self::sync_parent_dir(&self.storage_path).map_err(write_error)?;
 
         info!("Saved token to file: {}", self.storage_path.display());
         Ok(())
     }
 
-    /// Store token information and persist to disk
-    pub fn store(&mut self, token_info: TokenInfo) -> Result<(), AppError> {
-        self.save_to_file(&token_info)?;
+    /// Make a rename in the directory of `path` durable
+    #[cfg(unix)]
+    fn sync_parent_dir(path: &Path) -> std::io::Result<()> {
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        fs::File::open(dir)?.sync_all()
+    }
+
+    #[cfg(not(unix))]
+    fn sync_parent_dir(_path: &Path) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    /// Store token information and persist to disk (or the backend)
+    pub async fn store(&mut self, token_info: TokenInfo) -> Result<(), AppError> {
+        match &self.backend {
+            Some(backend) => backend.save(Some(&token_info)).await?,
+            None => self.save_to_file(&token_info)?,
+        }
         self.token = Some(token_info);
         Ok(())
     }
@@ -92,18 +245,25 @@ impl TokenStorage {
     }
 
     /// Clear the stored token and delete the file
-    pub fn clear(&mut self) -> Result<(), AppError> {
+    pub async fn clear(&mut self) -> Result<(), AppError> {
         self.token = None;
+        if let Some(backend) = &self.backend {
+            return backend.save(None).await;
+        }
 
-        if self.storage_path.exists() {
-            fs::remove_file(&self.storage_path).map_err(|e| {
-                AppError::ConfigError(format!(
-                    "Failed to delete token file {}: {}",
-                    self.storage_path.display(),
-                    e
-                ))
-            })?;
-            info!("Deleted token file: {}", self.storage_path.display());
+        // The backup goes too, or the cleared token would be loaded from it
+        let backup = Self::backup_path(&self.storage_path);
+        for path in [&self.storage_path, &backup] {
+            if path.exists() {
+                fs::remove_file(path).map_err(|e| {
+                    AppError::ConfigError(format!(
+                        "Failed to delete token file {}: {}",
+                        path.display(),
+                        e
+                    ))
+                })?;
+                info!("Deleted token file: {}", path.display());
+            }
         }
 
         Ok(())
@@ -127,12 +287,17 @@ impl TokenStorage {
 
     /// Reload token from file (useful if file was updated externally)
     pub fn reload(&mut self) -> Result<(), AppError> {
-        let token_info = Self::load_from_file(&self.storage_path)?;
+        let token_info = match &self.backend {
+            Some(backend) => backend
+                .load()?
+                .ok_or_else(|| AppError::ConfigError("No token stored".to_string()))?,
+            None => Self::load_from_file(&self.storage_path)?,
+        };
         self.token = Some(token_info);
         Ok(())
     }
 
-    /// Get the storage file path
+    /// Get the storage file path (the backend's description when there is one)
     pub fn storage_path(&self) -> &Path {
         &self.storage_path
     }
@@ -143,3 +308,106 @@ impl Default for TokenStorage {
         Self::new()
     }
 }
+
+/// Token of the last refresh. Refreshes run one at a time under this lock,
+/// so callers that find the token expired together don't each spend the
+/// refresh token; TikTok rotates it, so the loser's refresh would fail. Kept
+/// in memory too, in case it couldn't be saved. Shared by every
+/// [`TokenManager`], like the storage of `TokenStorage::new`.
+static LAST_REFRESH: tokio::sync::Mutex<Option<TokenInfo>> = tokio::sync::Mutex::const_new(None);
+
+/// Hands out the token of [`TokenStorage::new`], refreshing it first when
+/// the access token expired. Concurrent callers in the process share one
+/// refresh.
+#[derive(Clone)]
+pub struct TokenManager {
+    oauth_client: TikTokShopOAuth,
+}
+
+impl TokenManager {
+    pub fn new(oauth_client: TikTokShopOAuth) -> Self {
+        Self { oauth_client }
+    }
+
+    /// The stored token, refreshed and persisted if the access token expired
+    pub async fn valid_token(&self) -> Result<TokenInfo, AppError> {
+        self.token_refreshed_within(chrono::Duration::zero()).await
+    }
+
+    /// The stored token, refreshed and persisted if the access token expires
+    /// within `refresh_within`
+    pub async fn token_refreshed_within(
+        &self,
+        refresh_within: chrono::Duration,
+    ) -> Result<TokenInfo, AppError> {
+        let token_info = TokenStorage::new().get().cloned().ok_or(AppError::NoTokenStored)?;
+        if !token_info.expires_within(refresh_within) {
+            return Ok(token_info);
+        }
+
+        let mut last_refresh = LAST_REFRESH.lock().await;
+        // Another caller or process may have refreshed it while this one waited
+        let mut token_storage = TokenStorage::new();
+        let stored = token_storage.get().cloned().ok_or(AppError::NoTokenStored)?;
+        // The last refresh is newer than the stored token if saving it failed
+        let token_info = match last_refresh.as_ref() {
+            Some(refreshed) if refreshed.expires_at > stored.expires_at => {
+                debug!("Using the last refreshed token");
+                refreshed.clone()
+            }
+            _ => stored,
+        };
+        if !token_info.expires_within(refresh_within) {
+            return Ok(token_info);
+        }
+
+        let refreshed_token = self.refresh(&token_info).await?;
+        // A token that can't be saved is kept in memory and used until it expires
+        match token_storage.store(refreshed_token.clone()).await {
+            Ok(()) => info!("Refreshed token saved to {}", token_storage.storage_path().display()),
+            Err(e) => error!("Failed to save refreshed token: {}", e),
+        }
+        *last_refresh = Some(refreshed_token.clone());
+
+        Ok(refreshed_token)
+    }
+
+    /// Whether a token is stored
+    pub fn has_token(&self) -> bool {
+        TokenStorage::new().get().is_some()
+    }
+
+    /// Store a newly authorized token in place of the current one, and forget
+    /// the last refreshed one
+    pub async fn replace(&self, token_info: TokenInfo) -> Result<(), AppError> {
+        let mut last_refresh = LAST_REFRESH.lock().await;
+        *last_refresh = None;
+        TokenStorage::new().store(token_info).await
+    }
+
+    /// Delete the stored token, e.g. after the seller de-authorized the app,
+    /// and forget the last refreshed one
+    pub async fn clear(&self) -> Result<(), AppError> {
+        let mut last_refresh = LAST_REFRESH.lock().await;
+        *last_refresh = None;
+        TokenStorage::new().clear().await
+    }
+
+    async fn refresh(&self, token_info: &TokenInfo) -> Result<TokenInfo, AppError> {
+        info!("Access token expired. Attempting to refresh...");
+
+        if token_info.refresh_token_expires_at < Utc::now() {
+            return Err(AppError::ConfigError(
+                "Refresh token expired. Please re-authorize the app.".to_string(),
+            ));
+        }
+
+        let token_response = self
+            .oauth_client
+            .refresh_access_token(&token_info.refresh_token)
+            .await?;
+        info!("Successfully refreshed access token");
+
+        Ok(TokenInfo::from_response(token_response))
+    }
+}
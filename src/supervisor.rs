@@ -0,0 +1,269 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tokio::task::{AbortHandle, JoinError};
+use tracing::{error, info};
+use utoipa::ToSchema;
+
+/// First restart delay after a panic; doubles on every panic in a row
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// A task that ran this long before panicking starts over at `MIN_BACKOFF`
+const STABLE_AFTER: Duration = Duration::from_secs(300);
+
+/// What a supervised task is doing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SupervisedState {
+    Running,
+    /// Panicked and waiting to be restarted
+    BackingOff,
+    /// Returned on its own; not restarted
+    Finished,
+    /// Stopped from `POST /admin/tasks/{name}/stop`
+    Stopped,
+}
+
+/// Health of a background task run by the [`Supervisor`]
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct SupervisedStatus {
+    pub name: String,
+    /// `running`, `backing_off`, `finished` or `stopped`
+    pub state: SupervisedState,
+    pub started_at: DateTime<Utc>,
+    pub restarts: u32,
+    pub last_panic: Option<String>,
+    pub last_panic_at: Option<DateTime<Utc>>,
+    /// Last time the task did any work (was polled); idle tasks beat rarely
+    pub last_heartbeat_at: Option<DateTime<Utc>>,
+}
+
+impl SupervisedStatus {
+    pub fn is_healthy(&self) -> bool {
+        self.state != SupervisedState::BackingOff
+    }
+}
+
+type Factory = Arc<dyn Fn() -> Pin<Box<dyn Future<Output = ()> + Send>> + Send + Sync>;
+
+struct Entry {
+    status: SupervisedStatus,
+    heartbeat: Arc<AtomicI64>,
+    factory: Factory,
+    /// Bumped on every stop and start, so a superseded supervise loop exits
+    generation: u64,
+    /// The current run, aborted on stop
+    run: Option<AbortHandle>,
+}
+
+/// Registry of long-lived background tasks. Restarts them with backoff when
+/// they panic so a bug can't silently stop e.g. the job worker for good, and
+/// lets admins stop and start them by name at runtime.
+#[derive(Clone, Default)]
+pub struct Supervisor {
+    tasks: Arc<RwLock<BTreeMap<String, Entry>>>,
+}
+
+impl Supervisor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run the future made by `factory` under `name`, calling `factory` again
+    /// for each restart
+    pub fn spawn<F, Fut>(&self, name: &str, factory: F)
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let factory: Factory = Arc::new(move || Box::pin(factory()));
+        self.tasks.write().unwrap().insert(
+            name.to_string(),
+            Entry {
+                status: SupervisedStatus {
+                    name: name.to_string(),
+                    state: SupervisedState::Running,
+                    started_at: Utc::now(),
+                    restarts: 0,
+                    last_panic: None,
+                    last_panic_at: None,
+                    last_heartbeat_at: None,
+                },
+                heartbeat: Arc::new(AtomicI64::new(0)),
+                factory,
+                generation: 0,
+                run: None,
+            },
+        );
+        self.start(name);
+    }
+
+    /// Stop the task registered under `name`. Work it already handed off,
+    /// such as a job or scheduled run in progress, finishes on its own.
+    /// Returns `false` for an unknown task.
+    pub fn stop(&self, name: &str) -> bool {
+        let mut tasks = self.tasks.write().unwrap();
+        let Some(entry) = tasks.get_mut(name) else {
+            return false;
+        };
+        entry.generation += 1;
+        if let Some(run) = entry.run.take() {
+            run.abort();
+        }
+        entry.status.state = SupervisedState::Stopped;
+        info!("Background task {} stopped", name);
+        true
+    }
+
+    /// Start the task registered under `name` afresh, stopping the current
+    /// run if there is one. Returns `false` for an unknown task.
+    pub fn restart(&self, name: &str) -> bool {
+        self.stop(name) && self.start(name)
+    }
+
+    fn start(&self, name: &str) -> bool {
+        let mut tasks = self.tasks.write().unwrap();
+        let Some(entry) = tasks.get_mut(name) else {
+            return false;
+        };
+        entry.generation += 1;
+        entry.status.state = SupervisedState::Running;
+        entry.status.started_at = Utc::now();
+        tokio::spawn(self.clone().supervise(
+            name.to_string(),
+            entry.generation,
+            entry.factory.clone(),
+            entry.heartbeat.clone(),
+        ));
+        true
+    }
+
+    pub fn tasks(&self) -> Vec<SupervisedStatus> {
+        self.tasks
+            .read()
+            .unwrap()
+            .values()
+            .map(|entry| {
+                let mut status = entry.status.clone();
+                let beat = entry.heartbeat.load(Ordering::Relaxed);
+                status.last_heartbeat_at = (beat > 0)
+                    .then(|| DateTime::from_timestamp_millis(beat))
+                    .flatten();
+                status
+            })
+            .collect()
+    }
+
+    /// Apply `f` to the status of `name` unless a stop or start superseded
+    /// `generation`. Returns whether it still is the current run.
+    fn update(&self, name: &str, generation: u64, f: impl FnOnce(&mut Entry)) -> bool {
+        match self.tasks.write().unwrap().get_mut(name) {
+            Some(entry) if entry.generation == generation => {
+                f(entry);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    async fn supervise(
+        self,
+        name: String,
+        generation: u64,
+        factory: Factory,
+        heartbeat: Arc<AtomicI64>,
+    ) {
+        let mut backoff = MIN_BACKOFF;
+        loop {
+            let started = Instant::now();
+            let run = tokio::spawn(Beating {
+                inner: factory(),
+                heartbeat: heartbeat.clone(),
+            });
+            let abort = run.abort_handle();
+            if !self.update(&name, generation, |e| e.run = Some(abort.clone())) {
+                abort.abort();
+                return;
+            }
+
+            let panic = match run.await {
+                Ok(()) => None,
+                Err(e) if e.is_panic() => Some(panic_message(e)),
+                Err(_) => None,
+            };
+            let Some(panic) = panic else {
+                if self.update(&name, generation, |e| {
+                    e.status.state = SupervisedState::Finished;
+                    e.run = None;
+                }) {
+                    info!("Background task {} finished", name);
+                }
+                return;
+            };
+
+            if started.elapsed() >= STABLE_AFTER {
+                backoff = MIN_BACKOFF;
+            }
+            error!(
+                "Background task {} panicked, restarting in {:?}: {}",
+                name, backoff, panic
+            );
+            let current = self.update(&name, generation, |e| {
+                e.status.state = SupervisedState::BackingOff;
+                e.status.restarts += 1;
+                e.status.last_panic = Some(panic);
+                e.status.last_panic_at = Some(Utc::now());
+                e.run = None;
+            });
+            if !current {
+                return;
+            }
+
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+            let current = self.update(&name, generation, |e| {
+                e.status.state = SupervisedState::Running;
+                e.status.started_at = Utc::now();
+            });
+            if !current {
+                return;
+            }
+        }
+    }
+}
+
+/// Records a heartbeat every time the wrapped future is polled
+struct Beating {
+    inner: Pin<Box<dyn Future<Output = ()> + Send>>,
+    heartbeat: Arc<AtomicI64>,
+}
+
+impl Future for Beating {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.heartbeat
+            .store(Utc::now().timestamp_millis(), Ordering::Relaxed);
+        self.inner.as_mut().poll(cx)
+    }
+}
+
+/// The message a task panicked with
+pub fn panic_message(error: JoinError) -> String {
+    if !error.is_panic() {
+        return error.to_string();
+    }
+    let payload = error.into_panic();
+    payload
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "panic with a non-string payload".to_string())
+}
@@ -0,0 +1,334 @@
+use crate::database::{self, Database};
+use crate::jobs::{Job, JobHandler};
+use crate::order::Order;
+use async_trait::async_trait;
+use serde::Serialize;
+use sqlx::sqlite::{Sqlite, SqlitePool, SqliteRow};
+use sqlx::{Row, Transaction};
+use std::str::FromStr;
+use std::sync::Arc;
+use tracing::info;
+use utoipa::ToSchema;
+
+/// Job that re-evaluates tag rules against every stored order
+pub const RETAG_JOB_KIND: &str = "order_retag";
+
+/// Rules used when `ORDER_TAG_RULES` is not set
+pub const DEFAULT_RULES: &str = "sample=is_sample_order,gift=is_gift";
+
+/// Lowercase a tag and check it only uses `a-z`, `0-9`, `-` and `_`
+pub fn normalize_tag(tag: &str) -> Option<String> {
+    let tag = tag.trim().to_ascii_lowercase();
+    let valid = !tag.is_empty()
+        && tag.len() <= 64
+        && tag
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    valid.then_some(tag)
+}
+
+/// What an order must look like for a rule to tag it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TagCondition {
+    /// `is_sample_order`
+    SampleOrder,
+    /// `is_gift`: any line item is a gift
+    GiftItem,
+    /// `is_replacement_order`
+    ReplacementOrder,
+    /// `is_cod`
+    CashOnDelivery,
+    /// `is_on_hold_order`
+    OnHold,
+    /// `seller_sku:PREFIX`: any line item's seller SKU starts with the prefix
+    SellerSkuPrefix(String),
+}
+
+impl TagCondition {
+    pub fn matches(&self, order: &Order) -> bool {
+        match self {
+            TagCondition::SampleOrder => order.is_sample_order == Some(true),
+            TagCondition::GiftItem => order.item_list.iter().any(|i| i.is_gift == Some(true)),
+            TagCondition::ReplacementOrder => order.is_replacement_order == Some(true),
+            TagCondition::CashOnDelivery => order.is_cod == Some(true),
+            TagCondition::OnHold => order.is_on_hold_order == Some(true),
+            TagCondition::SellerSkuPrefix(prefix) => order.item_list.iter().any(|i| {
+                i.seller_sku
+                    .as_deref()
+                    .is_some_and(|s| s.starts_with(prefix.as_str()))
+            }),
+        }
+    }
+}
+
+impl FromStr for TagCondition {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let value = value.trim();
+        if let Some(prefix) = value.strip_prefix("seller_sku:") {
+            return match prefix.trim() {
+                "" => Err("seller_sku: needs a SKU prefix".to_string()),
+                prefix => Ok(TagCondition::SellerSkuPrefix(prefix.to_string())),
+            };
+        }
+
+        match value {
+            "is_sample_order" => Ok(TagCondition::SampleOrder),
+            "is_gift" => Ok(TagCondition::GiftItem),
+            "is_replacement_order" => Ok(TagCondition::ReplacementOrder),
+            "is_cod" => Ok(TagCondition::CashOnDelivery),
+            "is_on_hold_order" => Ok(TagCondition::OnHold),
+            other => Err(format!(
+                "unknown condition {:?}, expected is_sample_order, is_gift, is_replacement_order, is_cod, is_on_hold_order or seller_sku:PREFIX",
+                other
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for TagCondition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TagCondition::SampleOrder => write!(f, "is_sample_order"),
+            TagCondition::GiftItem => write!(f, "is_gift"),
+            TagCondition::ReplacementOrder => write!(f, "is_replacement_order"),
+            TagCondition::CashOnDelivery => write!(f, "is_cod"),
+            TagCondition::OnHold => write!(f, "is_on_hold_order"),
+            TagCondition::SellerSkuPrefix(prefix) => write!(f, "seller_sku:{}", prefix),
+        }
+    }
+}
+
+/// Tag orders matching `condition` with `tag`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TagRule {
+    pub tag: String,
+    pub condition: TagCondition,
+}
+
+impl std::fmt::Display for TagRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}={}", self.tag, self.condition)
+    }
+}
+
+/// Parse `tag=condition` pairs separated by commas, e.g.
+/// `sample=is_sample_order,gift=is_gift,gift=seller_sku:GIFT-`
+pub fn parse_rules(value: &str) -> Result<Vec<TagRule>, String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|rule| !rule.is_empty())
+        .map(|rule| {
+            let (tag, condition) = rule
+                .split_once('=')
+                .ok_or_else(|| format!("rule {:?} is not tag=condition", rule))?;
+            Ok(TagRule {
+                tag: normalize_tag(tag).ok_or_else(|| format!("invalid tag {:?}", tag.trim()))?,
+                condition: condition.parse()?,
+            })
+        })
+        .collect()
+}
+
+/// Routing rules evaluated on upsert, and the tags left out of sales stats
+#[derive(Debug, Clone, Default)]
+pub struct OrderTagger {
+    rules: Vec<TagRule>,
+    stats_excluded: Vec<String>,
+}
+
+impl OrderTagger {
+    pub fn new(rules: Vec<TagRule>) -> Self {
+        Self {
+            rules,
+            stats_excluded: Vec::new(),
+        }
+    }
+
+    /// Leave orders carrying any of these tags out of SKU sales stats
+    pub fn with_stats_excluded(mut self, tags: Vec<String>) -> Self {
+        self.stats_excluded = tags;
+        self
+    }
+
+    /// Tags the rules give `order`, deduplicated
+    pub fn tags_for(&self, order: &Order) -> Vec<String> {
+        let mut tags: Vec<String> = self
+            .rules
+            .iter()
+            .filter(|rule| rule.condition.matches(order))
+            .map(|rule| rule.tag.clone())
+            .collect();
+        tags.sort();
+        tags.dedup();
+        tags
+    }
+
+    /// Changes whenever the rules or exclusions do, so stored orders are
+    /// re-evaluated once per configuration
+    pub fn fingerprint(&self) -> String {
+        let rules: Vec<String> = self.rules.iter().map(|r| r.to_string()).collect();
+        format!(
+            "{};exclude={}",
+            rules.join(","),
+            self.stats_excluded.join(",")
+        )
+    }
+
+    /// Whether an order with `tags` counts towards sales stats
+    pub fn counts_in_stats(&self, tags: &[String]) -> bool {
+        !tags.iter().any(|tag| self.stats_excluded.contains(tag))
+    }
+}
+
+/// A tag on an order
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct OrderTag {
+    pub tag: String,
+    /// `rule` (set by `ORDER_TAG_RULES`) or `manual`
+    pub source: String,
+    pub created_at: i64,
+}
+
+/// Order tags in the `order_tags` table. `source` is `rule` for tags set by
+/// an [`OrderTagger`]; those are replaced whenever the order is re-evaluated.
+/// `manual` tags stay until removed.
+#[derive(Clone)]
+pub struct TagStore {
+    pool: SqlitePool,
+}
+
+impl TagStore {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Initialize the order_tags table
+    pub async fn init(&self) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS order_tags (
+                order_id TEXT NOT NULL,
+                tag TEXT NOT NULL,
+                source TEXT NOT NULL,
+                created_at INTEGER NOT NULL,
+                PRIMARY KEY (order_id, tag)
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_order_tags_tag ON order_tags (tag)")
+            .execute(&self.pool)
+            .await?;
+        // Listings filter on it, so changes invalidate their ETag
+        database::track_orders_version(&self.pool, "order_tags").await?;
+
+        Ok(())
+    }
+
+    /// Replace the rule-set tags of an order within an upsert transaction
+    pub async fn apply_rules(
+        tx: &mut Transaction<'_, Sqlite>,
+        order_id: &str,
+        tags: &[String],
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query("DELETE FROM order_tags WHERE order_id = ?1 AND source = 'rule'")
+            .bind(order_id)
+            .execute(&mut **tx)
+            .await?;
+
+        let now = chrono::Utc::now().timestamp();
+        for tag in tags {
+            sqlx::query(
+                "INSERT OR IGNORE INTO order_tags (order_id, tag, source, created_at)
+                VALUES (?1, ?2, 'rule', ?3)",
+            )
+            .bind(order_id)
+            .bind(tag)
+            .bind(now)
+            .execute(&mut **tx)
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn tags(&self, order_id: &str) -> Result<Vec<OrderTag>, sqlx::Error> {
+        sqlx::query(
+            "SELECT tag, source, created_at FROM order_tags WHERE order_id = ?1 ORDER BY tag",
+        )
+        .bind(order_id)
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(tag_from_row)
+        .collect()
+    }
+
+    /// Tag an order by hand. A tag already set by a rule becomes manual, so
+    /// re-evaluating the rules no longer removes it.
+    pub async fn add(&self, order_id: &str, tags: &[String]) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let now = chrono::Utc::now().timestamp();
+        for tag in tags {
+            sqlx::query(
+                "INSERT INTO order_tags (order_id, tag, source, created_at)
+                VALUES (?1, ?2, 'manual', ?3)
+                ON CONFLICT(order_id, tag) DO UPDATE SET source = 'manual'",
+            )
+            .bind(order_id)
+            .bind(tag)
+            .bind(now)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await
+    }
+
+    /// Remove a tag, whatever set it; a rule tag comes back when the order is
+    /// next re-evaluated. Returns `false` if the order didn't have it.
+    pub async fn remove(&self, order_id: &str, tag: &str) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query("DELETE FROM order_tags WHERE order_id = ?1 AND tag = ?2")
+            .bind(order_id)
+            .bind(tag)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+fn tag_from_row(row: &SqliteRow) -> Result<OrderTag, sqlx::Error> {
+    Ok(OrderTag {
+        tag: row.try_get("tag")?,
+        source: row.try_get("source")?,
+        created_at: row.try_get("created_at")?,
+    })
+}
+
+/// Re-evaluates tag rules against stored orders after the rules changed
+pub struct RetagHandler {
+    db: Arc<Database>,
+}
+
+impl RetagHandler {
+    pub fn new(db: Arc<Database>) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl JobHandler for RetagHandler {
+    async fn handle(&self, _job: &Job) -> Result<(), String> {
+        let tagged = self
+            .db
+            .retag_stored_orders()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        info!("Re-evaluated tag rules on {} orders", tagged);
+        Ok(())
+    }
+}
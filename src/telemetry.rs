@@ -0,0 +1,91 @@
+use axum::http::HeaderMap;
+use opentelemetry::propagation::Extractor;
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry::{global, Key, Value};
+use opentelemetry_otlp::{
+    SpanExporter, OTEL_EXPORTER_OTLP_ENDPOINT, OTEL_EXPORTER_OTLP_TRACES_ENDPOINT,
+};
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::{SdkTracerProvider, Tracer};
+use opentelemetry_sdk::Resource;
+use std::env;
+use tracing::Span;
+use tracing_opentelemetry::{OpenTelemetryLayer, OpenTelemetrySpanExt};
+use tracing_subscriber::registry::LookupSpan;
+
+/// Service name reported unless `OTEL_SERVICE_NAME` or
+/// `OTEL_RESOURCE_ATTRIBUTES` name another
+pub const DEFAULT_SERVICE_NAME: &str = "toptop-order";
+
+/// What the SDK calls a service nobody named
+const UNKNOWN_SERVICE_NAME: &str = "unknown_service";
+
+/// Tracer provider exporting spans over OTLP/HTTP, or `None` when neither
+/// `OTEL_EXPORTER_OTLP_ENDPOINT` nor `OTEL_EXPORTER_OTLP_TRACES_ENDPOINT` is
+/// set. The exporter reads the other standard `OTEL_EXPORTER_OTLP_*`
+/// variables, e.g. headers and timeout, itself.
+pub fn tracer_provider() -> Result<Option<SdkTracerProvider>, String> {
+    let configured = [
+        OTEL_EXPORTER_OTLP_ENDPOINT,
+        OTEL_EXPORTER_OTLP_TRACES_ENDPOINT,
+    ]
+    .iter()
+    .any(|var| env::var(var).is_ok_and(|v| !v.is_empty()));
+    if !configured {
+        return Ok(None);
+    }
+
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .build()
+        .map_err(|e| format!("Cannot build the OTLP exporter: {}", e))?;
+    let resource = Resource::builder().build();
+    let unnamed = resource.get(&Key::from_static_str("service.name"))
+        == Some(Value::from(UNKNOWN_SERVICE_NAME));
+    let resource = match unnamed {
+        true => Resource::builder()
+            .with_service_name(DEFAULT_SERVICE_NAME)
+            .build(),
+        false => resource,
+    };
+
+    global::set_text_map_propagator(TraceContextPropagator::new());
+    Ok(Some(
+        SdkTracerProvider::builder()
+            .with_batch_exporter(exporter)
+            .with_resource(resource)
+            .build(),
+    ))
+}
+
+/// Layer turning tracing spans into OpenTelemetry spans exported by `provider`
+pub fn layer<S>(provider: &SdkTracerProvider) -> OpenTelemetryLayer<S, Tracer>
+where
+    S: tracing::Subscriber + for<'span> LookupSpan<'span>,
+{
+    tracing_opentelemetry::layer().with_tracer(provider.tracer(DEFAULT_SERVICE_NAME))
+}
+
+/// Make `span` part of the caller's trace when the request carries a W3C
+/// `traceparent` header
+pub fn continue_trace(span: &Span, headers: &HeaderMap) {
+    if !headers.contains_key("traceparent") {
+        return;
+    }
+    let parent =
+        global::get_text_map_propagator(|propagator| propagator.extract(&HeaderExtractor(headers)));
+    // Fails only without the OpenTelemetry layer, when there is no trace to join
+    let _ = span.set_parent(parent);
+}
+
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|v| v.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|k| k.as_str()).collect()
+    }
+}
@@ -0,0 +1,337 @@
+use crate::pii_crypto::PiiCipher;
+use crate::redact;
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::OsRng;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePool, SqlitePoolOptions, SqliteRow};
+use sqlx::Row;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Header tenant API keys are presented in (`Authorization: Bearer` works too)
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+/// Prefix of tenant API keys, so leaked keys are easy to recognize
+pub const API_KEY_PREFIX: &str = "tok_";
+
+/// A client the service runs for, with its own TikTok app
+#[derive(Clone, Serialize)]
+pub struct Tenant {
+    pub id: String,
+    pub name: String,
+    pub app_key: String,
+    #[serde(skip)]
+    pub app_secret: String,
+    /// Shop the tenant's orders are synced for, when its app has several
+    pub shop_id: Option<String>,
+    pub shop_cipher: Option<String>,
+    pub created_at: i64,
+}
+
+impl std::fmt::Debug for Tenant {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Tenant")
+            .field("id", &self.id)
+            .field("name", &self.name)
+            .field("app_key", &self.app_key)
+            .field("app_secret", &redact::mask(&self.app_secret))
+            .field("shop_id", &self.shop_id)
+            .field("shop_cipher", &self.shop_cipher)
+            .finish()
+    }
+}
+
+/// An API key of a tenant, without the key itself
+#[derive(Debug, Clone, Serialize)]
+pub struct TenantApiKey {
+    pub id: i64,
+    pub tenant_id: String,
+    pub label: Option<String>,
+    /// First characters of the key, to tell keys apart
+    pub prefix: String,
+    pub created_at: i64,
+    pub revoked_at: Option<i64>,
+}
+
+/// Reason `id` can't name a tenant, if any. Ids name the tenant's data
+/// directory, so they are kept to lowercase letters, digits, `-` and `_`.
+pub fn validate_id(id: &str) -> Result<(), String> {
+    let valid = !id.is_empty()
+        && id.len() <= 64
+        && id
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(format!(
+            "tenant id {:?} must be 1-64 lowercase letters, digits, '-' or '_'",
+            id
+        ))
+    }
+}
+
+/// Directory a tenant's token file, database and documents live in
+pub fn data_dir(root: &Path, tenant_id: &str) -> PathBuf {
+    root.join(tenant_id)
+}
+
+fn hash_key(key: &str) -> String {
+    hex::encode(Sha256::digest(key.as_bytes()))
+}
+
+/// Tenants and their API keys, in a registry database shared by the
+/// processes serving them. Keys are stored as SHA-256 digests, and app
+/// secrets encrypted like buyer PII when `PII_ENCRYPTION_KEY` is set.
+#[derive(Clone)]
+pub struct TenantStore {
+    pool: SqlitePool,
+    pii_cipher: Option<PiiCipher>,
+}
+
+impl TenantStore {
+    /// Open (creating if needed) the registry at `path`. With `pii_cipher`,
+    /// app secrets stored in plaintext before are encrypted now.
+    pub async fn open(path: &Path, pii_cipher: Option<PiiCipher>) -> Result<Self, sqlx::Error> {
+        let options = SqliteConnectOptions::from_str(&format!("sqlite://{}", path.display()))?
+            .create_if_missing(true);
+        let pool = SqlitePoolOptions::new()
+            .max_connections(2)
+            .connect_with(options)
+            .await?;
+        let store = Self { pool, pii_cipher };
+        store.init().await?;
+        store.encrypt_plaintext_secrets().await?;
+        Ok(store)
+    }
+
+    /// Initialize the tenants and tenant_api_keys tables
+    async fn init(&self) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS tenants (
+                id TEXT PRIMARY KEY,
+                name TEXT NOT NULL,
+                app_key TEXT NOT NULL,
+                app_secret TEXT NOT NULL,
+                shop_id TEXT,
+                shop_cipher TEXT,
+                created_at INTEGER NOT NULL
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS tenant_api_keys (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                tenant_id TEXT NOT NULL REFERENCES tenants (id),
+                key_hash TEXT NOT NULL UNIQUE,
+                prefix TEXT NOT NULL,
+                label TEXT,
+                created_at INTEGER NOT NULL,
+                revoked_at INTEGER
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Encrypt app secrets stored before `PII_ENCRYPTION_KEY` was set
+    async fn encrypt_plaintext_secrets(&self) -> Result<(), sqlx::Error> {
+        let Some(cipher) = &self.pii_cipher else {
+            return Ok(());
+        };
+        let rows = sqlx::query("SELECT id, app_secret FROM tenants")
+            .fetch_all(&self.pool)
+            .await?;
+        for row in rows {
+            let app_secret: String = row.try_get("app_secret")?;
+            if PiiCipher::is_encrypted(&app_secret) {
+                continue;
+            }
+            sqlx::query("UPDATE tenants SET app_secret = ?1 WHERE id = ?2")
+                .bind(cipher.encrypt(&app_secret))
+                .bind(row.try_get::<String, _>("id")?)
+                .execute(&self.pool)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// App secret as stored: encrypted when there is a cipher
+    fn seal(&self, app_secret: &str) -> String {
+        match &self.pii_cipher {
+            Some(cipher) => cipher.encrypt(app_secret),
+            None => app_secret.to_string(),
+        }
+    }
+
+    fn tenant_from_row(&self, row: &SqliteRow) -> Result<Tenant, sqlx::Error> {
+        let stored: String = row.try_get("app_secret")?;
+        let app_secret = match (&self.pii_cipher, PiiCipher::is_encrypted(&stored)) {
+            (Some(cipher), true) => cipher.decrypt(&stored).map_err(|e| {
+                sqlx::Error::Decode(format!("app secret of a tenant: {}", e).into())
+            })?,
+            (None, true) => {
+                return Err(sqlx::Error::Decode(
+                    "app secrets in the tenant registry are encrypted; set PII_ENCRYPTION_KEY"
+                        .into(),
+                ))
+            }
+            (_, false) => stored,
+        };
+        Ok(Tenant {
+            id: row.try_get("id")?,
+            name: row.try_get("name")?,
+            app_key: row.try_get("app_key")?,
+            app_secret,
+            shop_id: row.try_get("shop_id")?,
+            shop_cipher: row.try_get("shop_cipher")?,
+            created_at: row.try_get("created_at")?,
+        })
+    }
+
+    /// Add `tenant`, or replace the name, credentials and shop of the tenant
+    /// with its id
+    pub async fn upsert(&self, tenant: &Tenant) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "INSERT INTO tenants (id, name, app_key, app_secret, shop_id, shop_cipher, created_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+            ON CONFLICT(id) DO UPDATE SET
+                name = excluded.name,
+                app_key = excluded.app_key,
+                app_secret = excluded.app_secret,
+                shop_id = excluded.shop_id,
+                shop_cipher = excluded.shop_cipher",
+        )
+        .bind(&tenant.id)
+        .bind(&tenant.name)
+        .bind(&tenant.app_key)
+        .bind(self.seal(&tenant.app_secret))
+        .bind(&tenant.shop_id)
+        .bind(&tenant.shop_cipher)
+        .bind(tenant.created_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get(&self, id: &str) -> Result<Option<Tenant>, sqlx::Error> {
+        sqlx::query(
+            "SELECT id, name, app_key, app_secret, shop_id, shop_cipher, created_at
+            FROM tenants WHERE id = ?1",
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?
+        .as_ref()
+        .map(|row| self.tenant_from_row(row))
+        .transpose()
+    }
+
+    pub async fn list(&self) -> Result<Vec<Tenant>, sqlx::Error> {
+        sqlx::query(
+            "SELECT id, name, app_key, app_secret, shop_id, shop_cipher, created_at
+            FROM tenants ORDER BY id",
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(|row| self.tenant_from_row(row))
+        .collect()
+    }
+
+    /// Create an API key for `tenant_id`. The key is returned only here.
+    pub async fn create_key(
+        &self,
+        tenant_id: &str,
+        label: Option<&str>,
+    ) -> Result<(String, TenantApiKey), sqlx::Error> {
+        let mut secret = [0u8; 24];
+        OsRng.fill_bytes(&mut secret);
+        let key = format!("{}{}", API_KEY_PREFIX, hex::encode(secret));
+        let prefix = key[..API_KEY_PREFIX.len() + 6].to_string();
+        let now = chrono::Utc::now().timestamp();
+
+        let id = sqlx::query(
+            "INSERT INTO tenant_api_keys (tenant_id, key_hash, prefix, label, created_at)
+            VALUES (?1, ?2, ?3, ?4, ?5)",
+        )
+        .bind(tenant_id)
+        .bind(hash_key(&key))
+        .bind(&prefix)
+        .bind(label)
+        .bind(now)
+        .execute(&self.pool)
+        .await?
+        .last_insert_rowid();
+
+        let api_key = TenantApiKey {
+            id,
+            tenant_id: tenant_id.to_string(),
+            label: label.map(str::to_string),
+            prefix,
+            created_at: now,
+            revoked_at: None,
+        };
+        Ok((key, api_key))
+    }
+
+    pub async fn keys(&self, tenant_id: &str) -> Result<Vec<TenantApiKey>, sqlx::Error> {
+        sqlx::query(
+            "SELECT id, tenant_id, label, prefix, created_at, revoked_at
+            FROM tenant_api_keys WHERE tenant_id = ?1 ORDER BY id",
+        )
+        .bind(tenant_id)
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(key_from_row)
+        .collect()
+    }
+
+    /// Revoke key `id` of `tenant_id`. Returns whether a live key was revoked.
+    pub async fn revoke_key(&self, tenant_id: &str, id: i64) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            "UPDATE tenant_api_keys SET revoked_at = ?1
+            WHERE id = ?2 AND tenant_id = ?3 AND revoked_at IS NULL",
+        )
+        .bind(chrono::Utc::now().timestamp())
+        .bind(id)
+        .bind(tenant_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Tenant a live API key belongs to
+    pub async fn authenticate(&self, key: &str) -> Result<Option<String>, sqlx::Error> {
+        if !key.starts_with(API_KEY_PREFIX) {
+            return Ok(None);
+        }
+        sqlx::query(
+            "SELECT tenant_id FROM tenant_api_keys WHERE key_hash = ?1 AND revoked_at IS NULL",
+        )
+        .bind(hash_key(key))
+        .fetch_optional(&self.pool)
+        .await?
+        .map(|row| row.try_get("tenant_id"))
+        .transpose()
+    }
+}
+
+fn key_from_row(row: &SqliteRow) -> Result<TenantApiKey, sqlx::Error> {
+    Ok(TenantApiKey {
+        id: row.try_get("id")?,
+        tenant_id: row.try_get("tenant_id")?,
+        label: row.try_get("label")?,
+        prefix: row.try_get("prefix")?,
+        created_at: row.try_get("created_at")?,
+        revoked_at: row.try_get("revoked_at")?,
+    })
+}
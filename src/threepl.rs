@@ -0,0 +1,588 @@
+use crate::address;
+use crate::config::Config;
+use crate::database::Database;
+use crate::fulfillment::ShipPackageRequest;
+use crate::jobs::{Job, JobHandler, JobQueue};
+use crate::order::Order;
+use crate::redact;
+use crate::shops::ShopRegistry;
+use crate::transport::{HttpRequest, HttpTransport};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::{SqlitePool, SqliteRow};
+use sqlx::Row;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tracing::{info, warn};
+use utoipa::ToSchema;
+
+/// Send an order to the 3PL
+pub const PUSH_JOB_KIND: &str = "threepl_push";
+
+/// Mark an order shipped in TikTok with the tracking the 3PL reported
+pub const SHIP_JOB_KIND: &str = "threepl_ship";
+
+/// Order status in which orders are handed to the 3PL
+const PUSHABLE_STATUS: &str = "AWAITING_SHIPMENT";
+
+/// Header the API key is sent in unless `THREEPL_AUTH_HEADER` names another
+pub const DEFAULT_AUTH_HEADER: &str = "Authorization";
+
+/// Where and how orders are pushed to a third-party logistics provider
+#[derive(Clone)]
+pub struct ThreePlSettings {
+    /// Endpoint orders are POSTed to
+    pub url: String,
+    /// Sent as `Bearer <key>` in `Authorization`, or as-is in any other header
+    pub api_key: Option<String>,
+    pub auth_header: String,
+    /// Bearer token the 3PL's tracking callbacks must present
+    pub callback_token: String,
+    /// TikTok shipping provider used when a callback names none
+    pub shipping_provider_id: Option<String>,
+}
+
+impl std::fmt::Debug for ThreePlSettings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ThreePlSettings")
+            .field("url", &self.url)
+            .field("api_key", &self.api_key.as_deref().map(redact::mask))
+            .field("auth_header", &self.auth_header)
+            .field("callback_token", &redact::mask(&self.callback_token))
+            .field("shipping_provider_id", &self.shipping_provider_id)
+            .finish()
+    }
+}
+
+impl ThreePlSettings {
+    /// Check the endpoint is an http(s) URL and a callback token is set
+    pub fn validate(&self) -> Result<(), String> {
+        match reqwest::Url::parse(&self.url) {
+            Ok(url) if url.scheme() == "http" || url.scheme() == "https" => {}
+            _ => return Err(format!("{:?} is not an http(s) URL", self.url)),
+        }
+        if self.callback_token.trim().is_empty() {
+            return Err("THREEPL_CALLBACK_TOKEN is required with THREEPL_URL".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Recipient of a pushed order
+#[derive(Debug, Clone, Serialize)]
+pub struct ThreePlRecipient {
+    pub name: Option<String>,
+    pub phone: Option<String>,
+    /// House number and street
+    pub street: Option<String>,
+    pub ward: Option<String>,
+    pub district: Option<String>,
+    pub province: Option<String>,
+    pub postal_code: Option<String>,
+    pub country: Option<String>,
+    /// ISO 3166-1 alpha-2 code
+    pub region_code: Option<String>,
+    /// TikTok's single-line address, for couriers that want it as printed
+    pub full_address: Option<String>,
+}
+
+/// A SKU of a pushed order
+#[derive(Debug, Clone, Serialize)]
+pub struct ThreePlItem {
+    /// Seller SKU, or the TikTok SKU id when the item has none
+    pub sku: String,
+    pub name: String,
+    pub variant: Option<String>,
+    pub quantity: i64,
+}
+
+/// Body POSTed to the 3PL for one order
+#[derive(Debug, Clone, Serialize)]
+pub struct ThreePlOrder {
+    /// TikTok order id; callbacks may name the order by it
+    pub reference: String,
+    pub created_at: i64,
+    pub shipping_due_time: Option<i64>,
+    pub recipient: ThreePlRecipient,
+    pub items: Vec<ThreePlItem>,
+    pub buyer_message: Option<String>,
+}
+
+impl ThreePlOrder {
+    /// The order in the 3PL's format. Cancelled line items are left out and
+    /// the rest are summed per SKU.
+    pub fn from_order(order: &Order) -> Self {
+        let address = order.recipient_address.as_ref();
+        let normalized = address.map(address::normalize).unwrap_or_default();
+
+        let mut items: BTreeMap<String, ThreePlItem> = BTreeMap::new();
+        for item in order
+            .item_list
+            .iter()
+            .filter(|item| item.display_status.as_deref() != Some("CANCELLED"))
+        {
+            let sku = item
+                .seller_sku
+                .clone()
+                .filter(|sku| !sku.trim().is_empty())
+                .unwrap_or_else(|| item.sku_id.clone());
+            items
+                .entry(sku.clone())
+                .or_insert_with(|| ThreePlItem {
+                    sku,
+                    name: item.product_name.clone(),
+                    variant: item.sku_name.clone(),
+                    quantity: 0,
+                })
+                .quantity += i64::from(item.quantity.unwrap_or(1));
+        }
+
+        Self {
+            reference: order.id.clone(),
+            created_at: order.create_time,
+            shipping_due_time: order.shipping_due_time,
+            recipient: ThreePlRecipient {
+                name: address.and_then(|a| a.name.clone()),
+                phone: address.and_then(|a| a.phone.clone()),
+                street: normalized.street,
+                ward: normalized.ward,
+                district: normalized.district,
+                province: normalized.province,
+                postal_code: normalized.postal_code,
+                country: normalized.country,
+                region_code: normalized.region_code,
+                full_address: address.and_then(|a| a.full_address.clone()),
+            },
+            items: items.into_values().collect(),
+            buyer_message: order.buyer_message.clone(),
+        }
+    }
+}
+
+/// What the 3PL answers to a pushed order
+#[derive(Debug, Deserialize)]
+struct PushResponse {
+    #[serde(alias = "id")]
+    shipment_id: String,
+}
+
+/// Tracking reported by the 3PL, body of `POST /webhooks/3pl`. The order is
+/// named by the 3PL's shipment id or by the TikTok order id.
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct ThreePlCallback {
+    pub shipment_id: Option<String>,
+    /// The `reference` the order was pushed with
+    pub order_id: Option<String>,
+    pub tracking_number: String,
+    /// TikTok shipping provider; defaults to `THREEPL_SHIPPING_PROVIDER_ID`
+    pub shipping_provider_id: Option<String>,
+}
+
+/// An order handed to the 3PL
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ThreePlShipment {
+    pub order_id: String,
+    /// The 3PL's id for the order
+    pub shipment_id: String,
+    pub pushed_at: i64,
+    pub tracking_number: Option<String>,
+    pub shipping_provider_id: Option<String>,
+    /// When the 3PL reported tracking
+    pub tracking_received_at: Option<i64>,
+    /// When TikTok accepted the tracking
+    pub shipped_at: Option<i64>,
+    /// Why the last attempt to mark the order shipped failed
+    pub ship_error: Option<String>,
+}
+
+/// Orders pushed to the 3PL, in the `threepl_shipments` table
+#[derive(Clone)]
+pub struct ThreePlStore {
+    pool: SqlitePool,
+}
+
+impl ThreePlStore {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Initialize the threepl_shipments table
+    pub async fn init(&self) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS threepl_shipments (
+                order_id TEXT PRIMARY KEY,
+                shipment_id TEXT NOT NULL UNIQUE,
+                pushed_at INTEGER NOT NULL,
+                tracking_number TEXT,
+                shipping_provider_id TEXT,
+                tracking_received_at INTEGER,
+                shipped_at INTEGER,
+                ship_error TEXT
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn record_push(&self, order_id: &str, shipment_id: &str) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "INSERT INTO threepl_shipments (order_id, shipment_id, pushed_at)
+            VALUES (?1, ?2, ?3)
+            ON CONFLICT(order_id) DO NOTHING",
+        )
+        .bind(order_id)
+        .bind(shipment_id)
+        .bind(chrono::Utc::now().timestamp())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn get(&self, order_id: &str) -> Result<Option<ThreePlShipment>, sqlx::Error> {
+        sqlx::query(
+            "SELECT order_id, shipment_id, pushed_at, tracking_number, shipping_provider_id,
+                tracking_received_at, shipped_at, ship_error
+            FROM threepl_shipments WHERE order_id = ?1",
+        )
+        .bind(order_id)
+        .fetch_optional(&self.pool)
+        .await?
+        .as_ref()
+        .map(shipment_from_row)
+        .transpose()
+    }
+
+    pub async fn find_by_shipment_id(
+        &self,
+        shipment_id: &str,
+    ) -> Result<Option<ThreePlShipment>, sqlx::Error> {
+        sqlx::query(
+            "SELECT order_id, shipment_id, pushed_at, tracking_number, shipping_provider_id,
+                tracking_received_at, shipped_at, ship_error
+            FROM threepl_shipments WHERE shipment_id = ?1",
+        )
+        .bind(shipment_id)
+        .fetch_optional(&self.pool)
+        .await?
+        .as_ref()
+        .map(shipment_from_row)
+        .transpose()
+    }
+
+    /// Store tracking reported by the 3PL. New tracking clears the shipped
+    /// state so it is sent to TikTok again.
+    pub async fn record_tracking(
+        &self,
+        order_id: &str,
+        tracking_number: &str,
+        shipping_provider_id: &str,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "UPDATE threepl_shipments SET
+                shipped_at = CASE WHEN tracking_number IS ?2 AND shipping_provider_id IS ?3
+                    THEN shipped_at ELSE NULL END,
+                tracking_number = ?2,
+                shipping_provider_id = ?3,
+                tracking_received_at = ?4,
+                ship_error = NULL
+            WHERE order_id = ?1",
+        )
+        .bind(order_id)
+        .bind(tracking_number)
+        .bind(shipping_provider_id)
+        .bind(chrono::Utc::now().timestamp())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Record the outcome of marking the order shipped in TikTok
+    pub async fn record_ship(
+        &self,
+        order_id: &str,
+        error: Option<&str>,
+    ) -> Result<(), sqlx::Error> {
+        let shipped_at = error.is_none().then(|| chrono::Utc::now().timestamp());
+        sqlx::query(
+            "UPDATE threepl_shipments SET shipped_at = COALESCE(?2, shipped_at), ship_error = ?3
+            WHERE order_id = ?1",
+        )
+        .bind(order_id)
+        .bind(shipped_at)
+        .bind(error)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+}
+
+fn shipment_from_row(row: &SqliteRow) -> Result<ThreePlShipment, sqlx::Error> {
+    Ok(ThreePlShipment {
+        order_id: row.try_get("order_id")?,
+        shipment_id: row.try_get("shipment_id")?,
+        pushed_at: row.try_get("pushed_at")?,
+        tracking_number: row.try_get("tracking_number")?,
+        shipping_provider_id: row.try_get("shipping_provider_id")?,
+        tracking_received_at: row.try_get("tracking_received_at")?,
+        shipped_at: row.try_get("shipped_at")?,
+        ship_error: row.try_get("ship_error")?,
+    })
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobPayload {
+    pub order_id: String,
+}
+
+/// Queue pushing `order` to the 3PL if it is awaiting shipment. Returns
+/// whether a job was queued; an order is only ever queued once.
+pub async fn enqueue_push(
+    job_queue: &JobQueue,
+    order: &Order,
+    max_attempts: u32,
+) -> Result<bool, sqlx::Error> {
+    if order.status != PUSHABLE_STATUS {
+        return Ok(false);
+    }
+    let payload = serde_json::to_value(JobPayload {
+        order_id: order.id.clone(),
+    })
+    .unwrap_or_default();
+    job_queue
+        .enqueue(
+            PUSH_JOB_KIND,
+            Some(&format!("{}:{}", PUSH_JOB_KIND, order.id)),
+            &payload,
+            max_attempts,
+        )
+        .await
+}
+
+/// Queue marking an order shipped with the tracking it has now
+pub async fn enqueue_ship(
+    job_queue: &JobQueue,
+    shipment: &ThreePlShipment,
+    max_attempts: u32,
+) -> Result<bool, sqlx::Error> {
+    let payload = serde_json::to_value(JobPayload {
+        order_id: shipment.order_id.clone(),
+    })
+    .unwrap_or_default();
+    job_queue
+        .enqueue(
+            SHIP_JOB_KIND,
+            Some(&format!(
+                "{}:{}:{}",
+                SHIP_JOB_KIND,
+                shipment.order_id,
+                shipment.tracking_number.as_deref().unwrap_or_default()
+            )),
+            &payload,
+            max_attempts,
+        )
+        .await
+}
+
+/// Sends orders to the 3PL's HTTP API
+pub struct ThreePlClient {
+    settings: ThreePlSettings,
+    transport: Arc<dyn HttpTransport>,
+}
+
+impl ThreePlClient {
+    pub fn new(settings: ThreePlSettings, transport: Arc<dyn HttpTransport>) -> Self {
+        Self {
+            settings,
+            transport,
+        }
+    }
+
+    /// POST the order and return the 3PL's shipment id
+    pub async fn push(&self, order: &ThreePlOrder) -> Result<String, String> {
+        let body = serde_json::to_string(order).map_err(|e| e.to_string())?;
+        let mut request = HttpRequest::post(&self.settings.url)
+            .with_header("Content-Type", "application/json")
+            .with_body(body);
+        if let Some(key) = &self.settings.api_key {
+            let value = if self
+                .settings
+                .auth_header
+                .eq_ignore_ascii_case(DEFAULT_AUTH_HEADER)
+            {
+                format!("Bearer {}", key)
+            } else {
+                key.clone()
+            };
+            request = request.with_header(&self.settings.auth_header, &value);
+        }
+
+        let response = self
+            .transport
+            .send(request)
+            .await
+            .map_err(|e| format!("{}: {}", self.settings.url, e))?;
+        if !response.status.is_success() {
+            return Err(format!(
+                "{} answered HTTP {}: {}",
+                self.settings.url, response.status, response.body
+            ));
+        }
+        let parsed: PushResponse = serde_json::from_str(&response.body)
+            .map_err(|e| format!("3PL response has no shipment id: {}", e))?;
+        Ok(parsed.shipment_id)
+    }
+}
+
+/// Pushes queued orders to the 3PL. Orders that left `AWAITING_SHIPMENT`
+/// before their turn, e.g. cancelled ones, are dropped.
+pub struct ThreePlPushHandler {
+    client: ThreePlClient,
+    store: ThreePlStore,
+    db: Arc<Database>,
+}
+
+impl ThreePlPushHandler {
+    pub fn new(client: ThreePlClient, store: ThreePlStore, db: Arc<Database>) -> Self {
+        Self { client, store, db }
+    }
+}
+
+#[async_trait]
+impl JobHandler for ThreePlPushHandler {
+    async fn handle(&self, job: &Job) -> Result<(), String> {
+        let payload: JobPayload = serde_json::from_value(job.payload.clone())
+            .map_err(|e| format!("Invalid 3PL push payload: {}", e))?;
+        if self
+            .store
+            .get(&payload.order_id)
+            .await
+            .map_err(|e| e.to_string())?
+            .is_some()
+        {
+            return Ok(());
+        }
+        let Some(order) = self
+            .db
+            .get_order_by_id(&payload.order_id)
+            .await
+            .map_err(|e| e.to_string())?
+        else {
+            return Ok(());
+        };
+        let order = self.db.reveal_pii(order);
+        if order.status != PUSHABLE_STATUS {
+            warn!(
+                "Not pushing order {} to the 3PL: it is {} now",
+                order.id, order.status
+            );
+            return Ok(());
+        }
+
+        let shipment_id = self.client.push(&ThreePlOrder::from_order(&order)).await?;
+        self.store
+            .record_push(&order.id, &shipment_id)
+            .await
+            .map_err(|e| e.to_string())?;
+        info!("Pushed order {} to the 3PL as {}", order.id, shipment_id);
+        Ok(())
+    }
+}
+
+/// Marks the packages of an order shipped in TikTok with the 3PL's tracking
+pub struct ThreePlShipHandler {
+    store: ThreePlStore,
+    db: Arc<Database>,
+    shops: ShopRegistry,
+    config: Config,
+}
+
+impl ThreePlShipHandler {
+    pub fn new(
+        store: ThreePlStore,
+        db: Arc<Database>,
+        shops: ShopRegistry,
+        config: Config,
+    ) -> Self {
+        Self {
+            store,
+            db,
+            shops,
+            config,
+        }
+    }
+
+    async fn ship(&self, shipment: &ThreePlShipment) -> Result<(), String> {
+        let (Some(tracking_number), Some(provider_id)) =
+            (&shipment.tracking_number, &shipment.shipping_provider_id)
+        else {
+            return Err("no tracking reported yet".to_string());
+        };
+        let order = self
+            .db
+            .get_order_by_id(&shipment.order_id)
+            .await
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| format!("order {} is not stored", shipment.order_id))?;
+        if order.packages.is_empty() {
+            return Err(format!("order {} has no package yet", order.id));
+        }
+
+        let token = self
+            .config
+            .token_manager()
+            .valid_token()
+            .await
+            .map_err(|e| e.to_string())?;
+        let client = self
+            .shops
+            .target(&self.config)
+            .await
+            .map_err(|e| e.to_string())?
+            .fulfillment_client(&self.config);
+        let request = ShipPackageRequest::self_shipment(tracking_number, provider_id);
+        for package in &order.packages {
+            client
+                .ship_package(&token.access_token, None, &package.id, &request)
+                .await
+                .map_err(|e| format!("package {}: {}", package.id, e))?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl JobHandler for ThreePlShipHandler {
+    async fn handle(&self, job: &Job) -> Result<(), String> {
+        let payload: JobPayload = serde_json::from_value(job.payload.clone())
+            .map_err(|e| format!("Invalid 3PL ship payload: {}", e))?;
+        let Some(shipment) = self
+            .store
+            .get(&payload.order_id)
+            .await
+            .map_err(|e| e.to_string())?
+        else {
+            return Ok(());
+        };
+        if shipment.shipped_at.is_some() {
+            return Ok(());
+        }
+
+        let result = self.ship(&shipment).await;
+        self.store
+            .record_ship(
+                &shipment.order_id,
+                result.as_ref().err().map(String::as_str),
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+        result?;
+
+        info!(
+            "Marked order {} shipped in TikTok with 3PL tracking {}",
+            shipment.order_id,
+            shipment.tracking_number.as_deref().unwrap_or_default()
+        );
+        Ok(())
+    }
+}
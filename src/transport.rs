@@ -0,0 +1,447 @@
+use crate::error::AppError;
+use async_trait::async_trait;
+use reqwest::{Client, Method, StatusCode};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+/// Request body sent by a transport
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HttpBody {
+    /// Raw body, sent as-is (callers set the Content-Type header)
+    Raw(String),
+    /// URL-encoded form fields
+    Form(Vec<(String, String)>),
+}
+
+/// Outgoing HTTP request, independent of the underlying client
+#[derive(Debug, Clone)]
+pub struct HttpRequest {
+    pub method: Method,
+    pub url: String,
+    pub query: Vec<(String, String)>,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<HttpBody>,
+}
+
+impl HttpRequest {
+    pub fn new(method: Method, url: impl Into<String>) -> Self {
+        Self {
+            method,
+            url: url.into(),
+            query: Vec::new(),
+            headers: Vec::new(),
+            body: None,
+        }
+    }
+
+    pub fn get(url: impl Into<String>) -> Self {
+        Self::new(Method::GET, url)
+    }
+
+    pub fn post(url: impl Into<String>) -> Self {
+        Self::new(Method::POST, url)
+    }
+
+    pub fn with_query<'a, I>(mut self, params: I) -> Self
+    where
+        I: IntoIterator<Item = (&'a String, &'a String)>,
+    {
+        self.query
+            .extend(params.into_iter().map(|(k, v)| (k.clone(), v.clone())));
+        self
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn with_body(mut self, body: String) -> Self {
+        self.body = Some(HttpBody::Raw(body));
+        self
+    }
+
+    pub fn with_form<'a, I>(mut self, fields: I) -> Self
+    where
+        I: IntoIterator<Item = (&'a str, &'a str)>,
+    {
+        self.body = Some(HttpBody::Form(
+            fields
+                .into_iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        ));
+        self
+    }
+
+    /// Look up a query parameter by name
+    pub fn query_param(&self, name: &str) -> Option<&str> {
+        self.query
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Look up a header by name (case-insensitive)
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// HTTP response as returned by a transport
+#[derive(Debug, Clone)]
+pub struct HttpResponse {
+    pub status: StatusCode,
+    pub body: String,
+}
+
+impl HttpResponse {
+    pub fn new(status: StatusCode, body: impl Into<String>) -> Self {
+        Self {
+            status,
+            body: body.into(),
+        }
+    }
+
+    pub fn ok(body: impl Into<String>) -> Self {
+        Self::new(StatusCode::OK, body)
+    }
+}
+
+/// Abstraction over the HTTP client used by the API clients.
+///
+/// The default implementation is [`ReqwestTransport`]; tests and offline tools
+/// can swap in [`MockTransport`] to avoid real network calls.
+#[async_trait]
+pub trait HttpTransport: Send + Sync {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse, AppError>;
+}
+
+/// Transport backed by a `reqwest::Client`
+#[derive(Clone, Default)]
+pub struct ReqwestTransport {
+    client: Client,
+}
+
+impl ReqwestTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_client(client: Client) -> Self {
+        Self { client }
+    }
+}
+
+#[async_trait]
+impl HttpTransport for ReqwestTransport {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse, AppError> {
+        let mut builder = self
+            .client
+            .request(request.method, &request.url)
+            .query(&request.query);
+
+        for (name, value) in &request.headers {
+            builder = builder.header(name, value);
+        }
+
+        builder = match request.body {
+            Some(HttpBody::Raw(body)) => builder.body(body),
+            Some(HttpBody::Form(fields)) => builder.form(&fields),
+            None => builder,
+        };
+
+        let response = builder
+            .send()
+            .await?;
+
+        let status = response.status();
+        let body = response
+            .text()
+            .await?;
+
+        Ok(HttpResponse { status, body })
+    }
+}
+
+/// Transport that answers every request with a fixed function, for offline stubs
+pub struct StubTransport {
+    respond: Box<dyn Fn(&HttpRequest) -> HttpResponse + Send + Sync>,
+}
+
+impl StubTransport {
+    pub fn new(respond: impl Fn(&HttpRequest) -> HttpResponse + Send + Sync + 'static) -> Self {
+        Self {
+            respond: Box::new(respond),
+        }
+    }
+}
+
+#[async_trait]
+impl HttpTransport for StubTransport {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse, AppError> {
+        Ok((self.respond)(&request))
+    }
+}
+
+/// Test double that records requests and replays queued responses in order
+#[derive(Default)]
+pub struct MockTransport {
+    responses: Mutex<VecDeque<Result<HttpResponse, AppError>>>,
+    requests: Mutex<Vec<HttpRequest>>,
+}
+
+impl MockTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queue a response to be returned by the next call to `send`
+    pub fn push_response(&self, response: HttpResponse) {
+        self.responses.lock().unwrap().push_back(Ok(response));
+    }
+
+    /// Queue a JSON body with a 200 status
+    pub fn push_json(&self, body: serde_json::Value) {
+        self.push_response(HttpResponse::ok(body.to_string()));
+    }
+
+    /// Queue a transport-level failure (e.g. connection refused)
+    pub fn push_error(&self, error: AppError) {
+        self.responses.lock().unwrap().push_back(Err(error));
+    }
+
+    /// All requests sent so far, oldest first
+    pub fn requests(&self) -> Vec<HttpRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// The most recent request, if any
+    pub fn last_request(&self) -> Option<HttpRequest> {
+        self.requests.lock().unwrap().last().cloned()
+    }
+}
+
+#[async_trait]
+impl HttpTransport for MockTransport {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse, AppError> {
+        self.requests.lock().unwrap().push(request);
+        self.responses
+            .lock()
+            .unwrap()
+            .pop_front()
+            .unwrap_or_else(|| {
+                Err(AppError::transport("MockTransport: no response queued"))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::requests::TikTokShopApiClient;
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+
+    const TIMESTAMP: i64 = 1_700_000_000;
+
+    fn client(transport: Arc<dyn HttpTransport>) -> TikTokShopApiClient {
+        TikTokShopApiClient::with_transport("testkey".to_string(), "testsecret".to_string(), transport)
+            .with_fixed_timestamp(TIMESTAMP)
+    }
+
+    fn success() -> serde_json::Value {
+        serde_json::json!({
+            "code": 0,
+            "message": "Success",
+            "data": {},
+            "request_id": "202311150000000000000000"
+        })
+    }
+
+    fn query(request: &HttpRequest) -> Vec<(&str, &str)> {
+        request
+            .query
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect()
+    }
+
+    /// The URL as sent, query string included
+    fn full_url(request: &HttpRequest) -> String {
+        reqwest::Url::parse_with_params(&request.url, &request.query)
+            .unwrap()
+            .to_string()
+    }
+
+    // Expected signatures were computed outside the crate:
+    // printf '%s' "testsecret${SIGN_STRING}testsecret" | openssl dgst -sha256 -hmac testsecret
+
+    #[tokio::test]
+    async fn signs_get_requests() {
+        let transport = Arc::new(MockTransport::new());
+        transport.push_json(success());
+
+        let _: serde_json::Value = client(transport.clone())
+            .get("/authorization/202309/shops", Some("TTP_token"), None, BTreeMap::new())
+            .await
+            .unwrap();
+
+        // Sign string: /authorization/202309/shopsapp_keytestkeytimestamp1700000000
+        let request = transport.last_request().unwrap();
+        assert_eq!(request.method, Method::GET);
+        assert_eq!(
+            query(&request),
+            [
+                ("access_token", "TTP_token"),
+                ("app_key", "testkey"),
+                (
+                    "sign",
+                    "ad27d2139b183141cff59b8845b72c0b799b531d142df44ffb4eb27e4f65547e"
+                ),
+                ("timestamp", "1700000000"),
+            ]
+        );
+        assert_eq!(
+            full_url(&request),
+            "https://open-api.tiktokglobalshop.com/authorization/202309/shops\
+             ?access_token=TTP_token&app_key=testkey\
+             &sign=ad27d2139b183141cff59b8845b72c0b799b531d142df44ffb4eb27e4f65547e\
+             &timestamp=1700000000"
+        );
+        assert_eq!(request.header("x-tts-access-token"), Some("TTP_token"));
+        assert_eq!(request.body, None);
+    }
+
+    #[tokio::test]
+    async fn signs_get_requests_with_shop_cipher_and_params() {
+        let transport = Arc::new(MockTransport::new());
+        transport.push_json(success());
+
+        let mut params = BTreeMap::new();
+        params.insert(
+            "ids".to_string(),
+            "576486316948490001,576486316948490002".to_string(),
+        );
+        let _: serde_json::Value = client(transport.clone())
+            .get("/order/202309/orders", Some("TTP_token"), Some("ROW_cipher"), params)
+            .await
+            .unwrap();
+
+        // Sign string: /order/202309/ordersapp_keytestkeyids576486316948490001,576486316948490002shop_cipherROW_ciphertimestamp1700000000
+        let request = transport.last_request().unwrap();
+        assert_eq!(
+            full_url(&request),
+            "https://open-api.tiktokglobalshop.com/order/202309/orders\
+             ?access_token=TTP_token&app_key=testkey\
+             &ids=576486316948490001%2C576486316948490002&shop_cipher=ROW_cipher\
+             &sign=d631ba1c789614dcf8ea0f34b9adc9c217ca7942066230fcacefc864c54a6448\
+             &timestamp=1700000000"
+        );
+    }
+
+    #[tokio::test]
+    async fn signs_post_requests_over_the_body() {
+        let transport = Arc::new(MockTransport::new());
+        transport.push_json(success());
+
+        let mut extra = BTreeMap::new();
+        extra.insert("page_size".to_string(), "10".to_string());
+        let _: serde_json::Value = client(transport.clone())
+            .post(
+                "/order/202309/orders/search",
+                Some("TTP_token"),
+                Some("ROW_cipher"),
+                &serde_json::json!({ "order_status": "AWAITING_SHIPMENT" }),
+                Some(extra),
+            )
+            .await
+            .unwrap();
+
+        // Sign string: /order/202309/orders/searchapp_keytestkeypage_size10shop_cipherROW_ciphertimestamp1700000000{"order_status":"AWAITING_SHIPMENT"}
+        let request = transport.last_request().unwrap();
+        assert_eq!(request.method, Method::POST);
+        assert_eq!(
+            query(&request),
+            [
+                ("access_token", "TTP_token"),
+                ("app_key", "testkey"),
+                ("page_size", "10"),
+                ("shop_cipher", "ROW_cipher"),
+                (
+                    "sign",
+                    "4dd2334504e12c510083668d169cf3059554a4d109fa3be929889b5a95d5fe63"
+                ),
+                ("timestamp", "1700000000"),
+            ]
+        );
+        assert_eq!(
+            request.body,
+            Some(HttpBody::Raw(
+                r#"{"order_status":"AWAITING_SHIPMENT"}"#.to_string()
+            ))
+        );
+        assert_eq!(request.header("content-type"), Some("application/json"));
+    }
+
+    #[tokio::test]
+    async fn replays_responses_in_order_then_fails() {
+        let transport = Arc::new(MockTransport::new());
+        transport.push_json(serde_json::json!({
+            "code": 105002,
+            "message": "Expired credentials",
+            "data": null,
+            "request_id": "r1"
+        }));
+        transport.push_error(AppError::transport("connection refused"));
+        let client = client(transport.clone());
+
+        let first: Result<serde_json::Value, _> = client
+            .get("/authorization/202309/shops", None, None, BTreeMap::new())
+            .await;
+        assert!(matches!(first, Err(AppError::ApiError { code: 105002, .. })));
+
+        let second: Result<serde_json::Value, _> = client
+            .get("/authorization/202309/shops", None, None, BTreeMap::new())
+            .await;
+        assert!(second.is_err());
+
+        // Nothing queued any more
+        let third: Result<serde_json::Value, _> = client
+            .get("/authorization/202309/shops", None, None, BTreeMap::new())
+            .await;
+        assert!(third.is_err());
+
+        let requests = transport.requests();
+        assert_eq!(requests.len(), 3);
+        // No access token: neither the parameter nor the header is sent
+        assert_eq!(requests[0].query_param("access_token"), None);
+        assert_eq!(requests[0].header("x-tts-access-token"), None);
+    }
+
+    #[tokio::test]
+    async fn stub_answers_every_request() {
+        let transport = Arc::new(StubTransport::new(|request| {
+            HttpResponse::ok(
+                serde_json::json!({
+                    "code": 0,
+                    "message": "Success",
+                    "data": { "path": request.url },
+                })
+                .to_string(),
+            )
+        }));
+        let client = client(transport);
+
+        for path in ["/authorization/202309/shops", "/order/202309/orders"] {
+            let data: serde_json::Value = client.get(path, None, None, BTreeMap::new()).await.unwrap();
+            assert_eq!(
+                data["path"],
+                format!("https://open-api.tiktokglobalshop.com{}", path)
+            );
+        }
+    }
+}
@@ -0,0 +1,308 @@
+use crate::order::{Order, PaymentInfo};
+use serde::Serialize;
+use sqlx::sqlite::{Sqlite, SqlitePool};
+use sqlx::{Row, Transaction};
+use utoipa::ToSchema;
+
+/// Amounts are compared in ten-thousandths of the currency unit
+const AMOUNT_SCALE: i64 = 10_000;
+
+/// Largest difference treated as rounding noise (0.01)
+const TOLERANCE: i64 = AMOUNT_SCALE / 100;
+
+/// An inconsistency found in an order's payment data
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Anomaly {
+    /// `total_mismatch`, `subtotal_mismatch`, `missing_discount_lines`,
+    /// `discount_mismatch`, `currency_mismatch` or `invalid_amount`
+    pub kind: &'static str,
+    pub detail: String,
+}
+
+/// A recorded anomaly for the version of the order last stored
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct OrderAnomaly {
+    pub id: i64,
+    pub order_id: String,
+    pub kind: String,
+    pub detail: String,
+    /// `update_time` of the order version that was checked
+    pub order_update_time: i64,
+    pub detected_at: i64,
+}
+
+/// Recomputes order totals from their parts and reports what doesn't add up.
+///
+/// Assumes TikTok's breakdown: `total_amount = sub_total + shipping_fee + tax`,
+/// `sub_total` is the sum of line item `sale_price`s, and the order-level
+/// discounts are the sums of the line item discounts.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OrderValidator;
+
+impl OrderValidator {
+    pub fn new() -> Self {
+        Self
+    }
+
+    pub fn validate(&self, order: &Order) -> Vec<Anomaly> {
+        let Some(payment) = &order.payment else {
+            return Vec::new();
+        };
+
+        let mut anomalies = Vec::new();
+        let mut amount = |field: &str, value: &str| {
+            let parsed = parse_amount(value);
+            if parsed.is_none() {
+                anomalies.push(Anomaly {
+                    kind: "invalid_amount",
+                    detail: format!("{} is not a number: {:?}", field, value),
+                });
+            }
+            parsed
+        };
+
+        let total = amount("total_amount", &payment.total_amount);
+        let sub_total = amount("sub_total", &payment.sub_total);
+        let shipping_fee = amount("shipping_fee", &payment.shipping_fee);
+        let tax = match payment.tax.as_deref().filter(|t| !t.is_empty()) {
+            Some(tax) => amount("tax", tax),
+            None => Some(0),
+        };
+        let order_discount = sum([
+            amount("seller_discount", &payment.seller_discount),
+            amount("platform_discount", &payment.platform_discount),
+        ]);
+
+        let mut item_total = Some(0);
+        let mut item_discount = Some(0);
+        let mut has_item_discounts = false;
+        for item in &order.item_list {
+            let quantity = i64::from(item.quantity.unwrap_or(1).max(1));
+            let price = amount(&format!("line item {} sale_price", item.id), &item.sale_price);
+            item_total = sum([item_total, price.map(|p| p * quantity)]);
+
+            for (field, value) in [
+                ("seller_discount", &item.seller_discount),
+                ("platform_discount", &item.platform_discount),
+            ] {
+                if let Some(value) = value.as_deref().filter(|v| !v.is_empty()) {
+                    has_item_discounts = true;
+                    let discount = amount(&format!("line item {} {}", item.id, field), value);
+                    item_discount = sum([item_discount, discount]);
+                }
+            }
+        }
+
+        if let (Some(total), Some(expected)) = (total, sum([sub_total, shipping_fee, tax])) {
+            if differs(total, expected) {
+                anomalies.push(Anomaly {
+                    kind: "total_mismatch",
+                    detail: format!(
+                        "total_amount {} != sub_total + shipping_fee + tax = {}",
+                        format_amount(total),
+                        format_amount(expected)
+                    ),
+                });
+            }
+        }
+
+        if let (Some(sub_total), Some(item_total)) = (sub_total, item_total) {
+            if !order.item_list.is_empty() && differs(sub_total, item_total) {
+                anomalies.push(Anomaly {
+                    kind: "subtotal_mismatch",
+                    detail: format!(
+                        "sub_total {} != sum of line item sale prices {}",
+                        format_amount(sub_total),
+                        format_amount(item_total)
+                    ),
+                });
+            }
+        }
+
+        if let (Some(order_discount), Some(item_discount)) = (order_discount, item_discount) {
+            if !has_item_discounts && order_discount > TOLERANCE {
+                anomalies.push(Anomaly {
+                    kind: "missing_discount_lines",
+                    detail: format!(
+                        "order discounts total {} but no line item carries a discount",
+                        format_amount(order_discount)
+                    ),
+                });
+            } else if has_item_discounts && differs(order_discount, item_discount) {
+                anomalies.push(Anomaly {
+                    kind: "discount_mismatch",
+                    detail: format!(
+                        "order discounts {} != sum of line item discounts {}",
+                        format_amount(order_discount),
+                        format_amount(item_discount)
+                    ),
+                });
+            }
+        }
+
+        anomalies.extend(currency_anomaly(order, payment));
+        anomalies
+    }
+}
+
+fn currency_anomaly(order: &Order, payment: &PaymentInfo) -> Option<Anomaly> {
+    let mismatched: Vec<String> = order
+        .item_list
+        .iter()
+        .filter_map(|item| item.currency.as_deref())
+        .filter(|currency| !currency.is_empty() && *currency != payment.currency)
+        .map(str::to_string)
+        .collect();
+
+    if mismatched.is_empty() {
+        return None;
+    }
+
+    Some(Anomaly {
+        kind: "currency_mismatch",
+        detail: format!(
+            "order currency is {} but line items use {}",
+            payment.currency,
+            mismatched.join(", ")
+        ),
+    })
+}
+
+/// Parse a decimal amount such as `"12.5"` into ten-thousandths. Empty means zero.
+fn parse_amount(value: &str) -> Option<i64> {
+    let value = value.trim();
+    if value.is_empty() {
+        return Some(0);
+    }
+
+    let (negative, digits) = match value.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, value),
+    };
+    let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+    if (whole.is_empty() && fraction.is_empty())
+        || !whole.bytes().all(|b| b.is_ascii_digit())
+        || !fraction.bytes().all(|b| b.is_ascii_digit())
+        || fraction.len() > 4
+    {
+        return None;
+    }
+
+    let whole: i64 = if whole.is_empty() { 0 } else { whole.parse().ok()? };
+    let fraction: i64 = format!("{:0<4}", fraction).parse().ok()?;
+    let amount = whole.checked_mul(AMOUNT_SCALE)?.checked_add(fraction)?;
+    Some(if negative { -amount } else { amount })
+}
+
+fn format_amount(amount: i64) -> String {
+    let sign = if amount < 0 { "-" } else { "" };
+    let amount = amount.abs();
+    format!("{}{}.{:02}", sign, amount / AMOUNT_SCALE, (amount % AMOUNT_SCALE) / 100)
+}
+
+fn sum<const N: usize>(amounts: [Option<i64>; N]) -> Option<i64> {
+    amounts.into_iter().try_fold(0i64, |acc, a| acc.checked_add(a?))
+}
+
+fn differs(a: i64, b: i64) -> bool {
+    (a - b).abs() > TOLERANCE
+}
+
+/// Anomalies recorded for stored orders in the `order_anomalies` table
+#[derive(Clone)]
+pub struct AnomalyStore {
+    pool: SqlitePool,
+}
+
+impl AnomalyStore {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Initialize the order_anomalies table
+    pub async fn init(&self) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS order_anomalies (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                order_id TEXT NOT NULL,
+                kind TEXT NOT NULL,
+                detail TEXT NOT NULL,
+                order_update_time INTEGER NOT NULL,
+                detected_at INTEGER NOT NULL
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            "CREATE INDEX IF NOT EXISTS idx_order_anomalies_order ON order_anomalies (order_id)",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Replace the anomalies recorded for `order` within an upsert transaction,
+    /// so a corrected order version clears earlier findings
+    pub async fn replace(
+        tx: &mut Transaction<'_, Sqlite>,
+        order: &Order,
+        anomalies: &[Anomaly],
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query("DELETE FROM order_anomalies WHERE order_id = ?1")
+            .bind(&order.id)
+            .execute(&mut **tx)
+            .await?;
+
+        let now = chrono::Utc::now().timestamp();
+        for anomaly in anomalies {
+            sqlx::query(
+                "INSERT INTO order_anomalies (order_id, kind, detail, order_update_time, detected_at)
+                VALUES (?1, ?2, ?3, ?4, ?5)",
+            )
+            .bind(&order.id)
+            .bind(anomaly.kind)
+            .bind(&anomaly.detail)
+            .bind(order.update_time)
+            .bind(now)
+            .execute(&mut **tx)
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Recorded anomalies, newest first, optionally filtered by kind or order
+    pub async fn list(
+        &self,
+        kind: Option<&str>,
+        order_id: Option<&str>,
+        limit: i64,
+    ) -> Result<Vec<OrderAnomaly>, sqlx::Error> {
+        let rows = sqlx::query(
+            "SELECT id, order_id, kind, detail, order_update_time, detected_at
+            FROM order_anomalies
+            WHERE (?1 IS NULL OR kind = ?1) AND (?2 IS NULL OR order_id = ?2)
+            ORDER BY id DESC LIMIT ?3",
+        )
+        .bind(kind)
+        .bind(order_id)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter()
+            .map(|row| {
+                Ok(OrderAnomaly {
+                    id: row.try_get("id")?,
+                    order_id: row.try_get("order_id")?,
+                    kind: row.try_get("kind")?,
+                    detail: row.try_get("detail")?,
+                    order_update_time: row.try_get("order_update_time")?,
+                    detected_at: row.try_get("detected_at")?,
+                })
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,247 @@
+use crate::address;
+use crate::order::Order;
+use serde::Serialize;
+use sqlx::sqlite::{Sqlite, SqlitePool};
+use sqlx::{Row, Transaction};
+use std::str::FromStr;
+use utoipa::ToSchema;
+
+/// Order statuses in which the preferred warehouse may still change
+const ROUTABLE_STATUSES: &[&str] = &["UNPAID", "ON_HOLD", "AWAITING_SHIPMENT"];
+
+/// Which orders a route applies to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RouteSelector {
+    /// `REGION/Province`: recipient province, compared like [`address::province_name`]
+    Province {
+        region_code: String,
+        province: String,
+    },
+    /// `REGION`: recipient `region_code`
+    Region(String),
+    /// `*`: every order
+    Any,
+}
+
+impl RouteSelector {
+    /// Province routes beat region routes, which beat `*`
+    fn specificity(&self) -> u8 {
+        match self {
+            RouteSelector::Province { .. } => 2,
+            RouteSelector::Region(_) => 1,
+            RouteSelector::Any => 0,
+        }
+    }
+
+    pub fn matches(&self, order: &Order) -> bool {
+        let normalized = order.recipient_address.as_ref().map(address::normalize);
+        let region = normalized.as_ref().and_then(|a| a.region_code.as_deref());
+        match self {
+            RouteSelector::Any => true,
+            RouteSelector::Region(code) => region == Some(code.as_str()),
+            RouteSelector::Province {
+                region_code,
+                province,
+            } => {
+                region == Some(region_code.as_str())
+                    && normalized
+                        .as_ref()
+                        .and_then(|a| a.province.as_deref())
+                        .is_some_and(|p| p.to_lowercase() == province.to_lowercase())
+            }
+        }
+    }
+}
+
+impl FromStr for RouteSelector {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let value = value.trim();
+        if value == "*" {
+            return Ok(RouteSelector::Any);
+        }
+        let (region, province) = match value.split_once('/') {
+            Some((region, province)) => (region.trim(), Some(province)),
+            None => (value, None),
+        };
+        if region.len() != 2 || !region.chars().all(|c| c.is_ascii_alphabetic()) {
+            return Err(format!("{:?} is not a two-letter region code", region));
+        }
+        let region_code = region.to_ascii_uppercase();
+        match province {
+            None => Ok(RouteSelector::Region(region_code)),
+            Some(province) => Ok(RouteSelector::Province {
+                region_code,
+                province: address::province_name(province)
+                    .ok_or_else(|| format!("{:?} has an empty province", value))?,
+            }),
+        }
+    }
+}
+
+impl std::fmt::Display for RouteSelector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RouteSelector::Province {
+                region_code,
+                province,
+            } => write!(f, "{}/{}", region_code, province),
+            RouteSelector::Region(code) => write!(f, "{}", code),
+            RouteSelector::Any => write!(f, "*"),
+        }
+    }
+}
+
+/// Ship orders matching `selector` from `warehouse_id`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WarehouseRoute {
+    pub selector: RouteSelector,
+    pub warehouse_id: String,
+}
+
+impl std::fmt::Display for WarehouseRoute {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}={}", self.selector, self.warehouse_id)
+    }
+}
+
+/// Parse `selector=warehouse_id` pairs separated by commas, e.g.
+/// `VN/Hà Nội=7001,VN=7002,*=7003`
+pub fn parse_routes(value: &str) -> Result<Vec<WarehouseRoute>, String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|route| !route.is_empty())
+        .map(|route| {
+            let (selector, warehouse_id) = route
+                .split_once('=')
+                .ok_or_else(|| format!("route {:?} is not selector=warehouse_id", route))?;
+            let warehouse_id = warehouse_id.trim();
+            if warehouse_id.is_empty() {
+                return Err(format!("route {:?} has no warehouse id", route));
+            }
+            Ok(WarehouseRoute {
+                selector: selector.parse()?,
+                warehouse_id: warehouse_id.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// The warehouse an order should ship from, and the route that chose it
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct WarehouseAssignment {
+    pub warehouse_id: String,
+    /// The matching route, as configured in `WAREHOUSE_ROUTES`
+    pub route: String,
+    pub assigned_at: i64,
+}
+
+/// Picks a preferred warehouse for each order from `WAREHOUSE_ROUTES`
+#[derive(Debug, Clone, Default)]
+pub struct WarehouseRouter {
+    routes: Vec<WarehouseRoute>,
+}
+
+impl WarehouseRouter {
+    pub fn new(routes: Vec<WarehouseRoute>) -> Self {
+        Self { routes }
+    }
+
+    /// The most specific matching route; the first listed among equals
+    pub fn route(&self, order: &Order) -> Option<&WarehouseRoute> {
+        self.routes
+            .iter()
+            .filter(|route| route.selector.matches(order))
+            .fold(None, |best: Option<&WarehouseRoute>, route| match best {
+                Some(best) if best.selector.specificity() >= route.selector.specificity() => {
+                    Some(best)
+                }
+                _ => Some(route),
+            })
+    }
+}
+
+/// Preferred warehouses in the `order_warehouses` table, one row per order
+#[derive(Clone)]
+pub struct WarehouseAssignmentStore {
+    pool: SqlitePool,
+}
+
+impl WarehouseAssignmentStore {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Initialize the order_warehouses table
+    pub async fn init(&self) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS order_warehouses (
+                order_id TEXT PRIMARY KEY,
+                warehouse_id TEXT NOT NULL,
+                route TEXT NOT NULL,
+                assigned_at INTEGER NOT NULL
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Route an order within an upsert transaction. Orders keep their
+    /// assignment once they are past awaiting shipment.
+    pub async fn record(
+        tx: &mut Transaction<'_, Sqlite>,
+        router: &WarehouseRouter,
+        order: &Order,
+    ) -> Result<(), sqlx::Error> {
+        if !ROUTABLE_STATUSES.contains(&order.status.as_str()) {
+            return Ok(());
+        }
+        let Some(route) = router.route(order) else {
+            sqlx::query("DELETE FROM order_warehouses WHERE order_id = ?1")
+                .bind(&order.id)
+                .execute(&mut **tx)
+                .await?;
+            return Ok(());
+        };
+
+        sqlx::query(
+            "INSERT INTO order_warehouses (order_id, warehouse_id, route, assigned_at)
+            VALUES (?1, ?2, ?3, ?4)
+            ON CONFLICT(order_id) DO UPDATE SET
+                warehouse_id = excluded.warehouse_id,
+                route = excluded.route,
+                assigned_at = CASE
+                    WHEN order_warehouses.warehouse_id = excluded.warehouse_id
+                    THEN order_warehouses.assigned_at
+                    ELSE excluded.assigned_at
+                END",
+        )
+        .bind(&order.id)
+        .bind(&route.warehouse_id)
+        .bind(route.to_string())
+        .bind(chrono::Utc::now().timestamp())
+        .execute(&mut **tx)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn get(&self, order_id: &str) -> Result<Option<WarehouseAssignment>, sqlx::Error> {
+        sqlx::query(
+            "SELECT warehouse_id, route, assigned_at FROM order_warehouses WHERE order_id = ?1",
+        )
+        .bind(order_id)
+        .fetch_optional(&self.pool)
+        .await?
+        .map(|row| {
+            Ok(WarehouseAssignment {
+                warehouse_id: row.try_get("warehouse_id")?,
+                route: row.try_get("route")?,
+                assigned_at: row.try_get("assigned_at")?,
+            })
+        })
+        .transpose()
+    }
+}
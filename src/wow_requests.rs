@@ -1,15 +1,27 @@
+use crate::error::AppError;
+use crate::redact;
+use crate::transport::{HttpBody, HttpRequest, HttpResponse, HttpTransport, ReqwestTransport, StubTransport};
 use hmac::{Hmac, Mac};
+use reqwest::StatusCode;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use sha2::Sha256;
 use std::collections::BTreeMap;
-use std::env;
+use std::sync::Arc;
+use thiserror::Error;
+use tracing::debug;
 
 type HmacSha256 = Hmac<Sha256>;
 
+/// API host used unless `WOW_API_BASE_URL` names another
+pub const DEFAULT_API_BASE_URL: &str = "https://api.wowesim.com/";
+
 #[derive(Clone)]
 pub struct WowEsimApiClient {
     wow_secret: String,
-    http_client: reqwest::Client,
+    api_base_url: String,
+    transport: Arc<dyn HttpTransport>,
+    log_bodies: bool,
+    fixed_timestamp: Option<i64>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -26,45 +38,145 @@ pub struct SignatureBody<T> {
     pub data: T,
 }
 
-#[derive(Debug)]
+/// eSIM details returned by a successful provisioning call
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct EsimProvisionResult {
+    #[serde(default)]
+    pub iccid: Option<String>,
+    #[serde(default)]
+    pub qr_code: Option<String>,
+    #[serde(default)]
+    pub activation_code: Option<String>,
+}
+
+/// Data balance of a provisioned eSIM
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct EsimUsage {
+    /// Provider status, e.g. `ACTIVE` or `EXPIRED`
+    #[serde(default)]
+    pub status: Option<String>,
+    #[serde(default)]
+    pub data_total_mb: Option<f64>,
+    #[serde(default)]
+    pub data_used_mb: Option<f64>,
+    #[serde(default)]
+    pub data_remaining_mb: Option<f64>,
+    #[serde(default)]
+    pub expires_at: Option<String>,
+}
+
+#[derive(Debug, Error)]
 pub enum WowApiError {
+    #[error("Signature error: {0}")]
     SignatureError(String),
+
+    /// The request never got a response
+    #[error("HTTP error: {0}")]
     HttpError(String),
+
+    /// WowEsim answered with a non-success HTTP status
+    #[error("HTTP {status} - {body}")]
+    HttpStatus { status: StatusCode, body: String },
+
+    #[error("Parse error: {0}")]
     ParseError(String),
+
+    /// WowEsim answered with `success: false`
+    #[error("API error: {0}")]
     ApiError(String),
 }
 
-impl std::fmt::Display for WowApiError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            WowApiError::SignatureError(e) => write!(f, "Signature error: {}", e),
-            WowApiError::HttpError(e) => write!(f, "HTTP error: {}", e),
-            WowApiError::ParseError(e) => write!(f, "Parse error: {}", e),
-            WowApiError::ApiError(e) => write!(f, "API error: {}", e),
+/// Classified like TikTok failures so callers handle both clients alike:
+/// transport failures and HTTP statuses map to the same variants, while
+/// rejections and unreadable responses become [`AppError::WowError`].
+impl From<WowApiError> for AppError {
+    fn from(e: WowApiError) -> Self {
+        match e {
+            WowApiError::SignatureError(e) => AppError::SignatureError(e),
+            WowApiError::HttpError(e) => AppError::transport(format!("WowEsim: {}", e)),
+            WowApiError::HttpStatus { status, body } => AppError::HttpStatus { status, body },
+            e @ (WowApiError::ParseError(_) | WowApiError::ApiError(_)) => {
+                AppError::WowError(e.to_string())
+            }
         }
     }
 }
 
-impl std::error::Error for WowApiError {}
-
-
-impl Default for WowEsimApiClient {
-    fn default() -> Self {
-        Self::new(dotenvy::var("WOW_SECRET").expect("WOW_SECRET env var not set"))
-    }
-}
-
 impl WowEsimApiClient {
-    // const API_BASE_URL: &'static str = "https://api.wowesim.com/";
+    const PROVISION_PATH: &'static str = "esim/order";
+    const USAGE_PATH: &'static str = "esim/usage";
 
     /// Create a new WowEsimApiClient with the given secret
     pub fn new(wow_secret: String) -> Self {
+        Self::with_transport(wow_secret, Arc::new(ReqwestTransport::new()))
+    }
+
+    /// Create a client that sends requests through a custom transport
+    pub fn with_transport(wow_secret: String, transport: Arc<dyn HttpTransport>) -> Self {
         Self {
             wow_secret,
-            http_client: reqwest::Client::new(),
+            api_base_url: DEFAULT_API_BASE_URL.to_string(),
+            transport,
+            log_bodies: false,
+            fixed_timestamp: None,
         }
     }
 
+    /// Stub client for sandbox mode: never touches the network and returns
+    /// fake eSIMs derived from the request reference
+    pub fn sandbox() -> Self {
+        let transport = StubTransport::new(|request| {
+            let reference = match &request.body {
+                Some(HttpBody::Raw(body)) => serde_json::from_str::<serde_json::Value>(body)
+                    .ok()
+                    .and_then(|v| v["data"]["reference"].as_str().map(str::to_string))
+                    .unwrap_or_default(),
+                _ => String::new(),
+            };
+
+            let data = if request.url.ends_with(Self::PROVISION_PATH) {
+                serde_json::json!({
+                    "iccid": format!("SANDBOX-{}", reference),
+                    "qr_code": format!("LPA:1$sandbox.invalid${}", reference),
+                    "activation_code": "SANDBOX"
+                })
+            } else if request.url.ends_with(Self::USAGE_PATH) {
+                serde_json::json!({
+                    "status": "ACTIVE",
+                    "data_total_mb": 1024.0,
+                    "data_used_mb": 256.0,
+                    "data_remaining_mb": 768.0
+                })
+            } else {
+                serde_json::Value::Null
+            };
+
+            HttpResponse::ok(serde_json::json!({ "success": true, "data": data }).to_string())
+        });
+
+        Self::with_transport("sandbox".to_string(), Arc::new(transport))
+            .with_base_url("https://sandbox.invalid/")
+    }
+
+    /// Use this base URL instead of [`DEFAULT_API_BASE_URL`]
+    pub fn with_base_url(mut self, api_base_url: impl Into<String>) -> Self {
+        self.api_base_url = api_base_url.into();
+        self
+    }
+
+    /// Log (redacted) request and response bodies and sign strings at debug level
+    pub fn with_body_logging(mut self, enabled: bool) -> Self {
+        self.log_bodies = enabled;
+        self
+    }
+
+    /// Sign every request with `timestamp` instead of the current time, so
+    /// requests are reproducible (e.g. in golden tests)
+    pub fn with_fixed_timestamp(mut self, timestamp: i64) -> Self {
+        self.fixed_timestamp = Some(timestamp);
+        self
+    }
+
     /// Generate HMAC-SHA256 signature for WowEsim API
     ///
     /// Format: ?key1=value1&key2=value2&timestamp=xxx
@@ -84,7 +196,9 @@ impl WowEsimApiClient {
         });
         sign_string.push_str(&format!("&timestamp={}", timestamp));
 
-        println!("Sign string: {}", sign_string);
+        if self.log_bodies {
+            debug!("Sign string: {}", sign_string);
+        }
 
         // Generate HMAC-SHA256
         let mut mac = HmacSha256::new_from_slice(self.wow_secret.as_bytes())
@@ -93,8 +207,6 @@ impl WowEsimApiClient {
         let result = mac.finalize();
         let signature = hex::encode(result.into_bytes());
 
-        println!("Generated signature: {}", signature);
-
         Ok(signature)
     }
 
@@ -103,7 +215,9 @@ impl WowEsimApiClient {
         path: &str,
         body: &BTreeMap<String, String>,
     ) -> Result<WowApiResponse<T>, WowApiError> {
-        let timestamp = chrono::Utc::now().timestamp();
+        let timestamp = self
+            .fixed_timestamp
+            .unwrap_or_else(|| chrono::Utc::now().timestamp());
 
         // Generate signature
         let signature = self.generate_signature(body, timestamp)?;
@@ -114,38 +228,42 @@ impl WowEsimApiClient {
             data: body,
         };
 
-        let api_base_url = env::var("WOW_API_BASE_URL").expect("WOW_API_BASE_URL env var not set");
-        let url = format!("{}{}", api_base_url, path);
-        println!("Making POST request to: {}", url);
-        println!("Request body: {:?}", &signature_body);
+        let url = format!("{}{}", self.api_base_url, path);
+        debug!("Making POST request to: {}", url);
 
         // Serialize body
         let body_json = serde_json::to_string(&signature_body)
             .map_err(|e| WowApiError::ParseError(format!("Failed to serialize body: {}", e)))?;
+        if self.log_bodies {
+            debug!("Request body: {}", redact::json(&body_json));
+        }
 
         // Make request
-        let response = self.http_client
-            .post(&url)
-            .body(body_json)
-            .header("Content-Type", "application/json")
-            .send()
-            .await
-            .map_err(|e| WowApiError::HttpError(e.to_string()))?;
+        let request = HttpRequest::post(url)
+            .with_body(body_json)
+            .with_header("Content-Type", "application/json");
 
-        let status = response.status();
-        let response_body = response
-            .text()
+        let response = self
+            .transport
+            .send(request)
             .await
             .map_err(|e| WowApiError::HttpError(e.to_string()))?;
 
-        println!("Response status: {}, body: {}", status, response_body);
+        let status = response.status;
+        let response_body = response.body;
+
+        if self.log_bodies {
+            debug!("Response status: {}, body: {}", status, redact::json(&response_body));
+        } else {
+            debug!("Response status: {}, {} bytes", status, response_body.len());
+        }
 
         // Check HTTP status
         if !status.is_success() {
-            return Err(WowApiError::ApiError(format!(
-                "HTTP {} - {}",
-                status, response_body
-            )));
+            return Err(WowApiError::HttpStatus {
+                status,
+                body: response_body,
+            });
         }
 
         // Parse response
@@ -162,6 +280,39 @@ impl WowEsimApiClient {
         Ok(api_response)
     }
 
+    /// Provision eSIMs for a package. `reference` is sent as the idempotency
+    /// reference so retries don't double-provision.
+    pub async fn provision_esim(
+        &self,
+        package_code: &str,
+        quantity: i32,
+        reference: &str,
+    ) -> Result<EsimProvisionResult, WowApiError> {
+        let mut body = BTreeMap::new();
+        body.insert("package_code".to_string(), package_code.to_string());
+        body.insert("quantity".to_string(), quantity.to_string());
+        body.insert("reference".to_string(), reference.to_string());
+
+        let response: WowApiResponse<EsimProvisionResult> =
+            self.post(Self::PROVISION_PATH, &body).await?;
+
+        response
+            .data
+            .ok_or_else(|| WowApiError::ParseError("No eSIM data in response".to_string()))
+    }
+
+    /// Current data balance of the eSIM with `iccid`
+    pub async fn esim_usage(&self, iccid: &str) -> Result<EsimUsage, WowApiError> {
+        let mut body = BTreeMap::new();
+        body.insert("iccid".to_string(), iccid.to_string());
+
+        let response: WowApiResponse<EsimUsage> = self.post(Self::USAGE_PATH, &body).await?;
+
+        response
+            .data
+            .ok_or_else(|| WowApiError::ParseError("No usage data in response".to_string()))
+    }
+
     /// Make a simple POST request without parsing response data
     pub async fn post_simple(
         &self,
@@ -172,3 +323,122 @@ impl WowEsimApiClient {
         Ok(response.success)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::MockTransport;
+    use reqwest::Method;
+
+    const TIMESTAMP: i64 = 1_700_000_000;
+
+    fn client(transport: Arc<MockTransport>) -> WowEsimApiClient {
+        WowEsimApiClient::with_transport("testsecret".to_string(), transport)
+            .with_fixed_timestamp(TIMESTAMP)
+    }
+
+    fn sent_body(transport: &MockTransport) -> serde_json::Value {
+        match transport.last_request().unwrap().body {
+            Some(HttpBody::Raw(body)) => serde_json::from_str(&body).unwrap(),
+            body => panic!("unexpected body {:?}", body),
+        }
+    }
+
+    // Expected signatures were computed outside the crate:
+    // printf '%s' "${SIGN_STRING}" | openssl dgst -sha256 -hmac testsecret
+
+    #[tokio::test]
+    async fn signs_provisioning_requests_and_decodes_the_esim() {
+        let transport = Arc::new(MockTransport::new());
+        transport.push_json(serde_json::json!({
+            "success": true,
+            "data": {
+                "iccid": "8984000000000000001",
+                "qr_code": "LPA:1$smdp.example$ACT-1",
+                "activation_code": "ACT-1"
+            }
+        }));
+
+        let esim = client(transport.clone())
+            .provision_esim("VN-5GB", 1, "576486316948490001")
+            .await
+            .unwrap();
+        assert_eq!(esim.iccid.as_deref(), Some("8984000000000000001"));
+        assert_eq!(esim.qr_code.as_deref(), Some("LPA:1$smdp.example$ACT-1"));
+        assert_eq!(esim.activation_code.as_deref(), Some("ACT-1"));
+
+        let request = transport.last_request().unwrap();
+        assert_eq!(request.method, Method::POST);
+        assert_eq!(request.url, "https://api.wowesim.com/esim/order");
+        assert_eq!(request.header("content-type"), Some("application/json"));
+        // Sign string: package_code=VN-5GB&quantity=1&reference=576486316948490001&timestamp=1700000000
+        assert_eq!(
+            sent_body(&transport),
+            serde_json::json!({
+                "signature": "cb68ada0b0110b67ca2b08282a33f07e9ad12176113f784dd18296d689f0c486",
+                "timestamp": TIMESTAMP,
+                "data": {
+                    "package_code": "VN-5GB",
+                    "quantity": "1",
+                    "reference": "576486316948490001"
+                }
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn signs_usage_requests_against_the_configured_host() {
+        let transport = Arc::new(MockTransport::new());
+        transport.push_json(serde_json::json!({
+            "success": true,
+            "data": { "status": "ACTIVE", "data_remaining_mb": 768.0 }
+        }));
+
+        let usage = client(transport.clone())
+            .with_base_url("https://staging.wowesim.test/v1/")
+            .esim_usage("8984000000000000001")
+            .await
+            .unwrap();
+        assert_eq!(usage.status.as_deref(), Some("ACTIVE"));
+        assert_eq!(usage.data_remaining_mb, Some(768.0));
+        assert_eq!(usage.data_used_mb, None);
+
+        assert_eq!(
+            transport.last_request().unwrap().url,
+            "https://staging.wowesim.test/v1/esim/usage"
+        );
+        // Sign string: iccid=8984000000000000001&timestamp=1700000000
+        assert_eq!(
+            sent_body(&transport)["signature"],
+            "87b1ad8f14727974c6c0d624c95c37d984888bf5aeab7c457a997f673ccdd638"
+        );
+    }
+
+    #[tokio::test]
+    async fn maps_provisioning_failures() {
+        let transport = Arc::new(MockTransport::new());
+        transport.push_json(serde_json::json!({ "success": false, "message": "out of stock" }));
+        transport.push_json(serde_json::json!({ "success": true, "data": null }));
+        transport.push_response(HttpResponse::new(StatusCode::SERVICE_UNAVAILABLE, "busy"));
+        transport.push_error(AppError::transport("connection refused"));
+        let client = client(transport);
+
+        let rejected = client.provision_esim("VN-5GB", 1, "r").await.unwrap_err();
+        assert!(matches!(&rejected, WowApiError::ApiError(message) if message == "out of stock"));
+        assert!(matches!(AppError::from(rejected), AppError::WowError(_)));
+
+        assert!(matches!(
+            client.provision_esim("VN-5GB", 1, "r").await,
+            Err(WowApiError::ParseError(_))
+        ));
+
+        let unavailable = client.provision_esim("VN-5GB", 1, "r").await.unwrap_err();
+        assert!(matches!(
+            AppError::from(unavailable),
+            AppError::HttpStatus { status: StatusCode::SERVICE_UNAVAILABLE, .. }
+        ));
+
+        let unreachable = client.provision_esim("VN-5GB", 1, "r").await.unwrap_err();
+        assert!(matches!(AppError::from(unreachable), AppError::Transport { .. }));
+    }
+}